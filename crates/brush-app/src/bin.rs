@@ -18,14 +18,14 @@ fn main() -> Result<(), anyhow::Error> {
             .expect("Failed to set tracing subscriber");
         }
 
-        use brush_process::process::process_stream;
+        use brush_process::process::process_stream_with_control;
         use brush_ui::BrushUiProcess;
         use brush_ui::app::App;
 
         let context = std::sync::Arc::new(ui_process::UiProcess::new(brush_ui::UiMode::Full));
         let wgpu_options = brush_ui::create_egui_options();
 
-        use brush_cli::Cli;
+        use brush_cli::{Cli, Command};
         use clap::Parser;
 
         let args = Cli::parse().validate()?;
@@ -40,6 +40,48 @@ fn main() -> Result<(), anyhow::Error> {
                 .target(env_logger::Target::Stdout)
                 .init();
 
+            if let Some(Command::Regress(regress)) = &args.command {
+                let device = brush_render::burn_init_setup().await;
+                let report = brush_process::regression::run_regression(regress, &device).await;
+                println!("Regression scene: {report}");
+                if let Some(path) = &regress.save_baseline {
+                    let baseline =
+                        brush_process::regression::RegressionBaseline::from_report(&report);
+                    std::fs::write(path, baseline.to_json())?;
+                    println!("Saved baseline to {}", path.display());
+                    return Ok(());
+                }
+                return report.check(regress);
+            }
+            if let Some(Command::Bundle(bundle)) = &args.command {
+                let device = brush_render::burn_init_setup().await;
+                let summary =
+                    brush_process::bundle::create_bundle(bundle, &args.process, &device).await?;
+                println!(
+                    "Bundled {} views into {:?} ({} KB)",
+                    summary.num_views,
+                    bundle.out,
+                    summary.size / 1024
+                );
+                return Ok(());
+            }
+            if let Some(Command::Compress(compress)) = &args.command {
+                let device = brush_render::burn_init_setup().await;
+                let report = brush_process::compress::run_compress(compress, &device).await?;
+                println!("Compressed {report}");
+                return Ok(());
+            }
+            if let Some(Command::Segment(segment)) = &args.command {
+                let device = brush_render::burn_init_setup().await;
+                let count = brush_process::segment::run_segment(segment, &device).await?;
+                println!("Wrote {count} splats to {}", segment.out.display());
+                return Ok(());
+            }
+            if let Some(Command::Serve(serve)) = &args.command {
+                let device = brush_render::burn_init_setup().await;
+                return brush_remote::render_service::serve(serve.clone(), device).await;
+            }
+
             let (sender, args_receiver) = tokio::sync::oneshot::channel();
             let _ = sender.send(args.process.clone());
 
@@ -49,6 +91,27 @@ fn main() -> Result<(), anyhow::Error> {
                 )
                 .expect("Failed to load icon");
 
+                // Headsets need eframe and burn on the device OpenXR creates.
+                #[cfg(feature = "xr")]
+                let (wgpu_options, xr) = if args.xr {
+                    let (xr, graphics) = brush_ui::xr::create_xr_graphics()?;
+                    let setup = eframe::egui_wgpu::WgpuSetupExisting {
+                        instance: graphics.instance,
+                        adapter: graphics.adapter,
+                        device: graphics.device,
+                        queue: graphics.queue,
+                    };
+                    let options = eframe::egui_wgpu::WgpuConfiguration {
+                        wgpu_setup: eframe::egui_wgpu::WgpuSetup::Existing(setup),
+                        ..wgpu_options
+                    };
+                    (options, Some(xr))
+                } else {
+                    (wgpu_options, None)
+                };
+                #[cfg(not(feature = "xr"))]
+                anyhow::ensure!(!args.xr, "Brush was built without the xr feature");
+
                 let native_options = eframe::NativeOptions {
                     // Build app display.
                     viewport: egui::ViewportBuilder::default()
@@ -72,15 +135,40 @@ fn main() -> Result<(), anyhow::Error> {
                 eframe::run_native(
                     title,
                     native_options,
-                    Box::new(move |cc| Ok(Box::new(App::new(cc, context)))),
+                    Box::new(move |cc| {
+                        #[cfg(feature = "xr")]
+                        if let Some(xr) = xr {
+                            return Ok(Box::new(App::with_xr(cc, context, xr)));
+                        }
+                        Ok(Box::new(App::new(cc, context)))
+                    }),
                 )?;
             } else {
                 let Some(source) = args.source else {
                     panic!("Validation of args failed?");
                 };
                 let device = brush_render::burn_init_setup().await;
-                let stream = process_stream(source, args_receiver, device);
-                brush_cli::process_ui(stream, args.process).await?;
+                let (commands, command_receiver) = tokio::sync::mpsc::unbounded_channel();
+                // Without a view, chunked files load in the order they're in.
+                let (_, focus) = tokio::sync::watch::channel(None);
+                let stream = process_stream_with_control(
+                    source,
+                    args_receiver,
+                    device,
+                    command_receiver,
+                    focus,
+                );
+                if let Some(addr) = args.control_addr {
+                    let server =
+                        brush_remote::server::RemoteServer::bind(addr, args.control_token.clone())
+                            .await?;
+                    let total_steps = args.process.train_config.total_steps;
+                    let stream =
+                        brush_remote::server::serve_training(stream, server, commands, total_steps);
+                    brush_cli::process_ui(stream, args.process).await?;
+                } else {
+                    brush_cli::process_ui(stream, args.process).await?;
+                }
             }
             anyhow::Result::<(), anyhow::Error>::Ok(())
         })?;
@@ -111,7 +199,9 @@ fn main() -> Result<(), anyhow::Error> {
 
         // Allowed to fail. When using the embedding API main canvas just won't be found.
         // Ideally it would catch only _that_ error.
-        let _ = wasm::wasm_app("main_canvas", start_uri.as_deref().unwrap_or(""));
+        if let Ok(context) = wasm::wasm_app("main_canvas", start_uri.as_deref().unwrap_or("")) {
+            wasm::sync_page_url(context);
+        }
     }
 
     Ok(())
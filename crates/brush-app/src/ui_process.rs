@@ -1,13 +1,22 @@
 use anyhow::Result;
-use brush_dataset::{Dataset, scene::SceneView};
-use brush_process::{config::ProcessArgs, message::ProcessMessage, process::process_stream};
-use brush_render::camera::Camera;
-use brush_ui::{BrushUiProcess, UiMode, app::CameraSettings, camera_controls::CameraController};
-use brush_vfs::DataSource;
+use brush_dataset::{Dataset, scene::SceneView, splat_export::SplatLabels};
+use brush_process::{
+    config::ProcessArgs, control::TrainCommand, message::ProcessMessage,
+    process::process_stream_with_control,
+};
+use brush_remote::{RemoteControl, is_remote_url, protocol::ClientMessage};
+use brush_render::{MainBackend, camera::Camera, gaussian_splats::Splats};
+use brush_ui::{
+    BrushUiProcess, UiMode,
+    app::{CameraSettings, ExportSettings, MeasureScale, ViewSettings},
+    camera_controls::CameraController,
+};
+use brush_vfs::{DataSource, DynStream};
 use burn_wgpu::WgpuDevice;
 use egui::Response;
 use glam::{Affine3A, Quat, Vec3};
 use parking_lot::RwLock;
+use std::{collections::HashMap, pin::Pin};
 use tokio::sync::{self, oneshot::Receiver};
 use tokio_stream::StreamExt;
 use tokio_with_wasm::alias as tokio_wasm;
@@ -15,6 +24,28 @@ use tokio_with_wasm::alias as tokio_wasm;
 #[derive(Debug, Clone)]
 enum ControlMessage {
     Paused(bool),
+    Train(TrainCommand),
+}
+
+// Where changes to a running process go.
+enum ProcessControl {
+    Local(sync::mpsc::UnboundedSender<ControlMessage>),
+    Remote(RemoteControl),
+}
+
+impl ProcessControl {
+    fn send(&self, message: ControlMessage) {
+        match self {
+            Self::Local(sender) => {
+                let _ = sender.send(message);
+            }
+            Self::Remote(remote) => remote.send(match message {
+                ControlMessage::Paused(true) => ClientMessage::Pause,
+                ControlMessage::Paused(false) => ClientMessage::Resume,
+                ControlMessage::Train(command) => ClientMessage::Train { command },
+            }),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -25,7 +56,9 @@ struct DeviceContext {
 
 struct RunningProcess {
     messages: sync::mpsc::Receiver<Result<ProcessMessage, anyhow::Error>>,
-    control: sync::mpsc::UnboundedSender<ControlMessage>,
+    control: ProcessControl,
+    // Where the view is, for loading what's in view first.
+    focus: sync::watch::Sender<Option<Camera>>,
     send_device: Option<sync::oneshot::Sender<DeviceContext>>,
 }
 
@@ -45,6 +78,21 @@ impl UiProcess {
             inner: RwLock::new(UiProcessInner::new(ui_mode)),
         }
     }
+
+    /// The splats last loaded or trained.
+    pub fn current_splats(&self) -> Option<Splats<MainBackend>> {
+        self.inner.read().splats.clone()
+    }
+
+    /// Labels of the current splats, when they were loaded from a file saved with them.
+    pub fn current_labels(&self) -> Option<SplatLabels> {
+        self.inner.read().labels.clone()
+    }
+
+    /// The url the running or last process loaded from, if it loaded from one.
+    pub fn current_url(&self) -> Option<String> {
+        self.inner.read().url.clone()
+    }
 }
 
 impl BrushUiProcess for UiProcess {
@@ -58,6 +106,16 @@ impl BrushUiProcess for UiProcess {
 
     fn tick_controls(&self, response: &Response, ui: &egui::Ui) {
         self.inner.write().controls.tick(response, ui);
+        let camera = self.current_camera();
+        if let Some(process) = self.inner.read().running_process.as_ref() {
+            process.focus.send_if_modified(|focus| {
+                let changed = focus.as_ref() != Some(&camera);
+                if changed {
+                    *focus = Some(camera);
+                }
+                changed
+            });
+        }
     }
 
     fn model_local_to_world(&self) -> glam::Affine3A {
@@ -80,7 +138,13 @@ impl BrushUiProcess for UiProcess {
 
     fn set_train_paused(&self, paused: bool) {
         if let Some(process) = self.inner.read().running_process.as_ref() {
-            let _ = process.control.send(ControlMessage::Paused(paused));
+            process.control.send(ControlMessage::Paused(paused));
+        }
+    }
+
+    fn send_train_command(&self, command: TrainCommand) {
+        if let Some(process) = self.inner.read().running_process.as_ref() {
+            process.control.send(ControlMessage::Train(command));
         }
     }
 
@@ -99,17 +163,29 @@ impl BrushUiProcess for UiProcess {
                 Some(inner.controls.speed_scale)
             },
             clamping: inner.controls.clamping.clone(),
+            controller: inner.controls.mode,
+            inertia: if inner.controls.inertia == 1.0 {
+                None
+            } else {
+                Some(inner.controls.inertia)
+            },
         }
     }
 
     fn set_cam_settings(&self, settings: CameraSettings) {
         let mut inner = self.inner.write();
-        inner.controls = CameraController::new(settings.clone());
+        inner.controls.apply_settings(&settings);
         // Update the camera to the new position.
         inner.camera.position = settings.position;
         inner.camera.rotation = settings.rotation;
 
-        inner.camera.fov_x = settings.fov_y;
+        // Keep the aspect ratio of the latest view, or else of the camera.
+        let half_tan = |fov: f64| (fov * 0.5).tan();
+        let aspect = inner.view_aspect.map_or_else(
+            || half_tan(inner.camera.fov_x) / half_tan(inner.camera.fov_y),
+            f64::from,
+        );
+        inner.camera.fov_x = 2.0 * (aspect * half_tan(settings.fov_y)).atan();
         inner.camera.fov_y = settings.fov_y;
 
         let cam = inner.camera.clone();
@@ -117,6 +193,36 @@ impl BrushUiProcess for UiProcess {
         inner.repaint();
     }
 
+    fn get_view_settings(&self) -> ViewSettings {
+        self.inner.read().view_settings
+    }
+
+    fn set_view_settings(&self, settings: ViewSettings) {
+        let mut inner = self.inner.write();
+        inner.view_settings = settings;
+        inner.repaint();
+    }
+
+    fn get_export_settings(&self) -> ExportSettings {
+        self.inner.read().export_settings.clone()
+    }
+
+    fn set_export_settings(&self, settings: ExportSettings) {
+        self.inner.write().export_settings = settings;
+    }
+
+    fn get_measure_scale(&self) -> MeasureScale {
+        self.inner.read().measure_scale.clone()
+    }
+
+    fn set_measure_scale(&self, scale: MeasureScale) {
+        let mut inner = self.inner.write();
+        if let Some(key) = inner.source_key.clone() {
+            inner.measure_scales.insert(key, scale.clone());
+        }
+        inner.measure_scale = scale;
+    }
+
     fn focus_view(&self, view: &SceneView) {
         let mut inner = self.inner.write();
         inner.match_controls_to(&view.camera);
@@ -142,6 +248,14 @@ impl BrushUiProcess for UiProcess {
         inner.repaint();
     }
 
+    fn set_camera(&self, camera: &Camera) {
+        let mut inner = self.inner.write();
+        inner.match_controls_to(camera);
+        inner.camera = camera.clone();
+        inner.controls.stop_movement();
+        inner.repaint();
+    }
+
     fn connect_device(&self, device: WgpuDevice, ctx: egui::Context) {
         let mut inner = self.inner.write();
         let ctx = DeviceContext { device, ctx };
@@ -158,11 +272,45 @@ impl BrushUiProcess for UiProcess {
         let mut inner = self.inner.write();
         let mut reset = UiProcessInner::new(ui_mode);
         reset.cur_device_ctx = inner.cur_device_ctx.clone();
+        reset.view_settings = inner.view_settings;
+        reset.export_settings = inner.export_settings.clone();
+        reset.url = match &source {
+            DataSource::Url(url) | DataSource::CachedUrl(url) => Some(url.clone()),
+            _ => None,
+        };
+        // Picked files aren't known by name, so their scale isn't kept.
+        reset.source_key = match &source {
+            DataSource::Url(key) | DataSource::CachedUrl(key) | DataSource::Path(key) => {
+                Some(key.clone())
+            }
+            DataSource::Memory(file) => Some(file.name.clone()),
+            _ => None,
+        };
+        reset.measure_scales = std::mem::take(&mut inner.measure_scales);
+        reset.measure_scale = reset
+            .source_key
+            .as_ref()
+            .and_then(|key| reset.measure_scales.get(key))
+            .cloned()
+            .unwrap_or_default();
         *inner = reset;
 
         let (sender, receiver) = sync::mpsc::channel(1);
         let (train_sender, mut train_receiver) = sync::mpsc::unbounded_channel();
         let (send_dev, rec_rev) = sync::oneshot::channel::<DeviceContext>();
+        let (focus, focus_receiver) = sync::watch::channel(None);
+
+        // Served training runs are followed rather than loaded.
+        let (control, remote) = match &source {
+            DataSource::Url(url) if is_remote_url(url) => {
+                let (control, requests) = RemoteControl::channel();
+                (
+                    ProcessControl::Remote(control),
+                    Some((url.clone(), requests)),
+                )
+            }
+            _ => (ProcessControl::Local(train_sender), None),
+        };
 
         tokio_with_wasm::alias::task::spawn(async move {
             // Wait for device & gui ctx to be available.
@@ -171,8 +319,19 @@ impl BrushUiProcess for UiProcess {
                 return;
             };
 
-            let stream = process_stream(source, args, device_ctx.device);
-            let mut stream = std::pin::pin!(stream);
+            let (commands, command_receiver) = sync::mpsc::unbounded_channel();
+            let mut stream: Pin<Box<dyn DynStream<Result<ProcessMessage>>>> = match remote {
+                Some((url, requests)) => {
+                    Box::pin(brush_remote::connect(url, device_ctx.device, requests))
+                }
+                None => Box::pin(process_stream_with_control(
+                    source,
+                    args,
+                    device_ctx.device,
+                    command_receiver,
+                    focus_receiver,
+                )),
+            };
 
             while let Some(msg) = stream.next().await {
                 // Mark egui as needing a repaint.
@@ -184,16 +343,24 @@ impl BrushUiProcess for UiProcess {
                 if sender.send(msg).await.is_err() {
                     break;
                 }
-                // Check if training is paused. Don't care about other messages as pausing loading
-                // doesn't make much sense.
-                if is_train_step
-                    && matches!(train_receiver.try_recv(), Ok(ControlMessage::Paused(true)))
-                {
-                    // Pause if needed.
-                    while !matches!(
-                        train_receiver.recv().await,
-                        Some(ControlMessage::Paused(false))
-                    ) {}
+                // Check if training is paused or changed. Don't care about other messages as
+                // pausing loading doesn't make much sense.
+                if is_train_step {
+                    let mut paused = false;
+                    loop {
+                        let control = if paused {
+                            train_receiver.recv().await
+                        } else {
+                            train_receiver.try_recv().ok()
+                        };
+                        match control {
+                            Some(ControlMessage::Paused(pause)) => paused = pause,
+                            Some(ControlMessage::Train(command)) => {
+                                let _ = commands.send(command);
+                            }
+                            None => break,
+                        }
+                    }
                 }
 
                 // Give back control to the runtime.
@@ -212,13 +379,15 @@ impl BrushUiProcess for UiProcess {
                 .expect("Failed to send device context");
             inner.running_process = Some(RunningProcess {
                 messages: receiver,
-                control: train_sender,
+                control,
+                focus,
                 send_device: None,
             });
         } else {
             inner.running_process = Some(RunningProcess {
                 messages: receiver,
-                control: train_sender,
+                control,
+                focus,
                 send_device: Some(send_dev),
             });
         }
@@ -242,6 +411,14 @@ impl BrushUiProcess for UiProcess {
                 Ok(ProcessMessage::DoneLoading) => {
                     inner.is_loading = false;
                 }
+                Ok(ProcessMessage::ViewSplats { splats, labels, .. }) => {
+                    inner.splats = Some(*splats.clone());
+                    inner.labels = labels.clone();
+                }
+                Ok(ProcessMessage::TrainStep { splats, .. }) => {
+                    inner.splats = Some(*splats.clone());
+                    inner.labels = None;
+                }
                 _ => (),
             }
             drop(inner);
@@ -268,6 +445,15 @@ struct UiProcessInner {
     model_local_to_world: Affine3A,
     running_process: Option<RunningProcess>,
     selected_view: Option<SceneView>,
+    splats: Option<Splats<MainBackend>>,
+    labels: Option<SplatLabels>,
+    view_settings: ViewSettings,
+    export_settings: ExportSettings,
+    url: Option<String>,
+    // What the source was loaded from, to keep the measure scale of each by.
+    source_key: Option<String>,
+    measure_scale: MeasureScale,
+    measure_scales: HashMap<String, MeasureScale>,
     cur_device_ctx: Option<DeviceContext>,
 }
 
@@ -295,6 +481,14 @@ impl UiProcessInner {
             is_loading: false,
             is_training: false,
             selected_view: None,
+            splats: None,
+            labels: None,
+            view_settings: ViewSettings::default(),
+            export_settings: ExportSettings::default(),
+            url: None,
+            source_key: None,
+            measure_scale: MeasureScale::default(),
+            measure_scales: HashMap::new(),
             running_process: None,
             cur_device_ctx: None,
         }
@@ -1,18 +1,24 @@
 use crate::ui_process::UiProcess;
 use anyhow::Context;
+use brush_dataset::splat_export::SplatLabels;
 use brush_process::config::ProcessArgs;
+use brush_render::camera::{focal_to_fov, fov_to_focal};
+use brush_render::camera_path::{CameraPath, PathPlayback};
+use brush_render::picking::{PickHit, pick};
 use brush_ui::BrushUiProcess;
 use brush_ui::UiMode;
-use brush_ui::app::App;
+use brush_ui::app::{App, ViewSettings};
 use brush_vfs::DataSource;
 use glam::Vec3;
 use glam::{EulerRot, Quat};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio_with_wasm::alias as tokio_wasm;
 use wasm_bindgen::JsCast;
 use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{future_to_promise, js_sys::Promise};
 
 fn parse_search(search: &str) -> HashMap<String, String> {
     let mut params = HashMap::new();
@@ -58,6 +64,102 @@ fn quat_from_uri(uri: &str) -> Option<Quat> {
     }
 }
 
+fn view_from_search(params: &HashMap<String, String>) -> ViewSettings {
+    let default = ViewSettings::default();
+    let lod_pixels = params.get("lod").and_then(|f| f.parse().ok());
+    ViewSettings {
+        exposure: params
+            .get("exposure")
+            .and_then(|f| f.parse().ok())
+            .unwrap_or(default.exposure),
+        debug_view: params
+            .get("view")
+            .and_then(|f| f.parse().ok())
+            .unwrap_or(default.debug_view),
+        lod: lod_pixels.is_some(),
+        lod_pixels: lod_pixels.unwrap_or(default.lod_pixels),
+        show_cameras: params
+            .get("cameras")
+            .and_then(|f| f.parse().ok())
+            .unwrap_or(default.show_cameras),
+        culling: default.culling,
+    }
+}
+
+/// Query string that opens the viewer at its current view, in the format parsed by [`wasm_app`].
+fn search_for_view(context: &UiProcess) -> String {
+    let mut params = vec![];
+    if let Some(url) = context.current_url() {
+        params.push(("url", url));
+    }
+    if context.ui_mode() == UiMode::Zen {
+        params.push(("zen", "true".to_owned()));
+    }
+
+    let cam = context.get_cam_settings();
+    let (p, r) = (cam.position, cam.rotation);
+    params.push(("position", format!("{},{},{}", p.x, p.y, p.z)));
+    params.push(("rotation", format!("{},{},{},{}", r.x, r.y, r.z, r.w)));
+    params.push(("focus_distance", cam.focus_distance.to_string()));
+    params.push(("fov_y", cam.fov_y.to_string()));
+    params.push(("controller", cam.controller.key().to_owned()));
+    if let Some(speed_scale) = cam.speed_scale {
+        params.push(("speed_scale", speed_scale.to_string()));
+    }
+    if let Some(inertia) = cam.inertia {
+        params.push(("inertia", inertia.to_string()));
+    }
+
+    // Only settings that were changed, to keep links short.
+    let view = context.get_view_settings();
+    let default = ViewSettings::default();
+    if view.exposure != default.exposure {
+        params.push(("exposure", view.exposure.to_string()));
+    }
+    if view.debug_view != default.debug_view {
+        params.push(("view", view.debug_view.key().to_owned()));
+    }
+    if view.lod {
+        params.push(("lod", view.lod_pixels.to_string()));
+    }
+    if view.show_cameras != default.show_cameras {
+        params.push(("cameras", view.show_cameras.to_string()));
+    }
+
+    let pairs: Vec<_> = params
+        .iter()
+        .map(|(key, value)| format!("{key}={}", urlencoding::encode(value)))
+        .collect();
+    format!("?{}", pairs.join("&"))
+}
+
+/// Keep the url of the page pointing at the current view, so it can be shared to open that view.
+pub fn sync_page_url(context: Arc<UiProcess>) {
+    tokio_wasm::spawn(async move {
+        let mut last = None;
+        loop {
+            tokio_wasm::time::sleep(Duration::from_millis(500)).await;
+            let search = search_for_view(&context);
+            if last.as_ref() == Some(&search) {
+                continue;
+            }
+            // Replace rather than push, moving the camera around shouldn't fill up the history.
+            let replaced = web_sys::window()
+                .and_then(|w| w.history().ok())
+                .is_some_and(|history| {
+                    history
+                        .replace_state_with_url(&JsValue::NULL, "", Some(&search))
+                        .is_ok()
+                });
+            if !replaced {
+                log::warn!("Failed to update page url");
+                return;
+            }
+            last = Some(search);
+        }
+    });
+}
+
 pub fn wasm_app(canvas_name: &str, start_uri: &str) -> anyhow::Result<Arc<UiProcess>> {
     let search_params = parse_search(start_uri);
     let mut zen = false;
@@ -121,6 +223,11 @@ pub fn wasm_app(canvas_name: &str, start_uri: &str) -> anyhow::Result<Arc<UiProc
     let speed_scale = search_params
         .get("speed_scale")
         .and_then(|f| f.parse().ok());
+    let controller = search_params
+        .get("controller")
+        .and_then(|f| f.parse().ok())
+        .unwrap_or_default();
+    let inertia = search_params.get("inertia").and_then(|f| f.parse().ok());
 
     context.set_cam_settings(brush_ui::app::CameraSettings {
         fov_y,
@@ -129,7 +236,10 @@ pub fn wasm_app(canvas_name: &str, start_uri: &str) -> anyhow::Result<Arc<UiProc
         focus_distance,
         speed_scale,
         clamping: Default::default(),
+        controller,
+        inertia,
     });
+    context.set_view_settings(view_from_search(&search_params));
 
     Ok(context)
 }
@@ -142,6 +252,40 @@ enum EmbeddedCommands {
 #[wasm_bindgen]
 pub struct EmbeddedApp {
     command_channel: UnboundedSender<EmbeddedCommands>,
+    context: Arc<UiProcess>,
+    // Camera path played along with the clock of the embedding page.
+    path: Option<CameraPath>,
+    playback: PathPlayback,
+}
+
+/// What's under a point of the viewer.
+#[wasm_bindgen]
+#[derive(Clone, Copy)]
+pub struct PickResult {
+    /// World space position of the point.
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    /// Index of the splat that contributes most to the point.
+    pub splat: u32,
+    /// Opacity of the splats at the point.
+    pub alpha: f32,
+    /// Layer of the splat, as labeled in the file it was loaded from. Undefined for files without
+    /// labels.
+    pub layer: Option<u32>,
+}
+
+impl PickResult {
+    fn new(hit: PickHit, labels: Option<&SplatLabels>) -> Self {
+        Self {
+            x: hit.point.x,
+            y: hit.point.y,
+            z: hit.point.z,
+            splat: hit.splat,
+            alpha: hit.alpha,
+            layer: labels.and_then(|l| l.labels.get(hit.splat as usize).copied()),
+        }
+    }
 }
 
 //Wrapper for interop.
@@ -184,6 +328,8 @@ impl CameraSettings {
                 min_yaw,
                 max_yaw,
             },
+            controller: Default::default(),
+            inertia: None,
         })
     }
 }
@@ -196,7 +342,9 @@ impl EmbeddedApp {
 
         let (cmd_send, mut cmd_rec) = tokio::sync::mpsc::unbounded_channel();
 
+        let context_cl = context.clone();
         tokio_wasm::spawn(async move {
+            let context = context_cl;
             while let Some(command) = cmd_rec.recv().await {
                 match command {
                     EmbeddedCommands::LoadDataSource(data_source) => {
@@ -213,6 +361,9 @@ impl EmbeddedApp {
 
         Ok(Self {
             command_channel: cmd_send,
+            context,
+            path: None,
+            playback: PathPlayback::default(),
         })
     }
 
@@ -231,4 +382,93 @@ impl EmbeddedApp {
             .send(EmbeddedCommands::SetCamSettings(settings))
             .expect("Viewer was closed?");
     }
+
+    /// Load a camera path (as saved from the camera path panel) to play with [`Self::set_path_time`].
+    #[wasm_bindgen]
+    pub fn load_camera_path(&mut self, json: &str) -> Result<(), JsError> {
+        self.path = Some(serde_json::from_str(json)?);
+        self.playback = PathPlayback::default();
+        Ok(())
+    }
+
+    /// Move the camera to where the loaded camera path is at `time` seconds. Call this every
+    /// frame with an external clock, eg. the current time of a song, to keep the camera in sync.
+    ///
+    /// Returns the names of the path events passed since the previous call, in order.
+    #[wasm_bindgen]
+    pub fn set_path_time(&mut self, time: f32) -> Vec<String> {
+        let Some(path) = &self.path else {
+            return vec![];
+        };
+        let current = self.context.current_camera();
+        let aspect_ratio = ((current.fov_x * 0.5).tan() / (current.fov_y * 0.5).tan()) as f32;
+        let (sample, events) = self.playback.advance(path, time, aspect_ratio);
+        if let Some(sample) = sample {
+            self.context.set_camera(&sample.camera);
+        }
+        events.into_iter().map(|e| e.name.clone()).collect()
+    }
+
+    /// Query string that opens the viewer at its current view, eg. to append to the url of the
+    /// embedding page for links to share.
+    #[wasm_bindgen]
+    pub fn view_search(&self) -> String {
+        search_for_view(&self.context)
+    }
+
+    /// Whether a splat or dataset is still loading.
+    #[wasm_bindgen]
+    pub fn is_loading(&self) -> bool {
+        self.context.is_loading()
+    }
+
+    /// Find what's under the pixel `x`, `y` of the viewer, as seen on a canvas of `width` by
+    /// `height` pixels. Resolves to a [`PickResult`], or to undefined when there's nothing there.
+    #[wasm_bindgen]
+    pub fn pick(&self, x: f32, y: f32, width: u32, height: u32) -> Promise {
+        let splats = self.context.current_splats();
+        let labels = self.context.current_labels();
+        let mut camera = self.context.current_camera();
+        future_to_promise(async move {
+            let Some(splats) = splats else {
+                return Ok(JsValue::UNDEFINED);
+            };
+            let size = glam::uvec2(width.max(1), height.max(1));
+            // Match the aspect ratio of the canvas, like the viewer does.
+            let focal_y = fov_to_focal(camera.fov_y, size.y);
+            camera.fov_x = focal_to_fov(focal_y, size.x);
+            let hit = pick(&splats, &camera, size, glam::vec2(x, y)).await;
+            Ok(hit.map_or(JsValue::UNDEFINED, |hit| {
+                PickResult::new(hit, labels.as_ref()).into()
+            }))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn pick_result_has_layer() {
+        let hit = PickHit {
+            splat: 1,
+            point: glam::vec3(1.0, 2.0, 3.0),
+            alpha: 0.5,
+        };
+        let labels = SplatLabels {
+            labels: vec![0, 2],
+            names: vec!["ground".to_owned(), "tree".to_owned(), "car".to_owned()],
+        };
+        let result = PickResult::new(hit, Some(&labels));
+        assert_eq!((result.x, result.y, result.z), (1.0, 2.0, 3.0));
+        assert_eq!(result.splat, 1);
+        assert_eq!(result.layer, Some(2));
+
+        // Without labels, or past the end of them, there's no layer.
+        assert_eq!(PickResult::new(hit, None).layer, None);
+        let far = PickHit { splat: 5, ..hit };
+        assert_eq!(PickResult::new(far, Some(&labels)).layer, None);
+    }
 }
@@ -0,0 +1,108 @@
+// Compares rendering a scene mostly hidden behind a wall with occlusion culling per splat, per
+// cluster, and without culling.
+use brush_render::{
+    MainBackend,
+    camera::Camera,
+    gaussian_splats::Splats,
+    occlusion::{DepthPyramid, SplatClusters},
+};
+use burn_wgpu::WgpuDevice;
+use glam::{Quat, Vec3, vec2, vec3};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+fn main() {
+    divan::main();
+}
+
+const BENCH_SIZES: [usize; 3] = [1 << 18, 1 << 20, 1 << 21];
+const RESOLUTION: glam::UVec2 = glam::uvec2(1024, 1024);
+// Side of the grid of splats making up the wall.
+const WALL_SPLATS: usize = 128;
+
+const TARGET_SAMPLE_COUNT: u32 = 50;
+const INTERNAL_ITERS: u32 = 5;
+
+#[derive(Clone, Copy)]
+enum Culling {
+    None,
+    Splats,
+    Clusters,
+}
+
+// Small splats spread through a deep room, and a wall of large opaque splats in front of them
+// with a window to see through.
+fn room(num_splats: usize, device: &WgpuDevice) -> Splats<MainBackend> {
+    let mut rng = StdRng::seed_from_u64(3);
+    let mut means = vec![];
+    let mut log_scales = vec![];
+    for _ in 0..num_splats {
+        means.push(vec3(
+            rng.random_range(-10.0..10.0),
+            rng.random_range(-10.0..10.0),
+            rng.random_range(5.0..50.0),
+        ));
+        log_scales.push(Vec3::splat(rng.random_range(-4.0..-2.0)));
+    }
+    let spacing = 8.0 / WALL_SPLATS as f32;
+    for y in 0..WALL_SPLATS {
+        for x in 0..WALL_SPLATS {
+            let [u, v] = [x, y].map(|i| (i as f32 + 0.5) * spacing - 4.0);
+            if u.abs() < 0.5 && v.abs() < 0.5 {
+                continue;
+            }
+            means.push(vec3(u, v, 3.0));
+            log_scales.push(vec3(spacing.ln(), spacing.ln(), -6.0));
+        }
+    }
+    let opacities = vec![5.0; means.len()];
+    let rotations = vec![Quat::IDENTITY; means.len()];
+    Splats::from_raw(
+        &means,
+        Some(rotations.as_slice()),
+        Some(log_scales.as_slice()),
+        None,
+        Some(opacities.as_slice()),
+        device,
+    )
+}
+
+fn bench_culling(bencher: divan::Bencher, num_splats: usize, culling: Culling) {
+    let device = WgpuDevice::DefaultDevice;
+    let splats = room(num_splats, &device);
+    let camera = Camera::new(Vec3::ZERO, Quat::IDENTITY, 1.2, 1.2, vec2(0.5, 0.5));
+    let (img, aux) = splats.render(&camera, RESOLUTION, true);
+    let depth = DepthPyramid::from_render(&splats, &camera, img, &aux);
+
+    bencher.bench_local(move || {
+        for _ in 0..INTERNAL_ITERS {
+            let splats = match culling {
+                Culling::None => splats.clone(),
+                Culling::Splats => depth.cull(&splats),
+                Culling::Clusters => depth.cull_clusters(&splats, &SplatClusters::new(&splats)),
+            };
+            let _ = splats.render(&camera, RESOLUTION, true);
+        }
+        // Wait for GPU work.
+        <MainBackend as burn::prelude::Backend>::sync(&device);
+    });
+}
+
+#[divan::bench_group(max_time = 20, sample_count = TARGET_SAMPLE_COUNT, sample_size = 1)]
+mod occlusion {
+    use crate::{BENCH_SIZES, Culling, bench_culling};
+
+    #[divan::bench(args = BENCH_SIZES)]
+    fn none(bencher: divan::Bencher, num_splats: usize) {
+        bench_culling(bencher, num_splats, Culling::None);
+    }
+
+    #[divan::bench(args = BENCH_SIZES)]
+    fn per_splat(bencher: divan::Bencher, num_splats: usize) {
+        bench_culling(bencher, num_splats, Culling::Splats);
+    }
+
+    #[divan::bench(args = BENCH_SIZES)]
+    fn per_cluster(bencher: divan::Bencher, num_splats: usize) {
+        bench_culling(bencher, num_splats, Culling::Clusters);
+    }
+}
@@ -0,0 +1,85 @@
+use brush_sort::{RadixDigits, radix_argsort_with_digits};
+use burn::tensor::{Int, Tensor};
+use burn_wgpu::{CubeBackend, WgpuDevice, WgpuRuntime};
+use rand::Rng;
+
+fn main() {
+    divan::main();
+}
+
+type Backend = CubeBackend<WgpuRuntime, f32, i32, u32>;
+
+// Number of keys, up to what a large scene has in tile intersections.
+const BENCH_SIZES: [usize; 5] = [1 << 16, 1 << 18, 1 << 20, 1 << 22, 1 << 24];
+
+const TARGET_SAMPLE_COUNT: u32 = 50;
+const INTERNAL_ITERS: u32 = 5;
+
+fn bench_sort(bencher: divan::Bencher, size: usize, sorting_bits: u32, digits: RadixDigits) {
+    let device = WgpuDevice::DefaultDevice;
+    let mut rng = rand::rng();
+    let max_key = if sorting_bits == 32 {
+        u32::MAX
+    } else {
+        (1 << sorting_bits) - 1
+    };
+    let keys: Vec<i32> = (0..size)
+        .map(|_| rng.random_range(0..=max_key) as i32)
+        .collect();
+    let values: Vec<i32> = (0..size as i32).collect();
+
+    let keys = Tensor::<Backend, 1, Int>::from_ints(keys.as_slice(), &device).into_primitive();
+    let values = Tensor::<Backend, 1, Int>::from_ints(values.as_slice(), &device).into_primitive();
+    let num_keys = Tensor::<Backend, 1, Int>::from_ints([size as i32], &device).into_primitive();
+
+    bencher.bench_local(move || {
+        for _ in 0..INTERNAL_ITERS {
+            let _ = radix_argsort_with_digits(
+                keys.clone(),
+                values.clone(),
+                &num_keys,
+                sorting_bits,
+                digits,
+            );
+        }
+        // Wait for GPU work.
+        <Backend as burn::prelude::Backend>::sync(&device);
+    });
+}
+
+// Sorting splats by depth, on all bits.
+#[divan::bench_group(max_time = 20, sample_count = TARGET_SAMPLE_COUNT, sample_size = 1)]
+mod depth {
+    use crate::{BENCH_SIZES, bench_sort};
+    use brush_sort::RadixDigits;
+
+    #[divan::bench(args = BENCH_SIZES)]
+    fn four_bits(bencher: divan::Bencher, size: usize) {
+        bench_sort(bencher, size, 32, RadixDigits::Four);
+    }
+
+    #[divan::bench(args = BENCH_SIZES)]
+    fn eight_bits(bencher: divan::Bencher, size: usize) {
+        bench_sort(bencher, size, 32, RadixDigits::Eight);
+    }
+}
+
+// Sorting intersections by tile, where only the low bits are used.
+#[divan::bench_group(max_time = 20, sample_count = TARGET_SAMPLE_COUNT, sample_size = 1)]
+mod tiles {
+    use crate::{BENCH_SIZES, bench_sort};
+    use brush_sort::RadixDigits;
+
+    // A 1080p image has ~8000 tiles, which needs 13 bits.
+    const TILE_BITS: u32 = 13;
+
+    #[divan::bench(args = BENCH_SIZES)]
+    fn four_bits(bencher: divan::Bencher, size: usize) {
+        bench_sort(bencher, size, TILE_BITS, RadixDigits::Four);
+    }
+
+    #[divan::bench(args = BENCH_SIZES)]
+    fn eight_bits(bencher: divan::Bencher, size: usize) {
+        bench_sort(bencher, size, TILE_BITS, RadixDigits::Eight);
+    }
+}
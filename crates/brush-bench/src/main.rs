@@ -0,0 +1,195 @@
+#![recursion_limit = "256"]
+// Trains the scenes of standard benchmarks end to end, and writes how quickly they reach their
+// quality to a JSON report, to compare performance between versions, GPUs and graphics APIs.
+mod report;
+mod suites;
+
+use std::path::{Path, PathBuf};
+
+use brush_process::{config::ProcessArgs, message::ProcessMessage, process::process_stream};
+use brush_render::burn_init_setup_with;
+use brush_vfs::DataSource;
+use burn_wgpu::{
+    WgpuDevice,
+    graphics::{AutoGraphicsApi, Dx12, Metal, OpenGl, Vulkan},
+};
+use clap::{Parser, ValueEnum};
+use report::{BenchReport, SceneRecorder, SceneReport};
+use suites::{Suite, Variant};
+use tokio_stream::StreamExt;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum GraphicsBackend {
+    Auto,
+    Vulkan,
+    Metal,
+    Dx12,
+    OpenGl,
+}
+
+#[derive(Parser)]
+#[command(about = "Train benchmark scenes and report time to quality")]
+struct Args {
+    /// Folder with the scenes, each in a folder of its name, or in `<suite>/<scene>`.
+    #[arg(long)]
+    data_dir: PathBuf,
+    /// Suites to run, all of them by default.
+    #[arg(long, value_delimiter = ',')]
+    suite: Vec<Suite>,
+    /// Only run these scenes of the suites.
+    #[arg(long, value_delimiter = ',')]
+    scenes: Vec<String>,
+    /// Train each scene with each of these settings, to compare them.
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "baseline")]
+    variants: Vec<Variant>,
+    /// Graphics API to train with.
+    #[arg(long, value_enum, default_value = "auto")]
+    backend: GraphicsBackend,
+    /// Where to write the report. The final splats of each scene are exported next to it.
+    #[arg(long, default_value = "bench_report.json")]
+    report: PathBuf,
+
+    #[clap(flatten)]
+    process: ProcessArgs,
+}
+
+async fn init_device(backend: GraphicsBackend) -> (WgpuDevice, String, String, String) {
+    let (device, info) = match backend {
+        GraphicsBackend::Auto => burn_init_setup_with::<AutoGraphicsApi>().await,
+        GraphicsBackend::Vulkan => burn_init_setup_with::<Vulkan>().await,
+        GraphicsBackend::Metal => burn_init_setup_with::<Metal>().await,
+        GraphicsBackend::Dx12 => burn_init_setup_with::<Dx12>().await,
+        GraphicsBackend::OpenGl => burn_init_setup_with::<OpenGl>().await,
+    };
+    (
+        device,
+        format!("{:?}", info.backend),
+        info.name,
+        info.driver,
+    )
+}
+
+fn find_scene(data_dir: &Path, suite: Suite, scene: &str) -> Option<PathBuf> {
+    [
+        data_dir.join(scene),
+        data_dir.join(suite.name()).join(scene),
+    ]
+    .into_iter()
+    .find(|path| path.is_dir())
+}
+
+async fn run_scene(
+    suite: Suite,
+    scene: &str,
+    variant: Variant,
+    path: &Path,
+    args: ProcessArgs,
+    device: WgpuDevice,
+) -> anyhow::Result<SceneReport> {
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    let _ = sender.send(args);
+    let source = DataSource::Path(path.to_string_lossy().into_owned());
+    let mut stream = std::pin::pin!(process_stream(source, receiver, device));
+
+    let mut recorder = SceneRecorder::default();
+    while let Some(message) = stream.next().await {
+        match message? {
+            ProcessMessage::TrainStep {
+                splats,
+                iter,
+                total_elapsed,
+                ..
+            } => {
+                recorder.train_step(iter, total_elapsed.as_secs_f32(), splats.num_splats());
+            }
+            ProcessMessage::EvalResult {
+                iter,
+                avg_psnr,
+                avg_ssim,
+            } => {
+                log::info!("{scene} at step {iter}: PSNR {avg_psnr:.2}, SSIM {avg_ssim:.3}");
+                recorder.eval(iter, avg_psnr, avg_ssim);
+            }
+            _ => {}
+        }
+    }
+    recorder.finish(suite.name(), scene, variant.name())
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+
+    runtime.block_on(async move {
+        env_logger::builder()
+            .target(env_logger::Target::Stdout)
+            .init();
+
+        let (device, backend, adapter, driver) = init_device(args.backend).await;
+        let mut report = BenchReport {
+            brush_version: env!("CARGO_PKG_VERSION"),
+            backend,
+            adapter,
+            driver,
+            scenes: vec![],
+        };
+        let report_dir = args
+            .report
+            .parent()
+            .map_or_else(PathBuf::new, Path::to_path_buf);
+
+        let suites = if args.suite.is_empty() {
+            Suite::ALL.to_vec()
+        } else {
+            args.suite.clone()
+        };
+        for suite in suites {
+            for &scene in suite.scenes() {
+                if !args.scenes.is_empty() && !args.scenes.iter().any(|s| s == scene) {
+                    continue;
+                }
+                let Some(path) = find_scene(&args.data_dir, suite, scene) else {
+                    log::warn!("Skipping {scene}, it's not in {:?}", args.data_dir);
+                    continue;
+                };
+
+                for &variant in &args.variants {
+                    let mut process = args.process.clone();
+                    suite.apply_protocol(scene, &mut process);
+                    variant.apply(&mut process);
+                    let config = &mut process.process_config;
+                    config.export_every = process.train_config.total_steps;
+                    config.export_path = report_dir
+                        .join(suite.name())
+                        .join(scene)
+                        .join(variant.name())
+                        .to_string_lossy()
+                        .into_owned();
+
+                    let scene_report =
+                        run_scene(suite, scene, variant, &path, process, device.clone()).await?;
+                    println!(
+                        "{}/{scene} ({}): PSNR {:.2}, SSIM {:.3}, {} splats in {:.0}s",
+                        suite.name(),
+                        variant.name(),
+                        scene_report.psnr,
+                        scene_report.ssim,
+                        scene_report.num_gs,
+                        scene_report.train_time_s
+                    );
+                    report.scenes.push(scene_report);
+                    // Write the report as it grows, so a failing scene doesn't lose the others.
+                    let json = serde_json::to_string_pretty(&report)?;
+                    rrfd::write_atomic(&args.report, json.as_bytes()).await?;
+                }
+            }
+        }
+
+        if report.scenes.is_empty() {
+            anyhow::bail!("None of the scenes were found in {:?}", args.data_dir);
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,128 @@
+use serde::Serialize;
+
+/// Quality of the splats at a step of training.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct CurvePoint {
+    pub step: u32,
+    /// Time spent training up to this step, without evaluations.
+    pub train_time_s: f32,
+    pub psnr: f32,
+    pub ssim: f32,
+}
+
+/// Results of training a scene. Final metrics use the key names of the stats of gsplat, so
+/// reports can be compared directly.
+#[derive(Serialize, Debug, Clone)]
+pub struct SceneReport {
+    pub suite: &'static str,
+    pub scene: String,
+    /// Name of the settings the scene was trained with, see `Variant`.
+    pub variant: &'static str,
+    pub steps: u32,
+    pub train_time_s: f32,
+    #[serde(rename = "num_GS")]
+    pub num_gs: u32,
+    pub psnr: f32,
+    pub ssim: f32,
+    /// Time to quality, at each evaluation.
+    pub curve: Vec<CurvePoint>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BenchReport {
+    pub brush_version: &'static str,
+    /// Graphics API the scenes were trained on.
+    pub backend: String,
+    pub adapter: String,
+    pub driver: String,
+    pub scenes: Vec<SceneReport>,
+}
+
+/// Builds the report of a scene as training progresses.
+#[derive(Default)]
+pub struct SceneRecorder {
+    curve: Vec<CurvePoint>,
+    steps: u32,
+    train_time_s: f32,
+    num_gs: u32,
+}
+
+impl SceneRecorder {
+    /// Record that training is at `step`.
+    pub fn train_step(&mut self, step: u32, train_time_s: f32, num_gs: u32) {
+        self.steps = step;
+        self.train_time_s = train_time_s;
+        self.num_gs = num_gs;
+        // Evaluations are sent before the step they're at, so fill in their time once it's known.
+        for point in &mut self.curve {
+            if point.step <= step && point.train_time_s.is_nan() {
+                point.train_time_s = train_time_s;
+            }
+        }
+    }
+
+    /// Record an evaluation at `step`.
+    pub fn eval(&mut self, step: u32, psnr: f32, ssim: f32) {
+        self.curve.push(CurvePoint {
+            step,
+            train_time_s: f32::NAN,
+            psnr,
+            ssim,
+        });
+    }
+
+    pub fn finish(
+        mut self,
+        suite: &'static str,
+        scene: &str,
+        variant: &'static str,
+    ) -> anyhow::Result<SceneReport> {
+        let Some(last) = self.curve.last().copied() else {
+            anyhow::bail!("Scene {scene} was never evaluated, does it have eval images?");
+        };
+        for point in &mut self.curve {
+            if point.train_time_s.is_nan() {
+                point.train_time_s = self.train_time_s;
+            }
+        }
+        Ok(SceneReport {
+            suite,
+            scene: scene.to_owned(),
+            variant,
+            steps: self.steps,
+            train_time_s: self.train_time_s,
+            num_gs: self.num_gs,
+            psnr: last.psnr,
+            ssim: last.ssim,
+            curve: self.curve,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evals_get_the_time_of_their_step() {
+        let mut recorder = SceneRecorder::default();
+        recorder.train_step(5, 1.0, 100);
+        recorder.eval(10, 20.0, 0.5);
+        recorder.train_step(10, 2.0, 120);
+        recorder.eval(15, 25.0, 0.7);
+
+        let report = recorder
+            .finish("suite", "scene", "baseline")
+            .expect("Was evaluated");
+        assert_eq!(report.curve[0].train_time_s, 2.0);
+        // The last eval has no step after it, so gets the total time.
+        assert_eq!(report.curve[1].train_time_s, 2.0);
+        assert_eq!((report.psnr, report.ssim, report.num_gs), (25.0, 0.7, 120));
+
+        assert!(
+            SceneRecorder::default()
+                .finish("suite", "scene", "baseline")
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use brush_process::config::ProcessArgs;
+use clap::ValueEnum;
+
+/// Training settings to compare on the scenes, on top of the settings the bench is run with.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    /// The settings as given.
+    Baseline,
+    /// Random splat dropout, see `TrainConfig::dropout_rate`.
+    Dropout,
+}
+
+impl Variant {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Baseline => "baseline",
+            Self::Dropout => "dropout",
+        }
+    }
+
+    pub fn apply(self, args: &mut ProcessArgs) {
+        match self {
+            Self::Baseline => {}
+            Self::Dropout => {
+                let config = &mut args.train_config;
+                config.dropout_rate = 0.1;
+                // Settle without dropout for the second half of training.
+                config.dropout_end = config.total_steps / 2;
+            }
+        }
+    }
+}
+
+/// A set of standard scenes, and how they're evaluated.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Suite {
+    /// The 9 scenes of Mip-NeRF 360.
+    MipNerf360,
+    /// The truck and train scenes of Tanks and Temples, as used by 3D Gaussian Splatting.
+    TanksTemples,
+}
+
+impl Suite {
+    pub const ALL: [Self; 2] = [Self::MipNerf360, Self::TanksTemples];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::MipNerf360 => "mipnerf360",
+            Self::TanksTemples => "tandt",
+        }
+    }
+
+    /// Names of the scenes, as the folders they're in.
+    pub fn scenes(self) -> &'static [&'static str] {
+        match self {
+            Self::MipNerf360 => &[
+                "bicycle", "flowers", "garden", "stump", "treehill", "room", "counter", "kitchen",
+                "bonsai",
+            ],
+            Self::TanksTemples => &["truck", "train"],
+        }
+    }
+
+    /// Set up the dataset options of `scene` like the published evaluations. A `brush_scene.json`
+    /// in the scene still overrides these.
+    pub fn apply_protocol(self, scene: &str, args: &mut ProcessArgs) {
+        let load = &mut args.load_config;
+        // All hold out every 8th image.
+        load.eval_split_every = Some(8);
+        match self {
+            // Outdoor scenes are evaluated at exactly a quarter of their resolution, indoor scenes
+            // at half.
+            Self::MipNerf360 => {
+                // The factor is the protocol, don't cap the images any further.
+                load.max_resolution = u32::MAX;
+                load.downscale = Some(match scene {
+                    "room" | "counter" | "kitchen" | "bonsai" => 2,
+                    _ => 4,
+                });
+            }
+            Self::TanksTemples => load.max_resolution = 1600,
+        }
+    }
+}
@@ -0,0 +1,215 @@
+// Draws the splat renders of each camera into its view, as a pass of Bevy's 3D render graph. The
+// splats are drawn after the transparent pass, and hidden where the depth buffer has geometry in
+// front of them.
+use bevy::{
+    asset::{load_internal_asset, weak_handle},
+    core_pipeline::{
+        core_3d::graph::{Core3d, Node3d},
+        fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    },
+    ecs::query::QueryItem,
+    image::BevyDefault,
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderSet,
+        camera::ExtractedCamera,
+        extract_component::{ExtractComponent, ExtractComponentPlugin},
+        render_asset::RenderAssets,
+        render_graph::{
+            NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner,
+        },
+        render_resource::{
+            BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, BlendState,
+            CachedRenderPipelineId, ColorTargetState, ColorWrites, FragmentState, PipelineCache,
+            RenderPassDescriptor, RenderPipelineDescriptor, Shader, ShaderStages,
+            SpecializedRenderPipeline, SpecializedRenderPipelines, TextureFormat,
+            TextureSampleType,
+            binding_types::{texture_2d, texture_depth_2d},
+        },
+        renderer::{RenderContext, RenderDevice},
+        texture::GpuImage,
+        view::{ExtractedView, ViewDepthTexture, ViewTarget},
+    },
+};
+
+const COMPOSITE_SHADER_HANDLE: Handle<Shader> =
+    weak_handle!("0f3b6a2e-7c1d-4e58-9a64-2d8b5c71e903");
+
+/// The images a camera draws its splats from: the premultiplied sRGB colors of the splats, and
+/// their depth in the reversed Z of Bevy's depth buffer.
+#[derive(Component, Clone, ExtractComponent)]
+pub(crate) struct SplatImages {
+    pub color: Handle<Image>,
+    pub depth: Handle<Image>,
+}
+
+pub(crate) struct CompositePlugin;
+
+impl Plugin for CompositePlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            COMPOSITE_SHADER_HANDLE,
+            "composite.wgsl",
+            Shader::from_wgsl
+        );
+        app.add_plugins(ExtractComponentPlugin::<SplatImages>::default());
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<SpecializedRenderPipelines<CompositePipeline>>()
+            .add_systems(Render, prepare_pipelines.in_set(RenderSet::Prepare))
+            .add_render_graph_node::<ViewNodeRunner<CompositeNode>>(Core3d, CompositePass)
+            .add_render_graph_edges(
+                Core3d,
+                (
+                    Node3d::MainTransparentPass,
+                    CompositePass,
+                    Node3d::EndMainPass,
+                ),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app.init_resource::<CompositePipeline>();
+    }
+}
+
+#[derive(Resource)]
+struct CompositePipeline {
+    layout: BindGroupLayout,
+}
+
+impl FromWorld for CompositePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let layout = world.resource::<RenderDevice>().create_bind_group_layout(
+            "splat_composite_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    texture_depth_2d(),
+                ),
+            ),
+        );
+        Self { layout }
+    }
+}
+
+impl SpecializedRenderPipeline for CompositePipeline {
+    // Whether the view is HDR.
+    type Key = bool;
+
+    fn specialize(&self, hdr: bool) -> RenderPipelineDescriptor {
+        let format = if hdr {
+            ViewTarget::TEXTURE_FORMAT_HDR
+        } else {
+            TextureFormat::bevy_default()
+        };
+        RenderPipelineDescriptor {
+            label: Some("splat_composite_pipeline".into()),
+            layout: vec![self.layout.clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: COMPOSITE_SHADER_HANDLE,
+                shader_defs: vec![],
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: default(),
+            depth_stencil: None,
+            multisample: default(),
+            push_constant_ranges: vec![],
+            zero_initialize_workgroup_memory: false,
+        }
+    }
+}
+
+#[derive(Component)]
+struct CompositePipelineId(CachedRenderPipelineId);
+
+fn prepare_pipelines(
+    mut commands: Commands,
+    cache: Res<PipelineCache>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<CompositePipeline>>,
+    pipeline: Res<CompositePipeline>,
+    views: Query<(Entity, &ExtractedView, &Msaa), With<SplatImages>>,
+) {
+    for (entity, view, msaa) in &views {
+        if *msaa != Msaa::Off {
+            warn_once!("Splats are only drawn for cameras without MSAA");
+            continue;
+        }
+        let id = pipelines.specialize(&cache, &pipeline, view.hdr);
+        commands.entity(entity).insert(CompositePipelineId(id));
+    }
+}
+
+#[derive(RenderLabel, Debug, Clone, Hash, PartialEq, Eq)]
+struct CompositePass;
+
+#[derive(Default)]
+struct CompositeNode;
+
+impl ViewNode for CompositeNode {
+    type ViewQuery = (
+        &'static ExtractedCamera,
+        &'static ViewTarget,
+        &'static ViewDepthTexture,
+        &'static SplatImages,
+        &'static CompositePipelineId,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (camera, target, depth, images, pipeline_id): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let gpu_images = world.resource::<RenderAssets<GpuImage>>();
+        let (Some(pipeline), Some(color), Some(splat_depth)) = (
+            world
+                .resource::<PipelineCache>()
+                .get_render_pipeline(pipeline_id.0),
+            gpu_images.get(&images.color),
+            gpu_images.get(&images.depth),
+        ) else {
+            return Ok(());
+        };
+
+        let bind_group = render_context.render_device().create_bind_group(
+            "splat_composite_bind_group",
+            &world.resource::<CompositePipeline>().layout,
+            &BindGroupEntries::sequential((
+                &color.texture_view,
+                &splat_depth.texture_view,
+                depth.view(),
+            )),
+        );
+        let mut pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("splat_composite_pass"),
+            color_attachments: &[Some(target.get_color_attachment())],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        if let Some(viewport) = camera.viewport.as_ref() {
+            pass.set_camera_viewport(viewport);
+        }
+        pass.set_render_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+@group(0) @binding(0) var splat_color: texture_2d<f32>;
+@group(0) @binding(1) var splat_depth: texture_2d<f32>;
+@group(0) @binding(2) var scene_depth: texture_depth_2d;
+
+fn srgb_to_linear(color: vec3f) -> vec3f {
+    return select(pow((color + 0.055) / 1.055, vec3f(2.4)), color / 12.92, color <= vec3f(0.04045));
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let size = vec2i(textureDimensions(splat_color));
+    let pixel = min(vec2i(in.uv * vec2f(size)), size - 1);
+    let color = textureLoad(splat_color, pixel, 0);
+    // Depths are reversed, so geometry in front of the splats has a larger depth.
+    let hidden = textureLoad(scene_depth, vec2i(in.position.xy), 0) > textureLoad(splat_depth, pixel, 0).r;
+    if color.a == 0.0 || hidden {
+        discard;
+    }
+    // The splats are blended in sRGB, and the view is linear.
+    return vec4f(srgb_to_linear(color.rgb / color.a) * color.a, color.a);
+}
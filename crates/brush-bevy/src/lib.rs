@@ -0,0 +1,395 @@
+// Splats in Bevy: splat files load through the asset server, and entities with a `SplatScene`
+// are drawn for cameras with a `SplatCamera`, placed by their transforms.
+//
+// Bevy renders with its own wgpu device, of an older wgpu than burn's, so the two can't share it.
+// Splats are rendered on a device of their own instead, with their color and depth read back in the
+// frame they're drawn in. A pass in Bevy's 3D render graph then draws them into the view of the
+// camera, hidden behind geometry in front of them.
+mod composite;
+
+use std::f32::consts::PI;
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+        view::VisibilitySystems,
+    },
+    tasks::{block_on, futures_lite::StreamExt},
+    transform::TransformSystem,
+};
+use brush_dataset::{
+    splat_formats::{SplatFormat, load_splats},
+    splat_import::SplatImportError,
+};
+use brush_render::{
+    MainBackend,
+    camera::{Camera as BrushCamera, Culling, focal_to_fov, fov_to_focal},
+    composition::{LayerTransform, compose_splats},
+    gaussian_splats::Splats,
+};
+use burn_wgpu::WgpuDevice;
+use composite::{CompositePlugin, SplatImages};
+use thiserror::Error;
+
+/// Loads splat files through the asset server, and draws them for cameras with a
+/// [`SplatCamera`].
+pub struct SplatPlugin;
+
+impl Plugin for SplatPlugin {
+    fn build(&self, app: &mut App) {
+        let device = block_on(brush_render::burn_init_setup());
+        app.add_plugins(CompositePlugin)
+            .init_asset::<SplatAsset>()
+            .register_asset_loader(SplatLoader { device })
+            .init_resource::<ComposedSplats>()
+            .add_systems(
+                PostUpdate,
+                (add_views, remove_views, compose_scenes, render_views)
+                    .chain()
+                    .after(TransformSystem::TransformPropagate)
+                    .after(VisibilitySystems::VisibilityPropagate),
+            );
+    }
+}
+
+/// Splats loaded from a file. Animated files hold their first frame.
+#[derive(Asset, TypePath)]
+pub struct SplatAsset {
+    pub splats: Splats<MainBackend>,
+    /// The up axis the file was saved with, if it has one. Splats trained on COLMAP data usually
+    /// have -Y up, and need a rotation to stand upright in Bevy.
+    pub up_axis: Option<glam::Vec3>,
+}
+
+#[derive(Debug, Error)]
+pub enum SplatLoadError {
+    #[error("Failed to read splat file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to import splats: {0}")]
+    Import(#[from] SplatImportError),
+    #[error("No splats in file")]
+    Empty,
+}
+
+struct SplatLoader {
+    device: WgpuDevice,
+}
+
+impl AssetLoader for SplatLoader {
+    type Asset = SplatAsset;
+    type Settings = ();
+    type Error = SplatLoadError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<SplatAsset, SplatLoadError> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data).await?;
+        let format = SplatFormat::from_path(load_context.path())
+            .unwrap_or_else(|| SplatFormat::from_header(&data));
+
+        let mut stream = load_splats(
+            format,
+            std::io::Cursor::new(data),
+            None,
+            self.device.clone(),
+        );
+        let mut loaded = None;
+        while let Some(message) = stream.next().await {
+            let message = message?;
+            // Animated files continue with the next frames.
+            if message.meta.current_frame > 0 {
+                break;
+            }
+            loaded = Some(message);
+        }
+        let loaded = loaded.ok_or(SplatLoadError::Empty)?;
+        Ok(SplatAsset {
+            splats: loaded.splats,
+            up_axis: loaded.meta.up_axis,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ply", "splat", "spz", "bsplat", "bsz"]
+    }
+}
+
+/// Splats to draw, placed by the transform of the entity.
+///
+/// Splats can only be scaled uniformly, so scenes with a non-uniform scale aren't drawn.
+#[derive(Component, Debug, Clone, Default)]
+#[require(Transform, Visibility)]
+pub struct SplatScene(pub Handle<SplatAsset>);
+
+/// Draws the visible [`SplatScene`]s in what this camera renders, behind the geometry in front of
+/// them. Only perspective 3D cameras without MSAA draw splats, leaving out splats outside of their
+/// near and far plane. Adding this turns MSAA off for the camera.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct SplatCamera {
+    /// Leave out splats drawn with a radius below this many pixels, see [`Culling::min_radius`].
+    pub min_radius: f32,
+}
+
+// What the splats of a camera were last rendered from, to only render again when something changed.
+#[derive(Component, Default)]
+struct SplatView {
+    rendered: Option<(BrushCamera, glam::UVec2, u64)>,
+}
+
+// All visible scenes in one set of splats, which only changes when the scenes do.
+#[derive(Resource, Default)]
+struct ComposedSplats {
+    layers: Vec<(AssetId<SplatAsset>, LayerTransform)>,
+    splats: Option<Splats<MainBackend>>,
+    generation: u64,
+}
+
+fn splat_image(size: glam::UVec2, data: Vec<u8>, format: TextureFormat) -> Image {
+    Image::new(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        format,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+}
+
+// Bevy's perspective projections map view depth `z` to `near / z`, so nearer is larger and
+// nothing is at 0.
+fn reversed_depth(depth: f32, near: f32) -> f32 {
+    near / depth.max(near)
+}
+
+async fn render_frame(
+    splats: &Splats<MainBackend>,
+    camera: &BrushCamera,
+    size: glam::UVec2,
+    near: f32,
+) -> (Image, Image) {
+    let (img, _) = splats.render(camera, size, false);
+    let (depth, _) = splats.render_depth(camera, size);
+    let color = img.into_data_async().await;
+    let depth = depth.into_data_async().await;
+    let depth = depth
+        .iter::<f32>()
+        .flat_map(|depth| reversed_depth(depth, near).to_ne_bytes())
+        .collect();
+    (
+        splat_image(size, color.as_bytes().to_vec(), TextureFormat::Rgba8Unorm),
+        splat_image(size, depth, TextureFormat::R32Float),
+    )
+}
+
+fn empty_frame() -> (Image, Image) {
+    (
+        splat_image(glam::UVec2::ONE, vec![0; 4], TextureFormat::Rgba8Unorm),
+        splat_image(glam::UVec2::ONE, vec![0; 4], TextureFormat::R32Float),
+    )
+}
+
+fn add_views(
+    mut commands: Commands,
+    mut cameras: Query<(Entity, Option<&mut Camera3d>), Added<SplatCamera>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for (camera, camera_3d) in &mut cameras {
+        let (color, depth) = empty_frame();
+        commands.entity(camera).insert((
+            SplatView::default(),
+            SplatImages {
+                color: images.add(color),
+                depth: images.add(depth),
+            },
+            // The splats are tested against the depth of single sampled views.
+            Msaa::Off,
+        ));
+        if let Some(mut camera_3d) = camera_3d {
+            let mut usages = TextureUsages::from(camera_3d.depth_texture_usages);
+            usages |= TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING;
+            camera_3d.depth_texture_usages = usages.into();
+        }
+    }
+}
+
+fn remove_views(mut commands: Commands, mut removed: RemovedComponents<SplatCamera>) {
+    for camera in removed.read() {
+        if let Ok(mut camera) = commands.get_entity(camera) {
+            camera.remove::<(SplatView, SplatImages)>();
+        }
+    }
+}
+
+// Where the splats of a scene go, from the transform of its entity. Splats can't be stretched
+// along an axis, so there's nowhere for them with a non-uniform or mirroring scale.
+fn layer_transform(transform: &GlobalTransform) -> Option<LayerTransform> {
+    let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+    let uniform = scale.max_element() - scale.min_element() <= 1e-4 * scale.max_element();
+    (scale.min_element() > 0.0 && uniform).then(|| LayerTransform {
+        translation: glam::Vec3::from_array(translation.to_array()),
+        rotation: glam::Quat::from_array(rotation.to_array()),
+        scale: scale.x,
+    })
+}
+
+fn compose_scenes(
+    scenes: Query<(Entity, &SplatScene, &GlobalTransform, &InheritedVisibility)>,
+    assets: Res<Assets<SplatAsset>>,
+    mut composed: ResMut<ComposedSplats>,
+) {
+    let layers: Vec<_> = scenes
+        .iter()
+        .filter(|(_, scene, _, visibility)| visibility.get() && assets.contains(&scene.0))
+        .filter_map(|(entity, scene, transform, _)| {
+            let Some(transform) = layer_transform(transform) else {
+                warn_once!("Splat scene {entity} has a non-uniform scale and isn't drawn");
+                return None;
+            };
+            Some((scene.0.id(), transform))
+        })
+        .collect();
+    // Assets change when they load or reload.
+    if layers == composed.layers && !assets.is_changed() {
+        return;
+    }
+    composed.splats = compose_splats(
+        layers
+            .iter()
+            .filter_map(|(id, transform)| Some((assets.get(*id)?.splats.clone(), *transform))),
+    );
+    composed.layers = layers;
+    composed.generation += 1;
+}
+
+// The camera of the splats for a Bevy camera. Bevy cameras look along -Z with +Y up, where brush
+// cameras look along +Z with +Y down.
+fn splat_camera(
+    transform: &GlobalTransform,
+    projection: &PerspectiveProjection,
+    size: glam::UVec2,
+    min_radius: f32,
+) -> BrushCamera {
+    let (_, rotation, translation) = transform.to_scale_rotation_translation();
+    let rotation = glam::Quat::from_array(rotation.to_array()) * glam::Quat::from_rotation_x(PI);
+    let fov_y = f64::from(projection.fov);
+    // Keep pixels square.
+    let fov_x = focal_to_fov(fov_to_focal(fov_y, size.y), size.x);
+    BrushCamera::new(
+        glam::Vec3::from_array(translation.to_array()),
+        rotation,
+        fov_x,
+        fov_y,
+        glam::vec2(0.5, 0.5),
+    )
+    .with_culling(Culling {
+        near: projection.near,
+        far: projection.far,
+        min_radius,
+    })
+}
+
+fn render_views(
+    mut views: Query<(
+        &Camera,
+        &GlobalTransform,
+        &Projection,
+        &SplatCamera,
+        &SplatImages,
+        &mut SplatView,
+    )>,
+    composed: Res<ComposedSplats>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for (camera, transform, projection, settings, splat_images, mut view) in &mut views {
+        let (Some(size), Projection::Perspective(projection)) =
+            (camera.physical_viewport_size(), projection)
+        else {
+            continue;
+        };
+        if size.x == 0 || size.y == 0 {
+            continue;
+        }
+        let size = glam::uvec2(size.x, size.y);
+        let camera = splat_camera(transform, projection, size, settings.min_radius);
+        let state = (camera.clone(), size, composed.generation);
+        if view.rendered.as_ref() == Some(&state) {
+            continue;
+        }
+        view.rendered = Some(state);
+
+        // Wait for the render, so the splats are drawn in the frame of the camera they're
+        // rendered from.
+        let (color, depth) = match &composed.splats {
+            Some(splats) => block_on(render_frame(splats, &camera, size, projection.near)),
+            // Nothing to draw, so clear what was drawn before.
+            None => empty_frame(),
+        };
+        if let Some(image) = images.get_mut(&splat_images.color) {
+            *image = color;
+        }
+        if let Some(image) = images.get_mut(&splat_images.depth) {
+            *image = depth;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::camera::CameraProjection;
+
+    #[test]
+    fn splat_camera_looks_down_negative_z() {
+        let transform = GlobalTransform::from(
+            Transform::from_xyz(1.0, 2.0, 3.0).looking_at(Vec3::new(1.0, 2.0, -7.0), Vec3::Y),
+        );
+        let projection = PerspectiveProjection::default();
+        let camera = splat_camera(&transform, &projection, glam::uvec2(200, 100), 0.0);
+        let local = camera.world_to_local();
+        // In front of the Bevy camera, which is in front of the splat camera.
+        let ahead = local.transform_point3(glam::vec3(1.0, 2.0, -7.0));
+        assert!((ahead - glam::vec3(0.0, 0.0, 10.0)).length() < 1e-4);
+        // Bevy's up is the splat camera's -Y.
+        let above = local.transform_point3(glam::vec3(1.0, 3.0, 3.0));
+        assert!((above - glam::vec3(0.0, -1.0, 0.0)).length() < 1e-4);
+        // Pixels stay square on a wide view.
+        let focal = camera.focal(glam::uvec2(200, 100));
+        assert!((focal.x - focal.y).abs() < 1e-3);
+    }
+
+    #[test]
+    fn non_uniform_scales_have_no_layer() {
+        let uniform = GlobalTransform::from(Transform::from_scale(Vec3::splat(2.0)));
+        assert_eq!(
+            layer_transform(&uniform).map(|layer| layer.scale),
+            Some(2.0)
+        );
+        let stretched = GlobalTransform::from(Transform::from_scale(Vec3::new(2.0, 1.0, 2.0)));
+        assert!(layer_transform(&stretched).is_none());
+        let mirrored = GlobalTransform::from(Transform::from_scale(Vec3::new(-1.0, 1.0, 1.0)));
+        assert!(layer_transform(&mirrored).is_none());
+    }
+
+    #[test]
+    fn depth_is_reversed_like_bevy() {
+        let near = 0.1;
+        let projection = PerspectiveProjection { near, ..default() };
+        let clip_from_view = projection.get_clip_from_view();
+        for depth in [0.5, 2.0, 100.0] {
+            let clip = clip_from_view * Vec4::new(0.0, 0.0, -depth, 1.0);
+            assert!((reversed_depth(depth, near) - clip.z / clip.w).abs() < 1e-6);
+        }
+        // Splats can't be nearer than the near plane.
+        assert_eq!(reversed_depth(0.0, near), 1.0);
+    }
+}
@@ -1,10 +1,15 @@
 #![recursion_limit = "256"]
 
-use brush_process::{config::ProcessArgs, message::ProcessMessage};
+use brush_dataset::{scene_transform::SceneTransform, watermark::Watermark};
+use brush_process::{
+    bundle::BundleArgs, compress::CompressArgs, config::ProcessArgs, mesh, message::ProcessMessage,
+    planner::check_output_dir, regression::RegressionArgs, segment::SegmentArgs,
+};
+use brush_remote::render_service::ServeArgs;
 use brush_vfs::DataSource;
-use clap::{Error, Parser, builder::ArgPredicate, error::ErrorKind};
-use indicatif::{ProgressBar, ProgressStyle};
-use std::time::Duration;
+use clap::{Error, Parser, Subcommand, builder::ArgPredicate, error::ErrorKind};
+use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
+use std::{net::SocketAddr, path::Path, time::Duration};
 use tokio_stream::{Stream, StreamExt};
 
 #[derive(Parser)]
@@ -27,13 +32,51 @@ pub struct Cli {
     )]
     pub with_viewer: bool,
 
+    /// Also show the scene in the headset of the OpenXR runtime, in the viewer. Needs a build
+    /// with the `xr` feature.
+    #[arg(long, default_value = "false")]
+    pub xr: bool,
+
+    /// Serve the training run on this address, eg. 0.0.0.0:9871, so viewers elsewhere can follow
+    /// and steer it by opening ws://<host>:<port>/?token=<token>. Addresses other than loopback
+    /// need a --control-token.
+    #[arg(long, help_heading = "Process options")]
+    pub control_addr: Option<SocketAddr>,
+
+    /// Token viewers have to give to follow the run served on --control-addr. Use letters and
+    /// digits, so it can be put in a URL as is.
+    #[arg(long, help_heading = "Process options", requires = "control_addr")]
+    pub control_token: Option<String>,
+
     #[clap(flatten)]
     pub process: ProcessArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Train a tiny built in scene with a fixed seed, and fail if the PSNR or splat count are off.
+    /// Catches regressions in the training math without needing a dataset.
+    Regress(RegressionArgs),
+    /// Pack the cameras and small copies of the images of a dataset into a zip, with the config
+    /// and log of a run, to attach to bug reports about it.
+    Bundle(BundleArgs),
+    /// Serve an HTTP API to upload splat files and render them from any camera, for thumbnail
+    /// servers and web backends.
+    Serve(ServeArgs),
+    /// Compress a splat file to a small .bsz file for phones and the web, and report how much it
+    /// changes how the scene looks.
+    Compress(CompressArgs),
+    /// Write the splats of a scene trained with feature maps whose features are like a query
+    /// feature, eg. the CLIP embedding of a prompt, to segment an object out of the scene.
+    Segment(SegmentArgs),
 }
 
 impl Cli {
     pub fn validate(self) -> Result<Self, Error> {
-        if !self.with_viewer && self.source.is_none() {
+        if !self.with_viewer && self.source.is_none() && self.command.is_none() {
             return Err(Error::raw(
                 ErrorKind::MissingRequiredArgument,
                 "When --with-viewer is false, --source must be provided",
@@ -118,6 +161,39 @@ pub async fn process_ui(
     let mut stream = std::pin::pin!(stream);
     let mut duration = Duration::from_secs(0);
 
+    // Latest splats, to render or read the watermark of after loading or training.
+    let render_config = &process_args.render_config;
+    let read_watermark = process_args.process_config.read_watermark;
+    let mesh_out = &process_args.mesh_config.mesh_out;
+    let keep_splats = render_config.is_enabled()
+        || render_config.render_shots.is_some()
+        || read_watermark
+        || mesh_out.is_some();
+    let mut last_splats = None;
+    let mut up_axis = None;
+
+    // These are written after training, check they can be before it starts.
+    let folder_of = |path: &str| {
+        Path::new(path)
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .to_owned()
+    };
+    if let Some(mesh_out) = mesh_out {
+        check_output_dir(&folder_of(mesh_out), 0).await?;
+    }
+    if render_config.is_enabled() {
+        check_output_dir(&folder_of(&render_config.render_out), 0).await?;
+    }
+    if render_config.render_shots.is_some() {
+        check_output_dir(Path::new(&render_config.render_shots_out), 0).await?;
+    }
+
+    // Training views to extract a mesh from, and the transform back to the frame of the dataset.
+    let mut mesh_cameras = vec![];
+    let mut export_transform = SceneTransform::IDENTITY;
+
     // TODO: Unify logging & CLI UI somehow.
     while let Some(msg) = stream.next().await {
         let msg = match msg {
@@ -133,18 +209,48 @@ pub async fn process_ui(
             ProcessMessage::NewSource => {
                 main_spinner.set_message("Starting process...");
             }
+            ProcessMessage::PassphraseRequired { retry, reply } => {
+                // There's no way to ask again, so only try the passphrase once.
+                match &process_args.process_config.passphrase {
+                    Some(passphrase) if !retry => {
+                        let _ = reply.try_send(passphrase.clone());
+                    }
+                    Some(_) => {
+                        let _ = sp.println("🔒 Wrong passphrase");
+                    }
+                    None => {
+                        let _ = sp.println("🔒 Source is encrypted, pass it with --passphrase");
+                    }
+                }
+            }
             ProcessMessage::StartLoading { training } => {
-                if !training {
+                if !training && !keep_splats {
                     // Display a big warning saying viewing splats from the CLI doesn't make sense.
                     let _ = sp.println("❌ Only training is supported in the CLI (try passing --with-viewer to view a splat)");
                     break;
                 }
                 main_spinner.set_message("Loading data...");
             }
-            ProcessMessage::ViewSplats { .. } => {
-                // I guess we're already showing a warning.
+            ProcessMessage::ViewSplats {
+                splats,
+                up_axis: splats_up,
+                frame,
+                ..
+            } => {
+                // Only the first frame of animated splats is rendered.
+                if keep_splats && frame == 0 {
+                    last_splats = Some(splats);
+                    up_axis = splats_up.or(up_axis);
+                }
             }
             ProcessMessage::Dataset { dataset } => {
+                mesh_cameras = dataset
+                    .train
+                    .views
+                    .iter()
+                    .map(|v| v.camera.clone())
+                    .collect();
+                export_transform = dataset.transform.inverse();
                 let train_views = dataset.train.views.len();
                 let eval_views = dataset.eval.as_ref().map_or(0, |v| v.views.len());
                 log::info!("Loaded dataset with {train_views} training, {eval_views} eval views",);
@@ -164,15 +270,40 @@ pub async fn process_ui(
                 main_spinner.set_message("Completed loading");
                 stats_spinner.set_message("Completed loading");
             }
+            ProcessMessage::RunPlan { plan } => {
+                let _ = sp.println(format!(
+                    "⏱ Estimated {} of training, up to {} of GPU memory",
+                    HumanDuration(plan.duration),
+                    HumanBytes(plan.peak_memory)
+                ));
+                if plan.exceeds_budget() {
+                    let _ = sp.println(
+                        "⚠ This run is likely to run out of GPU memory. Try lowering --max-splats or --max-resolution",
+                    );
+                }
+            }
             ProcessMessage::TrainStep {
+                splats,
                 iter,
                 total_elapsed,
                 ..
             } => {
+                if keep_splats {
+                    last_splats = Some(splats);
+                }
                 main_spinner.set_message("Training");
                 train_progress.set_position(iter as u64);
                 duration = total_elapsed;
             }
+            ProcessMessage::RemoteTrainStep { iter, .. } => {
+                train_progress.set_position(iter as u64);
+            }
+            ProcessMessage::Snapshot { .. } => {
+                // Snapshots are only useful for replaying in the viewer.
+            }
+            ProcessMessage::Exported { .. } => {
+                // Exports go straight to disk here.
+            }
             ProcessMessage::RefineStep {
                 cur_splat_count,
                 iter,
@@ -195,6 +326,54 @@ pub async fn process_ui(
         }
     }
 
+    if let Some(splats) = last_splats {
+        if read_watermark {
+            let key = process_args.process_config.watermark_key;
+            match Watermark::read_splats(key, &splats).await {
+                Some(reading) if reading.confidence > 4.0 => {
+                    let _ = sp.println(format!(
+                        "🔏 Watermark {} (confidence {:.1})",
+                        reading.payload, reading.confidence
+                    ));
+                }
+                _ => {
+                    let _ = sp.println("🔏 No watermark found with this key");
+                }
+            }
+        }
+
+        if render_config.is_enabled() {
+            main_spinner.set_message(format!("Rendering to {}", render_config.render_out));
+            brush_process::render_video::render_video(&splats, up_axis, render_config).await?;
+            let _ = sp.println(format!("🎬 Rendered to {}", render_config.render_out));
+        }
+
+        if render_config.render_shots.is_some() {
+            main_spinner.set_message(format!(
+                "Rendering stills to {}",
+                render_config.render_shots_out
+            ));
+            let count =
+                brush_process::render_video::render_shot_list(&splats, render_config).await?;
+            let _ = sp.println(format!(
+                "📸 Rendered {count} stills to {}",
+                render_config.render_shots_out
+            ));
+        }
+
+        if let Some(mesh_out) = mesh_out {
+            main_spinner.set_message("Extracting mesh");
+            let mut mesh =
+                mesh::extract_mesh(&splats, &mesh_cameras, &process_args.mesh_config).await?;
+            mesh.transform(&export_transform);
+            mesh::write_mesh(&mesh, Path::new(mesh_out)).await?;
+            let _ = sp.println(format!(
+                "🧊 Wrote mesh with {} triangles to {mesh_out}",
+                mesh.triangles.len()
+            ));
+        }
+    }
+
     let duration_secs = Duration::from_secs(duration.as_secs());
     let _ = sp.println(format!(
         "Training took {}",
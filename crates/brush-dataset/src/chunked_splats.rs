@@ -0,0 +1,663 @@
+// A splat file for scenes too large to download before viewing them. The splats are sorted along a
+// space filling curve and cut into chunks of nearby splats, with an index of where each chunk is
+// and what it covers at the start of the file. Loaders read the index, and then read chunks in
+// any order, those in view of the camera first.
+//
+// The file is laid out as:
+// - a header: the magic `bsplat`, the version as a u16, the SH degree and the number of chunks
+//   as u32s.
+// - the index: for each chunk, its offset in the file as a u64, its length in bytes and number of
+//   splats as u32s, and the min and max corners of the bounds of its splats as 3 f32s each.
+// - the chunks: each a gzipped list of f32s, with the means, rotations (w first), log scales, raw
+//   opacities and SH coefficients ([coeffs, channel] per splat) of all its splats after another.
+//
+// All numbers are little endian.
+use std::{
+    io::{Read, Write},
+    pin::Pin,
+};
+
+use async_fn_stream::try_fn_stream;
+use brush_render::{
+    MainBackend,
+    camera::{Camera, CameraModel},
+    gaussian_splats::Splats,
+};
+use brush_vfs::{DynStream, SendNotWasm, ranges::RangeSource};
+use burn::{
+    backend::wgpu::WgpuDevice,
+    prelude::Backend,
+    tensor::{Tensor, TensorData},
+};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use glam::Vec3;
+use tokio::sync::watch;
+
+use crate::splat_import::{ParseMetadata, SplatImportError, SplatMessage};
+
+/// The first bytes of chunked splat files.
+pub const CHUNKED_MAGIC: &[u8; 6] = b"bsplat";
+const VERSION: u16 = 1;
+const HEADER_LEN: u64 = 16;
+const INDEX_ENTRY_LEN: u64 = 40;
+
+/// Splats in a chunk by default. Small enough that a chunk downloads quickly, large enough that
+/// the index stays small for scenes of 100M splats.
+pub const CHUNK_SPLATS: usize = 65536;
+
+/// Where a chunk is in the file, and the space its splats cover.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkInfo {
+    pub offset: u64,
+    pub len: u32,
+    pub count: u32,
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+/// The index of a chunked splat file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkIndex {
+    pub sh_degree: u32,
+    pub chunks: Vec<ChunkInfo>,
+}
+
+impl ChunkIndex {
+    pub fn total_splats(&self) -> u64 {
+        self.chunks.iter().map(|c| u64::from(c.count)).sum()
+    }
+
+    fn coeffs_per_channel(&self) -> usize {
+        ((self.sh_degree + 1) * (self.sh_degree + 1)) as usize
+    }
+}
+
+// Values of splats, with the values of each splat next to each other like in the tensors.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub(crate) struct RawSplats {
+    pub(crate) means: Vec<f32>,
+    pub(crate) rotations: Vec<f32>,
+    pub(crate) log_scales: Vec<f32>,
+    pub(crate) raw_opacities: Vec<f32>,
+    pub(crate) sh_coeffs: Vec<f32>,
+}
+
+impl RawSplats {
+    pub(crate) async fn read<B: Backend>(splats: Splats<B>) -> Self {
+        let read = |t: Tensor<B, 2>| async move {
+            t.into_data_async()
+                .await
+                .into_vec::<f32>()
+                .expect("Splats are floats")
+        };
+        let n = splats.num_splats() as usize;
+        let coeffs = splats.sh_coeffs.dims()[1];
+        Self {
+            means: read(splats.means.val()).await,
+            rotations: read(splats.rotation.val()).await,
+            log_scales: read(splats.log_scales.val()).await,
+            raw_opacities: read(splats.raw_opacity.val().reshape([n, 1])).await,
+            sh_coeffs: read(splats.sh_coeffs.val().reshape([n, coeffs * 3])).await,
+        }
+    }
+
+    pub(crate) fn into_splats(self, coeffs: usize, device: &WgpuDevice) -> Splats<MainBackend> {
+        let n = self.len();
+        Splats::from_tensor_data(
+            Tensor::from_data(TensorData::new(self.means, [n, 3]), device),
+            Tensor::from_data(TensorData::new(self.rotations, [n, 4]), device),
+            Tensor::from_data(TensorData::new(self.log_scales, [n, 3]), device),
+            Tensor::from_data(TensorData::new(self.sh_coeffs, [n, coeffs, 3]), device),
+            Tensor::from_data(TensorData::new(self.raw_opacities, [n]), device),
+        )
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.raw_opacities.len()
+    }
+
+    fn append(&mut self, other: Self) {
+        self.means.extend(other.means);
+        self.rotations.extend(other.rotations);
+        self.log_scales.extend(other.log_scales);
+        self.raw_opacities.extend(other.raw_opacities);
+        self.sh_coeffs.extend(other.sh_coeffs);
+    }
+
+    fn select(&self, indices: &[usize], coeffs: usize) -> Self {
+        let pick = |values: &[f32], width: usize| -> Vec<f32> {
+            indices
+                .iter()
+                .flat_map(|&i| &values[i * width..(i + 1) * width])
+                .copied()
+                .collect()
+        };
+        Self {
+            means: pick(&self.means, 3),
+            rotations: pick(&self.rotations, 4),
+            log_scales: pick(&self.log_scales, 3),
+            raw_opacities: pick(&self.raw_opacities, 1),
+            sh_coeffs: pick(&self.sh_coeffs, coeffs * 3),
+        }
+    }
+}
+
+// Interleave the bits of a 10 bit number with two zeros each.
+fn spread_bits(v: u32) -> u32 {
+    let mut v = v & 0x3ff;
+    v = (v | (v << 16)) & 0x0300_00ff;
+    v = (v | (v << 8)) & 0x0300_f00f;
+    v = (v | (v << 4)) & 0x030c_30c3;
+    (v | (v << 2)) & 0x0924_9249
+}
+
+// Position of `p` on a Morton curve through the box from `min` to `max`. Points that are close on
+// the curve are close in space.
+fn morton_code(p: Vec3, min: Vec3, max: Vec3) -> u32 {
+    let cells = ((p - min) / (max - min).max(Vec3::splat(1e-12)) * 1023.0)
+        .clamp(Vec3::ZERO, Vec3::splat(1023.0))
+        .as_uvec3();
+    spread_bits(cells.x) | (spread_bits(cells.y) << 1) | (spread_bits(cells.z) << 2)
+}
+
+fn bounds(points: impl Iterator<Item = Vec3>) -> (Vec3, Vec3) {
+    points
+        .filter(|p| p.is_finite())
+        .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), p| {
+            (min.min(p), max.max(p))
+        })
+}
+
+fn means(raw: &RawSplats) -> impl Iterator<Item = Vec3> + '_ {
+    raw.means.chunks_exact(3).map(Vec3::from_slice)
+}
+
+// Bounds of the splats, with each splat reaching 3 times its largest scale around its center.
+fn splat_bounds(raw: &RawSplats) -> (Vec3, Vec3) {
+    let extents = raw
+        .log_scales
+        .chunks_exact(3)
+        .map(|s| 3.0 * Vec3::from_slice(s).max_element().exp());
+    means(raw)
+        .zip(extents)
+        .filter(|(p, extent)| p.is_finite() && extent.is_finite())
+        .fold(
+            (Vec3::INFINITY, Vec3::NEG_INFINITY),
+            |(min, max), (p, extent)| (min.min(p - extent), max.max(p + extent)),
+        )
+}
+
+fn write_chunked(raw: &RawSplats, sh_degree: u32, chunk_size: usize) -> std::io::Result<Vec<u8>> {
+    let coeffs = ((sh_degree + 1) * (sh_degree + 1)) as usize;
+    let (min, max) = bounds(means(raw));
+    let mut order: Vec<_> = (0..raw.len()).collect();
+    order.sort_by_key(|&i| morton_code(Vec3::from_slice(&raw.means[i * 3..]), min, max));
+
+    let mut chunks = vec![];
+    for indices in order.chunks(chunk_size.max(1)) {
+        let chunk = raw.select(indices, coeffs);
+        let mut encoder = GzEncoder::new(vec![], Compression::fast());
+        for values in [
+            &chunk.means,
+            &chunk.rotations,
+            &chunk.log_scales,
+            &chunk.raw_opacities,
+            &chunk.sh_coeffs,
+        ] {
+            for v in values {
+                encoder.write_all(&v.to_le_bytes())?;
+            }
+        }
+        let (min, max) = splat_bounds(&chunk);
+        chunks.push((indices.len(), min, max, encoder.finish()?));
+    }
+
+    let mut data = vec![];
+    data.extend(CHUNKED_MAGIC);
+    data.extend(VERSION.to_le_bytes());
+    data.extend(sh_degree.to_le_bytes());
+    data.extend((chunks.len() as u32).to_le_bytes());
+    let mut offset = HEADER_LEN + INDEX_ENTRY_LEN * chunks.len() as u64;
+    for (count, min, max, bytes) in &chunks {
+        data.extend(offset.to_le_bytes());
+        data.extend((bytes.len() as u32).to_le_bytes());
+        data.extend((*count as u32).to_le_bytes());
+        for v in min.to_array().into_iter().chain(max.to_array()) {
+            data.extend(v.to_le_bytes());
+        }
+        offset += bytes.len() as u64;
+    }
+    for (_, _, _, bytes) in chunks {
+        data.extend(bytes);
+    }
+    Ok(data)
+}
+
+/// Export splats to a chunked splat file, with `chunk_size` splats in each chunk. See
+/// [`load_chunked`] to view it while it downloads.
+pub async fn splat_to_chunked<B: Backend>(
+    splats: Splats<B>,
+    chunk_size: usize,
+) -> std::io::Result<Vec<u8>> {
+    let sh_degree = splats.sh_degree();
+    let raw = RawSplats::read(splats).await;
+    write_chunked(&raw, sh_degree, chunk_size)
+}
+
+fn u32_at(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+fn f32_at(data: &[u8], at: usize) -> f32 {
+    f32::from_bits(u32_at(data, at))
+}
+
+// The SH degree and number of chunks, from the header.
+fn parse_header(header: &[u8]) -> Result<(u32, u32), SplatImportError> {
+    if header.len() < HEADER_LEN as usize
+        || !header.starts_with(CHUNKED_MAGIC)
+        || u16::from_le_bytes([header[6], header[7]]) != VERSION
+    {
+        return Err(SplatImportError::InvalidFormat);
+    }
+    let sh_degree = u32_at(header, 8);
+    if sh_degree > 4 {
+        return Err(SplatImportError::InvalidFormat);
+    }
+    Ok((sh_degree, u32_at(header, 12)))
+}
+
+fn parse_index(sh_degree: u32, index: &[u8]) -> ChunkIndex {
+    let chunks = index
+        .chunks_exact(INDEX_ENTRY_LEN as usize)
+        .map(|entry| {
+            let offset = u64::from_le_bytes(entry[0..8].try_into().expect("Entries are 40 bytes"));
+            let vec = |at: usize| {
+                Vec3::new(
+                    f32_at(entry, at),
+                    f32_at(entry, at + 4),
+                    f32_at(entry, at + 8),
+                )
+            };
+            ChunkInfo {
+                offset,
+                len: u32_at(entry, 8),
+                count: u32_at(entry, 12),
+                min: vec(16),
+                max: vec(28),
+            }
+        })
+        .collect();
+    ChunkIndex { sh_degree, chunks }
+}
+
+/// Read the header and index of a chunked splat file.
+pub async fn read_index(source: &RangeSource) -> Result<ChunkIndex, SplatImportError> {
+    let (sh_degree, count) = parse_header(&source.read(0, HEADER_LEN).await?)?;
+    let index = source
+        .read(HEADER_LEN, INDEX_ENTRY_LEN * u64::from(count))
+        .await?;
+    Ok(parse_index(sh_degree, &index))
+}
+
+fn decode_chunk(data: &[u8], count: usize, coeffs: usize) -> Result<RawSplats, SplatImportError> {
+    let mut unzipped = vec![];
+    GzDecoder::new(data).read_to_end(&mut unzipped)?;
+    let values: Vec<f32> = unzipped
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    let widths = [3, 4, 3, 1, coeffs * 3].map(|w| w * count);
+    if values.len() != widths.iter().sum::<usize>() {
+        return Err(SplatImportError::InvalidFormat);
+    }
+    let mut rest = values.as_slice();
+    let [means, rotations, log_scales, raw_opacities, sh_coeffs] = widths.map(|w| {
+        let (taken, left) = rest.split_at(w);
+        rest = left;
+        taken.to_vec()
+    });
+    Ok(RawSplats {
+        means,
+        rotations,
+        log_scales,
+        raw_opacities,
+        sh_coeffs,
+    })
+}
+
+// Whether any of the box from `min` to `max` might be in view of `camera`. The box is out of view
+// when all its corners are on the outside of one of the sides of the view.
+fn box_in_view(camera: &Camera, min: Vec3, max: Vec3) -> bool {
+    if camera.model == CameraModel::Equirectangular {
+        return true;
+    }
+    let to_local = camera.world_to_local();
+    let corners: Vec<_> = (0..8)
+        .map(|i| {
+            let pick = |bit: usize, lo: f32, hi: f32| if i & bit == 0 { lo } else { hi };
+            let corner = Vec3::new(
+                pick(1, min.x, max.x),
+                pick(2, min.y, max.y),
+                pick(4, min.z, max.z),
+            );
+            to_local.transform_point3(corner)
+        })
+        .collect();
+    // Points are in view when their uv is between 0 and 1, see `Camera::world_to_uv`.
+    let tan_half = glam::vec2(
+        (camera.fov_x * 0.5).tan() as f32,
+        (camera.fov_y * 0.5).tan() as f32,
+    );
+    let (lo, hi) = (
+        -camera.center_uv * 2.0 * tan_half,
+        (glam::Vec2::ONE - camera.center_uv) * 2.0 * tan_half,
+    );
+    let sides: [&dyn Fn(Vec3) -> bool; 5] = [
+        &|p| p.z <= 0.0,
+        &|p| p.x < lo.x * p.z,
+        &|p| p.x > hi.x * p.z,
+        &|p| p.y < lo.y * p.z,
+        &|p| p.y > hi.y * p.z,
+    ];
+    !sides
+        .iter()
+        .any(|outside| corners.iter().all(|&p| outside(p)))
+}
+
+/// The chunk to load next of those not `loaded` yet: the closest one in view of `camera`, or
+/// without a camera, the next one in the file.
+pub fn next_chunk(index: &ChunkIndex, loaded: &[bool], camera: Option<&Camera>) -> Option<usize> {
+    let pending = (0..index.chunks.len()).filter(|&i| !loaded[i]);
+    let Some(camera) = camera else {
+        return pending.min();
+    };
+    pending.min_by(|&a, &b| {
+        let key = |i: usize| {
+            let chunk = &index.chunks[i];
+            let distance = camera
+                .position
+                .clamp(chunk.min, chunk.max)
+                .distance(camera.position);
+            (!box_in_view(camera, chunk.min, chunk.max), distance)
+        };
+        key(a)
+            .partial_cmp(&key(b))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    })
+}
+
+/// Bytes a splat of `sh_degree` takes up once loaded.
+pub fn splat_bytes(sh_degree: u32) -> u64 {
+    let coeffs = u64::from((sh_degree + 1) * (sh_degree + 1));
+    (3 + 4 + 3 + 1 + coeffs * 3) * 4
+}
+
+// The chunk to load next, and the loaded chunks to evict first to make room for it. With at most
+// `max_splats` loaded, chunks out of view of `camera` make room for chunks in view, the farthest
+// ones first. Returns None when nothing should load until the camera moves.
+fn next_load(
+    index: &ChunkIndex,
+    loaded: &[bool],
+    camera: Option<&Camera>,
+    max_splats: Option<u64>,
+) -> Option<(usize, Vec<usize>)> {
+    let next = next_chunk(index, loaded, camera)?;
+    let count = |i: usize| u64::from(index.chunks[i].count);
+    let loaded_splats: u64 = (0..loaded.len()).filter(|&i| loaded[i]).map(count).sum();
+    let Some(max_splats) = max_splats else {
+        return Some((next, vec![]));
+    };
+    if loaded_splats + count(next) <= max_splats {
+        return Some((next, vec![]));
+    }
+
+    // Chunks out of view don't push out other chunks, so loading doesn't go back and forth.
+    let camera = camera?;
+    let in_view = |i: usize| {
+        let chunk = &index.chunks[i];
+        box_in_view(camera, chunk.min, chunk.max)
+    };
+    if !in_view(next) {
+        return None;
+    }
+    let distance = |i: usize| {
+        let chunk = &index.chunks[i];
+        camera
+            .position
+            .clamp(chunk.min, chunk.max)
+            .distance(camera.position)
+    };
+    let mut evictable: Vec<_> = (0..loaded.len())
+        .filter(|&i| loaded[i] && !in_view(i))
+        .collect();
+    evictable.sort_by(|&a, &b| distance(b).total_cmp(&distance(a)));
+
+    let mut needed = loaded_splats + count(next) - max_splats;
+    let mut evict = vec![];
+    for i in evictable {
+        if needed == 0 {
+            break;
+        }
+        needed = needed.saturating_sub(count(i));
+        evict.push(i);
+    }
+    (needed == 0).then_some((next, evict))
+}
+
+type SplatStream = Pin<Box<dyn DynStream<Result<SplatMessage, SplatImportError>>>>;
+
+/// Load a chunked splat file a chunk at a time, those in view of the camera in `focus` first.
+/// Each message has all splats loaded at that point.
+///
+/// With a `max_bytes` budget, chunks out of view are dropped to keep the splats in the budget,
+/// and the stream keeps following the camera to load what comes into view, until the sender of
+/// `focus` is dropped.
+pub fn load_chunked(
+    source: RangeSource,
+    mut focus: watch::Receiver<Option<Camera>>,
+    max_bytes: Option<u64>,
+    device: WgpuDevice,
+) -> SplatStream {
+    Box::pin(try_fn_stream(move |emitter| async move {
+        let index = read_index(&source).await?;
+        let coeffs = index.coeffs_per_channel();
+        let total_splats = index.total_splats() as u32;
+        let max_splats = max_bytes.map(|bytes| bytes / splat_bytes(index.sh_degree));
+        let mut loaded = vec![false; index.chunks.len()];
+
+        // Splats of the loaded chunks, which grows as chunks come in, and the chunk of each of
+        // them.
+        let mut buffer = RawSplats::default();
+        let mut buffer_chunks: Vec<usize> = vec![];
+        // Splats are only sent again once they grew by a good part, so sending them all each
+        // time doesn't add up to more than a few times the scene.
+        let mut sent = 0;
+        let mut changed = false;
+        let message = |buffer: &RawSplats| SplatMessage {
+            meta: ParseMetadata {
+                up_axis: None,
+                total_splats,
+                frame_count: 0,
+                current_frame: 0,
+                labels: None,
+            },
+            splats: buffer.clone().into_splats(coeffs, &device),
+        };
+
+        loop {
+            let camera = focus.borrow_and_update().clone();
+            let Some((next, evict)) = next_load(&index, &loaded, camera.as_ref(), max_splats)
+            else {
+                if changed && buffer.len() > 0 {
+                    emitter.emit(message(&buffer)).await;
+                    sent = buffer.len();
+                    changed = false;
+                }
+                // Wait for the camera to move to load more.
+                if loaded.iter().all(|&l| l) || focus.changed().await.is_err() {
+                    break;
+                }
+                continue;
+            };
+
+            if !evict.is_empty() {
+                for &i in &evict {
+                    loaded[i] = false;
+                }
+                let keep: Vec<_> = (0..buffer.len())
+                    .filter(|&i| loaded[buffer_chunks[i]])
+                    .collect();
+                buffer = buffer.select(&keep, coeffs);
+                buffer_chunks = keep.into_iter().map(|i| buffer_chunks[i]).collect();
+                changed = true;
+            }
+
+            loaded[next] = true;
+            let chunk = &index.chunks[next];
+            if chunk.count == 0 {
+                continue;
+            }
+            let data = source.read(chunk.offset, u64::from(chunk.len)).await?;
+            buffer.append(decode_chunk(&data, chunk.count as usize, coeffs)?);
+            buffer_chunks.extend(std::iter::repeat_n(next, chunk.count as usize));
+            changed = true;
+
+            if buffer.len() >= sent + sent / 4 + 1 {
+                emitter.emit(message(&buffer)).await;
+                sent = buffer.len();
+                changed = false;
+            }
+        }
+        Ok(())
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Quat;
+
+    fn raw(count: usize) -> RawSplats {
+        let mut raw = RawSplats::default();
+        for i in 0..count {
+            let x = i as f32;
+            raw.means.extend([x, -x, 2.0 * x]);
+            raw.rotations.extend([1.0, 0.0, 0.0, 0.0]);
+            raw.log_scales.extend([x; 3]);
+            raw.raw_opacities.push(x);
+            raw.sh_coeffs.extend([x, x + 0.5, x + 0.25]);
+        }
+        raw
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let data = write_chunked(&raw(5), 0, 2).expect("Writes to memory");
+        let (sh_degree, count) = parse_header(&data).expect("Valid header");
+        let index = parse_index(
+            sh_degree,
+            &data[HEADER_LEN as usize..(HEADER_LEN + INDEX_ENTRY_LEN * u64::from(count)) as usize],
+        );
+        assert_eq!(index.chunks.len(), 3);
+        assert_eq!(index.total_splats(), 5);
+
+        let mut opacities = vec![];
+        for chunk in &index.chunks {
+            let start = chunk.offset as usize;
+            let bytes = &data[start..start + chunk.len as usize];
+            let decoded = decode_chunk(bytes, chunk.count as usize, 1).expect("Valid chunk");
+            for (mean, o) in means(&decoded).zip(&decoded.raw_opacities) {
+                // The bounds cover the whole splat, not just its center.
+                let extent = 3.0 * o.exp();
+                assert!((mean - extent).cmpge(chunk.min - 1e-4).all());
+                assert!((mean + extent).cmple(chunk.max + 1e-4).all());
+                assert_eq!(mean.x, *o);
+            }
+            opacities.extend(decoded.raw_opacities);
+        }
+        opacities.sort_by(f32::total_cmp);
+        assert_eq!(opacities, [0.0, 1.0, 2.0, 3.0, 4.0]);
+        let mut newer = data[..HEADER_LEN as usize].to_vec();
+        newer[6] = 2;
+        assert!(parse_header(&newer).is_err());
+    }
+
+    #[test]
+    fn loads_chunks_in_view_first() {
+        let chunk = |z: f32| ChunkInfo {
+            offset: 0,
+            len: 0,
+            count: 1,
+            min: Vec3::new(0.0, -1.0, z),
+            max: Vec3::new(1.0, 1.0, z + 1.0),
+        };
+        let index = ChunkIndex {
+            sh_degree: 0,
+            // Behind, far in front, and close in front of a camera looking down +z.
+            chunks: vec![chunk(-10.0), chunk(9.0), chunk(2.0)],
+        };
+        let camera = Camera::new(Vec3::ZERO, Quat::IDENTITY, 1.0, 1.0, glam::vec2(0.5, 0.5));
+
+        let mut loaded = vec![false; 3];
+        let mut order = vec![];
+        while let Some(next) = next_chunk(&index, &loaded, Some(&camera)) {
+            loaded[next] = true;
+            order.push(next);
+        }
+        assert_eq!(order, [2, 1, 0]);
+        assert_eq!(next_chunk(&index, &[true, false, false], None), Some(1));
+    }
+
+    #[test]
+    fn evicts_out_of_view_chunks_for_the_budget() {
+        let chunk = |z: f32| ChunkInfo {
+            offset: 0,
+            len: 0,
+            count: 10,
+            min: Vec3::new(0.0, -1.0, z),
+            max: Vec3::new(1.0, 1.0, z + 1.0),
+        };
+        let index = ChunkIndex {
+            sh_degree: 0,
+            // Far behind, close behind, and in front of a camera looking down +z.
+            chunks: vec![chunk(-20.0), chunk(-3.0), chunk(4.0)],
+        };
+        let camera = Camera::new(Vec3::ZERO, Quat::IDENTITY, 1.0, 1.0, glam::vec2(0.5, 0.5));
+
+        // Room for everything.
+        let loaded = [true, true, false];
+        assert_eq!(
+            next_load(&index, &loaded, Some(&camera), Some(30)),
+            Some((2, vec![]))
+        );
+        // The chunk in view pushes out the farthest chunk out of view.
+        assert_eq!(
+            next_load(&index, &loaded, Some(&camera), Some(25)),
+            Some((2, vec![0]))
+        );
+        assert_eq!(
+            next_load(&index, &loaded, Some(&camera), Some(10)),
+            Some((2, vec![0, 1]))
+        );
+        // Chunks out of view only load when there's room.
+        assert_eq!(
+            next_load(&index, &[true, false, true], Some(&camera), Some(20)),
+            None
+        );
+        assert_eq!(next_load(&index, &[false; 3], None, Some(0)), None);
+        // Without a budget, everything loads.
+        assert_eq!(
+            next_load(&index, &[true, false, true], Some(&camera), None),
+            Some((1, vec![]))
+        );
+    }
+
+    #[test]
+    fn morton_keeps_neighbours_close() {
+        let (min, max) = (Vec3::ZERO, Vec3::ONE);
+        assert_eq!(morton_code(Vec3::ZERO, min, max), 0);
+        assert_eq!(morton_code(Vec3::ONE, min, max), (1 << 30) - 1);
+        assert_eq!(spread_bits(0b11), 0b1001);
+    }
+}
@@ -0,0 +1,69 @@
+// Conversions between the color spaces of the pipeline.
+//
+// Images are decoded to sRGB encoded colors (except for HDR datasets, which stay linear), splats
+// are trained to reproduce those, and the viewer and exporters pass them on as sRGB.
+use burn::{prelude::Backend, tensor::Tensor};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Color space to compare colors in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum ColorSpace {
+    /// sRGB encoded colors, as displayed. Errors in dark areas weigh about as much as they're seen.
+    Srgb,
+    /// Linear light. Errors in bright areas weigh more, closer to how light adds up.
+    Linear,
+}
+
+const SRGB_KNEE: f32 = 0.003_130_8;
+
+/// Decode an sRGB encoded value in [0, 1] to linear color.
+pub fn srgb_to_linear(x: f32) -> f32 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encode a linear value in [0, 1] as sRGB.
+pub fn linear_to_srgb(x: f32) -> f32 {
+    if x <= SRGB_KNEE {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Like [`srgb_to_linear`], for all values of a tensor. Values are clamped to be positive.
+pub fn srgb_to_linear_tensor<B: Backend, const D: usize>(srgb: Tensor<B, D>) -> Tensor<B, D> {
+    let x = srgb.clamp_min(0.0);
+    let low = x.clone() / 12.92;
+    // Clamp before the power, so its gradient stays finite at zero.
+    let high = ((x.clone().clamp_min(0.04045) + 0.055) / 1.055).powf_scalar(2.4);
+    high.mask_where(x.lower_equal_elem(0.04045), low)
+}
+
+/// Like [`linear_to_srgb`], for all values of a tensor. Values are clamped to be positive.
+pub fn linear_to_srgb_tensor<B: Backend, const D: usize>(linear: Tensor<B, D>) -> Tensor<B, D> {
+    let x = linear.clamp_min(0.0);
+    let low = x.clone() * 12.92;
+    let high = x.clone().clamp_min(SRGB_KNEE).powf_scalar(1.0 / 2.4) * 1.055 - 0.055;
+    high.mask_where(x.lower_equal_elem(SRGB_KNEE), low)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for i in 0..=100 {
+            let x = i as f32 / 100.0;
+            assert!((linear_to_srgb(srgb_to_linear(x)) - x).abs() < 1e-5);
+        }
+        // Both pieces of the curve meet at the knee.
+        assert!((srgb_to_linear(0.04045) - SRGB_KNEE).abs() < 1e-6);
+        assert!((srgb_to_linear(0.5) - 0.214).abs() < 1e-3);
+    }
+}
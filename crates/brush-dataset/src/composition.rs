@@ -0,0 +1,218 @@
+// Saves and loads composed scenes: a zip of the splat files of each layer, next to a JSON file of
+// where each of them is placed.
+use std::{
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+};
+
+use brush_render::{
+    MainBackend,
+    composition::{CompositionLayer, SceneComposition},
+    gaussian_splats::Splats,
+};
+use brush_vfs::BrushVfs;
+use burn::backend::wgpu::WgpuDevice;
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+use tokio_stream::StreamExt;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    splat_export::splat_to_ply,
+    splat_formats::{SplatFormat, load_splats},
+    splat_import::SplatImportError,
+};
+
+/// Name of the JSON file which describes a composition.
+pub const COMPOSITION_FILE: &str = "composition.json";
+
+#[derive(Debug, Error)]
+pub enum CompositionError {
+    #[error("Invalid composition file: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("The composition has no layers")]
+    NoLayers,
+
+    #[error("Layer file {0} isn't a splat file")]
+    UnknownFormat(String),
+
+    #[error("No splats in layer file {0}")]
+    EmptyLayer(String),
+
+    #[error("Failed to write the composition: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
+    #[error(transparent)]
+    Import(#[from] SplatImportError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// The composition file of a vfs, if it has one.
+pub fn find_composition(vfs: &BrushVfs) -> Option<PathBuf> {
+    vfs.files_ending_in(COMPOSITION_FILE).next()
+}
+
+/// Read the composition at `path`, and the splats of each of its layers. Layer files are relative
+/// to the composition file, and only the first frame of animated files is kept.
+pub async fn load_composition(
+    vfs: &BrushVfs,
+    path: &Path,
+    device: WgpuDevice,
+) -> Result<Vec<(CompositionLayer, Splats<MainBackend>)>, CompositionError> {
+    let mut data = vec![];
+    vfs.reader_at_path(path)
+        .await?
+        .read_to_end(&mut data)
+        .await?;
+    let composition: SceneComposition = serde_json::from_slice(&data)?;
+    if composition.layers.is_empty() {
+        return Err(CompositionError::NoLayers);
+    }
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut layers = vec![];
+    for layer in composition.layers {
+        let file = dir.join(&layer.file);
+        let format = SplatFormat::from_path(&file)
+            .ok_or_else(|| CompositionError::UnknownFormat(layer.file.clone()))?;
+        let stream = load_splats(
+            format,
+            vfs.reader_at_path(&file).await?,
+            None,
+            device.clone(),
+        );
+        let mut stream = std::pin::pin!(stream);
+        let mut splats = None;
+        while let Some(message) = stream.next().await {
+            let message = message?;
+            if message.meta.current_frame > 0 {
+                break;
+            }
+            splats = Some(message.splats);
+        }
+        let splats = splats.ok_or_else(|| CompositionError::EmptyLayer(layer.file.clone()))?;
+        layers.push((layer, splats));
+    }
+    Ok(layers)
+}
+
+/// Write the splats of each layer as a ply file, with the composition file, into a zip that
+/// [`load_composition`] reads back.
+///
+/// Layers are saved under their own file name, with a ply extension, and a number added to tell
+/// files with the same name apart.
+pub async fn composition_to_zip(
+    layers: Vec<(CompositionLayer, Splats<MainBackend>)>,
+) -> Result<Vec<u8>, CompositionError> {
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    let mut composition = SceneComposition::default();
+
+    for (layer, splats) in layers {
+        let stem = Path::new(&layer.file)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .filter(|stem| !stem.is_empty())
+            .unwrap_or("layer")
+            .to_owned();
+        let mut file = format!("{stem}.ply");
+        let mut copy = 1;
+        while composition.layers.iter().any(|other| other.file == file) {
+            copy += 1;
+            file = format!("{stem}_{copy}.ply");
+        }
+
+        zip.start_file(file.as_str(), options)?;
+        zip.write_all(&splat_to_ply(splats).await?)?;
+        composition.layers.push(CompositionLayer {
+            file,
+            transform: layer.transform,
+        });
+    }
+
+    zip.start_file(COMPOSITION_FILE, options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&composition)?)?;
+    Ok(zip.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brush_render::composition::LayerTransform;
+    use glam::{Quat, Vec3};
+
+    #[tokio::test]
+    async fn composition_roundtrips() {
+        let device = WgpuDevice::DefaultDevice;
+        let splats = |means: &[Vec3]| {
+            Splats::<MainBackend>::from_raw(means, None, None, None, None, &device)
+        };
+        let moved = LayerTransform {
+            translation: Vec3::new(1.0, 2.0, 3.0),
+            rotation: Quat::from_rotation_y(0.5),
+            scale: 2.0,
+        };
+        let layers = vec![
+            (
+                CompositionLayer {
+                    file: "garden.ply".to_owned(),
+                    transform: LayerTransform::IDENTITY,
+                },
+                splats(&[Vec3::ZERO, Vec3::X]),
+            ),
+            (
+                CompositionLayer {
+                    file: "bench.spz".to_owned(),
+                    transform: moved,
+                },
+                splats(&[Vec3::Y]),
+            ),
+            (
+                CompositionLayer {
+                    file: "garden.ply".to_owned(),
+                    transform: moved,
+                },
+                splats(&[Vec3::Z; 3]),
+            ),
+        ];
+
+        let zip = composition_to_zip(layers).await.expect("Write composition");
+        let vfs = BrushVfs::from_reader(Cursor::new(zip))
+            .await
+            .expect("Read zip");
+        let path = find_composition(&vfs).expect("Composition file");
+        let layers = load_composition(&vfs, &path, device.clone())
+            .await
+            .expect("Load composition");
+
+        let files: Vec<_> = layers
+            .iter()
+            .map(|(layer, _)| layer.file.as_str())
+            .collect();
+        assert_eq!(files, ["garden.ply", "bench.ply", "garden_2.ply"]);
+        let counts: Vec<_> = layers
+            .iter()
+            .map(|(_, splats)| splats.num_splats())
+            .collect();
+        assert_eq!(counts, [2, 1, 3]);
+        assert_eq!(layers[0].0.transform, LayerTransform::IDENTITY);
+        let transform = layers[1].0.transform;
+        assert!(transform.translation.distance(moved.translation) < 1e-6);
+        assert!(transform.rotation.angle_between(moved.rotation) < 1e-6);
+        assert_eq!(transform.scale, moved.scale);
+    }
+
+    #[tokio::test]
+    async fn empty_composition_fails() {
+        let vfs = BrushVfs::from_memory_files(vec![brush_vfs::MemoryFile {
+            name: COMPOSITION_FILE.to_owned(),
+            data: br#"{"layers": []}"#.to_vec().into(),
+        }]);
+        let result =
+            load_composition(&vfs, Path::new(COMPOSITION_FILE), WgpuDevice::DefaultDevice).await;
+        assert!(matches!(result, Err(CompositionError::NoLayers)));
+    }
+}
@@ -0,0 +1,750 @@
+// Compressed splat files, to ship scenes to phones and the web. Positions, scales, rotations,
+// opacities and base colors are quantized to a few bits, and the view dependent SH coefficients
+// are replaced by an index into a codebook found with k-means. The codebook is fit with splats
+// weighted by how much they show, so the splats that matter keep their colors best. Optionally,
+// everything is gzipped on top.
+//
+// The file is laid out as:
+// - a header: the magic `bsz`, the version, flags (bit 0: gzipped), the SH degree and the bits of
+//   positions, scales, rotations and colors as bytes, 2 reserved bytes, and the number of splats
+//   and codebook entries as u32s.
+// - the payload, gzipped if flagged: the ranges values are quantized over as f32s (min and max
+//   corners of positions, log scales and base colors, min and max of the codebook), the codebook,
+//   with a byte per coefficient, and then a bit stream with, for all splats, their positions, log
+//   scales, rotations (the three smallest components and which one is left out), opacities (8
+//   bits, after the sigmoid), base colors and codebook indices.
+//
+// All numbers are little endian, and the bit stream starts at the lowest bit.
+use std::io::{Read, Write};
+
+use brush_render::{
+    MainBackend,
+    gaussian_splats::{Splats, inverse_sigmoid},
+};
+use burn::{
+    backend::wgpu::WgpuDevice,
+    prelude::Backend,
+    tensor::{Tensor, TensorData},
+};
+use clap::ValueEnum;
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+
+use crate::{chunked_splats::RawSplats, splat_import::SplatImportError};
+
+/// The first bytes of compressed splat files.
+pub const COMPRESSED_MAGIC: &[u8; 3] = b"bsz";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 20;
+const FLAG_GZIP: u8 = 1;
+const OPACITY_BITS: u32 = 8;
+const CODEBOOK_BITS: u32 = 8;
+
+// Splats to fit the codebook with. More barely changes the codebook, but takes longer.
+const KMEANS_SAMPLES: usize = 1 << 18;
+// Splats to find the nearest codebook entries of at once.
+const KMEANS_BATCH: usize = 4096;
+
+/// How much to give up for smaller files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum CompressionPreset {
+    /// Hard to tell apart from the original, at about a fifth of the size of a ply.
+    High,
+    /// Small differences up close, at about a tenth of the size of a ply.
+    Balanced,
+    /// Visibly blurrier colors and edges, for slow connections.
+    Small,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressionSettings {
+    pub position_bits: u32,
+    pub scale_bits: u32,
+    pub rotation_bits: u32,
+    /// Bits of each channel of the base colors.
+    pub color_bits: u32,
+    /// Entries of the codebook of SH coefficients, at most 65536.
+    pub codebook_size: u32,
+    pub kmeans_iters: u32,
+    /// Gzip the quantized values, which saves another 10 to 30 percent.
+    pub entropy_coding: bool,
+}
+
+impl CompressionPreset {
+    pub fn settings(self) -> CompressionSettings {
+        let (position_bits, scale_bits, rotation_bits, color_bits, codebook_size) = match self {
+            Self::High => (18, 10, 10, 10, 8192),
+            Self::Balanced => (16, 8, 9, 8, 4096),
+            Self::Small => (14, 6, 7, 6, 1024),
+        };
+        CompressionSettings {
+            position_bits,
+            scale_bits,
+            rotation_bits,
+            color_bits,
+            codebook_size,
+            kmeans_iters: 10,
+            entropy_coding: true,
+        }
+    }
+}
+
+fn mask(bits: u32) -> u32 {
+    if bits >= 32 {
+        u32::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+// Packs numbers of any number of bits up to 24 after another.
+#[derive(Default)]
+struct BitWriter {
+    data: Vec<u8>,
+    pending: u64,
+    len: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, bits: u32) {
+        self.pending |= u64::from(value & mask(bits)) << self.len;
+        self.len += bits;
+        while self.len >= 8 {
+            self.data.push(self.pending as u8);
+            self.pending >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.data.push(self.pending as u8);
+        }
+        self.data
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pending: u64,
+    len: u32,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, bits: u32) -> Result<u32, SplatImportError> {
+        while self.len < bits {
+            let (&byte, rest) = self
+                .data
+                .split_first()
+                .ok_or(SplatImportError::InvalidFormat)?;
+            self.data = rest;
+            self.pending |= u64::from(byte) << self.len;
+            self.len += 8;
+        }
+        let value = (self.pending as u32) & mask(bits);
+        self.pending >>= bits;
+        self.len -= bits;
+        Ok(value)
+    }
+}
+
+fn quantize(v: f32, min: f32, max: f32, bits: u32) -> u32 {
+    let steps = mask(bits) as f32;
+    let t = (v - min) / (max - min).max(1e-12);
+    // Non finite values end up at the min.
+    (t * steps).round().clamp(0.0, steps) as u32
+}
+
+fn dequantize(q: u32, min: f32, max: f32, bits: u32) -> f32 {
+    min + q as f32 / mask(bits) as f32 * (max - min)
+}
+
+// The smallest three components of a w, x, y, z rotation, with the largest made positive, and the
+// index of the largest.
+fn encode_rotation(q: [f32; 4], bits: u32, writer: &mut BitWriter) {
+    let length = q.iter().map(|v| v * v).sum::<f32>().sqrt();
+    let q = if length > 0.0 && length.is_finite() {
+        q.map(|v| v / length)
+    } else {
+        [1.0, 0.0, 0.0, 0.0]
+    };
+    let largest = (0..4)
+        .max_by(|&a, &b| q[a].abs().total_cmp(&q[b].abs()))
+        .expect("Four components");
+    let sign = q[largest].signum();
+    writer.write(largest as u32, 2);
+    for i in (0..4).filter(|&i| i != largest) {
+        let v = q[i] * sign;
+        writer.write(
+            quantize(
+                v,
+                -std::f32::consts::FRAC_1_SQRT_2,
+                std::f32::consts::FRAC_1_SQRT_2,
+                bits,
+            ),
+            bits,
+        );
+    }
+}
+
+fn decode_rotation(bits: u32, reader: &mut BitReader<'_>) -> Result<[f32; 4], SplatImportError> {
+    let largest = reader.read(2)? as usize;
+    let mut q = [0.0; 4];
+    let mut sum_squares = 0.0;
+    for i in (0..4).filter(|&i| i != largest) {
+        q[i] = dequantize(
+            reader.read(bits)?,
+            -std::f32::consts::FRAC_1_SQRT_2,
+            std::f32::consts::FRAC_1_SQRT_2,
+            bits,
+        );
+        sum_squares += q[i] * q[i];
+    }
+    q[largest] = (1.0 - sum_squares).max(0.0).sqrt();
+    Ok(q)
+}
+
+// Min and max of each of the `width` values of all items, ignoring values that aren't finite.
+fn ranges(values: &[f32], width: usize) -> (Vec<f32>, Vec<f32>) {
+    let mut min = vec![f32::INFINITY; width];
+    let mut max = vec![f32::NEG_INFINITY; width];
+    for item in values.chunks_exact(width) {
+        for (i, &v) in item.iter().enumerate().filter(|(_, v)| v.is_finite()) {
+            min[i] = min[i].min(v);
+            max[i] = max[i].max(v);
+        }
+    }
+    // Without any finite values, dequantize to zero.
+    for (min, max) in min.iter_mut().zip(max.iter_mut()) {
+        if min > max {
+            (*min, *max) = (0.0, 0.0);
+        }
+    }
+    (min, max)
+}
+
+// Index of the nearest of the `k` centroids to each of the `points`, with `dim` values each.
+async fn nearest_centroids<B: Backend>(
+    points: &[f32],
+    dim: usize,
+    centroids: &[f32],
+    device: &B::Device,
+) -> Vec<u32> {
+    let k = centroids.len() / dim;
+    let centroids =
+        Tensor::<B, 2>::from_data(TensorData::new(centroids.to_vec(), [k, dim]), device);
+    // |x - c|^2 = |x|^2 - 2 x.c + |c|^2, where |x|^2 is the same for all centroids.
+    let norms = centroids.clone().powi_scalar(2).sum_dim(1).reshape([1, k]);
+    let centroids_t = centroids.transpose();
+    let mut nearest = Vec::with_capacity(points.len() / dim);
+    for batch in points.chunks(KMEANS_BATCH * dim) {
+        let n = batch.len() / dim;
+        let x = Tensor::<B, 2>::from_data(TensorData::new(batch.to_vec(), [n, dim]), device);
+        let distances = norms.clone() - x.matmul(centroids_t.clone()) * 2.0;
+        let indices = distances
+            .argmin(1)
+            .into_data_async()
+            .await
+            .convert::<i32>()
+            .into_vec::<i32>()
+            .expect("Indices are ints");
+        nearest.extend(indices.into_iter().map(|i| i as u32));
+    }
+    nearest
+}
+
+/// Fit `k` centroids to `points` with `dim` values each, with points counting as much as their
+/// `weights`. Returns the centroids, and the index of the nearest one to each point.
+async fn kmeans<B: Backend>(
+    points: &[f32],
+    weights: &[f32],
+    dim: usize,
+    k: usize,
+    iters: u32,
+    device: &B::Device,
+) -> (Vec<f32>, Vec<u32>) {
+    let n = weights.len();
+    let k = k.min(n).max(1);
+    let pick = |count: usize| -> Vec<usize> { (0..count).map(|i| i * n / count).collect() };
+    let gather = |indices: &[usize]| -> Vec<f32> {
+        indices
+            .iter()
+            .flat_map(|&i| &points[i * dim..(i + 1) * dim])
+            .copied()
+            .collect()
+    };
+    // Start from points spread over the file, and fit to a sample of the splats.
+    let mut centroids = gather(&pick(k));
+    let sample = pick(n.min(KMEANS_SAMPLES));
+    let sample_points = gather(&sample);
+
+    for _ in 0..iters {
+        let nearest = nearest_centroids::<B>(&sample_points, dim, &centroids, device).await;
+        let mut sums = vec![0.0f64; k * dim];
+        let mut totals = vec![0.0f64; k];
+        for (s, (&i, &c)) in sample.iter().zip(&nearest).enumerate() {
+            let c = c as usize;
+            let w = f64::from(weights[i].max(1e-6));
+            totals[c] += w;
+            for (sum, &v) in sums[c * dim..(c + 1) * dim]
+                .iter_mut()
+                .zip(&sample_points[s * dim..(s + 1) * dim])
+            {
+                *sum += w * f64::from(v);
+            }
+        }
+        // Centroids without any points stay where they are.
+        for c in (0..k).filter(|&c| totals[c] > 0.0) {
+            for d in 0..dim {
+                centroids[c * dim + d] = (sums[c * dim + d] / totals[c]) as f32;
+            }
+        }
+    }
+    let nearest = nearest_centroids::<B>(points, dim, &centroids, device).await;
+    (centroids, nearest)
+}
+
+// Quantize each of the values of all items, with `min.len()` values each, over their range.
+fn write_quantized(writer: &mut BitWriter, values: &[f32], min: &[f32], max: &[f32], bits: u32) {
+    for item in values.chunks_exact(min.len()) {
+        for (i, &v) in item.iter().enumerate() {
+            writer.write(quantize(v, min[i], max[i], bits), bits);
+        }
+    }
+}
+
+fn read_quantized(
+    reader: &mut BitReader<'_>,
+    count: usize,
+    min: &[f32],
+    max: &[f32],
+    bits: u32,
+) -> Result<Vec<f32>, SplatImportError> {
+    let mut values = Vec::with_capacity(count * min.len());
+    for _ in 0..count {
+        for i in 0..min.len() {
+            values.push(dequantize(reader.read(bits)?, min[i], max[i], bits));
+        }
+    }
+    Ok(values)
+}
+
+fn write_compressed(
+    raw: &RawSplats,
+    sh_degree: u32,
+    codebook: &[f32],
+    indices: &[u32],
+    settings: &CompressionSettings,
+) -> std::io::Result<Vec<u8>> {
+    let n = raw.len();
+    let coeffs = ((sh_degree + 1) * (sh_degree + 1)) as usize;
+    let rest_dim = (coeffs - 1) * 3;
+    let codebook_size = if rest_dim == 0 {
+        0
+    } else {
+        codebook.len() / rest_dim
+    };
+    let dc: Vec<f32> = raw
+        .sh_coeffs
+        .chunks_exact(coeffs * 3)
+        .flat_map(|c| &c[0..3])
+        .copied()
+        .collect();
+
+    let mut payload = vec![];
+    let (pos_min, pos_max) = ranges(&raw.means, 3);
+    let (scale_min, scale_max) = ranges(&raw.log_scales, 3);
+    let (dc_min, dc_max) = ranges(&dc, 3);
+    let (book_min, book_max) = ranges(codebook, 1);
+    for v in [
+        pos_min, pos_max, scale_min, scale_max, dc_min, dc_max, book_min, book_max,
+    ]
+    .concat()
+    {
+        payload.extend(v.to_le_bytes());
+    }
+    payload.extend(
+        codebook
+            .iter()
+            .map(|&v| quantize(v, book_min[0], book_max[0], CODEBOOK_BITS) as u8),
+    );
+
+    let mut bits = BitWriter::default();
+    write_quantized(
+        &mut bits,
+        &raw.means,
+        &pos_min,
+        &pos_max,
+        settings.position_bits,
+    );
+    write_quantized(
+        &mut bits,
+        &raw.log_scales,
+        &scale_min,
+        &scale_max,
+        settings.scale_bits,
+    );
+    for q in raw.rotations.chunks_exact(4) {
+        encode_rotation([q[0], q[1], q[2], q[3]], settings.rotation_bits, &mut bits);
+    }
+    for &o in &raw.raw_opacities {
+        let alpha = 1.0 / (1.0 + (-o).exp());
+        bits.write(quantize(alpha, 0.0, 1.0, OPACITY_BITS), OPACITY_BITS);
+    }
+    write_quantized(&mut bits, &dc, &dc_min, &dc_max, settings.color_bits);
+    if codebook_size > 0 {
+        let index_bits = index_bits(codebook_size);
+        for &i in indices {
+            bits.write(i, index_bits);
+        }
+    }
+    payload.extend(bits.finish());
+
+    let mut data = vec![];
+    data.extend(COMPRESSED_MAGIC);
+    data.push(VERSION);
+    data.push(if settings.entropy_coding {
+        FLAG_GZIP
+    } else {
+        0
+    });
+    data.extend(
+        [
+            sh_degree,
+            settings.position_bits,
+            settings.scale_bits,
+            settings.rotation_bits,
+            settings.color_bits,
+        ]
+        .map(|v| v as u8),
+    );
+    data.extend([0, 0]);
+    data.extend((n as u32).to_le_bytes());
+    data.extend((codebook_size as u32).to_le_bytes());
+    if settings.entropy_coding {
+        let mut encoder = GzEncoder::new(data, Compression::best());
+        encoder.write_all(&payload)?;
+        encoder.finish()
+    } else {
+        data.extend(payload);
+        Ok(data)
+    }
+}
+
+fn index_bits(codebook_size: usize) -> u32 {
+    (codebook_size.max(2) as u32 - 1).ilog2() + 1
+}
+
+/// Compress splats with `settings`, see the top of this module for the file layout.
+pub async fn compress_splats<B: Backend>(
+    splats: Splats<B>,
+    settings: &CompressionSettings,
+) -> std::io::Result<Vec<u8>> {
+    let bits = [
+        settings.position_bits,
+        settings.scale_bits,
+        settings.rotation_bits,
+        settings.color_bits,
+    ];
+    if bits.iter().any(|b| !(1..=24).contains(b)) || settings.codebook_size > 1 << 16 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Quantize to 1 to 24 bits, with a codebook of up to 65536 entries",
+        ));
+    }
+
+    let device = splats.device();
+    let sh_degree = splats.sh_degree();
+    let weights = splats
+        .importance()
+        .into_data_async()
+        .await
+        .into_vec::<f32>()
+        .expect("Importance is a float");
+    let raw = RawSplats::read(splats).await;
+
+    let coeffs = ((sh_degree + 1) * (sh_degree + 1)) as usize;
+    let rest_dim = (coeffs - 1) * 3;
+    let (codebook, indices) =
+        if rest_dim == 0 || settings.codebook_size == 0 || raw.means.is_empty() {
+            (vec![], vec![])
+        } else {
+            let rest: Vec<f32> = raw
+                .sh_coeffs
+                .chunks_exact(coeffs * 3)
+                .flat_map(|c| &c[3..])
+                .map(|v| if v.is_finite() { *v } else { 0.0 })
+                .collect();
+            kmeans::<B>(
+                &rest,
+                &weights,
+                rest_dim,
+                settings.codebook_size as usize,
+                settings.kmeans_iters,
+                &device,
+            )
+            .await
+        };
+    // Splats without a codebook lose their view dependent colors.
+    let sh_degree = if codebook.is_empty() { 0 } else { sh_degree };
+    let raw = if sh_degree == 0 && coeffs > 1 {
+        RawSplats {
+            sh_coeffs: raw
+                .sh_coeffs
+                .chunks_exact(coeffs * 3)
+                .flat_map(|c| &c[0..3])
+                .copied()
+                .collect(),
+            ..raw
+        }
+    } else {
+        raw
+    };
+    write_compressed(&raw, sh_degree, &codebook, &indices, settings)
+}
+
+/// Whether `data` starts like a compressed splat file.
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.starts_with(COMPRESSED_MAGIC)
+}
+
+fn read_f32s(data: &[u8], count: usize) -> Result<(Vec<f32>, &[u8]), SplatImportError> {
+    if data.len() < count * 4 {
+        return Err(SplatImportError::InvalidFormat);
+    }
+    let (values, rest) = data.split_at(count * 4);
+    let values = values
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    Ok((values, rest))
+}
+
+fn decompress(data: &[u8]) -> Result<(RawSplats, usize), SplatImportError> {
+    if data.len() < HEADER_LEN || !is_compressed(data) || data[3] != VERSION {
+        return Err(SplatImportError::InvalidFormat);
+    }
+    let (header, body) = data.split_at(HEADER_LEN);
+    let [
+        sh_degree,
+        position_bits,
+        scale_bits,
+        rotation_bits,
+        color_bits,
+    ] = [5, 6, 7, 8, 9].map(|i| u32::from(header[i]));
+    let u32_at = |at: usize| {
+        u32::from_le_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]])
+    };
+    let (n, codebook_size) = (u32_at(12) as usize, u32_at(16) as usize);
+    let bits = [position_bits, scale_bits, rotation_bits, color_bits];
+    if sh_degree > 4 || bits.iter().any(|b| !(1..=24).contains(b)) {
+        return Err(SplatImportError::InvalidFormat);
+    }
+
+    let coeffs = ((sh_degree + 1) * (sh_degree + 1)) as usize;
+    let rest_dim = (coeffs - 1) * 3;
+    let index_bits = index_bits(codebook_size);
+    let splat_bits = 3 * (position_bits + scale_bits + rotation_bits + color_bits)
+        + 2
+        + OPACITY_BITS
+        + if rest_dim > 0 { index_bits } else { 0 };
+    // The splat count comes from the header, so check it against the data before
+    // allocating for it. The ranges take 80 bytes.
+    let stream_len = (n as u64 * u64::from(splat_bits)).div_ceil(8);
+    let max_payload = 80 + codebook_size as u64 * rest_dim as u64 + stream_len;
+
+    let mut unzipped = vec![];
+    let payload = if header[4] & FLAG_GZIP != 0 {
+        GzDecoder::new(body)
+            .take(max_payload + 1)
+            .read_to_end(&mut unzipped)?;
+        if unzipped.len() as u64 > max_payload {
+            return Err(SplatImportError::InvalidFormat);
+        }
+        unzipped.as_slice()
+    } else {
+        body
+    };
+
+    let (r, payload) = read_f32s(payload, 20)?;
+    let (pos_min, pos_max, scale_min, scale_max, dc_min, dc_max) = (
+        &r[0..3],
+        &r[3..6],
+        &r[6..9],
+        &r[9..12],
+        &r[12..15],
+        &r[15..18],
+    );
+    let (book_min, book_max) = (r[18], r[19]);
+    if payload.len() < codebook_size * rest_dim {
+        return Err(SplatImportError::InvalidFormat);
+    }
+    let (book, stream) = payload.split_at(codebook_size * rest_dim);
+    if (stream.len() as u64) < stream_len {
+        return Err(SplatImportError::InvalidFormat);
+    }
+    let codebook: Vec<f32> = book
+        .iter()
+        .map(|&v| dequantize(u32::from(v), book_min, book_max, CODEBOOK_BITS))
+        .collect();
+
+    let mut reader = BitReader {
+        data: stream,
+        pending: 0,
+        len: 0,
+    };
+    let means = read_quantized(&mut reader, n, pos_min, pos_max, position_bits)?;
+    let log_scales = read_quantized(&mut reader, n, scale_min, scale_max, scale_bits)?;
+    let mut rotations = Vec::with_capacity(n * 4);
+    for _ in 0..n {
+        rotations.extend(decode_rotation(rotation_bits, &mut reader)?);
+    }
+    let mut raw_opacities = Vec::with_capacity(n);
+    for _ in 0..n {
+        let alpha = dequantize(reader.read(OPACITY_BITS)?, 0.0, 1.0, OPACITY_BITS);
+        raw_opacities.push(inverse_sigmoid(alpha.clamp(1e-4, 1.0 - 1e-4)));
+    }
+    let dc = read_quantized(&mut reader, n, dc_min, dc_max, color_bits)?;
+
+    let mut sh_coeffs = Vec::with_capacity(n * coeffs * 3);
+    for rgb in dc.chunks_exact(3) {
+        sh_coeffs.extend(rgb);
+        if rest_dim > 0 {
+            let index = reader.read(index_bits)? as usize;
+            let entry = codebook
+                .get(index * rest_dim..(index + 1) * rest_dim)
+                .ok_or(SplatImportError::InvalidFormat)?;
+            sh_coeffs.extend(entry);
+        }
+    }
+    Ok((
+        RawSplats {
+            means,
+            rotations,
+            log_scales,
+            raw_opacities,
+            sh_coeffs,
+        },
+        coeffs,
+    ))
+}
+
+/// Load a compressed splat file written by [`compress_splats`].
+pub fn load_compressed(
+    data: &[u8],
+    device: &WgpuDevice,
+) -> Result<Splats<MainBackend>, SplatImportError> {
+    let (raw, coeffs) = decompress(data)?;
+    Ok(raw.into_splats(coeffs, device))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bits_round_trip() {
+        let mut writer = BitWriter::default();
+        let values = [(5, 3), (1023, 10), (0, 1), (70_000, 17), (1, 1)];
+        for (v, b) in values {
+            writer.write(v, b);
+        }
+        let data = writer.finish();
+        assert_eq!(data.len(), 4);
+        let mut reader = BitReader {
+            data: &data,
+            pending: 0,
+            len: 0,
+        };
+        for (v, b) in values {
+            assert_eq!(reader.read(b).expect("Enough bits"), v);
+        }
+        assert!(reader.read(8).is_err());
+    }
+
+    #[test]
+    fn rotation_round_trip() {
+        let q = glam::Quat::from_euler(glam::EulerRot::XYZ, 0.3, -1.2, 2.0);
+        let mut writer = BitWriter::default();
+        // Quaternions are the same rotation with either sign.
+        encode_rotation([-q.w, -q.x, -q.y, -q.z], 10, &mut writer);
+        let data = writer.finish();
+        let mut reader = BitReader {
+            data: &data,
+            pending: 0,
+            len: 0,
+        };
+        let [w, x, y, z] = decode_rotation(10, &mut reader).expect("Enough bits");
+        assert!(glam::Quat::from_xyzw(x, y, z, w).angle_between(q) < 0.01);
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let splat = |x: f32| {
+            (
+                [x, 2.0 * x, -x],
+                [1.0, 0.0, 0.0, 0.0],
+                [-2.0, -3.0, x],
+                0.5,
+                [0.1, 0.2, 0.3, x, x, x, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0],
+            )
+        };
+        let mut raw = RawSplats::default();
+        for x in [0.0, 1.0, 2.0, 3.0] {
+            let (mean, rot, scale, opacity, sh) = splat(x);
+            raw.means.extend(mean);
+            raw.rotations.extend(rot);
+            raw.log_scales.extend(scale);
+            raw.raw_opacities.push(opacity);
+            raw.sh_coeffs.extend(sh);
+        }
+        // One codebook entry for the SH of degree 1 of all splats.
+        let codebook = vec![0.5; 9];
+        for entropy_coding in [false, true] {
+            let settings = CompressionSettings {
+                entropy_coding,
+                ..CompressionPreset::High.settings()
+            };
+            let data = write_compressed(&raw, 1, &codebook, &[0; 4], &settings).expect("Writes");
+            let (decoded, coeffs) = decompress(&data).expect("Valid file");
+            assert_eq!(coeffs, 4);
+            assert_eq!(decoded.len(), 4);
+            for (a, b) in decoded.means.iter().zip(&raw.means) {
+                assert!((a - b).abs() < 1e-3, "{a} {b}");
+            }
+            assert!((decoded.raw_opacities[0] - 0.5).abs() < 0.02);
+            assert!((decoded.sh_coeffs[1] - 0.2).abs() < 1e-6);
+            assert!((decoded.sh_coeffs[5] - 0.5).abs() < 0.01);
+        }
+        assert!(decompress(b"bsz\x02").is_err());
+    }
+
+    #[test]
+    fn rejects_counts_beyond_data() {
+        let raw = RawSplats {
+            means: vec![0.0; 3],
+            rotations: vec![1.0, 0.0, 0.0, 0.0],
+            log_scales: vec![0.0; 3],
+            raw_opacities: vec![0.0],
+            sh_coeffs: vec![0.0; 3],
+        };
+        for entropy_coding in [false, true] {
+            let settings = CompressionSettings {
+                entropy_coding,
+                ..CompressionPreset::High.settings()
+            };
+            let mut data = write_compressed(&raw, 0, &[], &[0], &settings).expect("Writes");
+            assert!(decompress(&data).is_ok());
+            data[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+            assert!(decompress(&data).is_err());
+        }
+    }
+
+    #[test]
+    fn index_bits_fit_codebook() {
+        assert_eq!(index_bits(1), 1);
+        assert_eq!(index_bits(2), 1);
+        assert_eq!(index_bits(4096), 12);
+        assert_eq!(index_bits(4097), 13);
+    }
+}
@@ -1,5 +1,8 @@
+use crate::tonemap::Tonemap;
 use burn::config::Config;
-use clap::Args;
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
 
 #[derive(Config, Debug, Args)]
 pub struct ModelConfig {
@@ -7,6 +10,27 @@ pub struct ModelConfig {
     #[arg(long, help_heading = "Model Options", default_value = "3")]
     #[config(default = 3)]
     pub sh_degree: u32,
+
+    /// Nr. of splats to start with when the dataset has no initial point cloud.
+    #[arg(long, help_heading = "Model Options", default_value = "10000")]
+    #[config(default = 10000)]
+    pub random_init_count: usize,
+
+    /// When the dataset has no initial point cloud, sample the initial splats inside the volume
+    /// seen by the cameras, instead of in a box around the cameras.
+    #[arg(long, help_heading = "Model Options", default_value = "false")]
+    #[config(default = false)]
+    pub random_init_frustum: bool,
+}
+
+/// How initial splats are made from the points of a reconstruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum PointInit {
+    /// Size splats by the distance to their nearest neighbours, with random low opacities.
+    Neighbors,
+    /// Like `neighbors`, but points seen by many images with a low reprojection error start
+    /// smaller and more opaque than points the reconstruction is unsure of.
+    TrackWeighted,
 }
 
 #[derive(Config, Debug, Args)]
@@ -18,13 +42,140 @@ pub struct LoadDataseConfig {
     #[arg(long, help_heading = "Dataset Options", default_value = "1920")]
     #[config(default = 1920)]
     pub max_resolution: u32,
+    /// Shrink all images by exactly this factor, eg. 4 to train on a quarter of the resolution
+    /// like the published evaluations of Mip-NeRF 360. Images are still capped at the max
+    /// resolution after.
+    #[arg(long, help_heading = "Dataset Options")]
+    pub downscale: Option<u32>,
     /// Create an eval dataset by selecting every nth image
     #[arg(long, help_heading = "Dataset Options")]
     pub eval_split_every: Option<usize>,
+    /// Use the images with these file names as the eval dataset, instead of every nth image.
+    #[arg(long, help_heading = "Dataset Options", value_delimiter = ',')]
+    pub eval_images: Option<Vec<String>>,
     /// Load only every nth frame
     #[arg(long, help_heading = "Dataset Options")]
     pub subsample_frames: Option<u32>,
     /// Load only every nth point from the initial sfm data
     #[arg(long, help_heading = "Dataset Options")]
     pub subsample_points: Option<u32>,
+    /// Smooth the camera poses of datasets made from frames of a video, over this many frames on
+    /// each side of every frame, and replace poses far off the trajectory. Frames are ordered by
+    /// file name.
+    #[arg(long, help_heading = "Dataset Options")]
+    pub smooth_poses: Option<usize>,
+    /// How to size the initial splats made from the points of a COLMAP reconstruction, and how
+    /// opaque to make them.
+    #[arg(
+        long,
+        help_heading = "Dataset Options",
+        value_enum,
+        default_value = "neighbors"
+    )]
+    #[config(default = "PointInit::Neighbors")]
+    pub point_init: PointInit,
+    /// Start training from this point cloud, eg. a laser scan registered to the cameras, instead
+    /// of the points of the dataset. Either a .las, .e57 or .ply file, in the dataset or on disk.
+    /// Without this, a single .las or .e57 file in the dataset is used.
+    #[arg(long, help_heading = "Dataset Options")]
+    pub init_points: Option<String>,
+    /// Average the points of initial point clouds from scans inside voxels of this size, in the
+    /// units of the scan. Scans are often far denser than needed close to the scanner.
+    #[arg(long, help_heading = "Dataset Options")]
+    pub init_voxel_size: Option<f32>,
+    /// Start splats of scans as flat discs on the surface around them, oriented by the normal
+    /// estimated from their neighbours, instead of as round blobs.
+    #[arg(long, help_heading = "Dataset Options", default_value = "false")]
+    #[config(default = false)]
+    pub init_surface_aligned: bool,
+    /// Units of 16-bit PNG depth maps, in the units of the dataset. The default reads
+    /// millimeters for datasets in meters, like the depth of phone LiDAR. Depth maps are 16-bit
+    /// PNGs or float EXRs with the name of the image, in a `depths` directory next to the images,
+    /// with optional confidence maps in a `confidence` directory.
+    #[arg(long, help_heading = "Dataset Options", default_value = "0.001")]
+    #[config(default = 0.001)]
+    pub depth_unit_scale: f32,
+    /// Take the focal length of cameras from the EXIF metadata of their photos, instead of from
+    /// the dataset, for datasets with rough intrinsics. Datasets without any intrinsics use the
+    /// EXIF metadata either way.
+    #[arg(long, help_heading = "Dataset Options", default_value = "false")]
+    #[config(default = false)]
+    pub exif_intrinsics: bool,
+    /// Train on EXR and 16-bit PNG images in linear color, keeping the full range of HDR images
+    /// instead of clipping them to 8-bit sRGB. Renders are tonemapped before they're compared to
+    /// the images, and to show them.
+    #[arg(long, help_heading = "Dataset Options", default_value = "false")]
+    #[config(default = false)]
+    pub hdr: bool,
+    /// Curve mapping the linear colors of HDR datasets to display colors.
+    #[arg(
+        long,
+        help_heading = "Dataset Options",
+        value_enum,
+        default_value = "reinhard"
+    )]
+    #[config(default = "Tonemap::Reinhard")]
+    pub tonemap: Tonemap,
+    /// Composite transparent images of Blender synthetic scenes onto a white background, as is
+    /// standard for benchmarks on these scenes.
+    #[arg(long, help_heading = "Dataset Options", default_value = "false")]
+    #[config(default = false)]
+    pub white_background: bool,
+    /// Recenter and rescale the scene to fit in a unit sphere, based on the initial point cloud
+    /// if there is one, and the cameras otherwise. Exported splats are mapped back to the
+    /// original frame of the dataset.
+    #[arg(long, help_heading = "Dataset Options", default_value = "false")]
+    #[config(default = false)]
+    pub normalize_scene: bool,
+    /// Directory to keep resized copies of the images in, at full, half and quarter resolution.
+    /// Later runs on the same images load these instead of decoding the originals. Images that
+    /// get blurred or sky masked aren't cached.
+    #[arg(long, help_heading = "Dataset Options")]
+    pub image_cache: Option<String>,
+    /// Path to an ONNX segmentation model of the sky, or any other background. Pixels it finds
+    /// are made transparent, so no splats are trained to cover them, which avoids floaters in
+    /// the sky. In images with a mask, these pixels are ignored instead. Masks are saved in a
+    /// `sky_masks` directory next to the dataset. Requires Brush to be built with the `onnx`
+    /// feature.
+    #[arg(long, help_heading = "Dataset Options")]
+    pub sky_segmenter: Option<String>,
+    /// Minimum background score for a pixel to be masked out.
+    #[arg(long, help_heading = "Dataset Options", default_value = "0.5")]
+    #[config(default = 0.5)]
+    pub sky_threshold: f32,
+    /// Resolution of the square input image of the segmentation model.
+    #[arg(long, help_heading = "Dataset Options", default_value = "512")]
+    #[config(default = 512)]
+    pub sky_input_size: u32,
+    /// Path to an ONNX detector (eg. for faces and license plates). Detected regions of images
+    /// are blurred before training. Requires Brush to be built with the `onnx` feature.
+    #[arg(long, help_heading = "Privacy Options")]
+    pub blur_detector: Option<String>,
+    /// Minimum detector score for a region to be blurred.
+    #[arg(long, help_heading = "Privacy Options", default_value = "0.4")]
+    #[config(default = 0.4)]
+    pub blur_threshold: f32,
+    /// Resolution of the square input image of the detector.
+    #[arg(long, help_heading = "Privacy Options", default_value = "640")]
+    #[config(default = 640)]
+    pub blur_input_size: u32,
+    /// Also flatten the colors of the trained splats seen in blurred regions before the final
+    /// export, so details the detector missed in some views don't show in others.
+    #[arg(long, help_heading = "Privacy Options", default_value = "false")]
+    #[config(default = false)]
+    pub blur_scrub: bool,
+}
+
+impl LoadDataseConfig {
+    /// Whether the `index`th loaded image, at `path`, goes in the eval dataset.
+    pub fn is_eval_view(&self, index: usize, path: &Path) -> bool {
+        if let Some(names) = &self.eval_images {
+            let name = path.file_name().and_then(|n| n.to_str());
+            name.is_some_and(|name| names.iter().any(|n| n == name))
+        } else if let Some(eval_period) = self.eval_split_every {
+            index % eval_period == 0
+        } else {
+            false
+        }
+    }
 }
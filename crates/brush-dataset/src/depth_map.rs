@@ -0,0 +1,207 @@
+// Metric depth maps of views, eg. from the LiDAR of a phone or rendered from a registered scan,
+// which the rendered depth of the splats is trained to match.
+use burn::{
+    prelude::Backend,
+    tensor::{Tensor, TensorData},
+};
+use image::DynamicImage;
+use thiserror::Error;
+
+use crate::scene::{crop_pixels, crop_values};
+
+#[derive(Debug, Error)]
+pub enum DepthMapError {
+    #[error("I/O error while reading depth map.")]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to decode depth map.")]
+    Image(#[from] image::ImageError),
+
+    #[error("Depth maps must be 16-bit PNG or float EXR images, 8 bits can't hold metric depth.")]
+    Unsupported,
+
+    #[error("Confidence map is {0}x{1}, but its depth map is {2}x{3}.")]
+    SizeMismatch(u32, u32, u32, u32),
+}
+
+/// A depth, and how much to trust it, per pixel of a view. Depth maps can have a lower resolution
+/// than the image, as long as they have the same aspect ratio.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthMap {
+    pub width: u32,
+    pub height: u32,
+    /// Depth along the view direction, in the units of the scene. Zero where there's no depth.
+    pub depth: Vec<f32>,
+    /// Confidence of the depth from 0 to 1. Pixels without a depth have no confidence.
+    pub confidence: Vec<f32>,
+}
+
+impl DepthMap {
+    /// Decode a depth map, and the confidence map that goes with it if any.
+    ///
+    /// 16-bit PNGs store depth in units of `unit_scale`, float EXRs store it in the units of the
+    /// scene. Confidence maps are greyscale images, where 8-bit maps with values of at most 2 are
+    /// the confidence levels of ARKit.
+    pub fn decode(
+        depth_bytes: &[u8],
+        confidence_bytes: Option<&[u8]>,
+        unit_scale: f32,
+    ) -> Result<Self, DepthMapError> {
+        let img = image::load_from_memory(depth_bytes)?;
+        let (width, height) = (img.width(), img.height());
+        let depth: Vec<f32> = match img {
+            DynamicImage::ImageLuma16(img) => img
+                .into_raw()
+                .into_iter()
+                .map(|d| f32::from(d) * unit_scale)
+                .collect(),
+            DynamicImage::ImageRgb16(_) | DynamicImage::ImageRgba16(_) => img
+                .into_rgb16()
+                .pixels()
+                .map(|p| f32::from(p.0[0]) * unit_scale)
+                .collect(),
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                img.into_rgb32f().pixels().map(|p| p.0[0]).collect()
+            }
+            _ => return Err(DepthMapError::Unsupported),
+        };
+        let depth: Vec<f32> = depth
+            .into_iter()
+            .map(|d| if d.is_finite() && d > 0.0 { d } else { 0.0 })
+            .collect();
+
+        let confidence = match confidence_bytes {
+            Some(bytes) => {
+                let img = image::load_from_memory(bytes)?;
+                if (img.width(), img.height()) != (width, height) {
+                    return Err(DepthMapError::SizeMismatch(
+                        img.width(),
+                        img.height(),
+                        width,
+                        height,
+                    ));
+                }
+                decode_confidence(img)
+            }
+            None => vec![1.0; depth.len()],
+        };
+        let confidence = confidence
+            .into_iter()
+            .zip(&depth)
+            .map(|(c, &d)| if d > 0.0 { c } else { 0.0 })
+            .collect();
+
+        Ok(Self {
+            width,
+            height,
+            depth,
+            confidence,
+        })
+    }
+
+    /// Scale the depths, eg. when the scene is scaled.
+    pub fn scaled(mut self, scale: f32) -> Self {
+        for d in &mut self.depth {
+            *d *= scale;
+        }
+        self
+    }
+
+    /// The part of the map between the normalized image coordinates `min` and `max`.
+    pub fn crop(&self, min: glam::Vec2, max: glam::Vec2) -> Self {
+        let (corner, size) = crop_pixels(glam::uvec2(self.width, self.height), min, max);
+        Self {
+            width: size.x,
+            height: size.y,
+            depth: crop_values(&self.depth, self.width, 1, corner, size),
+            confidence: crop_values(&self.confidence, self.width, 1, corner, size),
+        }
+    }
+
+    /// The depth and confidence as a [H, W, 2] tensor.
+    pub fn to_tensor<B: Backend>(&self, device: &B::Device) -> Tensor<B, 3> {
+        let data: Vec<f32> = self
+            .depth
+            .iter()
+            .zip(&self.confidence)
+            .flat_map(|(&d, &c)| [d, c])
+            .collect();
+        let shape = [self.height as usize, self.width as usize, 2];
+        Tensor::from_data(TensorData::new(data, shape), device)
+    }
+}
+
+fn decode_confidence(img: DynamicImage) -> Vec<f32> {
+    match img {
+        DynamicImage::ImageLuma8(_) | DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_) => {
+            let values = img.into_luma8().into_raw();
+            // ARKit stores low, medium and high confidence as 0, 1 and 2.
+            let max = if values.iter().all(|&v| v <= 2) {
+                2.0
+            } else {
+                255.0
+            };
+            values.into_iter().map(|v| f32::from(v) / max).collect()
+        }
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => img
+            .into_rgb32f()
+            .pixels()
+            .map(|p| p.0[0].clamp(0.0, 1.0))
+            .collect(),
+        _ => img
+            .into_luma16()
+            .into_raw()
+            .into_iter()
+            .map(|v| f32::from(v) / 65535.0)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, ImageBuffer, ImageFormat, Luma};
+    use std::io::Cursor;
+
+    fn encode(img: DynamicImage) -> Vec<u8> {
+        let mut bytes = Cursor::new(vec![]);
+        img.write_to(&mut bytes, ImageFormat::Png)
+            .expect("Valid PNG");
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn reads_depth_png() {
+        let depth: ImageBuffer<Luma<u16>, _> =
+            ImageBuffer::from_raw(2, 1, vec![3u16, 0]).expect("Valid image");
+        let confidence = GrayImage::from_raw(2, 1, vec![1, 2]).expect("Valid image");
+        let map = DepthMap::decode(
+            &encode(DynamicImage::ImageLuma16(depth)),
+            Some(&encode(DynamicImage::ImageLuma8(confidence))),
+            0.5,
+        )
+        .expect("Valid depth map");
+        assert_eq!(map.depth, [1.5, 0.0]);
+        // Pixels without depth aren't trusted, whatever their confidence.
+        assert_eq!(map.confidence, [0.5, 0.0]);
+
+        let eight_bit = GrayImage::from_raw(2, 1, vec![10, 20]).expect("Valid image");
+        assert!(matches!(
+            DepthMap::decode(&encode(DynamicImage::ImageLuma8(eight_bit)), None, 0.001),
+            Err(DepthMapError::Unsupported)
+        ));
+    }
+
+    #[test]
+    fn crops_right_half() {
+        let map = DepthMap {
+            width: 4,
+            height: 2,
+            depth: (0..8).map(|d| d as f32).collect(),
+            confidence: vec![1.0; 8],
+        };
+        let crop = map.crop(glam::vec2(0.5, 0.0), glam::vec2(1.0, 1.0));
+        assert_eq!((crop.width, crop.height), (2, 2));
+        assert_eq!(crop.depth, [2.0, 3.0, 6.0, 7.0]);
+    }
+}
@@ -0,0 +1,268 @@
+// Reading the focal length of the camera that took a photo from its EXIF metadata, for datasets
+// with poses but without (accurate) intrinsics.
+//
+// Only the few tags needed for that are read, from JPEG and TIFF files.
+
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_FOCAL_LENGTH: u16 = 0x920A;
+const TAG_FOCAL_LENGTH_35MM: u16 = 0xA405;
+const TAG_PIXEL_X_DIMENSION: u16 = 0xA002;
+const TAG_FOCAL_PLANE_X_RESOLUTION: u16 = 0xA20E;
+const TAG_FOCAL_PLANE_RESOLUTION_UNIT: u16 = 0xA210;
+
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+
+/// Camera data from the EXIF metadata of a photo.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExifCamera {
+    /// Focal length of the lens, in mm.
+    pub focal_length: Option<f64>,
+    /// Focal length a full frame (36 x 24mm) camera with the same field of view would have.
+    pub focal_length_35mm: Option<f64>,
+    /// Pixels per mm on the sensor, at a width of `width`.
+    pub focal_plane_resolution: Option<f64>,
+    /// Width of the image the metadata was written for.
+    pub width: Option<u32>,
+}
+
+// A TIFF structure, which is what EXIF metadata is stored as.
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl Tiff<'_> {
+    fn u16(&self, at: usize) -> Option<u16> {
+        let bytes = self.data.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let bytes = self.data.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    // Tags and offsets of the entries of the directory at `offset`.
+    fn entries(&self, offset: usize) -> Vec<(u16, usize)> {
+        let count = self.u16(offset).unwrap_or(0) as usize;
+        (0..count)
+            .map(|i| offset + 2 + i * 12)
+            .filter_map(|entry| Some((self.u16(entry)?, entry)))
+            .collect()
+    }
+
+    // First value of the entry at `entry`, for the number types camera tags use.
+    fn value(&self, entry: usize) -> Option<f64> {
+        if self.u32(entry + 4)? == 0 {
+            return None;
+        }
+        match self.u16(entry + 2)? {
+            TYPE_SHORT => Some(self.u16(entry + 8)? as f64),
+            TYPE_LONG => Some(self.u32(entry + 8)? as f64),
+            TYPE_RATIONAL => {
+                let offset = self.u32(entry + 8)? as usize;
+                let numerator = self.u32(offset)?;
+                let denominator = self.u32(offset + 4)?;
+                (denominator != 0).then(|| numerator as f64 / denominator as f64)
+            }
+            _ => None,
+        }
+    }
+}
+
+// The EXIF metadata in the segments of a JPEG file.
+fn jpeg_exif(data: &[u8]) -> Option<&[u8]> {
+    let mut at = 2;
+    loop {
+        let marker = *data.get(at + 1)?;
+        // Metadata comes before the start of the image data.
+        if data[at] != 0xFF || marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+        let len = u16::from_be_bytes([*data.get(at + 2)?, *data.get(at + 3)?]) as usize;
+        let segment = data.get(at + 4..at + 2 + len)?;
+        if let (0xE1, Some(exif)) = (marker, segment.strip_prefix(b"Exif\0\0")) {
+            return Some(exif);
+        }
+        at += 2 + len;
+    }
+}
+
+impl ExifCamera {
+    /// Read the camera of a photo from the start of its JPEG or TIFF file.
+    ///
+    /// Returns None if the file has no camera metadata.
+    pub fn from_image(data: &[u8]) -> Option<Self> {
+        let tiff = if data.starts_with(&[0xFF, 0xD8]) {
+            jpeg_exif(data)?
+        } else {
+            data
+        };
+        let big_endian = match tiff.get(0..2)? {
+            b"II" => false,
+            b"MM" => true,
+            _ => return None,
+        };
+        let tiff = Tiff {
+            data: tiff,
+            big_endian,
+        };
+        if tiff.u16(2)? != 42 {
+            return None;
+        }
+
+        // Camera tags are usually in the EXIF directory, linked from the first directory, but some
+        // cameras write them in the first directory.
+        let first = tiff.u32(4)? as usize;
+        let mut entries = tiff.entries(first);
+        let exif_dir = entries
+            .iter()
+            .find(|(tag, _)| *tag == TAG_EXIF_IFD)
+            .and_then(|&(_, entry)| tiff.u32(entry + 8));
+        if let Some(exif_dir) = exif_dir {
+            entries.extend(tiff.entries(exif_dir as usize));
+        }
+
+        let mut camera = Self::default();
+        let mut resolution_unit = 2.0;
+        for (tag, entry) in entries {
+            let value = tiff.value(entry);
+            match tag {
+                TAG_FOCAL_LENGTH => camera.focal_length = value,
+                TAG_FOCAL_LENGTH_35MM => camera.focal_length_35mm = value,
+                TAG_PIXEL_X_DIMENSION => camera.width = value.map(|w| w as u32),
+                TAG_FOCAL_PLANE_X_RESOLUTION => camera.focal_plane_resolution = value,
+                TAG_FOCAL_PLANE_RESOLUTION_UNIT => resolution_unit = value.unwrap_or(2.0),
+                _ => {}
+            }
+        }
+        // Convert to pixels per mm, from pixels per inch, cm, mm or µm.
+        let mm_per_unit = match resolution_unit as u32 {
+            3 => 10.0,
+            4 => 1.0,
+            5 => 0.001,
+            _ => 25.4,
+        };
+        camera.focal_plane_resolution = camera
+            .focal_plane_resolution
+            .map(|res| res / mm_per_unit)
+            .filter(|res| *res > 0.0);
+        camera.focal_length = camera.focal_length.filter(|f| *f > 0.0);
+        camera.focal_length_35mm = camera.focal_length_35mm.filter(|f| *f > 0.0);
+
+        (camera.focal_length_35mm.is_some() || camera.focal_length.is_some()).then_some(camera)
+    }
+
+    /// Focal length in pixels, for an image of `width` x `height` pixels.
+    pub fn focal_in_pixels(&self, width: u32, height: u32) -> Option<f64> {
+        // The 35mm equivalent is relative to the diagonal of a full frame sensor. Cameras know
+        // their crop factor, so this is the most reliable.
+        if let Some(focal) = self.focal_length_35mm {
+            let diagonal = (width as f64).hypot(height as f64);
+            return Some(focal * diagonal / 36.0f64.hypot(24.0));
+        }
+        let focal = self.focal_length?;
+        let resolution = self.focal_plane_resolution?;
+        let scale = self.width.map_or(1.0, |w| width as f64 / w as f64);
+        Some(focal * resolution * scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A JPEG with EXIF metadata of a 4.5mm lens, 26mm in full frame terms.
+    fn jpeg(big_endian: bool) -> Vec<u8> {
+        let mut tiff = vec![];
+        let u16 = |tiff: &mut Vec<u8>, v: u16| {
+            tiff.extend(if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            });
+        };
+        let u32 = |tiff: &mut Vec<u8>, v: u32| {
+            tiff.extend(if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            });
+        };
+        tiff.extend(if big_endian { b"MM" } else { b"II" });
+        u16(&mut tiff, 42);
+        u32(&mut tiff, 8);
+        // First directory, linking to the EXIF directory at 26.
+        u16(&mut tiff, 1);
+        u16(&mut tiff, TAG_EXIF_IFD);
+        u16(&mut tiff, TYPE_LONG);
+        u32(&mut tiff, 1);
+        u32(&mut tiff, 26);
+        u32(&mut tiff, 0);
+        // EXIF directory, with the focal length fraction at 56.
+        u16(&mut tiff, 2);
+        u16(&mut tiff, TAG_FOCAL_LENGTH);
+        u16(&mut tiff, TYPE_RATIONAL);
+        u32(&mut tiff, 1);
+        u32(&mut tiff, 56);
+        u16(&mut tiff, TAG_FOCAL_LENGTH_35MM);
+        u16(&mut tiff, TYPE_SHORT);
+        u32(&mut tiff, 1);
+        u16(&mut tiff, 26);
+        u16(&mut tiff, 0);
+        u32(&mut tiff, 0);
+        u32(&mut tiff, 45);
+        u32(&mut tiff, 10);
+
+        let mut jpeg = vec![0xFF, 0xD8];
+        // A JFIF segment before the EXIF one.
+        jpeg.extend([0xFF, 0xE0, 0, 4, 0, 0]);
+        jpeg.extend([0xFF, 0xE1]);
+        jpeg.extend((2 + 6 + tiff.len() as u16).to_be_bytes());
+        jpeg.extend(b"Exif\0\0");
+        jpeg.extend(tiff);
+        jpeg.extend([0xFF, 0xDA, 0, 2]);
+        jpeg
+    }
+
+    #[test]
+    fn reads_focal_length() {
+        for big_endian in [false, true] {
+            let camera = ExifCamera::from_image(&jpeg(big_endian)).expect("Has EXIF");
+            assert_eq!(camera.focal_length, Some(4.5));
+            assert_eq!(camera.focal_length_35mm, Some(26.0));
+            let focal = camera
+                .focal_in_pixels(4000, 3000)
+                .expect("Has focal length");
+            assert!((focal - 26.0 * 5000.0 / 36.0f64.hypot(24.0)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn focal_plane_resolution() {
+        // A 10mm lens on a sensor with 200 pixels per mm, at twice the resolution of the image.
+        let camera = ExifCamera {
+            focal_length: Some(10.0),
+            focal_length_35mm: None,
+            focal_plane_resolution: Some(200.0),
+            width: Some(6000),
+        };
+        assert_eq!(camera.focal_in_pixels(3000, 2000), Some(1000.0));
+    }
+
+    #[test]
+    fn no_metadata() {
+        assert!(ExifCamera::from_image(b"not an image").is_none());
+        assert!(ExifCamera::from_image(&[0xFF, 0xD8, 0xFF, 0xDA, 0, 2]).is_none());
+    }
+}
@@ -0,0 +1,244 @@
+// 2D feature maps of views, eg. from SAM or CLIP, which splats can learn to render. These are
+// read from, and per splat features are written to, NumPy .npy files.
+use burn::{
+    prelude::Backend,
+    tensor::{Tensor, TensorData},
+};
+use thiserror::Error;
+
+use crate::scene::{crop_pixels, crop_values};
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+#[derive(Debug, Error)]
+pub enum FeatureMapError {
+    #[error("I/O error while reading feature map.")]
+    Io(#[from] std::io::Error),
+
+    #[error("Feature map is not a .npy file.")]
+    NotNpy,
+
+    #[error("Invalid .npy header: {0}")]
+    InvalidHeader(String),
+
+    #[error("Unsupported .npy data type {0}, only little endian f32 and f16 are supported.")]
+    UnsupportedType(String),
+
+    #[error("Feature maps must be [H, W, C] arrays in C order, got shape {0:?}.")]
+    InvalidShape(Vec<usize>),
+
+    #[error("Feature map has a non finite value at index {0}.")]
+    NonFinite(usize),
+}
+
+/// A feature vector per pixel of a view. Feature maps can have a lower resolution than the image,
+/// as long as they have the same aspect ratio.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureMap {
+    pub width: u32,
+    pub height: u32,
+    pub channels: usize,
+    /// Features in [H, W, C] order.
+    pub data: Vec<f32>,
+}
+
+// Value of `key` in the Python dict literal of a .npy header.
+fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str, FeatureMapError> {
+    let missing = || FeatureMapError::InvalidHeader(format!("missing '{key}'"));
+    let start = header.find(&format!("'{key}'")).ok_or_else(missing)? + key.len() + 2;
+    let rest = header[start..]
+        .trim_start()
+        .strip_prefix(':')
+        .ok_or_else(missing)?;
+    let rest = rest.trim_start();
+    let end = match rest.chars().next() {
+        Some('(') => rest.find(')').map(|i| i + 1),
+        Some(quote @ ('\'' | '"')) => rest[1..].find(quote).map(|i| i + 2),
+        _ => rest.find([',', '}']),
+    }
+    .ok_or_else(missing)?;
+    Ok(rest[..end].trim())
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = u32::from((bits >> 10) & 0x1f);
+    let mantissa = u32::from(bits & 0x3ff);
+    match exponent {
+        // Subnormal numbers.
+        0 => sign * mantissa as f32 / (1 << 24) as f32,
+        0x1f => {
+            let value = if mantissa == 0 {
+                f32::INFINITY
+            } else {
+                f32::NAN
+            };
+            sign * value
+        }
+        _ => sign * f32::from_bits(((exponent + 112) << 23) | (mantissa << 13)),
+    }
+}
+
+/// The shape and values of the array in the bytes of a .npy file, eg. the features of splats, or
+/// a query feature to compare them to.
+pub fn read_npy(bytes: &[u8]) -> Result<(Vec<usize>, Vec<f32>), FeatureMapError> {
+    if bytes.len() < 10 || !bytes.starts_with(NPY_MAGIC) {
+        return Err(FeatureMapError::NotNpy);
+    }
+    // Version 1 has a 2 byte header length, later versions 4 bytes.
+    let (header_len, header_start) = if bytes[6] == 1 {
+        (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10)
+    } else if bytes.len() >= 12 {
+        let len = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+        (len as usize, 12)
+    } else {
+        return Err(FeatureMapError::NotNpy);
+    };
+    let header = bytes
+        .get(header_start..header_start + header_len)
+        .and_then(|h| std::str::from_utf8(h).ok())
+        .ok_or_else(|| FeatureMapError::InvalidHeader("truncated header".to_owned()))?;
+    let data = &bytes[header_start + header_len..];
+
+    let descr = header_value(header, "descr")?.trim_matches(['\'', '"']);
+    if header_value(header, "fortran_order")? != "False" {
+        return Err(FeatureMapError::InvalidHeader(
+            "arrays in Fortran order aren't supported".to_owned(),
+        ));
+    }
+    let shape = header_value(header, "shape")?;
+    let shape = shape
+        .trim_matches(['(', ')'])
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| dim.parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| FeatureMapError::InvalidHeader(format!("invalid shape: {e}")))?;
+
+    let values: Vec<f32> = match descr {
+        "<f4" => data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        "<f2" => data
+            .chunks_exact(2)
+            .map(|b| f16_to_f32(u16::from_le_bytes([b[0], b[1]])))
+            .collect(),
+        _ => return Err(FeatureMapError::UnsupportedType(descr.to_owned())),
+    };
+
+    if values.len() != shape.iter().product::<usize>() || values.is_empty() {
+        return Err(FeatureMapError::InvalidShape(shape));
+    }
+    // A NaN would poison the features of every splat it reaches.
+    if let Some(index) = values.iter().position(|v| !v.is_finite()) {
+        return Err(FeatureMapError::NonFinite(index));
+    }
+    Ok((shape, values))
+}
+
+impl FeatureMap {
+    /// Read a feature map from the bytes of a .npy file holding an [H, W, C] array.
+    pub fn from_npy(bytes: &[u8]) -> Result<Self, FeatureMapError> {
+        let (shape, values) = read_npy(bytes)?;
+        let [height, width, channels] = shape[..] else {
+            return Err(FeatureMapError::InvalidShape(shape));
+        };
+        Ok(Self {
+            width: width as u32,
+            height: height as u32,
+            channels,
+            data: values,
+        })
+    }
+
+    /// The part of the map between the normalized image coordinates `min` and `max`.
+    pub fn crop(&self, min: glam::Vec2, max: glam::Vec2) -> Self {
+        let (corner, size) = crop_pixels(glam::uvec2(self.width, self.height), min, max);
+        Self {
+            width: size.x,
+            height: size.y,
+            channels: self.channels,
+            data: crop_values(&self.data, self.width, self.channels, corner, size),
+        }
+    }
+
+    /// The features as a [H, W, C] tensor.
+    pub fn to_tensor<B: Backend>(&self, device: &B::Device) -> Tensor<B, 3> {
+        let shape = [self.height as usize, self.width as usize, self.channels];
+        Tensor::from_data(TensorData::new(self.data.clone(), shape), device)
+    }
+}
+
+/// Write `data`, an array of f32s with `shape`, as a .npy file.
+pub fn write_npy(shape: &[usize], data: &[f32]) -> Vec<u8> {
+    assert_eq!(
+        shape.iter().product::<usize>(),
+        data.len(),
+        "Shape doesn't match the data"
+    );
+    let dims: Vec<_> = shape.iter().map(|d| format!("{d},")).collect();
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}), }}",
+        dims.join(" ")
+    );
+    // Pad so the data starts at a multiple of 64 bytes, with a newline at the end of the header.
+    let unpadded = NPY_MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat(unpadded.next_multiple_of(64) - unpadded));
+    header.push('\n');
+
+    let mut bytes = Vec::with_capacity(NPY_MAGIC.len() + 4 + header.len() + data.len() * 4);
+    bytes.extend_from_slice(NPY_MAGIC);
+    bytes.extend_from_slice(&[1, 0]);
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    for value in data {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_npy() {
+        let data: Vec<f32> = (0..24).map(|i| i as f32 * 0.5 - 3.0).collect();
+        let bytes = write_npy(&[2, 3, 4], &data);
+        let map = FeatureMap::from_npy(&bytes).expect("Valid npy");
+        assert_eq!((map.height, map.width, map.channels), (2, 3, 4));
+        assert_eq!(map.data, data);
+
+        // Half precision, as written by numpy.
+        let header = "{'descr': '<f2', 'fortran_order': False, 'shape': (1, 2, 2), }";
+        let mut bytes = NPY_MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        for value in [0x3c00u16, 0xc000, 0x3800, 0x0001] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let map = FeatureMap::from_npy(&bytes).expect("Valid npy");
+        assert_eq!(map.data, [1.0, -2.0, 0.5, 1.0 / (1 << 24) as f32]);
+
+        // Images of features need a channel dimension.
+        let bytes = write_npy(&[4, 6], &[0.0; 24]);
+        assert!(matches!(
+            FeatureMap::from_npy(&bytes),
+            Err(FeatureMapError::InvalidShape(_))
+        ));
+        assert_eq!(
+            read_npy_vector(&write_npy(&[3], &[1.0, 2.0, 3.0])).ok(),
+            Some(vec![1.0, 2.0, 3.0])
+        );
+
+        // Broken features are an error rather than NaN splats.
+        let bytes = write_npy(&[1, 2, 1], &[0.0, f32::NAN]);
+        assert!(matches!(
+            FeatureMap::from_npy(&bytes),
+            Err(FeatureMapError::NonFinite(1))
+        ));
+    }
+}
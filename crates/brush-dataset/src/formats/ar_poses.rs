@@ -0,0 +1,430 @@
+// Captures of AR frameworks like ARKit and ARCore, as many capture apps write them: a `poses.json`
+// with the tracked camera to world matrices, timestamps and intrinsics, next to a directory of
+// frames.
+//
+// Frames are matched to their pose by file name when the poses name them. Otherwise the pose of a
+// frame is found by the timestamp in its file name, interpolated between the poses around it, as
+// AR frameworks track poses at a different rate than they save frames.
+use super::{DataStream, FormatError, find_mask_path};
+use crate::{
+    Dataset,
+    config::LoadDataseConfig,
+    scene::{LoadImage, SceneView},
+    scene_transform::CoordinateConvention,
+    splat_import::SplatMessage,
+};
+use brush_render::camera::{Camera, focal_to_fov};
+use brush_vfs::BrushVfs;
+use glam::{Mat4, Quat, Vec3};
+use path_clean::PathClean;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::io::AsyncReadExt;
+
+const IMAGE_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "webp", "exr"];
+
+// Directories frames are looked for in, next to the poses.
+const FRAME_DIRS: [&str; 4] = ["frames", "images", "rgb", "."];
+
+/// A 4x4 or 3x3 matrix, as nested rows or as a flat list.
+#[derive(serde::Deserialize, Clone)]
+#[serde(untagged)]
+enum JsonMatrix {
+    Rows(Vec<Vec<f32>>),
+    Flat(Vec<f32>),
+}
+
+impl JsonMatrix {
+    // The values row by row. Flat matrices are written row by row by some tools, and column by
+    // column, as ARKit stores them, by others. Which one it is shows in where the constant row or
+    // column is.
+    fn rows(&self, size: usize) -> Option<Vec<f32>> {
+        let values: Vec<f32> = match self {
+            Self::Rows(rows) => rows.iter().flatten().copied().collect(),
+            Self::Flat(values) => values.clone(),
+        };
+        if values.len() != size * size {
+            return None;
+        }
+        let Self::Flat(_) = self else {
+            return Some(values);
+        };
+        // The last row, but for the diagonal, is zero when written row by row.
+        let last_row_zero = values[size * (size - 1)..size * size - 1]
+            .iter()
+            .all(|v| v.abs() < 1e-6);
+        let last_column_zero = (0..size - 1).all(|r| values[r * size + size - 1].abs() < 1e-6);
+        if last_row_zero || !last_column_zero {
+            Some(values)
+        } else {
+            Some(
+                (0..size * size)
+                    .map(|i| values[(i % size) * size + i / size])
+                    .collect(),
+            )
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+#[serde(untagged)]
+enum JsonIntrinsics {
+    Values {
+        #[serde(alias = "fl_x")]
+        fx: f32,
+        #[serde(alias = "fl_y")]
+        fy: Option<f32>,
+        cx: Option<f32>,
+        cy: Option<f32>,
+        #[serde(alias = "w")]
+        width: Option<f32>,
+        #[serde(alias = "h")]
+        height: Option<f32>,
+    },
+    // A 3x3 camera matrix.
+    Matrix(JsonMatrix),
+}
+
+/// Intrinsics, in pixels of an image of the given size, if any.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Intrinsics {
+    fx: f32,
+    fy: f32,
+    cx: Option<f32>,
+    cy: Option<f32>,
+    size: Option<(f32, f32)>,
+}
+
+impl JsonIntrinsics {
+    fn intrinsics(&self) -> Option<Intrinsics> {
+        match self {
+            Self::Values {
+                fx,
+                fy,
+                cx,
+                cy,
+                width,
+                height,
+            } => Some(Intrinsics {
+                fx: *fx,
+                fy: fy.unwrap_or(*fx),
+                cx: *cx,
+                cy: *cy,
+                size: width.zip(*height),
+            }),
+            Self::Matrix(matrix) => {
+                let k = matrix.rows(3)?;
+                Some(Intrinsics {
+                    fx: k[0],
+                    fy: k[4],
+                    cx: Some(k[2]),
+                    cy: Some(k[5]),
+                    size: None,
+                })
+            }
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct JsonPose {
+    #[serde(alias = "time", alias = "t")]
+    timestamp: Option<f64>,
+    /// Camera to world transform.
+    #[serde(alias = "pose", alias = "matrix", alias = "camera_to_world")]
+    transform: JsonMatrix,
+    intrinsics: Option<JsonIntrinsics>,
+    /// Path of the frame of this pose, relative to the poses file.
+    #[serde(alias = "image", alias = "file_path")]
+    file: Option<String>,
+}
+
+/// Axes of the cameras of the poses.
+#[derive(serde::Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum JsonConvention {
+    /// x right, y up and looking down -z, as ARKit and ARCore cameras.
+    #[default]
+    #[serde(alias = "arkit", alias = "arcore")]
+    OpenGl,
+    /// x right, y down and looking down +z.
+    #[serde(alias = "colmap")]
+    OpenCv,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct JsonPoses {
+    #[serde(alias = "frames")]
+    poses: Vec<JsonPose>,
+    /// Intrinsics of all frames, for poses without their own.
+    intrinsics: Option<JsonIntrinsics>,
+    #[serde(default)]
+    convention: JsonConvention,
+}
+
+/// A tracked pose at a point in time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TimedPose {
+    time: f64,
+    translation: Vec3,
+    rotation: Quat,
+    intrinsics: Option<Intrinsics>,
+}
+
+// The pose at `time`, interpolated between the poses around it. Poses must be sorted by time.
+// Frames further than `max_gap` from any pose don't get one, the tracking was lost there.
+fn pose_at(poses: &[TimedPose], time: f64, max_gap: f64) -> Option<TimedPose> {
+    let next = poses.partition_point(|p| p.time < time);
+    let before = next.checked_sub(1).map(|i| poses[i]);
+    let after = poses.get(next).copied();
+    match (before, after) {
+        (Some(a), Some(b)) => {
+            if (time - a.time).min(b.time - time) > max_gap {
+                return None;
+            }
+            let t = if b.time > a.time {
+                ((time - a.time) / (b.time - a.time)) as f32
+            } else {
+                0.0
+            };
+            Some(TimedPose {
+                time,
+                translation: a.translation.lerp(b.translation, t),
+                rotation: a.rotation.slerp(b.rotation, t),
+                intrinsics: if t < 0.5 { a.intrinsics } else { b.intrinsics },
+            })
+        }
+        (Some(p), None) | (None, Some(p)) => ((p.time - time).abs() <= max_gap).then_some(p),
+        (None, None) => None,
+    }
+}
+
+// The timestamp of a frame in its file name, eg. `frame_1699971234.5133.jpg`: the last number in
+// the name.
+fn timestamp_of(path: &Path) -> Option<f64> {
+    let stem = path.file_stem()?.to_str()?;
+    stem.split(|c: char| !c.is_ascii_digit() && c != '.')
+        .filter_map(|part| part.trim_matches('.').parse().ok())
+        .next_back()
+}
+
+pub async fn read_dataset(
+    vfs: Arc<BrushVfs>,
+    load_args: &LoadDataseConfig,
+) -> Option<Result<(DataStream<SplatMessage>, Dataset), FormatError>> {
+    let poses_path = vfs.files_ending_in("poses.json").next()?;
+    log::info!("Loading AR capture poses");
+    Some(read_dataset_inner(vfs, load_args, poses_path).await)
+}
+
+async fn read_dataset_inner(
+    vfs: Arc<BrushVfs>,
+    load_args: &LoadDataseConfig,
+    poses_path: PathBuf,
+) -> Result<(DataStream<SplatMessage>, Dataset), FormatError> {
+    let mut buf = String::new();
+    vfs.reader_at_path(&poses_path)
+        .await?
+        .read_to_string(&mut buf)
+        .await?;
+    let capture: JsonPoses = serde_json::from_str(&buf)?;
+    let base_dir = poses_path
+        .parent()
+        .expect("Poses path must be a filename")
+        .to_path_buf();
+
+    let convention = match capture.convention {
+        JsonConvention::OpenGl => CoordinateConvention::OpenGl,
+        JsonConvention::OpenCv => CoordinateConvention::Colmap,
+    };
+    let global_intrinsics = capture.intrinsics.as_ref().and_then(|i| i.intrinsics());
+
+    let mut poses = vec![];
+    let mut named_frames = vec![];
+    for pose in &capture.poses {
+        let transform = pose
+            .transform
+            .rows(4)
+            .ok_or(FormatError::InvalidCamera("Poses must be 4x4 matrices"))?;
+        let transform = Mat4::from_cols_slice(&transform).transpose();
+        let (_, rotation, translation) = transform.to_scale_rotation_translation();
+        let timed = TimedPose {
+            time: pose.timestamp.unwrap_or_default(),
+            translation,
+            rotation: convention.camera_to_brush(rotation),
+            intrinsics: pose
+                .intrinsics
+                .as_ref()
+                .and_then(|i| i.intrinsics())
+                .or(global_intrinsics),
+        };
+        match &pose.file {
+            Some(file) => named_frames.push((base_dir.join(file).clean(), timed)),
+            None => poses.push(timed),
+        }
+    }
+
+    // Without file names, match the frames to the poses by their timestamps.
+    let frames = if named_frames.is_empty() {
+        if capture.poses.iter().any(|p| p.timestamp.is_none()) {
+            return Err(FormatError::InvalidCamera(
+                "Poses need either a timestamp or the file name of their frame",
+            ));
+        }
+        poses.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        // Allow a gap of a few tracking intervals, any more and tracking was lost.
+        let mut intervals: Vec<f64> = poses.windows(2).map(|w| w[1].time - w[0].time).collect();
+        intervals.sort_by(f64::total_cmp);
+        let max_gap = intervals.get(intervals.len() / 2).copied().unwrap_or(0.0) * 2.0;
+
+        let frame_paths = FRAME_DIRS
+            .iter()
+            .map(|dir| base_dir.join(dir).clean())
+            .map(|dir| {
+                let mut paths: Vec<_> = vfs
+                    .file_paths()
+                    .filter(|p| p.parent().map(|p| p.clean()).as_ref() == Some(&dir))
+                    .filter(|p| {
+                        p.extension()
+                            .and_then(|ext| ext.to_str())
+                            .is_some_and(|ext| {
+                                IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())
+                            })
+                    })
+                    .collect();
+                paths.sort();
+                paths
+            })
+            .find(|paths| !paths.is_empty())
+            .unwrap_or_default();
+
+        let mut frames = vec![];
+        for path in frame_paths {
+            let Some(time) = timestamp_of(&path) else {
+                log::warn!("No timestamp in the name of frame {path:?}");
+                continue;
+            };
+            match pose_at(&poses, time, max_gap) {
+                Some(pose) => frames.push((path, pose)),
+                None => log::warn!("No pose near the time of frame {path:?}, skipping"),
+            }
+        }
+        frames
+    } else {
+        named_frames
+    };
+
+    let mut train_views = vec![];
+    let mut eval_views = vec![];
+    for (i, (path, pose)) in frames
+        .into_iter()
+        .take(load_args.max_frames.unwrap_or(usize::MAX))
+        .step_by(load_args.subsample_frames.unwrap_or(1) as usize)
+        .enumerate()
+    {
+        let mask_path = find_mask_path(&vfs, &path);
+        let image =
+            match LoadImage::new(vfs.clone(), &path, mask_path, load_args.max_resolution).await {
+                Ok(image) => image,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    log::warn!("Image not found: {path:?}");
+                    continue;
+                }
+                Err(e) => Err(e)?,
+            };
+
+        let (w, h) = (image.width() as f32, image.height() as f32);
+        let (fovx, fovy, cuv) = match pose.intrinsics {
+            Some(intrinsics) => {
+                // Intrinsics are often for the full sensor resolution, while frames are saved
+                // smaller. Field of view and principal point in UV don't depend on the size.
+                let (iw, ih) = intrinsics.size.unwrap_or((w, h));
+                let fovx = focal_to_fov(f64::from(intrinsics.fx), iw.round() as u32);
+                let fovy = focal_to_fov(f64::from(intrinsics.fy), ih.round() as u32);
+                let cx = intrinsics.cx.unwrap_or(iw / 2.0) / iw;
+                let cy = intrinsics.cy.unwrap_or(ih / 2.0) / ih;
+                (fovx, fovy, glam::vec2(cx, cy))
+            }
+            None => {
+                let Some((fovx, fovy)) = image.exif_fov().await else {
+                    Err(FormatError::InvalidCamera(
+                        "Must have some kind of focal length, in the poses or the EXIF metadata of the images",
+                    ))?
+                };
+                (fovx, fovy, glam::vec2(0.5, 0.5))
+            }
+        };
+        let view = SceneView {
+            image,
+            camera: Camera::new(pose.translation, pose.rotation, fovx, fovy, cuv),
+        };
+        if load_args.is_eval_view(i, &view.image.path) {
+            eval_views.push(view);
+        } else {
+            train_views.push(view);
+        }
+    }
+
+    if train_views.is_empty() {
+        return Err(FormatError::InvalidCamera(
+            "None of the frames could be matched to a pose",
+        ));
+    }
+
+    let dataset = Dataset::from_views(train_views, eval_views);
+    Ok((Box::pin(tokio_stream::empty()), dataset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pose(time: f64, x: f32) -> TimedPose {
+        TimedPose {
+            time,
+            translation: Vec3::new(x, 0.0, 0.0),
+            rotation: Quat::IDENTITY,
+            intrinsics: None,
+        }
+    }
+
+    #[test]
+    fn interpolates_poses() {
+        let poses = [pose(1.0, 0.0), pose(2.0, 2.0), pose(5.0, 8.0)];
+        let mid = pose_at(&poses, 1.5, 0.5).expect("Pose between two poses");
+        assert_eq!(mid.translation, Vec3::new(1.0, 0.0, 0.0));
+        // Tracking was lost between the last two poses.
+        assert!(pose_at(&poses, 3.5, 0.5).is_none());
+        assert_eq!(pose_at(&poses, 5.25, 0.5), Some(poses[2]));
+        assert!(pose_at(&poses, 0.0, 0.5).is_none());
+    }
+
+    #[test]
+    fn reads_matrix_layouts() {
+        let rows = [
+            1.0, 0.0, 0.0, 3.0, //
+            0.0, 1.0, 0.0, 4.0, //
+            0.0, 0.0, 1.0, 5.0, //
+            0.0, 0.0, 0.0, 1.0,
+        ];
+        let columns: Vec<f32> = (0..16).map(|i| rows[(i % 4) * 4 + i / 4]).collect();
+        let nested = JsonMatrix::Rows(rows.chunks(4).map(|r| r.to_vec()).collect());
+        assert_eq!(
+            JsonMatrix::Flat(rows.to_vec()).rows(4).as_deref(),
+            Some(&rows[..])
+        );
+        assert_eq!(
+            JsonMatrix::Flat(columns).rows(4).as_deref(),
+            Some(&rows[..])
+        );
+        assert_eq!(nested.rows(4).as_deref(), Some(&rows[..]));
+
+        assert_eq!(
+            timestamp_of(Path::new("frames/frame_1699971234.5.jpg")),
+            Some(1_699_971_234.5)
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use super::DataStream;
+use super::FormatError;
+use super::nerfstudio::{JsonScene, read_transforms_file};
+use crate::{
+    Dataset,
+    config::LoadDataseConfig,
+    scene::SceneView,
+    splat_import::{SplatImportError, SplatMessage},
+};
+use brush_vfs::BrushVfs;
+use glam::Vec3;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+
+async fn read_scene(vfs: &BrushVfs, path: &Path) -> Result<JsonScene, FormatError> {
+    let mut buf = String::new();
+    vfs.reader_at_path(path)
+        .await?
+        .read_to_string(&mut buf)
+        .await?;
+    Ok(serde_json::from_str(&buf)?)
+}
+
+/// Load a scene in the format of the original synthetic benchmark scenes (lego, chair, ...).
+///
+/// These are rendered from Blender and have a `transforms_train.json` and `transforms_test.json`, which
+/// only specify a horizontal field of view. Cameras use the Blender convention (y up, looking down -z), which
+/// is handled the same way as nerfstudio datasets. The test split is used for evaluation, as is standard
+/// for benchmarks on these scenes.
+pub async fn read_dataset(
+    vfs: Arc<BrushVfs>,
+    load_args: &LoadDataseConfig,
+) -> Option<Result<(DataStream<SplatMessage>, Dataset), FormatError>> {
+    let train_path = vfs.files_ending_in("transforms_train.json").next()?;
+    let test_path = vfs.files_ending_in("transforms_test.json").next()?;
+
+    let train_scene = match read_scene(&vfs, &train_path).await {
+        Ok(scene) => scene,
+        Err(e) => return Some(Err(e)),
+    };
+
+    // Synthetic scenes only have a global field of view. Anything with explicit intrinsics
+    // is left to the nerfstudio loader.
+    if train_scene.camera_angle_x.is_none() || train_scene.fl_x.is_some() {
+        return None;
+    }
+
+    log::info!("Loading Blender synthetic dataset");
+    Some(read_dataset_inner(vfs, load_args, train_scene, &train_path, &test_path).await)
+}
+
+fn with_white(views: Vec<SceneView>) -> Vec<SceneView> {
+    views
+        .into_iter()
+        .map(|mut view| {
+            view.image = view.image.with_background(Vec3::ONE);
+            view
+        })
+        .collect()
+}
+
+async fn read_dataset_inner(
+    vfs: Arc<BrushVfs>,
+    load_args: &LoadDataseConfig,
+    train_scene: JsonScene,
+    train_path: &Path,
+    test_path: &Path,
+) -> Result<(DataStream<SplatMessage>, Dataset), FormatError> {
+    let train_views = read_transforms_file(train_scene, train_path, vfs.clone(), load_args).await?;
+    let test_scene = read_scene(&vfs, test_path).await?;
+    let eval_views = read_transforms_file(test_scene, test_path, vfs.clone(), load_args).await?;
+
+    let (train_views, eval_views) = if load_args.white_background {
+        (with_white(train_views), with_white(eval_views))
+    } else {
+        (train_views, eval_views)
+    };
+
+    let dataset = Dataset::from_views(train_views, eval_views);
+
+    // Synthetic scenes don't come with a point cloud, training will start from a random initialization.
+    let splat_stream = tokio_stream::empty::<Result<SplatMessage, SplatImportError>>();
+    Ok((Box::pin(splat_stream), dataset))
+}
@@ -3,15 +3,17 @@ use std::{path::PathBuf, sync::Arc};
 use super::{DataStream, FormatError};
 use crate::{
     Dataset,
-    config::LoadDataseConfig,
+    config::{LoadDataseConfig, PointInit},
     formats::find_mask_path,
+    reprojection::camera_residuals,
     scene::{LoadImage, SceneView},
     splat_import::SplatMessage,
 };
 use async_fn_stream::try_fn_stream;
 use brush_render::{
     camera::{self, Camera},
-    gaussian_splats::Splats,
+    gaussian_splats::{Splats, inverse_sigmoid},
+    knn::nearest_neighbour_log_scales,
     sh::rgb_to_sh,
 };
 use brush_vfs::BrushVfs;
@@ -50,6 +52,22 @@ fn find_mask_and_img(vfs: &BrushVfs, name: &str) -> Option<(PathBuf, Option<Path
     path_masks.into_iter().min_by_key(|kv| kv.0.clone())
 }
 
+// Points seen by this many images, without reprojection error, are fully trusted.
+const TRUSTED_TRACK_LENGTH: usize = 10;
+
+// Offset to the log scale and raw opacity of the splat made from a point seen by `track_length`
+// images, with a mean reprojection error in pixels. Confident points start at half the size of
+// their neighbourhood and mostly opaque, unsure points at the full size and faint.
+fn track_weights(track_length: usize, error: f64) -> (f32, f32) {
+    // Two views are the least to triangulate a point from.
+    let track =
+        (track_length.saturating_sub(2) as f32 / (TRUSTED_TRACK_LENGTH - 2) as f32).min(1.0);
+    let confidence = track / (1.0 + error.max(0.0) as f32);
+    let scale = 1.0 - 0.5 * confidence;
+    let opacity = 0.05 + 0.45 * confidence;
+    (scale.ln(), inverse_sigmoid(opacity))
+}
+
 pub(crate) async fn load_dataset(
     vfs: Arc<BrushVfs>,
     load_args: &LoadDataseConfig,
@@ -90,6 +108,27 @@ async fn load_dataset_inner(
         colmap_reader::read_images(&mut buf_reader, is_binary).await?
     };
 
+    // Read the points up front, to check how well the cameras fit them.
+    let points_path = { vfs.files_ending_in("points3d.txt").next() }
+        .or_else(|| vfs.files_ending_in("points3d.bin").next());
+    let points_data = match points_path {
+        Some(points_path) => {
+            let is_binary = matches!(
+                points_path.extension().and_then(|p| p.to_str()),
+                Some("bin")
+            );
+            // At this point the VFS has said this file exists so just unwrap.
+            let mut points_file = vfs.reader_at_path(&points_path).await.expect("unreachable");
+            colmap_reader::read_points3d(&mut points_file, is_binary)
+                .await
+                .ok()
+        }
+        None => None,
+    };
+    let residuals = points_data.as_ref().map_or_else(Vec::new, |points| {
+        camera_residuals(&cam_model_data, &img_infos, points)
+    });
+
     let mut img_info_list = img_infos.into_iter().collect::<Vec<_>>();
     img_info_list.sort_by_key(|key_img| key_img.1.name.clone());
 
@@ -127,24 +166,26 @@ async fn load_dataset_inner(
         let cam_to_world = world_to_cam.inverse();
         let (_, quat, translation) = cam_to_world.to_scale_rotation_translation();
 
-        let camera = Camera::new(translation, quat, fovx, fovy, center_uv);
-
         log::info!("Loaded COLMAP image at path {path:?}");
 
         let load_img =
             LoadImage::new(vfs.clone(), &path, mask_path, load_args.max_resolution).await?;
 
+        let exif_fov = if load_args.exif_intrinsics {
+            load_img.exif_fov().await
+        } else {
+            None
+        };
+        let (fovx, fovy) = exif_fov.unwrap_or((fovx, fovy));
+        let camera = Camera::new(translation, quat, fovx, fovy, center_uv);
+
         let view = SceneView {
             camera,
             image: load_img,
         };
 
-        if let Some(eval_period) = load_args.eval_split_every {
-            if i % eval_period == 0 {
-                eval_views.push(view);
-            } else {
-                train_views.push(view);
-            }
+        if load_args.is_eval_view(i, &path) {
+            eval_views.push(view);
         } else {
             train_views.push(view);
         }
@@ -153,27 +194,8 @@ async fn load_dataset_inner(
     let device = device.clone();
     let load_args = load_args.clone();
     let init_stream = try_fn_stream(|emitter| async move {
-        let points_path = { vfs.files_ending_in("points3d.txt").next() }
-            .or_else(|| vfs.files_ending_in("points3d.bin").next());
-
-        let Some(points_path) = points_path else {
-            return Ok(());
-        };
-
-        let is_binary = matches!(
-            points_path.extension().and_then(|p| p.to_str()),
-            Some("bin")
-        );
-
-        // Extract COLMAP sfm points.
-        let points_data = {
-            // At this point the VFS has said this file exists so just unwrap.
-            let mut points_file = vfs.reader_at_path(&points_path).await.expect("unreachable");
-            colmap_reader::read_points3d(&mut points_file, is_binary).await
-        };
-
         // Ignore empty points data.
-        if let Ok(points_data) = points_data {
+        if let Some(points_data) = points_data {
             if !points_data.is_empty() {
                 log::info!("Starting from colmap points {}", points_data.len());
 
@@ -196,8 +218,35 @@ async fn load_dataset_inner(
                     })
                     .collect();
 
-                let init_splat =
-                    Splats::from_raw(&positions, None, None, Some(&colors), None, &device);
+                let mut log_scales = nearest_neighbour_log_scales(&positions, &device).await;
+                let init_splat = match load_args.point_init {
+                    PointInit::Neighbors => Splats::from_raw(
+                        &positions,
+                        None,
+                        Some(&log_scales),
+                        Some(&colors),
+                        None,
+                        &device,
+                    ),
+                    PointInit::TrackWeighted => {
+                        let (log_scale_offsets, raw_opacities): (Vec<f32>, Vec<f32>) = points_data
+                            .values()
+                            .step_by(step)
+                            .map(|p| track_weights(p.image_ids.len(), p.error))
+                            .unzip();
+                        for (scale, offset) in log_scales.iter_mut().zip(log_scale_offsets) {
+                            *scale += offset;
+                        }
+                        Splats::from_raw(
+                            &positions,
+                            None,
+                            Some(&log_scales),
+                            Some(&colors),
+                            Some(&raw_opacities),
+                            &device,
+                        )
+                    }
+                };
                 emitter
                     .emit(SplatMessage {
                         meta: crate::splat_import::ParseMetadata {
@@ -205,6 +254,7 @@ async fn load_dataset_inner(
                             total_splats: init_splat.num_splats(),
                             frame_count: 1,
                             current_frame: 0,
+                            labels: None,
                         },
                         splats: init_splat,
                     })
@@ -215,8 +265,26 @@ async fn load_dataset_inner(
         Ok(())
     });
 
-    Ok((
-        Box::pin(init_stream),
-        Dataset::from_views(train_views, eval_views),
-    ))
+    let dataset = Dataset {
+        residuals,
+        ..Dataset::from_views(train_views, eval_views)
+    };
+    Ok((Box::pin(init_stream), dataset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confident_points_are_smaller_and_opaque() {
+        let (trusted_scale, trusted_opacity) = track_weights(TRUSTED_TRACK_LENGTH, 0.0);
+        let (unsure_scale, unsure_opacity) = track_weights(2, 0.0);
+        let (blurry_scale, blurry_opacity) = track_weights(TRUSTED_TRACK_LENGTH, 3.0);
+
+        assert!((trusted_scale - 0.5f32.ln()).abs() < 1e-6);
+        assert!(unsure_scale.abs() < 1e-6);
+        assert!(trusted_scale < blurry_scale && blurry_scale < unsure_scale);
+        assert!(trusted_opacity > blurry_opacity && blurry_opacity > unsure_opacity);
+    }
 }
@@ -1,17 +1,27 @@
 use crate::{
     Dataset,
     config::LoadDataseConfig,
-    splat_import::{SplatImportError, SplatMessage, load_splat_from_ply},
+    point_cloud::{PointCloud, PointCloudError, read_point_cloud},
+    redact::{RedactError, Redactor, load_detector},
+    scene_overrides::read_overrides,
+    scene_transform::SceneTransform,
+    sky_mask::{SkyMaskError, SkyMasker, load_segmenter},
+    splat_import::{ParseMetadata, SplatImportError, SplatMessage, load_splat_from_ply},
 };
 use brush_vfs::{BrushVfs, DynStream};
 use burn::backend::wgpu::WgpuDevice;
+use glam::Vec3;
 use path_clean::PathClean;
 use std::{
     path::{Path, PathBuf},
     pin::Pin,
     sync::Arc,
 };
+use tokio::io::AsyncReadExt;
+use tokio_stream::StreamExt;
 
+pub mod ar_poses;
+pub mod blender;
 pub mod colmap;
 pub mod nerfstudio;
 
@@ -39,8 +49,22 @@ pub enum DatasetError {
     #[error("Failed to load initial point cloud.")]
     InitialPointCloudError(#[from] SplatImportError),
 
-    #[error("Format not recognized: Only colmap and nerfstudio json are supported.")]
+    #[error(
+        "Format not recognized: Only colmap, nerfstudio json, AR capture poses and Blender synthetic scenes are supported."
+    )]
     FormatNotSupported,
+
+    #[error("Failed to load detector for blurring images.")]
+    RedactError(#[from] RedactError),
+
+    #[error("Failed to load segmentation model for masking the sky.")]
+    SkyMaskError(#[from] SkyMaskError),
+
+    #[error("Failed to read scan to start from.")]
+    PointCloudError(#[from] PointCloudError),
+
+    #[error("The scan to start from has no points.")]
+    EmptyScan,
 }
 
 pub async fn load_dataset(
@@ -48,9 +72,21 @@ pub async fn load_dataset(
     load_args: &LoadDataseConfig,
     device: &WgpuDevice,
 ) -> Result<(DataStream<SplatMessage>, Dataset), DatasetError> {
-    let nerfstudio_fmt = nerfstudio::read_dataset(vfs.clone(), load_args, device).await;
+    let load_args = &match read_overrides(&vfs).await? {
+        Some(overrides) => {
+            log::info!("Loading dataset with overrides {overrides:?}");
+            overrides.apply(load_args)
+        }
+        None => load_args.clone(),
+    };
 
-    let format = if let Some(fmt) = nerfstudio_fmt {
+    let blender_fmt = blender::read_dataset(vfs.clone(), load_args).await;
+
+    let mut format = if let Some(fmt) = blender_fmt {
+        fmt?
+    } else if let Some(fmt) = ar_poses::read_dataset(vfs.clone(), load_args).await {
+        fmt?
+    } else if let Some(fmt) = nerfstudio::read_dataset(vfs.clone(), load_args, device).await {
         fmt?
     } else {
         let Some(stream) = colmap::load_dataset(vfs.clone(), load_args, device).await else {
@@ -59,10 +95,113 @@ pub async fn load_dataset(
         stream?
     };
 
-    // If there's an initial ply file, override the init stream with that.
+    if let Some(window) = load_args.smooth_poses {
+        format.1 = format.1.with_smoothed_poses(window);
+    }
+
+    if load_args.hdr {
+        format.1 = format.1.with_hdr(load_args.tonemap);
+    }
+
+    if let Some(factor) = load_args.downscale {
+        format.1 = format.1.with_downscale(factor);
+    }
+
+    if let Some(detector) = &load_args.blur_detector {
+        let detector = load_detector(
+            Path::new(detector),
+            load_args.blur_input_size,
+            load_args.blur_threshold,
+        )?;
+        format.1 = format.1.with_redactor(Arc::new(Redactor::new(detector)));
+    }
+
+    if let Some(segmenter) = &load_args.sky_segmenter {
+        let path = Path::new(segmenter);
+        let segmenter = load_segmenter(path, load_args.sky_input_size)?;
+        // Keep masks of different models apart.
+        let model = path.file_stem().unwrap_or_default();
+        let cache_dir = vfs.base_path().map(|dir| dir.join("sky_masks").join(model));
+        let masker = SkyMasker::new(segmenter, load_args.sky_threshold, cache_dir);
+        format.1 = format.1.with_sky_masker(Arc::new(masker));
+    }
+
+    format.1 = format
+        .1
+        .with_feature_maps(|path| find_feature_path(&vfs, path));
+    let num_features = format
+        .1
+        .train
+        .views
+        .iter()
+        .filter(|v| v.image.feature_path.is_some())
+        .count();
+    if num_features > 0 {
+        log::info!(
+            "Found feature maps for {num_features} of {} training views",
+            format.1.train.views.len()
+        );
+    }
+
+    format.1 = format.1.with_depth_maps(
+        |path| find_depth_path(&vfs, path),
+        load_args.depth_unit_scale,
+    );
+    let num_depths = format
+        .1
+        .train
+        .views
+        .iter()
+        .filter(|v| v.image.depth_path.is_some())
+        .count();
+    if num_depths > 0 {
+        log::info!(
+            "Found depth maps for {num_depths} of {} training views",
+            format.1.train.views.len()
+        );
+    }
+
+    format.1 = format.1.with_normalized_times();
+    if format.1.train.views.iter().any(|v| v.image.time.is_some()) {
+        log::info!("Dataset has timestamped views, it can be trained as a dynamic scene");
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    if let Some(dir) = &load_args.image_cache {
+        let cache = crate::image_cache::DiskImageCache::new(dir);
+        format.1 = format.1.with_disk_cache(Arc::new(cache));
+    }
+
+    // If there's a scan to start from, or an initial ply file, override the init stream with that.
     let path: Vec<_> = vfs.files_with_extension("ply").collect();
+    let scans: Vec<_> = ["las", "e57"]
+        .iter()
+        .flat_map(|ext| vfs.files_with_extension(ext))
+        .collect();
+    let scan_path = match &load_args.init_points {
+        Some(path) => Some(PathBuf::from(path)),
+        None if scans.len() == 1 => scans.first().cloned(),
+        None => None,
+    };
 
-    let init_stream = if path.len() == 1 {
+    let init_stream: DataStream<SplatMessage> = if let Some(scan_path) = scan_path {
+        log::info!("Using scan {scan_path:?} as initial point cloud.");
+        let cloud = read_scan(&vfs, &scan_path, load_args, device).await?;
+        let splats = cloud
+            .to_splats(load_args.init_surface_aligned, device)
+            .await;
+        let message = SplatMessage {
+            meta: ParseMetadata {
+                up_axis: None,
+                total_splats: splats.num_splats(),
+                frame_count: 1,
+                current_frame: 0,
+                labels: None,
+            },
+            splats,
+        };
+        Box::pin(tokio_stream::once(Ok(message)))
+    } else if path.len() == 1 {
         let main_path = path.first().expect("unreachable");
         log::info!("Using ply {main_path:?} as initial point cloud.");
 
@@ -79,7 +218,116 @@ pub async fn load_dataset(
         format.0
     };
 
-    Ok((init_stream, format.1))
+    if load_args.normalize_scene {
+        normalize_scene(init_stream, format.1).await
+    } else {
+        Ok((init_stream, format.1))
+    }
+}
+
+// Read a scan from the dataset, or from disk, and subsample it as configured.
+async fn read_scan(
+    vfs: &BrushVfs,
+    path: &Path,
+    load_args: &LoadDataseConfig,
+    device: &WgpuDevice,
+) -> Result<PointCloud, DatasetError> {
+    let mut data = vec![];
+    if vfs.contains(path) {
+        vfs.reader_at_path(path)
+            .await
+            .map_err(FormatError::Io)?
+            .read_to_end(&mut data)
+            .await
+            .map_err(FormatError::Io)?;
+    } else {
+        #[cfg(not(target_family = "wasm"))]
+        {
+            data = tokio::fs::read(path).await.map_err(FormatError::Io)?;
+        }
+        #[cfg(target_family = "wasm")]
+        return Err(FormatError::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Scan {} not found in the dataset", path.display()),
+        ))
+        .into());
+    }
+
+    let mut cloud = read_point_cloud(path, data, device).await?;
+    log::info!("Read {} points from scan", cloud.positions.len());
+    if let Some(size) = load_args.init_voxel_size {
+        cloud = cloud.voxel_subsample(size);
+    }
+    if let Some(step) = load_args.subsample_points {
+        cloud = cloud.every_nth(step as usize);
+    }
+    if cloud.positions.is_empty() {
+        return Err(DatasetError::EmptyScan);
+    }
+    Ok(cloud)
+}
+
+async fn normalize_scene(
+    mut init_stream: DataStream<SplatMessage>,
+    dataset: Dataset,
+) -> Result<(DataStream<SplatMessage>, Dataset), DatasetError> {
+    // The point cloud is only complete at the last message, so this has to wait for the whole
+    // stream before anything can be shown.
+    let mut messages = vec![];
+    while let Some(message) = init_stream.next().await {
+        messages.push(message?);
+    }
+
+    let transform = if let Some(last) = messages.last() {
+        let means: Vec<f32> = last
+            .splats
+            .means
+            .val()
+            .into_data_async()
+            .await
+            .to_vec()
+            .expect("Unreachable");
+        let points: Vec<_> = means.chunks_exact(3).map(Vec3::from_slice).collect();
+        SceneTransform::from_points(&points)
+    } else {
+        SceneTransform::from_cameras(&dataset.train)
+    };
+    log::info!("Normalizing scene with {transform:?}");
+
+    let stream = tokio_stream::iter(messages).map(move |mut message| {
+        message.splats = transform.transform_splats(message.splats);
+        message.meta.up_axis = message.meta.up_axis.map(|up| transform.rotation * up);
+        Ok(message)
+    });
+    Ok((Box::pin(stream), dataset.with_transform(transform)))
+}
+
+// Feature maps are .npy files with the name of the image, in a `features` directory next to the
+// images, like masks.
+fn find_feature_path(vfs: &BrushVfs, path: &Path) -> Option<PathBuf> {
+    let file_stem = path.file_stem()?.to_str()?;
+    let features_dir = path.parent()?.clean().parent()?.join("features").clean();
+    vfs.files_with_stem(file_stem).find(|candidate| {
+        candidate.parent() == Some(features_dir.as_path())
+            && candidate.extension().is_some_and(|ext| ext == "npy")
+    })
+}
+
+// Depth maps are 16-bit PNGs or EXRs with the name of the image, in a `depths` directory next to
+// the images. Their confidence maps are in a `confidence` directory.
+fn find_depth_path(vfs: &BrushVfs, path: &Path) -> Option<(PathBuf, Option<PathBuf>)> {
+    let file_stem = path.file_stem()?.to_str()?;
+    let images_dir = path.parent()?.clean().parent()?.to_path_buf();
+    let find_in = |dir: &str| {
+        let dir = images_dir.join(dir).clean();
+        vfs.files_with_stem(file_stem).find(|candidate| {
+            candidate.parent() == Some(dir.as_path())
+                && candidate.extension().is_some_and(|ext| {
+                    ext.eq_ignore_ascii_case("png") || ext.eq_ignore_ascii_case("exr")
+                })
+        })
+    };
+    Some((find_in("depths")?, find_in("confidence")))
 }
 
 fn find_mask_path(vfs: &BrushVfs, path: &Path) -> Option<PathBuf> {
@@ -5,13 +5,15 @@ use crate::{
     Dataset,
     config::LoadDataseConfig,
     scene::{LoadImage, SceneView},
+    scene_transform::CoordinateConvention,
     splat_import::{SplatMessage, load_splat_from_ply},
 };
 use async_fn_stream::try_fn_stream;
 use brush_render::camera::fov_to_focal;
-use brush_render::camera::{Camera, focal_to_fov};
+use brush_render::camera::{Camera, CameraModel, focal_to_fov};
 use brush_vfs::BrushVfs;
 use burn::backend::wgpu::WgpuDevice;
+use std::f64::consts::{PI, TAU};
 use std::path::Path;
 use std::sync::Arc;
 use tokio::io::AsyncReadExt;
@@ -19,18 +21,18 @@ use tokio_stream::StreamExt;
 
 #[derive(serde::Deserialize, Clone)]
 #[allow(unused)] // not reading camera distortions yet.
-struct JsonScene {
+pub(super) struct JsonScene {
     // Horizontal FOV.
-    camera_angle_x: Option<f64>,
+    pub(super) camera_angle_x: Option<f64>,
     // Vertical FOV.
     camera_angle_y: Option<f64>,
 
     /// Focal length x
-    fl_x: Option<f64>,
+    pub(super) fl_x: Option<f64>,
     /// Focal length y
     fl_y: Option<f64>,
 
-    // Not really used atm.
+    // Only used to tell panoramas (EQUIRECTANGULAR) apart, distortions aren't read yet.
     camera_model: Option<String>,
     // Nerfstudio doesn't mention this in their format? But fine to include really.
     ply_file_path: Option<String>,
@@ -98,14 +100,21 @@ struct FrameData {
 
     transform_matrix: Vec<Vec<f32>>,
     file_path: String,
+    /// When the frame was taken, for videos of dynamic scenes (eg. D-NeRF datasets).
+    time: Option<f32>,
 }
 
-async fn read_transforms_file(
+pub(super) async fn read_transforms_file(
     scene: JsonScene,
     transforms_path: &Path,
     vfs: Arc<BrushVfs>,
     load_args: &LoadDataseConfig,
 ) -> Result<Vec<SceneView>, FormatError> {
+    let is_panorama = scene
+        .camera_model
+        .as_deref()
+        .is_some_and(|m| m.eq_ignore_ascii_case("EQUIRECTANGULAR"));
+
     let mut results = vec![];
     for frame in scene
         .frames
@@ -115,20 +124,25 @@ async fn read_transforms_file(
     {
         // NeRF 'transform_matrix' is a camera-to-world transform
         let transform_matrix: Vec<f32> = frame.transform_matrix.iter().flatten().copied().collect();
-        let mut transform = glam::Mat4::from_cols_slice(&transform_matrix).transpose();
-        // Swap basis to match camera format and reconstrunstion ply (if included).
-        transform.y_axis *= -1.0;
-        transform.z_axis *= -1.0;
+        let transform = glam::Mat4::from_cols_slice(&transform_matrix).transpose();
         let (_, rotation, translation) = transform.to_scale_rotation_translation();
+        // Swap basis to match camera format and reconstrunstion ply (if included).
+        let rotation = CoordinateConvention::OpenGl.camera_to_brush(rotation);
 
         let mut path = transforms_path
             .parent()
             .expect("Transforms path must be a filename")
             .join(&frame.file_path);
 
-        // Assume png's by default if no extension is specified.
+        // Assume png's by default if no extension is specified, but allow exr frames (as
+        // some synthetic datasets use).
         if path.extension().is_none() {
-            path = path.with_extension("png");
+            let exr_path = path.with_extension("exr");
+            path = if vfs.contains(&exr_path) {
+                exr_path
+            } else {
+                path.with_extension("png")
+            };
         }
         let mask_path = find_mask_path(&vfs, &path);
 
@@ -142,6 +156,18 @@ async fn read_transforms_file(
             }
             Err(e) => Err(e)?,
         };
+        let image = match frame.time {
+            Some(time) => image.with_time(time),
+            None => image,
+        };
+
+        if is_panorama {
+            // Panoramas see all around, they have no focal length or principal point.
+            let camera = Camera::new(translation, rotation, TAU, PI, glam::vec2(0.5, 0.5))
+                .with_model(CameraModel::Equirectangular);
+            results.push(SceneView { image, camera });
+            continue;
+        }
 
         let w = frame.w.or(scene.w).unwrap_or(image.width() as f64) as u32;
         let h = frame.h.or(scene.h).unwrap_or(image.height() as f64) as u32;
@@ -158,19 +184,27 @@ async fn read_transforms_file(
             .or(scene.camera_angle_y)
             .or(scene.fl_y.map(|fy| focal_to_fov(fy, h)));
 
-        let (fovx, fovy) = match (fovx, fovy) {
-            (None, None) => Err(FormatError::InvalidCamera(
-                "Must have some kind of focal length",
+        // EXIF metadata fills in missing intrinsics, or replaces rough ones if asked to.
+        let exif_fov = if load_args.exif_intrinsics || (fovx.is_none() && fovy.is_none()) {
+            image.exif_fov().await
+        } else {
+            None
+        };
+
+        let (fovx, fovy) = match (exif_fov, fovx, fovy) {
+            (Some(fov), _, _) => fov,
+            (None, None, None) => Err(FormatError::InvalidCamera(
+                "Must have some kind of focal length, in the dataset or the EXIF metadata of the images",
             ))?,
-            (None, Some(fovy)) => {
+            (None, None, Some(fovy)) => {
                 let fovx = focal_to_fov(fov_to_focal(fovy, h), w);
                 (fovx, fovy)
             }
-            (Some(fovx), None) => {
+            (None, Some(fovx), None) => {
                 let fovy = focal_to_fov(fov_to_focal(fovx, w), h);
                 (fovx, fovy)
             }
-            (Some(fovx), Some(fovy)) => (fovx, fovy),
+            (None, Some(fovx), Some(fovy)) => (fovx, fovy),
         };
 
         let cx = frame.cx.or(scene.cx).unwrap_or(w as f64 / 2.0);
@@ -255,13 +289,9 @@ async fn read_dataset_inner(
     let mut train_views = vec![];
     let mut eval_views = vec![];
     for (i, view) in train_handles.into_iter().enumerate() {
-        if let Some(eval_period) = load_args.eval_split_every {
-            // Include extra eval images only when the dataset doesn't have them.
-            if i % eval_period == 0 && val_views.is_none() {
-                eval_views.push(view);
-            } else {
-                train_views.push(view);
-            }
+        // Include extra eval images only when the dataset doesn't have them.
+        if val_views.is_none() && load_args.is_eval_view(i, &view.image.path) {
+            eval_views.push(view);
         } else {
             train_views.push(view);
         }
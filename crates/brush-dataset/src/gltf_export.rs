@@ -0,0 +1,214 @@
+// Export of splats to binary glTF files with the KHR_gaussian_splatting extension, for engines like
+// three.js and Babylon.js that load splats as part of their glTF pipeline.
+use brush_render::{gaussian_splats::Splats, sh::sh_to_rgb};
+use burn::prelude::Backend;
+use serde_json::{Value, json};
+
+use crate::{parsed_gaussian::ParsedGaussian, splat_export::export_data, watermark::Watermark};
+
+const EXTENSION: &str = "KHR_gaussian_splatting";
+
+const GLB_MAGIC: u32 = 0x4654_6c67;
+const CHUNK_JSON: u32 = 0x4e4f_534a;
+const CHUNK_BIN: u32 = 0x004e_4942;
+const COMPONENT_FLOAT: u32 = 5126;
+const MODE_POINTS: u32 = 0;
+
+// An attribute of the splats, with a number of floats for each splat.
+struct Attribute {
+    name: String,
+    kind: &'static str,
+    values: Vec<f32>,
+}
+
+impl Attribute {
+    fn new(name: &str, kind: &'static str, count: usize) -> Self {
+        Self {
+            name: name.to_owned(),
+            kind,
+            values: Vec::with_capacity(count * 4),
+        }
+    }
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+// Lay out the splats as a glb file: a JSON chunk describing a single point primitive, and a binary
+// chunk with an attribute after the other.
+fn write_glb(splats: &[ParsedGaussian<false>], point_fallback: bool) -> Vec<u8> {
+    let count = splats.len();
+    let rest_per_channel = splats.first().map_or(0, |s| s.sh_coeffs_rest.len() / 3);
+
+    let mut position = Attribute::new("POSITION", "VEC3", count);
+    let mut color = Attribute::new("COLOR_0", "VEC4", count);
+    let mut rotation = Attribute::new(&format!("{EXTENSION}:ROTATION"), "VEC4", count);
+    let mut scale = Attribute::new(&format!("{EXTENSION}:SCALE"), "VEC3", count);
+    let mut opacity = Attribute::new(&format!("{EXTENSION}:OPACITY"), "SCALAR", count);
+    let mut sh_dc = Attribute::new(&format!("{EXTENSION}:SH_DEGREE_0_COEF_0"), "VEC3", count);
+    let mut sh_rest: Vec<_> = (0..rest_per_channel)
+        .map(|i| {
+            // Coefficients of each degree follow each other, after the one of degree 0.
+            let degree = ((i + 1) as f32).sqrt().floor() as usize;
+            let coef = i + 1 - degree * degree;
+            let name = format!("{EXTENSION}:SH_DEGREE_{degree}_COEF_{coef}");
+            Attribute::new(&name, "VEC3", count)
+        })
+        .collect();
+
+    let mut min = glam::Vec3::splat(f32::INFINITY);
+    let mut max = glam::Vec3::splat(f32::NEG_INFINITY);
+    for splat in splats {
+        min = min.min(splat.mean);
+        max = max.max(splat.mean);
+        position.values.extend(splat.mean.to_array());
+        let alpha = sigmoid(splat.opacity);
+        let rgb = sh_to_rgb(splat.sh_dc).clamp(glam::Vec3::ZERO, glam::Vec3::ONE);
+        color.values.extend(rgb.extend(alpha).to_array());
+        rotation.values.extend(splat.rotation.to_array());
+        scale.values.extend(splat.log_scale.exp().to_array());
+        opacity.values.push(alpha);
+        sh_dc.values.extend(splat.sh_dc.to_array());
+        // Rest coefficients are stored per channel.
+        for (i, attribute) in sh_rest.iter_mut().enumerate() {
+            attribute
+                .values
+                .extend((0..3).map(|channel| splat.sh_coeffs_rest[channel * rest_per_channel + i]));
+        }
+    }
+
+    let mut attributes = vec![position, color, rotation, scale, opacity, sh_dc];
+    attributes.append(&mut sh_rest);
+
+    let mut bin = vec![];
+    let mut buffer_views = vec![];
+    let mut accessors = vec![];
+    let mut primitive_attributes = serde_json::Map::new();
+    for (index, attribute) in attributes.iter().enumerate() {
+        let offset = bin.len();
+        for value in &attribute.values {
+            bin.extend_from_slice(&value.to_le_bytes());
+        }
+        buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": bin.len() - offset,
+        }));
+        let mut accessor = json!({
+            "bufferView": index,
+            "componentType": COMPONENT_FLOAT,
+            "count": count,
+            "type": attribute.kind,
+        });
+        // Positions need bounds.
+        if index == 0 {
+            accessor["min"] = json!(min.to_array());
+            accessor["max"] = json!(max.to_array());
+        }
+        accessors.push(accessor);
+        primitive_attributes.insert(attribute.name.clone(), json!(index));
+    }
+
+    let mut gltf = json!({
+        "asset": { "version": "2.0", "generator": "Brush" },
+        "extensionsUsed": [EXTENSION],
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        // Splats are exported with y pointing down, glTF has y pointing up.
+        "nodes": [{ "mesh": 0, "rotation": [1.0, 0.0, 0.0, 0.0] }],
+        "meshes": [{
+            "primitives": [{
+                "mode": MODE_POINTS,
+                "attributes": Value::Object(primitive_attributes),
+                "extensions": { EXTENSION: {} },
+            }],
+        }],
+        "buffers": [{ "byteLength": bin.len() }],
+        "bufferViews": buffer_views,
+        "accessors": accessors,
+    });
+    // Without the extension, the splats can still be loaded as colored points.
+    if !point_fallback {
+        gltf["extensionsRequired"] = json!([EXTENSION]);
+    }
+
+    let mut json = serde_json::to_vec(&gltf).expect("Valid JSON");
+    // Chunks are aligned to 4 bytes, padded with spaces for JSON and zeros for binary data.
+    json.resize(json.len().next_multiple_of(4), b' ');
+    bin.resize(bin.len().next_multiple_of(4), 0);
+
+    let total = 12 + 8 + json.len() + 8 + bin.len();
+    let mut glb = Vec::with_capacity(total);
+    for word in [GLB_MAGIC, 2, total as u32, json.len() as u32, CHUNK_JSON] {
+        glb.extend_from_slice(&word.to_le_bytes());
+    }
+    glb.extend_from_slice(&json);
+    for word in [bin.len() as u32, CHUNK_BIN] {
+        glb.extend_from_slice(&word.to_le_bytes());
+    }
+    glb.extend_from_slice(&bin);
+    glb
+}
+
+/// Export splats to a binary glTF file, as a point primitive with the `KHR_gaussian_splatting`
+/// extension. Colors are also written as `COLOR_0`, and with `point_fallback` the extension isn't
+/// required, so loaders without support for splats show them as colored points.
+///
+/// Like ply exports, the splats can have a payload hidden in their colors.
+pub async fn splat_to_glb<B: Backend>(
+    splats: Splats<B>,
+    watermark: Option<Watermark>,
+    point_fallback: bool,
+) -> std::io::Result<Vec<u8>> {
+    let data = export_data(splats, watermark, None).await;
+    if data.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "No splats to export",
+        ));
+    }
+    Ok(write_glb(&data, point_fallback))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::{Quat, Vec3};
+
+    #[test]
+    fn writes_glb() {
+        let splat = |x: f32| ParsedGaussian::<false> {
+            mean: Vec3::new(x, 1.0, 2.0),
+            log_scale: Vec3::ZERO,
+            opacity: 0.0,
+            rotation: Quat::IDENTITY,
+            sh_dc: Vec3::ZERO,
+            sh_coeffs_rest: (0..9).map(|i| i as f32).collect(),
+            label: 0,
+        };
+        let glb = write_glb(&[splat(-1.0), splat(3.0)], true);
+
+        let word = |i: usize| u32::from_le_bytes(glb[i * 4..i * 4 + 4].try_into().expect("Word"));
+        assert_eq!(word(0), GLB_MAGIC);
+        assert_eq!(word(2) as usize, glb.len());
+        let json_len = word(3) as usize;
+        let gltf: Value = serde_json::from_slice(&glb[20..20 + json_len]).expect("Valid JSON");
+
+        let primitive = &gltf["meshes"][0]["primitives"][0];
+        let attributes = primitive["attributes"].as_object().expect("Attributes");
+        // Position, color, rotation, scale, opacity and 4 SH coefficients of degree 0 and 1.
+        assert_eq!(attributes.len(), 9);
+        assert!(attributes.contains_key("KHR_gaussian_splatting:SH_DEGREE_1_COEF_2"));
+        assert_eq!(gltf["accessors"][0]["min"], json!([-1.0, 1.0, 2.0]));
+        assert!(gltf.get("extensionsRequired").is_none());
+
+        // Rest coefficients are read per channel, so the last has the last of each channel.
+        let last = &gltf["bufferViews"][8];
+        let offset = last["byteOffset"].as_u64().expect("Offset") as usize;
+        let bin = &glb[20 + json_len + 8..];
+        let value =
+            |i: usize| f32::from_le_bytes(bin[offset + i * 4..][..4].try_into().expect("Float"));
+        assert_eq!([value(0), value(1), value(2)], [2.0, 5.0, 8.0]);
+    }
+}
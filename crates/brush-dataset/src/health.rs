@@ -0,0 +1,198 @@
+use crate::Dataset;
+use glam::{Mat3, Vec3};
+
+// Below this many views, reconstructions usually have holes or floaters.
+const MIN_VIEWS: usize = 20;
+// Images smaller than this (in their largest dimension) give blurry results.
+const MIN_RESOLUTION: u32 = 800;
+// Cameras should move by at least this fraction of their distance to the subject.
+const MIN_PARALLAX: f32 = 0.05;
+// Good reconstructions explain the points they found to within a pixel or so.
+const MAX_RESIDUAL: f32 = 2.0;
+
+/// A likely problem with a dataset, found before training.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HealthIssue {
+    FewViews(usize),
+    LowResolution {
+        width: u32,
+        height: u32,
+    },
+    /// Cameras barely move compared to their distance to the subject, eg. when turning on the spot.
+    LittleParallax(f32),
+    /// The points of the reconstruction land far from where they were seen, in pixels.
+    PoorCalibration {
+        camera_id: i32,
+        mean_residual: f32,
+    },
+    /// The camera has lens distortion, which Brush doesn't model.
+    Distorted {
+        camera_id: i32,
+        model: &'static str,
+        pinhole_residual: f32,
+    },
+}
+
+impl HealthIssue {
+    /// A short explanation of the issue, and how to fix it.
+    pub fn advice(&self) -> String {
+        match self {
+            Self::FewViews(count) => format!(
+                "Only {count} photos. Take at least {MIN_VIEWS}, overlapping, from all around the subject."
+            ),
+            Self::LowResolution { width, height } => format!(
+                "Photos are small ({width}x{height}). Use photos of at least {MIN_RESOLUTION} pixels wide."
+            ),
+            Self::LittleParallax(parallax) => format!(
+                "The camera barely moves ({:.0}% of the distance to the subject). Walk around the subject instead of turning on the spot.",
+                parallax * 100.0
+            ),
+            Self::PoorCalibration {
+                camera_id,
+                mean_residual,
+            } => format!(
+                "Camera {camera_id} is poorly calibrated: points are off by {mean_residual:.1} pixels on average. Try reconstructing with more overlapping photos, or with a different camera model."
+            ),
+            Self::Distorted {
+                camera_id,
+                model,
+                pinhole_residual,
+            } => format!(
+                "Camera {camera_id} ({model}) has lens distortion, which puts points {pinhole_residual:.1} pixels off as Brush ignores it. Undistort the images first, eg. with COLMAP's image_undistorter."
+            ),
+        }
+    }
+}
+
+/// Rough measure of how much cameras move, relative to their distance to what they look at.
+///
+/// Returns None if the cameras don't look at a common point in front of them, eg. for a camera
+/// moving forward or looking outward.
+fn parallax(positions: &[Vec3], forwards: &[Vec3]) -> Option<f32> {
+    if positions.len() < 2 {
+        return None;
+    }
+
+    // Least squares point closest to all view rays, sum (I - d d^T) (x - o) = 0.
+    let mut lhs = Mat3::ZERO;
+    let mut rhs = Vec3::ZERO;
+    for (&origin, &dir) in positions.iter().zip(forwards) {
+        let dir = dir.normalize();
+        let proj = Mat3::IDENTITY - Mat3::from_cols(dir * dir.x, dir * dir.y, dir * dir.z);
+        lhs += proj;
+        rhs += proj * origin;
+    }
+    // Nearly parallel rays don't meet anywhere.
+    let count = positions.len() as f32;
+    if lhs.determinant().abs() < 1e-6 * count.powi(3) {
+        return None;
+    }
+    let focus = lhs.inverse() * rhs;
+    let in_front = positions
+        .iter()
+        .zip(forwards)
+        .map(|(&origin, dir)| (focus - origin).dot(dir.normalize()))
+        .sum::<f32>();
+    if in_front <= 0.0 {
+        return None;
+    }
+
+    let mean = positions.iter().sum::<Vec3>() / count;
+    let spread = (positions
+        .iter()
+        .map(|p| p.distance_squared(mean))
+        .sum::<f32>()
+        / count)
+        .sqrt();
+    let distance = positions.iter().map(|p| p.distance(focus)).sum::<f32>() / count;
+    (distance > 0.0).then(|| spread / distance)
+}
+
+/// Check a dataset for common capture problems.
+pub fn check_health(dataset: &Dataset) -> Vec<HealthIssue> {
+    let views: Vec<_> = dataset
+        .train
+        .views
+        .iter()
+        .chain(dataset.eval.iter().flat_map(|e| e.views.as_slice()))
+        .collect();
+    let mut issues = vec![];
+
+    if views.len() < MIN_VIEWS {
+        issues.push(HealthIssue::FewViews(views.len()));
+    }
+
+    let mut sizes: Vec<_> = views
+        .iter()
+        .map(|v| (v.image.width(), v.image.height()))
+        .collect();
+    sizes.sort_by_key(|&(w, h)| w.max(h));
+    if let Some(&(width, height)) = sizes.get(sizes.len() / 2) {
+        if width.max(height) < MIN_RESOLUTION {
+            issues.push(HealthIssue::LowResolution { width, height });
+        }
+    }
+
+    let positions: Vec<_> = views.iter().map(|v| v.camera.position).collect();
+    let forwards: Vec<_> = views.iter().map(|v| v.camera.rotation * Vec3::Z).collect();
+    if let Some(parallax) = parallax(&positions, &forwards) {
+        if parallax < MIN_PARALLAX {
+            issues.push(HealthIssue::LittleParallax(parallax));
+        }
+    }
+
+    for camera in &dataset.residuals {
+        let fitted = camera.residuals.unwrap_or(camera.pinhole);
+        if fitted.mean > MAX_RESIDUAL {
+            issues.push(HealthIssue::PoorCalibration {
+                camera_id: camera.camera_id,
+                mean_residual: fitted.mean,
+            });
+        } else if camera.pinhole.mean > MAX_RESIDUAL {
+            issues.push(HealthIssue::Distorted {
+                camera_id: camera.camera_id,
+                model: camera.model,
+                pinhole_residual: camera.pinhole.mean,
+            });
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(radius: f32, count: usize) -> (Vec<Vec3>, Vec<Vec3>) {
+        (0..count)
+            .map(|i| {
+                let angle = i as f32 / count as f32 * std::f32::consts::TAU;
+                let pos = Vec3::new(angle.cos(), 0.0, angle.sin()) * radius;
+                (pos, -pos)
+            })
+            .unzip()
+    }
+
+    #[test]
+    fn orbit_has_parallax() {
+        let (positions, forwards) = ring(2.0, 16);
+        let value = parallax(&positions, &forwards).expect("Cameras look at the center");
+        assert!((value - 1.0).abs() < 1e-3, "{value}");
+    }
+
+    #[test]
+    fn standing_still_has_little_parallax() {
+        // Cameras close together, all looking at a subject far away.
+        let (positions, _) = ring(0.1, 16);
+        let target = Vec3::new(0.0, 0.0, 10.0);
+        let forwards: Vec<_> = positions.iter().map(|&p| target - p).collect();
+        let value = parallax(&positions, &forwards).expect("Cameras look at the target");
+        assert!((value - 0.01).abs() < 1e-3, "{value}");
+
+        // Cameras looking outward, or all the same way, don't look at a common point.
+        assert_eq!(parallax(&positions, &positions), None);
+        let same_way = vec![Vec3::Z; positions.len()];
+        assert_eq!(parallax(&positions, &same_way), None);
+    }
+}
@@ -0,0 +1,129 @@
+// Resized copies of dataset images on disk. Decoding and resizing large photos is the slowest part
+// of loading views, and has the same result every run.
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    io::Cursor,
+    path::PathBuf,
+};
+
+use image::{
+    DynamicImage, ImageReader,
+    codecs::png::{CompressionType, FilterType, PngEncoder},
+};
+
+// Bump when the way images are processed changes, so old copies are ignored.
+const CACHE_VERSION: u32 = 1;
+
+/// Nr. of resolutions that can be kept for each image: the loaded resolution, half of it, and a
+/// quarter. Levels are only stored once they're loaded.
+pub const PYRAMID_LEVELS: u32 = 3;
+
+/// A directory of resized images, keyed by a hash of the files they were made from and the
+/// settings they were loaded with.
+#[derive(Debug, Clone)]
+pub struct DiskImageCache {
+    dir: PathBuf,
+}
+
+impl DiskImageCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Key of an image loaded from `sources` (eg. the image and its mask) with `settings`.
+    ///
+    /// The hash isn't stable across Rust versions, at worst that misses the cache.
+    pub fn key(sources: &[&[u8]], settings: impl Hash) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        CACHE_VERSION.hash(&mut hasher);
+        for source in sources {
+            source.hash(&mut hasher);
+        }
+        settings.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn path(&self, key: u64, level: u32) -> PathBuf {
+        self.dir.join(format!("{key:016x}_{level}.png"))
+    }
+
+    /// The cached image for `key` at a pyramid `level`, if there is one.
+    pub async fn read(&self, key: u64, level: u32) -> Option<DynamicImage> {
+        let data = tokio::fs::read(self.path(key, level)).await.ok()?;
+        ImageReader::new(Cursor::new(data))
+            .with_guessed_format()
+            .ok()?
+            .decode()
+            .ok()
+    }
+
+    /// Store `image` as the pyramid `level` of `key`. Images are encoded on a blocking thread,
+    /// as encoding large images takes a while.
+    ///
+    /// Failing to write only means the image is decoded again next time, so errors are logged
+    /// and otherwise ignored.
+    pub async fn write(&self, key: u64, level: u32, image: DynamicImage) {
+        if let Err(e) = self.try_write(key, level, image).await {
+            log::warn!("Failed to cache image in {:?}: {e}", self.dir);
+        }
+    }
+
+    async fn try_write(&self, key: u64, level: u32, image: DynamicImage) -> std::io::Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let data = tokio::task::spawn_blocking(move || {
+            let mut data = Cursor::new(vec![]);
+            // Favour speed, the cache is written while training.
+            let encoder = PngEncoder::new_with_quality(
+                &mut data,
+                CompressionType::Fast,
+                FilterType::Adaptive,
+            );
+            image
+                .write_with_encoder(encoder)
+                .map_err(std::io::Error::other)?;
+            Ok::<_, std::io::Error>(data.into_inner())
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+
+        // Write next to the final file and move it in place, so readers never see half an image.
+        let path = self.path(key, level);
+        let partial = path.with_extension("png.partial");
+        tokio::fs::write(&partial, data).await?;
+        tokio::fs::rename(&partial, &path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_follows_content_and_settings() {
+        let (image, mask): (&[u8], &[u8]) = (b"image", b"mask");
+        let key = DiskImageCache::key(&[image], 1920);
+        assert_eq!(key, DiskImageCache::key(&[image], 1920));
+        assert_ne!(key, DiskImageCache::key(&[b"other".as_slice()], 1920));
+        assert_ne!(key, DiskImageCache::key(&[image], 1080));
+        assert_ne!(key, DiskImageCache::key(&[image, mask], 1920));
+    }
+
+    #[tokio::test]
+    async fn levels_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("brush_image_cache_{}", std::process::id()));
+        let cache = DiskImageCache::new(dir.clone());
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(16, 8, |x, y| {
+            image::Rgb([x as u8 * 10, y as u8 * 20, 7])
+        }));
+        let half = crate::scene::pyramid_level(&image, 1);
+
+        cache.write(3, 1, half.clone()).await;
+        let read = cache.read(3, 1).await.expect("Cached level");
+        assert_eq!(read.to_rgb8(), half.to_rgb8());
+        // Only the written level is stored.
+        assert!(cache.read(3, 0).await.is_none());
+        assert!(cache.read(4, 1).await.is_none());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,10 +1,34 @@
 #![recursion_limit = "256"]
 
+pub mod chunked_splats;
+pub mod color;
+pub mod composition;
+pub mod compression;
 pub mod config;
+pub mod depth_map;
+pub mod exif;
+pub mod feature_map;
+pub mod gltf_export;
+pub mod health;
+#[cfg(not(target_family = "wasm"))]
+pub mod image_cache;
+pub mod point_cloud;
+pub mod pose_smoothing;
+pub mod redact;
+pub mod reprojection;
 pub mod scene;
+pub mod scene_builder;
 pub mod scene_loader;
+pub mod scene_overrides;
+pub mod scene_transform;
+pub mod sky_mask;
 pub mod splat_export;
+pub mod splat_formats;
 pub mod splat_import;
+pub mod tonemap;
+pub mod usd_export;
+pub mod watermark;
+pub mod white_balance;
 
 mod formats;
 mod parsed_gaussian;
@@ -12,10 +36,21 @@ mod quant;
 
 pub use formats::load_dataset;
 
+use brush_render::camera::Camera;
 use core::f32;
 use glam::{Mat3, Mat4, Vec3};
+#[cfg(not(target_family = "wasm"))]
+use image_cache::DiskImageCache;
+use pose_smoothing::smooth_poses;
+use redact::Redactor;
+use reprojection::CameraResiduals;
 use scene::Scene;
 use scene::SceneView;
+use scene_transform::SceneTransform;
+use sky_mask::SkyMasker;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tonemap::Tonemap;
 
 fn solve_cubic(a: f32, b: f32, c: f32, d: f32) -> (f32, f32, f32) {
     // Convert to depressed cubic t^3 + pt + q = 0
@@ -120,6 +155,15 @@ pub fn compute_sorted_eigenvectors(matrix: Mat3) -> (Vec3, Vec3, Vec3) {
 pub struct Dataset {
     pub train: Scene,
     pub eval: Option<Scene>,
+    /// Transform applied to the data when loading. Use the inverse to map results
+    /// back to the original frame of the dataset.
+    pub transform: SceneTransform,
+    /// How well the cameras fit the points of the reconstruction, if the dataset has one.
+    pub residuals: Vec<CameraResiduals>,
+    /// The train cameras as they were loaded, if their poses were smoothed.
+    pub unsmoothed_train: Option<Scene>,
+    /// Curve mapping the linear colors of HDR datasets to display colors, None for regular images.
+    pub tonemap: Option<Tonemap>,
 }
 
 impl Dataset {
@@ -127,6 +171,10 @@ impl Dataset {
         Self {
             train: Scene::new(vec![]),
             eval: None,
+            transform: SceneTransform::IDENTITY,
+            residuals: vec![],
+            unsmoothed_train: None,
+            tonemap: None,
         }
     }
 
@@ -138,9 +186,179 @@ impl Dataset {
             } else {
                 Some(Scene::new(eval_views))
             },
+            transform: SceneTransform::IDENTITY,
+            residuals: vec![],
+            unsmoothed_train: None,
+            tonemap: None,
         }
     }
 
+    // Rebuild all scenes of the dataset with each of their views changed by `f`.
+    fn map_views(self, mut f: impl FnMut(SceneView) -> SceneView) -> Self {
+        let mut map_scene =
+            |scene: Scene| Scene::new(scene.views.iter().cloned().map(&mut f).collect());
+        Self {
+            train: map_scene(self.train),
+            eval: self.eval.map(&mut map_scene),
+            unsmoothed_train: self.unsmoothed_train.map(&mut map_scene),
+            ..self
+        }
+    }
+
+    /// Apply a transform to all cameras of the dataset.
+    pub fn with_transform(self, transform: SceneTransform) -> Self {
+        let combined = self.transform.then(&transform);
+        Self {
+            transform: combined,
+            ..self.map_views(|view| transform.transform_view(view))
+        }
+    }
+
+    /// Smooth the camera poses of all views along the trajectory of a video, over `window` frames
+    /// on each side, see [`pose_smoothing::smooth_poses`]. Views are ordered by the file names of
+    /// their images.
+    pub fn with_smoothed_poses(self, window: usize) -> Self {
+        let views: Vec<&SceneView> = self
+            .train
+            .views
+            .iter()
+            .chain(self.eval.iter().flat_map(|e| e.views.iter()))
+            .collect();
+        let mut order: Vec<usize> = (0..views.len()).collect();
+        order.sort_by(|&a, &b| {
+            alphanumeric_sort::compare_path(&views[a].image.path, &views[b].image.path)
+        });
+        let cameras: Vec<Camera> = order.iter().map(|&i| views[i].camera.clone()).collect();
+        let smoothed = smooth_poses(&cameras, window);
+        if !smoothed.outliers.is_empty() {
+            log::info!(
+                "Replaced {} camera poses far off the trajectory",
+                smoothed.outliers.len()
+            );
+        }
+
+        let mut in_view_order = vec![Camera::default(); views.len()];
+        for (&i, camera) in order.iter().zip(smoothed.cameras) {
+            in_view_order[i] = camera;
+        }
+        let mut cameras = in_view_order.into_iter();
+        let unsmoothed_train = Some(self.train.clone());
+        let smoothed = Self {
+            unsmoothed_train: None,
+            ..self
+        };
+        Self {
+            unsmoothed_train,
+            ..smoothed.map_views(|view| SceneView {
+                camera: cameras.next().expect("A camera for each view"),
+                ..view
+            })
+        }
+    }
+
+    /// Load all images of the dataset in linear color, see [`scene::LoadImage::with_hdr`], and
+    /// compare them to renders through `tonemap`.
+    pub fn with_hdr(self, tonemap: Tonemap) -> Self {
+        Self {
+            tonemap: Some(tonemap),
+            ..self.map_views(|view| SceneView {
+                image: view.image.with_hdr(),
+                ..view
+            })
+        }
+    }
+
+    /// Shrink all images of the dataset by exactly `factor`, see
+    /// [`scene::LoadImage::with_downscale`].
+    pub fn with_downscale(self, factor: u32) -> Self {
+        self.map_views(|view| SceneView {
+            image: view.image.with_downscale(factor),
+            ..view
+        })
+    }
+
+    /// Blur detected regions of all images of the dataset when they're loaded.
+    pub fn with_redactor(self, redactor: Arc<Redactor>) -> Self {
+        self.map_views(|view| SceneView {
+            image: view.image.with_redactor(redactor.clone()),
+            ..view
+        })
+    }
+
+    /// Make the sky (or other background) of all images of the dataset transparent when they're
+    /// loaded.
+    pub fn with_sky_masker(self, masker: Arc<SkyMasker>) -> Self {
+        self.map_views(|view| SceneView {
+            image: view.image.with_sky_masker(masker.clone()),
+            ..view
+        })
+    }
+
+    /// Attach feature maps to the views, for splats to learn to render. `find` gives the path of
+    /// the feature map of an image, if it has one.
+    pub fn with_feature_maps(self, find: impl Fn(&Path) -> Option<PathBuf>) -> Self {
+        self.map_views(|view| SceneView {
+            image: match find(&view.image.path) {
+                Some(path) => view.image.with_feature_map(path),
+                None => view.image,
+            },
+            ..view
+        })
+    }
+
+    /// Attach depth maps to the views, for the depth of the splats to match. `find` gives the
+    /// paths of the depth map of an image and of its confidence map, if it has them. 16-bit depth
+    /// maps store depth in units of `unit_scale`.
+    pub fn with_depth_maps(
+        self,
+        find: impl Fn(&Path) -> Option<(PathBuf, Option<PathBuf>)>,
+        unit_scale: f32,
+    ) -> Self {
+        // Depth maps are in the frame of the dataset, before it was transformed.
+        let scene_scale = self.transform.scale;
+        self.map_views(|view| SceneView {
+            image: match find(&view.image.path) {
+                Some((path, confidence)) => view
+                    .image
+                    .with_depth_map(path, confidence, unit_scale)
+                    .with_depth_scale(scene_scale),
+                None => view.image,
+            },
+            ..view
+        })
+    }
+
+    /// Map the times of the views from their range to 0..1, so dynamic scenes train the same
+    /// whatever the units of the timestamps. Views of a single instant are left static.
+    pub fn with_normalized_times(self) -> Self {
+        let times = self
+            .train
+            .views
+            .iter()
+            .chain(self.eval.iter().flat_map(|s| s.views.iter()))
+            .filter_map(|v| v.image.time);
+        let (min, max) = times.fold((f32::MAX, f32::MIN), |(min, max), t| {
+            (min.min(t), max.max(t))
+        });
+        let range = (max > min).then_some((min, max - min));
+        self.map_views(|mut view| {
+            view.image.time = match (view.image.time, range) {
+                (Some(t), Some((min, len))) => Some((t - min) / len),
+                _ => None,
+            };
+            view
+        })
+    }
+
+    /// Keep resized copies of all images of the dataset in `cache` as they're loaded.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn with_disk_cache(self, cache: Arc<DiskImageCache>) -> Self {
+        self.map_views(|view| SceneView {
+            image: view.image.with_disk_cache(cache.clone()),
+            ..view
+        })
+    }
+
     pub fn estimate_up(&self) -> Vec3 {
         // based on https://github.com/jonbarron/camp_zipnerf/blob/8e6d57e3aee34235faf3ef99decca0994efe66c9/camp_zipnerf/internal/camera_utils.py#L233
         let (c2ws, ts): (Vec<_>, Vec<_>) = self
@@ -18,6 +18,8 @@ pub(crate) struct ParsedGaussian<const QUANT_PARSE: bool> {
     // NB: This is in the inria format, aka [channels, coeffs]
     // not [coeffs, channels].
     pub(crate) sh_coeffs_rest: Vec<f32>,
+    // Label of the splat, for ply files of labeled splats.
+    pub(crate) label: u32,
 }
 
 impl<const QUANT: bool> ParsedGaussian<QUANT> {
@@ -39,6 +41,18 @@ impl PropertyAccess for ParsedGaussian<false> {
     fn set_property(&mut self, key: &str, property: Property) {
         let ascii = key.as_bytes();
 
+        // Labels are indices, not normalized values.
+        if ascii == b"label" {
+            self.label = match property {
+                Property::UChar(value) => value as u32,
+                Property::UShort(value) => value as u32,
+                Property::UInt(value) => value,
+                Property::Int(value) => value.max(0) as u32,
+                _ => return,
+            };
+            return;
+        }
+
         let value = match property {
             Property::Double(value) => value as f32,
             Property::Float(value) => value,
@@ -109,6 +123,13 @@ impl PropertyAccess for ParsedGaussian<false> {
             _ => None,
         }
     }
+
+    fn get_uint(&self, key: &str) -> Option<u32> {
+        match key {
+            "label" => Some(self.label),
+            _ => None,
+        }
+    }
 }
 
 impl PropertyAccess for ParsedGaussian<true> {
@@ -0,0 +1,770 @@
+// Point clouds of laser scans, to start training from instead of the points of a reconstruction.
+//
+// Scans are read from LAS files, E57 files, or plys of points, and are expected to be registered
+// to the cameras of the dataset already.
+use std::{collections::HashMap, path::Path};
+
+use brush_render::{
+    MainBackend,
+    gaussian_splats::Splats,
+    knn::nearest_neighbour_log_scales,
+    sh::{rgb_to_sh, sh_to_rgb},
+};
+use burn::backend::wgpu::WgpuDevice;
+use glam::{DVec3, IVec3, Mat3, Quat, Vec3};
+use thiserror::Error;
+use tokio_stream::StreamExt;
+
+use crate::splat_import::{SplatImportError, load_splat_from_ply};
+
+// Splats aligned to the surface are this much thinner along the normal than along the surface.
+const SURFACE_FLATNESS: f32 = 0.1;
+
+// Neighbours within this many times the typical spacing of the points are used to estimate the
+// surface around a point.
+const NEIGHBOUR_RADIUS: f32 = 3.0;
+
+// Size of the header of LAS 1.2 files, later versions only add to it.
+const LAS_HEADER_SIZE: usize = 227;
+
+#[derive(Error, Debug)]
+pub enum PointCloudError {
+    #[error("Invalid LAS file: {0}")]
+    InvalidLas(&'static str),
+
+    #[error("Invalid E57 file: {0}")]
+    InvalidE57(&'static str),
+
+    #[error("Invalid XML in E57 file.")]
+    Xml(#[from] roxmltree::Error),
+
+    #[error("Failed to read ply points.")]
+    Ply(#[from] SplatImportError),
+
+    #[error("Point clouds can only be read from .las, .e57 or .ply files.")]
+    Unsupported,
+}
+
+/// Points with a color each, in sRGB from 0 to 1.
+#[derive(Debug, Clone, Default)]
+pub struct PointCloud {
+    pub positions: Vec<Vec3>,
+    pub colors: Vec<Vec3>,
+}
+
+impl PointCloud {
+    /// Keep only every `step`th point.
+    pub fn every_nth(self, step: usize) -> Self {
+        let step = step.max(1);
+        Self {
+            positions: self.positions.into_iter().step_by(step).collect(),
+            colors: self.colors.into_iter().step_by(step).collect(),
+        }
+    }
+
+    /// Replace all points inside each voxel of `size` by their average, which evens out the
+    /// density of scans that are much denser close to the scanner.
+    pub fn voxel_subsample(&self, size: f32) -> Self {
+        let mut voxels: HashMap<IVec3, (Vec3, Vec3, u32)> = HashMap::new();
+        let mut order = vec![];
+        for (&pos, &color) in self.positions.iter().zip(&self.colors) {
+            let cell = (pos / size).floor().as_ivec3();
+            let voxel = voxels.entry(cell).or_insert_with(|| {
+                order.push(cell);
+                (Vec3::ZERO, Vec3::ZERO, 0)
+            });
+            voxel.0 += pos;
+            voxel.1 += color;
+            voxel.2 += 1;
+        }
+        let (positions, colors) = order
+            .iter()
+            .map(|cell| {
+                let (pos, color, count) = voxels[cell];
+                (pos / count as f32, color / count as f32)
+            })
+            .unzip();
+        Self { positions, colors }
+    }
+
+    /// Initial splats at the points. With `align_to_surface`, splats are flat discs lying on the
+    /// surface the points are on, otherwise they're round and sized by their nearest neighbours.
+    pub async fn to_splats(
+        &self,
+        align_to_surface: bool,
+        device: &WgpuDevice,
+    ) -> Splats<MainBackend> {
+        let colors: Vec<f32> = self
+            .colors
+            .iter()
+            .flat_map(|&c| rgb_to_sh(c).to_array())
+            .collect();
+        let log_scales = nearest_neighbour_log_scales(&self.positions, device).await;
+        if align_to_surface {
+            let (rotations, log_scales) = surface_frames(&self.positions, &log_scales);
+            Splats::from_raw(
+                &self.positions,
+                Some(&rotations),
+                Some(&log_scales),
+                Some(&colors),
+                None,
+                device,
+            )
+        } else {
+            Splats::from_raw(
+                &self.positions,
+                None,
+                Some(&log_scales),
+                Some(&colors),
+                None,
+                device,
+            )
+        }
+    }
+}
+
+// Eigenvalues and eigenvectors (as columns) of a symmetric matrix, with cyclic Jacobi rotations.
+fn symmetric_eigen(m: Mat3) -> (Vec3, Mat3) {
+    let mut a = [0, 1, 2].map(|r| [0, 1, 2].map(|c| m.col(c)[r]));
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    for _ in 0..16 {
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q].abs() < 1e-20 {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+            for row in &mut a {
+                let (kp, kq) = (row[p], row[q]);
+                row[p] = c * kp - s * kq;
+                row[q] = s * kp + c * kq;
+            }
+            let (rp, rq) = (a[p], a[q]);
+            for k in 0..3 {
+                a[p][k] = c * rp[k] - s * rq[k];
+                a[q][k] = s * rp[k] + c * rq[k];
+            }
+            for row in &mut v {
+                let (kp, kq) = (row[p], row[q]);
+                row[p] = c * kp - s * kq;
+                row[q] = s * kp + c * kq;
+            }
+        }
+    }
+    let values = Vec3::new(a[0][0], a[1][1], a[2][2]);
+    let vectors = Mat3::from_cols_array_2d(&[0, 1, 2].map(|c| [0, 1, 2].map(|r| v[r][c])));
+    (values, vectors)
+}
+
+// Rotations and log scales of flat splats on the surface around each point. The normal of the
+// surface is the direction its neighbours spread the least in. Points without enough neighbours
+// keep round splats.
+fn surface_frames(positions: &[Vec3], log_scales: &[Vec3]) -> (Vec<Quat>, Vec<Vec3>) {
+    let mut spacings: Vec<f32> = log_scales.iter().map(|s| s.x.exp()).collect();
+    spacings.sort_by(f32::total_cmp);
+    let radius = spacings.get(spacings.len() / 2).copied().unwrap_or(1.0) * NEIGHBOUR_RADIUS;
+
+    let cell_of = |p: Vec3| (p / radius).floor().as_ivec3();
+    let mut grid: HashMap<IVec3, Vec<usize>> = HashMap::new();
+    for (i, &p) in positions.iter().enumerate() {
+        grid.entry(cell_of(p)).or_default().push(i);
+    }
+
+    positions
+        .iter()
+        .zip(log_scales)
+        .map(|(&p, &log_scale)| {
+            let cell = cell_of(p);
+            let mut neighbours = vec![];
+            for offset in (0..27).map(|i| IVec3::new(i % 3, (i / 3) % 3, i / 9) - IVec3::ONE) {
+                let Some(points) = grid.get(&(cell + offset)) else {
+                    continue;
+                };
+                neighbours.extend(
+                    points
+                        .iter()
+                        .map(|&j| positions[j])
+                        .filter(|q| q.distance_squared(p) < radius * radius),
+                );
+            }
+            if neighbours.len() < 4 {
+                return (Quat::IDENTITY, log_scale);
+            }
+
+            let mean = neighbours.iter().sum::<Vec3>() / neighbours.len() as f32;
+            let mut covariance = Mat3::ZERO;
+            for q in &neighbours {
+                let d = *q - mean;
+                covariance += Mat3::from_cols(d * d.x, d * d.y, d * d.z);
+            }
+            let (values, vectors) = symmetric_eigen(covariance * (1.0 / neighbours.len() as f32));
+
+            // The two directions the neighbours spread out the most lie on the surface.
+            let mut axes = [0, 1, 2];
+            axes.sort_by(|&a, &b| values[b].total_cmp(&values[a]));
+            let tangent = vectors.col(axes[0]);
+            let bitangent = vectors.col(axes[1]);
+            let frame = Mat3::from_cols(tangent, bitangent, tangent.cross(bitangent));
+            let flat = Vec3::new(
+                log_scale.x,
+                log_scale.x,
+                log_scale.x + SURFACE_FLATNESS.ln(),
+            );
+            (Quat::from_mat3(&frame).normalize(), flat)
+        })
+        .unzip()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn read_f64(data: &[u8], offset: usize) -> Option<f64> {
+    read_u64(data, offset).map(f64::from_bits)
+}
+
+// Colors stored as 16 bits, or as 8 bits by some writers. Without colors, the intensity of the
+// returns is used as gray.
+fn normalize_colors(colors: Vec<[u16; 3]>) -> Vec<Vec3> {
+    let max = colors.iter().flatten().copied().max().unwrap_or(0);
+    let range = if max <= 255 { 255.0 } else { 65535.0 };
+    colors
+        .into_iter()
+        .map(|c| Vec3::from_array(c.map(f32::from)) / range)
+        .collect()
+}
+
+/// Read the points of a LAS file. Compressed LAZ files aren't supported.
+pub fn read_las(data: &[u8]) -> Result<PointCloud, PointCloudError> {
+    if data.get(0..4) != Some(b"LASF") {
+        return Err(PointCloudError::InvalidLas("Missing LASF signature"));
+    }
+    if data.len() < LAS_HEADER_SIZE {
+        return Err(PointCloudError::InvalidLas("Truncated header"));
+    }
+    let minor_version = data[25];
+    let point_offset = read_u32(data, 96).unwrap_or(0) as usize;
+    let format = data[104];
+    if format & 0xc0 != 0 {
+        return Err(PointCloudError::InvalidLas(
+            "Compressed LAZ files aren't supported",
+        ));
+    }
+    let record_length = read_u16(data, 105).unwrap_or(0) as usize;
+    let min_record_length = match format {
+        0 => 20,
+        1 => 28,
+        2 => 26,
+        3 => 34,
+        4 => 57,
+        5 => 63,
+        6 => 30,
+        7 => 36,
+        8 => 38,
+        9 => 59,
+        10 => 67,
+        _ => 12,
+    };
+    if record_length < min_record_length {
+        return Err(PointCloudError::InvalidLas("Point records too short"));
+    }
+    let mut count = u64::from(read_u32(data, 107).unwrap_or(0));
+    // LAS 1.4 files with many points only store the count in the 64-bit field.
+    if minor_version >= 4 {
+        count = read_u64(data, 247).ok_or(PointCloudError::InvalidLas("Truncated header"))?;
+    }
+    // Check the count against the data before allocating for it.
+    let points_end = count
+        .checked_mul(record_length as u64)
+        .and_then(|len| len.checked_add(point_offset as u64));
+    if points_end.is_none_or(|end| end > data.len() as u64) {
+        return Err(PointCloudError::InvalidLas("Truncated point data"));
+    }
+    let vec3_at = |offset| -> Option<DVec3> {
+        Some(DVec3::new(
+            read_f64(data, offset)?,
+            read_f64(data, offset + 8)?,
+            read_f64(data, offset + 16)?,
+        ))
+    };
+    let scale = vec3_at(131).unwrap_or(DVec3::ONE);
+    let offset = vec3_at(155).unwrap_or(DVec3::ZERO);
+    let color_offset = match format {
+        2 => Some(20),
+        3 | 5 => Some(28),
+        7 | 8 | 10 => Some(30),
+        _ => None,
+    };
+
+    let mut positions = Vec::with_capacity(count as usize);
+    let mut colors = Vec::with_capacity(count as usize);
+    for i in 0..count as usize {
+        let record = point_offset + i * record_length;
+        let record = data
+            .get(record..record + record_length)
+            .ok_or(PointCloudError::InvalidLas("Truncated point data"))?;
+        let coord = |axis: usize| {
+            i32::from_le_bytes(record[axis * 4..axis * 4 + 4].try_into().expect("4 bytes"))
+        };
+        let raw = DVec3::new(coord(0).into(), coord(1).into(), coord(2).into());
+        positions.push((raw * scale + offset).as_vec3());
+        let color = match color_offset {
+            Some(at) => [0, 1, 2].map(|c| read_u16(record, at + c * 2).unwrap_or(0)),
+            None => [read_u16(record, 12).unwrap_or(0); 3],
+        };
+        colors.push(color);
+    }
+    Ok(PointCloud {
+        positions,
+        colors: normalize_colors(colors),
+    })
+}
+
+// How a field of an E57 point record is stored.
+#[derive(Debug, Clone, Copy)]
+enum E57Field {
+    Float {
+        double: bool,
+    },
+    Integer {
+        minimum: i64,
+        bits: u32,
+        scale: f64,
+        offset: f64,
+    },
+}
+
+impl E57Field {
+    fn parse(node: roxmltree::Node<'_, '_>) -> Option<Self> {
+        let number = |name: &str, default: f64| {
+            node.attribute(name)
+                .map_or(Some(default), |v| v.trim().parse::<f64>().ok())
+        };
+        match node.attribute("type")? {
+            "Float" => Some(Self::Float {
+                double: node.attribute("precision") != Some("single"),
+            }),
+            kind @ ("Integer" | "ScaledInteger") => {
+                let minimum: i64 = node.attribute("minimum")?.trim().parse().ok()?;
+                let maximum: i64 = node.attribute("maximum")?.trim().parse().ok()?;
+                let range = maximum.checked_sub(minimum)? as u64;
+                let bits = u64::BITS - range.leading_zeros();
+                let (scale, offset) = if kind == "ScaledInteger" {
+                    (number("scale", 1.0)?, number("offset", 0.0)?)
+                } else {
+                    (1.0, 0.0)
+                };
+                Some(Self::Integer {
+                    minimum,
+                    bits,
+                    scale,
+                    offset,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    // Decode all values of the field from its concatenated bytestream.
+    fn decode(&self, bytes: &[u8], count: usize) -> Vec<f64> {
+        match *self {
+            Self::Float { double: true } => bytes
+                .chunks_exact(8)
+                .take(count)
+                .map(|b| f64::from_le_bytes(b.try_into().expect("8 bytes")))
+                .collect(),
+            Self::Float { double: false } => bytes
+                .chunks_exact(4)
+                .take(count)
+                .map(|b| f32::from_le_bytes(b.try_into().expect("4 bytes")).into())
+                .collect(),
+            Self::Integer {
+                minimum,
+                bits,
+                scale,
+                offset,
+            } => (0..count)
+                .map_while(|i| {
+                    // Values are packed one after the other, starting at the lowest bit.
+                    let start = i * bits as usize;
+                    let mut raw = 0u128;
+                    for (k, byte) in (start / 8..(start + bits as usize).div_ceil(8)).enumerate() {
+                        raw |= u128::from(*bytes.get(byte)?) << (k * 8);
+                    }
+                    let raw = (raw >> (start % 8)) & ((1u128 << bits) - 1);
+                    Some((minimum + raw as i64) as f64 * scale + offset)
+                })
+                .collect(),
+        }
+    }
+}
+
+fn child<'a, 'i>(node: roxmltree::Node<'a, 'i>, name: &str) -> Option<roxmltree::Node<'a, 'i>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn child_number(node: roxmltree::Node<'_, '_>, name: &str) -> Option<f64> {
+    child(node, name)?.text()?.trim().parse().ok()
+}
+
+// Pages of an E57 file end with a checksum, which sits between the data. Offsets in the file are
+// physical, including the checksums.
+struct E57Pages<'a> {
+    data: &'a [u8],
+    page_size: usize,
+}
+
+impl E57Pages<'_> {
+    fn logical(&self, physical: usize) -> usize {
+        physical / self.page_size * (self.page_size - 4) + physical % self.page_size
+    }
+
+    fn physical(&self, logical: usize) -> usize {
+        logical / (self.page_size - 4) * self.page_size + logical % (self.page_size - 4)
+    }
+
+    // Read `len` bytes of data, starting at a physical offset.
+    fn read(&self, physical: usize, len: usize) -> Option<Vec<u8>> {
+        // Lengths come from the file, so check them before allocating.
+        if len > self.data.len() {
+            return None;
+        }
+        let mut out = Vec::with_capacity(len);
+        let mut logical = self.logical(physical);
+        while out.len() < len {
+            let at = self.physical(logical);
+            let take = (self.page_size - 4 - at % self.page_size).min(len - out.len());
+            out.extend_from_slice(self.data.get(at..at + take)?);
+            logical += take;
+        }
+        Some(out)
+    }
+
+    // Physical offset `len` bytes of data after a physical offset.
+    fn advance(&self, physical: usize, len: usize) -> usize {
+        self.physical(self.logical(physical) + len)
+    }
+}
+
+// Read the fields of the records of a compressed vector, each as one stream of bytes.
+fn read_streams(
+    pages: &E57Pages<'_>,
+    section: usize,
+    stream_count: usize,
+) -> Result<Vec<Vec<u8>>, PointCloudError> {
+    let invalid = PointCloudError::InvalidE57("Truncated binary section");
+    let header = pages.read(section, 32).ok_or(invalid)?;
+    if header[0] != 1 {
+        return Err(PointCloudError::InvalidE57(
+            "Missing compressed vector section",
+        ));
+    }
+    let section_length = read_u64(&header, 8).unwrap_or(0) as usize;
+    let end = pages.advance(section, section_length);
+    let mut packet = read_u64(&header, 16).unwrap_or(0) as usize;
+
+    let mut streams = vec![vec![]; stream_count];
+    while packet < end {
+        let invalid = || PointCloudError::InvalidE57("Truncated data packet");
+        let head = pages.read(packet, 4).ok_or_else(invalid)?;
+        let packet_length = read_u16(&head, 2).unwrap_or(0) as usize + 1;
+        // Index and empty packets hold no data.
+        if head[0] == 1 {
+            let data = pages.read(packet, packet_length).ok_or_else(invalid)?;
+            let count = read_u16(&data, 4).unwrap_or(0) as usize;
+            let mut at = 6 + count * 2;
+            for (i, stream) in streams.iter_mut().enumerate().take(count) {
+                let len = read_u16(&data, 6 + i * 2).unwrap_or(0) as usize;
+                stream.extend_from_slice(data.get(at..at + len).ok_or_else(invalid)?);
+                at += len;
+            }
+        }
+        packet = pages.advance(packet, packet_length);
+    }
+    Ok(streams)
+}
+
+/// Read the points of all scans in an E57 file, each placed by the pose of its scan. Points are
+/// read from cartesian or spherical coordinates, with their color or intensity if they have one.
+pub fn read_e57(data: &[u8]) -> Result<PointCloud, PointCloudError> {
+    if data.get(0..8) != Some(b"ASTM-E57") {
+        return Err(PointCloudError::InvalidE57("Missing ASTM-E57 signature"));
+    }
+    let truncated = || PointCloudError::InvalidE57("Truncated header");
+    let xml_offset = read_u64(data, 24).ok_or_else(truncated)? as usize;
+    let xml_length = read_u64(data, 32).ok_or_else(truncated)?;
+    let page_size = read_u64(data, 40).ok_or_else(truncated)?;
+    if page_size <= 4 {
+        return Err(PointCloudError::InvalidE57("Invalid page size"));
+    }
+    let pages = E57Pages {
+        data,
+        page_size: page_size as usize,
+    };
+    let xml = pages
+        .read(xml_offset, xml_length as usize)
+        .ok_or(PointCloudError::InvalidE57("Truncated XML section"))?;
+    let xml = String::from_utf8_lossy(&xml);
+    let document = roxmltree::Document::parse(&xml)?;
+
+    let mut cloud = PointCloud::default();
+    let mut colors = vec![];
+    let Some(scans) = child(document.root_element(), "data3D") else {
+        return Ok(cloud);
+    };
+    for scan in scans.children().filter(roxmltree::Node::is_element) {
+        let Some(points) = child(scan, "points") else {
+            continue;
+        };
+        let invalid = || PointCloudError::InvalidE57("Invalid points of scan");
+        let section: usize = points
+            .attribute("fileOffset")
+            .and_then(|v| v.trim().parse().ok())
+            .ok_or_else(invalid)?;
+        let count: usize = points
+            .attribute("recordCount")
+            .and_then(|v| v.trim().parse().ok())
+            .ok_or_else(invalid)?;
+        let prototype = child(points, "prototype").ok_or_else(invalid)?;
+        let fields: Vec<_> = prototype
+            .children()
+            .filter(roxmltree::Node::is_element)
+            .map(|node| {
+                Ok((
+                    node.tag_name().name(),
+                    E57Field::parse(node).ok_or_else(invalid)?,
+                ))
+            })
+            .collect::<Result<_, PointCloudError>>()?;
+
+        let streams = read_streams(&pages, section, fields.len())?;
+        let values: HashMap<&str, Vec<f64>> = fields
+            .iter()
+            .zip(&streams)
+            .map(|((name, field), bytes)| (*name, field.decode(bytes, count)))
+            .collect();
+        let range_of = |name: &str| {
+            fields.iter().find(|f| f.0 == name).and_then(|f| match f.1 {
+                E57Field::Integer { minimum, bits, .. } => {
+                    Some((minimum as f64, (minimum + (1i64 << bits) - 1) as f64))
+                }
+                E57Field::Float { .. } => None,
+            })
+        };
+
+        let pose = child(scan, "pose");
+        let rotation = pose
+            .and_then(|pose| child(pose, "rotation"))
+            .and_then(|r| {
+                let [w, x, y, z] = ["w", "x", "y", "z"].map(|n| child_number(r, n));
+                Some(Quat::from_xyzw(x? as f32, y? as f32, z? as f32, w? as f32).normalize())
+            })
+            .unwrap_or(Quat::IDENTITY);
+        let translation = pose
+            .and_then(|pose| child(pose, "translation"))
+            .and_then(|t| {
+                let [x, y, z] = ["x", "y", "z"].map(|n| child_number(t, n));
+                Some(DVec3::new(x?, y?, z?))
+            })
+            .unwrap_or(DVec3::ZERO);
+
+        let get = |name: &str, i: usize| values.get(name).and_then(|v| v.get(i)).copied();
+        let color_fields = ["colorRed", "colorGreen", "colorBlue"];
+        let has_color = color_fields.iter().all(|name| values.contains_key(name));
+        // Float colors and intensities go from 0 to 1.
+        let intensity = values
+            .contains_key("intensity")
+            .then(|| range_of("intensity").unwrap_or((0.0, 1.0)));
+        for i in 0..count {
+            if get("cartesianInvalidState", i).is_some_and(|s| s != 0.0)
+                || get("sphericalInvalidState", i).is_some_and(|s| s != 0.0)
+            {
+                continue;
+            }
+            let local = if let (Some(x), Some(y), Some(z)) = (
+                get("cartesianX", i),
+                get("cartesianY", i),
+                get("cartesianZ", i),
+            ) {
+                DVec3::new(x, y, z)
+            } else if let (Some(r), Some(azimuth), Some(elevation)) = (
+                get("sphericalRange", i),
+                get("sphericalAzimuth", i),
+                get("sphericalElevation", i),
+            ) {
+                DVec3::new(
+                    r * elevation.cos() * azimuth.cos(),
+                    r * elevation.cos() * azimuth.sin(),
+                    r * elevation.sin(),
+                )
+            } else {
+                continue;
+            };
+            // Rotate before translating in double precision, scans are often far from the origin.
+            let position = rotation.as_dquat() * local + translation;
+            cloud.positions.push(position.as_vec3());
+
+            let color = if has_color {
+                color_fields.map(|name| {
+                    let (min, max) = range_of(name).unwrap_or((0.0, 1.0));
+                    let v = get(name, i).unwrap_or(0.0);
+                    ((v - min) / (max - min)) as f32
+                })
+            } else if let (Some((min, max)), Some(v)) = (intensity, get("intensity", i)) {
+                [((v - min) / (max - min)) as f32; 3]
+            } else {
+                [0.5; 3]
+            };
+            colors.push(Vec3::from_array(color));
+        }
+    }
+    cloud.colors = colors;
+    Ok(cloud)
+}
+
+// Read the points of a ply, with the colors they have.
+async fn read_ply_points(
+    data: Vec<u8>,
+    device: &WgpuDevice,
+) -> Result<PointCloud, PointCloudError> {
+    let stream = load_splat_from_ply(std::io::Cursor::new(data), None, device.clone());
+    let mut stream = std::pin::pin!(stream);
+    let mut splats = None;
+    while let Some(message) = stream.next().await {
+        splats = Some(message?.splats);
+    }
+    let Some(splats) = splats else {
+        return Ok(PointCloud::default());
+    };
+    let means = splats
+        .means
+        .val()
+        .into_data_async()
+        .await
+        .into_vec::<f32>()
+        .expect("Wrong type");
+    let [n, _, _] = splats.sh_coeffs.dims();
+    let sh_dc = splats
+        .sh_coeffs
+        .val()
+        .slice([0..n, 0..1])
+        .into_data_async()
+        .await
+        .into_vec::<f32>()
+        .expect("Wrong type");
+    Ok(PointCloud {
+        positions: means.chunks_exact(3).map(Vec3::from_slice).collect(),
+        colors: sh_dc
+            .chunks_exact(3)
+            .map(|sh| sh_to_rgb(Vec3::from_slice(sh)).clamp(Vec3::ZERO, Vec3::ONE))
+            .collect(),
+    })
+}
+
+/// Read a point cloud of a scan, depending on the extension of its file.
+pub async fn read_point_cloud(
+    path: &Path,
+    data: Vec<u8>,
+    device: &WgpuDevice,
+) -> Result<PointCloud, PointCloudError> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase);
+    match ext.as_deref() {
+        Some("las") => read_las(&data),
+        Some("e57") => read_e57(&data),
+        Some("ply") => read_ply_points(data, device).await,
+        _ => Err(PointCloudError::Unsupported),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splats_lie_on_surface() {
+        // A grid of points on a tilted plane.
+        let normal = Vec3::new(1.0, 2.0, 3.0).normalize();
+        let tangent = normal.any_orthonormal_vector();
+        let bitangent = normal.cross(tangent);
+        let positions: Vec<_> = (0..100)
+            .map(|i| tangent * (i % 10) as f32 * 0.1 + bitangent * (i / 10) as f32 * 0.1)
+            .collect();
+        let log_scales = vec![Vec3::splat(0.1f32.ln()); positions.len()];
+
+        let (rotations, scales) = surface_frames(&positions, &log_scales);
+        for (rotation, scale) in rotations.iter().zip(&scales) {
+            // The thin axis of each splat is along the normal of the plane.
+            assert!((*rotation * Vec3::Z).dot(normal).abs() > 0.999);
+            assert!(scale.z < scale.x);
+        }
+    }
+
+    #[test]
+    fn reads_las() {
+        let mut data = vec![0u8; LAS_HEADER_SIZE];
+        data[0..4].copy_from_slice(b"LASF");
+        data[24] = 1;
+        data[25] = 2;
+        data[94..96].copy_from_slice(&227u16.to_le_bytes());
+        data[96..100].copy_from_slice(&227u32.to_le_bytes());
+        data[104] = 2;
+        data[105..107].copy_from_slice(&26u16.to_le_bytes());
+        data[107..111].copy_from_slice(&2u32.to_le_bytes());
+        for axis in 0..3 {
+            data[131 + axis * 8..139 + axis * 8].copy_from_slice(&0.5f64.to_le_bytes());
+            data[155 + axis * 8..163 + axis * 8].copy_from_slice(&100.0f64.to_le_bytes());
+        }
+        for (x, red) in [(0i32, 0u16), (3, 65535)] {
+            let mut record = [0u8; 26];
+            record[0..4].copy_from_slice(&x.to_le_bytes());
+            record[20..22].copy_from_slice(&red.to_le_bytes());
+            data.extend_from_slice(&record);
+        }
+
+        let cloud = read_las(&data).expect("Valid LAS");
+        assert_eq!(
+            cloud.positions,
+            [Vec3::splat(100.0), Vec3::new(101.5, 100.0, 100.0)]
+        );
+        assert_eq!(cloud.colors[1], Vec3::X);
+
+        // Counts and record lengths that don't fit the data are errors.
+        let mut huge = data.clone();
+        huge[107..111].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_las(&huge).is_err());
+        let mut short = data.clone();
+        short[105..107].copy_from_slice(&0u16.to_le_bytes());
+        assert!(read_las(&short).is_err());
+    }
+
+    #[test]
+    fn unpacks_integers() {
+        // Values 1, 2 and 3 above a minimum of 10, packed in 2 bits each.
+        let field = E57Field::Integer {
+            minimum: 10,
+            bits: 2,
+            scale: 1.0,
+            offset: 0.0,
+        };
+        assert_eq!(field.decode(&[0b11_10_01], 3), [11.0, 12.0, 13.0]);
+    }
+}
@@ -0,0 +1,222 @@
+// Smoothing the poses of cameras along the trajectory of a video. Structure from motion solves the
+// pose of each frame on its own, so poses jitter from frame to frame while the camera moved
+// smoothly, which blurs the reconstruction.
+//
+// Each position is replaced by a weighted least squares quadratic through the positions of nearby
+// frames, and each rotation by a weighted average of nearby rotations. Poses far off the trajectory
+// are left out of the fit, and replaced by it.
+use brush_render::camera::Camera;
+use glam::{Mat3, Quat, Vec3, Vec4};
+
+// Poses further off the fit than this many times the median distance of all poses are outliers.
+const OUTLIER_FACTOR: f32 = 4.0;
+
+/// Camera poses smoothed along a trajectory.
+pub struct SmoothedPoses {
+    pub cameras: Vec<Camera>,
+    /// Indices of the poses too far off the trajectory to be trusted, which were replaced by the
+    /// fit of the poses around them.
+    pub outliers: Vec<usize>,
+}
+
+// Weight of a frame `offset` frames away in a fit over `window` frames on each side.
+fn tricube(offset: f32, window: f32) -> f32 {
+    let x = (offset.abs() / (window + 1.0)).min(1.0);
+    (1.0 - x * x * x).powi(3)
+}
+
+// Position and rotation of the fit through the inlier poses around frame `index`.
+fn fit_at(cameras: &[Camera], inliers: &[bool], index: usize, window: usize) -> (Vec3, Quat) {
+    let start = index.saturating_sub(window);
+    let end = (index + window).min(cameras.len() - 1);
+    let reference = cameras[index].rotation;
+
+    // Normal equations of the fit of p(t) = a + b t + c t^2, with t relative to the frame.
+    let mut normal = Mat3::ZERO;
+    let mut rhs = [Vec3::ZERO; 3];
+    let mut weighted_sum = Vec3::ZERO;
+    let mut total_weight = 0.0;
+    let mut rotation_sum = Vec4::ZERO;
+
+    for j in start..=end {
+        if !inliers[j] {
+            continue;
+        }
+        let t = j as f32 - index as f32;
+        let weight = tricube(t, window as f32);
+        let basis = Vec3::new(1.0, t, t * t);
+        normal += Mat3::from_cols(basis * basis.x, basis * basis.y, basis * basis.z) * weight;
+        let position = cameras[j].position;
+        for (k, rhs) in rhs.iter_mut().enumerate() {
+            *rhs += position * (weight * basis[k]);
+        }
+        weighted_sum += position * weight;
+        total_weight += weight;
+
+        // q and -q are the same rotation, only average quaternions on the same side.
+        let rotation = cameras[j].rotation;
+        let rotation = if rotation.dot(reference) < 0.0 {
+            -rotation
+        } else {
+            rotation
+        };
+        rotation_sum += Vec4::from(rotation) * weight;
+    }
+
+    if total_weight <= 0.0 {
+        return (cameras[index].position, reference);
+    }
+
+    // With fewer than three frames there's no curve to fit, fall back to the average.
+    let position = if normal.determinant() > 1e-6 {
+        // The fit at t = 0 is its constant term, the first row of the inverse applied to `rhs`.
+        let row = normal.inverse().row(0);
+        rhs[0] * row.x + rhs[1] * row.y + rhs[2] * row.z
+    } else {
+        weighted_sum / total_weight
+    };
+    let rotation = Quat::from_vec4(rotation_sum).normalize();
+    (position, rotation)
+}
+
+fn median(values: &[f32]) -> f32 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    sorted[sorted.len() / 2]
+}
+
+/// Smooth the poses of `cameras`, in the order they were captured, over `window` frames on each
+/// side of every frame.
+pub fn smooth_poses(cameras: &[Camera], window: usize) -> SmoothedPoses {
+    let count = cameras.len();
+    if count < 3 || window == 0 {
+        return SmoothedPoses {
+            cameras: cameras.to_vec(),
+            outliers: vec![],
+        };
+    }
+
+    // Take out the pose furthest off the fit through the poses around it, until all poses are
+    // close to their fit. One at a time, as an outlier also pulls the fits of its neighbours.
+    let steps: Vec<f32> = cameras
+        .windows(2)
+        .map(|pair| pair[0].position.distance(pair[1].position))
+        .collect();
+    let typical_step = median(&steps);
+    let mut inliers = vec![true; count];
+    loop {
+        let distances: Vec<(usize, f32)> = (0..count)
+            .filter(|&i| inliers[i])
+            .map(|i| {
+                let mut others = inliers.clone();
+                others[i] = false;
+                let (position, _) = fit_at(cameras, &others, i, window);
+                (i, position.distance(cameras[i].position))
+            })
+            .collect();
+        let Some(&(worst, distance)) = distances.iter().max_by(|a, b| a.1.total_cmp(&b.1)) else {
+            break;
+        };
+        // Jitter smaller than the step between frames isn't an outlier, even on a trajectory the
+        // fit follows almost exactly.
+        let typical = median(&distances.iter().map(|d| d.1).collect::<Vec<_>>());
+        if distance <= (typical * OUTLIER_FACTOR).max(typical_step) {
+            break;
+        }
+        inliers[worst] = false;
+    }
+    let outliers = (0..count).filter(|&i| !inliers[i]).collect();
+
+    let cameras = (0..count)
+        .map(|i| {
+            let (position, rotation) = fit_at(cameras, &inliers, i, window);
+            Camera {
+                position,
+                rotation,
+                ..cameras[i].clone()
+            }
+        })
+        .collect();
+    SmoothedPoses { cameras, outliers }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec2;
+
+    fn camera(position: Vec3, rotation: Quat) -> Camera {
+        Camera::new(position, rotation, 0.8, 0.8, Vec2::splat(0.5))
+    }
+
+    // A camera moving along an arc while turning, as the truth and with jitter on top.
+    fn trajectory(count: usize) -> (Vec<Camera>, Vec<Camera>) {
+        let truth: Vec<_> = (0..count)
+            .map(|i| {
+                let t = i as f32 * 0.05;
+                camera(
+                    Vec3::new(t.cos(), 0.2 * t, t.sin()) * 4.0,
+                    Quat::from_rotation_y(t),
+                )
+            })
+            .collect();
+        let noisy = truth
+            .iter()
+            .enumerate()
+            .map(|(i, cam)| {
+                let phase = i as f32 * 2.3;
+                let jitter = Vec3::new(phase.sin(), (phase * 1.7).cos(), (phase * 0.6).sin());
+                camera(
+                    cam.position + jitter * 0.02,
+                    cam.rotation * Quat::from_rotation_x(0.01 * phase.cos()),
+                )
+            })
+            .collect();
+        (truth, noisy)
+    }
+
+    fn mean_error(cameras: &[Camera], truth: &[Camera]) -> (f32, f32) {
+        let count = cameras.len() as f32;
+        let position = cameras
+            .iter()
+            .zip(truth)
+            .map(|(a, b)| a.position.distance(b.position))
+            .sum::<f32>();
+        let rotation = cameras
+            .iter()
+            .zip(truth)
+            .map(|(a, b)| a.rotation.angle_between(b.rotation))
+            .sum::<f32>();
+        (position / count, rotation / count)
+    }
+
+    #[test]
+    fn reduces_jitter() {
+        let (truth, noisy) = trajectory(60);
+        let smoothed = smooth_poses(&noisy, 5);
+        assert!(smoothed.outliers.is_empty());
+
+        let (noisy_position, noisy_rotation) = mean_error(&noisy, &truth);
+        let (position, rotation) = mean_error(&smoothed.cameras, &truth);
+        assert!(position < noisy_position * 0.5);
+        assert!(rotation < noisy_rotation * 0.5);
+    }
+
+    #[test]
+    fn replaces_outliers() {
+        let (truth, mut noisy) = trajectory(60);
+        noisy[20].position += Vec3::Y * 2.0;
+        let smoothed = smooth_poses(&noisy, 5);
+        assert_eq!(smoothed.outliers, vec![20]);
+        assert!(smoothed.cameras[20].position.distance(truth[20].position) < 0.05);
+        // Neighbours aren't pulled towards the outlier.
+        assert!(smoothed.cameras[21].position.distance(truth[21].position) < 0.05);
+    }
+
+    #[test]
+    fn short_trajectories_are_kept() {
+        let (_, noisy) = trajectory(2);
+        let smoothed = smooth_poses(&noisy, 5);
+        assert_eq!(smoothed.cameras[1].position, noisy[1].position);
+    }
+}
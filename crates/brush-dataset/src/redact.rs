@@ -0,0 +1,403 @@
+use crate::scene::SceneView;
+use brush_render::{
+    camera::Camera,
+    gaussian_splats::Splats,
+    selection::{SelectionShape, select_splats},
+    sh::sh_to_rgb,
+};
+use burn::{
+    prelude::Backend,
+    tensor::{Bool, Tensor},
+};
+use glam::{UVec2, Vec2, Vec3};
+use image::{DynamicImage, GenericImageView};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RedactError {
+    #[error("Brush was built without ONNX support, enable the `onnx` feature to blur images.")]
+    Unsupported,
+
+    #[cfg(feature = "onnx")]
+    #[error("Error running detector: {0}")]
+    Onnx(#[from] ort::Error),
+
+    #[error(
+        "Unexpected detector output of shape {0:?}, expected [1, N, 5] or more values per row."
+    )]
+    InvalidOutput(Vec<i64>),
+}
+
+/// A region of an image, in normalized [0, 1] image coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Region {
+    /// The pixel bounds of this region as (min, max) in an image of the given size, if not empty.
+    fn pixel_bounds(&self, size: UVec2) -> Option<(UVec2, UVec2)> {
+        let size_f = size.as_vec2();
+        let min = (self.min * size_f)
+            .floor()
+            .clamp(Vec2::ZERO, size_f)
+            .as_uvec2();
+        let max = (self.max * size_f)
+            .ceil()
+            .clamp(Vec2::ZERO, size_f)
+            .as_uvec2();
+        (max.x > min.x && max.y > min.y).then_some((min, max))
+    }
+}
+
+/// Finds regions of an image to blur, eg. faces or license plates.
+pub trait RegionDetector: Send + Sync {
+    fn detect(&self, image: &DynamicImage) -> Result<Vec<Region>, RedactError>;
+}
+
+/// Blur the given regions of an image.
+///
+/// The blur radius scales with the size of each region, so that small and large regions are both
+/// unrecognizable.
+pub fn blur_regions(mut image: DynamicImage, regions: &[Region]) -> DynamicImage {
+    let size = UVec2::new(image.width(), image.height());
+    for region in regions {
+        let Some((min, max)) = region.pixel_bounds(size) else {
+            continue;
+        };
+        let extent = max - min;
+        let sigma = (extent.min_element() as f32 * 0.2).max(2.0);
+        let blurred = image.crop_imm(min.x, min.y, extent.x, extent.y).blur(sigma);
+        for (x, y, pixel) in blurred.pixels() {
+            image::GenericImage::put_pixel(&mut image, min.x + x, min.y + y, pixel);
+        }
+    }
+    image
+}
+
+#[cfg(feature = "onnx")]
+mod onnx {
+    use super::{RedactError, Region, RegionDetector};
+    use glam::vec2;
+    use image::DynamicImage;
+    use ort::{session::Session, value::Tensor};
+    use std::path::Path;
+
+    /// Detector running an ONNX object detection model.
+    ///
+    /// The model takes a [1, 3, S, S] RGB image in [0, 1], and outputs [1, N, 5+] detections,
+    /// where each row starts with x1, y1, x2, y2 in pixels of the model input, followed by a score.
+    /// This is the layout of YOLO models exported with NMS included.
+    pub struct OnnxDetector {
+        session: Session,
+        input_size: u32,
+        threshold: f32,
+    }
+
+    impl OnnxDetector {
+        pub fn load(path: &Path, input_size: u32, threshold: f32) -> Result<Self, RedactError> {
+            let session = Session::builder()?.commit_from_file(path)?;
+            Ok(Self {
+                session,
+                input_size,
+                threshold,
+            })
+        }
+    }
+
+    impl RegionDetector for OnnxDetector {
+        fn detect(&self, image: &DynamicImage) -> Result<Vec<Region>, RedactError> {
+            let size = self.input_size;
+            let resized = image
+                .resize_exact(size, size, image::imageops::FilterType::Triangle)
+                .into_rgb8();
+
+            // Planar CHW layout.
+            let plane = (size * size) as usize;
+            let mut data = vec![0.0f32; 3 * plane];
+            for (i, pixel) in resized.pixels().enumerate() {
+                for c in 0..3 {
+                    data[c * plane + i] = pixel[c] as f32 / 255.0;
+                }
+            }
+            let input = Tensor::from_array(([1, 3, size as usize, size as usize], data))?;
+            let outputs = self.session.run(ort::inputs![input]?)?;
+            let (shape, values) = outputs[0].try_extract_raw_tensor::<f32>()?;
+
+            let stride = match shape[..] {
+                [1, _, stride] if stride >= 5 => stride as usize,
+                _ => 0,
+            };
+            if stride == 0 {
+                return Err(RedactError::InvalidOutput(shape));
+            }
+
+            // The image was stretched to the model input, so normalizing by the input size
+            // maps straight back to the original image.
+            let scale = 1.0 / size as f32;
+            Ok(values
+                .chunks_exact(stride)
+                .filter(|row| row[4] >= self.threshold)
+                .map(|row| Region {
+                    min: vec2(row[0], row[1]) * scale,
+                    max: vec2(row[2], row[3]) * scale,
+                })
+                .collect())
+        }
+    }
+}
+
+#[cfg(feature = "onnx")]
+pub use onnx::OnnxDetector;
+
+/// Load an ONNX detector, see [`OnnxDetector`] for the expected model format.
+pub fn load_detector(
+    path: &Path,
+    input_size: u32,
+    threshold: f32,
+) -> Result<Box<dyn RegionDetector>, RedactError> {
+    #[cfg(feature = "onnx")]
+    {
+        Ok(Box::new(OnnxDetector::load(path, input_size, threshold)?))
+    }
+    #[cfg(not(feature = "onnx"))]
+    {
+        let _ = (path, input_size, threshold);
+        Err(RedactError::Unsupported)
+    }
+}
+
+/// Blurs detected regions of images as they are loaded.
+///
+/// Images are loaded many times during training, so detections are cached per image.
+pub struct Redactor {
+    detector: Box<dyn RegionDetector>,
+    cache: Mutex<HashMap<PathBuf, Arc<Vec<Region>>>>,
+}
+
+impl Redactor {
+    pub fn new(detector: Box<dyn RegionDetector>) -> Self {
+        Self {
+            detector,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Regions detected in the image at `path`, detecting them if this image wasn't seen before.
+    pub fn regions(
+        &self,
+        path: &Path,
+        image: &DynamicImage,
+    ) -> Result<Arc<Vec<Region>>, RedactError> {
+        let cached = self
+            .cache
+            .lock()
+            .expect("Redaction cache poisoned")
+            .get(path)
+            .cloned();
+        if let Some(regions) = cached {
+            return Ok(regions);
+        }
+
+        let regions = Arc::new(self.detector.detect(image)?);
+        if !regions.is_empty() {
+            log::info!("Blurring {} regions in {path:?}", regions.len());
+        }
+        self.cache
+            .lock()
+            .expect("Redaction cache poisoned")
+            .insert(path.to_path_buf(), regions.clone());
+        Ok(regions)
+    }
+
+    pub fn redact(&self, path: &Path, image: DynamicImage) -> Result<DynamicImage, RedactError> {
+        let regions = self.regions(path, &image)?;
+        Ok(blur_regions(image, &regions))
+    }
+
+    /// Regions detected in the image at `path`, if it was loaded before.
+    pub fn found_regions(&self, path: &Path) -> Option<Arc<Vec<Region>>> {
+        self.cache
+            .lock()
+            .expect("Redaction cache poisoned")
+            .get(path)
+            .cloned()
+    }
+}
+
+// The average base color of the selected splats, weighed by their opacity, if any are selected.
+async fn mean_color<B: Backend>(
+    splats: &Splats<B>,
+    selection: &Tensor<B, 1, Bool>,
+) -> Option<Vec3> {
+    let n = splats.num_splats() as usize;
+    let weights = selection.clone().float() * splats.opacities();
+    let dc = splats
+        .sh_coeffs
+        .val()
+        .slice([0..n, 0..1, 0..3])
+        .reshape([n, 3]);
+    let sum = (dc * weights.clone().reshape([n, 1]))
+        .sum_dim(0)
+        .reshape([3]);
+    let values = Tensor::cat(vec![sum, weights.sum()], 0)
+        .into_data_async()
+        .await
+        .into_vec::<f32>()
+        .expect("Wrong type");
+    (values[3] > 0.0).then(|| sh_to_rgb(Vec3::from_slice(&values[..3]) / values[3]))
+}
+
+/// Flatten the splats seen in `regions` of a view by `camera`, with an image of `size`, to the
+/// average color of the splats of each region. This looks like a strong blur, and leaves no
+/// view dependent color to bring back what was blurred.
+///
+/// Splats are found by where their center projects to, so splats behind a region are flattened
+/// along with it.
+pub async fn scrub_regions<B: Backend>(
+    mut splats: Splats<B>,
+    camera: &Camera,
+    size: UVec2,
+    regions: &[Region],
+) -> Splats<B> {
+    for region in regions {
+        let shape = SelectionShape::Rect {
+            min: region.min * size.as_vec2(),
+            max: region.max * size.as_vec2(),
+        };
+        let selection = select_splats(&splats, camera, size, &shape);
+        if let Some(color) = mean_color(&splats, &selection).await {
+            splats = splats.recolor(&selection, color, 1.0);
+        }
+    }
+    splats
+}
+
+/// Flatten the splats seen in the blurred regions of all `views`, see [`scrub_regions`]. Training
+/// images can be blurred and still leave details in the splats, eg. from views the detector
+/// missed.
+///
+/// Only the regions of images that were loaded are known, which after training is all of them.
+pub async fn scrub_splats<B: Backend>(mut splats: Splats<B>, views: &[SceneView]) -> Splats<B> {
+    for view in views {
+        let Some(regions) = view
+            .image
+            .redactor()
+            .and_then(|redactor| redactor.found_regions(&view.image.path))
+        else {
+            continue;
+        };
+        let size = view.image.dimensions();
+        splats = scrub_regions(splats, &view.camera, size, &regions).await;
+    }
+    splats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn blurs_only_inside_regions() {
+        let checker = RgbImage::from_fn(64, 64, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        });
+        let region = Region {
+            min: Vec2::new(0.25, 0.25),
+            max: Vec2::new(0.75, 0.5),
+        };
+        let blurred = blur_regions(checker.clone().into(), &[region]).into_rgb8();
+
+        for (x, y, pixel) in blurred.enumerate_pixels() {
+            let inside = (16..48).contains(&x) && (16..32).contains(&y);
+            if !inside {
+                assert_eq!(pixel, checker.get_pixel(x, y));
+            } else if (20..44).contains(&x) && (20..28).contains(&y) {
+                // Away from the edges of the region, a checkerboard blurs to a flat gray.
+                assert!((64..192).contains(&pixel[0]), "{x}, {y}: {pixel:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn regions_are_clamped_to_image() {
+        let region = Region {
+            min: Vec2::new(-0.5, 0.9),
+            max: Vec2::new(0.5, 1.5),
+        };
+        assert_eq!(
+            region.pixel_bounds(UVec2::new(100, 10)),
+            Some((UVec2::new(0, 9), UVec2::new(50, 10)))
+        );
+        let outside = Region {
+            min: Vec2::new(1.2, 0.0),
+            max: Vec2::new(1.5, 1.0),
+        };
+        assert_eq!(outside.pixel_bounds(UVec2::new(100, 10)), None);
+    }
+
+    #[tokio::test]
+    async fn scrubs_splats_in_regions() {
+        use brush_render::{MainBackend, sh::rgb_to_sh};
+        use burn::backend::wgpu::WgpuDevice;
+
+        let device = WgpuDevice::DefaultDevice;
+        let camera = Camera::new(
+            Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            std::f64::consts::FRAC_PI_2,
+            std::f64::consts::FRAC_PI_2,
+            glam::vec2(0.5, 0.5),
+        );
+        // Two splats in the middle of the view, and one at the side.
+        let means = [
+            Vec3::new(-0.2, 0.0, 2.0),
+            Vec3::new(0.2, 0.0, 2.0),
+            Vec3::new(1.5, 0.0, 2.0),
+        ];
+        let colors = [Vec3::X, Vec3::Z, Vec3::Y];
+        let sh_coeffs: Vec<f32> = colors
+            .iter()
+            .flat_map(|&color| rgb_to_sh(color).to_array())
+            .collect();
+        let splats = Splats::<MainBackend>::from_raw(
+            &means,
+            None,
+            None,
+            Some(&sh_coeffs),
+            Some(&[0.0; 3]),
+            &device,
+        );
+        let region = Region {
+            min: Vec2::splat(0.3),
+            max: Vec2::splat(0.7),
+        };
+
+        let scrubbed = scrub_regions(splats, &camera, UVec2::splat(100), &[region]).await;
+        let sh = scrubbed
+            .sh_coeffs
+            .val()
+            .into_data()
+            .into_vec::<f32>()
+            .expect("Wrong type");
+        let colors: Vec<_> = sh
+            .chunks_exact(3)
+            .map(|c| sh_to_rgb(Vec3::from_slice(c)))
+            .collect();
+        let purple = Vec3::new(0.5, 0.0, 0.5);
+        assert!(colors[0].distance(purple) < 1e-4, "{:?}", colors[0]);
+        assert!(colors[1].distance(purple) < 1e-4, "{:?}", colors[1]);
+        assert!(colors[2].distance(Vec3::Y) < 1e-4, "{:?}", colors[2]);
+    }
+}
@@ -0,0 +1,160 @@
+// How well the cameras of a reconstruction explain the points it found: each point is projected
+// back into the images it was seen in, and compared to where it was seen.
+use std::collections::HashMap;
+
+use colmap_reader::{Camera, Image, Point3D};
+use glam::Vec2;
+
+/// Reprojection residuals in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResidualStats {
+    pub mean: f32,
+    pub median: f32,
+    pub p90: f32,
+}
+
+impl ResidualStats {
+    fn from_residuals(mut residuals: Vec<f32>) -> Option<Self> {
+        if residuals.is_empty() {
+            return None;
+        }
+        residuals.sort_by(f32::total_cmp);
+        let at = |fraction: f32| residuals[((residuals.len() - 1) as f32 * fraction) as usize];
+        Some(Self {
+            mean: residuals.iter().sum::<f32>() / residuals.len() as f32,
+            median: at(0.5),
+            p90: at(0.9),
+        })
+    }
+}
+
+/// Reprojection residuals of the images taken with one camera of a COLMAP reconstruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraResiduals {
+    pub camera_id: i32,
+    /// COLMAP name of the camera model, eg. `OPENCV`.
+    pub model: &'static str,
+    pub num_images: usize,
+    pub num_observations: usize,
+    /// With the lens distortion of the camera model. None if the model isn't supported.
+    pub residuals: Option<ResidualStats>,
+    /// Without any lens distortion, the way Brush sees the camera.
+    pub pinhole: ResidualStats,
+}
+
+/// Project the points of a reconstruction into the images they were seen in, and gather the
+/// residuals per camera.
+pub fn camera_residuals(
+    cameras: &HashMap<i32, Camera>,
+    images: &HashMap<i32, Image>,
+    points: &HashMap<i64, Point3D>,
+) -> Vec<CameraResiduals> {
+    let mut image_ids: Vec<_> = images.keys().copied().collect();
+    image_ids.sort_unstable();
+
+    let mut per_camera: HashMap<i32, (usize, Vec<f32>, Vec<f32>)> = HashMap::new();
+    for image in image_ids.iter().map(|id| &images[id]) {
+        let Some(camera) = cameras.get(&image.camera_id) else {
+            continue;
+        };
+        let (fx, fy) = camera.focal();
+        let focal = Vec2::new(fx as f32, fy as f32);
+        let center = camera.principal_point();
+
+        let (num_images, residuals, pinhole) = per_camera.entry(image.camera_id).or_default();
+        *num_images += 1;
+
+        for (&seen, id) in image.xys.iter().zip(&image.point3d_ids) {
+            let Some(point) = points.get(id) else {
+                continue;
+            };
+            let local = image.quat * point.xyz + image.tvec;
+            if local.z <= f32::EPSILON {
+                continue;
+            }
+            let plane = local.truncate() / local.z;
+            pinhole.push((plane * focal + center).distance(seen));
+            if let Some(distorted) = camera.distort(plane) {
+                residuals.push((distorted * focal + center).distance(seen));
+            }
+        }
+    }
+
+    let mut camera_ids: Vec<_> = per_camera.keys().copied().collect();
+    camera_ids.sort_unstable();
+    camera_ids
+        .into_iter()
+        .filter_map(|id| {
+            let (num_images, residuals, pinhole) = per_camera.remove(&id)?;
+            Some(CameraResiduals {
+                camera_id: id,
+                model: cameras[&id].model.name(),
+                num_images,
+                num_observations: pinhole.len(),
+                residuals: ResidualStats::from_residuals(residuals),
+                pinhole: ResidualStats::from_residuals(pinhole)?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use colmap_reader::CameraModel;
+    use glam::{Quat, Vec3};
+
+    #[test]
+    fn residuals_of_distorted_camera() {
+        let camera = Camera {
+            id: 1,
+            model: CameraModel::SimpleRadial,
+            width: 200,
+            height: 100,
+            params: vec![100.0, 100.0, 50.0, 0.1],
+        };
+        let points: HashMap<_, _> = [
+            (7, Vec3::new(1.0, 0.5, 2.0)),
+            (8, Vec3::new(-1.0, 0.0, 4.0)),
+        ]
+        .into_iter()
+        .map(|(id, xyz)| {
+            let point = Point3D {
+                xyz,
+                rgb: [0; 3],
+                error: 0.0,
+                image_ids: vec![1],
+                point2d_idxs: vec![0],
+            };
+            (id, point)
+        })
+        .collect();
+        // Observations where the distorted camera would see the points, and one off by 3 pixels.
+        let seen = |xyz: Vec3, offset: f32| {
+            let plane = xyz.truncate() / xyz.z;
+            camera.distort(plane).expect("Supported model") * 100.0
+                + Vec2::new(100.0 + offset, 50.0)
+        };
+        let image = Image {
+            tvec: Vec3::ZERO,
+            quat: Quat::IDENTITY,
+            camera_id: 1,
+            name: "a.png".to_owned(),
+            xys: vec![seen(points[&7].xyz, 0.0), seen(points[&8].xyz, 3.0)],
+            point3d_ids: vec![7, 8],
+        };
+
+        let report = camera_residuals(
+            &HashMap::from([(1, camera)]),
+            &HashMap::from([(1, image)]),
+            &points,
+        );
+        assert_eq!(report.len(), 1);
+        let stats = report[0].residuals.expect("Supported model");
+        assert!((stats.mean - 1.5).abs() < 1e-3, "{stats:?}");
+        assert!(stats.median.abs() < 1e-3, "{stats:?}");
+        // The distortion shows up as extra error when left out.
+        assert!(report[0].pinhole.mean > stats.mean);
+        assert_eq!(report[0].num_observations, 2);
+    }
+}
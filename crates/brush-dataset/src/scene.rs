@@ -1,4 +1,15 @@
-use brush_render::{bounding_box::BoundingBox, camera::Camera};
+use crate::color::{linear_to_srgb, srgb_to_linear};
+use crate::depth_map::{DepthMap, DepthMapError};
+use crate::exif::ExifCamera;
+use crate::feature_map::{FeatureMap, FeatureMapError};
+#[cfg(not(target_family = "wasm"))]
+use crate::image_cache::{DiskImageCache, PYRAMID_LEVELS};
+use crate::redact::Redactor;
+use crate::sky_mask::SkyMasker;
+use brush_render::{
+    bounding_box::BoundingBox,
+    camera::{Camera, focal_to_fov},
+};
 use brush_vfs::BrushVfs;
 use burn::{
     prelude::Backend,
@@ -6,6 +17,7 @@ use burn::{
 };
 use glam::{Affine3A, Vec3, vec3};
 use image::{ColorType, DynamicImage, ImageDecoder, ImageReader};
+use rand::Rng;
 use std::{
     io::Cursor,
     path::{Path, PathBuf},
@@ -25,9 +37,27 @@ pub struct LoadImage {
     pub vfs: Arc<BrushVfs>,
     pub path: PathBuf,
     pub mask_path: Option<PathBuf>,
+    /// A .npy file of the features of this view, see [`FeatureMap`].
+    pub feature_path: Option<PathBuf>,
+    /// A depth map of this view, and the confidence map of the depth if any, see [`DepthMap`].
+    pub depth_path: Option<(PathBuf, Option<PathBuf>)>,
+    /// When the image was taken, for videos of dynamic scenes. Normalized to 0..1 over the
+    /// dataset once it's loaded.
+    pub time: Option<f32>,
+    // Scales the values of 16-bit depth maps to the units of the scene.
+    depth_unit_scale: f32,
+    // Scale of the scene relative to the depth maps, when the scene is transformed.
+    depth_scale: f32,
     color: image::ColorType,
     size: glam::UVec2,
     max_resolution: u32,
+    downscale: Option<u32>,
+    background: Option<Vec3>,
+    hdr: bool,
+    redactor: Option<Arc<Redactor>>,
+    sky_masker: Option<Arc<SkyMasker>>,
+    #[cfg(not(target_family = "wasm"))]
+    disk_cache: Option<Arc<DiskImageCache>>,
 }
 
 /// Gets the dimensions of an image from an [`AsyncRead`] source
@@ -42,7 +72,8 @@ where
 
     let mut n = 0;
     loop {
-        let read = reader.read_exact(&mut temp_buf[n..]).await?;
+        // Nb: Not read_exact, images can be smaller than the buffer.
+        let read = reader.read(&mut temp_buf[n..]).await?;
 
         if read == 0 {
             return Err(std::io::Error::new(
@@ -61,7 +92,9 @@ where
             return Ok((decoder.dimensions().into(), decoder.color_type()));
         }
         // Try reading up to double the size.
-        temp_buf.resize(temp_buf.len() * 2, 0);
+        if n == temp_buf.len() {
+            temp_buf.resize(temp_buf.len() * 2, 0);
+        }
     }
 }
 
@@ -79,26 +112,167 @@ impl LoadImage {
             vfs,
             path: path.to_path_buf(),
             mask_path,
+            feature_path: None,
+            depth_path: None,
+            time: None,
+            depth_unit_scale: 1.0,
+            depth_scale: 1.0,
             max_resolution,
+            downscale: None,
             size: data.0,
             color: data.1,
+            background: None,
+            hdr: false,
+            redactor: None,
+            sky_masker: None,
+            #[cfg(not(target_family = "wasm"))]
+            disk_cache: None,
         })
     }
 
+    /// Composite transparent images onto a solid background color when loading them.
+    pub fn with_background(mut self, color: Vec3) -> Self {
+        self.background = Some(color);
+        self
+    }
+
+    /// Shrink the image by exactly `factor` in both directions, before capping it at the max
+    /// resolution, like the downscaled images of benchmarks.
+    pub fn with_downscale(mut self, factor: u32) -> Self {
+        self.downscale = Some(factor).filter(|&f| f > 1);
+        self
+    }
+
+    /// Load the image as linear color in f32, keeping the range of HDR images (eg. EXR) instead of
+    /// converting them to 8 bit sRGB.
+    pub fn with_hdr(mut self) -> Self {
+        self.hdr = true;
+        self
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        self.hdr
+    }
+
+    /// Keep resized copies of the image in `cache`, to skip decoding it the next time.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn with_disk_cache(mut self, cache: Arc<DiskImageCache>) -> Self {
+        self.disk_cache = Some(cache);
+        self
+    }
+
+    /// Blur detected regions (eg. faces) of images when loading them.
+    pub fn with_redactor(mut self, redactor: Arc<Redactor>) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// What blurs regions of this image as it's loaded, if anything.
+    pub fn redactor(&self) -> Option<&Arc<Redactor>> {
+        self.redactor.as_ref()
+    }
+
+    /// Make the sky (or other background) of images transparent when loading them.
+    pub fn with_sky_masker(mut self, masker: Arc<SkyMasker>) -> Self {
+        self.sky_masker = Some(masker);
+        self
+    }
+
+    /// Load the feature map at `path` along with the image, for splats to learn to render.
+    pub fn with_feature_map(mut self, path: PathBuf) -> Self {
+        self.feature_path = Some(path);
+        self
+    }
+
+    /// The feature map of this view, if it has one.
+    pub async fn load_feature_map(&self) -> Result<Option<FeatureMap>, FeatureMapError> {
+        let Some(path) = &self.feature_path else {
+            return Ok(None);
+        };
+        let bytes = self.read_bytes(path).await?;
+        Ok(Some(FeatureMap::from_npy(&bytes)?))
+    }
+
+    /// Load the depth map at `path` along with the image, and its confidence map if any, for the
+    /// depth of the splats to match. 16-bit depth maps store depth in units of `unit_scale`.
+    pub fn with_depth_map(
+        mut self,
+        path: PathBuf,
+        confidence_path: Option<PathBuf>,
+        unit_scale: f32,
+    ) -> Self {
+        self.depth_path = Some((path, confidence_path));
+        self.depth_unit_scale = unit_scale;
+        self
+    }
+
+    /// Set when the image was taken, see [`Self::time`].
+    pub fn with_time(mut self, time: f32) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    /// Scale the loaded depth maps along with the scene.
+    pub fn with_depth_scale(mut self, scale: f32) -> Self {
+        self.depth_scale *= scale;
+        self
+    }
+
+    /// The depth map of this view, if it has one.
+    pub async fn load_depth_map(&self) -> Result<Option<DepthMap>, DepthMapError> {
+        let Some((path, confidence_path)) = &self.depth_path else {
+            return Ok(None);
+        };
+        let bytes = self.read_bytes(path).await?;
+        let confidence = match confidence_path {
+            Some(path) => Some(self.read_bytes(path).await?),
+            None => None,
+        };
+        let map = DepthMap::decode(&bytes, confidence.as_deref(), self.depth_unit_scale)?;
+        Ok(Some(map.scaled(self.depth_scale)))
+    }
+
+    /// Field of view of the camera that took the image, from its EXIF metadata.
+    pub async fn exif_fov(&self) -> Option<(f64, f64)> {
+        let reader = self.vfs.reader_at_path(&self.path).await.ok()?;
+        // EXIF metadata is in one of the first segments of a JPEG, each at most 64kB.
+        let mut data = vec![];
+        reader.take(128 * 1024).read_to_end(&mut data).await.ok()?;
+        let focal = ExifCamera::from_image(&data)?.focal_in_pixels(self.size.x, self.size.y)?;
+        Some((
+            focal_to_fov(focal, self.size.x),
+            focal_to_fov(focal, self.size.y),
+        ))
+    }
+
     pub fn has_alpha(&self) -> bool {
-        self.color.has_alpha() || self.is_masked()
+        (self.color.has_alpha() && self.background.is_none())
+            || self.is_masked()
+            || self.sky_masker.is_some()
+    }
+
+    // Size of the image after the exact downscale, before capping it at the max resolution.
+    fn downscaled_size(&self) -> glam::UVec2 {
+        match self.downscale {
+            Some(factor) => (self.size.as_dvec2() / f64::from(factor))
+                .round()
+                .as_uvec2()
+                .max(glam::UVec2::ONE),
+            None => self.size,
+        }
     }
 
     pub fn dimensions(&self) -> glam::UVec2 {
-        if self.size.x <= self.max_resolution && self.size.y <= self.max_resolution {
-            self.size
+        let size = self.downscaled_size();
+        if size.x <= self.max_resolution && size.y <= self.max_resolution {
+            size
         } else {
             // Take from image crate, just to be sure logic here matches exactly.
-            let wratio = f64::from(self.max_resolution) / f64::from(self.size.x);
-            let hratio = f64::from(self.max_resolution) / f64::from(self.size.y);
+            let wratio = f64::from(self.max_resolution) / f64::from(size.x);
+            let hratio = f64::from(self.max_resolution) / f64::from(size.y);
             let ratio = f64::min(wratio, hratio);
-            let nw = u64::max((f64::from(self.size.x) * ratio).round() as u64, 1);
-            let nh = u64::max((f64::from(self.size.y) * ratio).round() as u64, 1);
+            let nw = u64::max((f64::from(size.x) * ratio).round() as u64, 1);
+            let nh = u64::max((f64::from(size.y) * ratio).round() as u64, 1);
             glam::uvec2(nw as u32, nh as u32)
         }
     }
@@ -112,47 +286,188 @@ impl LoadImage {
     }
 
     pub async fn load(&self) -> image::ImageResult<DynamicImage> {
-        let mut img_bytes = vec![];
+        self.load_level(0).await
+    }
+
+    /// Load the image with its resolution halved `level` times, eg. for coarse-to-fine training.
+    pub async fn load_level(&self, level: u32) -> image::ImageResult<DynamicImage> {
+        let img_bytes = self.read_bytes(&self.path).await?;
+        let mask_bytes = match &self.mask_path {
+            Some(mask_path) => Some(self.read_bytes(mask_path).await?),
+            None => None,
+        };
+
+        // Redacted and sky masked images depend on the models too, don't bother caching those.
+        // HDR images don't fit in the PNGs of the cache.
+        #[cfg(not(target_family = "wasm"))]
+        let cached = self
+            .disk_cache
+            .as_ref()
+            .filter(|_| self.redactor.is_none() && self.sky_masker.is_none() && !self.hdr)
+            .map(|cache| {
+                let sources: Vec<&[u8]> = std::iter::once(img_bytes.as_slice())
+                    .chain(mask_bytes.as_deref())
+                    .collect();
+                let background = self.background.map(|c| c.to_array().map(f32::to_bits));
+                (
+                    cache,
+                    DiskImageCache::key(
+                        &sources,
+                        (self.max_resolution, self.downscale, background),
+                    ),
+                )
+            });
+        #[cfg(not(target_family = "wasm"))]
+        if let Some((cache, key)) = cached.filter(|_| level < PYRAMID_LEVELS) {
+            if let Some(img) = cache.read(key, level).await {
+                return Ok(img);
+            }
+        }
+
+        let mut img = self.decode(&img_bytes, mask_bytes.as_deref())?;
+        if let Some(masker) = &self.sky_masker {
+            img = masker
+                .mask(&self.path, img)
+                .await
+                .map_err(|e| image::ImageError::IoError(std::io::Error::other(e)))?;
+        }
+        let img = pyramid_level(&img, level);
+        #[cfg(not(target_family = "wasm"))]
+        if let Some((cache, key)) = cached.filter(|_| level < PYRAMID_LEVELS) {
+            cache.write(key, level, img.clone()).await;
+        }
+        Ok(img)
+    }
+
+    async fn read_bytes(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        let mut bytes = vec![];
         self.vfs
-            .reader_at_path(&self.path)
+            .reader_at_path(path)
             .await?
-            .read_to_end(&mut img_bytes)
+            .read_to_end(&mut bytes)
             .await?;
-        let mut img = image::load_from_memory(&img_bytes)?;
+        Ok(bytes)
+    }
+
+    // Decode the image, and process it the way this view wants it.
+    fn decode(
+        &self,
+        img_bytes: &[u8],
+        mask_bytes: Option<&[u8]>,
+    ) -> image::ImageResult<DynamicImage> {
+        let mut img = image::load_from_memory(img_bytes)?;
+        let is_float = matches!(
+            img,
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+        );
+
+        if self.hdr {
+            img = to_linear(img, is_float);
+        } else if is_float {
+            // Floating point images (eg. exr) are stored in linear space. Convert them
+            // to sRGB to match all other images, keeping whether they have alpha.
+            let has_alpha = img.color().has_alpha();
+            let mut rgba = img.into_rgba32f();
+            for pixel in rgba.pixels_mut() {
+                for c in &mut pixel.0[0..3] {
+                    *c = if c.is_finite() {
+                        linear_to_srgb(c.clamp(0.0, 1.0))
+                    } else {
+                        0.0
+                    };
+                }
+            }
+            let srgb = DynamicImage::ImageRgba32F(rgba);
+            img = if has_alpha {
+                DynamicImage::ImageRgba8(srgb.into_rgba8())
+            } else {
+                DynamicImage::ImageRgb8(srgb.into_rgb8())
+            };
+        }
+
+        if let Some(background) = self.background {
+            if img.color().has_alpha() && self.hdr {
+                let mut rgb = image::Rgb32FImage::new(img.width(), img.height());
+                for (out, pixel) in rgb.pixels_mut().zip(img.to_rgba32f().pixels()) {
+                    let alpha = pixel[3];
+                    for c in 0..3 {
+                        out[c] = pixel[c] * alpha + background[c] * (1.0 - alpha);
+                    }
+                }
+                img = rgb.into();
+            } else if img.color().has_alpha() {
+                let mut rgb = image::RgbImage::new(img.width(), img.height());
+                for (out, pixel) in rgb.pixels_mut().zip(img.to_rgba8().pixels()) {
+                    let alpha = pixel[3] as f32 / 255.0;
+                    for c in 0..3 {
+                        let value = pixel[c] as f32 / 255.0 * alpha + background[c] * (1.0 - alpha);
+                        out[c] = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+                    }
+                }
+                img = rgb.into();
+            }
+        }
+
+        if let Some(redactor) = &self.redactor {
+            img = redactor
+                .redact(&self.path, img)
+                .map_err(|e| image::ImageError::IoError(std::io::Error::other(e)))?;
+        }
 
         // Copy over mask.
         // TODO: Interleave this work better & speed things up here.
-        if let Some(mask_path) = &self.mask_path {
+        if let Some(mask_bytes) = mask_bytes {
+            let mask_img = image::load_from_memory(mask_bytes)?;
             // Add in alpha channel if needed to the image to copy the mask into.
-            let mut masked_img = img.into_rgba8();
-            let mut mask_bytes = vec![];
-            self.vfs
-                .reader_at_path(mask_path)
-                .await?
-                .read_to_end(&mut mask_bytes)
-                .await?;
-            let mask_img = image::load_from_memory(&mask_bytes)?;
-            if mask_img.color().has_alpha() {
-                let mask_img = mask_img.into_rgba8();
-                for (pixel, mask_pixel) in masked_img.pixels_mut().zip(mask_img.pixels()) {
-                    pixel[3] = mask_pixel[3];
+            if self.hdr {
+                let mut masked_img = img.into_rgba32f();
+                let mask = if mask_img.color().has_alpha() {
+                    mask_img
+                        .to_rgba32f()
+                        .pixels()
+                        .map(|p| p[3])
+                        .collect::<Vec<_>>()
+                } else {
+                    mask_img.to_rgb32f().pixels().map(|p| p[0]).collect()
+                };
+                for (pixel, alpha) in masked_img.pixels_mut().zip(mask) {
+                    pixel[3] = alpha;
                 }
+                img = masked_img.into();
             } else {
-                let mask_img = mask_img.into_rgb8();
-                for (pixel, mask_pixel) in masked_img.pixels_mut().zip(mask_img.pixels()) {
-                    pixel[3] = mask_pixel[0];
+                let mut masked_img = img.into_rgba8();
+                if mask_img.color().has_alpha() {
+                    let mask_img = mask_img.into_rgba8();
+                    for (pixel, mask_pixel) in masked_img.pixels_mut().zip(mask_img.pixels()) {
+                        pixel[3] = mask_pixel[3];
+                    }
+                } else {
+                    let mask_img = mask_img.into_rgb8();
+                    for (pixel, mask_pixel) in masked_img.pixels_mut().zip(mask_img.pixels()) {
+                        pixel[3] = mask_pixel[0];
+                    }
                 }
+                img = masked_img.into();
             }
-            img = masked_img.into();
         }
+        Ok(self.limit_resolution(img))
+    }
+
+    fn limit_resolution(&self, img: DynamicImage) -> DynamicImage {
+        let img = if self.downscale.is_some() {
+            let size = self.downscaled_size();
+            img.resize_exact(size.x, size.y, image::imageops::FilterType::Triangle)
+        } else {
+            img
+        };
         if img.width() <= self.max_resolution && img.height() <= self.max_resolution {
-            return Ok(img);
+            return img;
         }
-        Ok(img.resize(
+        img.resize(
             self.max_resolution,
             self.max_resolution,
             image::imageops::FilterType::Triangle,
-        ))
+        )
     }
 
     pub fn is_masked(&self) -> bool {
@@ -165,6 +480,70 @@ impl LoadImage {
     }
 }
 
+/// Downscale an image by a factor of two `level` times.
+pub fn pyramid_level(image: &DynamicImage, level: u32) -> DynamicImage {
+    if level == 0 {
+        return image.clone();
+    }
+    let width = (image.width() >> level).max(1);
+    let height = (image.height() >> level).max(1);
+    image.resize_exact(width, height, image::imageops::FilterType::Triangle)
+}
+
+/// The pixels of an image of `size` between the normalized image coordinates `min` and `max`, as
+/// the corner and size of the crop. Crops cover at least one pixel.
+pub fn crop_pixels(
+    size: glam::UVec2,
+    min: glam::Vec2,
+    max: glam::Vec2,
+) -> (glam::UVec2, glam::UVec2) {
+    let size_f = size.as_vec2();
+    let corner = (min * size_f).round().as_uvec2().min(size - 1);
+    let end = (max * size_f).round().as_uvec2().clamp(corner + 1, size);
+    (corner, end - corner)
+}
+
+// Crop an [H, W, C] array of values with `width` columns.
+pub(crate) fn crop_values<T: Copy>(
+    data: &[T],
+    width: u32,
+    channels: usize,
+    corner: glam::UVec2,
+    crop_size: glam::UVec2,
+) -> Vec<T> {
+    let row_len = width as usize * channels;
+    (corner.y..corner.y + crop_size.y)
+        .flat_map(|y| {
+            let start = y as usize * row_len + corner.x as usize * channels;
+            &data[start..start + crop_size.x as usize * channels]
+        })
+        .copied()
+        .collect()
+}
+
+// Convert any image to linear color in f32. Float images already are, other images are sRGB.
+fn to_linear(img: DynamicImage, is_float: bool) -> DynamicImage {
+    let has_alpha = img.color().has_alpha();
+    let mut rgba = img.into_rgba32f();
+    for pixel in rgba.pixels_mut() {
+        for c in &mut pixel.0[0..3] {
+            *c = if !c.is_finite() {
+                0.0
+            } else if is_float {
+                c.max(0.0)
+            } else {
+                srgb_to_linear(*c)
+            };
+        }
+    }
+    let img = DynamicImage::ImageRgba32F(rgba);
+    if has_alpha {
+        img
+    } else {
+        DynamicImage::ImageRgb32F(img.into_rgb32f())
+    }
+}
+
 #[derive(Clone)]
 pub struct SceneView {
     pub image: LoadImage,
@@ -238,6 +617,58 @@ impl Scene {
             .map(|(index, _)| index) // We return the index instead of the camera
     }
 
+    /// Sample random points in the volume seen by the cameras of this scene.
+    ///
+    /// Points are sampled along random rays of random views, between `near` and `far`. Points
+    /// that are visible in fewer than `min_views` views are rejected where possible, so points
+    /// end up in the region the cameras look at, rather than the space around a single camera.
+    pub fn sample_points_in_frustums(
+        &self,
+        count: usize,
+        near: f32,
+        far: f32,
+        min_views: usize,
+        rng: &mut impl Rng,
+    ) -> Vec<Vec3> {
+        if self.views.is_empty() {
+            return vec![];
+        }
+
+        // Give up on rejecting points after this many attempts per point.
+        const MAX_ATTEMPTS: usize = 64;
+        let min_views = min_views.min(self.views.len());
+        // Empty ranges, like the ones of a scene without any extent, sample at a single depth.
+        let near = near.max(0.0);
+        let far = far.max(near);
+
+        (0..count)
+            .map(|_| {
+                let mut point = Vec3::ZERO;
+                for _ in 0..MAX_ATTEMPTS {
+                    let view = &self.views[rng.random_range(0..self.views.len())];
+                    let uv = glam::vec2(rng.random(), rng.random());
+                    point = view.camera.uv_to_world(uv, rng.random_range(near..=far));
+
+                    let seen_by = self
+                        .views
+                        .iter()
+                        .filter(|v| {
+                            v.camera.world_to_uv(point).is_some_and(|uv| {
+                                uv.cmpge(glam::Vec2::ZERO).all() && uv.cmple(glam::Vec2::ONE).all()
+                            })
+                        })
+                        .take(min_views)
+                        .count();
+
+                    if seen_by >= min_views {
+                        break;
+                    }
+                }
+                point
+            })
+            .collect()
+    }
+
     pub fn estimate_extent(&self) -> Option<f32> {
         if self.views.len() < 5 {
             None
@@ -254,25 +685,36 @@ impl Scene {
 //
 // This assume the input image has un-premultiplied alpha, whereas the output has pre-multiplied alpha.
 pub fn view_to_sample_image(image: DynamicImage, alpha_is_mask: bool) -> DynamicImage {
-    if image.color().has_alpha() && !alpha_is_mask {
-        let mut rgba_bytes = image.to_rgba8();
-
-        // Assume image has un-multiplied alpha and convert it to pre-multiplied.
-        // Perform multiplication in byte space before converting to float.
-        for pixel in rgba_bytes.chunks_exact_mut(4) {
-            let r = pixel[0];
-            let g = pixel[1];
-            let b = pixel[2];
-            let a = pixel[3];
-
-            pixel[0] = ((r as u16 * a as u16 + 127) / 255) as u8;
-            pixel[1] = ((g as u16 * a as u16 + 127) / 255) as u8;
-            pixel[2] = ((b as u16 * a as u16 + 127) / 255) as u8;
-            pixel[3] = a;
+    match image {
+        // HDR images, which can't be multiplied in byte space.
+        DynamicImage::ImageRgba32F(mut rgba) if !alpha_is_mask => {
+            for pixel in rgba.pixels_mut() {
+                let a = pixel[3];
+                for c in &mut pixel.0[0..3] {
+                    *c *= a;
+                }
+            }
+            DynamicImage::ImageRgba32F(rgba)
         }
-        DynamicImage::ImageRgba8(rgba_bytes)
-    } else {
-        image
+        image if image.color().has_alpha() && !alpha_is_mask => {
+            let mut rgba_bytes = image.to_rgba8();
+
+            // Assume image has un-multiplied alpha and convert it to pre-multiplied.
+            // Perform multiplication in byte space before converting to float.
+            for pixel in rgba_bytes.chunks_exact_mut(4) {
+                let r = pixel[0];
+                let g = pixel[1];
+                let b = pixel[2];
+                let a = pixel[3];
+
+                pixel[0] = ((r as u16 * a as u16 + 127) / 255) as u8;
+                pixel[1] = ((g as u16 * a as u16 + 127) / 255) as u8;
+                pixel[2] = ((b as u16 * a as u16 + 127) / 255) as u8;
+                pixel[3] = a;
+            }
+            DynamicImage::ImageRgba8(rgba_bytes)
+        }
+        image => image,
     }
 }
 
@@ -290,7 +732,15 @@ pub fn sample_to_tensor<B: Backend>(sample: &DynamicImage, device: &B::Device) -
 pub struct SceneBatch<B: Backend> {
     pub img_tensor: Tensor<B, 3>,
     pub alpha_is_mask: bool,
+    /// [H, W, C] features of the view, if it has a feature map.
+    pub features: Option<Tensor<B, 3>>,
+    /// [H, W, 2] depth and confidence of the view, if it has a depth map.
+    pub depth: Option<Tensor<B, 3>>,
+    /// Time the view was taken from 0 to 1, for dynamic scenes.
+    pub time: Option<f32>,
     pub camera: Camera,
+    /// Index of the view in the scene this batch was sampled from.
+    pub view_index: usize,
 }
 
 impl<B: Backend> SceneBatch<B> {
@@ -298,3 +748,68 @@ impl<B: Backend> SceneBatch<B> {
         self.img_tensor.shape().dims[2] == 4
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brush_vfs::MemoryFile;
+    use image::{ImageFormat, RgbImage};
+    use rand::{SeedableRng, rngs::StdRng};
+
+    #[tokio::test]
+    async fn downscales_exactly_then_caps() {
+        let mut png = Cursor::new(vec![]);
+        DynamicImage::ImageRgb8(RgbImage::new(101, 50))
+            .write_to(&mut png, ImageFormat::Png)
+            .expect("Encode image");
+        let vfs = Arc::new(BrushVfs::from_memory_files(vec![MemoryFile {
+            name: "view.png".to_owned(),
+            data: png.into_inner().into(),
+        }]));
+        let image = |max_resolution| {
+            LoadImage::new(vfs.clone(), Path::new("view.png"), None, max_resolution)
+        };
+
+        let downscaled = image(1920).await.expect("Read image").with_downscale(4);
+        assert_eq!(downscaled.dimensions(), glam::uvec2(25, 13));
+        let loaded = downscaled.load().await.expect("Load image");
+        assert_eq!((loaded.width(), loaded.height()), (25, 13));
+
+        let capped = image(20).await.expect("Read image").with_downscale(4);
+        assert_eq!(capped.dimensions(), glam::uvec2(20, 10));
+        let loaded = capped.load().await.expect("Load image");
+        assert_eq!((loaded.width(), loaded.height()), (20, 10));
+    }
+
+    #[tokio::test]
+    async fn samples_empty_depth_ranges() {
+        let mut png = Cursor::new(vec![]);
+        DynamicImage::ImageRgb8(RgbImage::new(4, 4))
+            .write_to(&mut png, ImageFormat::Png)
+            .expect("Encode image");
+        let vfs = Arc::new(BrushVfs::from_memory_files(vec![MemoryFile {
+            name: "view.png".to_owned(),
+            data: png.into_inner().into(),
+        }]));
+        let image = LoadImage::new(vfs, Path::new("view.png"), None, 1920)
+            .await
+            .expect("Read image");
+        let camera = Camera::new(
+            Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            0.5,
+            0.5,
+            glam::vec2(0.5, 0.5),
+        );
+        let scene = Scene::new(vec![SceneView { image, camera }]);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for (near, far) in [(2.0, 2.0), (3.0, 1.0)] {
+            let points = scene.sample_points_in_frustums(4, near, far, 1, &mut rng);
+            assert_eq!(points.len(), 4);
+            for point in points {
+                assert!((point.z - near).abs() < 1e-4, "{point}");
+            }
+        }
+    }
+}
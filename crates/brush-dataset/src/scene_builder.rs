@@ -0,0 +1,319 @@
+// Building datasets in code, eg. for synthetic scenes rendered by another program, without writing
+// a COLMAP or nerfstudio dataset to disk first.
+use std::{
+    collections::HashSet,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use brush_render::camera::{Camera, focal_to_fov, fov_to_focal};
+use brush_vfs::{BrushVfs, MemoryFile};
+use glam::{Mat4, Quat, Vec3};
+use image::{DynamicImage, ImageFormat};
+use thiserror::Error;
+
+use crate::{
+    Dataset,
+    scene::{LoadImage, SceneView},
+    scene_transform::CoordinateConvention,
+};
+
+/// Intrinsics of a pinhole camera, in pixels of an image of `width` x `height`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Intrinsics {
+    pub width: u32,
+    pub height: u32,
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+}
+
+impl Intrinsics {
+    /// Intrinsics of a camera with a horizontal field of view of `fov_x` radians, square pixels
+    /// and the principal point in the center.
+    pub fn from_fov(width: u32, height: u32, fov_x: f64) -> Self {
+        let focal = fov_to_focal(fov_x, width);
+        Self {
+            width,
+            height,
+            fx: focal,
+            fy: focal,
+            cx: width as f64 / 2.0,
+            cy: height as f64 / 2.0,
+        }
+    }
+
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.width == 0 || self.height == 0 {
+            Err("Image size must be larger than zero")
+        } else if !(self.fx > 0.0 && self.fy > 0.0) {
+            Err("Focal lengths must be positive")
+        } else if !(self.cx.is_finite() && self.cy.is_finite()) {
+            Err("Principal point must be finite")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A camera pose as a 4x4 matrix.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pose {
+    /// Transform from camera to world coordinates, as in nerfstudio datasets.
+    CameraToWorld(Mat4),
+    /// Transform from world to camera coordinates, as in COLMAP datasets.
+    WorldToCamera(Mat4),
+}
+
+/// Where the image of a view comes from.
+pub enum ImageSource {
+    /// An image file in memory, eg. a PNG or JPEG. The name needs an extension.
+    Encoded { name: String, data: Vec<u8> },
+    /// Decoded pixels, kept in memory as a PNG named `name`.
+    Pixels { name: String, image: DynamicImage },
+    /// A file in a VFS, eg. an image in a directory on disk.
+    File { vfs: Arc<BrushVfs>, path: PathBuf },
+}
+
+#[derive(Debug, Error)]
+pub enum SceneBuilderError {
+    #[error("Pose of view {0} isn't a rotation and translation.")]
+    InvalidPose(String),
+
+    #[error("Invalid intrinsics for view {0}: {1}")]
+    InvalidIntrinsics(String, &'static str),
+
+    #[error(
+        "Image of view {name} is {image_size:?} pixels, which doesn't match the aspect ratio of its intrinsics {intrinsics_size:?}."
+    )]
+    SizeMismatch {
+        name: String,
+        image_size: glam::UVec2,
+        intrinsics_size: glam::UVec2,
+    },
+
+    #[error("Image name {0} needs to be unique and have an extension.")]
+    InvalidName(String),
+
+    #[error("Failed to read image.")]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to encode image.")]
+    Image(#[from] image::ImageError),
+}
+
+// Position and rotation of a camera to world matrix, if it's a rigid transform.
+fn rigid_pose(camera_to_world: Mat4) -> Option<(Vec3, Quat)> {
+    if !camera_to_world.is_finite() {
+        return None;
+    }
+    let (_, rotation, translation) = camera_to_world.to_scale_rotation_translation();
+    // Scaled, sheared or mirrored matrices don't survive the round trip.
+    let rigid = Mat4::from_rotation_translation(rotation, translation);
+    rigid
+        .abs_diff_eq(camera_to_world, 1e-3)
+        .then_some((translation, rotation))
+}
+
+/// Builds a [`Dataset`] one view at a time, checking each view as it's added.
+///
+/// ```ignore
+/// let mut builder = SceneBuilder::new().with_convention(CoordinateConvention::OpenGl);
+/// for (pose, image) in renders {
+///     let intrinsics = Intrinsics::from_fov(image.width(), image.height(), 0.8);
+///     let name = format!("render_{}.png", builder.len());
+///     builder
+///         .add_view(Pose::CameraToWorld(pose), intrinsics, ImageSource::Pixels { name, image })
+///         .await?;
+/// }
+/// let dataset = builder.build();
+/// ```
+pub struct SceneBuilder {
+    convention: CoordinateConvention,
+    max_resolution: u32,
+    names: HashSet<String>,
+    train_views: Vec<SceneView>,
+    eval_views: Vec<SceneView>,
+}
+
+impl Default for SceneBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SceneBuilder {
+    pub fn new() -> Self {
+        Self {
+            convention: CoordinateConvention::Colmap,
+            max_resolution: 1920,
+            names: HashSet::new(),
+            train_views: vec![],
+            eval_views: vec![],
+        }
+    }
+
+    /// Axis convention of the cameras of the poses that are added, COLMAP (x right, y down, z
+    /// forward) by default.
+    pub fn with_convention(mut self, convention: CoordinateConvention) -> Self {
+        self.convention = convention;
+        self
+    }
+
+    /// Max resolution of images of views added after this, see
+    /// [`crate::config::LoadDataseConfig::max_resolution`].
+    pub fn with_max_resolution(mut self, max_resolution: u32) -> Self {
+        self.max_resolution = max_resolution;
+        self
+    }
+
+    /// Nr. of views added so far.
+    pub fn len(&self) -> usize {
+        self.train_views.len() + self.eval_views.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add a view to train on.
+    pub async fn add_view(
+        &mut self,
+        pose: Pose,
+        intrinsics: Intrinsics,
+        image: ImageSource,
+    ) -> Result<(), SceneBuilderError> {
+        let view = self.make_view(pose, intrinsics, image).await?;
+        self.train_views.push(view);
+        Ok(())
+    }
+
+    /// Add a view to evaluate on.
+    pub async fn add_eval_view(
+        &mut self,
+        pose: Pose,
+        intrinsics: Intrinsics,
+        image: ImageSource,
+    ) -> Result<(), SceneBuilderError> {
+        let view = self.make_view(pose, intrinsics, image).await?;
+        self.eval_views.push(view);
+        Ok(())
+    }
+
+    async fn make_view(
+        &mut self,
+        pose: Pose,
+        intrinsics: Intrinsics,
+        image: ImageSource,
+    ) -> Result<SceneView, SceneBuilderError> {
+        let (vfs, path) = match image {
+            ImageSource::Encoded { name, data } => {
+                let vfs = BrushVfs::from_memory_files(vec![MemoryFile {
+                    name: name.clone(),
+                    data: data.into(),
+                }]);
+                (Arc::new(vfs), PathBuf::from(name))
+            }
+            ImageSource::Pixels { name, image } => {
+                let name = Path::new(&name).with_extension("png");
+                let mut data = Cursor::new(vec![]);
+                image.write_to(&mut data, ImageFormat::Png)?;
+                let vfs = BrushVfs::from_memory_files(vec![MemoryFile {
+                    name: name.to_string_lossy().into_owned(),
+                    data: data.into_inner().into(),
+                }]);
+                (Arc::new(vfs), name)
+            }
+            ImageSource::File { vfs, path } => (vfs, path),
+        };
+        let name = path.to_string_lossy().into_owned();
+
+        // Views are told apart by the paths of their images, which the VFS compares
+        // case-insensitively.
+        let key = name.to_lowercase();
+        if path.extension().is_none() || self.names.contains(&key) {
+            return Err(SceneBuilderError::InvalidName(name));
+        }
+
+        let camera_to_world = match pose {
+            Pose::CameraToWorld(matrix) => matrix,
+            Pose::WorldToCamera(matrix) => matrix.inverse(),
+        };
+        let Some((position, rotation)) = rigid_pose(camera_to_world) else {
+            return Err(SceneBuilderError::InvalidPose(name));
+        };
+        intrinsics
+            .validate()
+            .map_err(|e| SceneBuilderError::InvalidIntrinsics(name.clone(), e))?;
+
+        let image = LoadImage::new(vfs, &path, None, self.max_resolution).await?;
+
+        // Images can be a resized copy of what the intrinsics describe, but not a crop.
+        let image_size = image.dimensions();
+        let intrinsics_size = glam::uvec2(intrinsics.width, intrinsics.height);
+        let image_aspect = image_size.x as f64 / image_size.y as f64;
+        let intrinsics_aspect = intrinsics_size.x as f64 / intrinsics_size.y as f64;
+        if (image_aspect / intrinsics_aspect - 1.0).abs() > 0.01 {
+            return Err(SceneBuilderError::SizeMismatch {
+                name,
+                image_size,
+                intrinsics_size,
+            });
+        }
+
+        let camera = Camera::new(
+            position,
+            self.convention.camera_to_brush(rotation),
+            focal_to_fov(intrinsics.fx, intrinsics.width),
+            focal_to_fov(intrinsics.fy, intrinsics.height),
+            glam::vec2(
+                (intrinsics.cx / intrinsics.width as f64) as f32,
+                (intrinsics.cy / intrinsics.height as f64) as f32,
+            ),
+        );
+        self.names.insert(key);
+        Ok(SceneView { image, camera })
+    }
+
+    /// The dataset of all views added.
+    pub fn build(self) -> Dataset {
+        Dataset::from_views(self.train_views, self.eval_views)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_rigid_poses() {
+        let rotation = Quat::from_rotation_y(0.7) * Quat::from_rotation_x(-0.3);
+        let translation = Vec3::new(1.0, -2.0, 3.0);
+        let (position, found) =
+            rigid_pose(Mat4::from_rotation_translation(rotation, translation)).expect("Rigid");
+        assert!(position.abs_diff_eq(translation, 1e-5));
+        assert!(found.angle_between(rotation) < 1e-4);
+
+        let scaled = Mat4::from_scale_rotation_translation(Vec3::splat(2.0), rotation, translation);
+        assert!(rigid_pose(scaled).is_none());
+        let mirrored = Mat4::from_scale(Vec3::new(1.0, 1.0, -1.0));
+        assert!(rigid_pose(mirrored).is_none());
+        assert!(rigid_pose(Mat4::NAN).is_none());
+    }
+
+    #[test]
+    fn intrinsics_from_fov() {
+        let intrinsics = Intrinsics::from_fov(640, 480, 1.0);
+        assert!(intrinsics.validate().is_ok());
+        assert!((focal_to_fov(intrinsics.fx, 640) - 1.0).abs() < 1e-9);
+        assert_eq!((intrinsics.cx, intrinsics.cy), (320.0, 240.0));
+
+        let zero_focal = Intrinsics {
+            fx: 0.0,
+            ..intrinsics
+        };
+        assert!(zero_focal.validate().is_err());
+    }
+}
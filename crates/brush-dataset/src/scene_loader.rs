@@ -1,20 +1,42 @@
-use std::sync::Arc;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicU32, AtomicUsize, Ordering},
+};
 
+use brush_render::camera::CameraModel;
 use burn::prelude::Backend;
+use clap::ValueEnum;
+use glam::{UVec2, Vec2};
 use image::DynamicImage;
-use rand::{SeedableRng, seq::SliceRandom};
+use rand::{Rng, SeedableRng, seq::SliceRandom};
+use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::Receiver;
-use tokio::sync::{RwLock, mpsc};
+use tokio::sync::{RwLock, mpsc, watch};
 use tokio_with_wasm::alias as tokio_wasm;
 
-use crate::scene::{Scene, SceneBatch, sample_to_tensor, view_to_sample_image};
+use crate::scene::{
+    Scene, SceneBatch, SceneView, crop_pixels, sample_to_tensor, view_to_sample_image,
+};
+
+/// How to pick the views to train on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum ViewSampling {
+    /// Every view once per epoch, in a random order.
+    Uniform,
+    /// Views with a higher recent loss more often, which trains parts of the scene covered by
+    /// few views faster.
+    Loss,
+}
 
 pub struct SceneLoader<B: Backend> {
     receiver: Receiver<SceneBatch<B>>,
+    level: Arc<AtomicU32>,
 }
 
 struct ImageCache {
-    states: Vec<Option<Arc<DynamicImage>>>,
+    // Images of each view, and the pyramid level they were loaded at.
+    states: Vec<Option<(u32, Arc<DynamicImage>)>>,
+    // Most bytes of images to keep, and the bytes kept so far.
     max_size: usize,
     size: usize,
 }
@@ -25,35 +47,143 @@ struct ImageCache {
 const MAX_CACHE_MB: usize = 6 * 1024;
 
 // On WASM, not much hope a big dataset will work anyway but let's not
-// cache more than what fits in memory.
+// cache more than what fits in memory. The whole dataset archive is in memory
+// as well, and a tab only gets a few gigs.
 #[cfg(target_family = "wasm")]
-const MAX_CACHE_MB: usize = 2 * 1024;
+const MAX_CACHE_MB: usize = 1024;
 
 impl ImageCache {
-    fn new(max_size: usize, n_images: usize) -> Self {
+    fn new(max_size_mb: usize, n_images: usize) -> Self {
         Self {
             states: vec![None; n_images],
-            max_size,
+            max_size: max_size_mb * 1024 * 1024,
             size: 0,
         }
     }
 
-    fn try_get(&self, index: usize) -> Option<Arc<DynamicImage>> {
-        self.states[index].clone()
+    fn try_get(&self, index: usize, level: u32) -> Option<Arc<DynamicImage>> {
+        self.states[index]
+            .as_ref()
+            .filter(|(cached_level, _)| *cached_level == level)
+            .map(|(_, image)| image.clone())
+    }
+
+    fn is_full(&self) -> bool {
+        self.size >= self.max_size
+    }
+
+    // Keep the image of a view at a pyramid level if there's room for it, in place of the image at
+    // other levels. Returns whether the image is kept.
+    fn insert(&mut self, index: usize, level: u32, data: Arc<DynamicImage>) -> bool {
+        match &self.states[index] {
+            Some((cached_level, _)) if *cached_level == level => return true,
+            Some((_, old)) => {
+                self.size -= old.as_bytes().len();
+                self.states[index] = None;
+            }
+            None => {}
+        }
+        let data_size = data.as_bytes().len();
+        if self.size + data_size > self.max_size {
+            return false;
+        }
+        self.states[index] = Some((level, data));
+        self.size += data_size;
+        true
     }
+}
+
+// Load the image of a view with its resolution halved `level` times, ready to be turned into a
+// training sample. Levels come from the disk cache where they can.
+async fn load_sample(view: &SceneView, level: u32) -> Arc<DynamicImage> {
+    let image = view
+        .image
+        .load_level(level)
+        .await
+        .expect("Scene loader encountered an error while loading an image");
+    // Don't premultiply the image if it's a mask - treat as fully opaque.
+    Arc::new(view_to_sample_image(image, view.image.is_masked()))
+}
 
-    fn insert(&mut self, index: usize, data: Arc<DynamicImage>) {
-        let data_size_mb = data.as_bytes().len() / (1024 * 1024);
+// Load the views of `order` into `cache` at the current `level`, from the `next` one on, and mark
+// them ready to train on. Several of these can run at once, sharing `next`.
+//
+// Once the cache is full, the remaining views are marked ready without loading them. Their images
+// would be thrown away, and only be loaded again once they're trained on.
+async fn preload_views(
+    views: &[SceneView],
+    order: &[usize],
+    next: &AtomicUsize,
+    level: &AtomicU32,
+    cache: &RwLock<ImageCache>,
+    ready: &watch::Sender<Vec<usize>>,
+) {
+    let mark_ready = |indices: &[usize]| {
+        ready.send_modify(|ready| {
+            ready.extend_from_slice(indices);
+            if ready.len() == views.len() {
+                log::info!("All {} views loaded", views.len());
+            }
+        });
+    };
 
-        if self.size + data_size_mb < self.max_size && self.states[index].is_none() {
-            self.states[index] = Some(data);
-            self.size += data_size_mb;
+    while let Some(&index) = order.get(next.fetch_add(1, Ordering::Relaxed)) {
+        let cached = !cache.read().await.is_full() && {
+            let level = level.load(Ordering::Relaxed);
+            let sample = load_sample(&views[index], level).await;
+            cache.write().await.insert(index, level, sample)
+        };
+        if !cached {
+            let rest = next.swap(order.len(), Ordering::Relaxed).min(order.len());
+            log::info!(
+                "Image cache is full, loading {} views as they're trained on",
+                order.len() - rest + 1
+            );
+            mark_ready(&[index]);
+            mark_ready(&order[rest..]);
+            break;
         }
+        mark_ready(&[index]);
+    }
+}
+
+// A random crop of at most `patch_size` pixels on a side of an image of `size`, in normalized
+// image coordinates. None if the image fits in a patch already.
+fn random_patch(size: UVec2, patch_size: u32, rng: &mut impl Rng) -> Option<(Vec2, Vec2)> {
+    let crop = size.min(UVec2::splat(patch_size.max(1)));
+    if crop == size {
+        return None;
     }
+    let corner = glam::uvec2(
+        rng.random_range(0..=size.x - crop.x),
+        rng.random_range(0..=size.y - crop.y),
+    );
+    let size = size.as_vec2();
+    Some((corner.as_vec2() / size, (corner + crop).as_vec2() / size))
 }
 
 impl<B: Backend> SceneLoader<B> {
-    pub fn new(scene: &Scene, seed: u64, device: &B::Device) -> Self {
+    /// Load batches of the views of `scene`, in the order `pick_view` picks them from the indices
+    /// of the views loaded so far.
+    ///
+    /// Views load in the background, and batches are drawn from the views loaded so far, so
+    /// training can start once `start_views` views are loaded instead of waiting for all of them.
+    ///
+    /// Images are loaded in parallel, so the order can differ between runs. With `deterministic`,
+    /// a single task loads the images, batches are drawn from all views straight away, and the
+    /// order only depends on the seed and `pick_view`.
+    ///
+    /// With a `patch_size`, batches are random crops of at most that many pixels on a side of
+    /// the views, with cameras seeing just the crop.
+    pub fn new(
+        scene: &Scene,
+        seed: u64,
+        deterministic: bool,
+        start_views: usize,
+        patch_size: Option<u32>,
+        pick_view: impl FnMut(&[usize]) -> usize + Send + 'static,
+        device: &B::Device,
+    ) -> Self {
         let num_img_queue = 32;
 
         // The bounded size == number of batches to prefetch.
@@ -62,7 +192,7 @@ impl<B: Backend> SceneLoader<B> {
         // On wasm, there is little point to spawning multiple of these. In theory there would be
         // IF file reading truly was async, but since the zip archive is just in memory it isn't really
         // any faster.
-        let parallelism = if cfg!(target_family = "wasm") {
+        let parallelism = if cfg!(target_family = "wasm") || deterministic {
             1
         } else {
             std::thread::available_parallelism()
@@ -75,44 +205,119 @@ impl<B: Backend> SceneLoader<B> {
         let num_views = scene.views.len();
 
         let load_cache = Arc::new(RwLock::new(ImageCache::new(MAX_CACHE_MB, num_views)));
+        let level = Arc::new(AtomicU32::new(0));
+
+        // Views loaded at least once, which batches are drawn from.
+        let (ready_sender, ready) = watch::channel(if deterministic {
+            (0..num_views).collect()
+        } else {
+            vec![]
+        });
+        let min_ready = start_views.clamp(1, num_views.max(1));
+
+        if !deterministic {
+            log::info!("Training starts once {min_ready} of {num_views} views are loaded");
+
+            // Datasets are usually in capture order, so load views from all over the scene first.
+            let mut order: Vec<_> = (0..num_views).collect();
+            order.shuffle(&mut rand::rngs::StdRng::seed_from_u64(seed));
+            let order = Arc::new(order);
+            let next = Arc::new(AtomicUsize::new(0));
+            let ready_sender = Arc::new(ready_sender);
+
+            for _ in 0..parallelism {
+                let order = order.clone();
+                let next = next.clone();
+                let ready_sender = ready_sender.clone();
+                let views = scene.views.clone();
+                let level = level.clone();
+                let load_cache = load_cache.clone();
 
+                tokio_wasm::spawn(async move {
+                    preload_views(&views, &order, &next, &level, &load_cache, &ready_sender).await;
+                });
+            }
+        }
+
+        let pick_view = Arc::new(Mutex::new(pick_view));
         for i in 0..parallelism {
             let mut rng = rand::rngs::StdRng::seed_from_u64(seed + i);
+            let pick_view = pick_view.clone();
             let send_img = send_img.clone();
             let views = scene.views.clone();
+            let mut ready = ready.clone();
+            let level = level.clone();
 
             let load_cache = load_cache.clone();
 
             tokio_wasm::spawn(async move {
-                let mut shuf_indices = vec![];
-
                 loop {
-                    let index = shuf_indices.pop().unwrap_or_else(|| {
-                        shuf_indices = (0..num_views).collect();
-                        shuf_indices.shuffle(&mut rng);
-                        shuf_indices
-                            .pop()
-                            .expect("Need at least one view in dataset")
-                    });
+                    let loaded = ready
+                        .wait_for(|ready| ready.len() >= min_ready)
+                        .await
+                        .expect("Need at least one view in dataset")
+                        .clone();
+                    let index = (pick_view.lock().expect("View picker panicked"))(&loaded);
 
                     let view = &views[index];
 
-                    let sample = if let Some(image) = load_cache.read().await.try_get(index) {
+                    let level = level.load(Ordering::Relaxed);
+                    let cached = load_cache.read().await.try_get(index, level);
+                    let sample = if let Some(image) = cached {
                         image
                     } else {
-                        let image = view
-                            .image
-                            .load()
+                        let sample = load_sample(view, level).await;
+                        load_cache
+                            .write()
                             .await
-                            .expect("Scene loader encountered an error while loading an image");
-                        // Don't premultiply the image if it's a mask - treat as fully opaque.
-                        let sample = Arc::new(view_to_sample_image(image, view.image.is_masked()));
-                        load_cache.write().await.insert(index, sample.clone());
+                            .insert(index, level, sample.clone());
                         sample
                     };
 
+                    // Feature and depth maps are small next to the images, so aren't cached.
+                    let features =
+                        view.image.load_feature_map().await.expect(
+                            "Scene loader encountered an error while loading a feature map",
+                        );
+                    let depth = view
+                        .image
+                        .load_depth_map()
+                        .await
+                        .expect("Scene loader encountered an error while loading a depth map");
+
+                    let size = glam::uvec2(sample.width(), sample.height());
+                    // Panoramas can't be cropped to a pinhole view.
+                    let patch = patch_size
+                        .filter(|_| view.camera.model != CameraModel::Equirectangular)
+                        .and_then(|patch_size| random_patch(size, patch_size, &mut rng));
+                    let (sample, features, depth, camera) = match patch {
+                        Some((min, max)) => {
+                            let (corner, crop_size) = crop_pixels(size, min, max);
+                            (
+                                Arc::new(sample.crop_imm(
+                                    corner.x,
+                                    corner.y,
+                                    crop_size.x,
+                                    crop_size.y,
+                                )),
+                                features.map(|f| f.crop(min, max)),
+                                depth.map(|d| d.crop(min, max)),
+                                view.camera.crop(min, max),
+                            )
+                        }
+                        None => (sample, features, depth, view.camera.clone()),
+                    };
+
                     if send_img
-                        .send((sample, view.image.is_masked(), view.camera.clone()))
+                        .send((
+                            sample,
+                            view.image.is_masked(),
+                            features,
+                            depth,
+                            view.image.time,
+                            camera,
+                            index,
+                        ))
                         .await
                         .is_err()
                     {
@@ -126,14 +331,20 @@ impl<B: Backend> SceneLoader<B> {
         let device = device.clone();
         tokio_wasm::spawn(async move {
             while let Some(rec) = rec_imag.recv().await {
-                let (sample, alpha_is_mask, camera) = rec;
+                let (sample, alpha_is_mask, features, depth, time, camera, view_index) = rec;
                 let img_tensor = sample_to_tensor(&sample, &device);
+                let features = features.map(|f| f.to_tensor(&device));
+                let depth = depth.map(|d| d.to_tensor(&device));
 
                 if send_batch
                     .send(SceneBatch {
                         img_tensor,
                         alpha_is_mask,
+                        features,
+                        depth,
+                        time,
                         camera,
+                        view_index,
                     })
                     .await
                     .is_err()
@@ -145,9 +356,16 @@ impl<B: Backend> SceneLoader<B> {
 
         Self {
             receiver: rec_batch,
+            level,
         }
     }
 
+    /// Load images with their resolution halved `level` times from now on. Batches already
+    /// loaded keep their resolution, and cached images of other levels are loaded again.
+    pub fn set_resolution_level(&self, level: u32) {
+        self.level.store(level, Ordering::Relaxed);
+    }
+
     pub async fn next_batch(&mut self) -> SceneBatch<B> {
         self.receiver
             .recv()
@@ -155,3 +373,86 @@ impl<B: Backend> SceneLoader<B> {
             .expect("Somehow lost data loading channel!")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brush_render::camera::Camera;
+    use brush_vfs::{BrushVfs, MemoryFile};
+    use image::{ImageFormat, RgbImage};
+
+    use crate::scene::LoadImage;
+
+    #[tokio::test]
+    async fn preload_stops_decoding_once_cache_is_full() {
+        let mut png = std::io::Cursor::new(vec![]);
+        DynamicImage::ImageRgb8(RgbImage::new(8, 8))
+            .write_to(&mut png, ImageFormat::Png)
+            .expect("Encode image");
+        let vfs = Arc::new(BrushVfs::from_memory_files(vec![MemoryFile {
+            name: "view.png".to_owned(),
+            data: png.into_inner().into(),
+        }]));
+        let camera = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            0.5,
+            0.5,
+            glam::vec2(0.5, 0.5),
+        );
+        let mut views = vec![];
+        for i in 0..4 {
+            // Views past the first fail to load, as their mask doesn't exist.
+            let mask = (i > 0).then(|| "missing_mask.png".into());
+            let image = LoadImage::new(vfs.clone(), std::path::Path::new("view.png"), mask, 64)
+                .await
+                .expect("Read image header");
+            views.push(SceneView {
+                image,
+                camera: camera.clone(),
+            });
+        }
+
+        // Room for exactly one image.
+        let mut cache = ImageCache::new(0, views.len());
+        cache.max_size = load_sample(&views[0], 0).await.as_bytes().len();
+        let cache = RwLock::new(cache);
+        let (ready_sender, ready) = watch::channel(vec![]);
+        let order = [0, 1, 2, 3];
+        preload_views(
+            &views,
+            &order,
+            &AtomicUsize::new(0),
+            &AtomicU32::new(0),
+            &cache,
+            &ready_sender,
+        )
+        .await;
+
+        let mut ready = ready.borrow().clone();
+        ready.sort_unstable();
+        assert_eq!(ready, order);
+        let cache = cache.read().await;
+        assert!(cache.try_get(0, 0).is_some());
+        assert!((1..4).all(|i| cache.try_get(i, 0).is_none()));
+    }
+
+    #[test]
+    fn cache_keeps_one_level_per_view() {
+        let image = |size| Arc::new(DynamicImage::ImageRgb8(RgbImage::new(size, size)));
+        let mut cache = ImageCache::new(0, 2);
+        cache.max_size = image(8).as_bytes().len();
+
+        assert!(cache.insert(0, 0, image(8)));
+        // No room for a second view.
+        assert!(!cache.insert(1, 1, image(4)));
+        assert!(cache.try_get(0, 1).is_none());
+
+        // A lower resolution takes the place of the old one, and frees up room.
+        assert!(cache.insert(0, 1, image(4)));
+        assert!(cache.try_get(0, 0).is_none());
+        assert_eq!(cache.try_get(0, 1).expect("Cached level").width(), 4);
+        assert!(cache.insert(1, 1, image(4)));
+        assert_eq!(cache.size, 2 * image(4).as_bytes().len());
+    }
+}
@@ -0,0 +1,128 @@
+// Settings a dataset ships with, so benchmark scenes are loaded like their published evaluations
+// without having to pass the right flags for every scene.
+use brush_vfs::BrushVfs;
+use serde::Deserialize;
+use tokio::io::AsyncReadExt;
+
+use crate::{config::LoadDataseConfig, formats::FormatError};
+
+/// Name of the file with the overrides of a dataset, anywhere in the dataset.
+pub const OVERRIDES_FILE: &str = "brush_scene.json";
+
+/// Benchmarks with a known evaluation protocol.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Benchmark {
+    /// Tanks and Temples, as evaluated by 3D Gaussian Splatting.
+    TanksAndTemples,
+    /// Deep Blending, as evaluated by 3D Gaussian Splatting.
+    DeepBlending,
+}
+
+impl Benchmark {
+    fn overrides(self) -> SceneOverrides {
+        match self {
+            // Both hold out every 8th image by name, and cap images at 1600 pixels.
+            Self::TanksAndTemples | Self::DeepBlending => SceneOverrides {
+                max_resolution: Some(1600),
+                eval_split_every: Some(8),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Dataset options set by a dataset, which replace the options it's loaded with.
+///
+/// Read from a JSON file like:
+///
+/// ```json
+/// { "benchmark": "tanks_and_temples", "max_resolution": 1200 }
+/// ```
+///
+/// A benchmark sets the options of its protocol, and options set in the file take precedence over
+/// those.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SceneOverrides {
+    pub benchmark: Option<Benchmark>,
+    pub max_resolution: Option<u32>,
+    /// Shrink images by exactly this factor.
+    pub downscale: Option<u32>,
+    pub max_frames: Option<usize>,
+    pub subsample_frames: Option<u32>,
+    pub eval_split_every: Option<usize>,
+    /// File names of the eval images, instead of every nth image.
+    pub eval_images: Option<Vec<String>>,
+    pub white_background: Option<bool>,
+    pub normalize_scene: Option<bool>,
+}
+
+impl SceneOverrides {
+    /// The options to load the dataset with.
+    pub fn apply(&self, config: &LoadDataseConfig) -> LoadDataseConfig {
+        let mut config = config.clone();
+        if let Some(benchmark) = self.benchmark {
+            config = benchmark.overrides().apply(&config);
+        }
+        if let Some(max_resolution) = self.max_resolution {
+            config.max_resolution = max_resolution;
+        }
+        if let Some(downscale) = self.downscale {
+            config.downscale = Some(downscale);
+        }
+        if let Some(max_frames) = self.max_frames {
+            config.max_frames = Some(max_frames);
+        }
+        if let Some(subsample_frames) = self.subsample_frames {
+            config.subsample_frames = Some(subsample_frames);
+        }
+        if let Some(eval_split_every) = self.eval_split_every {
+            config.eval_split_every = Some(eval_split_every);
+        }
+        if let Some(eval_images) = &self.eval_images {
+            config.eval_images = Some(eval_images.clone());
+        }
+        if let Some(white_background) = self.white_background {
+            config.white_background = white_background;
+        }
+        if let Some(normalize_scene) = self.normalize_scene {
+            config.normalize_scene = normalize_scene;
+        }
+        config
+    }
+}
+
+/// Read the overrides of a dataset, if it has any.
+pub async fn read_overrides(vfs: &BrushVfs) -> Result<Option<SceneOverrides>, FormatError> {
+    let Some(path) = vfs.files_ending_in(OVERRIDES_FILE).next() else {
+        return Ok(None);
+    };
+    let mut json = String::new();
+    vfs.reader_at_path(&path)
+        .await?
+        .read_to_string(&mut json)
+        .await?;
+    Ok(Some(serde_json::from_str(&json)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_options_override_benchmark() {
+        let overrides: SceneOverrides = serde_json::from_str(
+            r#"{ "benchmark": "deep_blending", "max_resolution": 1200, "downscale": 2, "eval_images": ["a.jpg"] }"#,
+        )
+        .expect("Valid overrides");
+        let config = overrides.apply(&LoadDataseConfig::new());
+        assert_eq!(config.max_resolution, 1200);
+        assert_eq!(config.downscale, Some(2));
+        assert_eq!(config.eval_split_every, Some(8));
+        assert!(config.is_eval_view(1, std::path::Path::new("images/a.jpg")));
+        assert!(!config.is_eval_view(0, std::path::Path::new("images/b.jpg")));
+
+        assert!(serde_json::from_str::<SceneOverrides>(r#"{ "max_res": 10 }"#).is_err());
+    }
+}
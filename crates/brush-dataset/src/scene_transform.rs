@@ -0,0 +1,260 @@
+use brush_render::{camera::Camera, gaussian_splats::Splats};
+use burn::prelude::Backend;
+use glam::{Affine3A, Quat, Vec3};
+use std::f32::consts::FRAC_PI_2;
+use std::f32::consts::PI;
+
+use crate::scene::{Scene, SceneView};
+
+/// Coordinate conventions used by common dataset formats.
+///
+/// Brush itself uses the COLMAP convention for cameras: x right, y down and looking down +z.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoordinateConvention {
+    /// x right, y down, z forward, for both cameras and the world.
+    Colmap,
+    /// x right, y up, z backward, for both cameras and the world.
+    OpenGl,
+    /// Cameras as in [`CoordinateConvention::OpenGl`], but the world is z up with y forward.
+    Blender,
+}
+
+impl CoordinateConvention {
+    /// Rotation taking world coordinates in the COLMAP convention to this convention.
+    fn world_from_colmap(self) -> Quat {
+        match self {
+            Self::Colmap => Quat::IDENTITY,
+            Self::OpenGl => Quat::from_rotation_x(PI),
+            Self::Blender => Quat::from_rotation_x(-FRAC_PI_2),
+        }
+    }
+
+    /// Rotation taking camera local coordinates in the COLMAP convention to this convention.
+    fn camera_from_colmap(self) -> Quat {
+        match self {
+            Self::Colmap => Quat::IDENTITY,
+            Self::OpenGl | Self::Blender => Quat::from_rotation_x(PI),
+        }
+    }
+
+    /// Convert a camera to world rotation in this convention to the camera convention Brush uses.
+    ///
+    /// This only changes the local axes of the camera, the world frame is left as is.
+    pub fn camera_to_brush(self, rotation: Quat) -> Quat {
+        rotation * self.camera_from_colmap()
+    }
+
+    /// Convert a camera to world rotation of Brush to this convention, the inverse of
+    /// [`CoordinateConvention::camera_to_brush`].
+    pub fn camera_from_brush(self, rotation: Quat) -> Quat {
+        rotation * self.camera_from_colmap().inverse()
+    }
+}
+
+/// A similarity transform (uniform scale, rotation and translation) applied to a whole scene.
+///
+/// Points are transformed as `rotation * (scale * p) + translation`. The transform applied
+/// to a dataset is kept around, so results can be mapped back to the original frame of the data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SceneTransform {
+    pub scale: f32,
+    pub rotation: Quat,
+    pub translation: Vec3,
+}
+
+impl Default for SceneTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl SceneTransform {
+    pub const IDENTITY: Self = Self {
+        scale: 1.0,
+        rotation: Quat::IDENTITY,
+        translation: Vec3::ZERO,
+    };
+
+    /// Recenter and rescale the scene so that all cameras of the scene lie in the unit sphere.
+    pub fn from_cameras(scene: &Scene) -> Self {
+        let positions: Vec<_> = scene.views.iter().map(|v| v.camera.position).collect();
+        if positions.is_empty() {
+            return Self::IDENTITY;
+        }
+        let center = positions.iter().sum::<Vec3>() / positions.len() as f32;
+        let radius = positions
+            .iter()
+            .map(|p| p.distance(center))
+            .fold(0.0, f32::max);
+        Self::from_center_radius(center, radius)
+    }
+
+    /// Recenter and rescale the scene so that most of the given points lie in the unit sphere.
+    ///
+    /// Point clouds from SfM often have some far away outliers, so this uses the median point
+    /// as the center, and ignores the furthest 5% of points.
+    pub fn from_points(points: &[Vec3]) -> Self {
+        if points.is_empty() {
+            return Self::IDENTITY;
+        }
+
+        let median = |axis: usize| {
+            let mut coords: Vec<f32> = points.iter().map(|p| p[axis]).collect();
+            coords.sort_by(|a, b| a.total_cmp(b));
+            coords[coords.len() / 2]
+        };
+        let center = Vec3::new(median(0), median(1), median(2));
+
+        let mut dists: Vec<f32> = points.iter().map(|p| p.distance(center)).collect();
+        dists.sort_by(|a, b| a.total_cmp(b));
+        let radius = dists[((dists.len() - 1) as f32 * 0.95) as usize];
+        Self::from_center_radius(center, radius)
+    }
+
+    fn from_center_radius(center: Vec3, radius: f32) -> Self {
+        let scale = if radius > 1e-6 { 1.0 / radius } else { 1.0 };
+        Self {
+            scale,
+            rotation: Quat::IDENTITY,
+            translation: -center * scale,
+        }
+    }
+
+    /// Rotate the world frame of one coordinate convention into another.
+    pub fn between_conventions(from: CoordinateConvention, to: CoordinateConvention) -> Self {
+        Self {
+            scale: 1.0,
+            rotation: to.world_from_colmap() * from.world_from_colmap().inverse(),
+            translation: Vec3::ZERO,
+        }
+    }
+
+    pub fn to_affine(self) -> Affine3A {
+        Affine3A::from_scale_rotation_translation(
+            Vec3::splat(self.scale),
+            self.rotation,
+            self.translation,
+        )
+    }
+
+    pub fn inverse(&self) -> Self {
+        let rotation = self.rotation.inverse();
+        let scale = 1.0 / self.scale;
+        Self {
+            scale,
+            rotation,
+            translation: -(rotation * self.translation) * scale,
+        }
+    }
+
+    /// The transform which first applies `self`, then `next`.
+    pub fn then(&self, next: &Self) -> Self {
+        Self {
+            scale: self.scale * next.scale,
+            rotation: next.rotation * self.rotation,
+            translation: next.rotation * (self.translation * next.scale) + next.translation,
+        }
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.rotation * (point * self.scale) + self.translation
+    }
+
+    pub fn transform_camera(&self, camera: &Camera) -> Camera {
+        Camera {
+            position: self.transform_point(camera.position),
+            rotation: self.rotation * camera.rotation,
+            ..camera.clone()
+        }
+    }
+
+    pub fn transform_view(&self, view: SceneView) -> SceneView {
+        SceneView {
+            image: view.image.with_depth_scale(self.scale),
+            camera: self.transform_camera(&view.camera),
+        }
+    }
+
+    pub fn transform_scene(&self, scene: &Scene) -> Scene {
+        let views = scene
+            .views
+            .iter()
+            .map(|view| self.transform_view(view.clone()))
+            .collect();
+        Scene::new(views)
+    }
+
+    /// Transform the means, rotations and scales of splats.
+    ///
+    /// Higher order SH coefficients are not rotated, so view dependent colors are only exactly
+    /// preserved for transforms without a rotation (like the ones from [`Self::from_cameras`]).
+    pub fn transform_splats<B: Backend>(&self, splats: Splats<B>) -> Splats<B> {
+        if *self == Self::IDENTITY {
+            return splats;
+        }
+        splats.transform(self.scale, self.rotation, self.translation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!(a.distance(b) < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn inverse_round_trips() {
+        let transform = SceneTransform {
+            scale: 2.5,
+            rotation: Quat::from_euler(glam::EulerRot::XYZ, 0.3, -1.2, 0.7),
+            translation: Vec3::new(1.0, -2.0, 0.5),
+        };
+        let p = Vec3::new(0.3, 4.0, -1.5);
+        assert_close(
+            transform
+                .inverse()
+                .transform_point(transform.transform_point(p)),
+            p,
+        );
+        assert_close(transform.then(&transform.inverse()).transform_point(p), p);
+        assert_close(
+            transform.to_affine().transform_point3(p),
+            transform.transform_point(p),
+        );
+    }
+
+    #[test]
+    fn points_fit_unit_sphere() {
+        let mut points: Vec<_> = (0..100)
+            .map(|i| Vec3::new(10.0 + (i % 10) as f32, 5.0, -(i / 10) as f32))
+            .collect();
+        // A far away outlier shouldn't affect the normalization much.
+        points.push(Vec3::splat(1000.0));
+        let transform = SceneTransform::from_points(&points);
+        let inside = points
+            .iter()
+            .filter(|&&p| transform.transform_point(p).length() <= 1.0 + 1e-5)
+            .count();
+        assert!(inside >= 95);
+    }
+
+    #[test]
+    fn blender_world_is_z_up() {
+        let transform = SceneTransform::between_conventions(
+            CoordinateConvention::Colmap,
+            CoordinateConvention::Blender,
+        );
+        // Up in COLMAP is -y.
+        assert_close(transform.transform_point(Vec3::NEG_Y), Vec3::Z);
+        assert_close(transform.transform_point(Vec3::Z), Vec3::Y);
+    }
+
+    #[test]
+    fn opengl_cameras_look_down_neg_z() {
+        let rotation = CoordinateConvention::OpenGl.camera_to_brush(Quat::IDENTITY);
+        assert_close(rotation * Vec3::Z, Vec3::NEG_Z);
+        assert_close(rotation * Vec3::NEG_Y, Vec3::Y);
+    }
+}
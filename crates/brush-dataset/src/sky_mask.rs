@@ -0,0 +1,281 @@
+// Masks out the sky (or any other background) of images, so training doesn't fill it with
+// floaters. A segmentation model finds the background, which then becomes transparent: splats
+// are trained to not cover it, the same as for images with a transparent background.
+use image::{DynamicImage, GrayImage, imageops::FilterType};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SkyMaskError {
+    #[error("Brush was built without ONNX support, enable the `onnx` feature to mask the sky.")]
+    Unsupported,
+
+    #[cfg(feature = "onnx")]
+    #[error("Error running segmentation model: {0}")]
+    Onnx(#[from] ort::Error),
+
+    #[error("Unexpected segmentation output of shape {0:?}, expected [1, H, W] or [1, 1, H, W].")]
+    InvalidOutput(Vec<i64>),
+}
+
+/// Finds the background of an image, eg. the sky.
+pub trait BackgroundSegmenter: Send + Sync {
+    /// How likely each pixel is to be background, from 0 to 255. The mask can have any
+    /// resolution, it's stretched over the image.
+    fn segment(&self, image: &DynamicImage) -> Result<GrayImage, SkyMaskError>;
+}
+
+#[cfg(feature = "onnx")]
+mod onnx {
+    use super::{BackgroundSegmenter, SkyMaskError};
+    use image::{DynamicImage, GrayImage};
+    use ort::{session::Session, value::Tensor};
+    use std::path::Path;
+
+    /// Segmenter running an ONNX segmentation model.
+    ///
+    /// The model takes a [1, 3, S, S] RGB image in [0, 1], and outputs a [1, H, W] or
+    /// [1, 1, H, W] map of how likely each pixel is to be background, in [0, 1].
+    pub struct OnnxSegmenter {
+        session: Session,
+        input_size: u32,
+    }
+
+    impl OnnxSegmenter {
+        pub fn load(path: &Path, input_size: u32) -> Result<Self, SkyMaskError> {
+            let session = Session::builder()?.commit_from_file(path)?;
+            Ok(Self {
+                session,
+                input_size,
+            })
+        }
+    }
+
+    impl BackgroundSegmenter for OnnxSegmenter {
+        fn segment(&self, image: &DynamicImage) -> Result<GrayImage, SkyMaskError> {
+            let size = self.input_size;
+            let resized = image
+                .resize_exact(size, size, image::imageops::FilterType::Triangle)
+                .into_rgb8();
+
+            // Planar CHW layout.
+            let plane = (size * size) as usize;
+            let mut data = vec![0.0f32; 3 * plane];
+            for (i, pixel) in resized.pixels().enumerate() {
+                for c in 0..3 {
+                    data[c * plane + i] = pixel[c] as f32 / 255.0;
+                }
+            }
+            let input = Tensor::from_array(([1, 3, size as usize, size as usize], data))?;
+            let outputs = self.session.run(ort::inputs![input]?)?;
+            let (shape, values) = outputs[0].try_extract_raw_tensor::<f32>()?;
+
+            let (height, width) = match shape[..] {
+                [1, h, w] | [1, 1, h, w] if h > 0 && w > 0 => (h as u32, w as u32),
+                _ => return Err(SkyMaskError::InvalidOutput(shape)),
+            };
+            let bytes = values
+                .iter()
+                .map(|p| (p.clamp(0.0, 1.0) * 255.0).round() as u8)
+                .collect();
+            GrayImage::from_raw(width, height, bytes).ok_or(SkyMaskError::InvalidOutput(shape))
+        }
+    }
+}
+
+#[cfg(feature = "onnx")]
+pub use onnx::OnnxSegmenter;
+
+/// Load an ONNX segmentation model, see [`OnnxSegmenter`] for the expected model format.
+pub fn load_segmenter(
+    path: &Path,
+    input_size: u32,
+) -> Result<Box<dyn BackgroundSegmenter>, SkyMaskError> {
+    #[cfg(feature = "onnx")]
+    {
+        Ok(Box::new(OnnxSegmenter::load(path, input_size)?))
+    }
+    #[cfg(not(feature = "onnx"))]
+    {
+        let _ = (path, input_size);
+        Err(SkyMaskError::Unsupported)
+    }
+}
+
+/// Make the pixels of `image` where `background` is at least `threshold` transparent.
+///
+/// The mask is stretched over the image. Float (HDR) images stay float.
+pub fn mask_background(
+    image: DynamicImage,
+    background: &GrayImage,
+    threshold: f32,
+) -> DynamicImage {
+    let (width, height) = (image.width(), image.height());
+    let background = image::imageops::resize(background, width, height, FilterType::Triangle);
+    let is_background = |p: &image::Luma<u8>| p[0] as f32 / 255.0 >= threshold;
+
+    if matches!(
+        image,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    ) {
+        let mut rgba = image.into_rgba32f();
+        for (pixel, bg) in rgba.pixels_mut().zip(background.pixels()) {
+            if is_background(bg) {
+                pixel[3] = 0.0;
+            }
+        }
+        rgba.into()
+    } else {
+        let mut rgba = image.into_rgba8();
+        for (pixel, bg) in rgba.pixels_mut().zip(background.pixels()) {
+            if is_background(bg) {
+                pixel[3] = 0;
+            }
+        }
+        rgba.into()
+    }
+}
+
+/// Masks the background of images as they are loaded.
+///
+/// Images are loaded many times during training, so masks are cached per image. When the dataset
+/// is a directory, masks are also saved next to it, so later runs don't need the model again.
+pub struct SkyMasker {
+    segmenter: Box<dyn BackgroundSegmenter>,
+    threshold: f32,
+    cache_dir: Option<PathBuf>,
+    cache: Mutex<HashMap<PathBuf, Arc<GrayImage>>>,
+}
+
+impl SkyMasker {
+    /// Mask the background of images using `segmenter`. Masks are saved in `cache_dir`, with
+    /// the same relative paths as the images.
+    pub fn new(
+        segmenter: Box<dyn BackgroundSegmenter>,
+        threshold: f32,
+        cache_dir: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            segmenter,
+            threshold,
+            cache_dir,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Where the mask of the image at `path` is saved, if masks are saved.
+    pub fn cache_path(&self, path: &Path) -> Option<PathBuf> {
+        let mut name = path.file_name()?.to_os_string();
+        name.push(".png");
+        Some(self.cache_dir.as_ref()?.join(path.with_file_name(name)))
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    async fn read_cached(&self, path: &Path) -> Option<GrayImage> {
+        let data = tokio::fs::read(self.cache_path(path)?).await.ok()?;
+        Some(image::load_from_memory(&data).ok()?.into_luma8())
+    }
+
+    // Failing to save a mask only means it's segmented again next time, so errors are logged
+    // and otherwise ignored.
+    #[cfg(not(target_family = "wasm"))]
+    async fn write_cached(&self, path: &Path, mask: &GrayImage) {
+        let Some(cache_path) = self.cache_path(path) else {
+            return;
+        };
+        let write = async {
+            if let Some(parent) = cache_path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            let mut data = std::io::Cursor::new(vec![]);
+            mask.write_to(&mut data, image::ImageFormat::Png)
+                .map_err(std::io::Error::other)?;
+            tokio::fs::write(&cache_path, data.into_inner()).await
+        };
+        if let Err(e) = write.await {
+            log::warn!("Failed to save sky mask to {cache_path:?}: {e}");
+        }
+    }
+
+    /// Background mask of the image at `path`, segmenting it if this image wasn't seen before.
+    pub async fn background(
+        &self,
+        path: &Path,
+        image: &DynamicImage,
+    ) -> Result<Arc<GrayImage>, SkyMaskError> {
+        let cached = self
+            .cache
+            .lock()
+            .expect("Sky mask cache poisoned")
+            .get(path)
+            .cloned();
+        if let Some(mask) = cached {
+            return Ok(mask);
+        }
+
+        #[cfg(not(target_family = "wasm"))]
+        let saved = self.read_cached(path).await;
+        #[cfg(target_family = "wasm")]
+        let saved = None;
+
+        let mask = match saved {
+            Some(mask) => mask,
+            None => {
+                let mask = self.segmenter.segment(image)?;
+                #[cfg(not(target_family = "wasm"))]
+                self.write_cached(path, &mask).await;
+                mask
+            }
+        };
+        let mask = Arc::new(mask);
+        self.cache
+            .lock()
+            .expect("Sky mask cache poisoned")
+            .insert(path.to_path_buf(), mask.clone());
+        Ok(mask)
+    }
+
+    /// Make the background of the image at `path` transparent.
+    pub async fn mask(
+        &self,
+        path: &Path,
+        image: DynamicImage,
+    ) -> Result<DynamicImage, SkyMaskError> {
+        let background = self.background(path, &image).await?;
+        Ok(mask_background(image, &background, self.threshold))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Luma, Rgb, Rgb32FImage, RgbImage};
+
+    #[test]
+    fn masks_only_background() {
+        let image = RgbImage::from_pixel(8, 8, Rgb([200, 100, 50]));
+        // Sky in the top half of a lower resolution mask.
+        let background = GrayImage::from_fn(4, 4, |_, y| Luma([if y < 2 { 255 } else { 0 }]));
+
+        let masked = mask_background(image.into(), &background, 0.5).into_rgba8();
+        for (_, y, pixel) in masked.enumerate_pixels() {
+            assert_eq!(pixel.0[..3], [200, 100, 50]);
+            if y < 3 {
+                assert_eq!(pixel[3], 0, "{y}");
+            } else if y > 4 {
+                assert_eq!(pixel[3], 255, "{y}");
+            }
+        }
+
+        let hdr = Rgb32FImage::from_pixel(4, 4, Rgb([4.0, 2.0, 1.0]));
+        let masked = mask_background(hdr.into(), &background, 0.5);
+        assert!(matches!(masked, DynamicImage::ImageRgba32F(_)));
+        let masked = masked.into_rgba32f();
+        assert_eq!(masked.get_pixel(0, 0).0, [4.0, 2.0, 1.0, 0.0]);
+        assert_eq!(masked.get_pixel(0, 3).0, [4.0, 2.0, 1.0, 1.0]);
+    }
+}
@@ -1,13 +1,63 @@
-use crate::parsed_gaussian::ParsedGaussian;
+use crate::{parsed_gaussian::ParsedGaussian, watermark::Watermark};
 use brush_render::gaussian_splats::Splats;
-use burn::prelude::Backend;
-use glam::{Quat, Vec3};
+use burn::{
+    prelude::Backend,
+    tensor::{Int, Tensor},
+};
+use glam::{Quat, Vec3, Vec4};
 use ply_rs::{
     ply::{self, Ply, PropertyDef, PropertyType, ScalarType},
     writer::Writer,
 };
 
-async fn read_splat_data<B: Backend>(splats: Splats<B>) -> Vec<ParsedGaussian<false>> {
+/// A label for each splat, eg. which object of the scene it belongs to, with a name for each
+/// label.
+///
+/// In ply files, labels are stored in a `label` vertex property. Label names are stored in the
+/// header, as comments of the form `label <index> <name>`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SplatLabels {
+    /// Label of each splat, an index into `names`.
+    pub labels: Vec<u32>,
+    pub names: Vec<String>,
+}
+
+const LABEL_COMMENT: &str = "label ";
+
+fn label_comment(index: usize, name: &str) -> String {
+    // Names end up on a single header line.
+    let name = name.replace(['\n', '\r'], " ");
+    format!("{LABEL_COMMENT}{index} {name}")
+}
+
+/// Name of the elements of the frames of ply files with delta frames, followed by the index of
+/// the frame. See [`splat_sequence_to_ply`].
+pub const DELTA_VERTEX_PREFIX: &str = "delta_vertex_";
+
+// Properties of the delta frames, which animate everything but the colors.
+const DELTA_PROPERTIES: [&str; 11] = [
+    "x", "y", "z", "scale_0", "scale_1", "scale_2", "opacity", "rot_0", "rot_1", "rot_2", "rot_3",
+];
+
+/// Header comment of ply files with the most important splats first, so the first part of the file
+/// already shows a recognizable scene.
+pub const PROGRESSIVE_COMMENT: &str = "Progressive: most important splats first";
+
+/// Whether a ply header comment marks the file as progressive, see [`PROGRESSIVE_COMMENT`].
+pub fn is_progressive_comment(comment: &str) -> bool {
+    comment.eq_ignore_ascii_case(PROGRESSIVE_COMMENT)
+}
+
+/// Parse a label name from a ply header comment, as written with [`SplatLabels`].
+pub fn parse_label_comment(comment: &str) -> Option<(u32, String)> {
+    let (index, name) = comment.strip_prefix(LABEL_COMMENT)?.split_once(' ')?;
+    Some((index.parse().ok()?, name.to_owned()))
+}
+
+async fn read_splat_data<B: Backend>(
+    splats: Splats<B>,
+    labels: Option<&[u32]>,
+) -> Vec<ParsedGaussian<false>> {
     let means = splats
         .means
         .val()
@@ -48,7 +98,7 @@ async fn read_splat_data<B: Backend>(splats: Splats<B>) -> Vec<ParsedGaussian<fa
     let sh_coeffs_num = splats.sh_coeffs.dims()[1];
 
     (0..splats.num_splats())
-        .filter_map(|i| {
+        .map(|i| {
             let i = i as usize;
             // Read SH data from [coeffs, channel] format to
             let sh_start = i * sh_coeffs_num * 3;
@@ -65,7 +115,7 @@ async fn read_splat_data<B: Backend>(splats: Splats<B>) -> Vec<ParsedGaussian<fa
             let sh_dc = glam::vec3(sh_red[0], sh_green[0], sh_blue[0]);
             let sh_coeffs_rest = [&sh_red[1..], &sh_green[1..], &sh_blue[1..]].concat();
 
-            let splat = ParsedGaussian {
+            ParsedGaussian {
                 mean: Vec3::new(means[i * 3], means[i * 3 + 1], means[i * 3 + 2]),
                 log_scale: Vec3::new(
                     log_scales[i * 3],
@@ -81,18 +131,169 @@ async fn read_splat_data<B: Backend>(splats: Splats<B>) -> Vec<ParsedGaussian<fa
                 ),
                 sh_dc,
                 sh_coeffs_rest,
-            };
-
-            splat.is_finite().then_some(splat)
+                label: labels.and_then(|l| l.get(i).copied()).unwrap_or(0),
+            }
         })
         .collect()
 }
 
 pub async fn splat_to_ply<B: Backend>(splats: Splats<B>) -> std::io::Result<Vec<u8>> {
-    let splats = splats.with_normed_rotations();
+    splat_to_ply_with_watermark(splats, None).await
+}
+
+/// Export splats to a ply file, optionally with a payload hidden in the colors of the splats.
+pub async fn splat_to_ply_with_watermark<B: Backend>(
+    splats: Splats<B>,
+    watermark: Option<Watermark>,
+) -> std::io::Result<Vec<u8>> {
+    splat_to_ply_with(splats, watermark, None).await
+}
+
+/// Export splats to a ply file, optionally with a watermark and a label for each splat.
+pub async fn splat_to_ply_with<B: Backend>(
+    splats: Splats<B>,
+    watermark: Option<Watermark>,
+    labels: Option<&SplatLabels>,
+) -> std::io::Result<Vec<u8>> {
+    write_ply(splats, watermark, labels, false).await
+}
+
+/// Export splats to a ply file from the highest to the lowest of `scores`, and mark it as
+/// progressive in the header. Loaders that show splats as they stream in then show a recognizable
+/// scene after only the first fraction of the file.
+///
+/// Scores are best the screen coverage of each splat over the views of the scene, eg. as given by
+/// `brush_train::simplify::contribution`, or [`Splats::importance`] without views.
+///
+/// Returns the file, and the order the splats were written in, to put other per splat data in the
+/// same order. Labels are written in that order already.
+pub async fn splat_to_progressive_ply<B: Backend>(
+    splats: Splats<B>,
+    scores: Tensor<B, 1>,
+    watermark: Option<Watermark>,
+    labels: Option<&SplatLabels>,
+) -> std::io::Result<(Vec<u8>, Tensor<B, 1, Int>)> {
+    let order = scores.argsort_descending(0);
+    let labels = match labels {
+        Some(labels) => {
+            let order = order.clone().into_data_async().await;
+            Some(SplatLabels {
+                labels: order
+                    .iter::<i64>()
+                    .map(|i| labels.labels.get(i as usize).copied().unwrap_or(0))
+                    .collect(),
+                names: labels.names.clone(),
+            })
+        }
+        None => None,
+    };
+    let data = write_ply(
+        splats.reorder(order.clone()),
+        watermark,
+        labels.as_ref(),
+        true,
+    )
+    .await?;
+    Ok((data, order))
+}
+
+/// Export an animation of splats to a ply file with delta frames, which Brush loads and plays.
+/// All frames must have the same splats, in the same order, that only move.
+///
+/// The `vertex` element holds the splats at `rest`, like a regular ply file. Each frame follows
+/// as a `delta_vertex_<frame>` element, with a row per splat of the rest. Its `x`, `y`, `z`,
+/// `scale_*`, `rot_*` and `opacity` properties are float offsets from the values of the splat
+/// at rest, so a frame is the rest plus its offsets. Rotations are normalized before taking
+/// offsets. Splats which aren't finite in some frame are left out of all of them.
+pub async fn splat_sequence_to_ply<B: Backend>(
+    rest: Splats<B>,
+    frames: Vec<Splats<B>>,
+) -> std::io::Result<Vec<u8>> {
+    if frames.iter().any(|f| f.num_splats() != rest.num_splats()) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "All frames of an animation need the same splats",
+        ));
+    }
+
+    let sh_coeffs_rest = (rest.sh_coeffs.dims()[1] - 1) * 3;
+    let data = read_splat_data(rest.with_normed_rotations(), None).await;
+    let mut frame_data = vec![];
+    for frame in frames {
+        frame_data.push(read_splat_data(frame.with_normed_rotations(), None).await);
+    }
+    let keep: Vec<bool> = (0..data.len())
+        .map(|i| data[i].is_finite() && frame_data.iter().all(|f| f[i].is_finite()))
+        .collect();
+
+    let mut ply: Ply<ParsedGaussian<false>> = Ply::new();
+    let mut vertex = ply::ElementDef::new("vertex");
+    vertex.properties = ply_properties(sh_coeffs_rest, false);
+    ply.header.elements.push(vertex);
+
+    for (index, frame) in frame_data.into_iter().enumerate() {
+        let name = format!("{DELTA_VERTEX_PREFIX}{index}");
+        let mut element = ply::ElementDef::new(&name);
+        element.properties = DELTA_PROPERTIES
+            .iter()
+            .map(|name| PropertyDef::new(name, PropertyType::Scalar(ScalarType::Float)))
+            .collect();
+        ply.header.elements.push(element);
+
+        let deltas = frame
+            .into_iter()
+            .zip(&data)
+            .zip(&keep)
+            .filter(|(_, keep)| **keep)
+            .map(|((splat, rest), _)| ParsedGaussian {
+                mean: splat.mean - rest.mean,
+                log_scale: splat.log_scale - rest.log_scale,
+                opacity: splat.opacity - rest.opacity,
+                rotation: Quat::from_vec4(Vec4::from(splat.rotation) - Vec4::from(rest.rotation)),
+                ..Default::default()
+            })
+            .collect();
+        ply.payload.insert(name, deltas);
+    }
+
+    let data = data
+        .into_iter()
+        .zip(&keep)
+        .filter(|(_, keep)| **keep)
+        .map(|(splat, _)| splat)
+        .collect();
+    ply.header.encoding = ply::Encoding::BinaryLittleEndian;
+    ply.header.comments.push("Exported from Brush".to_owned());
+    ply.header.comments.push("Vertical axis: y".to_owned());
+    ply.payload.insert("vertex".to_owned(), data);
+
+    let mut buf = vec![];
+    let writer = Writer::<ParsedGaussian<false>>::new();
+    writer.write_ply(&mut buf, &mut ply)?;
+    Ok(buf)
+}
 
-    let data = read_splat_data(splats.clone()).await;
+// The splats to write to a file, with the watermark embedded in their colors.
+pub(crate) async fn export_data<B: Backend>(
+    splats: Splats<B>,
+    watermark: Option<Watermark>,
+    labels: Option<&[u32]>,
+) -> Vec<ParsedGaussian<false>> {
+    let mut data = read_splat_data(splats.with_normed_rotations(), labels).await;
+    data.retain(ParsedGaussian::is_finite);
 
+    if let Some(watermark) = watermark {
+        let mut sh_dc: Vec<_> = data.iter().map(|splat| splat.sh_dc).collect();
+        watermark.embed(&mut sh_dc);
+        for (splat, dc) in data.iter_mut().zip(sh_dc) {
+            splat.sh_dc = dc;
+        }
+    }
+    data
+}
+
+// Properties of the vertices of a ply file of splats.
+fn ply_properties(sh_coeffs_rest: usize, labels: bool) -> Vec<PropertyDef> {
     let property_names = vec![
         "x", "y", "z", "scale_0", "scale_1", "scale_2", "opacity", "rot_0", "rot_1", "rot_2",
         "rot_3", "f_dc_0", "f_dc_1", "f_dc_2",
@@ -103,8 +304,6 @@ pub async fn splat_to_ply<B: Backend>(splats: Splats<B>) -> std::io::Result<Vec<
         .map(|name| PropertyDef::new(name, PropertyType::Scalar(ScalarType::Float)))
         .collect();
 
-    let sh_coeffs_rest = (splats.sh_coeffs.dims()[1] - 1) * 3;
-
     for i in 0..sh_coeffs_rest {
         properties.push(PropertyDef::new(
             &format!("f_rest_{i}"),
@@ -112,15 +311,41 @@ pub async fn splat_to_ply<B: Backend>(splats: Splats<B>) -> std::io::Result<Vec<
         ));
     }
 
+    if labels {
+        properties.push(PropertyDef::new(
+            "label",
+            PropertyType::Scalar(ScalarType::UInt),
+        ));
+    }
+    properties
+}
+
+async fn write_ply<B: Backend>(
+    splats: Splats<B>,
+    watermark: Option<Watermark>,
+    labels: Option<&SplatLabels>,
+    progressive: bool,
+) -> std::io::Result<Vec<u8>> {
+    let sh_coeffs_rest = (splats.sh_coeffs.dims()[1] - 1) * 3;
+    let data = export_data(splats, watermark, labels.map(|l| l.labels.as_slice())).await;
+
     let mut ply: Ply<ParsedGaussian<false>> = Ply::new();
 
     // Create PLY header
     let mut vertex = ply::ElementDef::new("vertex");
-    vertex.properties = properties;
+    vertex.properties = ply_properties(sh_coeffs_rest, labels.is_some());
     ply.header.elements.push(vertex);
     ply.header.encoding = ply::Encoding::BinaryLittleEndian;
     ply.header.comments.push("Exported from Brush".to_owned());
     ply.header.comments.push("Vertical axis: y".to_owned());
+    if progressive {
+        ply.header.comments.push(PROGRESSIVE_COMMENT.to_owned());
+    }
+    if let Some(labels) = labels {
+        for (index, name) in labels.names.iter().enumerate() {
+            ply.header.comments.push(label_comment(index, name));
+        }
+    }
     ply.payload.insert("vertex".to_owned(), data);
 
     let mut buf = vec![];
@@ -128,3 +353,104 @@ pub async fn splat_to_ply<B: Backend>(splats: Splats<B>) -> std::io::Result<Vec<
     writer.write_ply(&mut buf, &mut ply)?;
     Ok(buf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::splat_import::load_splat_from_ply;
+    use brush_render::MainBackend;
+    use burn::backend::wgpu::WgpuDevice;
+    use tokio_stream::StreamExt;
+
+    async fn load_labels(ply: Vec<u8>) -> Option<SplatLabels> {
+        let stream =
+            load_splat_from_ply(std::io::Cursor::new(ply), None, WgpuDevice::DefaultDevice);
+        let mut stream = std::pin::pin!(stream);
+        let mut labels = None;
+        while let Some(message) = stream.next().await {
+            labels = message.expect("Load ply").meta.labels;
+        }
+        labels
+    }
+
+    #[tokio::test]
+    async fn labels_round_trip() {
+        let splats = Splats::<MainBackend>::from_raw(
+            &[Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z],
+            None,
+            None,
+            None,
+            None,
+            &WgpuDevice::DefaultDevice,
+        );
+        let labels = SplatLabels {
+            labels: vec![0, 2, 1, 2],
+            names: vec![
+                "unlabeled".to_owned(),
+                "tree".to_owned(),
+                "park bench".to_owned(),
+            ],
+        };
+        let ply = splat_to_ply_with(splats.clone(), None, Some(&labels))
+            .await
+            .expect("Export ply");
+        assert_eq!(load_labels(ply).await, Some(labels));
+
+        let ply = splat_to_ply(splats).await.expect("Export ply");
+        assert_eq!(load_labels(ply).await, None);
+    }
+
+    #[tokio::test]
+    async fn progressive_follows_scores() {
+        let means = [Vec3::ZERO, Vec3::X, Vec3::Y];
+        let splats = Splats::<MainBackend>::from_raw(
+            &means,
+            None,
+            None,
+            None,
+            None,
+            &WgpuDevice::DefaultDevice,
+        );
+        let scores = Tensor::from_floats([0.5, 2.0, 1.0], &WgpuDevice::DefaultDevice);
+        let labels = SplatLabels {
+            labels: vec![0, 1, 2],
+            names: vec!["a".to_owned(), "b".to_owned(), "c".to_owned()],
+        };
+        let (ply, order) =
+            splat_to_progressive_ply(splats.clone(), scores.clone(), None, Some(&labels))
+                .await
+                .expect("Export ply");
+        let order: Vec<i64> = order.into_data().iter::<i64>().collect();
+        assert_eq!(order, [1, 2, 0]);
+
+        // Labels follow their splats.
+        let loaded = load_labels(ply).await.expect("Labels");
+        assert_eq!(loaded.labels, [1, 2, 0]);
+
+        // Splats without a label get the first one.
+        let labels = SplatLabels {
+            labels: vec![0, 1],
+            ..labels
+        };
+        let (ply, _) = splat_to_progressive_ply(splats, scores, None, Some(&labels))
+            .await
+            .expect("Export ply");
+        let loaded = load_labels(ply).await.expect("Labels");
+        assert_eq!(loaded.labels, [1, 0, 0]);
+    }
+
+    #[test]
+    fn label_comments_round_trip() {
+        let comment = label_comment(3, "tree trunk");
+        assert_eq!(
+            parse_label_comment(&comment),
+            Some((3, "tree trunk".to_owned()))
+        );
+        // Names can't break the header.
+        let comment = label_comment(0, "two\nlines");
+        assert!(!comment.contains('\n'));
+        assert_eq!(parse_label_comment("Vertical axis: y"), None);
+        assert_eq!(parse_label_comment(PROGRESSIVE_COMMENT), None);
+        assert!(is_progressive_comment(&PROGRESSIVE_COMMENT.to_lowercase()));
+    }
+}
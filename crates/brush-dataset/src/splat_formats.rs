@@ -0,0 +1,421 @@
+// Picks the importer for a splat file, and reads the formats besides ply: the `.splat` files of
+// antimatter15's web viewer, and Niantic's compressed `.spz` files. Chunked `.bsplat` files are
+// read in `chunked_splats`, and compressed `.bsz` files in `compression`.
+use std::{io::Read, path::Path, pin::Pin};
+
+use async_fn_stream::try_fn_stream;
+use brush_render::{
+    gaussian_splats::{Splats, inverse_sigmoid},
+    sh::rgb_to_sh,
+};
+use brush_vfs::{DynStream, SendNotWasm, ranges::RangeSource};
+use burn::backend::wgpu::WgpuDevice;
+use flate2::read::GzDecoder;
+use glam::{Quat, Vec3};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_stream::StreamExt;
+
+use crate::{
+    chunked_splats::{CHUNKED_MAGIC, load_chunked},
+    compression::{is_compressed, load_compressed},
+    splat_import::{ParseMetadata, SplatImportError, SplatMessage, TimeYield, load_splat_from_ply},
+};
+
+const SPZ_MAGIC: u32 = 0x5053_474e;
+
+/// File formats splats can be loaded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplatFormat {
+    Ply,
+    /// Fixed size records of 32 bytes, without any header.
+    Splat,
+    /// Gzipped and quantized splats.
+    Spz,
+    /// Chunks of nearby splats with an index, see [`crate::chunked_splats`].
+    Chunked,
+    /// Quantized splats with a codebook of SH coefficients, see [`crate::compression`].
+    Compressed,
+}
+
+impl SplatFormat {
+    /// The format of a file, from its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "ply" => Some(Self::Ply),
+            "splat" => Some(Self::Splat),
+            "spz" => Some(Self::Spz),
+            "bsplat" => Some(Self::Chunked),
+            "bsz" => Some(Self::Compressed),
+            _ => None,
+        }
+    }
+
+    /// The format of a file from its first bytes, for files picked without a name. Files that
+    /// aren't ply or gzipped are taken to be `.splat` files, which have no header.
+    pub fn from_header(header: &[u8]) -> Self {
+        if header.starts_with(b"ply") {
+            Self::Ply
+        } else if header.starts_with(&[0x1f, 0x8b]) {
+            Self::Spz
+        } else if header.starts_with(CHUNKED_MAGIC) {
+            Self::Chunked
+        } else if is_compressed(header) {
+            Self::Compressed
+        } else {
+            Self::Splat
+        }
+    }
+}
+
+type SplatStream = Pin<Box<dyn DynStream<Result<SplatMessage, SplatImportError>>>>;
+
+/// Load splats from a file in `format`.
+pub fn load_splats<T: AsyncRead + SendNotWasm + Unpin + 'static>(
+    format: SplatFormat,
+    mut reader: T,
+    subsample_points: Option<u32>,
+    device: WgpuDevice,
+) -> SplatStream {
+    if format == SplatFormat::Ply {
+        return Box::pin(load_splat_from_ply(reader, subsample_points, device));
+    }
+    if format == SplatFormat::Compressed {
+        return Box::pin(try_fn_stream(move |emitter| async move {
+            let mut data = vec![];
+            reader.read_to_end(&mut data).await?;
+            let splats = load_compressed(&data, &device)?;
+            emitter
+                .emit(SplatMessage {
+                    meta: ParseMetadata {
+                        up_axis: None,
+                        total_splats: splats.num_splats(),
+                        frame_count: 0,
+                        current_frame: 0,
+                        labels: None,
+                    },
+                    splats,
+                })
+                .await;
+            Ok(())
+        }));
+    }
+    if format == SplatFormat::Chunked {
+        // Without parts of the file to read in any order, read it all, and load the chunks in
+        // order.
+        return Box::pin(try_fn_stream(move |emitter| async move {
+            let mut data = vec![];
+            reader.read_to_end(&mut data).await?;
+            let source = RangeSource::Memory(data.into());
+            let (_, focus) = tokio::sync::watch::channel(None);
+            let mut stream = load_chunked(source, focus, None, device);
+            while let Some(message) = stream.next().await {
+                emitter.emit(message?).await;
+            }
+            Ok(())
+        }));
+    }
+
+    Box::pin(try_fn_stream(move |emitter| async move {
+        let mut data = vec![];
+        reader.read_to_end(&mut data).await?;
+        let raw = match format {
+            SplatFormat::Splat => parse_splat(&data)?,
+            _ => {
+                let mut unzipped = vec![];
+                GzDecoder::new(data.as_slice()).read_to_end(&mut unzipped)?;
+                parse_spz(&unzipped)?
+            }
+        };
+
+        // Send the splats in a few parts, so they show up while the rest is uploaded.
+        let count = raw.means.len();
+        let sh_per_splat = raw.sh_coeffs.len() / count.max(1);
+        let update_every = count.div_ceil(8).max(1);
+        let mut yielder = TimeYield::new();
+        let keep: Vec<_> = (0..count)
+            .filter(|i| subsample_points.is_none_or(|s| i % s as usize == 0))
+            .collect();
+
+        for end in (update_every..count + update_every).step_by(update_every) {
+            yielder.try_yield().await;
+            let picked: Vec<_> = keep.iter().copied().take_while(|&i| i < end).collect();
+            let means: Vec<_> = picked.iter().map(|&i| raw.means[i]).collect();
+            let rotations: Vec<_> = picked.iter().map(|&i| raw.rotations[i]).collect();
+            let log_scales: Vec<_> = picked.iter().map(|&i| raw.log_scales[i]).collect();
+            let opacities: Vec<_> = picked.iter().map(|&i| raw.opacities[i]).collect();
+            let sh_coeffs: Vec<_> = picked
+                .iter()
+                .flat_map(|&i| &raw.sh_coeffs[i * sh_per_splat..(i + 1) * sh_per_splat])
+                .copied()
+                .collect();
+
+            emitter
+                .emit(SplatMessage {
+                    meta: ParseMetadata {
+                        up_axis: None,
+                        total_splats: count as u32,
+                        frame_count: 0,
+                        current_frame: 0,
+                        labels: None,
+                    },
+                    splats: Splats::from_raw(
+                        &means,
+                        Some(&rotations),
+                        Some(&log_scales),
+                        Some(&sh_coeffs),
+                        Some(&opacities),
+                        &device,
+                    ),
+                })
+                .await;
+        }
+        Ok(())
+    }))
+}
+
+// Decoded splats, with the SH coefficients of each splat interleaved by channel.
+#[derive(Default)]
+struct RawSplats {
+    means: Vec<Vec3>,
+    rotations: Vec<Quat>,
+    log_scales: Vec<Vec3>,
+    opacities: Vec<f32>,
+    sh_coeffs: Vec<f32>,
+}
+
+// Both formats store opacity after the sigmoid, as a byte. Fully opaque would be infinite.
+fn opacity_from_byte(alpha: u8) -> f32 {
+    inverse_sigmoid((f32::from(alpha) / 255.0).clamp(1e-4, 1.0 - 1e-4))
+}
+
+fn parse_splat(data: &[u8]) -> Result<RawSplats, SplatImportError> {
+    const RECORD: usize = 32;
+    if data.is_empty() || data.len() % RECORD != 0 {
+        return Err(SplatImportError::InvalidFormat);
+    }
+    let float = |bytes: &[u8], i: usize| {
+        f32::from_le_bytes([
+            bytes[i * 4],
+            bytes[i * 4 + 1],
+            bytes[i * 4 + 2],
+            bytes[i * 4 + 3],
+        ])
+    };
+
+    let mut raw = RawSplats::default();
+    for record in data.chunks_exact(RECORD) {
+        raw.means.push(Vec3::new(
+            float(record, 0),
+            float(record, 1),
+            float(record, 2),
+        ));
+        // Scales are stored as is, not in log space.
+        let scale = Vec3::new(float(record, 3), float(record, 4), float(record, 5));
+        let scale = scale.max(Vec3::splat(1e-8));
+        raw.log_scales
+            .push(Vec3::new(scale.x.ln(), scale.y.ln(), scale.z.ln()));
+
+        let rgb = Vec3::new(
+            f32::from(record[24]),
+            f32::from(record[25]),
+            f32::from(record[26]),
+        ) / 255.0;
+        let dc = rgb_to_sh(rgb);
+        raw.sh_coeffs.extend([dc.x, dc.y, dc.z]);
+        raw.opacities.push(opacity_from_byte(record[27]));
+
+        let [w, x, y, z] = [record[28], record[29], record[30], record[31]]
+            .map(|v| (f32::from(v) - 128.0) / 128.0);
+        raw.rotations.push(Quat::from_xyzw(x, y, z, w).normalize());
+    }
+    Ok(raw)
+}
+
+// Reads the sections of an spz file in order.
+struct SpzReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> SpzReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SplatImportError> {
+        if self.data.len() < len {
+            return Err(SplatImportError::InvalidFormat);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, SplatImportError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+// Rotation taking the right, up, back frame of spz to the right, down, forward frame of plys.
+fn spz_to_ply(q: Quat) -> Quat {
+    Quat::from_xyzw(q.x, -q.y, -q.z, q.w)
+}
+
+// Signs of the SH coefficients of degree 1 to 3 after flipping y and z.
+const SPZ_SH_SIGNS: [f32; 15] = [
+    -1.0, -1.0, 1.0, -1.0, 1.0, 1.0, -1.0, 1.0, -1.0, 1.0, -1.0, -1.0, 1.0, -1.0, 1.0,
+];
+
+fn spz_rotation(bytes: &[u8], version: u32) -> Quat {
+    if version >= 3 {
+        // The three smallest components in 10 bits each, and which one was left out.
+        const MASK: u32 = (1 << 9) - 1;
+        let mut packed = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let largest = (packed >> 30) as usize;
+        let mut q = [0.0; 4];
+        let mut sum_squares = 0.0;
+        for i in (0..4).rev().filter(|&i| i != largest) {
+            let magnitude = (packed & MASK) as f32 / MASK as f32 * std::f32::consts::FRAC_1_SQRT_2;
+            q[i] = if (packed >> 9) & 1 == 1 {
+                -magnitude
+            } else {
+                magnitude
+            };
+            sum_squares += q[i] * q[i];
+            packed >>= 10;
+        }
+        q[largest] = (1.0 - sum_squares).max(0.0).sqrt();
+        Quat::from_xyzw(q[0], q[1], q[2], q[3])
+    } else {
+        let [x, y, z] = [bytes[0], bytes[1], bytes[2]].map(|v| f32::from(v) / 127.5 - 1.0);
+        let w = (1.0 - x * x - y * y - z * z).max(0.0).sqrt();
+        Quat::from_xyzw(x, y, z, w)
+    }
+}
+
+fn parse_spz(data: &[u8]) -> Result<RawSplats, SplatImportError> {
+    let mut reader = SpzReader { data };
+    if reader.u32()? != SPZ_MAGIC {
+        return Err(SplatImportError::InvalidFormat);
+    }
+    let version = reader.u32()?;
+    // Version 1 stored positions as half floats, which nothing writes anymore.
+    if !(2..=3).contains(&version) {
+        return Err(SplatImportError::InvalidFormat);
+    }
+    let count = reader.u32()? as usize;
+    let [sh_degree, fractional_bits, _flags, _reserved] = reader
+        .take(4)?
+        .try_into()
+        .map_err(|_e| SplatImportError::InvalidFormat)?;
+    let sh_rest = match sh_degree {
+        0 => 0,
+        1 => 3,
+        2 => 8,
+        3 => 15,
+        _ => return Err(SplatImportError::InvalidFormat),
+    };
+
+    let positions = reader.take(count * 9)?;
+    let alphas = reader.take(count)?;
+    let colors = reader.take(count * 3)?;
+    let scales = reader.take(count * 3)?;
+    let rotations = reader.take(count * if version >= 3 { 4 } else { 3 })?;
+    let sh = reader.take(count * sh_rest * 3)?;
+
+    let position_scale = 1.0 / (1u32 << fractional_bits) as f32;
+    // Colors are scaled up before quantizing, to use more of the byte.
+    const COLOR_SCALE: f32 = 0.15;
+
+    let mut raw = RawSplats::default();
+    for i in 0..count {
+        let p = &positions[i * 9..];
+        let fixed = |j: usize| {
+            // Sign extend the 24 bit value.
+            let value = i32::from_le_bytes([p[j * 3], p[j * 3 + 1], p[j * 3 + 2], 0]);
+            ((value << 8) >> 8) as f32 * position_scale
+        };
+        let mean = Vec3::new(fixed(0), fixed(1), fixed(2));
+        raw.means.push(mean * Vec3::new(1.0, -1.0, -1.0));
+
+        raw.opacities.push(opacity_from_byte(alphas[i]));
+        let c = &colors[i * 3..i * 3 + 3];
+        raw.sh_coeffs.extend(
+            c.iter()
+                .map(|&c| (f32::from(c) / 255.0 - 0.5) / COLOR_SCALE),
+        );
+        let s = &scales[i * 3..i * 3 + 3];
+        raw.log_scales.push(Vec3::new(
+            f32::from(s[0]) / 16.0 - 10.0,
+            f32::from(s[1]) / 16.0 - 10.0,
+            f32::from(s[2]) / 16.0 - 10.0,
+        ));
+
+        let rotation_size = if version >= 3 { 4 } else { 3 };
+        let rotation = spz_rotation(&rotations[i * rotation_size..], version);
+        raw.rotations.push(spz_to_ply(rotation).normalize());
+
+        let coeffs = &sh[i * sh_rest * 3..(i + 1) * sh_rest * 3];
+        for (j, rgb) in coeffs.chunks_exact(3).enumerate() {
+            raw.sh_coeffs.extend(
+                rgb.iter()
+                    .map(|&v| (f32::from(v) - 128.0) / 128.0 * SPZ_SH_SIGNS[j]),
+            );
+        }
+    }
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_from_extension() {
+        assert_eq!(
+            SplatFormat::from_path(Path::new("a/scene.SPLAT")),
+            Some(SplatFormat::Splat)
+        );
+        assert_eq!(
+            SplatFormat::from_path(Path::new("scene.spz")),
+            Some(SplatFormat::Spz)
+        );
+        assert_eq!(SplatFormat::from_path(Path::new("images/0001.png")), None);
+        assert_eq!(
+            SplatFormat::from_header(b"ply\nformat binary_little_endian 1.0"),
+            SplatFormat::Ply
+        );
+        assert_eq!(SplatFormat::from_header(&[0x1f, 0x8b, 8]), SplatFormat::Spz);
+        assert_eq!(
+            SplatFormat::from_header(b"bsplat\x01\0"),
+            SplatFormat::Chunked
+        );
+        assert_eq!(
+            SplatFormat::from_header(b"bsz\x01"),
+            SplatFormat::Compressed
+        );
+        assert_eq!(SplatFormat::from_header(&[0; 32]), SplatFormat::Splat);
+    }
+
+    #[test]
+    fn splat_record_decodes() {
+        let mut record = vec![];
+        for v in [1.0f32, 2.0, 3.0, 0.5, 0.5, 2.0] {
+            record.extend(v.to_le_bytes());
+        }
+        // Mid gray, half transparent, and no rotation.
+        record.extend([128, 128, 128, 128, 255, 128, 128, 128]);
+
+        let raw = parse_splat(&record).expect("Valid record");
+        assert_eq!(raw.means, vec![Vec3::new(1.0, 2.0, 3.0)]);
+        assert!((raw.log_scales[0].z - 2.0f32.ln()).abs() < 1e-5);
+        assert!(raw.opacities[0].abs() < 0.01);
+        assert!(raw.rotations[0].angle_between(Quat::IDENTITY) < 0.02);
+        assert!(parse_splat(&record[..31]).is_err());
+    }
+
+    #[test]
+    fn spz_smallest_three_rotation() {
+        // Largest component is w (index 3), the others are zero.
+        let packed = 3u32 << 30;
+        let q = spz_rotation(&packed.to_le_bytes(), 3);
+        assert!(q.angle_between(Quat::IDENTITY) < 1e-5);
+    }
+}
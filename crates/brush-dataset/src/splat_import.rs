@@ -2,6 +2,7 @@ use std::{collections::HashSet, pin::Pin};
 
 use async_fn_stream::try_fn_stream;
 use brush_render::gaussian_splats::Splats;
+use brush_render::knn::nearest_neighbour_log_scales;
 use brush_render::{MainBackend, gaussian_splats::inverse_sigmoid, sh::rgb_to_sh};
 use brush_vfs::{DynStream, SendNotWasm};
 use burn::{
@@ -19,13 +20,18 @@ use tokio_stream::{Stream, StreamExt};
 use tokio_with_wasm::alias as tokio_wasm;
 use tracing::trace_span;
 
-use crate::parsed_gaussian::ParsedGaussian;
+use crate::{
+    parsed_gaussian::ParsedGaussian,
+    splat_export::{DELTA_VERTEX_PREFIX, SplatLabels, is_progressive_comment, parse_label_comment},
+};
 
 pub struct ParseMetadata {
     pub up_axis: Option<Vec3>,
     pub total_splats: u32,
     pub frame_count: u32,
     pub current_frame: u32,
+    /// Labels of the loaded splats, for ply files saved with them.
+    pub labels: Option<SplatLabels>,
 }
 
 pub struct SplatMessage {
@@ -51,6 +57,31 @@ fn interleave_coeffs(sh_dc: Vec3, sh_rest: &[f32], result: &mut Vec<f32>) {
     }
 }
 
+// Splats from the parsed properties. Files without scales get them from the distances between
+// points, which for point clouds of millions of points is best done on the GPU.
+async fn splats_from_parts(
+    means: &[Vec3],
+    rotations: Option<&[Quat]>,
+    log_scales: Option<&[Vec3]>,
+    sh_coeffs: Option<&[f32]>,
+    opacity: Option<&[f32]>,
+    device: &WgpuDevice,
+) -> Splats<MainBackend> {
+    let nearest_scales = if log_scales.is_none() {
+        Some(nearest_neighbour_log_scales(means, device).await)
+    } else {
+        None
+    };
+    Splats::from_raw(
+        means,
+        rotations,
+        log_scales.or(nearest_scales.as_deref()),
+        sh_coeffs,
+        opacity,
+        device,
+    )
+}
+
 async fn parse_elem<T: AsyncBufRead + Unpin + 'static, E: PropertyAccess>(
     reader: &mut T,
     parser: &Parser<E>,
@@ -70,13 +101,13 @@ async fn parse_elem<T: AsyncBufRead + Unpin + 'static, E: PropertyAccess>(
     }
 }
 
-struct TimeYield {
+pub(crate) struct TimeYield {
     last_yield: web_time::Instant,
     tick: usize,
 }
 
 impl TimeYield {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             last_yield: web_time::Instant::now(),
             tick: 0,
@@ -84,7 +115,7 @@ impl TimeYield {
     }
 
     /// Check if we need to yield. Should be called in loops.
-    async fn try_yield(&mut self) {
+    pub(crate) async fn try_yield(&mut self) {
         self.tick += 1;
 
         // Only check every so many iterations as checking the time isn't super cheap either.
@@ -133,6 +164,7 @@ pub fn load_splat_from_ply<T: AsyncRead + SendNotWasm + Unpin + 'static>(
                 _ => None,
             })
             .next_back();
+        let progressive = header.comments.iter().any(|c| is_progressive_comment(c));
 
         // Check whether there is a vertex header that has at least XYZ.
         let has_vertex = header.elements.iter().any(|el| el.name == "vertex");
@@ -140,7 +172,12 @@ pub fn load_splat_from_ply<T: AsyncRead + SendNotWasm + Unpin + 'static>(
         let ply_type = if has_vertex && header.elements.first().is_some_and(|el| el.name == "chunk")
         {
             PlyFormat::SuperSplatCompressed
-        } else if has_vertex && header.elements.iter().any(|el| el.name == "delta_vertex_") {
+        } else if has_vertex
+            && header
+                .elements
+                .iter()
+                .any(|el| el.name.starts_with(DELTA_VERTEX_PREFIX))
+        {
             PlyFormat::Brush4DCompressed
         } else if has_vertex {
             PlyFormat::Ply
@@ -151,9 +188,14 @@ pub fn load_splat_from_ply<T: AsyncRead + SendNotWasm + Unpin + 'static>(
         type SplatStream = Pin<Box<dyn DynStream<Result<SplatMessage, SplatImportError>>>>;
 
         let mut sub_stream: SplatStream = match ply_type {
-            PlyFormat::Ply => {
-                Box::pin(parse_ply(reader, subsample_points, device, header, up_axis))
-            }
+            PlyFormat::Ply => Box::pin(parse_ply(
+                reader,
+                subsample_points,
+                device,
+                header,
+                up_axis,
+                progressive,
+            )),
             PlyFormat::Brush4DCompressed => Box::pin(parse_delta_ply(
                 reader,
                 subsample_points,
@@ -178,12 +220,27 @@ pub fn load_splat_from_ply<T: AsyncRead + SendNotWasm + Unpin + 'static>(
     })
 }
 
+// Names of the labels in the comments of a ply header, see [`SplatLabels`]. Labels without a name
+// get an empty one.
+fn label_names(comments: &[String]) -> Vec<String> {
+    let mut names = vec![];
+    for (index, name) in comments.iter().filter_map(|c| parse_label_comment(c)) {
+        let index = index as usize;
+        if index >= names.len() {
+            names.resize(index + 1, String::new());
+        }
+        names[index] = name;
+    }
+    names
+}
+
 fn parse_ply<T: AsyncBufRead + Unpin + 'static>(
     mut reader: T,
     subsample_points: Option<u32>,
     device: WgpuDevice,
     header: Header,
     up_axis: Option<Vec3>,
+    progressive: bool,
 ) -> impl Stream<Item = Result<SplatMessage, SplatImportError>> + 'static {
     try_fn_stream(|emitter| async move {
         let vertex = header
@@ -210,8 +267,19 @@ fn parse_ply<T: AsyncBufRead + Unpin + 'static>(
         let mut opacity = properties
             .contains("opacity")
             .then(|| Vec::with_capacity(vertex.count));
+        let mut labels = properties
+            .contains("label")
+            .then(|| Vec::with_capacity(vertex.count));
+        let names = label_names(&header.comments);
 
         let update_every = vertex.count.div_ceil(8);
+        // The first splats of progressive files already show most of the scene, so show them early
+        // and double the interval between updates each time, up to the regular interval.
+        let mut interval = if progressive {
+            vertex.count.div_ceil(64)
+        } else {
+            update_every
+        };
 
         let mut last_update = 0;
         let mut yielder = TimeYield::new();
@@ -246,16 +314,20 @@ fn parse_ply<T: AsyncBufRead + Unpin + 'static>(
             if let Some(sh_coeffs) = &mut sh_coeffs {
                 interleave_coeffs(splat.sh_dc, &splat.sh_coeffs_rest, sh_coeffs);
             }
+            if let Some(labels) = &mut labels {
+                labels.push(splat.label);
+            }
 
-            if (i - last_update) >= update_every || i == vertex.count - 1 {
-                let splats = Splats::from_raw(
+            if (i - last_update) >= interval || i == vertex.count - 1 {
+                let splats = splats_from_parts(
                     &means,
                     rotations.as_deref(),
                     log_scales.as_deref(),
                     sh_coeffs.as_deref(),
                     opacity.as_deref(),
                     &device,
-                );
+                )
+                .await;
                 emitter
                     .emit(SplatMessage {
                         meta: ParseMetadata {
@@ -263,12 +335,17 @@ fn parse_ply<T: AsyncBufRead + Unpin + 'static>(
                             up_axis,
                             frame_count: 0,
                             current_frame: 0,
+                            labels: labels.as_ref().map(|labels| SplatLabels {
+                                labels: labels.clone(),
+                                names: names.clone(),
+                            }),
                         },
                         splats,
                     })
                     .await;
 
                 last_update = i;
+                interval = (interval * 2).min(update_every);
             }
         }
 
@@ -427,6 +504,7 @@ fn parse_compressed_ply<T: AsyncBufRead + Unpin + 'static>(
                             up_axis,
                             frame_count: 0,
                             current_frame: 0,
+                            labels: None,
                         },
                         splats: Splats::from_raw(
                             &means,
@@ -483,6 +561,7 @@ fn parse_compressed_ply<T: AsyncBufRead + Unpin + 'static>(
                         up_axis,
                         frame_count: 0,
                         current_frame: 0,
+                        labels: None,
                     },
                     splats: Splats::from_raw(
                         &means,
@@ -515,7 +594,7 @@ fn parse_delta_ply<T: AsyncBufRead + Unpin + 'static>(
         let frame_count = header
             .elements
             .iter()
-            .filter(|e| e.name.starts_with("delta_vertex_"))
+            .filter(|e| e.name.starts_with(DELTA_VERTEX_PREFIX))
             .count() as u32;
 
         let mut final_splat = None;
@@ -526,17 +605,20 @@ fn parse_delta_ply<T: AsyncBufRead + Unpin + 'static>(
             mean: Vec3,
             rotation: Vec4,
             scale: Vec3,
+            opacity: f32,
         }
 
         let mut meta_min = QuantMeta {
             mean: Vec3::ZERO,
             rotation: Vec4::ZERO,
             scale: Vec3::ZERO,
+            opacity: 0.0,
         };
         let mut meta_max = QuantMeta {
             mean: Vec3::ONE,
             rotation: Vec4::ONE,
             scale: Vec3::ONE,
+            opacity: 1.0,
         };
 
         for element in &header.elements {
@@ -571,15 +653,17 @@ fn parse_delta_ply<T: AsyncBufRead + Unpin + 'static>(
                                     up_axis,
                                     frame_count,
                                     current_frame: frame,
+                                    labels: None,
                                 },
-                                splats: Splats::from_raw(
+                                splats: splats_from_parts(
                                     &means,
                                     rotations.as_deref(),
                                     log_scales.as_deref(),
                                     sh_coeffs.as_deref(),
                                     opacity.as_deref(),
                                     &device,
-                                ),
+                                )
+                                .await,
                             })
                             .await;
                     }
@@ -608,14 +692,15 @@ fn parse_delta_ply<T: AsyncBufRead + Unpin + 'static>(
                         interleave_coeffs(splat.sh_dc, &splat.sh_coeffs_rest, sh_coeffs);
                     }
                 }
-                let splats = Splats::from_raw(
+                let splats = splats_from_parts(
                     &means,
                     rotations.as_deref(),
                     log_scales.as_deref(),
                     sh_coeffs.as_deref(),
                     opacity.as_deref(),
                     &device,
-                );
+                )
+                .await;
                 final_splat = Some(splats.clone());
                 emitter
                     .emit(SplatMessage {
@@ -624,6 +709,7 @@ fn parse_delta_ply<T: AsyncBufRead + Unpin + 'static>(
                             up_axis,
                             frame_count,
                             current_frame: frame,
+                            labels: None,
                         },
                         splats,
                     })
@@ -633,12 +719,14 @@ fn parse_delta_ply<T: AsyncBufRead + Unpin + 'static>(
                 meta_min.mean = splat.mean;
                 meta_min.rotation = splat.rotation.into();
                 meta_min.scale = splat.log_scale;
+                meta_min.opacity = splat.opacity;
             } else if element.name.starts_with("meta_delta_max_") {
                 let splat = parse_elem(&mut reader, &parser, header.encoding, element).await?;
                 meta_max.mean = splat.mean;
                 meta_max.rotation = splat.rotation.into();
                 meta_max.scale = splat.log_scale;
-            } else if element.name.starts_with("delta_vertex_") {
+                meta_max.opacity = splat.opacity;
+            } else if element.name.starts_with(DELTA_VERTEX_PREFIX) {
                 let Some(splats) = final_splat.clone() else {
                     return Err(SplatImportError::InvalidFormat);
                 };
@@ -650,7 +738,7 @@ fn parse_delta_ply<T: AsyncBufRead + Unpin + 'static>(
                     let splat_enc =
                         parse_elem(&mut reader, &parser, header.encoding, element).await?;
 
-                    // Let's only animate transforms for now.
+                    // Colors don't animate.
                     means.push(splat_enc.mean * (meta_max.mean - meta_min.mean) + meta_min.mean);
 
                     if let Some(rotation) = rotations.as_mut() {
@@ -665,6 +753,12 @@ fn parse_delta_ply<T: AsyncBufRead + Unpin + 'static>(
                                 + meta_min.scale,
                         );
                     }
+                    if let Some(opacity) = opacity.as_mut() {
+                        opacity.push(
+                            splat_enc.opacity * (meta_max.opacity - meta_min.opacity)
+                                + meta_min.opacity,
+                        );
+                    }
                     // Don't emit any intermediate states as it looks strange to have a torn state.
                 }
 
@@ -698,6 +792,13 @@ fn parse_delta_ply<T: AsyncBufRead + Unpin + 'static>(
                     splats.log_scales.val()
                 };
 
+                let raw_opacity = if let Some(opacity) = opacity {
+                    Tensor::from_data(TensorData::new(opacity, [n_splats]), &device)
+                        + splats.raw_opacity.val()
+                } else {
+                    splats.raw_opacity.val()
+                };
+
                 // Emit newly animated splat.
                 emitter
                     .emit(SplatMessage {
@@ -706,13 +807,14 @@ fn parse_delta_ply<T: AsyncBufRead + Unpin + 'static>(
                             up_axis,
                             frame_count,
                             current_frame: frame,
+                            labels: None,
                         },
                         splats: Splats::from_tensor_data(
                             means,
                             rotations,
                             log_scales,
                             splats.sh_coeffs.val(),
-                            splats.raw_opacity.val(),
+                            raw_opacity,
                         ),
                     })
                     .await;
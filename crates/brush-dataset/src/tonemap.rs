@@ -0,0 +1,124 @@
+// Mapping linear HDR colors (eg. from EXR renders or merged exposure brackets) to display colors.
+//
+// HDR datasets are trained in linear color, so the splats keep the full range of the scene, but
+// losses compare tonemapped colors, which weighs errors about the way they're seen.
+use brush_render::{gaussian_splats::Splats, shaders::project_visible::SH_C0};
+use burn::{
+    prelude::Backend,
+    tensor::{Tensor, s},
+};
+use clap::ValueEnum;
+use image::{DynamicImage, Rgba32FImage};
+use serde::{Deserialize, Serialize};
+
+use crate::color::{linear_to_srgb, linear_to_srgb_tensor};
+
+/// Curve mapping linear colors of HDR images to display colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum Tonemap {
+    /// Clip to [0, 1]. Brighter highlights are lost.
+    Clip,
+    /// Compress highlights with x / (1 + x), which keeps detail in very bright areas.
+    Reinhard,
+    /// The filmic ACES curve (as fit by Narkowicz), with more contrast than `reinhard`.
+    Aces,
+}
+
+impl Tonemap {
+    /// Map a linear value to a display value in [0, 1], before sRGB encoding.
+    fn curve(self, x: f32) -> f32 {
+        let x = x.max(0.0);
+        match self {
+            Self::Clip => x.min(1.0),
+            Self::Reinhard => x / (1.0 + x),
+            Self::Aces => {
+                ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }
+        }
+    }
+
+    /// Map a linear value to an sRGB encoded display value in [0, 1].
+    pub fn map(self, x: f32) -> f32 {
+        linear_to_srgb(self.curve(x))
+    }
+
+    /// Like [`Tonemap::map`], for all values of a tensor.
+    pub fn apply<B: Backend, const D: usize>(self, linear: Tensor<B, D>) -> Tensor<B, D> {
+        let x = linear.clamp_min(0.0);
+        let x = match self {
+            Self::Clip => x.clamp_max(1.0),
+            Self::Reinhard => x.clone() / (x + 1.0),
+            Self::Aces => {
+                let num = x.clone() * (x.clone() * 2.51 + 0.03);
+                let den = x.clone() * (x * 2.43 + 0.59) + 0.14;
+                (num / den).clamp(0.0, 1.0)
+            }
+        };
+        linear_to_srgb_tensor(x)
+    }
+
+    /// Tonemap the colors of splats trained on HDR images, so they show up right in viewers that
+    /// expect the sRGB colors of regular splats, eg. for export.
+    ///
+    /// The base color of each splat is mapped exactly, view dependent colors are scaled along with
+    /// it.
+    pub fn apply_splats<B: Backend>(self, mut splats: Splats<B>) -> Splats<B> {
+        splats.sh_coeffs = splats.sh_coeffs.map(|coeffs| {
+            let [n, c, _] = coeffs.dims();
+            let base = coeffs.clone().slice(s![.., 0..1]) * SH_C0 + 0.5;
+            let mapped = self.apply(base.clone());
+            let dc = (mapped.clone() - 0.5) / SH_C0;
+            let coeffs = if c > 1 {
+                let scale = mapped / base.clamp_min(1e-4);
+                let rest = coeffs.slice(s![.., 1..]) * scale.expand([n, c - 1, 3]);
+                Tensor::cat(vec![dc, rest], 1)
+            } else {
+                dc
+            };
+            coeffs.detach().require_grad()
+        });
+        splats
+    }
+
+    /// Tonemap the colors of an image to 8 bits, eg. to save or show it. Alpha is kept as is.
+    pub fn apply_image(self, image: &DynamicImage) -> DynamicImage {
+        let mut rgba: Rgba32FImage = image.to_rgba32f();
+        for pixel in rgba.pixels_mut() {
+            for c in &mut pixel.0[0..3] {
+                *c = self.map(*c);
+            }
+        }
+        let mapped = DynamicImage::ImageRgba32F(rgba);
+        if image.color().has_alpha() {
+            DynamicImage::ImageRgba8(mapped.into_rgba8())
+        } else {
+            DynamicImage::ImageRgb8(mapped.into_rgb8())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::srgb_to_linear;
+
+    #[test]
+    fn curves_fit_display_range() {
+        for tonemap in [Tonemap::Clip, Tonemap::Reinhard, Tonemap::Aces] {
+            assert_eq!(tonemap.map(0.0), 0.0);
+            assert_eq!(tonemap.map(-1.0), 0.0);
+            let mut last = 0.0;
+            for i in 1..200 {
+                let value = tonemap.map(i as f32 * 0.1);
+                assert!(value >= last && value <= 1.0);
+                last = value;
+            }
+        }
+        // Clipping leaves regular colors as they would be without HDR.
+        for x in [0.001, 0.2, 0.5, 1.0] {
+            assert!((srgb_to_linear(Tonemap::Clip.map(x)) - x).abs() < 1e-5);
+        }
+        // Other curves keep highlights apart.
+        assert!(Tonemap::Reinhard.map(4.0) < Tonemap::Reinhard.map(8.0));
+    }
+}
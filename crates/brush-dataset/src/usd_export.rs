@@ -0,0 +1,226 @@
+// Export of splats and meshes to USD, for Omniverse, Houdini and AR Quick Look. Splats are written
+// as points, with the attributes needed to draw them as splats in custom primvars.
+use std::{fmt::Write as _, io::Write as _};
+
+use brush_render::{gaussian_splats::Splats, sh::sh_to_rgb};
+use burn::prelude::Backend;
+use glam::Vec3;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use crate::splat_export::export_data;
+
+// Name of the root layer in a USDZ package.
+const USDZ_ROOT: &str = "scene.usda";
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+// A USD array of `values`, each written with `item`.
+fn array<T>(values: &[T], mut item: impl FnMut(&mut String, &T)) -> String {
+    let mut out = String::from("[");
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            out += ", ";
+        }
+        item(&mut out, value);
+    }
+    out += "]";
+    out
+}
+
+fn vec3(out: &mut String, v: &Vec3) {
+    let _ = write!(out, "({}, {}, {})", v.x, v.y, v.z);
+}
+
+// A prim name from `name`, which may only have letters, digits and underscores.
+fn prim_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) || name.is_empty() {
+        format!("_{name}")
+    } else {
+        name
+    }
+}
+
+/// A USD stage of splats and meshes, written as text (`.usda`) or packaged as USDZ.
+///
+/// Everything is put under one root transform, which maps the frame of Brush exports, with y
+/// pointing down, to the y up frame of USD.
+#[derive(Debug, Clone, Default)]
+pub struct UsdStage {
+    prims: String,
+}
+
+impl UsdStage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add splats as a `Points` prim. Points get the color and opacity of the splats, and a width
+    /// of twice their largest scale.
+    ///
+    /// The attributes to draw them as splats are in custom primvars: `splat:orientation`,
+    /// `splat:scale` (not in log space), `splat:opacity`, and `splat:sh` with the SH coefficients
+    /// above degree 0 of each splat, as RGB triples.
+    pub async fn add_splats<B: Backend>(&mut self, name: &str, splats: Splats<B>) {
+        let data = export_data(splats, None, None).await;
+        let rest_per_channel = data.first().map_or(0, |s| s.sh_coeffs_rest.len() / 3);
+
+        let points: Vec<_> = data.iter().map(|s| s.mean).collect();
+        let scales: Vec<_> = data.iter().map(|s| s.log_scale.exp()).collect();
+        let colors: Vec<_> = data
+            .iter()
+            .map(|s| sh_to_rgb(s.sh_dc).clamp(Vec3::ZERO, Vec3::ONE))
+            .collect();
+        let opacities: Vec<_> = data.iter().map(|s| sigmoid(s.opacity)).collect();
+        let widths: Vec<_> = scales.iter().map(|s| s.max_element() * 2.0).collect();
+        let float = |out: &mut String, v: &f32| {
+            let _ = write!(out, "{v}");
+        };
+
+        let prims = &mut self.prims;
+        let _ = writeln!(prims, "    def Points \"{}\"", prim_name(name));
+        let _ = writeln!(prims, "    {{");
+        let _ = writeln!(prims, "        point3f[] points = {}", array(&points, vec3));
+        let _ = writeln!(prims, "        float[] widths = {}", array(&widths, float));
+        let vertex_primvar = |prims: &mut String, kind: &str, name: &str, values: String| {
+            let _ = writeln!(prims, "        {kind}[] primvars:{name} = {values} (");
+            let _ = writeln!(prims, "            interpolation = \"vertex\"");
+            let _ = writeln!(prims, "        )");
+        };
+        vertex_primvar(prims, "color3f", "displayColor", array(&colors, vec3));
+        vertex_primvar(prims, "float", "displayOpacity", array(&opacities, float));
+        // Quaternions in USD have the real part first.
+        let orientations = array(&data, |out, s| {
+            let q = s.rotation;
+            let _ = write!(out, "({}, {}, {}, {})", q.w, q.x, q.y, q.z);
+        });
+        vertex_primvar(prims, "quatf", "splat:orientation", orientations);
+        vertex_primvar(prims, "float3", "splat:scale", array(&scales, vec3));
+        vertex_primvar(prims, "float", "splat:opacity", array(&opacities, float));
+
+        if rest_per_channel > 0 {
+            // Coefficients are stored per channel, USD wants one RGB triple per coefficient.
+            let sh = array(&data, |out, s| {
+                for i in 0..rest_per_channel {
+                    if i > 0 {
+                        *out += ", ";
+                    }
+                    let [r, g, b] = [0, 1, 2].map(|c| s.sh_coeffs_rest[c * rest_per_channel + i]);
+                    vec3(out, &Vec3::new(r, g, b));
+                }
+            });
+            let _ = writeln!(prims, "        float3[] primvars:splat:sh = {sh} (");
+            let _ = writeln!(prims, "            elementSize = {rest_per_channel}");
+            let _ = writeln!(prims, "            interpolation = \"vertex\"");
+            let _ = writeln!(prims, "        )");
+        }
+        let _ = writeln!(prims, "    }}");
+    }
+
+    /// Add a triangle mesh with a color per vertex as a `Mesh` prim.
+    pub fn add_mesh(
+        &mut self,
+        name: &str,
+        positions: &[Vec3],
+        colors: &[Vec3],
+        triangles: &[[u32; 3]],
+    ) {
+        let int = |out: &mut String, v: &u32| {
+            let _ = write!(out, "{v}");
+        };
+        let indices: Vec<u32> = triangles.iter().flatten().copied().collect();
+
+        let prims = &mut self.prims;
+        let _ = writeln!(prims, "    def Mesh \"{}\"", prim_name(name));
+        let _ = writeln!(prims, "    {{");
+        let _ = writeln!(
+            prims,
+            "        int[] faceVertexCounts = {}",
+            array(&vec![3u32; triangles.len()], int)
+        );
+        let _ = writeln!(
+            prims,
+            "        int[] faceVertexIndices = {}",
+            array(&indices, int)
+        );
+        let _ = writeln!(
+            prims,
+            "        point3f[] points = {}",
+            array(positions, vec3)
+        );
+        let _ = writeln!(
+            prims,
+            "        color3f[] primvars:displayColor = {} (",
+            array(colors, vec3)
+        );
+        let _ = writeln!(prims, "            interpolation = \"vertex\"");
+        let _ = writeln!(prims, "        )");
+        // Faces are wound counter clockwise when seen from the outside.
+        let _ = writeln!(prims, "        uniform token orientation = \"rightHanded\"");
+        let _ = writeln!(prims, "        uniform token subdivisionScheme = \"none\"");
+        let _ = writeln!(prims, "    }}");
+    }
+
+    /// The stage as a text USD file.
+    pub fn to_usda(&self) -> Vec<u8> {
+        let mut out = String::new();
+        out += "#usda 1.0\n(\n";
+        out += "    defaultPrim = \"Scene\"\n";
+        out += "    doc = \"Exported from Brush\"\n";
+        out += "    metersPerUnit = 1\n";
+        out += "    upAxis = \"Y\"\n";
+        out += ")\n\n";
+        out += "def Xform \"Scene\"\n{\n";
+        // Brush exports have y pointing down.
+        out += "    float xformOp:rotateX = 180\n";
+        out += "    uniform token[] xformOpOrder = [\"xformOp:rotateX\"]\n\n";
+        out += &self.prims;
+        out += "}\n";
+        out.into_bytes()
+    }
+
+    /// The stage packaged as a USDZ file, with the text file as its root layer.
+    pub fn to_usdz(&self) -> std::io::Result<Vec<u8>> {
+        let mut zip = ZipWriter::new(std::io::Cursor::new(vec![]));
+        // Files in USDZ packages are uncompressed, and start at a multiple of 64 bytes.
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .with_alignment(64);
+        zip.start_file(USDZ_ROOT, options)
+            .map_err(std::io::Error::other)?;
+        zip.write_all(&self.to_usda())?;
+        Ok(zip.finish().map_err(std::io::Error::other)?.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_mesh() {
+        let mut stage = UsdStage::new();
+        stage.add_mesh(
+            "3d scan",
+            &[Vec3::ZERO, Vec3::X, Vec3::Y],
+            &[Vec3::ONE; 3],
+            &[[0, 1, 2]],
+        );
+        let usda = String::from_utf8(stage.to_usda()).expect("Valid text");
+        assert!(usda.starts_with("#usda 1.0\n"));
+        assert!(usda.contains("def Mesh \"_3d_scan\""));
+        assert!(usda.contains("int[] faceVertexIndices = [0, 1, 2]"));
+        assert!(usda.contains("point3f[] points = [(0, 0, 0), (1, 0, 0), (0, 1, 0)]"));
+        // Braces of the prims are balanced.
+        assert_eq!(usda.matches('{').count(), usda.matches('}').count());
+
+        let usdz = stage.to_usdz().expect("Valid package");
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(usdz)).expect("Valid zip");
+        assert_eq!(archive.file_names().collect::<Vec<_>>(), [USDZ_ROOT]);
+    }
+}
@@ -0,0 +1,195 @@
+use brush_render::{gaussian_splats::Splats, shaders::project_visible::SH_C0};
+use burn::prelude::Backend;
+use glam::Vec3;
+
+/// Nr. of payload bits embedded in splats.
+const PAYLOAD_BITS: u64 = 64;
+
+// Spacing of the lattice the brightness of splats is snapped to, in SH units. Embedding changes
+// colors by at most half of this, which is ~2/255 in rgb, while 8 bit quantization of colors
+// changes them by at most 0.5/255.
+const STEP: f32 = 0.06;
+
+/// An imperceptible payload embedded in the colors of splats, to trace where exported splats
+/// ended up.
+///
+/// Each splat carries one bit of the payload in its brightness using dither modulation, with the
+/// bit and dither picked from `key`. The payload is recovered by a majority vote over all splats,
+/// which survives mild quantization of colors. It does not survive removing or reordering splats,
+/// or retraining.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watermark {
+    pub payload: u64,
+    pub key: u64,
+}
+
+/// A payload read back from splats.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatermarkReading {
+    pub payload: u64,
+    /// How sure the weakest bit of the payload is, as a z-score. Splats without a watermark
+    /// give values well below 1, anything over 4 is a reliable reading.
+    pub confidence: f32,
+}
+
+// Splitmix64, simple and stable across platforms and versions.
+fn hash(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// The payload bit a splat carries, and its dither in [0, 1).
+fn splat_code(key: u64, index: usize) -> (u64, f32) {
+    let h = hash(key ^ hash(index as u64));
+    let bit = h % PAYLOAD_BITS;
+    let dither = (h >> 40) as f32 / (1u64 << 24) as f32;
+    (bit, dither)
+}
+
+impl Watermark {
+    /// Embed the payload in the DC colors of splats.
+    pub fn embed(&self, sh_dc: &mut [Vec3]) {
+        for (index, dc) in sh_dc.iter_mut().enumerate() {
+            let (bit, dither) = splat_code(self.key, index);
+            let offset = (dither + ((self.payload >> bit) & 1) as f32 * 0.5) * STEP;
+            let brightness = dc.element_sum() / 3.0;
+            let snapped = ((brightness - offset) / STEP).round() * STEP + offset;
+            *dc += Vec3::splat(snapped - brightness);
+        }
+    }
+
+    /// Read back a payload embedded with the given key. Returns None if there are no splats.
+    pub fn read(key: u64, sh_dc: &[Vec3]) -> Option<WatermarkReading> {
+        let mut votes = [0.0f32; PAYLOAD_BITS as usize];
+        let mut counts = [0u32; PAYLOAD_BITS as usize];
+
+        for (index, dc) in sh_dc.iter().enumerate() {
+            let (bit, dither) = splat_code(key, index);
+            let brightness = dc.element_sum() / 3.0;
+            // Phase of the brightness on the lattice, 0 for a zero bit and 0.5 for a one bit.
+            let phase = (brightness / STEP - dither).rem_euclid(1.0);
+            votes[bit as usize] += (phase * std::f32::consts::TAU).cos();
+            counts[bit as usize] += 1;
+        }
+
+        let mut payload = 0;
+        let mut confidence = f32::INFINITY;
+        for (bit, (vote, count)) in votes.iter().zip(counts).enumerate() {
+            if *vote < 0.0 {
+                payload |= 1 << bit;
+            }
+            // Without a watermark the phase is uniform, so each vote has a variance of 0.5.
+            let z = if count > 0 {
+                vote.abs() / (0.5 * count as f32).sqrt()
+            } else {
+                0.0
+            };
+            confidence = confidence.min(z);
+        }
+
+        (!sh_dc.is_empty()).then_some(WatermarkReading {
+            payload,
+            confidence,
+        })
+    }
+
+    /// Read back a payload embedded in splats with the given key.
+    pub async fn read_splats<B: Backend>(key: u64, splats: &Splats<B>) -> Option<WatermarkReading> {
+        let n = splats.num_splats() as usize;
+        let dc = splats
+            .sh_coeffs
+            .val()
+            .slice([0..n, 0..1])
+            .into_data_async()
+            .await
+            .into_vec::<f32>()
+            .expect("Wrong type");
+        let dc: Vec<_> = dc.chunks_exact(3).map(Vec3::from_slice).collect();
+        Self::read(key, &dc)
+    }
+}
+
+/// Blend an image onto the bottom right corner of a frame, eg. a studio logo on renders.
+///
+/// The watermark is scaled to a fifth of the frame width.
+pub fn overlay_watermark(frame: &mut image::RgbImage, mark: &image::RgbaImage, opacity: f32) {
+    if mark.width() == 0 || mark.height() == 0 {
+        return;
+    }
+    let width = (frame.width() / 5).max(1);
+    let height = ((mark.height() as f32 * width as f32 / mark.width() as f32).round() as u32)
+        .clamp(1, frame.height());
+    let mark = image::imageops::resize(mark, width, height, image::imageops::FilterType::Triangle);
+
+    // Keep a small margin to the edges of the frame.
+    let margin = frame.width() / 50;
+    let x0 = frame.width().saturating_sub(width + margin);
+    let y0 = frame.height().saturating_sub(height + margin);
+
+    for (x, y, pixel) in mark.enumerate_pixels() {
+        let (fx, fy) = (x0 + x, y0 + y);
+        if fx >= frame.width() || fy >= frame.height() {
+            continue;
+        }
+        let alpha = pixel[3] as f32 / 255.0 * opacity.clamp(0.0, 1.0);
+        let out = frame.get_pixel_mut(fx, fy);
+        for c in 0..3 {
+            out[c] = (out[c] as f32 * (1.0 - alpha) + pixel[c] as f32 * alpha).round() as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host_colors(n: usize) -> Vec<Vec3> {
+        (0..n)
+            .map(|i| {
+                let h = hash(i as u64 + 12345);
+                let c = |shift: u32| ((h >> shift) & 0xffff) as f32 / 65535.0 * 3.0 - 1.5;
+                Vec3::new(c(0), c(16), c(32))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn survives_color_quantization() {
+        let watermark = Watermark {
+            payload: 0xdead_beef_1234_5678,
+            key: 42,
+        };
+        let mut dc = host_colors(8192);
+        watermark.embed(&mut dc);
+
+        // Round trip through 8 bit rgb colors.
+        for c in &mut dc {
+            let rgb = (*c * SH_C0 + 0.5) * 255.0;
+            *c = (rgb.round() / 255.0 - 0.5) / SH_C0;
+        }
+
+        let reading = Watermark::read(watermark.key, &dc).expect("Has splats");
+        assert_eq!(reading.payload, watermark.payload);
+        assert!(reading.confidence > 4.0, "{reading:?}");
+
+        // Without the key, there's nothing to find.
+        let reading = Watermark::read(7, &dc).expect("Has splats");
+        assert!(reading.confidence < 4.0, "{reading:?}");
+    }
+
+    #[test]
+    fn changes_are_small() {
+        let original = host_colors(1000);
+        let mut dc = original.clone();
+        Watermark {
+            payload: !0,
+            key: 1,
+        }
+        .embed(&mut dc);
+        for (a, b) in original.iter().zip(&dc) {
+            assert!(((*a - *b) * SH_C0).abs().max_element() <= STEP * 0.5 * SH_C0 + 1e-6);
+        }
+    }
+}
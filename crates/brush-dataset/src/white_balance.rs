@@ -0,0 +1,104 @@
+use glam::Vec3;
+
+use crate::scene::SceneView;
+
+// Size of the thumbnail used to estimate the average color of a view. The exact
+// value doesn't matter much, it only needs to be big enough to be a stable average.
+const THUMBNAIL_SIZE: u32 = 64;
+
+/// Estimate the chromaticity (average rgb, normalized to sum to 1) of a view.
+///
+/// This is used as a cheap proxy for the white balance the camera used for this frame.
+pub async fn view_chromaticity(view: &SceneView) -> image::ImageResult<Vec3> {
+    let img = view.image.load().await?;
+    let thumb = img.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).into_rgba32f();
+
+    let mut sum = Vec3::ZERO;
+    let mut weight = 0.0;
+    for pixel in thumb.pixels() {
+        // Ignore masked out or transparent pixels, and clipped highlights which carry no color information.
+        let alpha = pixel[3];
+        let rgb = glam::vec3(pixel[0], pixel[1], pixel[2]);
+        if alpha <= 0.0 || rgb.max_element() >= 0.99 {
+            continue;
+        }
+        sum += rgb * alpha;
+        weight += alpha;
+    }
+
+    if weight <= 0.0 || sum.element_sum() <= 0.0 {
+        return Ok(Vec3::splat(1.0 / 3.0));
+    }
+    Ok(sum / sum.element_sum())
+}
+
+/// Cluster temporally adjacent views into white balance groups.
+///
+/// Views are assumed to be in capture order. A new group is started whenever
+/// a frame's chromaticity jumps away from the rolling average of the current group
+/// by more than `threshold`. Gradual drift (eg. walking from shade into sunlight) is
+/// followed by the rolling average and does not start a new group, whereas the
+/// abrupt jumps of a camera's auto white balance do.
+///
+/// Returns the group index for each view, and the number of groups.
+pub fn cluster_wb_groups(chromaticities: &[Vec3], threshold: f32) -> (Vec<u32>, u32) {
+    // How quickly the rolling average follows new frames.
+    const ROLLING_WEIGHT: f32 = 0.25;
+
+    let mut groups = Vec::with_capacity(chromaticities.len());
+    let mut cur_group = 0;
+    let mut rolling: Option<Vec3> = None;
+
+    for &chroma in chromaticities {
+        match rolling {
+            Some(avg) if avg.distance(chroma) > threshold => {
+                cur_group += 1;
+                rolling = Some(chroma);
+            }
+            Some(avg) => {
+                rolling = Some(avg.lerp(chroma, ROLLING_WEIGHT));
+            }
+            None => {
+                rolling = Some(chroma);
+            }
+        }
+        groups.push(cur_group);
+    }
+
+    let count = if chromaticities.is_empty() {
+        0
+    } else {
+        cur_group + 1
+    };
+    (groups, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clusters_abrupt_changes() {
+        let warm = glam::vec3(0.4, 0.33, 0.27);
+        let cool = glam::vec3(0.3, 0.33, 0.37);
+        let chroma = [warm, warm, warm * 1.01, cool, cool, warm];
+        let (groups, count) = cluster_wb_groups(&chroma, 0.05);
+        assert_eq!(groups, vec![0, 0, 0, 1, 1, 2]);
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn follows_gradual_drift() {
+        let chroma: Vec<_> = (0..20)
+            .map(|i| glam::vec3(0.33 + i as f32 * 0.005, 0.33, 0.34 - i as f32 * 0.005))
+            .collect();
+        let (groups, count) = cluster_wb_groups(&chroma, 0.05);
+        assert!(groups.iter().all(|&g| g == 0));
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn empty_has_no_groups() {
+        assert_eq!(cluster_wb_groups(&[], 0.05), (vec![], 0));
+    }
+}
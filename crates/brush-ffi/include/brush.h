@@ -0,0 +1,81 @@
+/*
+ * C API to render Gaussian splats with brush.
+ *
+ * Build with `cargo build -p brush-ffi --release`, and link the brush_ffi shared or static library
+ * from target/release.
+ *
+ *     BrushContext *ctx = brush_context_create();
+ *     if (!ctx || brush_load_ply(ctx, "scene.ply") != BRUSH_OK) {
+ *         fprintf(stderr, "%s\n", brush_last_error());
+ *     }
+ *     BrushCamera camera = {{0, 0, -5}, {0, 0, 0, 1}, 0.8f};
+ *     brush_set_camera(ctx, &camera);
+ *     brush_render(ctx, width, height, pixels, width * height * 4);
+ *     brush_context_destroy(ctx);
+ *
+ * Functions can be called from any thread, but a context should only be used by one thread at a
+ * time. Renders are read back to the CPU, to upload to a texture of the host.
+ */
+#ifndef BRUSH_H
+#define BRUSH_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct BrushContext BrushContext;
+
+/* Whether a call succeeded. When it didn't, brush_last_error says why. */
+typedef enum BrushResult {
+    BRUSH_OK = 0,
+    /* A pointer was null, or a value out of range. */
+    BRUSH_INVALID_ARGUMENT = 1,
+    /* Rendering before any splats were loaded. */
+    BRUSH_NO_SPLATS = 2,
+    BRUSH_FAILED = 3,
+} BrushResult;
+
+/* A camera, in the conventions of brush: it looks along +Z, with +Y down and +X to the right. */
+typedef struct BrushCamera {
+    float position[3];
+    /* Camera to world rotation, as a quaternion in x, y, z, w order. */
+    float rotation[4];
+    /* Vertical field of view in radians. The horizontal one follows from the size of a render. */
+    float fov_y;
+} BrushCamera;
+
+/* Message of the last call on this thread that failed. Valid until the next failing call on this
+ * thread. */
+const char *brush_last_error(void);
+
+/* Create a context, which sets up the GPU on its first call. Returns NULL if that fails. */
+BrushContext *brush_context_create(void);
+
+/* Destroy a context and the splats it holds. */
+void brush_context_destroy(BrushContext *ctx);
+
+/* Load splats from a ply file, replacing the splats of the context. */
+BrushResult brush_load_ply(BrushContext *ctx, const char *path);
+
+/* Load splats from the bytes of a ply file, for hosts that have their own file system. */
+BrushResult brush_load_ply_bytes(BrushContext *ctx, const uint8_t *data, size_t len);
+
+/* Number of splats the context holds, 0 if none were loaded. */
+uint32_t brush_num_splats(BrushContext *ctx);
+
+/* Set the camera to render from. */
+BrushResult brush_set_camera(BrushContext *ctx, const BrushCamera *camera);
+
+/* Render the splats into pixels, as width * height RGBA8 pixels with premultiplied alpha, row by
+ * row from the top. len is the size of pixels in bytes. Waits for the GPU to finish. */
+BrushResult brush_render(BrushContext *ctx, uint32_t width, uint32_t height, uint8_t *pixels,
+                         size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,433 @@
+// A C API to embed the renderer in engines and native apps, without linking Rust directly.
+//
+// The declarations are in include/brush.h. All functions can be called from any thread, but a
+// context should only be used by one thread at a time.
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char},
+    fmt::Display,
+    panic::{AssertUnwindSafe, catch_unwind},
+    sync::OnceLock,
+};
+
+use brush_dataset::splat_import::load_splat_from_ply;
+use brush_render::{
+    MainBackend, burn_init_setup,
+    camera::{Camera, focal_to_fov, fov_to_focal},
+    gaussian_splats::Splats,
+};
+use burn_wgpu::WgpuDevice;
+use glam::{Quat, UVec2, Vec3};
+use tokio::{io::AsyncRead, runtime::Runtime};
+use tokio_stream::StreamExt;
+
+/// Whether a call succeeded. When it didn't, `brush_last_error` says why.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrushResult {
+    Ok = 0,
+    /// A pointer was null, or a value out of range.
+    InvalidArgument = 1,
+    /// Rendering before any splats were loaded.
+    NoSplats = 2,
+    Failed = 3,
+}
+
+/// A camera, in the conventions of brush: it looks along +Z, with +Y down and +X to the right.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BrushCamera {
+    pub position: [f32; 3],
+    /// Camera to world rotation, as a quaternion in x, y, z, w order.
+    pub rotation: [f32; 4],
+    /// Vertical field of view in radians. The horizontal one follows from the size of a render.
+    pub fov_y: f32,
+}
+
+impl Default for BrushCamera {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 0.0, -5.0],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            fov_y: 0.8,
+        }
+    }
+}
+
+impl BrushCamera {
+    fn to_camera(self, size: UVec2) -> Camera {
+        let fov_y = self.fov_y as f64;
+        // Keep pixels square.
+        let fov_x = focal_to_fov(fov_to_focal(fov_y, size.y), size.x);
+        Camera::new(
+            Vec3::from_array(self.position),
+            Quat::from_array(self.rotation).normalize(),
+            fov_x,
+            fov_y,
+            glam::vec2(0.5, 0.5),
+        )
+    }
+}
+
+/// Splats loaded on the GPU, and the camera to render them from.
+pub struct BrushContext {
+    device: WgpuDevice,
+    splats: Option<Splats<MainBackend>>,
+    camera: BrushCamera,
+}
+
+struct Error {
+    result: BrushResult,
+    message: String,
+}
+
+impl Error {
+    fn invalid(message: &str) -> Self {
+        Self {
+            result: BrushResult::InvalidArgument,
+            message: message.to_owned(),
+        }
+    }
+
+    fn failed(error: impl Display) -> Self {
+        Self {
+            result: BrushResult::Failed,
+            message: error.to_string(),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+// Runs a call, turning errors and panics into a result, as panics can't unwind into C.
+fn guard(call: impl FnOnce() -> Result<(), Error>) -> BrushResult {
+    match catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(())) => BrushResult::Ok,
+        Ok(Err(error)) => {
+            set_last_error(error.message);
+            error.result
+        }
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| (*s).to_owned())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "Unknown panic".to_owned());
+            set_last_error(format!("Brush panicked: {message}"));
+            BrushResult::Failed
+        }
+    }
+}
+
+// One runtime for all contexts, they only need it to wait for the GPU and files.
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to start the async runtime")
+    })
+}
+
+fn device() -> WgpuDevice {
+    static DEVICE: OnceLock<WgpuDevice> = OnceLock::new();
+    DEVICE
+        .get_or_init(|| runtime().block_on(burn_init_setup()))
+        .clone()
+}
+
+/// # Safety
+///
+/// `ctx` has to be null or a context from `brush_context_create` that wasn't destroyed.
+unsafe fn context<'a>(ctx: *mut BrushContext) -> Result<&'a mut BrushContext, Error> {
+    // SAFETY: Either null or a valid context, as required by the caller.
+    unsafe { ctx.as_mut() }.ok_or_else(|| Error::invalid("The context is null"))
+}
+
+async fn read_splats(
+    reader: impl AsyncRead + Send + Unpin + 'static,
+    device: WgpuDevice,
+) -> Result<Splats<MainBackend>, Error> {
+    let mut stream = std::pin::pin!(load_splat_from_ply(reader, None, device));
+    let mut splats = None;
+    while let Some(message) = stream.next().await {
+        let message = message.map_err(Error::failed)?;
+        // Animated plys continue with the next frames, only the first one is rendered.
+        if message.meta.current_frame > 0 {
+            break;
+        }
+        splats = Some(message.splats);
+    }
+    splats.ok_or_else(|| Error::failed("The ply has no splats"))
+}
+
+/// Message of the last call on this thread that failed. Valid until the next failing call on
+/// this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn brush_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// Create a context, which sets up the GPU on its first call. Returns null if that fails.
+#[unsafe(no_mangle)]
+pub extern "C" fn brush_context_create() -> *mut BrushContext {
+    let mut ctx = std::ptr::null_mut();
+    guard(|| {
+        let context = BrushContext {
+            device: device(),
+            splats: None,
+            camera: BrushCamera::default(),
+        };
+        ctx = Box::into_raw(Box::new(context));
+        Ok(())
+    });
+    ctx
+}
+
+/// Destroy a context and the splats it holds.
+///
+/// # Safety
+///
+/// `ctx` has to be null or a context from `brush_context_create` that wasn't destroyed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn brush_context_destroy(ctx: *mut BrushContext) {
+    if !ctx.is_null() {
+        // SAFETY: The context was made by Box::into_raw, and isn't used after this.
+        drop(unsafe { Box::from_raw(ctx) });
+    }
+}
+
+/// Load splats from a ply file at `path`, replacing the splats of the context.
+///
+/// # Safety
+///
+/// `ctx` has to be null or a valid context, and `path` null or a nul terminated UTF-8 string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn brush_load_ply(
+    ctx: *mut BrushContext,
+    path: *const c_char,
+) -> BrushResult {
+    guard(|| {
+        // SAFETY: Valid as required by the caller.
+        let ctx = unsafe { context(ctx) }?;
+        if path.is_null() {
+            return Err(Error::invalid("The path is null"));
+        }
+        // SAFETY: Not null, and nul terminated as required by the caller.
+        let path = unsafe { CStr::from_ptr(path) }
+            .to_str()
+            .map_err(|_e| Error::invalid("The path isn't valid UTF-8"))?;
+        let device = ctx.device.clone();
+        let splats = runtime().block_on(async move {
+            let file = tokio::fs::File::open(path)
+                .await
+                .map_err(|e| Error::failed(format!("Failed to open {path}: {e}")))?;
+            read_splats(file, device).await
+        })?;
+        ctx.splats = Some(splats);
+        Ok(())
+    })
+}
+
+/// Load splats from the bytes of a ply file, for hosts that have their own file system.
+///
+/// # Safety
+///
+/// `ctx` has to be null or a valid context, and `data` null or valid for reads of `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn brush_load_ply_bytes(
+    ctx: *mut BrushContext,
+    data: *const u8,
+    len: usize,
+) -> BrushResult {
+    guard(|| {
+        // SAFETY: Valid as required by the caller.
+        let ctx = unsafe { context(ctx) }?;
+        if data.is_null() {
+            return Err(Error::invalid("The data is null"));
+        }
+        // SAFETY: Not null, and valid for `len` bytes as required by the caller.
+        let data = unsafe { std::slice::from_raw_parts(data, len) }.to_vec();
+        let splats =
+            runtime().block_on(read_splats(std::io::Cursor::new(data), ctx.device.clone()))?;
+        ctx.splats = Some(splats);
+        Ok(())
+    })
+}
+
+/// Number of splats the context holds, 0 if none were loaded.
+///
+/// # Safety
+///
+/// `ctx` has to be null or a valid context.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn brush_num_splats(ctx: *mut BrushContext) -> u32 {
+    // SAFETY: Valid as required by the caller.
+    unsafe { context(ctx) }
+        .ok()
+        .and_then(|ctx| ctx.splats.as_ref())
+        .map_or(0, Splats::num_splats)
+}
+
+/// Set the camera to render from.
+///
+/// # Safety
+///
+/// `ctx` has to be null or a valid context, and `camera` null or a valid camera.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn brush_set_camera(
+    ctx: *mut BrushContext,
+    camera: *const BrushCamera,
+) -> BrushResult {
+    guard(|| {
+        // SAFETY: Valid as required by the caller.
+        let ctx = unsafe { context(ctx) }?;
+        // SAFETY: Null or valid as required by the caller.
+        let camera =
+            *unsafe { camera.as_ref() }.ok_or_else(|| Error::invalid("The camera is null"))?;
+        if !(camera.fov_y > 0.0 && camera.fov_y < std::f32::consts::PI) {
+            return Err(Error::invalid(
+                "The field of view has to be between 0 and pi",
+            ));
+        }
+        if !Quat::from_array(camera.rotation).length().is_normal() {
+            return Err(Error::invalid(
+                "The rotation has to be a non zero quaternion",
+            ));
+        }
+        ctx.camera = camera;
+        Ok(())
+    })
+}
+
+/// Render the splats into `pixels`, as `width` * `height` RGBA8 pixels with premultiplied alpha,
+/// row by row from the top. Waits for the GPU to finish.
+///
+/// # Safety
+///
+/// `ctx` has to be null or a valid context, and `pixels` null or valid for writes of `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn brush_render(
+    ctx: *mut BrushContext,
+    width: u32,
+    height: u32,
+    pixels: *mut u8,
+    len: usize,
+) -> BrushResult {
+    guard(|| {
+        // SAFETY: Valid as required by the caller.
+        let ctx = unsafe { context(ctx) }?;
+        if width == 0 || height == 0 {
+            return Err(Error::invalid("The image has to be at least 1x1"));
+        }
+        let needed = width as usize * height as usize * 4;
+        if pixels.is_null() || len < needed {
+            return Err(Error::invalid(&format!(
+                "The pixel buffer has to be at least {needed} bytes"
+            )));
+        }
+        let Some(splats) = &ctx.splats else {
+            return Err(Error {
+                result: BrushResult::NoSplats,
+                message: "No splats were loaded".to_owned(),
+            });
+        };
+
+        let size = glam::uvec2(width, height);
+        let (img, _) = splats.render(&ctx.camera.to_camera(size), size, false);
+        // Without a float buffer, the render is packed RGBA8, in the byte order of the pixels.
+        let data = runtime().block_on(img.into_data_async());
+        let packed = data.as_bytes();
+        if packed.len() != needed {
+            return Err(Error::failed(format!(
+                "The render has {} bytes instead of {needed}",
+                packed.len()
+            )));
+        }
+
+        // SAFETY: Not null, and valid for `len` bytes as required by the caller.
+        let pixels = unsafe { std::slice::from_raw_parts_mut(pixels, needed) };
+        pixels.copy_from_slice(packed);
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_arguments_are_reported() {
+        let mut pixels = [0u8; 4];
+        // SAFETY: Null contexts are allowed.
+        let result = unsafe { brush_render(std::ptr::null_mut(), 1, 1, pixels.as_mut_ptr(), 4) };
+        assert_eq!(result, BrushResult::InvalidArgument);
+        // SAFETY: Points at the thread local error string.
+        let error = unsafe { CStr::from_ptr(brush_last_error()) };
+        assert_eq!(error.to_str(), Ok("The context is null"));
+
+        // SAFETY: Null contexts are allowed.
+        assert_eq!(unsafe { brush_num_splats(std::ptr::null_mut()) }, 0);
+    }
+
+    #[test]
+    fn renders_into_pixels() {
+        let ctx = brush_context_create();
+        assert!(!ctx.is_null(), "Failed to create a context");
+        // SAFETY: Made just above, and not null.
+        let context = unsafe { &mut *ctx };
+        // A big opaque splat in front of the default camera.
+        context.splats = Some(Splats::from_raw(
+            &[Vec3::ZERO],
+            None,
+            Some(&[Vec3::splat(0.0)]),
+            Some(&[1.0, 1.0, 1.0]),
+            Some(&[5.0]),
+            &context.device,
+        ));
+
+        let (width, height) = (16, 8);
+        let mut pixels = vec![0u8; width * height * 4];
+        // SAFETY: The context is valid, and the buffer has `len` bytes.
+        let result = unsafe {
+            brush_render(
+                ctx,
+                width as u32,
+                height as u32,
+                pixels.as_mut_ptr(),
+                pixels.len(),
+            )
+        };
+        assert_eq!(result, BrushResult::Ok);
+        // The center of the image is covered by the splat.
+        let center = ((height / 2) * width + width / 2) * 4;
+        let alpha = pixels[center + 3];
+        assert!(alpha > 200, "Center alpha is {alpha}");
+        assert!(
+            pixels[center] > 100,
+            "Center is {:?}",
+            &pixels[center..center + 4]
+        );
+
+        // SAFETY: Not used after this.
+        unsafe { brush_context_destroy(ctx) };
+    }
+
+    #[test]
+    fn cameras_keep_pixels_square() {
+        let camera = BrushCamera::default().to_camera(glam::uvec2(200, 100));
+        let focal = camera.focal(glam::uvec2(200, 100));
+        assert!(
+            (focal.x - focal.y).abs() < 1e-3,
+            "Focal lengths differ: {focal}"
+        );
+    }
+}
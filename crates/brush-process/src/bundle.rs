@@ -0,0 +1,317 @@
+// Packs the cameras and small copies of the images of a dataset into a zip, with the config and
+// logs of a run, so problems with a dataset can be reproduced without sharing the dataset itself.
+use std::{
+    io::{Cursor, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use anyhow::Context;
+use brush_dataset::{
+    health::check_health,
+    scene::SceneView,
+    scene_transform::{CoordinateConvention, SceneTransform},
+    splat_export::splat_to_ply,
+};
+use brush_render::camera::{Camera, CameraModel, fov_to_focal};
+use brush_vfs::DataSource;
+use burn_wgpu::WgpuDevice;
+use clap::Args;
+use glam::Mat4;
+use image::{DynamicImage, ImageFormat, imageops::FilterType};
+use serde::Serialize;
+use tokio_stream::StreamExt;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use crate::config::ProcessArgs;
+
+const IMAGE_SIZE: u32 = 512;
+const ANONYMIZED_IMAGE_SIZE: u32 = 32;
+
+#[derive(Args, Clone, Debug)]
+pub struct BundleArgs {
+    /// Dataset to bundle (path or URL).
+    #[arg(value_name = "PATH_OR_URL")]
+    pub source: DataSource,
+    /// Where to write the bundle.
+    #[arg(long, short, default_value = "brush_bundle.zip")]
+    pub out: PathBuf,
+    /// Make the bundle safe to share publicly: images are shrunk until nothing can be recognized,
+    /// files are renamed, the initial points are left out and paths are scrubbed from the logs and
+    /// config.
+    #[arg(long, default_value = "false")]
+    pub anonymize: bool,
+    /// Largest side of the bundled images. Defaults to 512, or 32 when anonymizing.
+    #[arg(long)]
+    pub image_size: Option<u32>,
+    /// Log of the failing run to include.
+    #[arg(long)]
+    pub log: Option<PathBuf>,
+}
+
+/// What ended up in a bundle.
+#[derive(Debug, Clone, Copy)]
+pub struct BundleSummary {
+    pub num_views: usize,
+    pub size: usize,
+}
+
+// A view as a nerfstudio frame, which Brush loads back like any dataset.
+#[derive(Serialize)]
+struct BundleFrame {
+    file_path: String,
+    transform_matrix: [[f32; 4]; 4],
+    w: u32,
+    h: u32,
+    fl_x: f64,
+    fl_y: f64,
+    cx: f64,
+    cy: f64,
+}
+
+#[derive(Serialize)]
+struct BundleScene {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ply_file_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    camera_model: Option<&'static str>,
+    frames: Vec<BundleFrame>,
+}
+
+// Camera to world matrix of a view in the original frame of the dataset, in the OpenGL
+// convention of nerfstudio, as rows.
+fn transform_matrix(camera: &Camera, transform: &SceneTransform) -> [[f32; 4]; 4] {
+    let camera = transform.inverse().transform_camera(camera);
+    let rotation = CoordinateConvention::OpenGl.camera_from_brush(camera.rotation);
+    Mat4::from_rotation_translation(rotation, camera.position)
+        .transpose()
+        .to_cols_array_2d()
+}
+
+fn view_file_name(view: &SceneView, index: usize, anonymize: bool) -> String {
+    let name = view.image.path.file_stem().map_or_else(
+        || format!("{index:04}"),
+        |s| s.to_string_lossy().into_owned(),
+    );
+    if anonymize {
+        format!("{index:04}.png")
+    } else {
+        // Keep the index, names aren't unique across folders.
+        format!("{index:04}_{name}.png")
+    }
+}
+
+// Remove what could identify the user from the text of a log: the source, and the file names
+// of the images.
+fn scrub_log(log: &str, source: &DataSource, renames: &[(String, String)]) -> String {
+    let mut log = log.to_owned();
+    if let DataSource::Path(s) | DataSource::Url(s) | DataSource::CachedUrl(s) = source {
+        log = log.replace(s.as_str(), "<dataset>");
+    }
+    for (original, renamed) in renames {
+        log = log.replace(original.as_str(), renamed);
+    }
+    log
+}
+
+// The config of the run, without secrets, and without local paths when anonymizing.
+fn scrub_config(process_args: &ProcessArgs, anonymize: bool) -> ProcessArgs {
+    let mut args = process_args.clone();
+    args.process_config.passphrase = None;
+    args.process_config.export_passphrase = None;
+    if anonymize {
+        args.process_config.export_path = String::from(".");
+        args.load_config.blur_detector = None;
+        args.load_config.sky_segmenter = None;
+        args.load_config.image_cache = None;
+        args.load_config.init_points = None;
+        args.render_config.render_path = None;
+        args.mesh_config.mesh_out = None;
+        args.sfm_config.colmap_binary = None;
+        args.sfm_config.glomap_binary = None;
+        args.sfm_config.sfm_export = None;
+    }
+    args
+}
+
+fn encode_png(image: &DynamicImage) -> anyhow::Result<Vec<u8>> {
+    let mut data = Cursor::new(vec![]);
+    image.write_to(&mut data, ImageFormat::Png)?;
+    Ok(data.into_inner())
+}
+
+/// Bundle the dataset of `args` as loaded with `process_args` into a zip, to attach to bug reports.
+///
+/// The bundle opens in Brush as a nerfstudio dataset, with the same cameras as the original.
+pub async fn create_bundle(
+    args: &BundleArgs,
+    process_args: &ProcessArgs,
+    device: &WgpuDevice,
+) -> anyhow::Result<BundleSummary> {
+    let vfs = Arc::new(args.source.clone().into_vfs().await?);
+    let (init_stream, dataset) =
+        brush_dataset::load_dataset(vfs, &process_args.load_config, device).await?;
+    let image_size = args.image_size.unwrap_or(if args.anonymize {
+        ANONYMIZED_IMAGE_SIZE
+    } else {
+        IMAGE_SIZE
+    });
+
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    // Pngs are compressed already.
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    let init_ply = if args.anonymize {
+        None
+    } else {
+        let mut init_stream = std::pin::pin!(init_stream);
+        let mut init = None;
+        while let Some(message) = init_stream.next().await {
+            init = Some(message?.splats);
+        }
+        match init {
+            Some(splats) => {
+                let splats = dataset.transform.inverse().transform_splats(splats);
+                zip.start_file("init.ply", deflated)?;
+                zip.write_all(&splat_to_ply(splats).await?)?;
+                Some("init.ply".to_owned())
+            }
+            None => None,
+        }
+    };
+
+    let mut renames = vec![];
+    let mut index = 0;
+    let splits = [
+        ("train", Some(&dataset.train)),
+        ("val", dataset.eval.as_ref()),
+    ];
+    for (split, scene) in splits {
+        let Some(scene) = scene.filter(|scene| !scene.views.is_empty()) else {
+            continue;
+        };
+        let mut frames = vec![];
+        for view in scene.views.iter() {
+            let image = view
+                .image
+                .load()
+                .await
+                .with_context(|| format!("Failed to load {:?}", view.image.path))?;
+            let image = if image.width().max(image.height()) > image_size {
+                image.resize(image_size, image_size, FilterType::Triangle)
+            } else {
+                image
+            };
+            let file_name = view_file_name(view, index, args.anonymize);
+            index += 1;
+
+            zip.start_file(format!("images/{file_name}"), stored)?;
+            zip.write_all(&encode_png(&image)?)?;
+
+            let (w, h) = (image.width(), image.height());
+            let camera = &view.camera;
+            frames.push(BundleFrame {
+                file_path: format!("images/{file_name}"),
+                transform_matrix: transform_matrix(camera, &dataset.transform),
+                w,
+                h,
+                fl_x: fov_to_focal(camera.fov_x, w),
+                fl_y: fov_to_focal(camera.fov_y, h),
+                cx: f64::from(camera.center_uv.x) * f64::from(w),
+                cy: f64::from(camera.center_uv.y) * f64::from(h),
+            });
+            if let Some(original) = view.image.path.file_name() {
+                renames.push((original.to_string_lossy().into_owned(), file_name));
+            }
+        }
+
+        let scene = BundleScene {
+            ply_file_path: init_ply.clone().filter(|_| split == "train"),
+            camera_model: scene
+                .views
+                .iter()
+                .any(|v| v.camera.model == CameraModel::Equirectangular)
+                .then_some("EQUIRECTANGULAR"),
+            frames,
+        };
+        zip.start_file(format!("transforms_{split}.json"), deflated)?;
+        zip.write_all(serde_json::to_string_pretty(&scene)?.as_bytes())?;
+    }
+
+    let config = scrub_config(process_args, args.anonymize);
+    zip.start_file("config.json", deflated)?;
+    zip.write_all(serde_json::to_string_pretty(&config)?.as_bytes())?;
+
+    let issues: String = check_health(&dataset)
+        .iter()
+        .map(|issue| format!("- {}\n", issue.advice()))
+        .collect();
+    let report = format!(
+        "Bundled by Brush {} with {index} views, {}anonymized.\n{issues}",
+        env!("CARGO_PKG_VERSION"),
+        if args.anonymize { "" } else { "not " }
+    );
+    zip.start_file("report.txt", deflated)?;
+    zip.write_all(report.as_bytes())?;
+
+    if let Some(log_path) = &args.log {
+        let log = tokio::fs::read_to_string(log_path)
+            .await
+            .with_context(|| format!("Failed to read log {log_path:?}"))?;
+        let log = if args.anonymize {
+            scrub_log(&log, &args.source, &renames)
+        } else {
+            log
+        };
+        zip.start_file("log.txt", deflated)?;
+        zip.write_all(log.as_bytes())?;
+    }
+
+    let data = zip.finish()?.into_inner();
+    rrfd::write_atomic(&args.out, &data).await?;
+    Ok(BundleSummary {
+        num_views: index,
+        size: data.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::{Quat, Vec3};
+
+    #[test]
+    fn transform_matrix_reads_back() {
+        let rotation = Quat::from_euler(glam::EulerRot::XYZ, 0.4, -0.9, 0.2);
+        let position = Vec3::new(1.0, 2.0, -3.0);
+        let transform = SceneTransform {
+            scale: 2.0,
+            rotation: Quat::from_rotation_y(0.5),
+            translation: Vec3::new(0.5, 0.0, 1.0),
+        };
+        let camera = Camera::new(position, rotation, 0.8, 0.6, glam::vec2(0.5, 0.5));
+
+        // Read the matrix the way the nerfstudio loader does.
+        let rows = transform_matrix(&camera, &transform);
+        let flat: Vec<f32> = rows.iter().flatten().copied().collect();
+        let matrix = Mat4::from_cols_slice(&flat).transpose();
+        let (_, read_rotation, read_position) = matrix.to_scale_rotation_translation();
+        let read_rotation = CoordinateConvention::OpenGl.camera_to_brush(read_rotation);
+
+        let original = transform.inverse().transform_camera(&camera);
+        assert!(read_position.distance(original.position) < 1e-4);
+        assert!(read_rotation.angle_between(original.rotation) < 1e-4);
+    }
+
+    #[test]
+    fn log_is_scrubbed() {
+        let source = DataSource::Path("/home/someone/scans/garden".to_owned());
+        let renames = vec![("DSC_0042.JPG".to_owned(), "0003.png".to_owned())];
+        let log = "Loading /home/someone/scans/garden/images/DSC_0042.JPG";
+        assert_eq!(
+            scrub_log(log, &source, &renames),
+            "Loading <dataset>/images/0003.png"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use brush_dataset::scene::{Scene, SceneView};
+use brush_render::{MainBackend, gaussian_splats::Splats};
+use burn::tensor::{Bool, Tensor, TensorData};
+use glam::{Vec2, Vec3};
+use rand::Rng;
+
+// Chunks with fewer cameras than this borrow the closest cameras of the rest of the scene.
+const MIN_CHUNK_VIEWS: usize = 10;
+
+/// A part of the scene that's trained on its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    /// The part of the ground plane this chunk keeps the splats of. Chunks at the edge of the
+    /// grid extend to infinity, so every splat ends up in exactly one chunk.
+    pub core: (Vec2, Vec2),
+    /// The core grown by the overlap. Cameras and initial splats in here are used for training.
+    pub region: (Vec2, Vec2),
+    /// Half the width of the band around the edges of the core where this chunk and its
+    /// neighbours blend into each other when merging, see [`Chunk::blend_weight`].
+    pub blend: Vec2,
+}
+
+impl Chunk {
+    /// How much this chunk provides of the splats at `p` on the ground plane, when merging.
+    ///
+    /// This is 1 deep inside the core, 0 outside of the blend band around it, and falls off
+    /// linearly across the band. Neighbouring chunks fall off the opposite way, so the weights of
+    /// all chunks add up to 1 everywhere.
+    pub fn blend_weight(&self, p: Vec2) -> f32 {
+        let along = |axis: usize| {
+            let (lo, hi) = (self.core.0[axis], self.core.1[axis]);
+            let blend = self.blend[axis];
+            if blend <= 0.0 {
+                return if p[axis] >= lo && p[axis] < hi {
+                    1.0
+                } else {
+                    0.0
+                };
+            }
+            // Distance outside of the core, negative inside.
+            let outside = (lo - p[axis]).max(p[axis] - hi);
+            (0.5 - outside / (2.0 * blend)).clamp(0.0, 1.0)
+        };
+        along(0) * along(1)
+    }
+}
+
+/// Splits a scene into a grid of chunks on the ground plane, to train scenes that don't fit in
+/// memory at once.
+#[derive(Debug, Clone)]
+pub struct ChunkGrid {
+    // Axes spanning the ground plane.
+    axes: (Vec3, Vec3),
+    pub chunks: Vec<Chunk>,
+}
+
+fn contains((min, max): (Vec2, Vec2), p: Vec2) -> bool {
+    p.cmpge(min).all() && p.cmplt(max).all()
+}
+
+impl ChunkGrid {
+    /// Split the area spanned by `positions` into `grid` x `grid` chunks, perpendicular to `up`.
+    ///
+    /// Each chunk is grown by `overlap` times its size on all sides, so neighbouring chunks
+    /// share the cameras and splats near their border.
+    pub fn new(positions: &[Vec3], up: Vec3, grid: u32, overlap: f32) -> Self {
+        let axes = up.normalize().any_orthonormal_pair();
+        let to_plane = |p: Vec3| Vec2::new(p.dot(axes.0), p.dot(axes.1));
+
+        let (min, max) = positions.iter().fold(
+            (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+            |(min, max), &p| (min.min(to_plane(p)), max.max(to_plane(p))),
+        );
+        let grid = grid.max(1);
+        // Keep cells from collapsing when all positions are in a line.
+        let cell = ((max - min) / grid as f32).max(Vec2::splat(1e-6));
+
+        let edge = |i: u32| {
+            if i == 0 {
+                f32::NEG_INFINITY
+            } else if i == grid {
+                f32::INFINITY
+            } else {
+                i as f32
+            }
+        };
+
+        let mut chunks = vec![];
+        for y in 0..grid {
+            for x in 0..grid {
+                let lo = Vec2::new(edge(x), edge(y));
+                let hi = Vec2::new(edge(x + 1), edge(y + 1));
+                let core = (min + lo * cell, min + hi * cell);
+                let margin = cell * overlap.max(0.0);
+                chunks.push(Chunk {
+                    core,
+                    region: (core.0 - margin, core.1 + margin),
+                    // Bands of opposite edges of a chunk can't overlap, or the weights of the
+                    // chunks wouldn't add up.
+                    blend: margin.min(cell * 0.5),
+                });
+            }
+        }
+        Self { axes, chunks }
+    }
+
+    fn to_plane(&self, p: Vec3) -> Vec2 {
+        Vec2::new(p.dot(self.axes.0), p.dot(self.axes.1))
+    }
+
+    /// Cameras of `scene` to train `chunk` with.
+    ///
+    /// Returns None if the chunk has no cameras of its own, as there is nothing to train then.
+    pub fn chunk_scene(&self, chunk: &Chunk, scene: &Scene) -> Option<Scene> {
+        let in_core = scene
+            .views
+            .iter()
+            .any(|v| contains(chunk.core, self.to_plane(v.camera.position)));
+        if !in_core {
+            return None;
+        }
+
+        let mut views: Vec<SceneView> = scene
+            .views
+            .iter()
+            .filter(|v| contains(chunk.region, self.to_plane(v.camera.position)))
+            .cloned()
+            .collect();
+
+        if views.len() < MIN_CHUNK_VIEWS {
+            // Borrow the cameras closest to the ones the chunk has.
+            let center = views
+                .iter()
+                .map(|v| self.to_plane(v.camera.position))
+                .sum::<Vec2>()
+                / views.len().max(1) as f32;
+            let mut rest: Vec<&SceneView> = scene
+                .views
+                .iter()
+                .filter(|v| !contains(chunk.region, self.to_plane(v.camera.position)))
+                .collect();
+            rest.sort_by(|a, b| {
+                let dist = |v: &SceneView| self.to_plane(v.camera.position).distance(center);
+                dist(a).total_cmp(&dist(b))
+            });
+            let missing = MIN_CHUNK_VIEWS - views.len();
+            views.extend(rest.into_iter().take(missing).cloned());
+        }
+        Some(Scene::new(views))
+    }
+
+    /// Which splats of `chunk` to keep when merging it with the other chunks.
+    ///
+    /// Each splat is kept with a chance of its [`Chunk::blend_weight`]. Where chunks overlap,
+    /// both were trained on the same part of the scene, so this picks between their splats
+    /// without doubling the density, and without a visible seam where one chunk ends.
+    pub async fn merge_mask(
+        &self,
+        splats: &Splats<MainBackend>,
+        chunk: &Chunk,
+        rng: &mut impl Rng,
+    ) -> Tensor<MainBackend, 1, Bool> {
+        let means = splats
+            .means
+            .val()
+            .into_data_async()
+            .await
+            .into_vec::<f32>()
+            .expect("Unreachable");
+        let keep: Vec<bool> = means
+            .chunks_exact(3)
+            .map(|p| {
+                let weight = chunk.blend_weight(self.to_plane(Vec3::from_slice(p)));
+                rng.random::<f32>() < weight
+            })
+            .collect();
+        let n = keep.len();
+        Tensor::from_data(TensorData::new(keep, [n]), &splats.device())
+    }
+
+    /// Which splats lie within an area of the ground plane.
+    pub fn mask(
+        &self,
+        splats: &Splats<MainBackend>,
+        (min, max): (Vec2, Vec2),
+    ) -> Tensor<MainBackend, 1, Bool> {
+        let device = splats.device();
+        let n = splats.num_splats() as usize;
+        let axes = Tensor::<MainBackend, 1>::from_floats(
+            [
+                self.axes.0.x,
+                self.axes.1.x,
+                self.axes.0.y,
+                self.axes.1.y,
+                self.axes.0.z,
+                self.axes.1.z,
+            ],
+            &device,
+        )
+        .reshape([3, 2]);
+        let coords = splats.means.val().matmul(axes);
+
+        let mut inside = Tensor::<MainBackend, 1>::ones([n], &device);
+        for axis in 0..2 {
+            let coord = coords.clone().slice([0..n, axis..axis + 1]).reshape([n]);
+            inside = inside
+                * coord.clone().greater_equal_elem(min[axis]).float()
+                * coord.lower_elem(max[axis]).float();
+        }
+        inside.greater_elem(0.5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_cover_plane() {
+        let positions = [Vec3::new(0.0, 0.0, 0.0), Vec3::new(10.0, 3.0, 10.0)];
+        let grid = ChunkGrid::new(&positions, Vec3::Y, 3, 0.25);
+        assert_eq!(grid.chunks.len(), 9);
+
+        // Any point, also far outside the cameras, is in the core of exactly one chunk.
+        for p in [
+            Vec3::new(5.0, 0.0, 5.0),
+            Vec3::new(-100.0, 7.0, 3.0),
+            Vec3::new(3.3333, 0.0, 6.6667),
+            Vec3::new(1e6, -2.0, -1e6),
+        ] {
+            let p = grid.to_plane(p);
+            let count = grid.chunks.iter().filter(|c| contains(c.core, p)).count();
+            assert_eq!(count, 1, "{p}");
+
+            // And regions overlap around the core.
+            for chunk in &grid.chunks {
+                if contains(chunk.core, p) {
+                    assert!(contains(chunk.region, p));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blend_weights_add_up() {
+        let positions = [Vec3::new(0.0, 0.0, 0.0), Vec3::new(9.0, 3.0, 9.0)];
+        for overlap in [0.0, 0.2, 0.8] {
+            let grid = ChunkGrid::new(&positions, Vec3::Y, 3, overlap);
+            for i in 0..400 {
+                let p = Vec3::new(
+                    (i % 20) as f32 * 0.6 - 1.5,
+                    1.0,
+                    (i / 20) as f32 * 0.6 - 1.5,
+                );
+                let p = grid.to_plane(p);
+                let weights: Vec<f32> = grid.chunks.iter().map(|c| c.blend_weight(p)).collect();
+                let total: f32 = weights.iter().sum();
+                assert!((total - 1.0).abs() < 1e-5, "{p} {overlap}");
+                // Chunks only provide splats they trained.
+                for (chunk, weight) in grid.chunks.iter().zip(weights) {
+                    assert!(weight <= 0.0 || contains(chunk.region, p));
+                }
+            }
+        }
+    }
+}
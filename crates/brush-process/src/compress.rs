@@ -0,0 +1,208 @@
+// Compresses a splat file for shipping, and measures how much the compression changes how it
+// looks, by rendering views around the scene before and after.
+use std::{fmt, io::Cursor, path::PathBuf};
+
+use anyhow::Context;
+use brush_dataset::{
+    compression::{CompressionPreset, compress_splats, load_compressed},
+    scene_transform::SceneTransform,
+    splat_formats::{self, SplatFormat},
+};
+use brush_render::{MainBackend, camera_path::look_at, gaussian_splats::Splats, shots::Shot};
+use brush_vfs::DataSource;
+use burn::tensor::{Tensor, TensorData};
+use burn_wgpu::WgpuDevice;
+use clap::Args;
+use glam::Vec3;
+use tokio::io::AsyncReadExt;
+use tokio_stream::StreamExt;
+
+use crate::stills::render_shot;
+
+#[derive(Args, Clone, Debug)]
+pub struct CompressArgs {
+    /// Splat file to compress (path or URL).
+    #[arg(value_name = "PATH_OR_URL")]
+    pub source: DataSource,
+    /// Where to write the compressed file. Defaults to the source with a .bsz extension.
+    #[arg(long, short)]
+    pub out: Option<PathBuf>,
+    /// How much to give up for a smaller file.
+    #[arg(long, value_enum, default_value = "balanced")]
+    pub preset: CompressionPreset,
+    /// Entries of the codebook of view dependent colors, in place of the one of the preset.
+    #[arg(long)]
+    pub codebook_size: Option<u32>,
+    /// Don't gzip the quantized values, for loaders without gzip support.
+    #[arg(long, default_value = "false")]
+    pub no_entropy_coding: bool,
+    /// Views around the scene to compare the original and compressed splats in.
+    #[arg(long, default_value = "8")]
+    pub views: u32,
+    /// Size of the compared views.
+    #[arg(long, default_value = "512")]
+    pub view_size: u32,
+}
+
+impl CompressArgs {
+    fn out_path(&self) -> PathBuf {
+        if let Some(out) = &self.out {
+            return out.clone();
+        }
+        match &self.source {
+            DataSource::Path(path) => PathBuf::from(path).with_extension("bsz"),
+            _ => PathBuf::from("compressed.bsz"),
+        }
+    }
+}
+
+/// How a compressed file compares to the original.
+#[derive(Debug, Clone)]
+pub struct CompressReport {
+    pub out: PathBuf,
+    pub original_size: usize,
+    pub compressed_size: usize,
+    pub num_splats: u32,
+    /// Average PSNR of the compressed splats against the original, over all views.
+    pub psnr: f32,
+    /// PSNR of the view that changed most.
+    pub min_psnr: f32,
+}
+
+impl fmt::Display for CompressReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} splats to {} ({:.1} MB to {:.1} MB, {:.1}x smaller), PSNR {:.2}, lowest {:.2}",
+            self.num_splats,
+            self.out.display(),
+            self.original_size as f64 / 1e6,
+            self.compressed_size as f64 / 1e6,
+            self.original_size as f64 / self.compressed_size.max(1) as f64,
+            self.psnr,
+            self.min_psnr,
+        )
+    }
+}
+
+// Views on a ring around the scene, looking at its center from a bit above.
+async fn orbit_shots(splats: &Splats<MainBackend>, up: Vec3, args: &CompressArgs) -> Vec<Shot> {
+    let means = splats
+        .means
+        .val()
+        .into_data_async()
+        .await
+        .into_vec::<f32>()
+        .expect("Wrong type");
+    let points: Vec<_> = means.chunks_exact(3).map(Vec3::from_slice).collect();
+    // This ignores outliers, which trained splats usually have plenty of.
+    let fit = SceneTransform::from_points(&points);
+    let radius = 1.0 / fit.scale;
+    let center = -fit.translation * radius;
+
+    let fov_y = 50.0f64.to_radians();
+    // Far enough for the bounding sphere to fit in view.
+    let distance = radius / (fov_y as f32 * 0.5).sin();
+    let (side, forward) = up.any_orthonormal_pair();
+    (0..args.views)
+        .map(|i| {
+            let angle = i as f32 / args.views as f32 * std::f32::consts::TAU;
+            let dir = side * angle.cos() + forward * angle.sin() + up * 0.3;
+            let position = center + dir.normalize() * distance;
+            Shot {
+                name: format!("view_{i}"),
+                position,
+                rotation: look_at(position, center, up),
+                fov_y,
+                exposure: 0.0,
+                width: args.view_size,
+                height: args.view_size,
+            }
+        })
+        .collect()
+}
+
+async fn view_psnr(
+    original: &Splats<MainBackend>,
+    compressed: &Splats<MainBackend>,
+    shot: &Shot,
+) -> anyhow::Result<f32> {
+    let device = original.device();
+    let to_tensor = |img: image::RgbImage| {
+        let data = TensorData::new(
+            img.into_raw(),
+            [shot.height as usize, shot.width as usize, 3],
+        );
+        Tensor::<MainBackend, 3>::from_data(data.convert::<f32>(), &device) / 255.0
+    };
+    let expected = to_tensor(render_shot(original, shot).await?);
+    let rendered = to_tensor(render_shot(compressed, shot).await?);
+    let mse = (rendered - expected).powi_scalar(2).mean();
+    let psnr = mse.recip().log() * 10.0 / std::f32::consts::LN_10;
+    let psnr = psnr
+        .into_data_async()
+        .await
+        .into_vec::<f32>()
+        .expect("Wrong type");
+    Ok(psnr[0])
+}
+
+/// Compress the splat file of `args`, write it out, and report how much it changed.
+///
+/// Animated files are compressed at their last frame.
+pub async fn run_compress(
+    args: &CompressArgs,
+    device: &WgpuDevice,
+) -> anyhow::Result<CompressReport> {
+    let vfs = args.source.clone().into_vfs().await?;
+    let (path, format) = vfs
+        .file_paths()
+        .find_map(|path| {
+            let format = SplatFormat::from_path(&path)?;
+            Some((path, format))
+        })
+        .context("No splat file to compress")?;
+    let mut data = vec![];
+    vfs.reader_at_path(&path)
+        .await?
+        .read_to_end(&mut data)
+        .await?;
+    let original_size = data.len();
+
+    let stream = splat_formats::load_splats(format, Cursor::new(data), None, device.clone());
+    let mut stream = std::pin::pin!(stream);
+    let mut loaded = None;
+    while let Some(message) = stream.next().await {
+        loaded = Some(message?);
+    }
+    let loaded = loaded.with_context(|| format!("No splats in {}", path.display()))?;
+    let original = loaded.splats;
+
+    let mut settings = args.preset.settings();
+    if let Some(codebook_size) = args.codebook_size {
+        settings.codebook_size = codebook_size;
+    }
+    settings.entropy_coding = !args.no_entropy_coding;
+    let compressed_data = compress_splats(original.clone(), &settings).await?;
+    let out = args.out_path();
+    rrfd::write_atomic(&out, &compressed_data).await?;
+
+    // Compare against what loaders get back, not the splats before quantizing.
+    let compressed = load_compressed(&compressed_data, device)?;
+    // Splats without an up axis are usually in the frame of COLMAP, with y pointing down.
+    let up = loaded.meta.up_axis.unwrap_or(Vec3::NEG_Y).normalize();
+    let shots = orbit_shots(&original, up, args).await;
+    let mut psnrs = vec![];
+    for shot in &shots {
+        psnrs.push(view_psnr(&original, &compressed, shot).await?);
+    }
+
+    Ok(CompressReport {
+        out,
+        original_size,
+        compressed_size: compressed_data.len(),
+        num_splats: original.num_splats(),
+        psnr: psnrs.iter().sum::<f32>() / psnrs.len().max(1) as f32,
+        min_psnr: psnrs.iter().copied().fold(f32::INFINITY, f32::min),
+    })
+}
@@ -1,8 +1,14 @@
-use brush_dataset::config::{LoadDataseConfig, ModelConfig};
+use brush_dataset::{
+    compression::CompressionPreset,
+    config::{LoadDataseConfig, ModelConfig},
+};
+use brush_sfm::{SfmEngine, SfmMatcher};
 use brush_train::config::TrainConfig;
 use burn::config::Config;
 use clap::Args;
 
+use crate::planner::floats_per_splat;
+
 #[derive(Config, Args)]
 pub struct ProcessConfig {
     /// Random seed.
@@ -10,6 +16,19 @@ pub struct ProcessConfig {
     #[arg(long, help_heading = "Process options", default_value = "42")]
     pub seed: u64,
 
+    /// Make runs reproducible: views are always sampled in the same order, all random choices
+    /// follow the seed, and gradients are summed in a fixed order. Loads images and trains more
+    /// slowly. Results can still differ slightly between GPUs or drivers.
+    #[config(default = false)]
+    #[arg(long, help_heading = "Process options", default_value = "false")]
+    pub deterministic: bool,
+
+    /// Start training once this many views are loaded, and load the others while training.
+    /// Deterministic runs wait for nothing, as they load views as they're needed.
+    #[config(default = 32)]
+    #[arg(long, help_heading = "Process options", default_value = "32")]
+    pub start_views: usize,
+
     /// Iteration to resume from
     #[config(default = 0)]
     #[arg(long, help_heading = "Process options", default_value = "0")]
@@ -34,7 +53,11 @@ pub struct ProcessConfig {
     #[arg(long, help_heading = "Process options", default_value = ".")]
     #[config(default = "String::from('.')")]
     pub export_path: String,
-    /// Filename of exported ply file
+    /// Filename of exported ply file. Names ending in .glb export glTF files with the
+    /// `KHR_gaussian_splatting` extension instead, names ending in .usda or .usdz export USD
+    /// points with the splat attributes as primvars, names ending in .bsplat export chunked
+    /// files that viewers can show while they download, and names ending in .bsz export
+    /// compressed files.
     #[arg(
         long,
         help_heading = "Process options",
@@ -42,6 +65,91 @@ pub struct ProcessConfig {
     )]
     #[config(default = "String::from(\"export_{iter}.ply\")")]
     pub export_name: String,
+    /// Don't require support for splats to load exported glTF files, so loaders without it show
+    /// the splats as colored points.
+    #[arg(long, help_heading = "Process options", default_value = "false")]
+    #[config(default = false)]
+    pub export_gltf_fallback: bool,
+    /// How much to compress exported .bsz files.
+    #[arg(
+        long,
+        help_heading = "Process options",
+        value_enum,
+        default_value = "balanced"
+    )]
+    #[config(default = "CompressionPreset::Balanced")]
+    pub export_compression: CompressionPreset,
+    /// Write exported plys from most to least important, by how much of the training views each
+    /// splat covers, so viewers that stream files in show a recognizable scene after loading only
+    /// the first part.
+    #[arg(long, help_heading = "Process options", default_value = "false")]
+    #[config(default = false)]
+    pub export_progressive: bool,
+    /// Hide this number in the colors of exported splats, to trace where they end up.
+    #[arg(long, help_heading = "Process options")]
+    pub export_watermark: Option<u64>,
+    /// Secret key the watermark is embedded and read with.
+    #[arg(long, help_heading = "Process options", default_value = "0")]
+    #[config(default = 0)]
+    pub watermark_key: u64,
+    /// Read back the watermark of a loaded ply file, instead of viewing it.
+    #[arg(long, help_heading = "Process options", default_value = "false")]
+    #[config(default = false)]
+    pub read_watermark: bool,
+
+    /// Passphrase to open an encrypted source with.
+    #[arg(long, help_heading = "Process options")]
+    pub passphrase: Option<String>,
+    /// Encrypt exported ply files with this passphrase. Encrypted files can only be opened in Brush.
+    #[arg(long, help_heading = "Process options")]
+    pub export_passphrase: Option<String>,
+
+    /// GPU memory available for training, in MB. Runs estimated to need more than this give a warning
+    /// before training starts. Without it, runs are only checked against the buffer size limits of
+    /// the GPU, as its memory size isn't known.
+    #[arg(long, help_heading = "Process options")]
+    pub memory_budget_mb: Option<u64>,
+
+    /// Remove the splats adding least to the training views from the final export, until at
+    /// most this many are left.
+    #[arg(long, help_heading = "Process options")]
+    pub simplify_splats: Option<u32>,
+    /// Remove the splats adding least to the training views from the final export, until it fits
+    /// in this many MB as a ply.
+    #[arg(long, help_heading = "Process options")]
+    pub simplify_size_mb: Option<f32>,
+
+    /// Record a compact snapshot of the splats every this many steps, to replay training in the viewer.
+    #[arg(
+        long,
+        help_heading = "Process options",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub replay_snapshot_every: Option<u32>,
+
+    /// Split the scene into a grid of this many by this many chunks on the ground plane. Chunks are
+    /// trained one after another and merged, for scenes too big to train at once. 1 trains the whole
+    /// scene at once.
+    #[arg(long, help_heading = "Process options", default_value = "1")]
+    #[config(default = 1)]
+    pub train_chunks: u32,
+    /// How far chunks extend into their neighbours, as a fraction of the chunk size. Cameras and
+    /// splats in the overlap are trained with both chunks, and blended between them when merging,
+    /// which hides the seams.
+    #[arg(long, help_heading = "Process options", default_value = "0.2")]
+    #[config(default = 0.2)]
+    pub chunk_overlap: f32,
+}
+
+impl ProcessConfig {
+    /// Most splats to keep in the final export, if simplifying it.
+    pub fn simplify_budget(&self, sh_degree: u32) -> Option<u32> {
+        let bytes_per_splat = floats_per_splat(sh_degree) * 4;
+        let from_size = self
+            .simplify_size_mb
+            .map(|mb| (f64::from(mb) * 1e6 / bytes_per_splat as f64) as u32);
+        self.simplify_splats.into_iter().chain(from_size).min()
+    }
 }
 
 #[derive(Config, Args)]
@@ -56,6 +164,12 @@ pub struct ProcessArgs {
     pub process_config: ProcessConfig,
     #[clap(flatten)]
     pub rerun_config: RerunConfig,
+    #[clap(flatten)]
+    pub render_config: RenderConfig,
+    #[clap(flatten)]
+    pub mesh_config: MeshConfig,
+    #[clap(flatten)]
+    pub sfm_config: SfmConfig,
 }
 
 impl Default for ProcessArgs {
@@ -66,8 +180,81 @@ impl Default for ProcessArgs {
             load_config: LoadDataseConfig::new(),
             process_config: ProcessConfig::new(),
             rerun_config: RerunConfig::new(),
+            render_config: RenderConfig::new(),
+            mesh_config: MeshConfig::new(),
+            sfm_config: SfmConfig::new(),
+        }
+    }
+}
+
+/// Settings trading training time for quality, for when tuning each setting is too much.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityPreset {
+    Preview,
+    Balanced,
+    High,
+}
+
+impl QualityPreset {
+    pub const ALL: [Self; 3] = [Self::Preview, Self::Balanced, Self::High];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Preview => "Preview",
+            Self::Balanced => "Balanced",
+            Self::High => "High quality",
         }
     }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Preview => "A quick, rough result to check the capture worked.",
+            Self::Balanced => "Good quality in about half the time.",
+            Self::High => "The best quality, takes the longest and needs the most memory.",
+        }
+    }
+
+    /// Process arguments for this preset. Settings the preset doesn't cover keep their defaults.
+    pub fn args(self) -> ProcessArgs {
+        let mut args = ProcessArgs::default();
+        let (total_steps, max_resolution, sh_degree, max_splats) = match self {
+            Self::Preview => (5000, 1024, 1, 1_000_000),
+            Self::Balanced => (15000, 1600, 3, 3_000_000),
+            Self::High => return args,
+        };
+        let tc = &mut args.train_config;
+        // Keep growth stopping at the same point of training as the defaults.
+        tc.growth_stop_iter =
+            (tc.growth_stop_iter as u64 * total_steps as u64 / tc.total_steps as u64) as u32;
+        tc.total_steps = total_steps;
+        tc.max_splats = max_splats;
+        args.load_config.max_resolution = max_resolution;
+        args.model_config.sh_degree = sh_degree;
+        args
+    }
+}
+
+/// Largest images to train on in the browser.
+pub const WEB_MAX_RESOLUTION: u32 = 1024;
+/// Most splats to train in the browser.
+pub const WEB_MAX_SPLATS: u32 = 1_000_000;
+
+impl ProcessArgs {
+    /// Lower the settings that decide how much memory training needs to what fits in a browser
+    /// tab, which gets a few GB at most for both the dataset and the splats. Returns whether
+    /// anything was lowered.
+    pub fn limit_for_web(&mut self) -> bool {
+        let load = &mut self.load_config;
+        let train = &mut self.train_config;
+        let limited = load.max_resolution > WEB_MAX_RESOLUTION
+            || train.max_splats > WEB_MAX_SPLATS
+            || self.process_config.train_chunks > 1;
+        load.max_resolution = load.max_resolution.min(WEB_MAX_RESOLUTION);
+        train.max_splats = train.max_splats.min(WEB_MAX_SPLATS);
+        // Chunks are for scenes far too big for the web anyway.
+        self.process_config.train_chunks = 1;
+        limited
+    }
 }
 
 #[derive(Config, Args)]
@@ -88,3 +275,139 @@ pub struct RerunConfig {
     #[config(default = 512)]
     pub rerun_max_img_size: u32,
 }
+
+#[derive(Config, Args)]
+pub struct RenderConfig {
+    /// Render the splats along a camera path (a JSON file) after loading or training.
+    #[arg(long, help_heading = "Render options")]
+    pub render_path: Option<String>,
+    /// Render a turntable orbiting the splats after loading or training.
+    #[arg(long, help_heading = "Render options", default_value = "false")]
+    #[config(default = false)]
+    pub render_turntable: bool,
+    /// Where to write the render. Either a directory for PNG frames, or an .mp4 file, which
+    /// requires ffmpeg to be installed.
+    #[arg(long, help_heading = "Render options", default_value = "render.mp4")]
+    #[config(default = "String::from(\"render.mp4\")")]
+    pub render_out: String,
+    /// Width of the rendered frames.
+    #[arg(long, help_heading = "Render options", default_value = "1920")]
+    #[config(default = 1920)]
+    pub render_width: u32,
+    /// Height of the rendered frames.
+    #[arg(long, help_heading = "Render options", default_value = "1080")]
+    #[config(default = 1080)]
+    pub render_height: u32,
+    /// Image (eg. a logo) to blend onto the corner of rendered frames.
+    #[arg(long, help_heading = "Render options")]
+    pub render_watermark: Option<String>,
+    /// Opacity of the watermark on rendered frames.
+    #[arg(long, help_heading = "Render options", default_value = "0.5")]
+    #[config(default = 0.5)]
+    pub render_watermark_opacity: f32,
+    /// Frames per second of the render.
+    #[arg(long, help_heading = "Render options", default_value = "30")]
+    #[config(default = 30)]
+    pub render_fps: u32,
+    /// Length of the turntable in seconds.
+    #[arg(long, help_heading = "Render options", default_value = "8.0")]
+    #[config(default = 8.0)]
+    pub render_duration: f32,
+    /// Fade splats in and out over this many frames when they cross the near or far plane, instead
+    /// of popping. 0 culls every frame on its own.
+    #[arg(long, help_heading = "Render options", default_value = "0")]
+    #[config(default = 0)]
+    pub render_fade_frames: u32,
+    /// Also write motion vectors of each frame, as EXR images with the pixel offset to the
+    /// previous frame in red and green, and to the next frame in blue and alpha.
+    #[arg(long, help_heading = "Render options", default_value = "false")]
+    #[config(default = false)]
+    pub render_motion_vectors: bool,
+    /// Blur motion like a real camera with its shutter open for this many degrees of each frame.
+    /// 180 gives the usual film look, 0 renders sharp frames.
+    #[arg(long, help_heading = "Render options", default_value = "0")]
+    #[config(default = 0.0)]
+    pub render_shutter_angle: f32,
+    /// Number of renders averaged for each frame with motion blur. More samples give smoother blur,
+    /// but take longer to render.
+    #[arg(long, help_heading = "Render options", default_value = "8")]
+    #[config(default = 8)]
+    pub render_shutter_samples: u32,
+    /// Place copies of a captured prop around the scene, as listed in a JSON file. Each copy has its
+    /// own position, rotation, scale, tint and random color variation.
+    #[arg(long, help_heading = "Render options")]
+    pub render_instances: Option<String>,
+    /// Render a still for each camera of a shot list (a JSON file, as saved from the stills panel)
+    /// after loading or training, each at the resolution saved with it.
+    #[arg(long, help_heading = "Render options")]
+    pub render_shots: Option<String>,
+    /// Directory to write the stills of the shot list to, as PNG images named after the shots.
+    #[arg(long, help_heading = "Render options", default_value = "stills")]
+    #[config(default = "String::from(\"stills\")")]
+    pub render_shots_out: String,
+}
+
+impl RenderConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.render_path.is_some() || self.render_turntable
+    }
+}
+
+#[derive(Config, Args)]
+pub struct MeshConfig {
+    /// Extract a triangle mesh of the splats after loading or training, and write it to this file.
+    /// Either an .obj, .glb, .usda or .usdz file, with vertex colors.
+    #[arg(long, help_heading = "Mesh options")]
+    pub mesh_out: Option<String>,
+    /// Number of voxels along each side of the grid the mesh is extracted from. Memory use grows
+    /// with the cube of this.
+    #[arg(long, help_heading = "Mesh options", default_value = "128")]
+    #[config(default = 128)]
+    pub mesh_resolution: u32,
+    /// Size of the depth maps rendered to fuse into the mesh.
+    #[arg(long, help_heading = "Mesh options", default_value = "512")]
+    #[config(default = 512)]
+    pub mesh_view_size: u32,
+    /// Number of views around the splats to fuse into the mesh, when there are no training views.
+    #[arg(long, help_heading = "Mesh options", default_value = "64")]
+    #[config(default = 64)]
+    pub mesh_views: u32,
+}
+
+#[derive(Config, Args)]
+pub struct SfmConfig {
+    /// Reconstruct the cameras of a directory of photos without camera poses with this structure
+    /// from motion engine, which needs to be installed. The reconstruction is kept next to the
+    /// directory, and reused until the photos change.
+    #[arg(long, help_heading = "SfM options", value_enum)]
+    pub sfm: Option<SfmEngine>,
+    /// Which pairs of photos to match features between.
+    #[arg(
+        long,
+        help_heading = "SfM options",
+        value_enum,
+        default_value = "exhaustive"
+    )]
+    #[config(default = "SfmMatcher::Exhaustive")]
+    pub sfm_matcher: SfmMatcher,
+    /// All photos were taken with the same camera and zoom, which makes the reconstruction more
+    /// robust.
+    #[arg(long, help_heading = "SfM options", default_value = "false")]
+    #[config(default = false)]
+    pub sfm_single_camera: bool,
+    /// Path to the COLMAP binary, if it isn't on the PATH.
+    #[arg(long, help_heading = "SfM options")]
+    pub colmap_binary: Option<String>,
+    /// Path to the GLOMAP binary, if it isn't on the PATH.
+    #[arg(long, help_heading = "SfM options")]
+    pub glomap_binary: Option<String>,
+    /// Command line for `--sfm command`, to reconstruct with tools that don't need COLMAP, like
+    /// learned reconstruction models. `{images}` is replaced by the directory of photos, and
+    /// `{output}` by the directory the tool writes a COLMAP model of pinhole cameras to.
+    #[arg(long, help_heading = "SfM options")]
+    pub sfm_command: Option<String>,
+    /// Export the features and sparse model of the reconstruction to this directory, in the
+    /// layout of COLMAP, to localize new photos against it with tools like hloc.
+    #[arg(long, help_heading = "SfM options")]
+    pub sfm_export: Option<String>,
+}
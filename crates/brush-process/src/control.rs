@@ -0,0 +1,19 @@
+use brush_render::camera::Camera;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc::UnboundedReceiver, watch};
+
+/// Changes to a training run while it's going.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TrainCommand {
+    /// Scale all learning rates by this factor, relative to the configured ones.
+    SetLrScale { scale: f64 },
+    /// Evaluate after the next step, rather than waiting for the eval interval.
+    Eval,
+}
+
+pub type TrainCommands = UnboundedReceiver<TrainCommand>;
+
+/// The camera the splats are viewed from, if they're viewed, for loaders that load what's in view
+/// first.
+pub type ViewFocus = watch::Receiver<Option<Camera>>;
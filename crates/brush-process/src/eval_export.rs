@@ -28,7 +28,9 @@ pub async fn eval_save_to_disk<B: Backend>(sample: &EvalSample<B>, path: &Path)
         let parent = path.parent().expect("Eval must have a filename");
         tokio::fs::create_dir_all(parent).await?;
         log::info!("Saving eval view to {path:?}");
-        img.save(path)?;
+        let mut data = std::io::Cursor::new(vec![]);
+        img.write_to(&mut data, image::ImageFormat::from_path(path)?)?;
+        rrfd::write_atomic(path, data.get_ref()).await?;
     }
     Ok(())
 }
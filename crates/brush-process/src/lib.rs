@@ -1,10 +1,26 @@
 #![recursion_limit = "256"]
 
+#[cfg(not(target_family = "wasm"))]
+pub mod bundle;
+#[cfg(not(target_family = "wasm"))]
+pub mod compress;
 pub mod config;
+pub mod control;
+pub mod mesh;
 pub mod message;
+pub mod planner;
 pub mod process;
+pub mod regression;
+#[cfg(not(target_family = "wasm"))]
+pub mod segment;
+#[cfg(not(target_family = "wasm"))]
+pub mod render_video;
+pub mod stills;
 pub mod train_stream;
 pub mod view_stream;
 
+mod chunks;
 mod eval_export;
+#[cfg(not(target_family = "wasm"))]
+mod sfm;
 mod visualize_tools;
@@ -0,0 +1,616 @@
+// Triangle meshes of splats, for collision, CAD or DCC tools that can't use splats.
+//
+// Depth maps of the splats from many views are fused into a truncated signed distance field on a
+// voxel grid, which is turned into a mesh with surface nets.
+use std::path::Path;
+
+use anyhow::Context;
+use brush_dataset::{scene_transform::SceneTransform, usd_export::UsdStage};
+use brush_render::{
+    MainBackend,
+    camera::{Camera, project_points},
+    camera_path::look_at,
+    gaussian_splats::Splats,
+};
+use burn::tensor::{Int, Tensor, s};
+use burn_wgpu::WgpuDevice;
+use glam::Vec3;
+
+use crate::config::MeshConfig;
+
+/// A triangle mesh with a color per vertex.
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub positions: Vec<Vec3>,
+    pub colors: Vec<Vec3>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+impl Mesh {
+    /// Move the vertices, eg. back to the frame of the dataset.
+    pub fn transform(&mut self, transform: &SceneTransform) {
+        for p in &mut self.positions {
+            *p = transform.transform_point(*p);
+        }
+    }
+
+    /// Encode as a Wavefront OBJ file, with the vertex colors after the positions.
+    pub fn to_obj(&self) -> Vec<u8> {
+        let mut out = String::new();
+        for (p, c) in self.positions.iter().zip(&self.colors) {
+            out += &format!("v {} {} {} {} {} {}\n", p.x, p.y, p.z, c.x, c.y, c.z);
+        }
+        for [a, b, c] in &self.triangles {
+            // Indices in OBJ files start at 1.
+            out += &format!("f {} {} {}\n", a + 1, b + 1, c + 1);
+        }
+        out.into_bytes()
+    }
+
+    /// Decode a Wavefront OBJ file. Faces with more than three corners are split into a fan of
+    /// triangles, and vertices without a color are white.
+    pub fn from_obj(data: &[u8]) -> anyhow::Result<Self> {
+        let text = std::str::from_utf8(data).context("OBJ file isn't valid text")?;
+        let mut mesh = Self::default();
+        for (line_nr, line) in text.lines().enumerate() {
+            let mut parts = line.split_whitespace();
+            match parts.next() {
+                Some("v") => {
+                    let values = parts
+                        .map(str::parse::<f32>)
+                        .collect::<Result<Vec<_>, _>>()
+                        .with_context(|| format!("Invalid vertex on line {}", line_nr + 1))?;
+                    anyhow::ensure!(
+                        values.len() >= 3,
+                        "Vertex on line {} has less than three coordinates",
+                        line_nr + 1
+                    );
+                    mesh.positions
+                        .push(Vec3::new(values[0], values[1], values[2]));
+                    mesh.colors.push(if values.len() >= 6 {
+                        Vec3::new(values[3], values[4], values[5])
+                    } else {
+                        Vec3::ONE
+                    });
+                }
+                Some("f") => {
+                    let num_vertices = mesh.positions.len() as i64;
+                    let corners = parts
+                        .map(|corner| {
+                            // Corners can also refer to texture coordinates and normals, as v/vt/vn.
+                            let index: i64 = corner.split('/').next().unwrap_or(corner).parse()?;
+                            // Negative indices count back from the last vertex so far.
+                            let index = if index < 0 {
+                                num_vertices + index
+                            } else {
+                                index - 1
+                            };
+                            u32::try_from(index).context("Index out of range")
+                        })
+                        .collect::<anyhow::Result<Vec<_>>>()
+                        .with_context(|| format!("Invalid face on line {}", line_nr + 1))?;
+                    for i in 1..corners.len().saturating_sub(1) {
+                        mesh.triangles
+                            .push([corners[0], corners[i], corners[i + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        anyhow::ensure!(
+            mesh.triangles
+                .iter()
+                .flatten()
+                .all(|&i| (i as usize) < mesh.positions.len()),
+            "Face refers to a vertex that doesn't exist"
+        );
+        Ok(mesh)
+    }
+
+    /// A USD stage with the mesh.
+    pub fn to_usd(&self) -> UsdStage {
+        let mut stage = UsdStage::new();
+        stage.add_mesh("Mesh", &self.positions, &self.colors, &self.triangles);
+        stage
+    }
+
+    /// Encode as a binary glTF file.
+    pub fn to_glb(&self) -> Vec<u8> {
+        let floats = |values: &[Vec3]| -> Vec<u8> {
+            values
+                .iter()
+                .flat_map(|v| v.to_array())
+                .flat_map(f32::to_le_bytes)
+                .collect()
+        };
+        // Vertex colors are linear in glTF.
+        let linear: Vec<Vec3> = self.colors.iter().map(|c| c.powf(2.2)).collect();
+        let positions = floats(&self.positions);
+        let colors = floats(&linear);
+        let indices: Vec<u8> = self
+            .triangles
+            .iter()
+            .flatten()
+            .flat_map(|i| i.to_le_bytes())
+            .collect();
+
+        let (min, max) = self
+            .positions
+            .iter()
+            .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), p| {
+                (min.min(*p), max.max(*p))
+            });
+        let count = self.positions.len();
+        let json = serde_json::json!({
+            "asset": { "version": "2.0", "generator": "Brush" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "mesh": 0 }],
+            "meshes": [{
+                "primitives": [{
+                    "attributes": { "POSITION": 0, "COLOR_0": 1 },
+                    "indices": 2,
+                }],
+            }],
+            "buffers": [{ "byteLength": positions.len() + colors.len() + indices.len() }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": positions.len(), "target": 34962 },
+                {
+                    "buffer": 0,
+                    "byteOffset": positions.len(),
+                    "byteLength": colors.len(),
+                    "target": 34962,
+                },
+                {
+                    "buffer": 0,
+                    "byteOffset": positions.len() + colors.len(),
+                    "byteLength": indices.len(),
+                    "target": 34963,
+                },
+            ],
+            "accessors": [
+                {
+                    "bufferView": 0,
+                    "componentType": 5126,
+                    "count": count,
+                    "type": "VEC3",
+                    "min": min.to_array(),
+                    "max": max.to_array(),
+                },
+                { "bufferView": 1, "componentType": 5126, "count": count, "type": "VEC3" },
+                {
+                    "bufferView": 2,
+                    "componentType": 5125,
+                    "count": self.triangles.len() * 3,
+                    "type": "SCALAR",
+                },
+            ],
+        });
+
+        // Chunks are padded to 4 bytes, the JSON with spaces.
+        let mut json = json.to_string().into_bytes();
+        json.resize(json.len().div_ceil(4) * 4, b' ');
+        let mut bin = [positions, colors, indices].concat();
+        bin.resize(bin.len().div_ceil(4) * 4, 0);
+
+        let mut out = vec![];
+        let total = 12 + 8 + json.len() + 8 + bin.len();
+        out.extend(b"glTF");
+        out.extend(2u32.to_le_bytes());
+        out.extend((total as u32).to_le_bytes());
+        out.extend((json.len() as u32).to_le_bytes());
+        out.extend(b"JSON");
+        out.extend(json);
+        out.extend((bin.len() as u32).to_le_bytes());
+        out.extend(b"BIN\0");
+        out.extend(bin);
+        out
+    }
+}
+
+// A cube of voxels, indexed with x changing fastest.
+struct Grid {
+    origin: Vec3,
+    voxel_size: f32,
+    res: usize,
+}
+
+impl Grid {
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.res + y) * self.res + x
+    }
+
+    // World space position of a point in voxel coordinates, where voxel centers are at integers.
+    fn world(&self, p: Vec3) -> Vec3 {
+        self.origin + (p + 0.5) * self.voxel_size
+    }
+
+    // [N, 3] centers of all voxels.
+    fn centers(&self, device: &WgpuDevice) -> Tensor<MainBackend, 2> {
+        let r = self.res;
+        let axis = |dim: usize| {
+            let mut shape = [1; 3];
+            shape[dim] = r;
+            let mut t = Tensor::<MainBackend, 1, Int>::arange(0..r as i64, device)
+                .float()
+                .reshape(shape);
+            for other in (0..3).filter(|&d| d != dim) {
+                t = t.repeat_dim(other, r);
+            }
+            t
+        };
+        let [zs, ys, xs] = [0, 1, 2].map(axis);
+        let origin = Tensor::<MainBackend, 1>::from_floats(self.origin.to_array(), device);
+        (Tensor::stack::<4>(vec![xs, ys, zs], 3).reshape([r * r * r, 3]) + 0.5) * self.voxel_size
+            + origin.reshape([1, 3])
+    }
+}
+
+// The fused field of a grid: the truncated signed distance of each voxel in units of the truncation
+// distance, how many views saw it, and its color.
+struct Field {
+    sdf: Vec<f32>,
+    weight: Vec<f32>,
+    colors: Vec<f32>,
+}
+
+async fn fuse(
+    splats: &Splats<MainBackend>,
+    cameras: &[Camera],
+    view_size: u32,
+    grid: &Grid,
+) -> Field {
+    let device = splats.device();
+    let n = grid.res.pow(3);
+    // Only voxels this close to the surface are carved, the ones further behind it stay unknown.
+    let truncation = grid.voxel_size * 3.0;
+
+    let points = grid.centers(&device);
+    let mut sdf_sum = Tensor::<MainBackend, 1>::zeros([n], &device);
+    let mut weight_sum = Tensor::<MainBackend, 1>::zeros([n], &device);
+    let mut color_sum = Tensor::<MainBackend, 2>::zeros([n, 3], &device);
+    let mut color_weight = Tensor::<MainBackend, 1>::zeros([n], &device);
+
+    for camera in cameras {
+        // Keep the aspect ratio of the view.
+        let aspect = ((camera.fov_x * 0.5).tan() / (camera.fov_y * 0.5).tan()) as f32;
+        let size = if aspect >= 1.0 {
+            glam::uvec2(view_size, ((view_size as f32 / aspect) as u32).max(1))
+        } else {
+            glam::uvec2(((view_size as f32 * aspect) as u32).max(1), view_size)
+        };
+        let [w, h] = [size.x as usize, size.y as usize];
+
+        let (depth, alpha) = splats.render_depth(camera, size);
+        let (img, _) = splats.render(camera, size, true);
+        let depth = depth.reshape([h * w]);
+        let alpha = alpha.reshape([h * w]);
+        // Colors are premultiplied by alpha.
+        let rgb = img.slice(s![.., .., 0..3]).reshape([h * w, 3])
+            / alpha.clone().clamp_min(1e-6).reshape([h * w, 1]);
+
+        let (z, px, py) = project_points(camera, size, points.clone());
+        let in_view = z.clone().greater_elem(1e-3).float()
+            * px.clone().greater_equal_elem(0.0).float()
+            * px.clone().lower_elem(w as f32).float()
+            * py.clone().greater_equal_elem(0.0).float()
+            * py.clone().lower_elem(h as f32).float();
+        let pixel = py.floor().int().clamp(0, h as i32 - 1) * w as i32
+            + px.floor().int().clamp(0, w as i32 - 1);
+
+        let alpha = alpha.select(0, pixel.clone());
+        let sdf = depth.select(0, pixel.clone()) - z;
+        // Pixels that hit nothing show the voxels along them are empty.
+        let hit = alpha.greater_elem(0.5).float();
+        let miss = hit.clone().neg() + 1.0;
+        let tsdf = (sdf.clone() / truncation).clamp(-1.0, 1.0) * hit.clone() + miss.clone();
+        let in_band = sdf.clone().greater_elem(-truncation).float();
+        let seen = in_view * (in_band * hit.clone() + miss);
+        sdf_sum = sdf_sum + tsdf * seen.clone();
+        weight_sum = weight_sum + seen.clone();
+
+        let near = seen * hit * sdf.abs().lower_elem(truncation).float();
+        color_sum = color_sum + rgb.select(0, pixel) * near.clone().reshape([n, 1]);
+        color_weight = color_weight + near;
+    }
+
+    let sdf = sdf_sum / weight_sum.clone().clamp_min(1e-6);
+    let colors = color_sum / color_weight.clamp_min(1e-6).reshape([n, 1]);
+    let read = |t: Tensor<MainBackend, 1>| async move {
+        t.into_data_async()
+            .await
+            .into_vec::<f32>()
+            .expect("Wrong type")
+    };
+    Field {
+        sdf: read(sdf).await,
+        weight: read(weight_sum).await,
+        colors: read(colors.reshape([n * 3])).await,
+    }
+}
+
+// Corners of a cube, as offsets from its first corner.
+const CORNERS: [[usize; 3]; 8] = [
+    [0, 0, 0],
+    [1, 0, 0],
+    [0, 1, 0],
+    [1, 1, 0],
+    [0, 0, 1],
+    [1, 0, 1],
+    [0, 1, 1],
+    [1, 1, 1],
+];
+
+// Edges of a cube, as pairs of corners.
+const EDGES: [[usize; 2]; 12] = [
+    [0, 1],
+    [2, 3],
+    [4, 5],
+    [6, 7],
+    [0, 2],
+    [1, 3],
+    [4, 6],
+    [5, 7],
+    [0, 4],
+    [1, 5],
+    [2, 6],
+    [3, 7],
+];
+
+// Surface nets: one vertex in each cell of 8 voxels the surface passes through, at the average of
+// where it crosses the edges of the cell, and a quad for each crossed edge between the 4 cells
+// around it. Cells with voxels no view saw are skipped, as their distance is unknown.
+fn surface_nets(grid: &Grid, field: &Field) -> Mesh {
+    let r = grid.res;
+    let cells = r - 1;
+    let cell_index = |x: usize, y: usize, z: usize| (z * cells + y) * cells + x;
+    let mut vertex_of_cell = vec![u32::MAX; cells.pow(3)];
+    let mut mesh = Mesh::default();
+
+    for z in 0..cells {
+        for y in 0..cells {
+            for x in 0..cells {
+                let corners = CORNERS.map(|[dx, dy, dz]| grid.index(x + dx, y + dy, z + dz));
+                if corners.iter().any(|&i| field.weight[i] <= 0.0) {
+                    continue;
+                }
+                let values = corners.map(|i| field.sdf[i]);
+                let inside = values.iter().filter(|v| **v < 0.0).count();
+                if inside == 0 || inside == 8 {
+                    continue;
+                }
+
+                let mut sum = Vec3::ZERO;
+                let mut crossings = 0;
+                for [a, b] in EDGES {
+                    let (va, vb) = (values[a], values[b]);
+                    if (va < 0.0) == (vb < 0.0) {
+                        continue;
+                    }
+                    let t = va / (va - vb);
+                    let pa = Vec3::from_array(CORNERS[a].map(|c| c as f32));
+                    let pb = Vec3::from_array(CORNERS[b].map(|c| c as f32));
+                    sum += pa.lerp(pb, t);
+                    crossings += 1;
+                }
+                let local = sum / crossings as f32;
+                let cell = Vec3::new(x as f32, y as f32, z as f32);
+
+                let mut color = Vec3::ZERO;
+                for &i in &corners {
+                    color += Vec3::from_slice(&field.colors[i * 3..i * 3 + 3]);
+                }
+
+                vertex_of_cell[cell_index(x, y, z)] = mesh.positions.len() as u32;
+                mesh.positions.push(grid.world(cell + local));
+                mesh.colors.push((color / 8.0).clamp(Vec3::ZERO, Vec3::ONE));
+            }
+        }
+    }
+
+    // Each axis, with the two axes the quads around its edges span, in a right handed order.
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        for z in 0..r {
+            for y in 0..r {
+                for x in 0..r {
+                    let p = [x, y, z];
+                    // The edge to the next voxel along the axis, and the cells around it.
+                    if p[axis] >= cells || p[u] == 0 || p[v] == 0 || p[u] >= cells || p[v] >= cells
+                    {
+                        continue;
+                    }
+                    let mut next = p;
+                    next[axis] += 1;
+                    let (i0, i1) = (grid.index(x, y, z), grid.index(next[0], next[1], next[2]));
+                    if field.weight[i0] <= 0.0 || field.weight[i1] <= 0.0 {
+                        continue;
+                    }
+                    let (a, b) = (field.sdf[i0] < 0.0, field.sdf[i1] < 0.0);
+                    if a == b {
+                        continue;
+                    }
+
+                    let cell = |du: usize, dv: usize| {
+                        let mut c = p;
+                        c[u] -= du;
+                        c[v] -= dv;
+                        vertex_of_cell[cell_index(c[0], c[1], c[2])]
+                    };
+                    // Counter clockwise around the axis.
+                    let quad = [cell(1, 1), cell(0, 1), cell(0, 0), cell(1, 0)];
+                    if quad.contains(&u32::MAX) {
+                        continue;
+                    }
+                    // Faces point out of the surface, from the inside to the outside voxel.
+                    let [q0, q1, q2, q3] = if a {
+                        quad
+                    } else {
+                        [quad[3], quad[2], quad[1], quad[0]]
+                    };
+                    mesh.triangles.push([q0, q1, q2]);
+                    mesh.triangles.push([q0, q2, q3]);
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+// Views all around a sphere, looking at its center.
+fn sphere_cameras(center: Vec3, radius: f32, count: u32) -> Vec<Camera> {
+    let fov = 50.0f64.to_radians();
+    // Far enough for the sphere to fit in view.
+    let distance = radius / (fov as f32 * 0.5).sin();
+    // A Fibonacci lattice spreads the views evenly.
+    let golden_angle = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+    (0..count)
+        .map(|i| {
+            let y = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+            let ring = (1.0 - y * y).sqrt();
+            let angle = golden_angle * i as f32;
+            let dir = Vec3::new(ring * angle.cos(), y, ring * angle.sin());
+            let position = center + dir * distance;
+            // Don't look straight along the up axis.
+            let up = if y.abs() > 0.99 { Vec3::X } else { Vec3::Y };
+            Camera::new(
+                position,
+                look_at(position, center, up),
+                fov,
+                fov,
+                glam::vec2(0.5, 0.5),
+            )
+        })
+        .collect()
+}
+
+/// Extract a mesh of the surface of the splats.
+///
+/// Fuses depth maps rendered from `cameras`, or from views all around the splats if there are none.
+/// The mesh covers the bulk of the splats, ignoring outliers.
+pub async fn extract_mesh(
+    splats: &Splats<MainBackend>,
+    cameras: &[Camera],
+    config: &MeshConfig,
+) -> anyhow::Result<Mesh> {
+    let means = splats
+        .means
+        .val()
+        .into_data_async()
+        .await
+        .into_vec::<f32>()
+        .expect("Wrong type");
+    let points: Vec<_> = means.chunks_exact(3).map(Vec3::from_slice).collect();
+    let fit = SceneTransform::from_points(&points);
+    let radius = 1.0 / fit.scale;
+    let center = -fit.translation * radius;
+
+    let res = config.mesh_resolution.max(2) as usize;
+    let grid = Grid {
+        origin: center - radius,
+        voxel_size: 2.0 * radius / res as f32,
+        res,
+    };
+
+    let sphere_views;
+    let cameras = if cameras.is_empty() {
+        sphere_views = sphere_cameras(center, radius, config.mesh_views.max(1));
+        &sphere_views
+    } else {
+        cameras
+    };
+
+    let field = fuse(splats, cameras, config.mesh_view_size.max(8), &grid).await;
+    let mesh = surface_nets(&grid, &field);
+    anyhow::ensure!(!mesh.triangles.is_empty(), "No surface found in the splats");
+    Ok(mesh)
+}
+
+/// Write a mesh to an .obj, .glb, .usda or .usdz file, depending on the extension.
+#[cfg(not(target_family = "wasm"))]
+pub async fn write_mesh(mesh: &Mesh, path: &Path) -> anyhow::Result<()> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase);
+    let data = match ext.as_deref() {
+        Some("glb") => mesh.to_glb(),
+        Some("obj") => mesh.to_obj(),
+        Some("usda") => mesh.to_usd().to_usda(),
+        Some("usdz") => mesh.to_usd().to_usdz()?,
+        _ => anyhow::bail!("Meshes can only be written to .obj, .glb, .usda or .usdz files"),
+    };
+    rrfd::write_atomic(path, &data)
+        .await
+        .with_context(|| format!("Failed to write mesh to {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn obj_round_trip() {
+        let mesh = Mesh {
+            positions: vec![Vec3::ZERO, Vec3::X, Vec3::Y],
+            colors: vec![Vec3::X, Vec3::Y, Vec3::Z],
+            triangles: vec![[0, 1, 2]],
+        };
+        let decoded = Mesh::from_obj(&mesh.to_obj()).expect("Valid OBJ");
+        assert_eq!(decoded.positions, mesh.positions);
+        assert_eq!(decoded.colors, mesh.colors);
+        assert_eq!(decoded.triangles, mesh.triangles);
+
+        // Quads with texture coordinates and relative indices.
+        let quad = b"v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nf 1/1 2/1 -2/1 -1/1\n";
+        let decoded = Mesh::from_obj(quad).expect("Valid OBJ");
+        assert_eq!(decoded.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(decoded.colors, vec![Vec3::ONE; 4]);
+
+        assert!(Mesh::from_obj(b"v 0 0 0\nf 1 2 3\n").is_err());
+    }
+
+    #[test]
+    fn surface_nets_closes_a_sphere() {
+        let grid = Grid {
+            origin: Vec3::splat(-1.0),
+            voxel_size: 0.1,
+            res: 20,
+        };
+        let n = grid.res.pow(3);
+        let mut sdf = vec![0.0; n];
+        for z in 0..grid.res {
+            for y in 0..grid.res {
+                for x in 0..grid.res {
+                    let p = grid.world(Vec3::new(x as f32, y as f32, z as f32));
+                    sdf[grid.index(x, y, z)] = p.length() - 0.6;
+                }
+            }
+        }
+        let field = Field {
+            sdf,
+            weight: vec![1.0; n],
+            colors: vec![0.5; n * 3],
+        };
+        let mesh = surface_nets(&grid, &field);
+
+        assert!(!mesh.triangles.is_empty());
+        for p in &mesh.positions {
+            assert!((p.length() - 0.6).abs() < 0.1);
+        }
+        // A closed surface has every edge shared by two faces, in opposite directions.
+        let mut edges = std::collections::HashSet::new();
+        for [a, b, c] in &mesh.triangles {
+            for edge in [(*a, *b), (*b, *c), (*c, *a)] {
+                assert!(edges.insert(edge), "Faces aren't oriented consistently");
+            }
+        }
+        for &(a, b) in &edges {
+            assert!(edges.contains(&(b, a)), "Surface has a hole");
+        }
+        // Faces point outwards.
+        let [a, b, c] = mesh.triangles[0].map(|i| mesh.positions[i as usize]);
+        assert!((b - a).cross(c - a).dot(a) > 0.0);
+    }
+}
@@ -1,12 +1,28 @@
-use brush_dataset::Dataset;
+use brush_dataset::{Dataset, splat_export::SplatLabels};
 use brush_render::MainBackend;
 use brush_render::gaussian_splats::Splats;
+use brush_render::motion::SplatMotion;
 use brush_train::msg::{RefineStats, TrainStepStats};
+use brush_vfs::MemoryFile;
+
+use crate::planner::RunPlan;
 use glam::Vec3;
 use web_time::Duration;
 
+/// Most snapshots a run keeps for its replay. Past this, snapshots are thinned out to every other
+/// one, and recorded half as often.
+pub const MAX_REPLAY_SNAPSHOTS: usize = 256;
+
 pub enum ProcessMessage {
     NewSource,
+    /// The source is encrypted. Loading continues once a passphrase is sent back, and fails if
+    /// the sender is dropped.
+    ///
+    /// Nb: `retry` is set when the previous passphrase was wrong.
+    PassphraseRequired {
+        retry: bool,
+        reply: tokio::sync::mpsc::Sender<String>,
+    },
     StartLoading {
         training: bool,
     },
@@ -19,6 +35,8 @@ pub enum ProcessMessage {
         splats: Box<Splats<MainBackend>>,
         frame: u32,
         total_frames: u32,
+        /// Labels of the splats, for files saved with them.
+        labels: Option<SplatLabels>,
     },
     /// Loaded a bunch of viewpoints to train on.
     Dataset {
@@ -27,14 +45,37 @@ pub enum ProcessMessage {
     /// Splat, or dataset and initial splat, are done loading.
     #[allow(unused)]
     DoneLoading,
+    /// Estimated cost of the training run, sent right before training starts.
+    RunPlan {
+        plan: RunPlan,
+    },
     /// Some number of training steps are done.
     #[allow(unused)]
     TrainStep {
         splats: Box<Splats<MainBackend>>,
+        /// How the splats move over time, when training a dynamic scene.
+        motion: Option<Box<SplatMotion<MainBackend>>>,
         stats: Box<TrainStepStats<MainBackend>>,
         iter: u32,
         total_elapsed: Duration,
     },
+    /// Some number of training steps are done by a training process elsewhere, which the splats
+    /// don't come with. Its splats are sent as [`ProcessMessage::ViewSplats`] now and then.
+    RemoteTrainStep {
+        iter: u32,
+        total_steps: u32,
+        num_splats: u32,
+        total_elapsed: Duration,
+        paused: bool,
+    },
+    /// A snapshot of the splats during training, to replay how training progressed.
+    ///
+    /// Nb: To keep these small, snapshots only have SH degree 0.
+    #[allow(unused)]
+    Snapshot {
+        splats: Box<Splats<MainBackend>>,
+        iter: u32,
+    },
     /// Some number of training steps are done.
     #[allow(unused)]
     RefineStep {
@@ -42,6 +83,13 @@ pub enum ProcessMessage {
         cur_splat_count: u32,
         iter: u32,
     },
+    /// The files of an export at step `iter`, for when there's no disk to write them to, eg. on
+    /// the web.
+    #[allow(unused)]
+    Exported {
+        iter: u32,
+        files: Vec<MemoryFile>,
+    },
     /// Eval was run successfully with these results.
     #[allow(unused)]
     EvalResult {
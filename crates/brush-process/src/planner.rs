@@ -0,0 +1,401 @@
+// Estimates of how long a training run takes and how much GPU memory and disk space it needs, to
+// catch runs that won't fit before spending any time on them.
+use std::sync::Mutex;
+
+use brush_dataset::Dataset;
+use brush_render::{
+    MainBackend,
+    gaussian_splats::{RandomSplatsConfig, Splats},
+};
+use burn_cubecl::cubecl::Runtime;
+use burn_wgpu::{WgpuDevice, WgpuRuntime};
+use glam::UVec2;
+use rand::SeedableRng;
+use web_time::{Duration, Instant};
+
+use crate::config::ProcessArgs;
+
+// Floats per splat for a given SH degree: means, scales, rotation, opacity and SH coefficients.
+pub(crate) fn floats_per_splat(sh_degree: u32) -> u64 {
+    3 + 3 + 4 + 1 + 3 * (sh_degree as u64 + 1).pow(2)
+}
+
+// Each parameter is stored along with its gradient and two Adam moments.
+const PARAM_COPIES: u64 = 4;
+// Projected splats and the screen space gradients of the backward pass.
+const PROJECTED_BYTES_PER_SPLAT: u64 = 96;
+// Tile intersections, assuming a splat covers a handful of tiles. Sorting needs two copies of them.
+const INTERSECT_BYTES_PER_SPLAT: u64 = 8 * 8 * 2;
+// Rendered image, ground truth, their gradients and the SSIM intermediates.
+const BYTES_PER_PIXEL: u64 = 192;
+
+// A training step is a forward pass, a backward pass which is a few times slower, and an
+// optimizer step.
+const TRAIN_STEP_COST: f64 = 4.0;
+// Splat counts for the micro-benchmark. Two sizes, to split the cost per splat from the fixed cost
+// per image.
+const BENCH_SPLATS: [usize; 2] = [50_000, 200_000];
+const BENCH_RUNS: u32 = 3;
+
+// Render cost measured on each device and image size, as the fixed seconds per render and the
+// seconds per splat. The benchmark gives the same answer every run, so only runs once.
+static RENDER_COSTS: Mutex<Vec<(WgpuDevice, UVec2, RenderCost)>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy)]
+struct RenderCost {
+    fixed: f64,
+    per_splat: f64,
+}
+
+/// Estimated cost of a training run.
+#[derive(Debug, Clone)]
+pub struct RunPlan {
+    /// Step the run ends at.
+    pub total_steps: u32,
+    /// Estimated time to train all remaining steps.
+    pub duration: Duration,
+    /// Estimated GPU memory at the peak of the run, in bytes. This assumes the splat count grows
+    /// all the way to the max, so it is an upper bound.
+    pub peak_memory: u64,
+    /// GPU memory available, in bytes, if given.
+    pub memory_budget: Option<u64>,
+    /// Estimated size of the largest buffer of the run, in bytes, at the peak splat count.
+    pub largest_buffer: u64,
+    /// Largest buffer the adapter can bind, in bytes, if known.
+    pub buffer_limit: Option<u64>,
+}
+
+impl RunPlan {
+    /// Whether the run is likely to run out of GPU memory, or to need bigger buffers than the
+    /// adapter supports.
+    pub fn exceeds_budget(&self) -> bool {
+        self.memory_budget
+            .is_some_and(|budget| self.peak_memory > budget)
+            || self
+                .buffer_limit
+                .is_some_and(|limit| self.largest_buffer > limit)
+    }
+}
+
+/// GPU memory needed to train `num_splats` splats on images of `num_pixels` pixels, rendering
+/// `batch_size` views per step.
+pub fn estimate_memory(num_splats: u32, sh_degree: u32, num_pixels: u64, batch_size: u32) -> u64 {
+    // Every view in a batch keeps its own render buffers around for the backward pass.
+    let per_view = num_splats as u64 * (PROJECTED_BYTES_PER_SPLAT + INTERSECT_BYTES_PER_SPLAT)
+        + num_pixels * BYTES_PER_PIXEL;
+    num_splats as u64 * floats_per_splat(sh_degree) * 4 * PARAM_COPIES
+        + per_view * batch_size.max(1) as u64
+}
+
+/// Size of the largest single buffer when training `num_splats` splats on images of `num_pixels`
+/// pixels: the SH coefficients, the tile intersections, or the rendered image.
+pub fn estimate_largest_buffer(num_splats: u32, sh_degree: u32, num_pixels: u64) -> u64 {
+    let sh_bytes = num_splats as u64 * 3 * (sh_degree as u64 + 1).pow(2) * 4;
+    // Sorting keeps two copies of the intersections, in separate buffers.
+    let intersect_bytes = num_splats as u64 * INTERSECT_BYTES_PER_SPLAT / 2;
+    let image_bytes = num_pixels * 4 * 4;
+    sh_bytes.max(intersect_bytes).max(image_bytes)
+}
+
+/// Average splat count over the steps from `start` to `end`, assuming the count grows linearly
+/// from `init` to `max` until `growth_stop`, and stays constant after.
+fn average_splats(init: u32, max: u32, growth_stop: u32, start: u32, end: u32) -> f64 {
+    let count_at = |iter: f64| {
+        let t = (iter / growth_stop.max(1) as f64).min(1.0);
+        init as f64 + (max as f64 - init as f64).max(0.0) * t
+    };
+    if end <= start {
+        return count_at(start as f64);
+    }
+    // The count is piecewise linear, so averaging over the two pieces is exact.
+    let (start, end) = (start as f64, end as f64);
+    let knee = (growth_stop as f64).clamp(start, end);
+    let growing = (knee - start) * (count_at(start) + count_at(knee)) / 2.0;
+    let constant = (end - knee) * count_at(end);
+    (growing + constant) / (end - start)
+}
+
+// Number of times something done every `every` steps happens in a run from `start` to `end`,
+// counting the last step, which always is.
+fn times_in_run(start: u32, end: u32, every: u32) -> u64 {
+    if end <= start {
+        return 0;
+    }
+    let every = every.max(1);
+    (end / every - start / every) as u64 + u64::from(end % every != 0)
+}
+
+/// Disk space a training run writes to the export path, in bytes. With `export`, this includes
+/// the exported splats. This assumes the splat count grows all the way to the max, so it is an
+/// upper bound.
+pub fn estimate_disk_usage(
+    args: &ProcessArgs,
+    dataset: &Dataset,
+    init_splats: u32,
+    export: bool,
+) -> u64 {
+    let config = &args.process_config;
+    let start = config.start_iter;
+    let end = args.train_config.total_steps;
+    let mut bytes = 0;
+
+    if export {
+        let max_splats = args.train_config.max_splats.max(init_splats) as u64;
+        let ply_bytes = max_splats * floats_per_splat(args.model_config.sh_degree) * 4;
+        let exports = times_in_run(start, end, config.export_every);
+        // Exports to the same name replace each other, but the new one is written next to the old
+        // one first.
+        let files = if config.export_name.contains("{iter}") {
+            exports
+        } else {
+            exports.min(2)
+        };
+        bytes += ply_bytes * files;
+    }
+
+    if let Some(eval) = dataset.eval.as_ref().filter(|_| config.eval_save_to_disk) {
+        // Assume the images don't compress.
+        let eval_bytes: u64 = eval
+            .views
+            .iter()
+            .map(|v| {
+                let size = v.image.dimensions();
+                size.x as u64 * size.y as u64 * 3
+            })
+            .sum();
+        bytes += eval_bytes * times_in_run(start, end, config.eval_every);
+    }
+    bytes
+}
+
+/// Check that `dir` can be written to and has room for `needed` bytes, creating it if it doesn't
+/// exist yet.
+#[cfg(not(target_family = "wasm"))]
+pub async fn check_output_dir(dir: &std::path::Path, needed: u64) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    tokio::fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("Can't create output folder {}", dir.display()))?;
+    let probe = dir.join(".brush_write_check");
+    tokio::fs::write(&probe, b"")
+        .await
+        .with_context(|| format!("Can't write to output folder {}", dir.display()))?;
+    let _ = tokio::fs::remove_file(&probe).await;
+
+    if let Some(available) = available_space(dir).filter(|&available| available < needed) {
+        anyhow::bail!(
+            "Output folder {} has {} MB free, but is estimated to need {} MB. Free up space, or \
+             write to another folder.",
+            dir.display(),
+            available / (1024 * 1024),
+            needed / (1024 * 1024)
+        );
+    }
+    Ok(())
+}
+
+// Free space on the disk `path` is on, if it can be found.
+#[cfg(not(target_family = "wasm"))]
+fn available_space(path: &std::path::Path) -> Option<u64> {
+    let path = std::fs::canonicalize(path).ok()?;
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    // The most specific mount point the path is in.
+    disks
+        .list()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
+
+// Time a few forward renders of `num_splats` random splats.
+async fn time_render(dataset: &Dataset, num_splats: usize, device: &WgpuDevice) -> Duration {
+    let Some(view) = dataset.train.views.first() else {
+        return Duration::ZERO;
+    };
+    let mut rng = rand::rngs::StdRng::from_seed([0; 32]);
+    let bounds = dataset.train.bounds();
+    let config = RandomSplatsConfig::new().with_init_count(num_splats);
+    let splats: Splats<MainBackend> = Splats::from_random_config(&config, bounds, &mut rng, device);
+    let img_size = view.image.dimensions();
+
+    let mut total = Duration::ZERO;
+    // The first render compiles the kernels, don't count it.
+    for run in 0..=BENCH_RUNS {
+        let start = Instant::now();
+        let (img, _) = splats.render(&view.camera, img_size, true);
+        // Reading back the image waits for the render to finish.
+        img.into_data_async().await;
+        if run > 0 {
+            total += start.elapsed();
+        }
+    }
+    total / BENCH_RUNS
+}
+
+// The cost of renders on `device`, measured with the first view of `dataset` the first time it's
+// asked for, and fit as a fixed cost plus a cost per splat.
+async fn render_cost(dataset: &Dataset, device: &WgpuDevice) -> RenderCost {
+    let img_size = dataset
+        .train
+        .views
+        .first()
+        .map_or(UVec2::ZERO, |view| view.image.dimensions());
+    let cached = RENDER_COSTS
+        .lock()
+        .expect("Render costs poisoned")
+        .iter()
+        .find(|(cached_device, size, _)| cached_device == device && *size == img_size)
+        .map(|(_, _, cost)| *cost);
+    if let Some(cost) = cached {
+        return cost;
+    }
+
+    let [small, large] = BENCH_SPLATS;
+    let small_time = time_render(dataset, small, device).await.as_secs_f64();
+    let large_time = time_render(dataset, large, device).await.as_secs_f64();
+    let per_splat = ((large_time - small_time) / (large - small) as f64).max(0.0);
+    let cost = RenderCost {
+        fixed: (small_time - per_splat * small as f64).max(0.0),
+        per_splat,
+    };
+    RENDER_COSTS
+        .lock()
+        .expect("Render costs poisoned")
+        .push((device.clone(), img_size, cost));
+    cost
+}
+
+/// Estimate the cost of training on `dataset` with `args`, starting from `init_splats` splats.
+///
+/// The first plan on a device runs a quick benchmark, so takes a moment. Without a memory budget
+/// in `args`, only the limits of the adapter are checked.
+pub async fn plan_run(
+    args: &ProcessArgs,
+    dataset: &Dataset,
+    init_splats: u32,
+    device: &WgpuDevice,
+) -> RunPlan {
+    let train_config = &args.train_config;
+    let max_splats = train_config.max_splats.max(init_splats);
+    let num_pixels = dataset
+        .train
+        .views
+        .iter()
+        .map(|v| {
+            let mut size = v.image.dimensions();
+            if let Some(patch_size) = train_config.patch_size {
+                size = size.min(glam::UVec2::splat(patch_size));
+            }
+            size.x as u64 * size.y as u64
+        })
+        .max()
+        .unwrap_or(0);
+    let batch_size = train_config.batch_size.max(1);
+    let peak_memory = estimate_memory(
+        max_splats,
+        args.model_config.sh_degree,
+        num_pixels,
+        batch_size,
+    );
+
+    let cost = render_cost(dataset, device).await;
+
+    let start = args.process_config.start_iter;
+    let end = train_config.total_steps;
+    let splats = average_splats(
+        init_splats,
+        max_splats,
+        train_config.growth_stop_iter,
+        start,
+        end,
+    );
+    let step_time = (cost.fixed + cost.per_splat * splats) * TRAIN_STEP_COST * batch_size as f64;
+    let steps = end.saturating_sub(start) as f64;
+
+    RunPlan {
+        total_steps: end,
+        duration: Duration::from_secs_f64(step_time * steps),
+        peak_memory,
+        memory_budget: args
+            .process_config
+            .memory_budget_mb
+            .map(|mb| mb * 1024 * 1024),
+        largest_buffer: estimate_largest_buffer(
+            max_splats,
+            args.model_config.sh_degree,
+            num_pixels,
+        ),
+        buffer_limit: Some(
+            WgpuRuntime::client(device)
+                .properties()
+                .memory
+                .max_page_size,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn average_splats_follows_growth() {
+        // Constant count when there's no growth.
+        assert_eq!(average_splats(100, 100, 1000, 0, 2000), 100.0);
+        // Growing from 0 to 100 over the whole run averages to half.
+        assert_eq!(average_splats(0, 100, 1000, 0, 1000), 50.0);
+        // Half the time growing, half the time at the max.
+        assert_eq!(average_splats(0, 100, 1000, 0, 2000), 75.0);
+        // Resuming after growth stopped.
+        assert_eq!(average_splats(0, 100, 1000, 1500, 2000), 100.0);
+    }
+
+    #[test]
+    fn counts_exports_including_the_last_step() {
+        assert_eq!(times_in_run(0, 30_000, 5000), 6);
+        assert_eq!(times_in_run(0, 29_000, 5000), 6);
+        assert_eq!(times_in_run(12_000, 30_000, 5000), 4);
+        assert_eq!(times_in_run(30_000, 30_000, 5000), 0);
+    }
+
+    #[test]
+    fn memory_grows_with_splats_and_sh() {
+        let base = estimate_memory(1_000_000, 0, 0, 1);
+        assert_eq!(estimate_memory(2_000_000, 0, 0, 1), 2 * base);
+        assert!(estimate_memory(1_000_000, 3, 0, 1) > base);
+        assert!(estimate_memory(1_000_000, 0, 1_000_000, 1) > base);
+        // Batches only grow the per view buffers, not the parameters.
+        let batched = estimate_memory(1_000_000, 0, 0, 2);
+        assert!(batched > base && batched < 2 * base);
+        let plan = RunPlan {
+            total_steps: 0,
+            duration: Duration::ZERO,
+            peak_memory: base,
+            memory_budget: Some(base - 1),
+            largest_buffer: 0,
+            buffer_limit: None,
+        };
+        assert!(plan.exceeds_budget());
+    }
+
+    #[test]
+    fn buffers_past_the_adapter_limit_exceed_the_budget() {
+        let largest = estimate_largest_buffer(1_000_000, 3, 0);
+        // SH coefficients are the biggest buffer of high SH degree splats.
+        assert_eq!(largest, 1_000_000 * 48 * 4);
+        assert_eq!(estimate_largest_buffer(0, 0, 100), 100 * 16);
+        let plan = |buffer_limit| RunPlan {
+            total_steps: 0,
+            duration: Duration::ZERO,
+            peak_memory: largest,
+            memory_budget: None,
+            largest_buffer: largest,
+            buffer_limit,
+        };
+        assert!(!plan(None).exceeds_budget());
+        assert!(!plan(Some(largest)).exceeds_budget());
+        assert!(plan(Some(largest - 1)).exceeds_budget());
+    }
+}
@@ -1,7 +1,11 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
-use async_fn_stream::try_fn_stream;
-use brush_vfs::DataSource;
+use anyhow::anyhow;
+use async_fn_stream::{TryStreamEmitter, try_fn_stream};
+use brush_vfs::{
+    BrushVfs, DataSource, DataSourceError, EncryptedData, VfsConstructError,
+    encryption::EncryptionError, ranges::RangeSource,
+};
 use burn_cubecl::cubecl::Runtime;
 use burn_wgpu::{WgpuDevice, WgpuRuntime};
 use tokio::sync::oneshot::Receiver;
@@ -9,44 +13,191 @@ use tokio_stream::Stream;
 
 #[allow(unused)]
 use brush_dataset::splat_export;
+use brush_dataset::{composition::find_composition, splat_formats::SplatFormat};
 
 use crate::{
-    config::ProcessArgs, message::ProcessMessage, train_stream::train_stream,
-    view_stream::view_stream,
+    config::ProcessArgs,
+    control::{TrainCommand, TrainCommands, ViewFocus},
+    message::ProcessMessage,
+    train_stream::train_stream,
+    view_stream::{view_chunked, view_composition, view_stream},
 };
 
+/// Ask for a passphrase until the file opens, or no passphrase is given.
+async fn unlock(
+    encrypted: &EncryptedData,
+    emitter: &TryStreamEmitter<ProcessMessage, anyhow::Error>,
+) -> anyhow::Result<BrushVfs> {
+    let mut retry = false;
+    loop {
+        let (reply, mut receiver) = tokio::sync::mpsc::channel(1);
+        emitter
+            .emit(ProcessMessage::PassphraseRequired { retry, reply })
+            .await;
+        let passphrase = receiver
+            .recv()
+            .await
+            .ok_or_else(|| anyhow!("No passphrase given to open the encrypted file"))?;
+
+        match encrypted.unlock(&passphrase).await {
+            Err(VfsConstructError::Decrypt(EncryptionError::WrongPassphrase)) => retry = true,
+            vfs => return Ok(vfs?),
+        }
+    }
+}
+
+#[cfg(target_family = "wasm")]
+fn dataset_name(source: &DataSource) -> String {
+    match source {
+        DataSource::Url(url) | DataSource::CachedUrl(url) => {
+            url.rsplit('/').next().unwrap_or(url).to_owned()
+        }
+        DataSource::Memory(file) => file.name.clone(),
+        _ => "dataset.zip".to_owned(),
+    }
+}
+
+/// Keep the dataset of a training run in the browser, so the run can continue after the page is
+/// reloaded. When `resume`-ing the run kept before, returns its latest checkpoint instead.
+#[cfg(target_family = "wasm")]
+async fn keep_web_run(
+    vfs: &BrushVfs,
+    resume: bool,
+    name: &str,
+    url: Option<&str>,
+    device: &WgpuDevice,
+) -> Option<crate::train_stream::Checkpoint> {
+    use brush_dataset::splat_import::load_splat_from_ply;
+    use brush_vfs::web_store::WebStore;
+    use tokio_stream::StreamExt;
+
+    let store = WebStore::open()
+        .await
+        .inspect_err(|e| log::warn!("Can't keep the run in the browser: {e}"))
+        .ok()?;
+    if !resume {
+        if let Some(data) = vfs.archive_data() {
+            if let Err(e) = store.save_dataset(name, url, &data).await {
+                log::warn!("Failed to keep the dataset in the browser: {e}");
+            }
+        }
+        return None;
+    }
+
+    let stored = store
+        .checkpoint()
+        .await
+        .inspect_err(|e| log::warn!("Failed to read the checkpoint of the run: {e}"))
+        .ok()??;
+    let reader = std::io::Cursor::new(stored.ply);
+    let mut stream = std::pin::pin!(load_splat_from_ply(reader, None, device.clone()));
+    let mut splats = None;
+    while let Some(message) = stream.next().await {
+        match message {
+            Ok(message) => splats = Some(message.splats),
+            Err(e) => {
+                log::warn!("Failed to read the checkpoint of the run: {e}");
+                return None;
+            }
+        }
+    }
+    Some(crate::train_stream::Checkpoint {
+        iter: stored.iter,
+        splats: splats?,
+    })
+}
+
+// Chunked splat files are read in parts as they're needed, rather than all before viewing them.
+fn chunked_source(source: &DataSource) -> Option<RangeSource> {
+    let name = match source {
+        DataSource::Url(url) | DataSource::CachedUrl(url) => url.split('?').next()?,
+        DataSource::Path(path) => path.as_str(),
+        DataSource::Memory(file) => file.name.as_str(),
+        _ => return None,
+    };
+    if SplatFormat::from_path(Path::new(name)) != Some(SplatFormat::Chunked) {
+        return None;
+    }
+    RangeSource::from_data_source(source)
+}
+
 pub fn process_stream(
     source: DataSource,
     process_args: Receiver<ProcessArgs>,
     device: WgpuDevice,
+) -> impl Stream<Item = Result<ProcessMessage, anyhow::Error>> + 'static {
+    let (_, commands) = tokio::sync::mpsc::unbounded_channel::<TrainCommand>();
+    let (_, focus) = tokio::sync::watch::channel(None);
+    process_stream_with_control(source, process_args, device, commands, focus)
+}
+
+/// Like [`process_stream`], but training follows the `commands` sent while it runs, and chunked
+/// splat files load the chunks in view of `focus` first.
+pub fn process_stream_with_control(
+    source: DataSource,
+    process_args: Receiver<ProcessArgs>,
+    device: WgpuDevice,
+    commands: TrainCommands,
+    focus: ViewFocus,
 ) -> impl Stream<Item = Result<ProcessMessage, anyhow::Error>> + 'static {
     try_fn_stream(|emitter| async move {
         log::info!("Starting process with source {source:?}");
         emitter.emit(ProcessMessage::NewSource).await;
 
-        let vfs = Arc::new(source.into_vfs().await?);
+        if let Some(source) = chunked_source(&source) {
+            drop(process_args);
+            return view_chunked(source, focus, device, emitter).await;
+        }
+
+        #[cfg(target_family = "wasm")]
+        let (resume, dataset_name) = (matches!(source, DataSource::Stored), dataset_name(&source));
+        #[cfg(target_family = "wasm")]
+        let dataset_url = match &source {
+            DataSource::Url(url) | DataSource::CachedUrl(url) => Some(url.clone()),
+            _ => None,
+        };
+
+        let vfs = match source.into_vfs().await {
+            Err(DataSourceError::VfsError(VfsConstructError::Encrypted(encrypted))) => {
+                unlock(&encrypted, &emitter).await?
+            }
+            vfs => vfs?,
+        };
+        let vfs = Arc::new(vfs);
 
         let client = WgpuRuntime::client(&device);
         // Start with memory cleared out.
         client.memory_cleanup();
 
         let vfs_counts = vfs.file_count();
-        let ply_count = vfs.files_with_extension("ply").count();
+        let splat_count = vfs
+            .file_paths()
+            .filter(|path| SplatFormat::from_path(path).is_some())
+            .count();
 
         log::info!(
-            "Mounted VFS with {} files. (plys: {})",
+            "Mounted VFS with {} files. (splat files: {})",
             vfs.file_count(),
-            ply_count
+            splat_count
         );
 
         log::info!("Start of view stream");
 
-        if vfs_counts == ply_count {
+        if let Some(path) = find_composition(&vfs) {
+            drop(process_args);
+            view_composition(vfs, &path, device, emitter).await?;
+        } else if vfs_counts == splat_count {
             drop(process_args);
             view_stream(vfs, device, emitter).await?;
         } else {
+            #[cfg(target_family = "wasm")]
+            let checkpoint =
+                keep_web_run(&vfs, resume, &dataset_name, dataset_url.as_deref(), &device).await;
+            #[cfg(not(target_family = "wasm"))]
+            let checkpoint = None;
+
             // Receive the processing args.
-            train_stream(vfs, process_args, device, emitter).await?;
+            train_stream(vfs, process_args, commands, device, emitter, checkpoint).await?;
         };
 
         Ok(())
@@ -0,0 +1,307 @@
+// A quick check of training quality on a tiny built in scene, to catch regressions in the training
+// math before they show up on real datasets.
+use std::{fmt, path::PathBuf};
+
+use brush_dataset::{config::ModelConfig, scene::SceneBatch};
+use brush_render::{
+    MainBackend,
+    bounding_box::BoundingBox,
+    camera::Camera,
+    camera_path::look_at,
+    gaussian_splats::{RandomSplatsConfig, Splats},
+};
+use brush_train::{config::TrainConfig, train::SplatTrainer};
+use burn::{
+    backend::Autodiff,
+    module::AutodiffModule,
+    prelude::Backend,
+    tensor::{Tensor, s},
+};
+use burn_wgpu::WgpuDevice;
+use clap::Args;
+use glam::{Quat, Vec3, vec2, vec3};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+
+const SEED: u64 = 42;
+const NUM_VIEWS: usize = 16;
+const VIEW_SIZE: u32 = 64;
+const CAMERA_DISTANCE: f32 = 3.0;
+const TARGET_SPLATS: usize = 200;
+const INIT_SPLATS: usize = 1000;
+const DEFAULT_STEPS: u32 = 1000;
+
+// Bounds for the default number of steps. These haven't been measured on a GPU yet, and are only
+// loose enough to catch training that breaks outright. Measure them with
+// `brush regress --save-baseline` to tighten them.
+const BASELINE_JSON: &str = include_str!("regression_baseline.json");
+// How far runs can be from the measured one and still pass, to allow for other GPUs and small
+// numerical changes.
+const PSNR_MARGIN: f32 = 1.0;
+const SPLATS_MARGIN: f32 = 0.25;
+
+#[derive(Args, Clone, Debug)]
+pub struct RegressionArgs {
+    /// Steps to train the scene for. The committed baseline is for the default.
+    #[arg(long, default_value_t = DEFAULT_STEPS)]
+    pub steps: u32,
+    /// Lowest average PSNR of the views to pass. Defaults to the committed baseline.
+    #[arg(long)]
+    pub min_psnr: Option<f32>,
+    /// Lowest splat count after training to pass. Defaults to the committed baseline.
+    #[arg(long)]
+    pub min_splats: Option<u32>,
+    /// Highest splat count after training to pass. Defaults to the committed baseline.
+    #[arg(long)]
+    pub max_splats: Option<u32>,
+    /// Write bounds around the results of this run to a baseline file, instead of checking them.
+    /// Used to update `regression_baseline.json` when training changes on purpose.
+    #[arg(long)]
+    pub save_baseline: Option<PathBuf>,
+}
+
+impl Default for RegressionArgs {
+    fn default() -> Self {
+        Self {
+            steps: DEFAULT_STEPS,
+            min_psnr: None,
+            min_splats: None,
+            max_splats: None,
+            save_baseline: None,
+        }
+    }
+}
+
+impl RegressionArgs {
+    /// Bounds to check runs against: the committed baseline, with any bounds given in the args.
+    pub fn bounds(&self) -> RegressionBaseline {
+        let baseline = RegressionBaseline::committed();
+        RegressionBaseline {
+            min_psnr: self.min_psnr.unwrap_or(baseline.min_psnr),
+            min_splats: self.min_splats.unwrap_or(baseline.min_splats),
+            max_splats: self.max_splats.unwrap_or(baseline.max_splats),
+        }
+    }
+}
+
+/// Bounds the results of the regression scene have to stay within.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RegressionBaseline {
+    pub min_psnr: f32,
+    pub min_splats: u32,
+    pub max_splats: u32,
+}
+
+impl RegressionBaseline {
+    /// The baseline committed with the source.
+    pub fn committed() -> Self {
+        serde_json::from_str(BASELINE_JSON).expect("Committed regression baseline must be valid")
+    }
+
+    /// Bounds around a measured run.
+    pub fn from_report(report: &RegressionReport) -> Self {
+        let splats = report.num_splats as f32;
+        Self {
+            min_psnr: ((report.psnr - PSNR_MARGIN) * 10.0).floor() / 10.0,
+            min_splats: (splats * (1.0 - SPLATS_MARGIN)).floor() as u32,
+            max_splats: (splats * (1.0 + SPLATS_MARGIN)).ceil() as u32,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Baseline serializes")
+    }
+}
+
+/// Results of training the regression scene.
+#[derive(Debug, Clone, Copy)]
+pub struct RegressionReport {
+    pub psnr: f32,
+    pub num_splats: u32,
+}
+
+impl fmt::Display for RegressionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PSNR {:.2}, {} splats", self.psnr, self.num_splats)
+    }
+}
+
+impl RegressionReport {
+    /// Check the results are within the bounds of `args`.
+    pub fn check(&self, args: &RegressionArgs) -> anyhow::Result<()> {
+        let bounds = args.bounds();
+        if self.psnr.is_nan() || self.psnr < bounds.min_psnr {
+            anyhow::bail!("PSNR {:.2} is below {:.2}", self.psnr, bounds.min_psnr);
+        }
+        if !(bounds.min_splats..=bounds.max_splats).contains(&self.num_splats) {
+            anyhow::bail!(
+                "{} splats is outside of {}..={}",
+                self.num_splats,
+                bounds.min_splats,
+                bounds.max_splats
+            );
+        }
+        Ok(())
+    }
+}
+
+// Cameras on two rings around the scene, looking at its center.
+fn view_cameras() -> Vec<Camera> {
+    (0..NUM_VIEWS)
+        .map(|i| {
+            let angle = i as f32 / NUM_VIEWS as f32 * std::f32::consts::TAU;
+            let height = if i % 2 == 0 { 0.5 } else { -0.5 };
+            let position = vec3(angle.cos(), height, angle.sin()) * CAMERA_DISTANCE;
+            let rotation = look_at(position, Vec3::ZERO, Vec3::NEG_Y);
+            Camera::new(position, rotation, 0.8, 0.8, vec2(0.5, 0.5))
+        })
+        .collect()
+}
+
+// The splats the views are rendered from: a small cloud of opaque, colored blobs.
+fn target_splats(rng: &mut StdRng, device: &WgpuDevice) -> Splats<MainBackend> {
+    let mut means = vec![];
+    let mut log_scales = vec![];
+    let mut colors = vec![];
+    for _ in 0..TARGET_SPLATS {
+        means.push(Vec3::from_array(
+            [(); 3].map(|_| rng.random_range(-0.8..0.8)),
+        ));
+        log_scales.push(Vec3::from_array(
+            [(); 3].map(|_| rng.random_range(-3.0..-1.5)),
+        ));
+        colors.extend([(); 3].map(|_| rng.random_range(-1.0..1.0)));
+    }
+    let rotations = vec![Quat::IDENTITY; TARGET_SPLATS];
+    let opacities = vec![3.0; TARGET_SPLATS];
+    Splats::from_raw(
+        &means,
+        Some(rotations.as_slice()),
+        Some(log_scales.as_slice()),
+        Some(colors.as_slice()),
+        Some(opacities.as_slice()),
+        device,
+    )
+}
+
+fn render_rgb(splats: &Splats<MainBackend>, camera: &Camera) -> Tensor<MainBackend, 3> {
+    let size = glam::uvec2(VIEW_SIZE, VIEW_SIZE);
+    splats.render(camera, size, true).0.slice(s![.., .., 0..3])
+}
+
+/// Train a tiny scene with a fixed seed, and report how well it reproduces its views.
+///
+/// The scene is rendered from built in splats, so this needs no data on disk. Views are trained
+/// in a fixed order, but results can still vary a bit between GPUs.
+pub async fn run_regression(args: &RegressionArgs, device: &WgpuDevice) -> RegressionReport {
+    <MainBackend as Backend>::seed(SEED);
+    let mut rng = StdRng::seed_from_u64(SEED);
+
+    let target = target_splats(&mut rng, device);
+    let views: Vec<_> = view_cameras()
+        .into_iter()
+        .map(|camera| {
+            let img = render_rgb(&target, &camera);
+            (camera, img)
+        })
+        .collect();
+
+    let config = TrainConfig::new().with_total_steps(args.steps);
+    let mut trainer = SplatTrainer::new(&config, device).with_seed(SEED);
+    let bounds = BoundingBox::from_min_max(Vec3::splat(-1.0), Vec3::splat(1.0));
+    let init_config = RandomSplatsConfig::new().with_init_count(INIT_SPLATS);
+    let mut splats =
+        Splats::<MainBackend>::from_random_config(&init_config, bounds, &mut rng, device)
+            .with_sh_degree(ModelConfig::new().sh_degree)
+            .into_autodiff();
+
+    for iter in 0..args.steps {
+        let view_index = iter as usize % NUM_VIEWS;
+        let (camera, img) = &views[view_index];
+        let batch = SceneBatch::<Autodiff<MainBackend>> {
+            img_tensor: Tensor::from_inner(img.clone()),
+            alpha_is_mask: false,
+            camera: camera.clone(),
+            view_index,
+        };
+        let (new_splats, _) = trainer.step(CAMERA_DISTANCE, iter, &[batch], splats).await;
+        let (new_splats, _) = trainer.refine_if_needed(iter, new_splats).await;
+        splats = new_splats;
+    }
+
+    let splats = splats.valid();
+    let mut psnr_sum = 0.0;
+    for (camera, gt) in &views {
+        // Round to 8 bits like the eval of a dataset does.
+        let rendered = (render_rgb(&splats, camera) * 255.0).round() / 255.0;
+        let mse = (rendered - gt.clone()).powi_scalar(2).mean();
+        let psnr = mse.recip().log() * 10.0 / std::f32::consts::LN_10;
+        let psnr = psnr
+            .into_data_async()
+            .await
+            .into_vec::<f32>()
+            .expect("Wrong type");
+        psnr_sum += psnr[0];
+    }
+
+    RegressionReport {
+        psnr: psnr_sum / NUM_VIEWS as f32,
+        num_splats: splats.num_splats(),
+    }
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn committed_baseline_is_valid() {
+        let baseline = RegressionBaseline::committed();
+        assert!(baseline.min_psnr > 0.0);
+        assert!(baseline.min_splats <= baseline.max_splats);
+    }
+
+    #[test]
+    fn baseline_brackets_the_measured_run() {
+        let report = RegressionReport {
+            psnr: 31.27,
+            num_splats: 4000,
+        };
+        let baseline = RegressionBaseline::from_report(&report);
+        assert_eq!(
+            baseline,
+            RegressionBaseline {
+                min_psnr: 30.2,
+                min_splats: 3000,
+                max_splats: 5000,
+            }
+        );
+        let args = RegressionArgs {
+            min_psnr: Some(baseline.min_psnr),
+            min_splats: Some(baseline.min_splats),
+            max_splats: Some(baseline.max_splats),
+            ..Default::default()
+        };
+        assert!(report.check(&args).is_ok());
+        let worse = RegressionReport {
+            psnr: 29.0,
+            ..report
+        };
+        assert!(worse.check(&args).is_err());
+    }
+
+    // Trains the scene like `brush regress` does. This needs a GPU and takes a while, so only runs
+    // when asked for with `cargo test -- --ignored`.
+    #[test]
+    #[ignore = "Trains for 1000 steps on the GPU"]
+    fn regression_scene_matches_baseline() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("Failed to build runtime");
+        let args = RegressionArgs::default();
+        let report = runtime.block_on(run_regression(&args, &WgpuDevice::DefaultDevice));
+        if let Err(err) = report.check(&args) {
+            panic!("Regression scene: {report}: {err}");
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+};
+
+use anyhow::{Context, bail};
+use brush_dataset::{
+    scene_transform::SceneTransform, splat_import::load_splat_from_ply,
+    watermark::overlay_watermark,
+};
+use brush_render::{
+    MainBackend,
+    camera_path::{CameraPath, FrameCoherence, render_shutter, render_shutter_instanced},
+    gaussian_splats::Splats,
+    instancing::{InstancedSplats, PropLayout},
+    shots::ShotList,
+};
+use glam::Vec3;
+use image::RgbaImage;
+use tokio_stream::StreamExt;
+
+use crate::{
+    config::RenderConfig,
+    stills::{render_shots, to_rgb8},
+};
+
+// How far past the near or far plane a visible splat can go before it starts fading out, relative
+// to the distance of the plane.
+const CULL_MARGIN: f32 = 0.1;
+
+enum FrameSink {
+    Png(PathBuf),
+    Ffmpeg(Child),
+}
+
+impl FrameSink {
+    fn new(out: &Path, size: glam::UVec2, fps: u32) -> anyhow::Result<Self> {
+        if out
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("mp4"))
+        {
+            let child = Command::new("ffmpeg")
+                .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "rgb24"])
+                .args(["-s", &format!("{}x{}", size.x, size.y)])
+                .args(["-r", &fps.to_string(), "-i", "-"])
+                // H.264 in yuv420p needs even dimensions. Frames are sRGB, convert them with the
+                // BT.709 matrix and tag them as such, otherwise players guess and colors come out
+                // washed out or shifted.
+                .args([
+                    "-vf",
+                    "pad=ceil(iw/2)*2:ceil(ih/2)*2,scale=out_color_matrix=bt709:out_range=tv",
+                ])
+                .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+                .args(["-colorspace", "bt709", "-color_primaries", "bt709"])
+                .args(["-color_trc", "iec61966-2-1", "-color_range", "tv"])
+                .arg(out)
+                .stdin(Stdio::piped())
+                .spawn()
+                .context("Failed to start ffmpeg, is it installed? Renders to a directory of PNG frames work without it.")?;
+            Ok(Self::Ffmpeg(child))
+        } else {
+            std::fs::create_dir_all(out)?;
+            Ok(Self::Png(out.to_path_buf()))
+        }
+    }
+
+    fn write(&mut self, index: u32, frame: &image::RgbImage) -> anyhow::Result<()> {
+        match self {
+            Self::Png(dir) => {
+                frame.save(dir.join(format!("frame_{index:05}.png")))?;
+            }
+            Self::Ffmpeg(child) => {
+                child
+                    .stdin
+                    .as_mut()
+                    .context("ffmpeg input was closed")?
+                    .write_all(frame.as_raw())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        if let Self::Ffmpeg(mut child) = self {
+            // Close the input so ffmpeg finishes the file.
+            drop(child.stdin.take());
+            let status = child.wait()?;
+            if !status.success() {
+                bail!("ffmpeg failed to encode the video ({status})");
+            }
+        }
+        Ok(())
+    }
+}
+
+fn load_watermark(config: &RenderConfig) -> anyhow::Result<Option<RgbaImage>> {
+    let Some(path) = &config.render_watermark else {
+        return Ok(None);
+    };
+    let mark = image::open(path).with_context(|| format!("Failed to load watermark {path}"))?;
+    Ok(Some(mark.into_rgba8()))
+}
+
+/// Where motion vector frames of a render go. These are next to the PNG frames, or in a
+/// directory next to a video.
+fn motion_dir(out: &Path) -> PathBuf {
+    if out
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mp4"))
+    {
+        let stem = out.file_stem().unwrap_or_default().to_string_lossy();
+        out.with_file_name(format!("{stem}_motion"))
+    } else {
+        out.to_path_buf()
+    }
+}
+
+/// A turntable around the splats, framing most of them.
+async fn turntable_path(splats: &Splats<MainBackend>, up: Vec3, duration: f32) -> CameraPath {
+    let means = splats
+        .means
+        .val()
+        .into_data_async()
+        .await
+        .into_vec::<f32>()
+        .expect("Wrong type");
+    let points: Vec<_> = means.chunks_exact(3).map(Vec3::from_slice).collect();
+
+    // This ignores outliers, which trained splats usually have plenty of.
+    let fit = SceneTransform::from_points(&points);
+    let radius = 1.0 / fit.scale;
+    let center = -fit.translation * radius;
+
+    let fov_y = 50.0f64.to_radians();
+    // Far enough for the bounding sphere to fit in view.
+    let distance = radius / (fov_y as f32 * 0.5).sin();
+    CameraPath::turntable(center, up, distance, distance * 0.25, fov_y, duration)
+}
+
+/// Place the copies of a prop from a layout file in the splats.
+///
+/// Returns None when the layout places no copies.
+async fn add_instances(
+    splats: Splats<MainBackend>,
+    layout_file: &str,
+) -> anyhow::Result<Option<InstancedSplats<MainBackend>>> {
+    let data = std::fs::read_to_string(layout_file)
+        .with_context(|| format!("Failed to read instances {layout_file}"))?;
+    let layout = serde_json::from_str::<PropLayout>(&data)
+        .with_context(|| format!("Failed to parse instances {layout_file}"))?;
+
+    let prop_file = Path::new(layout_file)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(&layout.prop);
+    let prop_data = std::fs::read(&prop_file)
+        .with_context(|| format!("Failed to read prop {}", prop_file.display()))?;
+    let prop_stream = load_splat_from_ply(std::io::Cursor::new(prop_data), None, splats.device());
+    let mut prop_stream = std::pin::pin!(prop_stream);
+    // Animated files end on their last frame.
+    let mut prop = None;
+    while let Some(message) = prop_stream.next().await {
+        prop = Some(message?.splats);
+    }
+    let prop = prop.with_context(|| format!("No splats in prop {}", prop_file.display()))?;
+
+    let instanced = InstancedSplats::new(splats, prop, &layout.instances, layout.variation);
+    if instanced.is_some() {
+        log::info!(
+            "Placing {} copies of {}",
+            layout.instances.len(),
+            prop_file.display()
+        );
+    }
+    Ok(instanced)
+}
+
+// What the frames of a video are rendered from.
+enum VideoSplats<'a> {
+    Splats(&'a Splats<MainBackend>),
+    Instanced(&'a InstancedSplats<MainBackend>),
+}
+
+/// Render splats along a camera path to a video, as set up in the config.
+///
+/// Uses the path file if set, and a turntable around the splats otherwise.
+pub async fn render_video(
+    splats: &Splats<MainBackend>,
+    up_axis: Option<Vec3>,
+    config: &RenderConfig,
+) -> anyhow::Result<()> {
+    let path = if let Some(path_file) = &config.render_path {
+        let data = std::fs::read_to_string(path_file)
+            .with_context(|| format!("Failed to read camera path {path_file}"))?;
+        serde_json::from_str::<CameraPath>(&data)
+            .with_context(|| format!("Failed to parse camera path {path_file}"))?
+    } else {
+        // Without any other info, assume the COLMAP convention where -y is up.
+        let up = up_axis.unwrap_or(Vec3::NEG_Y);
+        turntable_path(splats, up, config.render_duration).await
+    };
+    let instanced = match &config.render_instances {
+        Some(layout_file) => add_instances(splats.clone(), layout_file).await?,
+        None => None,
+    };
+    if let Some(instanced) = &instanced {
+        return render_frames(VideoSplats::Instanced(instanced), &path, config).await;
+    }
+    render_path(splats, &path, config).await
+}
+
+/// Render splats along a camera path, to PNG frames or an mp4 file.
+pub async fn render_path(
+    splats: &Splats<MainBackend>,
+    path: &CameraPath,
+    config: &RenderConfig,
+) -> anyhow::Result<()> {
+    render_frames(VideoSplats::Splats(splats), path, config).await
+}
+
+async fn render_frames(
+    splats: VideoSplats<'_>,
+    path: &CameraPath,
+    config: &RenderConfig,
+) -> anyhow::Result<()> {
+    // Both need the splats of each copy of a prop, which only exist in the renderer.
+    if matches!(splats, VideoSplats::Instanced(_))
+        && (config.render_fade_frames > 0 || config.render_motion_vectors)
+    {
+        bail!("Fade frames and motion vectors can't be rendered with instances");
+    }
+
+    let size = glam::uvec2(config.render_width, config.render_height);
+    let aspect_ratio = size.x as f32 / size.y as f32;
+    let fps = config.render_fps.max(1);
+    let frame_count = (path.duration() * fps as f32).floor() as u32 + 1;
+
+    log::info!(
+        "Rendering {frame_count} frames at {}x{} to {}",
+        size.x,
+        size.y,
+        config.render_out
+    );
+
+    let watermark = load_watermark(config)?;
+
+    let mut coherence = (config.render_fade_frames > 0)
+        .then(|| FrameCoherence::new(config.render_fade_frames, CULL_MARGIN));
+
+    let out = Path::new(&config.render_out);
+    let motion_dir = config.render_motion_vectors.then(|| motion_dir(out));
+    if let Some(dir) = &motion_dir {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let mut sink = FrameSink::new(out, size, fps)?;
+    for index in 0..frame_count {
+        let time = index as f32 / fps as f32;
+        let Some(sample) = path.sample(time, aspect_ratio) else {
+            bail!("Camera path has no keyframes");
+        };
+        let shutter = if config.render_shutter_angle > 0.0 {
+            path.sample_shutter(
+                time,
+                1.0 / fps as f32,
+                config.render_shutter_angle,
+                config.render_shutter_samples,
+                aspect_ratio,
+            )
+        } else {
+            vec![]
+        };
+        let img = match (&splats, &mut coherence) {
+            (VideoSplats::Splats(splats), Some(coherence)) => {
+                coherence.render_shutter(&sample, &shutter, splats, size)
+            }
+            (VideoSplats::Splats(splats), None) => render_shutter(&shutter, splats, size)
+                .unwrap_or_else(|| sample.render(splats, size)),
+            (VideoSplats::Instanced(splats), _) => render_shutter_instanced(&shutter, splats, size)
+                .unwrap_or_else(|| sample.render_instanced(splats, size)),
+        };
+        let mut frame = to_rgb8(img, size).await?;
+        if let Some(mark) = &watermark {
+            overlay_watermark(&mut frame, mark, config.render_watermark_opacity);
+        }
+        sink.write(index, &frame)?;
+
+        if let (Some(dir), VideoSplats::Splats(splats)) = (&motion_dir, &splats) {
+            let camera_at = |index: u32| {
+                path.sample(index as f32 / fps as f32, aspect_ratio)
+                    .map_or_else(|| sample.camera.clone(), |s| s.camera)
+            };
+            let prev = camera_at(index.saturating_sub(1));
+            let next = camera_at(index + 1);
+            let motion = sample
+                .render_motion(splats, size, &prev, &next)
+                .into_data_async()
+                .await
+                .into_vec::<f32>()
+                .expect("Wrong type");
+            let motion = image::Rgba32FImage::from_raw(size.x, size.y, motion)
+                .context("Rendered motion vectors have the wrong size")?;
+            motion
+                .save(dir.join(format!("motion_{index:05}.exr")))
+                .context("Failed to write motion vectors")?;
+        }
+    }
+    sink.finish()
+}
+
+/// Render the stills of the shot list in the config to its output directory.
+///
+/// Returns how many stills were written.
+pub async fn render_shot_list(
+    splats: &Splats<MainBackend>,
+    config: &RenderConfig,
+) -> anyhow::Result<usize> {
+    let Some(shots_file) = &config.render_shots else {
+        return Ok(0);
+    };
+    let data = std::fs::read_to_string(shots_file)
+        .with_context(|| format!("Failed to read shot list {shots_file}"))?;
+    let shots = serde_json::from_str::<ShotList>(&data)
+        .with_context(|| format!("Failed to parse shot list {shots_file}"))?;
+
+    let out = Path::new(&config.render_shots_out);
+    std::fs::create_dir_all(out)?;
+    log::info!(
+        "Rendering {} stills to {}",
+        shots.shots.len(),
+        out.display()
+    );
+    let watermark = load_watermark(config)?;
+    let watermark = watermark
+        .as_ref()
+        .map(|mark| (mark, config.render_watermark_opacity));
+    let stills = render_shots(splats, &shots, watermark).await?;
+    for (name, data) in &stills {
+        std::fs::write(out.join(name), data)
+            .with_context(|| format!("Failed to write still {name}"))?;
+    }
+    Ok(stills.len())
+}
@@ -0,0 +1,90 @@
+// Cuts the splats that mean something out of a scene trained with feature maps, by comparing the
+// features exported next to the splats to a query feature, eg. the CLIP embedding of a prompt.
+use std::{io::Cursor, path::PathBuf};
+
+use anyhow::Context;
+use brush_dataset::{
+    feature_map::read_npy,
+    splat_export::splat_to_ply,
+    splat_formats::{self, SplatFormat},
+};
+use brush_render::MainBackend;
+use brush_train::features::segment_by_features;
+use brush_vfs::DataSource;
+use burn::tensor::{Tensor, TensorData};
+use burn_wgpu::WgpuDevice;
+use clap::Args;
+use tokio::io::AsyncReadExt;
+use tokio_stream::StreamExt;
+
+#[derive(Args, Clone, Debug)]
+pub struct SegmentArgs {
+    /// Splat file trained with feature maps (path or URL).
+    #[arg(value_name = "PATH_OR_URL")]
+    pub source: DataSource,
+    /// The .features.npy file of the splats, exported next to them.
+    #[arg(long)]
+    pub features: PathBuf,
+    /// A .npy file with the [C] feature to look for.
+    #[arg(long)]
+    pub query: PathBuf,
+    /// Lowest cosine similarity to the query of the splats to keep.
+    #[arg(long, default_value = "0.8")]
+    pub threshold: f32,
+    /// Where to write the splats that match.
+    #[arg(long, short, default_value = "segment.ply")]
+    pub out: PathBuf,
+}
+
+/// Write the splats of `args` with features like the query, and return how many there are.
+pub async fn run_segment(args: &SegmentArgs, device: &WgpuDevice) -> anyhow::Result<u32> {
+    let vfs = args.source.clone().into_vfs().await?;
+    let (path, format) = vfs
+        .file_paths()
+        .find_map(|path| {
+            let format = SplatFormat::from_path(&path)?;
+            Some((path, format))
+        })
+        .context("No splat file to segment")?;
+    let mut data = vec![];
+    vfs.reader_at_path(&path)
+        .await?
+        .read_to_end(&mut data)
+        .await?;
+    let stream = splat_formats::load_splats(format, Cursor::new(data), None, device.clone());
+    let mut stream = std::pin::pin!(stream);
+    let mut splats = None;
+    while let Some(message) = stream.next().await {
+        splats = Some(message?.splats);
+    }
+    let splats = splats.with_context(|| format!("No splats in {}", path.display()))?;
+
+    let (shape, features) = read_npy(&tokio::fs::read(&args.features).await?)?;
+    let [num_splats, channels] = shape[..] else {
+        anyhow::bail!("Features of splats are [N, C] arrays, got shape {shape:?}");
+    };
+    anyhow::ensure!(
+        num_splats == splats.num_splats() as usize,
+        "{} has features of {num_splats} splats, but {} has {}",
+        args.features.display(),
+        path.display(),
+        splats.num_splats()
+    );
+    let (query_shape, query) = read_npy(&tokio::fs::read(&args.query).await?)?;
+    anyhow::ensure!(
+        query_shape == [channels],
+        "The query has shape {query_shape:?}, but the splats have {channels} features"
+    );
+
+    let features = TensorData::new(features, [num_splats, channels]);
+    let features = Tensor::<MainBackend, 2>::from_data(features, device);
+    let query = Tensor::<MainBackend, 1>::from_floats(query.as_slice(), device);
+    let keep = segment_by_features(features, query, args.threshold);
+    let segment = splats
+        .retain(keep)
+        .await
+        .context("No splats are like the query")?;
+    let count = segment.num_splats();
+    rrfd::write_atomic(&args.out, &splat_to_ply(segment).await?).await?;
+    Ok(count)
+}
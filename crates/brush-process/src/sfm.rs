@@ -0,0 +1,80 @@
+// Reconstructing the cameras of a directory of photos, so it can be trained without running a
+// structure from motion tool by hand first.
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use brush_sfm::{SfmOptions, export_features, reconstruct};
+use brush_vfs::BrushVfs;
+
+use crate::config::SfmConfig;
+
+// Whether the files have cameras in any of the formats Brush loads.
+fn has_cameras(vfs: &BrushVfs) -> bool {
+    vfs.files_ending_in("cameras.bin").next().is_some()
+        || vfs.files_ending_in("cameras.txt").next().is_some()
+        || vfs.file_paths().any(|path| {
+            let name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default();
+            name.starts_with("transforms") && name.ends_with(".json")
+        })
+}
+
+// The reconstruction goes next to the photos rather than in between them, so it isn't mistaken
+// for part of the dataset when loading the photos again.
+fn workspace_dir(photos: &Path) -> PathBuf {
+    let name = photos
+        .file_name()
+        .map_or_else(|| "photos".into(), |n| n.to_string_lossy());
+    photos.with_file_name(format!("{name}_sfm"))
+}
+
+/// Swap a directory of photos without cameras for a reconstruction of them, if structure from
+/// motion is enabled. Anything else is left as is.
+pub(crate) async fn with_reconstruction(
+    vfs: Arc<BrushVfs>,
+    config: &SfmConfig,
+) -> anyhow::Result<Arc<BrushVfs>> {
+    let Some(engine) = config.sfm else {
+        return Ok(vfs);
+    };
+    if has_cameras(&vfs) {
+        if config.sfm_export.is_some() {
+            log::warn!("Features can only be exported for cameras reconstructed by Brush.");
+        }
+        return Ok(vfs);
+    }
+    let Some(photos) = vfs.base_path() else {
+        log::warn!("Cameras can only be reconstructed for a directory of photos.");
+        return Ok(vfs);
+    };
+
+    let photos = photos.canonicalize()?;
+    let options = SfmOptions {
+        engine,
+        matcher: config.sfm_matcher,
+        single_camera: config.sfm_single_camera,
+        colmap_binary: config.colmap_binary.as_ref().map(PathBuf::from),
+        glomap_binary: config.glomap_binary.as_ref().map(PathBuf::from),
+        command: config.sfm_command.clone(),
+    };
+    log::info!(
+        "Reconstructing cameras of {} with {engine:?}",
+        photos.display()
+    );
+    let workspace = workspace_dir(&photos);
+    let output = reconstruct(&photos, &workspace, &options)
+        .await
+        .context("Failed to reconstruct the cameras of the photos")?;
+    if let Some(export) = &config.sfm_export {
+        export_features(&workspace, Path::new(export), &options)
+            .await
+            .context("Failed to export the features of the reconstruction")?;
+        log::info!("Exported features and sparse model to {export}");
+    }
+    Ok(Arc::new(BrushVfs::from_path(&output).await?))
+}
@@ -0,0 +1,73 @@
+// Stills of a scene from a list of saved cameras, see [`brush_render::shots`].
+use std::io::{Cursor, Write};
+
+use anyhow::Context;
+use brush_dataset::watermark::overlay_watermark;
+use brush_render::{
+    MainBackend,
+    gaussian_splats::Splats,
+    shots::{Shot, ShotList},
+};
+use burn::tensor::{Tensor, s};
+use image::RgbaImage;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+// A [H, W, 4] render as an 8 bit image. The background is black, so the premultiplied colors
+// can be used as is.
+pub(crate) async fn to_rgb8(
+    img: Tensor<MainBackend, 3>,
+    size: glam::UVec2,
+) -> anyhow::Result<image::RgbImage> {
+    let rgb = img
+        .slice(s![.., .., 0..3])
+        .into_data_async()
+        .await
+        .into_vec::<f32>()
+        .expect("Wrong type")
+        .into_iter()
+        .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+        .collect();
+    image::RgbImage::from_raw(size.x, size.y, rgb).context("Rendered frame has the wrong size")
+}
+
+/// Render the still of a shot.
+pub async fn render_shot(
+    splats: &Splats<MainBackend>,
+    shot: &Shot,
+) -> anyhow::Result<image::RgbImage> {
+    let size = shot.size();
+    to_rgb8(shot.sample().render(splats, size), size).await
+}
+
+/// Render the stills of all shots, as PNG files named after the shots.
+///
+/// A watermark, with its opacity, is blended onto each still.
+pub async fn render_shots(
+    splats: &Splats<MainBackend>,
+    shots: &ShotList,
+    watermark: Option<(&RgbaImage, f32)>,
+) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let mut stills = vec![];
+    for (shot, name) in shots.shots.iter().zip(shots.file_names("png")) {
+        let mut still = render_shot(splats, shot).await?;
+        if let Some((mark, opacity)) = watermark {
+            overlay_watermark(&mut still, mark, opacity);
+        }
+        let mut data = Cursor::new(vec![]);
+        still.write_to(&mut data, image::ImageFormat::Png)?;
+        stills.push((name, data.into_inner()));
+    }
+    Ok(stills)
+}
+
+/// Pack rendered stills into a zip, to save them in one go.
+pub fn zip_stills(stills: &[(String, Vec<u8>)]) -> anyhow::Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    // Pngs are compressed already.
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    for (name, data) in stills {
+        zip.start_file(name.as_str(), stored)?;
+        zip.write_all(data)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
@@ -1,32 +1,72 @@
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use crate::{
-    config::ProcessArgs, eval_export::eval_save_to_disk, message::ProcessMessage,
+    chunks::ChunkGrid,
+    config::ProcessArgs,
+    control::{TrainCommand, TrainCommands},
+    eval_export::eval_save_to_disk,
+    message::{MAX_REPLAY_SNAPSHOTS, ProcessMessage},
+    planner::plan_run,
     visualize_tools::VisualizeTools,
 };
 use anyhow::Context;
 use async_fn_stream::TryStreamEmitter;
-use brush_dataset::scene_loader::SceneLoader;
+use brush_dataset::{
+    Dataset,
+    chunked_splats::{CHUNK_SPLATS, splat_to_chunked},
+    compression::compress_splats,
+    feature_map::write_npy,
+    gltf_export::splat_to_glb,
+    redact::scrub_splats,
+    scene::Scene,
+    scene_loader::{SceneLoader, ViewSampling},
+    splat_export::{splat_sequence_to_ply, splat_to_ply_with_watermark, splat_to_progressive_ply},
+    tonemap::Tonemap,
+    usd_export::UsdStage,
+    watermark::Watermark,
+    white_balance::{cluster_wb_groups, view_chromaticity},
+};
 use brush_render::{
     MainBackend,
     gaussian_splats::{RandomSplatsConfig, Splats},
+    motion::SplatMotion,
+};
+use brush_train::{
+    config::TrainConfig,
+    eval::eval_stats,
+    simplify::{contribution, simplify},
+    train::SplatTrainer,
+    view_sampler::{ViewSampler, view_sampler},
 };
-use brush_train::{eval::eval_stats, train::SplatTrainer};
-use brush_vfs::BrushVfs;
-use burn::{module::AutodiffModule, prelude::Backend};
+use brush_vfs::{BrushVfs, MemoryFile};
+use burn::{module::AutodiffModule, prelude::Backend, tensor::Tensor};
 use burn_cubecl::cubecl::Runtime;
 use burn_wgpu::{WgpuDevice, WgpuRuntime};
-use rand::SeedableRng;
+use glam::Vec3;
+use rand::{SeedableRng, rngs::StdRng};
 use tokio::sync::oneshot::Receiver;
 use tokio_stream::StreamExt;
 use web_time::{Duration, Instant};
 
+// Steps between reading back the losses for the view sampler. Reading them every step would stall
+// training on the GPU.
+const LOSS_READ_EVERY: usize = 20;
+
+/// Splats of an earlier run to continue training from.
+pub(crate) struct Checkpoint {
+    pub(crate) iter: u32,
+    /// The splats, in the frame of the dataset as it's stored.
+    pub(crate) splats: Splats<MainBackend>,
+}
+
 pub(crate) async fn train_stream(
     vfs: Arc<BrushVfs>,
     process_args: Receiver<ProcessArgs>,
+    mut commands: TrainCommands,
     device: WgpuDevice,
     emitter: TryStreamEmitter<ProcessMessage, anyhow::Error>,
+    checkpoint: Option<Checkpoint>,
 ) -> anyhow::Result<()> {
     log::info!("Start of training stream");
 
@@ -35,15 +75,35 @@ pub(crate) async fn train_stream(
         .await;
 
     // Now wait for the process args.
-    let process_args = process_args.await?;
+    let mut process_args = process_args.await?;
+
+    // A browser tab only gets a few GB of memory.
+    #[cfg(target_family = "wasm")]
+    if process_args.limit_for_web() {
+        log::warn!(
+            "Training at most {} splats on images of at most {}px, to fit in the browser",
+            process_args.train_config.max_splats,
+            process_args.load_config.max_resolution
+        );
+    }
+    if let Some(checkpoint) = &checkpoint {
+        log::info!("Continuing training from step {}", checkpoint.iter);
+        process_args.process_config.start_iter = checkpoint.iter;
+    }
 
     log::info!("Create rerun {}", process_args.rerun_config.rerun_enabled);
     let visualize = VisualizeTools::new(process_args.rerun_config.rerun_enabled);
 
     let process_config = &process_args.process_config;
     log::info!("Using seed {}", process_config.seed);
+    if process_config.deterministic {
+        log::info!("Training deterministically, images are loaded one at a time.");
+    }
     <MainBackend as Backend>::seed(process_config.seed);
-    let mut rng = rand::rngs::StdRng::from_seed([process_config.seed as u8; 32]);
+    let mut rng = StdRng::from_seed([process_config.seed as u8; 32]);
+
+    #[cfg(not(target_family = "wasm"))]
+    let vfs = crate::sfm::with_reconstruction(vfs, &process_args.sfm_config).await?;
 
     log::info!("Loading dataset");
     let (mut splat_stream, dataset) =
@@ -71,127 +131,292 @@ pub(crate) async fn train_stream(
             splats: Box::new(message.splats.clone()),
             frame: 0,
             total_frames: 0,
+            labels: None,
         };
         emitter.emit(msg).await;
         initial_splats = Some(message.splats);
     }
+    if let Some(checkpoint) = checkpoint {
+        initial_splats = Some(dataset.transform.transform_splats(checkpoint.splats));
+    }
 
     emitter.emit(ProcessMessage::DoneLoading).await;
 
-    let splats = if let Some(splats) = initial_splats {
-        splats
+    if process_config.train_chunks > 1 {
+        train_chunked(
+            &process_args,
+            &dataset,
+            initial_splats,
+            estimated_up,
+            &mut commands,
+            &device,
+            &emitter,
+            &visualize,
+            &mut rng,
+        )
+        .await?;
     } else {
-        log::info!("Starting with random splat config.");
+        let splats = init_splats(
+            &process_args,
+            &dataset.train,
+            initial_splats,
+            &mut rng,
+            &device,
+        );
+        train_loop(
+            &process_args,
+            &dataset,
+            splats,
+            true,
+            &mut commands,
+            &device,
+            &emitter,
+            &visualize,
+        )
+        .await?;
+    }
 
-        // By default, spawn the splats in bounds.
-        let bounds = dataset.train.bounds();
+    Ok(())
+}
+
+/// Initial splats to train a scene from: the splats loaded with the dataset, or random ones.
+fn init_splats(
+    process_args: &ProcessArgs,
+    scene: &Scene,
+    initial_splats: Option<Splats<MainBackend>>,
+    rng: &mut StdRng,
+    device: &WgpuDevice,
+) -> Splats<MainBackend> {
+    if let Some(splats) = initial_splats {
+        splats
+    } else {
+        let model_config = &process_args.model_config;
+        let bounds = scene.bounds();
         let bounds_extent = bounds.extent.length();
-        // Arbitrarily assume area of interest is 0.2 - 0.75 of scene bounds.
-        // Somewhat specific to the blender scenes
-        let adjusted_bounds = dataset
-            .train
-            .adjusted_bounds(bounds_extent * 0.25, bounds_extent);
-        let config = RandomSplatsConfig::new();
-
-        Splats::from_random_config(&config, adjusted_bounds, &mut rng, &device)
-    };
+
+        if model_config.random_init_frustum {
+            log::info!("Starting with random splats in the camera frustums.");
+
+            // Only keep points seen by a few views, this rejects points
+            // in front of only a single camera.
+            const MIN_VIEWS: usize = 3;
+            let points = scene.sample_points_in_frustums(
+                model_config.random_init_count,
+                bounds_extent * 0.05,
+                bounds_extent * 2.0,
+                MIN_VIEWS,
+                rng,
+            );
+            Splats::from_random_points(&points, rng, device)
+        } else {
+            log::info!("Starting with random splat config.");
+
+            // By default, spawn the splats in bounds.
+            // Arbitrarily assume area of interest is 0.2 - 0.75 of scene bounds.
+            // Somewhat specific to the blender scenes
+            let adjusted_bounds = scene.adjusted_bounds(bounds_extent * 0.25, bounds_extent);
+            let config = RandomSplatsConfig::new().with_init_count(model_config.random_init_count);
+
+            Splats::from_random_config(&config, adjusted_bounds, rng, device)
+        }
+    }
+}
+
+// Nr. of times images are halved at the start of coarse-to-fine training.
+const COARSE_LEVELS: u32 = 2;
+
+/// How many times to halve the resolution of the images trained on at step `iter`.
+fn resolution_level(train_config: &TrainConfig, iter: u32) -> u32 {
+    let steps = train_config.coarse_to_fine_steps;
+    if iter >= steps {
+        0
+    } else {
+        COARSE_LEVELS - (u64::from(iter) * u64::from(COARSE_LEVELS) / u64::from(steps)) as u32
+    }
+}
+
+/// Train on a dataset, starting from `splats`. Returns the trained splats.
+///
+/// With `export`, the splats are exported every so often as configured. Otherwise they are only returned.
+#[allow(clippy::too_many_arguments)]
+async fn train_loop(
+    process_args: &ProcessArgs,
+    dataset: &Dataset,
+    splats: Splats<MainBackend>,
+    export: bool,
+    commands: &mut TrainCommands,
+    device: &WgpuDevice,
+    emitter: &TryStreamEmitter<ProcessMessage, anyhow::Error>,
+    visualize: &VisualizeTools,
+) -> anyhow::Result<Splats<MainBackend>> {
+    let process_config = &process_args.process_config;
+    let device = device.clone();
 
     let splats = splats.with_sh_degree(process_args.model_config.sh_degree);
     let mut splats = splats.into_autodiff();
 
-    let mut eval_scene = dataset.eval;
+    // Fail now rather than at the first export or eval that doesn't fit.
+    #[cfg(not(target_family = "wasm"))]
+    if export || process_config.eval_save_to_disk {
+        use crate::planner::{check_output_dir, estimate_disk_usage};
+
+        let needed = estimate_disk_usage(process_args, dataset, splats.num_splats(), export);
+        check_output_dir(Path::new(&process_config.export_path), needed).await?;
+    }
+
+    log::info!("Estimating run cost");
+    let plan = plan_run(process_args, dataset, splats.num_splats(), &device).await;
+    log::info!(
+        "Estimated {:?} training, {} MB peak GPU memory",
+        plan.duration,
+        plan.peak_memory / (1024 * 1024)
+    );
+    if plan.exceeds_budget() {
+        log::warn!("Run is likely to need more GPU memory than available.");
+    }
+    emitter.emit(ProcessMessage::RunPlan { plan }).await;
+
     let scene_extent = dataset.train.estimate_extent().unwrap_or(1.0);
 
     let mut train_duration = Duration::from_secs(0);
-    let mut dataloader = SceneLoader::new(&dataset.train, 42, &device);
-    let mut trainer = SplatTrainer::new(&process_args.train_config, &device);
+    // Snapshots for the replay are recorded less often the longer the run goes, so there are never
+    // more than a few hundred of them alive at once.
+    let mut snapshot_every = process_config
+        .replay_snapshot_every
+        .map(|every| every.max(1));
+    let mut snapshot_count = 0;
+    // Which views loss aware sampling picks depends on when losses are read back, which varies
+    // between runs.
+    let sampling = if process_config.deterministic {
+        ViewSampling::Uniform
+    } else {
+        process_args.train_config.view_sampling
+    };
+    let sampler = Arc::new(Mutex::new(view_sampler(
+        sampling,
+        dataset.train.views.len(),
+        process_config.seed,
+    )));
+    let mut pending_losses = vec![];
+    let mut dataloader = SceneLoader::new(
+        &dataset.train,
+        process_config.seed,
+        process_config.deterministic,
+        process_config.start_views,
+        process_args.train_config.patch_size,
+        {
+            let sampler = sampler.clone();
+            move |views: &[usize]| {
+                sampler
+                    .lock()
+                    .expect("View sampler panicked")
+                    .next_view(views)
+            }
+        },
+        &device,
+    );
+    let mut level = resolution_level(&process_args.train_config, process_config.start_iter);
+    dataloader.set_resolution_level(level);
+    if level > 0 {
+        log::info!("Starting training at 1/{} resolution", 1 << level);
+    }
+    let mut trainer = SplatTrainer::new(&process_args.train_config, &device)
+        .with_seed(process_config.seed)
+        .with_deterministic(process_config.deterministic);
+
+    if process_args.train_config.wb_correction {
+        log::info!("Detecting white balance groups");
+        let mut chromaticities = vec![];
+        for view in dataset.train.views.iter() {
+            chromaticities.push(view_chromaticity(view).await?);
+        }
+        let (groups, num_groups) = cluster_wb_groups(
+            &chromaticities,
+            process_args.train_config.wb_group_threshold,
+        );
+        log::info!("Found {num_groups} white balance groups");
+        trainer = trainer.with_wb_groups(groups, num_groups, &device);
+    }
+    if let Some(tonemap) = dataset.tonemap {
+        trainer = trainer.with_tonemap(tonemap);
+    }
 
     log::info!("Start training loop.");
     for iter in process_args.process_config.start_iter..process_args.train_config.total_steps {
         let step_time = Instant::now();
 
-        let batch = dataloader.next_batch().await;
-        let (new_splats, stats) = trainer.step(scene_extent, iter, &batch, splats);
+        let mut eval_requested = false;
+        while let Ok(command) = commands.try_recv() {
+            match command {
+                TrainCommand::SetLrScale { scale } => trainer.set_lr_scale(scale),
+                TrainCommand::Eval => eval_requested = true,
+            }
+        }
+
+        let iter_level = resolution_level(&process_args.train_config, iter);
+        if iter_level != level {
+            if iter_level == 0 {
+                log::info!("Training at full resolution");
+            } else {
+                log::info!("Training at 1/{} resolution", 1 << iter_level);
+            }
+            dataloader.set_resolution_level(iter_level);
+            level = iter_level;
+        }
+
+        let mut batches = vec![];
+        for _ in 0..process_args.train_config.batch_size.max(1) {
+            batches.push(dataloader.next_batch().await);
+        }
+        let (new_splats, stats) = trainer.step(scene_extent, iter, &batches, splats).await;
         splats = new_splats;
+        if sampling == ViewSampling::Loss {
+            let views: Vec<_> = batches.iter().map(|batch| batch.view_index).collect();
+            pending_losses.push((views, stats.loss.clone()));
+            if pending_losses.len() >= LOSS_READ_EVERY {
+                record_losses(&sampler, std::mem::take(&mut pending_losses)).await;
+            }
+        }
         let (new_splats, refine) = trainer.refine_if_needed(iter, splats).await;
         splats = new_splats;
 
-        #[allow(unused)]
-        let export_path = Path::new(&process_config.export_path).to_owned();
-
         // We just finished iter 'iter', now starting iter + 1.
         let iter = iter + 1;
         let is_last_step = iter == process_args.train_config.total_steps;
 
         // Check if we want to evaluate _next iteration_. Small detail, but this ensures we evaluate
         // before doing a refine.
-        if iter % process_config.eval_every == 0 || is_last_step {
-            if let Some(eval_scene) = eval_scene.as_mut() {
-                let mut psnr = 0.0;
-                let mut ssim = 0.0;
-                let mut count = 0;
-
-                log::info!("Running evaluation for iteration {iter}");
-
-                for (i, view) in eval_scene.views.iter().enumerate() {
-                    let sample = eval_stats(splats.valid(), view, &device)
-                        .await
-                        .context("Failed to run eval for sample.")?;
-
-                    count += 1;
-                    psnr += sample.psnr.clone().into_scalar_async().await;
-                    ssim += sample.ssim.clone().into_scalar_async().await;
-
-                    if process_args.process_config.eval_save_to_disk {
-                        let img_name = Path::new(&view.image.path)
-                            .file_stem()
-                            .expect("No file name for eval view.")
-                            .to_string_lossy();
-                        let path = Path::new(&export_path)
-                            .join(format!("eval_{iter}"))
-                            .join(format!("{img_name}.png"));
-                        eval_save_to_disk(&sample, &path).await?;
-                    }
-
-                    visualize.log_eval_sample(iter, i as u32, sample).await?;
-                }
-
-                psnr /= count as f32;
-                ssim /= count as f32;
-
-                visualize.log_eval_stats(iter, psnr, ssim)?;
-
-                let message = ProcessMessage::EvalResult {
+        if iter % process_config.eval_every == 0 || is_last_step || eval_requested {
+            if let Some(eval_scene) = &dataset.eval {
+                run_eval(
+                    process_args,
+                    eval_scene,
+                    dataset.tonemap,
+                    &splats.valid(),
+                    Some(&trainer),
                     iter,
-                    avg_psnr: psnr,
-                    avg_ssim: ssim,
-                };
-
-                emitter.emit(message).await;
+                    &device,
+                    emitter,
+                    visualize,
+                )
+                .await?;
             }
         }
 
         let client = WgpuRuntime::client(&device);
         visualize.log_memory(iter, &client.memory_usage())?;
 
-        // TODO: Support this on WASM somehow. Maybe have user pick a file once,
-        // and write to it repeatedly?
-        #[cfg(not(target_family = "wasm"))]
-        if iter % process_config.export_every == 0 || is_last_step {
-            let total_steps = process_args.train_config.total_steps;
-
-            // Ad-hoc format string.
-            let digits = (total_steps as f64).log10().ceil() as usize;
-            let export_name = process_config
-                .export_name
-                .replace("{iter}", &format!("{iter:0digits$}"));
-
-            tokio::fs::create_dir_all(&export_path).await?;
-
-            let splat_data = brush_dataset::splat_export::splat_to_ply(splats.valid()).await?;
-            tokio::fs::write(export_path.join(&export_name), splat_data)
-                .await
-                .with_context(|| format!("Failed to export ply {export_path:?}"))?;
+        if export && (iter % process_config.export_every == 0 || is_last_step) {
+            export_splats(
+                process_args,
+                dataset,
+                splats.valid(),
+                trainer.features(),
+                trainer.motion(),
+                iter,
+                emitter,
+            )
+            .await?;
         }
 
         if let Some(every) = process_args.rerun_config.rerun_log_splats_every {
@@ -222,11 +447,28 @@ pub(crate) async fn train_stream(
                 .await;
         }
 
+        if let Some(every) = snapshot_every.as_mut() {
+            if iter % *every == 0 || is_last_step {
+                snapshot_count += 1;
+                if snapshot_count >= MAX_REPLAY_SNAPSHOTS {
+                    snapshot_count /= 2;
+                    *every *= 2;
+                }
+                emitter
+                    .emit(ProcessMessage::Snapshot {
+                        splats: Box::new(splats.valid().with_sh_degree(0)),
+                        iter,
+                    })
+                    .await;
+            }
+        }
+
         // How frequently to update the UI after a training step.
         const UPDATE_EVERY: u32 = 5;
         if iter % UPDATE_EVERY == 0 || is_last_step {
             let message = ProcessMessage::TrainStep {
                 splats: Box::new(splats.valid()),
+                motion: trainer.motion().map(Box::new),
                 stats: Box::new(stats),
                 iter,
                 total_elapsed: train_duration,
@@ -235,5 +477,427 @@ pub(crate) async fn train_stream(
         }
     }
 
+    Ok(splats.valid())
+}
+
+/// Train the scene as a grid of overlapping chunks, one after another, and merge the results.
+///
+/// Only one chunk is trained at a time, so this needs a lot less memory than training the whole
+/// scene at once. The merged splats still need to fit in memory.
+#[allow(clippy::too_many_arguments)]
+async fn train_chunked(
+    process_args: &ProcessArgs,
+    dataset: &Dataset,
+    initial_splats: Option<Splats<MainBackend>>,
+    up: Vec3,
+    commands: &mut TrainCommands,
+    device: &WgpuDevice,
+    emitter: &TryStreamEmitter<ProcessMessage, anyhow::Error>,
+    visualize: &VisualizeTools,
+    rng: &mut StdRng,
+) -> anyhow::Result<()> {
+    let process_config = &process_args.process_config;
+    let positions: Vec<Vec3> = dataset
+        .train
+        .views
+        .iter()
+        .map(|v| v.camera.position)
+        .collect();
+    let grid = ChunkGrid::new(
+        &positions,
+        up,
+        process_config.train_chunks,
+        process_config.chunk_overlap,
+    );
+
+    let num_chunks = grid.chunks.len();
+    let mut merged: Option<Splats<MainBackend>> = None;
+
+    for (i, chunk) in grid.chunks.iter().enumerate() {
+        let Some(chunk_scene) = grid.chunk_scene(chunk, &dataset.train) else {
+            log::info!(
+                "Skipping chunk {} of {num_chunks}, it has no cameras",
+                i + 1
+            );
+            continue;
+        };
+        log::info!(
+            "Training chunk {} of {num_chunks} with {} views",
+            i + 1,
+            chunk_scene.views.len()
+        );
+
+        let chunk_initial = if let Some(splats) = &initial_splats {
+            splats.clone().retain(grid.mask(splats, chunk.region)).await
+        } else {
+            None
+        };
+        let splats = init_splats(process_args, &chunk_scene, chunk_initial, rng, device);
+
+        let chunk_dataset = Dataset {
+            train: chunk_scene,
+            eval: None,
+            transform: dataset.transform,
+            residuals: vec![],
+            unsmoothed_train: None,
+            tonemap: dataset.tonemap,
+        };
+        let trained = train_loop(
+            process_args,
+            &chunk_dataset,
+            splats,
+            false,
+            commands,
+            device,
+            emitter,
+            visualize,
+        )
+        .await?;
+
+        // Near the seams both neighbouring chunks trained the splats, so blend between them to
+        // keep the density even.
+        let keep = grid.merge_mask(&trained, chunk, rng).await;
+        let Some(trained) = trained.retain(keep).await else {
+            continue;
+        };
+        merged = Some(match merged {
+            Some(merged) => merged.merge(trained),
+            None => trained,
+        });
+    }
+
+    let splats = merged.context("No chunk of the scene had any splats after training")?;
+    let iter = process_args.train_config.total_steps;
+    log::info!("Merged chunks into {} splats", splats.num_splats());
+
+    emitter
+        .emit(ProcessMessage::ViewSplats {
+            up_axis: Some(up),
+            splats: Box::new(splats.clone()),
+            frame: 0,
+            total_frames: 0,
+            labels: None,
+        })
+        .await;
+
+    if let Some(eval_scene) = &dataset.eval {
+        run_eval(
+            process_args,
+            eval_scene,
+            dataset.tonemap,
+            &splats,
+            None,
+            iter,
+            device,
+            emitter,
+            visualize,
+        )
+        .await?;
+    }
+
+    // Features of chunks aren't merged, so aren't exported here.
+    export_splats(process_args, dataset, splats, None, None, iter, emitter).await?;
+
+    Ok(())
+}
+
+// Tell the sampler the loss of the steps trained since the last read.
+async fn record_losses(
+    sampler: &Mutex<Box<dyn ViewSampler>>,
+    pending: Vec<(Vec<usize>, Tensor<MainBackend, 1>)>,
+) {
+    let (views, losses): (Vec<_>, Vec<_>) = pending.into_iter().unzip();
+    let losses = Tensor::cat(losses, 0)
+        .into_data_async()
+        .await
+        .into_vec::<f32>()
+        .expect("Wrong type");
+    let mut sampler = sampler.lock().expect("View sampler panicked");
+    for (views, loss) in views.iter().zip(losses) {
+        // The loss of a batch is shared by all of its views.
+        for &view in views {
+            sampler.record_loss(view, loss);
+        }
+    }
+}
+
+async fn run_eval(
+    process_args: &ProcessArgs,
+    eval_scene: &Scene,
+    tonemap: Option<Tonemap>,
+    splats: &Splats<MainBackend>,
+    // Moves the splats to the time of each view, for dynamic scenes.
+    trainer: Option<&SplatTrainer>,
+    iter: u32,
+    device: &WgpuDevice,
+    emitter: &TryStreamEmitter<ProcessMessage, anyhow::Error>,
+    visualize: &VisualizeTools,
+) -> anyhow::Result<()> {
+    let export_path = Path::new(&process_args.process_config.export_path);
+    let mut psnr = 0.0;
+    let mut ssim = 0.0;
+    let mut count = 0;
+
+    log::info!("Running evaluation for iteration {iter}");
+
+    for (i, view) in eval_scene.views.iter().enumerate() {
+        let splats = match (trainer, view.image.time) {
+            (Some(trainer), Some(time)) => trainer.splats_at_time(splats.clone(), time),
+            _ => splats.clone(),
+        };
+        let sample = eval_stats(splats, view, tonemap, device)
+            .await
+            .context("Failed to run eval for sample.")?;
+
+        count += 1;
+        psnr += sample.psnr.clone().into_scalar_async().await;
+        ssim += sample.ssim.clone().into_scalar_async().await;
+
+        if process_args.process_config.eval_save_to_disk {
+            let img_name = Path::new(&view.image.path)
+                .file_stem()
+                .expect("No file name for eval view.")
+                .to_string_lossy();
+            let path = export_path
+                .join(format!("eval_{iter}"))
+                .join(format!("{img_name}.png"));
+            eval_save_to_disk(&sample, &path).await?;
+        }
+
+        visualize.log_eval_sample(iter, i as u32, sample).await?;
+    }
+
+    psnr /= count as f32;
+    ssim /= count as f32;
+
+    visualize.log_eval_stats(iter, psnr, ssim)?;
+
+    let message = ProcessMessage::EvalResult {
+        iter,
+        avg_psnr: psnr,
+        avg_ssim: ssim,
+    };
+
+    emitter.emit(message).await;
+    Ok(())
+}
+
+async fn export_splats(
+    process_args: &ProcessArgs,
+    dataset: &Dataset,
+    splats: Splats<MainBackend>,
+    features: Option<Tensor<MainBackend, 2>>,
+    motion: Option<SplatMotion<MainBackend>>,
+    iter: u32,
+    emitter: &TryStreamEmitter<ProcessMessage, anyhow::Error>,
+) -> anyhow::Result<()> {
+    // Runs in the browser keep a checkpoint with their dataset, to continue after a reload.
+    #[cfg(target_family = "wasm")]
+    let checkpoint = brush_dataset::splat_export::splat_to_ply(
+        dataset.transform.inverse().transform_splats(splats.clone()),
+    )
+    .await?;
+
+    let (splats, features) = if iter == process_args.train_config.total_steps {
+        let splats = if process_args.load_config.blur_scrub {
+            scrub_splats(splats, &dataset.train.views).await
+        } else {
+            splats
+        };
+        simplify_final(process_args, dataset, splats, features, motion.is_some())
+    } else {
+        (splats, features)
+    };
+    let files = export_files(process_args, dataset, splats, features, motion, iter).await?;
+
+    #[cfg(not(target_family = "wasm"))]
+    {
+        let _ = emitter;
+        let export_path = Path::new(&process_args.process_config.export_path);
+        tokio::fs::create_dir_all(export_path).await?;
+        for file in files {
+            rrfd::write_atomic(&export_path.join(&file.name), &file.data)
+                .await
+                .with_context(|| format!("Failed to export {} to {export_path:?}", file.name))?;
+        }
+    }
+
+    // There's no disk to write to on the web, so the viewer keeps the files to download.
+    #[cfg(target_family = "wasm")]
+    {
+        emitter.emit(ProcessMessage::Exported { iter, files }).await;
+        let saved = match brush_vfs::web_store::WebStore::open().await {
+            Ok(store) => store.save_checkpoint(iter, &checkpoint).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = saved {
+            log::warn!("Failed to keep a checkpoint in the browser: {e}");
+        }
+    }
+
     Ok(())
 }
+
+// Remove the splats adding least to the training views from the final export, down to the budget
+// set in the config.
+fn simplify_final(
+    process_args: &ProcessArgs,
+    dataset: &Dataset,
+    splats: Splats<MainBackend>,
+    features: Option<Tensor<MainBackend, 2>>,
+    has_motion: bool,
+) -> (Splats<MainBackend>, Option<Tensor<MainBackend, 2>>) {
+    let budget = process_args
+        .process_config
+        .simplify_budget(splats.sh_degree());
+    let Some(target) = budget.filter(|&target| splats.num_splats() > target) else {
+        return (splats, features);
+    };
+    if has_motion {
+        log::warn!("Dynamic scenes can't be simplified yet, exporting all splats.");
+        return (splats, features);
+    }
+    let before = splats.num_splats();
+    let (splats, kept) = simplify(splats, &dataset.train.views, target);
+    log::info!("Simplified {before} splats to {}", splats.num_splats());
+    (splats, features.map(|features| features.select(0, kept)))
+}
+
+/// The files of an export: the splats, and their features and frames if any.
+async fn export_files(
+    process_args: &ProcessArgs,
+    dataset: &Dataset,
+    splats: Splats<MainBackend>,
+    features: Option<Tensor<MainBackend, 2>>,
+    motion: Option<SplatMotion<MainBackend>>,
+    iter: u32,
+) -> anyhow::Result<Vec<MemoryFile>> {
+    let process_config = &process_args.process_config;
+    let total_steps = process_args.train_config.total_steps;
+
+    // Ad-hoc format string.
+    let digits = (total_steps as f64).log10().ceil() as usize;
+    let export_name = process_config
+        .export_name
+        .replace("{iter}", &format!("{iter:0digits$}"));
+
+    // Export in the original frame of the dataset, with the sRGB colors viewers expect.
+    let to_export = |splats: Splats<MainBackend>| {
+        let splats = dataset.transform.inverse().transform_splats(splats);
+        match dataset.tonemap {
+            Some(tonemap) => tonemap.apply_splats(splats),
+            None => splats,
+        }
+    };
+    // Dynamic scenes also get a ply with a frame for each keyframe of the motion.
+    let frames = motion.map(|motion| {
+        let frames: Vec<_> = (0..motion.num_keyframes())
+            .map(|k| to_export(motion.apply(splats.clone(), motion.keyframe_time(k))))
+            .collect();
+        (to_export(splats.clone()), frames)
+    });
+    // Kept to measure against the training views, which are in the frame of training.
+    let train_splats = splats.clone();
+    let export_splats = to_export(splats);
+    let watermark = process_config.export_watermark.map(|payload| Watermark {
+        payload,
+        key: process_config.watermark_key,
+    });
+    let ext = Path::new(&export_name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase);
+    let (splat_data, features) = if let Some(ext @ ("usda" | "usdz")) = ext.as_deref() {
+        let mut stage = UsdStage::new();
+        stage.add_splats("Splats", export_splats).await;
+        let data = if ext == "usdz" {
+            stage.to_usdz()?
+        } else {
+            stage.to_usda()
+        };
+        (data, features)
+    } else if ext.as_deref() == Some("glb") {
+        let data = splat_to_glb(
+            export_splats,
+            watermark,
+            process_config.export_gltf_fallback,
+        )
+        .await?;
+        (data, features)
+    } else if ext.as_deref() == Some("bsplat") {
+        // Chunks are sorted by position, which features don't follow.
+        (splat_to_chunked(export_splats, CHUNK_SPLATS).await?, None)
+    } else if ext.as_deref() == Some("bsz") {
+        let settings = process_config.export_compression.settings();
+        // Compressed files keep the order of the splats, but not their exact values.
+        (compress_splats(export_splats, &settings).await?, features)
+    } else if process_config.export_progressive {
+        // Most of the training views show after loading only the first splats.
+        let scores = if dataset.train.views.is_empty() {
+            train_splats.importance()
+        } else {
+            contribution(&train_splats, &dataset.train.views)
+        };
+        let (data, order) =
+            splat_to_progressive_ply(export_splats, scores, watermark, None).await?;
+        // Features stay in the order of the splats.
+        (data, features.map(|features| features.select(0, order)))
+    } else {
+        (
+            splat_to_ply_with_watermark(export_splats, watermark).await?,
+            features,
+        )
+    };
+    let mut files = vec![];
+    let mut add_file = |name: String, data: Vec<u8>| -> anyhow::Result<()> {
+        let data = if let Some(passphrase) = &process_config.export_passphrase {
+            brush_vfs::encryption::encrypt(&data, passphrase)?
+        } else {
+            data
+        };
+        files.push(MemoryFile {
+            name,
+            data: data.into(),
+        });
+        Ok(())
+    };
+    add_file(export_name.clone(), splat_data)?;
+
+    // Features of the splats go next to the ply, in the same order as the splats.
+    if let Some(features) = features {
+        let shape = features.dims();
+        let values = features
+            .into_data_async()
+            .await
+            .into_vec::<f32>()
+            .expect("Features are floats");
+        let name = Path::new(&export_name).with_extension("features.npy");
+        add_file(
+            name.to_string_lossy().into_owned(),
+            write_npy(&shape, &values),
+        )?;
+    }
+
+    if let Some((rest, frames)) = frames {
+        let data = splat_sequence_to_ply(rest, frames).await?;
+        let name = Path::new(&export_name).with_extension("frames.ply");
+        add_file(name.to_string_lossy().into_owned(), data)?;
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolution_goes_from_coarse_to_fine() {
+        let config = TrainConfig::new().with_coarse_to_fine_steps(300);
+        let levels: Vec<_> = [0, 149, 150, 299, 300, 1000]
+            .map(|iter| resolution_level(&config, iter))
+            .to_vec();
+        assert_eq!(levels, [2, 2, 1, 1, 0, 0]);
+        // Without a schedule, training is at full resolution straight away.
+        assert_eq!(resolution_level(&TrainConfig::new(), 0), 0);
+    }
+}
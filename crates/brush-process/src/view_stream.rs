@@ -1,10 +1,15 @@
-use crate::message::ProcessMessage;
+use crate::{control::ViewFocus, message::ProcessMessage};
 
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
 use async_fn_stream::TryStreamEmitter;
-use brush_dataset::splat_import;
-use brush_vfs::BrushVfs;
+use brush_dataset::{
+    chunked_splats::load_chunked,
+    composition::load_composition,
+    splat_formats::{self, SplatFormat},
+};
+use brush_render::composition::compose_splats;
+use brush_vfs::{BrushVfs, ranges::RangeSource};
 use burn_cubecl::cubecl::Runtime;
 use burn_wgpu::{WgpuDevice, WgpuRuntime};
 use tokio_stream::StreamExt;
@@ -19,14 +24,16 @@ pub(crate) async fn view_stream(
     let client = WgpuRuntime::client(&device);
 
     for (i, path) in paths.iter().enumerate() {
-        log::info!("Loading single ply file");
+        let format = SplatFormat::from_path(path).unwrap_or(SplatFormat::Ply);
+        log::info!("Loading single {format:?} file");
 
         emitter
             .emit(ProcessMessage::StartLoading { training: false })
             .await;
 
-        let sub_sample = None; // Subsampling a trained ply doesn't really make sense.
-        let splat_stream = splat_import::load_splat_from_ply(
+        let sub_sample = None; // Subsampling trained splats doesn't really make sense.
+        let splat_stream = splat_formats::load_splats(
+            format,
             vfs.reader_at_path(path).await?,
             sub_sample,
             device.clone(),
@@ -50,6 +57,7 @@ pub(crate) async fn view_stream(
                 splats: Box::new(message.splats),
                 frame,
                 total_frames,
+                labels: message.meta.labels,
             };
 
             // As loading concatenates splats each time, memory usage tends to accumulate a lot
@@ -67,3 +75,77 @@ pub(crate) async fn view_stream(
 
     Ok(())
 }
+
+/// View the layers of a composition file merged into one scene, each placed by its transform.
+pub(crate) async fn view_composition(
+    vfs: Arc<BrushVfs>,
+    path: &Path,
+    device: WgpuDevice,
+    emitter: TryStreamEmitter<ProcessMessage, anyhow::Error>,
+) -> anyhow::Result<()> {
+    log::info!("Loading composition {}", path.display());
+    emitter
+        .emit(ProcessMessage::StartLoading { training: false })
+        .await;
+
+    let layers = load_composition(&vfs, path, device.clone()).await?;
+    let splats = compose_splats(
+        layers
+            .into_iter()
+            .map(|(layer, splats)| (splats, layer.transform)),
+    )
+    .expect("Compositions have layers");
+    emitter
+        .emit(ProcessMessage::ViewSplats {
+            up_axis: None,
+            splats: Box::new(splats),
+            frame: 0,
+            total_frames: 1,
+            labels: None,
+        })
+        .await;
+    emitter.emit(ProcessMessage::DoneLoading).await;
+    WgpuRuntime::client(&device).memory_cleanup();
+    Ok(())
+}
+
+// Bytes of splats to keep loaded when viewing a chunked file. Browser tabs get a few GB at most.
+#[cfg(target_family = "wasm")]
+const CHUNKED_VIEW_BYTES: u64 = 1 << 30;
+#[cfg(not(target_family = "wasm"))]
+const CHUNKED_VIEW_BYTES: u64 = 4 << 30;
+
+/// View a chunked splat file while it loads, the chunks in view of `focus` first. Chunks out of
+/// view make room for others once the loaded splats take up `CHUNKED_VIEW_BYTES`, and then loading
+/// goes on as the camera moves.
+pub(crate) async fn view_chunked(
+    source: RangeSource,
+    focus: ViewFocus,
+    device: WgpuDevice,
+    emitter: TryStreamEmitter<ProcessMessage, anyhow::Error>,
+) -> anyhow::Result<()> {
+    log::info!("Loading chunked splat file");
+    let client = WgpuRuntime::client(&device);
+    emitter
+        .emit(ProcessMessage::StartLoading { training: false })
+        .await;
+
+    let splat_stream = load_chunked(source, focus, Some(CHUNKED_VIEW_BYTES), device);
+    let mut splat_stream = std::pin::pin!(splat_stream);
+    while let Some(message) = splat_stream.next().await {
+        let message = message?;
+        client.memory_cleanup();
+        emitter
+            .emit(ProcessMessage::ViewSplats {
+                up_axis: message.meta.up_axis,
+                splats: Box::new(message.splats),
+                frame: 0,
+                total_frames: 0,
+                labels: None,
+            })
+            .await;
+    }
+    emitter.emit(ProcessMessage::DoneLoading).await;
+    client.memory_cleanup();
+    Ok(())
+}
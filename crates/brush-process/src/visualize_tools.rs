@@ -10,6 +10,7 @@ mod visualize_tools_impl {
     use std::sync::Arc;
 
     use brush_dataset::scene::Scene;
+    use brush_render::camera::CameraModel;
     use brush_render::gaussian_splats::Splats;
     use brush_render::shaders::project_visible::SH_C0;
     use brush_train::eval::EvalSample;
@@ -120,15 +121,17 @@ mod visualize_tools_impl {
                 for (i, view) in scene.views.iter().enumerate() {
                     let path = format!("world/dataset/camera/{i}");
 
-                    let focal = view.camera.focal(glam::uvec2(1, 1));
-
-                    self.rec.log_static(
-                        path.clone(),
-                        &rerun::Pinhole::from_fov_and_aspect_ratio(
-                            view.camera.fov_y as f32,
-                            focal.x / focal.y,
-                        ),
-                    )?;
+                    // Rerun has no panoramic cameras, panoramas only show their pose.
+                    if view.camera.model == CameraModel::Pinhole {
+                        let focal = view.camera.focal(glam::uvec2(1, 1));
+                        self.rec.log_static(
+                            path.clone(),
+                            &rerun::Pinhole::from_fov_and_aspect_ratio(
+                                view.camera.fov_y as f32,
+                                focal.x / focal.y,
+                            ),
+                        )?;
+                    }
                     self.rec.log_static(
                         path.clone(),
                         &rerun::Transform3D::from_translation_rotation(
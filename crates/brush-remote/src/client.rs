@@ -0,0 +1,166 @@
+use std::ops::ControlFlow;
+
+use anyhow::anyhow;
+use async_fn_stream::try_fn_stream;
+use brush_dataset::splat_import::load_splat_from_ply;
+use brush_process::message::ProcessMessage;
+use burn_wgpu::WgpuDevice;
+use ewebsock::{WsEvent, WsMessage, WsSender};
+use tokio::sync::mpsc;
+use tokio_stream::{Stream, StreamExt};
+use web_time::{Duration, Instant};
+
+use crate::protocol::{ClientMessage, ServerMessage};
+
+// How often to fetch the splats of the run. They're a whole ply file, so not too often.
+const SPLATS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Whether `url` points at a served training run, rather than at data to load.
+pub fn is_remote_url(url: &str) -> bool {
+    url.starts_with("ws://") || url.starts_with("wss://")
+}
+
+/// Sends requests to a remote training run.
+#[derive(Clone, Debug)]
+pub struct RemoteControl {
+    requests: mpsc::UnboundedSender<ClientMessage>,
+}
+
+impl RemoteControl {
+    /// A control, and the requests sent with it to pass to [`connect`].
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<ClientMessage>) {
+        let (requests, receiver) = mpsc::unbounded_channel();
+        (Self { requests }, receiver)
+    }
+
+    pub fn send(&self, message: ClientMessage) {
+        // Fails when the connection is gone, which the stream already reports.
+        let _ = self.requests.send(message);
+    }
+}
+
+fn send(socket: &mut WsSender, message: &ClientMessage) {
+    if let Ok(json) = serde_json::to_string(message) {
+        socket.send(WsMessage::Text(json));
+    }
+}
+
+async fn read_splats(ply: Vec<u8>, device: WgpuDevice) -> anyhow::Result<Option<ProcessMessage>> {
+    let mut stream = std::pin::pin!(load_splat_from_ply(std::io::Cursor::new(ply), None, device));
+    let mut last = None;
+    while let Some(message) = stream.next().await {
+        last = Some(message?.splats);
+    }
+    Ok(last.map(|splats| ProcessMessage::ViewSplats {
+        up_axis: None,
+        splats: Box::new(splats),
+        frame: 0,
+        total_frames: 1,
+        labels: None,
+    }))
+}
+
+/// Connect to a training run served at `url`, and follow it as a stream of process messages.
+///
+/// The run shows up as a training process, whose splats are fetched every few seconds. Pausing
+/// and other changes are sent to the run from the `requests` of a [`RemoteControl`].
+pub fn connect(
+    url: String,
+    device: WgpuDevice,
+    mut requests: mpsc::UnboundedReceiver<ClientMessage>,
+) -> impl Stream<Item = anyhow::Result<ProcessMessage>> + 'static {
+    try_fn_stream(|emitter| async move {
+        let (event_sender, mut events) = mpsc::unbounded_channel();
+        let on_event = Box::new(move |event: WsEvent| match event_sender.send(event) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(_) => ControlFlow::Break(()),
+        });
+        let mut socket = ewebsock::ws_connect(url.clone(), ewebsock::Options::default(), on_event)
+            .map_err(|e| anyhow!("Failed to connect to {url}: {e}"))?;
+
+        emitter.emit(ProcessMessage::NewSource).await;
+        emitter
+            .emit(ProcessMessage::StartLoading { training: true })
+            .await;
+
+        let mut last_fetch: Option<Instant> = None;
+        loop {
+            tokio::select! {
+                request = requests.recv() => {
+                    // Nobody is following the run anymore.
+                    let Some(request) = request else {
+                        break;
+                    };
+                    send(&mut socket, &request);
+                }
+                event = events.recv() => {
+                    let Some(event) = event else {
+                        break;
+                    };
+                    match event {
+                        WsEvent::Opened => {
+                            log::info!("Connected to training run at {url}");
+                            emitter.emit(ProcessMessage::DoneLoading).await;
+                        }
+                        WsEvent::Message(WsMessage::Text(text)) => {
+                            match serde_json::from_str(&text)? {
+                                ServerMessage::Status {
+                                    iter,
+                                    total_steps,
+                                    num_splats,
+                                    elapsed_secs,
+                                    paused,
+                                } => {
+                                    let fetch = last_fetch
+                                        .is_none_or(|last| last.elapsed() > SPLATS_INTERVAL);
+                                    if fetch {
+                                        send(&mut socket, &ClientMessage::RequestSplats);
+                                        last_fetch = Some(Instant::now());
+                                    }
+                                    emitter
+                                        .emit(ProcessMessage::RemoteTrainStep {
+                                            iter,
+                                            total_steps,
+                                            num_splats,
+                                            total_elapsed: Duration::from_secs_f32(
+                                                elapsed_secs,
+                                            ),
+                                            paused,
+                                        })
+                                        .await;
+                                }
+                                ServerMessage::Eval { iter, psnr, ssim } => {
+                                    emitter
+                                        .emit(ProcessMessage::EvalResult {
+                                            iter,
+                                            avg_psnr: psnr,
+                                            avg_ssim: ssim,
+                                        })
+                                        .await;
+                                }
+                                ServerMessage::Finished => {
+                                    log::info!("Training run at {url} finished");
+                                    break;
+                                }
+                                ServerMessage::Error { message } => {
+                                    return Err(anyhow!("Training run failed: {message}"));
+                                }
+                            }
+                        }
+                        WsEvent::Message(WsMessage::Binary(ply)) => {
+                            if let Some(message) = read_splats(ply, device.clone()).await? {
+                                emitter.emit(message).await;
+                            }
+                        }
+                        WsEvent::Message(_) => {}
+                        WsEvent::Error(e) => {
+                            return Err(anyhow!("Lost connection to {url}: {e}"));
+                        }
+                        WsEvent::Closed => break,
+                    }
+                }
+            }
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,13 @@
+// Follow and steer a training run from another process or machine, over a WebSocket.
+//
+// The training process serves its run with a `RemoteServer`, and viewers connect to it with
+// `connect`, which turns the run into process messages like a local one. `render_service` serves
+// renders of splat files over HTTP instead.
+pub mod client;
+pub mod protocol;
+#[cfg(not(target_family = "wasm"))]
+pub mod render_service;
+#[cfg(not(target_family = "wasm"))]
+pub mod server;
+
+pub use client::{RemoteControl, connect, is_remote_url};
@@ -0,0 +1,65 @@
+use brush_process::control::TrainCommand;
+use serde::{Deserialize, Serialize};
+
+/// Port training runs are served on, unless another one is picked.
+pub const DEFAULT_PORT: u16 = 9871;
+
+/// Sent by a viewer to the training process, as JSON text messages.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Pause,
+    Resume,
+    /// Ask for the current splats. These are sent to all viewers as a binary message with a ply
+    /// file.
+    RequestSplats,
+    Train {
+        command: TrainCommand,
+    },
+}
+
+/// Sent by the training process to all viewers, as JSON text messages.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// Training is at step `iter`. Sent a few times a second at most, and when pausing.
+    Status {
+        iter: u32,
+        total_steps: u32,
+        num_splats: u32,
+        elapsed_secs: f32,
+        paused: bool,
+    },
+    Eval {
+        iter: u32,
+        psnr: f32,
+        ssim: f32,
+    },
+    Finished,
+    Error {
+        message: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_are_tagged_json() {
+        let message = ClientMessage::Train {
+            command: TrainCommand::SetLrScale { scale: 0.5 },
+        };
+        let json = serde_json::to_string(&message).expect("Serializable");
+        assert_eq!(
+            json,
+            r#"{"type":"train","command":{"type":"set_lr_scale","scale":0.5}}"#
+        );
+        let parsed: ClientMessage = serde_json::from_str(&json).expect("Valid message");
+        assert_eq!(parsed, message);
+        assert_eq!(
+            serde_json::from_str::<ClientMessage>(r#"{"type":"pause"}"#).ok(),
+            Some(ClientMessage::Pause)
+        );
+    }
+}
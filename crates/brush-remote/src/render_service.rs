@@ -0,0 +1,580 @@
+// Render splats over HTTP, for thumbnail servers and web backends that can't run a GPU renderer
+// themselves. Splat files are uploaded once, and can then be rendered from any camera.
+//
+// Uploads are decoded by the HTTP server as they come in. Other requests are passed on to the task
+// that owns the loaded splats, which answers them one at a time.
+use std::{collections::BTreeMap, io::Cursor, net::SocketAddr};
+
+use anyhow::Context;
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use brush_dataset::{
+    scene_transform::SceneTransform,
+    splat_formats::{self, SplatFormat},
+};
+use brush_process::stills::render_shot;
+use brush_render::{MainBackend, camera_path::look_at, gaussian_splats::Splats, shots::Shot};
+use burn_wgpu::WgpuDevice;
+use clap::Args;
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, oneshot},
+};
+use tokio_stream::StreamExt;
+
+#[derive(Args, Clone, Debug)]
+pub struct ServeArgs {
+    /// Address to serve the API on. Use 0.0.0.0 to accept requests from other machines, anyone who
+    /// can reach the address can upload files.
+    #[arg(long, default_value = "127.0.0.1:9872")]
+    pub addr: SocketAddr,
+    /// Most splat files to keep loaded. Uploading more unloads the oldest one.
+    #[arg(long, default_value = "8")]
+    pub max_scenes: usize,
+    /// Largest splat file to accept, in MB.
+    #[arg(long, default_value = "2048")]
+    pub max_upload_mb: usize,
+    /// Largest width or height to render.
+    #[arg(long, default_value = "4096")]
+    pub max_size: u32,
+}
+
+/// What's known about an uploaded splat file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SceneInfo {
+    pub id: u32,
+    pub num_splats: u32,
+    pub sh_degree: u32,
+    /// Bounds of the centers of the splats.
+    pub min: Vec3,
+    pub max: Vec3,
+    /// Up direction of the scene, if the file has one.
+    pub up_axis: Option<Vec3>,
+}
+
+#[derive(Deserialize)]
+struct UploadQuery {
+    /// Name of the file, to recognize files without a header by their extension.
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ThumbnailQuery {
+    #[serde(default = "default_thumbnail_size")]
+    width: u32,
+    #[serde(default = "default_thumbnail_size")]
+    height: u32,
+}
+
+fn default_thumbnail_size() -> u32 {
+    512
+}
+
+// An error to answer a request with.
+struct ApiError(StatusCode, String);
+
+impl ApiError {
+    fn not_found(id: u32) -> Self {
+        Self(StatusCode::NOT_FOUND, format!("No splats with id {id}"))
+    }
+
+    fn unavailable() -> Self {
+        Self(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The renderer stopped".to_owned(),
+        )
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, self.1).into_response()
+    }
+}
+
+type Reply<T> = oneshot::Sender<Result<T, ApiError>>;
+
+enum Request {
+    Add {
+        scene: Box<Scene>,
+        reply: Reply<SceneInfo>,
+    },
+    List {
+        reply: Reply<Vec<SceneInfo>>,
+    },
+    Info {
+        id: u32,
+        reply: Reply<SceneInfo>,
+    },
+    Remove {
+        id: u32,
+        reply: Reply<()>,
+    },
+    Render {
+        id: u32,
+        shot: Shot,
+        reply: Reply<Vec<u8>>,
+    },
+    Thumbnail {
+        id: u32,
+        width: u32,
+        height: u32,
+        reply: Reply<Vec<u8>>,
+    },
+}
+
+type Requests = mpsc::Sender<Request>;
+
+// State of the HTTP handlers.
+#[derive(Clone)]
+struct Service {
+    requests: Requests,
+    device: WgpuDevice,
+}
+
+// Pass a request on to the renderer, and wait for the answer.
+async fn ask<T>(
+    requests: &Requests,
+    request: impl FnOnce(Reply<T>) -> Request,
+) -> Result<T, ApiError> {
+    let (reply, answer) = oneshot::channel();
+    requests
+        .send(request(reply))
+        .await
+        .map_err(|_| ApiError::unavailable())?;
+    answer.await.map_err(|_| ApiError::unavailable())?
+}
+
+fn png(data: Vec<u8>) -> Response {
+    ([(header::CONTENT_TYPE, "image/png")], data).into_response()
+}
+
+async fn upload(
+    State(service): State<Service>,
+    Query(query): Query<UploadQuery>,
+    data: Bytes,
+) -> Result<(StatusCode, Json<SceneInfo>), ApiError> {
+    // Decode the file here, so renders of other scenes don't wait for it.
+    let scene = Scene::load(data, query.name.as_deref(), &service.device)
+        .await
+        .map_err(|e| {
+            ApiError(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Failed to load splats: {e:#}"),
+            )
+        })?;
+    let scene = Box::new(scene);
+    let info = ask(&service.requests, |reply| Request::Add { scene, reply }).await?;
+    Ok((StatusCode::CREATED, Json(info)))
+}
+
+async fn list(State(service): State<Service>) -> Result<Json<Vec<SceneInfo>>, ApiError> {
+    Ok(Json(
+        ask(&service.requests, |reply| Request::List { reply }).await?,
+    ))
+}
+
+async fn info(
+    State(service): State<Service>,
+    Path(id): Path<u32>,
+) -> Result<Json<SceneInfo>, ApiError> {
+    Ok(Json(
+        ask(&service.requests, |reply| Request::Info { id, reply }).await?,
+    ))
+}
+
+async fn remove(
+    State(service): State<Service>,
+    Path(id): Path<u32>,
+) -> Result<StatusCode, ApiError> {
+    ask(&service.requests, |reply| Request::Remove { id, reply }).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn render(
+    State(service): State<Service>,
+    Path(id): Path<u32>,
+    Json(shot): Json<Shot>,
+) -> Result<Response, ApiError> {
+    let data = ask(&service.requests, |reply| Request::Render {
+        id,
+        shot,
+        reply,
+    })
+    .await?;
+    Ok(png(data))
+}
+
+async fn thumbnail(
+    State(service): State<Service>,
+    Path(id): Path<u32>,
+    Query(query): Query<ThumbnailQuery>,
+) -> Result<Response, ApiError> {
+    let (width, height) = (query.width, query.height);
+    let data = ask(&service.requests, |reply| Request::Thumbnail {
+        id,
+        width,
+        height,
+        reply,
+    })
+    .await?;
+    Ok(png(data))
+}
+
+struct Scene {
+    info: SceneInfo,
+    splats: Splats<MainBackend>,
+    // Sphere around most of the splats, to frame thumbnails with.
+    center: Vec3,
+    radius: f32,
+}
+
+impl Scene {
+    async fn load(data: Bytes, name: Option<&str>, device: &WgpuDevice) -> anyhow::Result<Self> {
+        let format = name
+            .and_then(|name| SplatFormat::from_path(std::path::Path::new(name)))
+            .unwrap_or_else(|| SplatFormat::from_header(&data));
+        let stream = splat_formats::load_splats(format, Cursor::new(data), None, device.clone());
+        let mut stream = std::pin::pin!(stream);
+        // Animated files end on their last frame.
+        let mut loaded = None;
+        while let Some(message) = stream.next().await {
+            loaded = Some(message?);
+        }
+        let loaded = loaded.context("No splats in the file")?;
+        let splats = loaded.splats;
+
+        let means = splats
+            .means
+            .val()
+            .into_data_async()
+            .await
+            .into_vec::<f32>()
+            .expect("Wrong type");
+        let points: Vec<_> = means.chunks_exact(3).map(Vec3::from_slice).collect();
+        let min = points.iter().copied().fold(Vec3::INFINITY, Vec3::min);
+        let max = points.iter().copied().fold(Vec3::NEG_INFINITY, Vec3::max);
+        // This ignores outliers, which trained splats usually have plenty of.
+        let fit = SceneTransform::from_points(&points);
+        let radius = 1.0 / fit.scale;
+
+        Ok(Self {
+            info: SceneInfo {
+                // Set once the scene is added.
+                id: 0,
+                num_splats: splats.num_splats(),
+                sh_degree: splats.sh_degree(),
+                min,
+                max,
+                up_axis: loaded.meta.up_axis,
+            },
+            splats,
+            center: -fit.translation * radius,
+            radius,
+        })
+    }
+
+    /// A view of the whole scene from above and to the side.
+    fn thumbnail_shot(&self, width: u32, height: u32) -> Shot {
+        let fov_y = 50.0f64.to_radians();
+        // Splats without an up axis are usually in the frame of COLMAP, with y pointing down.
+        let up = self.info.up_axis.unwrap_or(Vec3::NEG_Y).normalize();
+        let (side, _) = up.any_orthonormal_pair();
+        // Far enough for the bounding sphere to fit in view.
+        let distance = self.radius / (fov_y as f32 * 0.5).sin();
+        let position = self.center + (side + up * 0.4).normalize() * distance;
+        Shot {
+            name: String::new(),
+            position,
+            rotation: look_at(position, self.center, up),
+            fov_y,
+            exposure: 0.0,
+            width,
+            height,
+        }
+    }
+}
+
+struct Renderer {
+    scenes: BTreeMap<u32, Scene>,
+    next_id: u32,
+    args: ServeArgs,
+}
+
+impl Renderer {
+    fn scene(&self, id: u32) -> Result<&Scene, ApiError> {
+        self.scenes.get(&id).ok_or_else(|| ApiError::not_found(id))
+    }
+
+    async fn render(&self, id: u32, shot: &Shot) -> Result<Vec<u8>, ApiError> {
+        let max = self.args.max_size;
+        if shot.width == 0 || shot.height == 0 || shot.width > max || shot.height > max {
+            return Err(ApiError(
+                StatusCode::BAD_REQUEST,
+                format!("Renders must be between 1 and {max} pixels wide and high"),
+            ));
+        }
+        let scene = self.scene(id)?;
+        let encode = async {
+            let still = render_shot(&scene.splats, shot).await?;
+            let mut data = Cursor::new(vec![]);
+            still.write_to(&mut data, image::ImageFormat::Png)?;
+            anyhow::Ok(data.into_inner())
+        };
+        encode
+            .await
+            .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")))
+    }
+
+    async fn handle(&mut self, request: Request) {
+        match request {
+            Request::Add { mut scene, reply } => {
+                let id = self.next_id;
+                self.next_id += 1;
+                scene.info.id = id;
+                log::info!("Loaded {} splats as {id}", scene.info.num_splats);
+                let info = scene.info.clone();
+                self.scenes.insert(id, *scene);
+                while self.scenes.len() > self.args.max_scenes.max(1) {
+                    self.scenes.pop_first();
+                }
+                let _ = reply.send(Ok(info));
+            }
+            Request::List { reply } => {
+                let infos = self.scenes.values().map(|s| s.info.clone()).collect();
+                let _ = reply.send(Ok(infos));
+            }
+            Request::Info { id, reply } => {
+                let _ = reply.send(self.scene(id).map(|s| s.info.clone()));
+            }
+            Request::Remove { id, reply } => {
+                let removed = self.scenes.remove(&id).map(|_| ());
+                let _ = reply.send(removed.ok_or_else(|| ApiError::not_found(id)));
+            }
+            Request::Render { id, shot, reply } => {
+                let _ = reply.send(self.render(id, &shot).await);
+            }
+            Request::Thumbnail {
+                id,
+                width,
+                height,
+                reply,
+            } => {
+                let answer = match self.scene(id) {
+                    Ok(scene) => {
+                        let shot = scene.thumbnail_shot(width, height);
+                        self.render(id, &shot).await
+                    }
+                    Err(e) => Err(e),
+                };
+                let _ = reply.send(answer);
+            }
+        }
+    }
+}
+
+/// Serve the render API on the address of `args`:
+///
+/// - `POST /splats` uploads a splat file as the body, and answers with its [`SceneInfo`].
+/// - `GET /splats` lists the loaded files, and `GET /splats/{id}` describes one.
+/// - `DELETE /splats/{id}` unloads a file.
+/// - `POST /splats/{id}/render` renders a [`Shot`], given as JSON like in a shots file, as a PNG.
+/// - `GET /splats/{id}/thumbnail?width=&height=` renders a PNG that frames the whole scene.
+///
+/// Renders happen one at a time, on this task.
+pub async fn serve(args: ServeArgs, device: WgpuDevice) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(args.addr)
+        .await
+        .with_context(|| format!("Failed to serve the render API on {}", args.addr))?;
+    log::info!("Serving the render API on http://{}", args.addr);
+
+    let (app, renderer) = service(args, device);
+    let server = tokio::spawn(async move { axum::serve(listener, app).await });
+    renderer.await;
+    server.await?.context("The render API failed")
+}
+
+// The routes of the API, and the renderer answering them, which runs until the routes are gone.
+fn service(args: ServeArgs, device: WgpuDevice) -> (Router, impl Future<Output = ()>) {
+    let (sender, mut requests) = mpsc::channel(16);
+    let app = Router::new()
+        .route("/splats", get(list).post(upload))
+        .route("/splats/:id", get(info).delete(remove))
+        .route("/splats/:id/render", post(render))
+        .route("/splats/:id/thumbnail", get(thumbnail))
+        .layer(DefaultBodyLimit::max(args.max_upload_mb * 1024 * 1024))
+        .with_state(Service {
+            requests: sender,
+            device,
+        });
+
+    let mut renderer = Renderer {
+        scenes: BTreeMap::new(),
+        next_id: 0,
+        args,
+    };
+    let run = async move {
+        // Requests only stop coming once the routes are gone.
+        while let Some(request) = requests.recv().await {
+            renderer.handle(request).await;
+        }
+    };
+    (app, run)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{Body, to_bytes};
+    use brush_dataset::splat_export::splat_to_ply;
+    use tower::ServiceExt;
+
+    fn args(max_scenes: usize) -> ServeArgs {
+        ServeArgs {
+            addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+            max_scenes,
+            max_upload_mb: 16,
+            max_size: 256,
+        }
+    }
+
+    // Runs the checks against the routes, while the renderer answers them.
+    async fn with_service(args: ServeArgs, checks: impl AsyncFnOnce(Router)) {
+        let (app, renderer) = service(args, WgpuDevice::DefaultDevice);
+        tokio::select! {
+            () = checks(app) => {}
+            () = renderer => panic!("The renderer stopped"),
+        }
+    }
+
+    async fn send(app: &Router, method: &str, uri: &str, body: Vec<u8>) -> (StatusCode, Bytes) {
+        let request = axum::http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::from(body))
+            .expect("Valid request");
+        let response = app.clone().oneshot(request).await.expect("Infallible");
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("Read body");
+        (status, body)
+    }
+
+    async fn ply(num_splats: usize) -> Vec<u8> {
+        let means: Vec<_> = (0..num_splats)
+            .map(|i| Vec3::new(i as f32, 0.5, -1.0))
+            .collect();
+        let splats = Splats::<MainBackend>::from_raw(
+            &means,
+            None,
+            None,
+            None,
+            None,
+            &WgpuDevice::DefaultDevice,
+        );
+        splat_to_ply(splats).await.expect("Write ply")
+    }
+
+    async fn upload(app: &Router, num_splats: usize) -> SceneInfo {
+        let (status, body) =
+            send(app, "POST", "/splats?name=test.ply", ply(num_splats).await).await;
+        assert_eq!(status, StatusCode::CREATED);
+        serde_json::from_slice(&body).expect("Scene info")
+    }
+
+    #[tokio::test]
+    async fn uploads_list_and_remove() {
+        with_service(args(8), async |app| {
+            let first = upload(&app, 3).await;
+            let second = upload(&app, 5).await;
+            assert_eq!(first.num_splats, 3);
+            assert_eq!(second.num_splats, 5);
+            assert_ne!(first.id, second.id);
+            assert!(first.min.x < 1e-6 && (first.max.x - 2.0).abs() < 1e-6);
+
+            let (status, body) = send(&app, "GET", "/splats", vec![]).await;
+            assert_eq!(status, StatusCode::OK);
+            let list: Vec<SceneInfo> = serde_json::from_slice(&body).expect("Scene list");
+            assert_eq!(list, [first.clone(), second.clone()]);
+
+            let uri = format!("/splats/{}", first.id);
+            let (status, _) = send(&app, "DELETE", &uri, vec![]).await;
+            assert_eq!(status, StatusCode::NO_CONTENT);
+            let (status, _) = send(&app, "GET", &uri, vec![]).await;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+            let (status, _) = send(&app, "DELETE", &uri, vec![]).await;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+
+            let (status, body) = send(&app, "GET", &format!("/splats/{}", second.id), vec![]).await;
+            assert_eq!(status, StatusCode::OK);
+            let info: SceneInfo = serde_json::from_slice(&body).expect("Scene info");
+            assert_eq!(info, second);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn invalid_uploads_fail() {
+        with_service(args(8), async |app| {
+            let (status, _) = send(
+                &app,
+                "POST",
+                "/splats?name=test.ply",
+                b"not splats".to_vec(),
+            )
+            .await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+            let (status, body) = send(&app, "GET", "/splats", vec![]).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(&body[..], b"[]");
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn uploads_unload_the_oldest() {
+        with_service(args(2), async |app| {
+            let ids = [
+                upload(&app, 1).await.id,
+                upload(&app, 2).await.id,
+                upload(&app, 3).await.id,
+            ];
+            let (_, body) = send(&app, "GET", "/splats", vec![]).await;
+            let list: Vec<SceneInfo> = serde_json::from_slice(&body).expect("Scene list");
+            let loaded: Vec<_> = list.iter().map(|info| info.id).collect();
+            assert_eq!(loaded, ids[1..]);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn renders_thumbnails() {
+        with_service(args(8), async |app| {
+            let id = upload(&app, 4).await.id;
+            let uri = format!("/splats/{id}/thumbnail?width=32&height=16");
+            let (status, body) = send(&app, "GET", &uri, vec![]).await;
+            assert_eq!(status, StatusCode::OK);
+            let image = image::load_from_memory_with_format(&body, image::ImageFormat::Png)
+                .expect("Thumbnail is a png");
+            assert_eq!((image.width(), image.height()), (32, 16));
+
+            for size in ["width=0&height=16", "width=32&height=1000"] {
+                let uri = format!("/splats/{id}/thumbnail?{size}");
+                let (status, _) = send(&app, "GET", &uri, vec![]).await;
+                assert_eq!(status, StatusCode::BAD_REQUEST);
+            }
+            let (status, _) = send(&app, "GET", "/splats/1000/thumbnail", vec![]).await;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+        })
+        .await;
+    }
+}
@@ -0,0 +1,431 @@
+use std::net::SocketAddr;
+
+use anyhow::Context;
+use async_fn_stream::try_fn_stream;
+use brush_dataset::splat_export::splat_to_ply;
+use brush_process::{control::TrainCommand, message::ProcessMessage};
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, mpsc},
+};
+use tokio_stream::Stream;
+use tokio_tungstenite::tungstenite::{
+    Message,
+    handshake::server::{ErrorResponse, Request, Response},
+    http::StatusCode,
+};
+use web_time::{Duration, Instant};
+
+use crate::protocol::{ClientMessage, ServerMessage};
+
+// Don't flood viewers with the status of every step.
+const STATUS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Accepts viewers of a training run, and passes messages between them and the run.
+pub struct RemoteServer {
+    addr: SocketAddr,
+    clients: broadcast::Sender<Message>,
+    events: mpsc::UnboundedReceiver<ViewerEvent>,
+}
+
+// What viewers tell the run.
+enum ViewerEvent {
+    Request(ClientMessage),
+    // A viewer disconnected.
+    Left,
+}
+
+impl RemoteServer {
+    /// Listen for viewers on `addr`.
+    ///
+    /// With a `token`, viewers have to give it to connect, in the query of the URL they open, eg.
+    /// `ws://<host>:<port>/?token=<token>`. Addresses other than loopback need a token, as anyone
+    /// who can reach them could control the run otherwise.
+    pub async fn bind(addr: SocketAddr, token: Option<String>) -> anyhow::Result<Self> {
+        if token.is_none() && !addr.ip().is_loopback() {
+            anyhow::bail!(
+                "Serving the training run on {addr} needs a token, so only viewers given it can control the run"
+            );
+        }
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to listen for viewers on {addr}"))?;
+        let addr = listener.local_addr()?;
+        log::info!("Serving training run on ws://{addr}");
+
+        let (clients, _) = broadcast::channel(64);
+        let (event_sender, events) = mpsc::unbounded_channel();
+        let outgoing = clients.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, peer)) = listener.accept().await {
+                tokio::spawn(serve_viewer(
+                    stream,
+                    peer,
+                    token.clone(),
+                    outgoing.clone(),
+                    event_sender.clone(),
+                ));
+            }
+        });
+        Ok(Self {
+            addr,
+            clients,
+            events,
+        })
+    }
+
+    /// The address viewers connect to.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    fn num_viewers(&self) -> usize {
+        self.clients.receiver_count()
+    }
+
+    fn send(&self, message: &ServerMessage) {
+        if let Ok(json) = serde_json::to_string(message) {
+            // Fails when no viewers are connected, which is fine.
+            let _ = self.clients.send(Message::text(json));
+        }
+    }
+}
+
+// Compares all of the bytes, so the time taken doesn't tell how much of a guess was right.
+fn tokens_match(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn has_token(request: &Request, token: &str) -> bool {
+    request.uri().query().is_some_and(|query| {
+        query.split('&').any(|pair| {
+            pair.strip_prefix("token=")
+                .is_some_and(|given| tokens_match(given, token))
+        })
+    })
+}
+
+async fn serve_viewer(
+    stream: TcpStream,
+    peer: SocketAddr,
+    token: Option<String>,
+    clients: broadcast::Sender<Message>,
+    events: mpsc::UnboundedSender<ViewerEvent>,
+) {
+    let check_token = |request: &Request, response: Response| match &token {
+        Some(token) if !has_token(request, token) => {
+            let mut error = ErrorResponse::new(Some("Missing or wrong token".to_owned()));
+            *error.status_mut() = StatusCode::UNAUTHORIZED;
+            Err(error)
+        }
+        _ => Ok(response),
+    };
+    let socket = match tokio_tungstenite::accept_hdr_async(stream, check_token).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            log::warn!("Failed to accept viewer {peer}: {e}");
+            return;
+        }
+    };
+    log::info!("Viewer {peer} connected");
+    let mut outgoing = clients.subscribe();
+
+    let (mut sink, mut incoming) = socket.split();
+    loop {
+        tokio::select! {
+            message = outgoing.recv() => match message {
+                Ok(message) => {
+                    if sink.send(message).await.is_err() {
+                        break;
+                    }
+                }
+                // A slow viewer misses some updates, the next ones still arrive.
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            message = incoming.next() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(request) => {
+                        let _ = events.send(ViewerEvent::Request(request));
+                    }
+                    Err(e) => log::warn!("Invalid message from viewer {peer}: {e}"),
+                },
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    log::info!("Viewer {peer} disconnected");
+    // Stop counting as a viewer before telling the run.
+    drop(outgoing);
+    let _ = events.send(ViewerEvent::Left);
+}
+
+/// Pass on the messages of a training run, while sharing its progress with the viewers of
+/// `server` and following their requests. Changes to the run are sent to `commands`.
+///
+/// Viewers pause the run by holding up the stream, like pausing a local run. When the last viewer
+/// disconnects, the run resumes, as nobody is left to resume it.
+pub fn serve_training(
+    stream: impl Stream<Item = anyhow::Result<ProcessMessage>>,
+    mut server: RemoteServer,
+    commands: mpsc::UnboundedSender<TrainCommand>,
+    total_steps: u32,
+) -> impl Stream<Item = anyhow::Result<ProcessMessage>> {
+    try_fn_stream(|emitter| async move {
+        let mut stream = std::pin::pin!(stream);
+        let mut splats = None;
+        let mut paused = false;
+        let mut status = (0, 0, 0.0);
+        let mut last_status: Option<Instant> = None;
+
+        let send_status = |server: &RemoteServer, (iter, num_splats, elapsed_secs), paused| {
+            server.send(&ServerMessage::Status {
+                iter,
+                total_steps,
+                num_splats,
+                elapsed_secs,
+                paused,
+            });
+        };
+
+        while let Some(message) = stream.next().await {
+            let message = match message {
+                Ok(message) => message,
+                Err(e) => {
+                    server.send(&ServerMessage::Error {
+                        message: format!("{e:#}"),
+                    });
+                    return Err(e);
+                }
+            };
+
+            match &message {
+                ProcessMessage::TrainStep {
+                    splats: step_splats,
+                    iter,
+                    total_elapsed,
+                    ..
+                } => {
+                    status = (*iter, step_splats.num_splats(), total_elapsed.as_secs_f32());
+                    splats = Some(step_splats.clone());
+                    if last_status.is_none_or(|last| last.elapsed() > STATUS_INTERVAL) {
+                        send_status(&server, status, paused);
+                        last_status = Some(Instant::now());
+                    }
+                }
+                ProcessMessage::EvalResult {
+                    iter,
+                    avg_psnr,
+                    avg_ssim,
+                } => server.send(&ServerMessage::Eval {
+                    iter: *iter,
+                    psnr: *avg_psnr,
+                    ssim: *avg_ssim,
+                }),
+                _ => {}
+            }
+            emitter.emit(message).await;
+
+            // Follow requests, and wait for a viewer to resume while paused.
+            loop {
+                let event = if paused {
+                    server.events.recv().await
+                } else {
+                    server.events.try_recv().ok()
+                };
+                let request = match event {
+                    Some(ViewerEvent::Request(request)) => request,
+                    Some(ViewerEvent::Left) => {
+                        if paused && server.num_viewers() == 0 {
+                            log::info!("All viewers left, resuming the training run");
+                            paused = false;
+                        }
+                        continue;
+                    }
+                    None => break,
+                };
+                match request {
+                    ClientMessage::Pause | ClientMessage::Resume => {
+                        paused = request == ClientMessage::Pause;
+                        send_status(&server, status, paused);
+                    }
+                    ClientMessage::RequestSplats => {
+                        if let Some(splats) = &splats {
+                            let ply = splat_to_ply(*splats.clone()).await?;
+                            let _ = server.clients.send(Message::binary(ply));
+                        }
+                    }
+                    ClientMessage::Train { command } => {
+                        let _ = commands.send(command);
+                    }
+                }
+            }
+        }
+
+        server.send(&ServerMessage::Finished);
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::timeout;
+    use tokio_stream::wrappers::UnboundedReceiverStream;
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+
+    type Viewer = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    // Long enough for a message to arrive when it's going to.
+    const WAIT: Duration = Duration::from_millis(200);
+
+    fn loopback() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 0))
+    }
+
+    fn eval(iter: u32) -> anyhow::Result<ProcessMessage> {
+        Ok(ProcessMessage::EvalResult {
+            iter,
+            avg_psnr: 30.0,
+            avg_ssim: 0.9,
+        })
+    }
+
+    async fn request(viewer: &mut Viewer, message: ClientMessage) {
+        let json = serde_json::to_string(&message).expect("Serializable");
+        viewer
+            .send(Message::text(json))
+            .await
+            .expect("Send request");
+    }
+
+    // Whether the run is paused, by the next status it sends, if it sends one soon.
+    async fn next_status(viewer: &mut Viewer) -> Option<bool> {
+        timeout(WAIT, async {
+            while let Some(Ok(Message::Text(text))) = viewer.next().await {
+                if let Ok(ServerMessage::Status { paused, .. }) = serde_json::from_str(&text) {
+                    return Some(paused);
+                }
+            }
+            None
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+
+    // A served run of eval results, fed by the sender, whose passed on iterations come out of
+    // the receiver.
+    fn serve_run(
+        server: RemoteServer,
+    ) -> (
+        mpsc::UnboundedSender<anyhow::Result<ProcessMessage>>,
+        mpsc::UnboundedReceiver<u32>,
+    ) {
+        let (source, run) = mpsc::unbounded_channel();
+        let (commands, _) = mpsc::unbounded_channel();
+        let (passed, output) = mpsc::unbounded_channel();
+        let stream = serve_training(UnboundedReceiverStream::new(run), server, commands, 100);
+        tokio::spawn(async move {
+            let mut stream = std::pin::pin!(stream);
+            while let Some(Ok(ProcessMessage::EvalResult { iter, .. })) = stream.next().await {
+                let _ = passed.send(iter);
+            }
+        });
+        (source, output)
+    }
+
+    // Requests are followed between messages of the run, so keep the run going until the pause
+    // is seen.
+    async fn pause(
+        viewer: &mut Viewer,
+        source: &mpsc::UnboundedSender<anyhow::Result<ProcessMessage>>,
+        output: &mut mpsc::UnboundedReceiver<u32>,
+        iter: &mut u32,
+    ) {
+        request(viewer, ClientMessage::Pause).await;
+        for _ in 0..20 {
+            source.send(eval(*iter)).expect("Run is served");
+            assert_eq!(output.recv().await, Some(*iter));
+            *iter += 1;
+            if next_status(viewer).await == Some(true) {
+                return;
+            }
+        }
+        panic!("Run never paused");
+    }
+
+    #[tokio::test]
+    async fn open_addresses_need_a_token() {
+        let open = SocketAddr::from(([0, 0, 0, 0], 0));
+        assert!(RemoteServer::bind(open, None).await.is_err());
+        assert!(
+            RemoteServer::bind(open, Some("secret".to_owned()))
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn viewers_need_the_token() {
+        let server = RemoteServer::bind(loopback(), Some("secret".to_owned()))
+            .await
+            .expect("Bind");
+        let addr = server.addr();
+        assert!(connect_async(format!("ws://{addr}/")).await.is_err());
+        assert!(
+            connect_async(format!("ws://{addr}/?token=secrex"))
+                .await
+                .is_err()
+        );
+        assert!(
+            connect_async(format!("ws://{addr}/?a=1&token=secret"))
+                .await
+                .is_ok()
+        );
+        assert!(!tokens_match("secre", "secret"));
+    }
+
+    #[tokio::test]
+    async fn viewers_pause_and_resume_the_run() {
+        let server = RemoteServer::bind(loopback(), None).await.expect("Bind");
+        let (mut viewer, _) = connect_async(format!("ws://{}", server.addr()))
+            .await
+            .expect("Connect");
+        let (source, mut output) = serve_run(server);
+        let mut iter = 0;
+
+        pause(&mut viewer, &source, &mut output, &mut iter).await;
+        // The run is held up until resumed.
+        source.send(eval(iter)).expect("Run is served");
+        assert!(timeout(WAIT, output.recv()).await.is_err());
+        request(&mut viewer, ClientMessage::Resume).await;
+        assert_eq!(output.recv().await, Some(iter));
+        assert_eq!(next_status(&mut viewer).await, Some(false));
+    }
+
+    #[tokio::test]
+    async fn runs_resume_when_all_viewers_leave() {
+        let server = RemoteServer::bind(loopback(), None).await.expect("Bind");
+        let url = format!("ws://{}", server.addr());
+        let (mut viewer, _) = connect_async(&url).await.expect("Connect");
+        let (mut other, _) = connect_async(&url).await.expect("Connect");
+        let (source, mut output) = serve_run(server);
+        let mut iter = 0;
+
+        pause(&mut viewer, &source, &mut output, &mut iter).await;
+        source.send(eval(iter)).expect("Run is served");
+        // Someone is still watching.
+        viewer.close(None).await.expect("Close");
+        assert!(timeout(WAIT, output.recv()).await.is_err());
+        other.close(None).await.expect("Close");
+        assert_eq!(output.recv().await, Some(iter));
+    }
+}
@@ -6,6 +6,7 @@ fn main() -> miette::Result<()> {
             "src/shaders/rasterize_backwards.wgsl",
             "src/shaders/gather_grads.wgsl",
             "src/shaders/project_backwards.wgsl",
+            "src/shaders/sum_isect_grads.wgsl",
         ],
         &["../brush-render/src/shaders/helpers.wgsl"],
         "src/shaders/mod.rs",
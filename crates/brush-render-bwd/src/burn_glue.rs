@@ -76,6 +76,7 @@ impl SplatBackwardOps<Self> for MainBackendBase {
             state.tile_offsets,
             state.final_idx,
             state.sh_degree,
+            state.deterministic,
         )
     }
 }
@@ -94,6 +95,7 @@ pub struct GaussianBackwardState<B: Backend> {
     tile_offsets: IntTensor<B>,
     final_idx: IntTensor<B>,
     sh_degree: u32,
+    deterministic: bool,
 }
 
 #[derive(Debug)]
@@ -236,6 +238,7 @@ impl<B: Backend + SplatBackwardOps<B> + SplatForward<B>, C: CheckpointStrategy>
                     tile_offsets: aux.tile_offsets,
                     compact_gid_from_isect: aux.compact_gid_from_isect,
                     global_from_compact_gid: aux.global_from_compact_gid,
+                    deterministic: camera.deterministic,
                 };
 
                 let out_img = prep.finish(state, out_img);
@@ -267,6 +270,7 @@ impl SplatBackwardOps<Self> for Fusion<MainBackendBase> {
         struct CustomOp {
             desc: CustomOpIr,
             sh_degree: u32,
+            deterministic: bool,
         }
 
         impl<BT: BoolElement> Operation<FusionCubeRuntime<WgpuRuntime, BT>> for CustomOp {
@@ -307,6 +311,7 @@ impl SplatBackwardOps<Self> for Fusion<MainBackendBase> {
                     global_from_compact_gid: h
                         .get_int_tensor::<MainBackendBase>(global_from_compact_gid),
                     sh_degree: self.sh_degree,
+                    deterministic: self.deterministic,
                 };
 
                 let grads =
@@ -374,6 +379,7 @@ impl SplatBackwardOps<Self> for Fusion<MainBackendBase> {
                 // state,
                 desc,
                 sh_degree: state.sh_degree,
+                deterministic: state.deterministic,
             },
         );
         grads
@@ -0,0 +1,81 @@
+//! Typed, differentiable entry points to the splat renderer.
+//!
+//! These wrap [`SplatForwardDiff`] so the renderer can be used as part of any Burn autodiff graph.
+//! Gradients flow back to all inputs.
+
+use brush_render::{camera::Camera, gaussian_splats::Splats, render_aux::RenderAux};
+use burn::{
+    prelude::Backend,
+    tensor::{Tensor, TensorPrimitive},
+};
+
+use crate::burn_glue::SplatForwardDiff;
+
+/// Output of a differentiable render.
+pub struct DiffRenderOutput<B: Backend> {
+    /// Rendered image, [H, W, 4] with RGB + alpha. Colors are premultiplied by alpha,
+    /// as if composited on a black background.
+    pub image: Tensor<B, 3>,
+    /// Intermediate buffers of the render, eg. to count visible splats.
+    pub aux: RenderAux<B>,
+    /// Dummy tensor which receives the (per splat) screen space gradient norm as its gradient.
+    /// Only useful for densification heuristics, can be ignored otherwise.
+    pub refine_weight_holder: Tensor<B, 1>,
+}
+
+/// Render gaussians in a differentiable way.
+///
+/// For N gaussians with SH coefficients up to some degree (C coefficients), inputs are:
+/// - `means`: [N, 3] world space positions.
+/// - `log_scales`: [N, 3] log of the scale along each local axis.
+/// - `quats`: [N, 4] rotations, as (w, x, y, z) quaternions. These are normalized by the renderer.
+/// - `sh_coeffs`: [N, C, 3] SH coefficients per color channel.
+/// - `opacities`: [N] opacities in [0, 1] (so after activation).
+///
+/// The image has size `img_size` (width, height).
+pub fn render_splats<B: Backend + SplatForwardDiff<B>>(
+    camera: &Camera,
+    img_size: glam::UVec2,
+    means: Tensor<B, 2>,
+    log_scales: Tensor<B, 2>,
+    quats: Tensor<B, 2>,
+    sh_coeffs: Tensor<B, 3>,
+    opacities: Tensor<B, 1>,
+) -> DiffRenderOutput<B> {
+    let out = B::render_splats(
+        camera,
+        img_size,
+        means.into_primitive().tensor(),
+        log_scales.into_primitive().tensor(),
+        quats.into_primitive().tensor(),
+        sh_coeffs.into_primitive().tensor(),
+        opacities.into_primitive().tensor(),
+    );
+    DiffRenderOutput {
+        image: Tensor::from_primitive(TensorPrimitive::Float(out.img)),
+        aux: out.aux,
+        refine_weight_holder: out.refine_weight_holder,
+    }
+}
+
+/// Differentiable rendering of a [`Splats`] module.
+///
+/// As [`Splats`] is a regular Burn module, it can be embedded in other modules and optimized with
+/// any Burn optimizer.
+pub trait SplatsRenderDiff<B: Backend> {
+    fn render_diff(&self, camera: &Camera, img_size: glam::UVec2) -> DiffRenderOutput<B>;
+}
+
+impl<B: Backend + SplatForwardDiff<B>> SplatsRenderDiff<B> for Splats<B> {
+    fn render_diff(&self, camera: &Camera, img_size: glam::UVec2) -> DiffRenderOutput<B> {
+        render_splats(
+            camera,
+            img_size,
+            self.means.val(),
+            self.log_scales.val(),
+            self.rotation.val(),
+            self.sh_coeffs.val(),
+            self.opacities(),
+        )
+    }
+}
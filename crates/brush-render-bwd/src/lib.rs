@@ -1,3 +1,7 @@
 pub mod burn_glue;
+pub mod diff_render;
 mod render_bwd;
 mod shaders;
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests;
@@ -1,10 +1,12 @@
 use super::shaders::{project_backwards, rasterize_backwards};
-use crate::shaders::gather_grads;
+use crate::shaders::{gather_grads, sum_isect_grads};
 use brush_kernel::{CubeCount, CubeTensor, calc_cube_count, kernel_source_gen};
 
 use brush_render::MainBackendBase;
 use brush_render::sh::sh_coeffs_for_degree;
-use burn::tensor::ops::FloatTensorOps;
+use brush_sort::radix_argsort;
+use burn::tensor::ops::{FloatTensorOps, IntTensorOps};
+use burn::tensor::{Int, Tensor, s};
 use burn::{backend::wgpu::WgpuRuntime, prelude::Backend, tensor::ops::FloatTensor};
 use burn_cubecl::cubecl::AtomicFeature;
 use burn_cubecl::cubecl::server::Bindings;
@@ -12,7 +14,14 @@ use glam::uvec2;
 
 kernel_source_gen!(GatherGrads {}, gather_grads);
 kernel_source_gen!(ProjectBackwards {}, project_backwards);
-kernel_source_gen!(RasterizeBackwards { hard_float }, rasterize_backwards);
+kernel_source_gen!(
+    RasterizeBackwards {
+        hard_float,
+        deterministic
+    },
+    rasterize_backwards
+);
+kernel_source_gen!(SumIsectGrads {}, sum_isect_grads);
 
 #[derive(Debug, Clone)]
 pub struct SplatGrads<B: Backend> {
@@ -40,6 +49,7 @@ pub(crate) fn render_backward(
     tile_offsets: CubeTensor<WgpuRuntime>,
     final_index: CubeTensor<WgpuRuntime>,
     sh_degree: u32,
+    deterministic: bool,
 ) -> SplatGrads<MainBackendBase> {
     let device = &out_img.device;
     let img_dimgs = out_img.shape.dims;
@@ -84,24 +94,80 @@ pub(crate) fn render_backward(
                 AtomicFeature::Add,
             ));
 
-    // Use checked execution, as the atomic loops are potentially unbounded.
-    tracing::trace_span!("RasterizeBackwards", sync_burn = true).in_scope(|| {
-        client.execute(
-            RasterizeBackwards::task(hard_floats),
-            CubeCount::Static(invocations, 1, 1),
-            Bindings::new().with_buffers(vec![
-                uniforms_buffer.clone().handle.binding(),
-                compact_gid_from_isect.handle.binding(),
-                tile_offsets.handle.binding(),
-                projected_splats.handle.binding(),
-                final_index.handle.binding(),
-                out_img.handle.binding(),
-                v_output.handle.binding(),
-                v_grads.clone().handle.binding(),
-                v_refine_weight.clone().handle.binding(),
-            ]),
-        );
-    });
+    if deterministic {
+        // Atomics add up the gradients of all pixels in whatever order the GPU gets to them, so
+        // float rounding makes the sums differ between runs. Instead, sum the gradients of each
+        // intersection over its tile, and then the intersections of each splat, in a fixed order.
+        let num_isects = compact_gid_from_isect.shape.dims[0];
+        let v_isect_grads = MainBackendBase::float_zeros([num_isects, 11].into(), device);
+
+        tracing::trace_span!("RasterizeBackwards", sync_burn = true).in_scope(|| {
+            client.execute(
+                RasterizeBackwards::task(false, true),
+                CubeCount::Static(invocations, 1, 1),
+                Bindings::new().with_buffers(vec![
+                    uniforms_buffer.clone().handle.binding(),
+                    compact_gid_from_isect.clone().handle.binding(),
+                    tile_offsets.clone().handle.binding(),
+                    projected_splats.handle.binding(),
+                    final_index.handle.binding(),
+                    out_img.handle.binding(),
+                    v_output.handle.binding(),
+                    v_isect_grads.clone().handle.binding(),
+                ]),
+            );
+        });
+
+        // Group the intersections by splat. The sort is stable, so the intersections of each
+        // splat stay in order.
+        let num_intersections =
+            Tensor::<MainBackendBase, 1, Int>::from_primitive(tile_offsets.clone()).slice(s![-1]);
+        let bits = u32::BITS - (num_points as u32).leading_zeros();
+        let (sorted_compact_gids, sorted_isect_ids) =
+            tracing::trace_span!("Splat sort", sync_burn = true).in_scope(|| {
+                radix_argsort(
+                    compact_gid_from_isect,
+                    MainBackendBase::int_arange(0..num_isects as i64, device),
+                    &num_intersections.into_primitive(),
+                    bits,
+                )
+            });
+
+        tracing::trace_span!("SumIsectGrads", sync_burn = true).in_scope(|| {
+            client.execute(
+                SumIsectGrads::task(),
+                calc_cube_count([num_points as u32], SumIsectGrads::WORKGROUP_SIZE),
+                Bindings::new().with_buffers(vec![
+                    uniforms_buffer.clone().handle.binding(),
+                    tile_offsets.handle.binding(),
+                    sorted_compact_gids.handle.binding(),
+                    sorted_isect_ids.handle.binding(),
+                    v_isect_grads.handle.binding(),
+                    v_grads.clone().handle.binding(),
+                    v_refine_weight.clone().handle.binding(),
+                ]),
+            );
+        });
+    } else {
+        // Use checked execution, as the atomic loops are potentially unbounded.
+        tracing::trace_span!("RasterizeBackwards", sync_burn = true).in_scope(|| {
+            client.execute(
+                RasterizeBackwards::task(hard_floats, false),
+                CubeCount::Static(invocations, 1, 1),
+                Bindings::new().with_buffers(vec![
+                    uniforms_buffer.clone().handle.binding(),
+                    compact_gid_from_isect.handle.binding(),
+                    tile_offsets.handle.binding(),
+                    projected_splats.handle.binding(),
+                    final_index.handle.binding(),
+                    out_img.handle.binding(),
+                    v_output.handle.binding(),
+                    v_grads.clone().handle.binding(),
+                    v_refine_weight.clone().handle.binding(),
+                ]),
+            );
+        });
+    }
 
     let _span = tracing::trace_span!("GatherGrads", sync_burn = true).entered();
 
@@ -146,6 +146,51 @@ fn persp_proj_vjp(
     return v_mean3d;
 }
 
+fn equirect_proj_vjp(
+    J: mat3x2f,
+    // fwd inputs
+    mean3d: vec3f,
+    cov3d: mat3x3f,
+    focal: vec2f,
+    // grad outputs
+    v_cov2d: mat2x2f,
+    v_mean2d: vec2f,
+) -> vec3f {
+    let x = mean3d.x;
+    let y = mean3d.y;
+    let z = mean3d.z;
+
+    // The projected mean moves with the Jacobian.
+    var v_mean3d = transpose(J) * v_mean2d;
+
+    // The Jacobian itself depends on the mean too.
+    let v_J = v_cov2d * J * transpose(cov3d) + transpose(v_cov2d) * J * cov3d;
+
+    let rho2 = max(x * x + z * z, helpers::EQUIRECT_MIN_RHO * helpers::EQUIRECT_MIN_RHO);
+    let rho = sqrt(rho2);
+    let r2 = rho2 + y * y;
+    let r4 = r2 * r2;
+    let rho4 = rho2 * rho2;
+
+    // Longitude row: J_00 = fx * z / rho2, J_02 = -fx * x / rho2.
+    let d_lon_a = focal.x * (x * x - z * z) / rho4;
+    let d_lon_b = 2.0 * focal.x * x * z / rho4;
+    v_mean3d += v_J[0][0] * vec3f(-d_lon_b, 0.0, d_lon_a);
+    v_mean3d += v_J[2][0] * vec3f(d_lon_a, 0.0, d_lon_b);
+
+    // Latitude row: J_10 = -fy * x * g, J_11 = fy * rho / r2, J_12 = -fy * z * g,
+    // with g = y / (rho * r2).
+    let g = y / (rho * r2);
+    let k = y * (1.0 / (rho * rho2 * r2) + 2.0 / (rho * r4));
+    let dg_dy = (r2 - 2.0 * y * y) / (rho * r4);
+    v_mean3d += v_J[0][1] * -focal.y * vec3f(g - x * x * k, x * dg_dy, -x * z * k);
+    v_mean3d += v_J[2][1] * -focal.y * vec3f(-x * z * k, z * dg_dy, g - z * z * k);
+    let q = 1.0 / (rho * r2) - 2.0 * rho / r4;
+    v_mean3d += v_J[1][1] * focal.y * vec3f(x * q, -2.0 * rho * y / r4, z * q);
+
+    return v_mean3d;
+}
+
 @compute
 @workgroup_size(256, 1, 1)
 fn main(@builtin(global_invocation_id) gid: vec3u) {
@@ -179,7 +224,7 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     let M = rotmat * S;
 
     let covar = M * transpose(M);
-    let cov2d = helpers::calc_cov2d(covar, mean_c, focal, img_size, pixel_center, viewmat);
+    let cov2d = helpers::calc_cov2d(covar, mean_c, focal, img_size, pixel_center, viewmat, uniforms.camera_model);
     let covar2d_inv = helpers::inverse(cov2d);
 
     let v_covar2d_inv = mat2x2f(vec2f(v_conics.x, v_conics.y * 0.5f), vec2f(v_conics.y * 0.5f, v_conics.z));
@@ -190,8 +235,13 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     let covar_c = R * covar * transpose(R);
 
     // persp_proj_vjp
-    let J = helpers::calc_cam_J(mean_c, focal, img_size, pixel_center);
-    let v_mean_c = persp_proj_vjp(J, mean_c, covar_c, focal, pixel_center, img_size, v_covar2d, v_mean2d);
+    let J = helpers::calc_cam_J(mean_c, focal, img_size, pixel_center, uniforms.camera_model);
+    var v_mean_c: vec3f;
+    if uniforms.camera_model == helpers::CAMERA_EQUIRECT {
+        v_mean_c = equirect_proj_vjp(J, mean_c, covar_c, focal, v_covar2d, v_mean2d);
+    } else {
+        v_mean_c = persp_proj_vjp(J, mean_c, covar_c, focal, pixel_center, img_size, v_covar2d, v_mean2d);
+    }
     // cov = J * V * Jt; G = df/dcov = v_cov
     // -> df/dV = Jt * G * J
     // -> df/dJ = G * J * Vt + Gt * J * V
@@ -11,6 +11,11 @@
 @group(0) @binding(5) var<storage, read> output: array<vec4f>;
 @group(0) @binding(6) var<storage, read> v_output: array<vec4f>;
 
+#ifdef DETERMINISTIC
+    // The gradients of each intersection, summed over its tile: the 9 splat gradients and then
+    // the 2 refine gradients. These are summed per splat in a fixed order afterwards.
+    @group(0) @binding(7) var<storage, read_write> v_isect_grads: array<f32>;
+#else
 #ifdef HARD_FLOAT
     @group(0) @binding(7) var<storage, read_write> v_splats: array<atomic<f32>>;
     @group(0) @binding(8) var<storage, read_write> v_refine_grad: array<atomic<f32>>;
@@ -18,6 +23,7 @@
     @group(0) @binding(7) var<storage, read_write> v_splats: array<atomic<u32>>;
     @group(0) @binding(8) var<storage, read_write> v_refine_grad: array<atomic<u32>>;
 #endif
+#endif
 
 const BATCH_SIZE = helpers::TILE_SIZE;
 
@@ -28,6 +34,26 @@ var<workgroup> local_id: array<u32, BATCH_SIZE>;
 var<workgroup> max_idx: atomic<u32>;
 var<workgroup> max_idx_uniform: u32;
 
+#ifdef DETERMINISTIC
+var<workgroup> tile_sums: array<vec4f, helpers::TILE_SIZE>;
+
+// Sum `value` over all threads of the tile. The values are always added in the same order, unlike
+// atomics which add them in whatever order the threads get to them.
+fn tile_sum(local_idx: u32, value: vec4f) -> vec4f {
+    tile_sums[local_idx] = value;
+    workgroupBarrier();
+    for (var stride = helpers::TILE_SIZE / 2u; stride > 0u; stride /= 2u) {
+        if local_idx < stride {
+            tile_sums[local_idx] += tile_sums[local_idx + stride];
+        }
+        workgroupBarrier();
+    }
+    let sum = tile_sums[0];
+    // Wait for all threads to read the sum before the next one overwrites it.
+    workgroupBarrier();
+    return sum;
+}
+#else
 fn add_bitcast(cur: u32, add: f32) -> u32 {
     return bitcast<u32>(bitcast<f32>(cur) + add);
 }
@@ -57,6 +83,7 @@ fn write_refine_atomic(id: u32, grads: f32) {
     }
 #endif
 }
+#endif
 
 // kernel function for rasterizing each tile
 // each thread treats a single pixel
@@ -210,6 +237,27 @@ fn main(
                 }
             }
 
+#ifdef DETERMINISTIC
+            let sum_a = tile_sum(local_idx, vec4f(v_xy, v_conic.xy));
+            let sum_b = tile_sum(local_idx, vec4f(v_conic.z, v_colors.xyz));
+            let sum_c = tile_sum(local_idx, vec4f(v_colors.w, v_refine, 0.0));
+
+            // Each intersection is in one tile, so can be written without atomics.
+            if local_idx == 0u {
+                let base = isect_id * 11u;
+                v_isect_grads[base + 0u] = sum_a.x;
+                v_isect_grads[base + 1u] = sum_a.y;
+                v_isect_grads[base + 2u] = sum_a.z;
+                v_isect_grads[base + 3u] = sum_a.w;
+                v_isect_grads[base + 4u] = sum_b.x;
+                v_isect_grads[base + 5u] = sum_b.y;
+                v_isect_grads[base + 6u] = sum_b.z;
+                v_isect_grads[base + 7u] = sum_b.w;
+                v_isect_grads[base + 8u] = sum_c.x;
+                v_isect_grads[base + 9u] = sum_c.y;
+                v_isect_grads[base + 10u] = sum_c.z;
+            }
+#else
             let v_xy_sum = subgroupAdd(v_xy);
             let v_conic_sum = subgroupAdd(v_conic);
             let v_colors_sum = subgroupAdd(v_colors);
@@ -246,6 +294,7 @@ fn main(
                     default: {}
                 }
             }
+#endif
         }
 
         // Wait for all gradients to be written.
@@ -0,0 +1,59 @@
+#import helpers;
+
+@group(0) @binding(0) var<storage, read> uniforms: helpers::RenderUniforms;
+@group(0) @binding(1) var<storage, read> tile_offsets: array<i32>;
+
+// The intersections, sorted by the splat they belong to.
+@group(0) @binding(2) var<storage, read> sorted_compact_gids: array<i32>;
+@group(0) @binding(3) var<storage, read> sorted_isect_ids: array<i32>;
+@group(0) @binding(4) var<storage, read> v_isect_grads: array<f32>;
+
+@group(0) @binding(5) var<storage, read_write> v_splats: array<f32>;
+@group(0) @binding(6) var<storage, read_write> v_refine_grad: array<f32>;
+
+// First of the `count` sorted intersections which belongs to a splat at or after `compact_gid`.
+fn lower_bound(compact_gid: i32, count: u32) -> u32 {
+    var low = 0u;
+    var high = count;
+    while low < high {
+        let mid = (low + high) / 2u;
+        if sorted_compact_gids[mid] < compact_gid {
+            low = mid + 1u;
+        } else {
+            high = mid;
+        }
+    }
+    return low;
+}
+
+// Sums the gradients of the intersections of each splat, in the order of the intersections, so
+// the sums are the same every run.
+@compute
+@workgroup_size(256, 1, 1)
+fn main(@builtin(global_invocation_id) gid: vec3u) {
+    let compact_gid = i32(gid.x);
+
+    if compact_gid >= uniforms.num_visible {
+        return;
+    }
+
+    let num_tiles = uniforms.tile_bounds.x * uniforms.tile_bounds.y;
+    let num_isects = u32(clamp(tile_offsets[num_tiles], 0, i32(uniforms.max_intersects)));
+
+    let start = lower_bound(compact_gid, num_isects);
+    let end = lower_bound(compact_gid + 1, num_isects);
+
+    var grads: array<f32, 11>;
+    for (var i = start; i < end; i++) {
+        let base = u32(sorted_isect_ids[i]) * 11u;
+        for (var c = 0u; c < 11u; c++) {
+            grads[c] += v_isect_grads[base + c];
+        }
+    }
+
+    for (var c = 0; c < 9; c++) {
+        v_splats[compact_gid * 9 + c] = grads[c];
+    }
+    v_refine_grad[compact_gid * 2 + 0] = grads[9];
+    v_refine_grad[compact_gid * 2 + 1] = grads[10];
+}
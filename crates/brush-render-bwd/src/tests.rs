@@ -0,0 +1,103 @@
+// Checks the gradients of renders against finite differences of the forward pass, for each camera
+// model, and when summing gradients in a fixed order.
+use brush_render::{
+    MainBackend,
+    camera::{Camera, CameraModel},
+};
+use burn::{
+    backend::{Autodiff, wgpu::WgpuDevice},
+    tensor::{Tensor, TensorData},
+};
+
+use crate::diff_render::render_splats;
+
+type Diff = Autodiff<MainBackend>;
+
+const IMG_SIZE: glam::UVec2 = glam::uvec2(64, 32);
+
+// The render is weighed by a smooth pattern, so moving the splat any way changes the loss.
+fn weights(device: &WgpuDevice) -> Tensor<Diff, 3> {
+    let [w, h] = IMG_SIZE.to_array().map(|v| v as usize);
+    let values: Vec<f32> = (0..h)
+        .flat_map(|y| (0..w).flat_map(move |x| (0..4).map(move |c| (x, y, c))))
+        .map(|(x, y, c)| (x as f32 * 0.21 + c as f32).sin() * (y as f32 * 0.17 + 0.5).cos())
+        .collect();
+    Tensor::from_data(TensorData::new(values, [h, w, 4]), device)
+}
+
+fn values<const D: usize>(tensor: Tensor<MainBackend, D>) -> Vec<f32> {
+    tensor.into_data().into_vec::<f32>().expect("Wrong type")
+}
+
+// A single splat, off the axes of the camera, so all terms of the projection Jacobian matter.
+fn check_gradients(model: CameraModel, deterministic: bool) {
+    let device = WgpuDevice::DefaultDevice;
+    let camera = Camera::new(
+        glam::Vec3::ZERO,
+        glam::Quat::IDENTITY,
+        1.2,
+        0.8,
+        glam::vec2(0.5, 0.5),
+    )
+    .with_model(model)
+    .with_deterministic(deterministic);
+    let weights = weights(&device);
+
+    let means = [0.3, 0.2, 1.5];
+    let log_scales = [-2.0, -1.7, -1.5];
+    let loss = |means: Tensor<Diff, 2>, log_scales: Tensor<Diff, 2>| {
+        let output = render_splats(
+            &camera,
+            IMG_SIZE,
+            means,
+            log_scales,
+            Tensor::<Diff, 1>::from_floats([0.9, 0.2, 0.3, 0.1], &device).reshape([1, 4]),
+            Tensor::ones([1, 1, 3], &device),
+            Tensor::ones([1], &device) * 0.8,
+        );
+        (output.image * weights.clone()).sum()
+    };
+    let tensor = |values: [f32; 3]| Tensor::<Diff, 1>::from_floats(values, &device).reshape([1, 3]);
+
+    let means_param = tensor(means).require_grad();
+    let log_scales_param = tensor(log_scales).require_grad();
+    let grads = loss(means_param.clone(), log_scales_param.clone()).backward();
+    let analytic = [
+        values(means_param.grad(&grads).expect("Means gradient")),
+        values(log_scales_param.grad(&grads).expect("Scales gradient")),
+    ];
+
+    const EPS: f32 = 2e-3;
+    for (param, analytic) in analytic.iter().enumerate() {
+        for i in 0..3 {
+            let shifted = |offset: f32| {
+                let mut inputs = [means, log_scales];
+                inputs[param][i] += offset;
+                let [means, log_scales] = inputs.map(tensor);
+                values(loss(means, log_scales).inner())[0]
+            };
+            let numeric = (shifted(EPS) - shifted(-EPS)) / (2.0 * EPS);
+            let tolerance = 0.05 * analytic.iter().map(|g| g.abs()).fold(0.0, f32::max) + 1e-2;
+            assert!(
+                (numeric - analytic[i]).abs() < tolerance,
+                "{model:?}, parameter {param}[{i}]: numeric {numeric} analytic {}",
+                analytic[i]
+            );
+        }
+    }
+}
+
+#[test]
+fn pinhole_gradients_match_finite_differences() {
+    check_gradients(CameraModel::Pinhole, false);
+}
+
+#[test]
+fn equirect_gradients_match_finite_differences() {
+    check_gradients(CameraModel::Equirectangular, false);
+}
+
+#[test]
+fn deterministic_gradients_match_finite_differences() {
+    check_gradients(CameraModel::Pinhole, true);
+}
@@ -7,6 +7,9 @@ fn main() -> miette::Result<()> {
             "src/shaders/project_visible.wgsl",
             "src/shaders/map_gaussian_to_intersects.wgsl",
             "src/shaders/rasterize.wgsl",
+            "src/shaders/knn.wgsl",
+            "src/shaders/random_values.wgsl",
+            "src/shaders/wind.wgsl",
         ],
         &["src/shaders/helpers.wgsl"],
         "src/shaders/mod.rs",
@@ -7,7 +7,7 @@ use burn_wgpu::WgpuRuntime;
 use crate::{
     MainBackendBase, SplatForward,
     camera::Camera,
-    render::{calc_tile_bounds, max_intersections, render_forward},
+    render::{RenderInstances, calc_tile_bounds, max_intersections, render_forward},
     render_aux::RenderAux,
     shaders,
 };
@@ -25,7 +25,36 @@ impl SplatForward<Self> for MainBackendBase {
         bwd_info: bool,
     ) -> (FloatTensor<Self>, RenderAux<Self>) {
         render_forward(
-            camera, img_size, means, log_scales, quats, sh_coeffs, opacity, bwd_info,
+            camera, img_size, means, log_scales, quats, sh_coeffs, opacity, bwd_info, None, None,
+        )
+    }
+
+    fn render_splats_instanced(
+        camera: &Camera,
+        img_size: glam::UVec2,
+        means: FloatTensor<Self>,
+        log_scales: FloatTensor<Self>,
+        quats: FloatTensor<Self>,
+        sh_coeffs: FloatTensor<Self>,
+        opacity: FloatTensor<Self>,
+        instance_start: u32,
+        instance_transforms: FloatTensor<Self>,
+        bwd_info: bool,
+    ) -> (FloatTensor<Self>, RenderAux<Self>) {
+        render_forward(
+            camera,
+            img_size,
+            means,
+            log_scales,
+            quats,
+            sh_coeffs,
+            opacity,
+            bwd_info,
+            None,
+            Some(RenderInstances {
+                start: instance_start,
+                transforms: instance_transforms,
+            }),
         )
     }
 }
@@ -41,134 +70,217 @@ impl SplatForward<Self> for Fusion<MainBackendBase> {
         opacity: FloatTensor<Self>,
         bwd_info: bool,
     ) -> (FloatTensor<Self>, RenderAux<Self>) {
-        struct CustomOp {
-            cam: Camera,
-            img_size: glam::UVec2,
-            bwd_info: bool,
-            desc: CustomOpIr,
-        }
+        render_fused(
+            cam, img_size, means, log_scales, quats, sh_coeffs, opacity, None, bwd_info,
+        )
+    }
 
-        impl<BT: BoolElement> Operation<FusionCubeRuntime<WgpuRuntime, BT>> for CustomOp {
-            fn execute(
-                &self,
-                h: &mut HandleContainer<FusionHandle<FusionCubeRuntime<WgpuRuntime, BT>>>,
-            ) {
-                let (inputs, outputs) = self.desc.as_fixed();
-
-                let [means, log_scales, quats, sh_coeffs, opacity] = inputs;
-                let [
-                    projected_splats,
-                    uniforms_buffer,
-                    tile_offsets,
-                    compact_gid_from_isect,
-                    global_from_compact_gid,
-                    out_img,
-                    visible,
-                    final_idx,
-                ] = outputs;
-
-                let (img, aux) = MainBackendBase::render_splats(
+    fn render_splats_instanced(
+        cam: &Camera,
+        img_size: glam::UVec2,
+        means: FloatTensor<Self>,
+        log_scales: FloatTensor<Self>,
+        quats: FloatTensor<Self>,
+        sh_coeffs: FloatTensor<Self>,
+        opacity: FloatTensor<Self>,
+        instance_start: u32,
+        instance_transforms: FloatTensor<Self>,
+        bwd_info: bool,
+    ) -> (FloatTensor<Self>, RenderAux<Self>) {
+        render_fused(
+            cam,
+            img_size,
+            means,
+            log_scales,
+            quats,
+            sh_coeffs,
+            opacity,
+            Some(RenderInstances {
+                start: instance_start,
+                transforms: instance_transforms,
+            }),
+            bwd_info,
+        )
+    }
+}
+
+// Queue a render on the fusion stream of the splats, see `render_forward`.
+fn render_fused(
+    cam: &Camera,
+    img_size: glam::UVec2,
+    means: FloatTensor<Fusion<MainBackendBase>>,
+    log_scales: FloatTensor<Fusion<MainBackendBase>>,
+    quats: FloatTensor<Fusion<MainBackendBase>>,
+    sh_coeffs: FloatTensor<Fusion<MainBackendBase>>,
+    opacity: FloatTensor<Fusion<MainBackendBase>>,
+    instances: Option<RenderInstances<FloatTensor<Fusion<MainBackendBase>>>>,
+    bwd_info: bool,
+) -> (
+    FloatTensor<Fusion<MainBackendBase>>,
+    RenderAux<Fusion<MainBackendBase>>,
+) {
+    struct CustomOp {
+        cam: Camera,
+        img_size: glam::UVec2,
+        bwd_info: bool,
+        instance_start: Option<u32>,
+        desc: CustomOpIr,
+    }
+
+    impl<BT: BoolElement> Operation<FusionCubeRuntime<WgpuRuntime, BT>> for CustomOp {
+        fn execute(
+            &self,
+            h: &mut HandleContainer<FusionHandle<FusionCubeRuntime<WgpuRuntime, BT>>>,
+        ) {
+            let inputs: Vec<_> = self
+                .desc
+                .inputs
+                .iter()
+                .map(|input| h.get_float_tensor::<MainBackendBase>(input))
+                .collect();
+            let mut inputs = inputs.into_iter();
+            let mut next_input = || inputs.next().expect("Missing render input");
+            let [means, log_scales, quats, sh_coeffs, opacity] =
+                std::array::from_fn(|_| next_input());
+            let outputs: [_; 8] = self
+                .desc
+                .outputs
+                .clone()
+                .try_into()
+                .expect("Render has 8 outputs");
+
+            let [
+                projected_splats,
+                uniforms_buffer,
+                tile_offsets,
+                compact_gid_from_isect,
+                global_from_compact_gid,
+                out_img,
+                visible,
+                final_idx,
+            ] = outputs;
+
+            let (img, aux) = match self.instance_start {
+                Some(instance_start) => MainBackendBase::render_splats_instanced(
                     &self.cam,
                     self.img_size,
-                    h.get_float_tensor::<MainBackendBase>(means),
-                    h.get_float_tensor::<MainBackendBase>(log_scales),
-                    h.get_float_tensor::<MainBackendBase>(quats),
-                    h.get_float_tensor::<MainBackendBase>(sh_coeffs),
-                    h.get_float_tensor::<MainBackendBase>(opacity),
+                    means,
+                    log_scales,
+                    quats,
+                    sh_coeffs,
+                    opacity,
+                    instance_start,
+                    next_input(),
                     self.bwd_info,
-                );
-
-                // Register output.
-                h.register_float_tensor::<MainBackendBase>(&out_img.id, img);
-                h.register_float_tensor::<MainBackendBase>(
-                    &projected_splats.id,
-                    aux.projected_splats,
-                );
-                h.register_int_tensor::<MainBackendBase>(&uniforms_buffer.id, aux.uniforms_buffer);
-                h.register_int_tensor::<MainBackendBase>(&tile_offsets.id, aux.tile_offsets);
-                h.register_int_tensor::<MainBackendBase>(
-                    &compact_gid_from_isect.id,
-                    aux.compact_gid_from_isect,
-                );
-                h.register_int_tensor::<MainBackendBase>(
-                    &global_from_compact_gid.id,
-                    aux.global_from_compact_gid,
-                );
-
-                h.register_float_tensor::<MainBackendBase>(&visible.id, aux.visible);
-                h.register_int_tensor::<MainBackendBase>(&final_idx.id, aux.final_idx);
-            }
-        }
+                ),
+                None => MainBackendBase::render_splats(
+                    &self.cam,
+                    self.img_size,
+                    means,
+                    log_scales,
+                    quats,
+                    sh_coeffs,
+                    opacity,
+                    self.bwd_info,
+                ),
+            };
 
-        let stream = means.stream;
-        let client = means.client.clone();
-
-        let num_points = means.shape[0];
-
-        let proj_size = size_of::<shaders::helpers::ProjectedSplat>() / 4;
-        let uniforms_size = size_of::<shaders::helpers::RenderUniforms>() / 4;
-        let tile_bounds = calc_tile_bounds(img_size);
-        let max_intersects = max_intersections(img_size, num_points as u32);
-
-        // If render_u32_buffer is true, we render a packed buffer of u32 values, otherwise
-        // render RGBA f32 values.
-        let channels = if bwd_info { 4 } else { 1 };
-
-        let out_img = client.tensor_uninitialized(
-            vec![img_size.y as usize, img_size.x as usize, channels],
-            if bwd_info { DType::F32 } else { DType::U32 },
-        );
-
-        let final_index_shape = if bwd_info {
-            vec![img_size.y as usize, img_size.x as usize]
-        } else {
-            vec![1, 1]
-        };
-        let visible_shape = if bwd_info { vec![num_points] } else { vec![1] };
-
-        let aux = RenderAux::<Self> {
-            projected_splats: client.tensor_uninitialized(vec![num_points, proj_size], DType::F32),
-            uniforms_buffer: client.tensor_uninitialized(vec![uniforms_size], DType::I32),
-            tile_offsets: client.tensor_uninitialized(
-                vec![(tile_bounds.y * tile_bounds.x) as usize + 1],
-                DType::I32,
-            ),
-            compact_gid_from_isect: client
-                .tensor_uninitialized(vec![max_intersects as usize], DType::I32),
-            global_from_compact_gid: client.tensor_uninitialized(vec![num_points], DType::I32),
-            visible: client.tensor_uninitialized(visible_shape, DType::F32),
-            final_idx: client.tensor_uninitialized(final_index_shape, DType::I32),
-        };
-
-        let desc = CustomOpIr::new(
-            "render_splats",
-            &[
-                means.into_ir(),
-                log_scales.into_ir(),
-                quats.into_ir(),
-                sh_coeffs.into_ir(),
-                opacity.into_ir(),
-            ],
-            &[
-                aux.projected_splats.to_ir_out(),
-                aux.uniforms_buffer.to_ir_out(),
-                aux.tile_offsets.to_ir_out(),
-                aux.compact_gid_from_isect.to_ir_out(),
-                aux.global_from_compact_gid.to_ir_out(),
-                out_img.to_ir_out(),
-                aux.visible.to_ir_out(),
-                aux.final_idx.to_ir_out(),
-            ],
-        );
-
-        let op = CustomOp {
-            cam: cam.clone(),
-            img_size,
-            bwd_info,
-            desc: desc.clone(),
-        };
+            // Register output.
+            h.register_float_tensor::<MainBackendBase>(&out_img.id, img);
+            h.register_float_tensor::<MainBackendBase>(&projected_splats.id, aux.projected_splats);
+            h.register_int_tensor::<MainBackendBase>(&uniforms_buffer.id, aux.uniforms_buffer);
+            h.register_int_tensor::<MainBackendBase>(&tile_offsets.id, aux.tile_offsets);
+            h.register_int_tensor::<MainBackendBase>(
+                &compact_gid_from_isect.id,
+                aux.compact_gid_from_isect,
+            );
+            h.register_int_tensor::<MainBackendBase>(
+                &global_from_compact_gid.id,
+                aux.global_from_compact_gid,
+            );
 
-        client.register(vec![stream], OperationIr::Custom(desc), op);
-        (out_img, aux)
+            h.register_float_tensor::<MainBackendBase>(&visible.id, aux.visible);
+            h.register_int_tensor::<MainBackendBase>(&final_idx.id, aux.final_idx);
+        }
     }
+
+    let stream = means.stream;
+    let client = means.client.clone();
+
+    // Copies of a prop are drawn as splats of their own.
+    let num_points = match &instances {
+        Some(instances) => instances.total_splats(means.shape[0], instances.transforms.shape[0]),
+        None => means.shape[0],
+    };
+
+    let proj_size = size_of::<shaders::helpers::ProjectedSplat>() / 4;
+    let uniforms_size = size_of::<shaders::helpers::RenderUniforms>() / 4;
+    let tile_bounds = calc_tile_bounds(img_size);
+    let max_intersects = max_intersections(img_size, num_points as u32);
+
+    // If render_u32_buffer is true, we render a packed buffer of u32 values, otherwise
+    // render RGBA f32 values.
+    let channels = if bwd_info { 4 } else { 1 };
+
+    let out_img = client.tensor_uninitialized(
+        vec![img_size.y as usize, img_size.x as usize, channels],
+        if bwd_info { DType::F32 } else { DType::U32 },
+    );
+
+    let final_index_shape = if bwd_info {
+        vec![img_size.y as usize, img_size.x as usize]
+    } else {
+        vec![1, 1]
+    };
+    let visible_shape = if bwd_info { vec![num_points] } else { vec![1] };
+
+    let aux = RenderAux::<Fusion<MainBackendBase>> {
+        projected_splats: client.tensor_uninitialized(vec![num_points, proj_size], DType::F32),
+        uniforms_buffer: client.tensor_uninitialized(vec![uniforms_size], DType::I32),
+        tile_offsets: client.tensor_uninitialized(
+            vec![(tile_bounds.y * tile_bounds.x) as usize + 1],
+            DType::I32,
+        ),
+        compact_gid_from_isect: client
+            .tensor_uninitialized(vec![max_intersects as usize], DType::I32),
+        global_from_compact_gid: client.tensor_uninitialized(vec![num_points], DType::I32),
+        visible: client.tensor_uninitialized(visible_shape, DType::F32),
+        final_idx: client.tensor_uninitialized(final_index_shape, DType::I32),
+    };
+
+    let instance_start = instances.as_ref().map(|instances| instances.start);
+    let mut inputs = vec![
+        means.into_ir(),
+        log_scales.into_ir(),
+        quats.into_ir(),
+        sh_coeffs.into_ir(),
+        opacity.into_ir(),
+    ];
+    inputs.extend(instances.map(|instances| instances.transforms.into_ir()));
+
+    let desc = CustomOpIr::new(
+        "render_splats",
+        &inputs,
+        &[
+            aux.projected_splats.to_ir_out(),
+            aux.uniforms_buffer.to_ir_out(),
+            aux.tile_offsets.to_ir_out(),
+            aux.compact_gid_from_isect.to_ir_out(),
+            aux.global_from_compact_gid.to_ir_out(),
+            out_img.to_ir_out(),
+            aux.visible.to_ir_out(),
+            aux.final_idx.to_ir_out(),
+        ],
+    );
+
+    let op = CustomOp {
+        cam: cam.clone(),
+        img_size,
+        bwd_info,
+        instance_start,
+        desc: desc.clone(),
+    };
+
+    client.register(vec![stream], OperationIr::Custom(desc), op);
+    (out_img, aux)
 }
@@ -1,4 +1,45 @@
-use glam::Affine3A;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use burn::{prelude::Backend, tensor::Tensor};
+use glam::{Affine3A, Mat3};
+
+/// How a camera maps directions to pixels.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CameraModel {
+    /// A pinhole camera, seeing its field of view.
+    #[default]
+    Pinhole,
+    /// A 360° panorama, as captured by 360 cameras. The longitude around the y axis maps linearly
+    /// to x, with the image center looking down +z, and the latitude maps linearly to y, with the
+    /// poles at the top and bottom edges. The field of view is ignored.
+    ///
+    /// Splats crossing the seam behind the camera are only drawn on one side of the image.
+    Equirectangular,
+}
+
+/// Which splats renders from a camera leave out, on top of those outside the view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Culling {
+    /// Splats closer to the camera than this are left out. Splats right in front of the camera
+    /// cover huge parts of the image, which makes for slow renders and unstable training.
+    pub near: f32,
+    /// Splats further from the camera than this are left out.
+    pub far: f32,
+    /// Splats with a projected radius below this many pixels are left out, which speeds up
+    /// renders of big scenes from afar. Tiny splats still add up to a lot of the image when there
+    /// are many of them, so this changes how renders look when set too high.
+    pub min_radius: f32,
+}
+
+impl Default for Culling {
+    fn default() -> Self {
+        Self {
+            near: 0.01,
+            far: 1e10,
+            min_radius: 0.0,
+        }
+    }
+}
 
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct Camera {
@@ -7,6 +48,17 @@ pub struct Camera {
     pub center_uv: glam::Vec2,
     pub position: glam::Vec3,
     pub rotation: glam::Quat,
+    /// Subpixel offset of the projection, in pixels. This shifts the whole image without
+    /// changing the pose, eg. to accumulate jittered renders for antialiasing.
+    pub jitter: glam::Vec2,
+    /// Seed of the random numbers kernels draw for stochastic effects, see
+    /// [`crate::random::random_f32`]. Renders with the same seed draw the same numbers.
+    pub seed: u32,
+    pub model: CameraModel,
+    pub culling: Culling,
+    /// Sum the gradients of differentiable renders from this camera in a fixed order, so they're
+    /// the same every run. This is slower, and needs more memory, than summing them atomically.
+    pub deterministic: bool,
 }
 
 impl Camera {
@@ -23,21 +75,81 @@ impl Camera {
             center_uv,
             position,
             rotation,
+            jitter: glam::Vec2::ZERO,
+            seed: 0,
+            model: CameraModel::Pinhole,
+            culling: Culling::default(),
+            deterministic: false,
         }
     }
 
+    /// Map directions to pixels with `model`, see [`CameraModel`].
+    pub fn with_model(mut self, model: CameraModel) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Offset the projection by a subpixel amount, see [`jitter_offset`].
+    pub fn with_jitter(mut self, jitter: glam::Vec2) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Draw the random numbers of renders from this camera with `seed`, see [`Self::seed`].
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Leave splats out of renders from this camera following `culling`, see [`Culling`].
+    pub fn with_culling(mut self, culling: Culling) -> Self {
+        self.culling = culling;
+        self
+    }
+
+    /// Sum the gradients of renders from this camera in a fixed order, see
+    /// [`Self::deterministic`].
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Focal length in pixels. For panoramas, these are the pixels per radian of longitude and
+    /// latitude.
     pub fn focal(&self, img_size: glam::UVec2) -> glam::Vec2 {
+        if self.model == CameraModel::Equirectangular {
+            return glam::vec2(img_size.x as f32 / TAU, img_size.y as f32 / PI);
+        }
         glam::vec2(
             fov_to_focal(self.fov_x, img_size.x) as f32,
             fov_to_focal(self.fov_y, img_size.y) as f32,
         )
     }
 
+    /// Principal point in pixels, including the jitter offset.
     pub fn center(&self, img_size: glam::UVec2) -> glam::Vec2 {
         glam::vec2(
             self.center_uv.x * img_size.x as f32,
             self.center_uv.y * img_size.y as f32,
-        )
+        ) + self.jitter
+    }
+
+    /// The camera seeing only the part of the image between the normalized image coordinates
+    /// `min` and `max`, eg. to render a crop of a view at the size of the crop.
+    ///
+    /// Panoramas can't be cropped to a pinhole view, and are returned as they are.
+    pub fn crop(&self, min: glam::Vec2, max: glam::Vec2) -> Self {
+        if self.model == CameraModel::Equirectangular {
+            return self.clone();
+        }
+        let extent = max - min;
+        let crop_fov = |fov: f64, extent: f32| 2.0 * ((fov * 0.5).tan() * f64::from(extent)).atan();
+        Self {
+            fov_x: crop_fov(self.fov_x, extent.x),
+            fov_y: crop_fov(self.fov_y, extent.y),
+            center_uv: (self.center_uv - min) / extent,
+            ..self.clone()
+        }
     }
 
     pub fn local_to_world(&self) -> Affine3A {
@@ -47,7 +159,139 @@ impl Camera {
     pub fn world_to_local(&self) -> Affine3A {
         self.local_to_world().inverse()
     }
+
+    /// Project a world space point to normalized image coordinates ([0, 1] inside the image).
+    ///
+    /// Returns None if the point is behind the camera. This ignores the jitter offset.
+    pub fn world_to_uv(&self, point: glam::Vec3) -> Option<glam::Vec2> {
+        let local = self.world_to_local().transform_point3(point);
+        if self.model == CameraModel::Equirectangular {
+            // Panoramas see in all directions, but not the point they're at.
+            if local.length_squared() <= 0.0 {
+                return None;
+            }
+            let lon = local.x.atan2(local.z);
+            let lat = local.y.atan2(glam::vec2(local.x, local.z).length());
+            return Some(self.center_uv + glam::vec2(lon / TAU, lat / PI));
+        }
+        if local.z <= 0.0 {
+            return None;
+        }
+        let tan_half = glam::vec2(
+            (self.fov_x * 0.5).tan() as f32,
+            (self.fov_y * 0.5).tan() as f32,
+        );
+        Some(self.center_uv + local.truncate() / local.z / (2.0 * tan_half))
+    }
+
+    /// Get the world space point at the given normalized image coordinates, at some depth along the view axis.
+    ///
+    /// For panoramas, the depth is the distance from the camera.
+    pub fn uv_to_world(&self, uv: glam::Vec2, depth: f32) -> glam::Vec3 {
+        if self.model == CameraModel::Equirectangular {
+            let lon = (uv.x - self.center_uv.x) * TAU;
+            let lat = ((uv.y - self.center_uv.y) * PI).clamp(-FRAC_PI_2, FRAC_PI_2);
+            let dir = glam::vec3(lat.cos() * lon.sin(), lat.sin(), lat.cos() * lon.cos());
+            return self.local_to_world().transform_point3(dir * depth);
+        }
+        let tan_half = glam::vec2(
+            (self.fov_x * 0.5).tan() as f32,
+            (self.fov_y * 0.5).tan() as f32,
+        );
+        let xy = (uv - self.center_uv) * 2.0 * tan_half;
+        self.local_to_world()
+            .transform_point3(glam::vec3(xy.x, xy.y, 1.0) * depth)
+    }
 }
+
+// Elementwise atan2, which tensors don't have, to within 1e-5 radians.
+fn atan2<B: Backend>(y: Tensor<B, 1>, x: Tensor<B, 1>) -> Tensor<B, 1> {
+    let (ax, ay) = (x.clone().abs(), y.clone().abs());
+    let steep = ay.clone().greater(ax.clone());
+    let t = ax.clone().min_pair(ay.clone()) / ax.max_pair(ay).clamp_min(1e-30);
+    let t2 = t.clone().powi_scalar(2);
+    // Polynomial fit of atan on [0, 1].
+    let poly = [
+        0.057_477_314,
+        -0.121_239_07,
+        0.195_635_93,
+        -0.332_994_6,
+        0.999_995_6,
+    ]
+    .into_iter()
+    .fold(t2.ones_like() * -0.013_480_47, |acc, c| {
+        acc * t2.clone() + c
+    });
+    let angle = poly * t;
+    let angle = angle.clone().mask_where(steep, angle.neg() + FRAC_PI_2);
+    let angle = angle
+        .clone()
+        .mask_where(x.lower_elem(0.0), angle.neg() + PI);
+    angle.clone().mask_where(y.lower_elem(0.0), angle.neg())
+}
+
+/// Project [N, 3] world space points to pixels like the renderer does, and return their [N] depth
+/// and pixel coordinates. Depths are along the view axis, or the distance for panoramas.
+///
+/// Points behind a pinhole camera get a depth of at most zero, and meaningless pixels.
+pub fn project_points<B: Backend>(
+    camera: &Camera,
+    img_size: glam::UVec2,
+    points: Tensor<B, 2>,
+) -> (Tensor<B, 1>, Tensor<B, 1>, Tensor<B, 1>) {
+    let device = points.device();
+    let n = points.dims()[0];
+    let world_to_local = camera.world_to_local();
+    // Points are row vectors, so multiply by the transposed rotation, which is the column major
+    // layout of glam read as row major.
+    let rot = Mat3::from(world_to_local.matrix3).to_cols_array();
+    let rot = Tensor::<B, 1>::from_floats(rot, &device).reshape([3, 3]);
+    let translation =
+        Tensor::<B, 1>::from_floats(world_to_local.translation.to_array(), &device).reshape([1, 3]);
+    let local = points.matmul(rot) + translation;
+    let axis = |i: usize| local.clone().slice([0..n, i..i + 1]).reshape([n]);
+    let [x, y, z] = [0, 1, 2].map(axis);
+
+    let focal = camera.focal(img_size);
+    let center = camera.center(img_size);
+    match camera.model {
+        CameraModel::Pinhole => {
+            let inv_z = z.clone().clamp_min(1e-6).recip();
+            let px = x * inv_z.clone() * focal.x + center.x;
+            let py = y * inv_z * focal.y + center.y;
+            (z, px, py)
+        }
+        CameraModel::Equirectangular => {
+            let rho2 = x.clone().powi_scalar(2) + z.clone().powi_scalar(2);
+            let dist = (rho2.clone() + y.clone().powi_scalar(2)).sqrt();
+            let px = atan2(x, z) * focal.x + center.x;
+            let py = atan2(y, rho2.sqrt()) * focal.y + center.y;
+            (dist, px, py)
+        }
+    }
+}
+
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut f = 1.0;
+    while index > 0 {
+        f /= base as f32;
+        result += f * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Subpixel jitter offset for the nth sample of a low discrepancy (Halton 2, 3) sequence, in [-0.5, 0.5] pixels.
+///
+/// Cycling through these offsets with [`Camera::with_jitter`] covers the pixel footprint evenly, which is
+/// what's needed for temporal antialiasing or supersampling while training.
+pub fn jitter_offset(index: u32) -> glam::Vec2 {
+    // Skip the first sample, which is always at the pixel corner.
+    let index = index + 1;
+    glam::vec2(halton(index, 2), halton(index, 3)) - 0.5
+}
+
 // Converts field of view to focal length
 pub fn fov_to_focal(fov_rad: f64, pixels: u32) -> f64 {
     0.5 * (pixels as f64) / (fov_rad * 0.5).tan()
@@ -57,3 +301,134 @@ pub fn fov_to_focal(fov_rad: f64, pixels: u32) -> f64 {
 pub fn focal_to_fov(focal: f64, pixels: u32) -> f64 {
     2.0 * f64::atan((pixels as f64) / (2.0 * focal))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crop_sees_the_same_points() {
+        let camera = Camera::new(
+            glam::vec3(0.5, -1.0, 2.0),
+            glam::Quat::from_rotation_x(0.2),
+            1.2,
+            0.9,
+            glam::vec2(0.45, 0.55),
+        );
+        let (min, max) = (glam::vec2(0.2, 0.5), glam::vec2(0.6, 0.75));
+        let crop = camera.crop(min, max);
+        for uv in [glam::vec2(0.3, 0.6), glam::vec2(0.55, 0.7)] {
+            let point = camera.uv_to_world(uv, 3.0);
+            let cropped = crop.world_to_uv(point).expect("Point is in front");
+            let expected = (uv - min) / (max - min);
+            assert!(cropped.distance(expected) < 1e-5, "{cropped} != {expected}");
+        }
+    }
+
+    #[test]
+    fn jitter_covers_the_pixel() {
+        let offsets: Vec<_> = (0..64).map(jitter_offset).collect();
+        for (i, offset) in offsets.iter().enumerate() {
+            assert!(offset.abs().max_element() <= 0.5, "{offset}");
+            assert!(offsets[..i].iter().all(|o| o.distance(*offset) > 1e-4));
+        }
+        let mean = offsets.iter().sum::<glam::Vec2>() / offsets.len() as f32;
+        assert!(mean.length() < 0.05, "{mean}");
+        // Each quadrant of the pixel gets a fair share of the samples.
+        for quadrant in [
+            glam::vec2(1.0, 1.0),
+            glam::vec2(-1.0, 1.0),
+            glam::vec2(1.0, -1.0),
+            glam::vec2(-1.0, -1.0),
+        ] {
+            let count = offsets
+                .iter()
+                .filter(|o| (**o * quadrant).min_element() > 0.0)
+                .count();
+            assert!((12..=20).contains(&count), "{quadrant}: {count}");
+        }
+
+        let camera = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            1.0,
+            1.0,
+            glam::vec2(0.5, 0.5),
+        );
+        let size = glam::uvec2(64, 32);
+        let jittered = camera.clone().with_jitter(offsets[3]);
+        assert_eq!(jittered.center(size), camera.center(size) + offsets[3]);
+    }
+
+    #[test]
+    fn panorama_round_trip() {
+        let camera = Camera::new(
+            glam::vec3(1.0, 2.0, 3.0),
+            glam::Quat::from_rotation_y(0.3),
+            1.0,
+            1.0,
+            glam::vec2(0.5, 0.5),
+        )
+        .with_model(CameraModel::Equirectangular);
+
+        // Points behind and above the camera are seen too.
+        for uv in [
+            glam::vec2(0.5, 0.5),
+            glam::vec2(0.95, 0.1),
+            glam::vec2(0.1, 0.8),
+        ] {
+            let point = camera.uv_to_world(uv, 2.0);
+            assert!((point.distance(camera.position) - 2.0).abs() < 1e-5);
+            let back = camera
+                .world_to_uv(point)
+                .expect("Panoramas see every point");
+            assert!(back.distance(uv) < 1e-5, "{back} != {uv}");
+        }
+        assert_eq!(
+            camera.focal(glam::uvec2(200, 100)),
+            glam::vec2(200.0 / TAU, 100.0 / PI)
+        );
+    }
+
+    #[test]
+    fn projects_points_like_world_to_uv() {
+        let device = burn_wgpu::WgpuDevice::DefaultDevice;
+        let img_size = glam::uvec2(200, 100);
+        let pinhole = Camera::new(
+            glam::vec3(0.5, -1.0, 2.0),
+            glam::Quat::from_rotation_x(0.2),
+            1.2,
+            0.9,
+            glam::vec2(0.45, 0.55),
+        );
+        let panorama = pinhole.clone().with_model(CameraModel::Equirectangular);
+        let uvs = [
+            glam::vec2(0.3, 0.6),
+            glam::vec2(0.55, 0.7),
+            glam::vec2(0.05, 0.2),
+            glam::vec2(0.9, 0.95),
+        ];
+
+        for camera in [pinhole, panorama] {
+            let points: Vec<_> = uvs.map(|uv| camera.uv_to_world(uv, 3.0)).to_vec();
+            let flat: Vec<f32> = points.iter().flat_map(|p| p.to_array()).collect();
+            let points_tensor = Tensor::<burn_wgpu::Wgpu, 1>::from_floats(flat.as_slice(), &device)
+                .reshape([uvs.len(), 3]);
+            let (depth, px, py) = project_points(&camera, img_size, points_tensor);
+            let values = |t: Tensor<burn_wgpu::Wgpu, 1>| {
+                t.into_data().into_vec::<f32>().expect("Wrong type")
+            };
+            let (depth, px, py) = (values(depth), values(px), values(py));
+            for (i, uv) in uvs.iter().enumerate() {
+                let expected = *uv * img_size.as_vec2();
+                let pixel = glam::vec2(px[i], py[i]);
+                assert!(
+                    pixel.distance(expected) < 1e-2,
+                    "{:?}: {pixel} != {expected}",
+                    camera.model
+                );
+                assert!((depth[i] - 3.0).abs() < 1e-4);
+            }
+        }
+    }
+}
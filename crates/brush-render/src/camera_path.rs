@@ -0,0 +1,833 @@
+use crate::{
+    SplatForward,
+    camera::{Camera, Culling, focal_to_fov, fov_to_focal},
+    gaussian_splats::Splats,
+    instancing::InstancedSplats,
+    sh::channel_to_sh,
+};
+use burn::{
+    prelude::Backend,
+    tensor::{Int, Tensor, TensorData, TensorPrimitive, s},
+};
+use glam::{Mat3, Quat, Vec3};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Near plane the renderer always clips at.
+pub const DEFAULT_NEAR: f32 = 0.01;
+/// Far plane the renderer always clips at.
+pub const DEFAULT_FAR: f32 = 1e10;
+
+fn default_near() -> f32 {
+    DEFAULT_NEAR
+}
+
+fn default_far() -> f32 {
+    DEFAULT_FAR
+}
+
+/// A keyframe of a camera path.
+///
+/// Rotations are stored as a quaternion, as [x, y, z, w] when serialized.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraKeyframe {
+    /// Time of this keyframe in seconds.
+    pub time: f32,
+    pub position: Vec3,
+    pub rotation: Quat,
+    /// Vertical field of view in radians. The horizontal field of view follows from the aspect ratio.
+    pub fov_y: f64,
+    /// Exposure in stops, 0 leaves the image as is.
+    #[serde(default)]
+    pub exposure: f32,
+    #[serde(default = "default_near")]
+    pub near: f32,
+    #[serde(default = "default_far")]
+    pub far: f32,
+    /// How much to slow down when arriving at this keyframe, from 0 (not at all) to 1 (come to a stop).
+    #[serde(default)]
+    pub ease_in: f32,
+    /// How much to slow down when leaving this keyframe, from 0 (not at all) to 1 (start from a stop).
+    #[serde(default)]
+    pub ease_out: f32,
+}
+
+impl CameraKeyframe {
+    pub fn from_camera(time: f32, camera: &Camera) -> Self {
+        Self {
+            time,
+            position: camera.position,
+            rotation: camera.rotation,
+            fov_y: camera.fov_y,
+            exposure: 0.0,
+            near: DEFAULT_NEAR,
+            far: DEFAULT_FAR,
+            ease_in: 0.0,
+            ease_out: 0.0,
+        }
+    }
+}
+
+/// The state of a camera path at some point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraPathSample {
+    pub camera: Camera,
+    pub exposure: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl CameraPathSample {
+    /// Render splats as seen at this point of the path.
+    ///
+    /// Splats outside of the near and far plane are hidden, and the exposure is applied to the colors.
+    /// Returns a [H, W, 4] float image.
+    ///
+    /// NB: This doesn't work on a differentiable backend.
+    pub fn render<B: Backend + SplatForward<B>>(
+        &self,
+        splats: &Splats<B>,
+        img_size: glam::UVec2,
+    ) -> Tensor<B, 3> {
+        let img = self.render_with(
+            splats,
+            splats.sh_coeffs.val(),
+            self.clipped_opacities(splats),
+            img_size,
+        );
+        self.expose(img)
+    }
+
+    /// Render a scene with copies of a prop as seen at this point of the path, like [`Self::render`].
+    pub fn render_instanced<B: Backend + SplatForward<B>>(
+        &self,
+        splats: &InstancedSplats<B>,
+        img_size: glam::UVec2,
+    ) -> Tensor<B, 3> {
+        // The copies only exist in the renderer, so clip them there instead of by opacity.
+        let camera = self.camera.clone().with_culling(Culling {
+            near: self.near,
+            far: self.far,
+            ..self.camera.culling
+        });
+        let (img, _) = splats.render(&camera, img_size, true);
+        self.expose(img)
+    }
+
+    /// Render motion vectors from this sample to the cameras of the previous and next frame.
+    ///
+    /// Returns a [H, W, 4] image. The first two channels hold the offset in pixels to where each
+    /// pixel was in the previous frame, the last two to where it goes in the next frame. Motion
+    /// follows the average position of the splats in a pixel, assuming the splats don't move.
+    /// Empty pixels, and pixels ending up behind a camera, have no motion.
+    ///
+    /// NB: This doesn't work on a differentiable backend.
+    pub fn render_motion<B: Backend + SplatForward<B>>(
+        &self,
+        splats: &Splats<B>,
+        img_size: glam::UVec2,
+        prev: &Camera,
+        next: &Camera,
+    ) -> Tensor<B, 3> {
+        let device = splats.device();
+        let n = splats.num_splats() as usize;
+        let [w, h] = [img_size.x as usize, img_size.y as usize];
+
+        // Render the world positions as colors. Colors are clamped to be positive, so render
+        // them relative to the minimum.
+        let means = splats.means.val();
+        let origin = means.clone().min_dim(0);
+        let sh_scale = channel_to_sh(1.0) - channel_to_sh(0.0);
+        let position_sh =
+            ((means - origin.clone()) * sh_scale + channel_to_sh(0.0)).reshape([n, 1, 3]);
+        let img = self.render_with(
+            splats,
+            position_sh,
+            self.clipped_opacities(splats),
+            img_size,
+        );
+
+        let alpha = img.clone().slice(s![.., .., 3..4]).reshape([h * w, 1]);
+        // Colors are premultiplied by alpha.
+        let positions = img.slice(s![.., .., 0..3]).reshape([h * w, 3])
+            / alpha.clone().clamp_min(1e-6)
+            + origin;
+
+        let xs = Tensor::<B, 1, Int>::arange(0..w as i64, &device)
+            .float()
+            .reshape([1, w])
+            .repeat_dim(0, h);
+        let ys = Tensor::<B, 1, Int>::arange(0..h as i64, &device)
+            .float()
+            .reshape([h, 1])
+            .repeat_dim(1, w);
+        let pixels = Tensor::stack::<3>(vec![xs, ys], 2).reshape([h * w, 2]) + 0.5;
+
+        let covered = alpha.greater_elem(1e-3).float();
+        let motion = |camera: &Camera| {
+            let (projected, in_front) = project_points(positions.clone(), camera, img_size);
+            (projected - pixels.clone()) * (in_front * covered.clone())
+        };
+        Tensor::cat(vec![motion(prev), motion(next)], 1).reshape([h, w, 4])
+    }
+
+    // Opacities, with splats outside of the near and far plane hidden.
+    fn clipped_opacities<B: Backend>(&self, splats: &Splats<B>) -> Tensor<B, 1> {
+        let depth = self.depths(splats);
+        splats
+            .opacities()
+            .mask_fill(depth.clone().lower_elem(self.near), 0.0)
+            .mask_fill(depth.greater_elem(self.far), 0.0)
+    }
+
+    // Distance of each splat along the view direction.
+    fn depths<B: Backend>(&self, splats: &Splats<B>) -> Tensor<B, 1> {
+        let device = splats.device();
+        let n = splats.num_splats() as usize;
+
+        let forward = self.camera.rotation * Vec3::Z;
+        let forward = Tensor::<B, 1>::from_floats(forward.to_array(), &device).reshape([3, 1]);
+        let position =
+            Tensor::<B, 1>::from_floats(self.camera.position.to_array(), &device).reshape([1, 3]);
+        (splats.means.val() - position).matmul(forward).reshape([n])
+    }
+
+    fn render_with<B: Backend + SplatForward<B>>(
+        &self,
+        splats: &Splats<B>,
+        sh_coeffs: Tensor<B, 3>,
+        opacities: Tensor<B, 1>,
+        img_size: glam::UVec2,
+    ) -> Tensor<B, 3> {
+        let (img, _) = B::render_splats(
+            &self.camera,
+            img_size,
+            splats.means.val().into_primitive().tensor(),
+            splats.log_scales.val().into_primitive().tensor(),
+            splats.rotation.val().into_primitive().tensor(),
+            sh_coeffs.into_primitive().tensor(),
+            opacities.into_primitive().tensor(),
+            true,
+        );
+        Tensor::from_primitive(TensorPrimitive::Float(img))
+    }
+
+    fn expose<B: Backend>(&self, img: Tensor<B, 3>) -> Tensor<B, 3> {
+        if self.exposure == 0.0 {
+            return img;
+        }
+
+        let rgb = img.clone().slice(s![.., .., 0..3]) * 2.0f32.powf(self.exposure);
+        let alpha = img.slice(s![.., .., 3..4]);
+        Tensor::cat(vec![rgb, alpha], 2)
+    }
+}
+
+// Project world space points [N, 3] to pixel coordinates [N, 2] of a view. Also returns [N, 1]
+// with 1 for points in front of the camera, and 0 otherwise.
+fn project_points<B: Backend>(
+    points: Tensor<B, 2>,
+    camera: &Camera,
+    img_size: glam::UVec2,
+) -> (Tensor<B, 2>, Tensor<B, 2>) {
+    let device = points.device();
+    let n = points.dims()[0];
+
+    let world_to_local = camera.world_to_local();
+    // Points are row vectors, so multiply by the transpose. The column major layout of glam is
+    // exactly the row major layout of the transpose.
+    let rot_t = Tensor::<B, 2>::from_data(
+        TensorData::new(world_to_local.matrix3.to_cols_array().to_vec(), [3, 3]),
+        &device,
+    );
+    let translation =
+        Tensor::<B, 1>::from_floats(world_to_local.translation.to_array(), &device).reshape([1, 3]);
+    let local = points.matmul(rot_t) + translation;
+
+    let depth = local.clone().slice([0..n, 2..3]);
+    let in_front = depth.clone().greater_elem(0.01).float();
+    let focal =
+        Tensor::<B, 1>::from_floats(camera.focal(img_size).to_array(), &device).reshape([1, 2]);
+    let center =
+        Tensor::<B, 1>::from_floats(camera.center(img_size).to_array(), &device).reshape([1, 2]);
+    let projected = local.slice([0..n, 0..2]) / depth.clamp_min(0.01) * focal + center;
+    (projected, in_front)
+}
+
+/// Keeps the near and far plane culling of a camera path consistent between frames of a video.
+///
+/// Culling each frame on its own makes splats pop in and out as they cross a plane, and flicker
+/// when they hover around it. Instead, a visible splat stays visible until it's `margin` (relative
+/// to the plane distance) past a plane, and the opacity of a splat fades by at most
+/// `1 / fade_frames` per frame.
+pub struct FrameCoherence<B: Backend> {
+    fade_frames: u32,
+    margin: f32,
+    // Opacity multiplier of each splat in the previous frame.
+    visibility: Option<Tensor<B, 1>>,
+}
+
+impl<B: Backend + SplatForward<B>> FrameCoherence<B> {
+    pub fn new(fade_frames: u32, margin: f32) -> Self {
+        Self {
+            fade_frames: fade_frames.max(1),
+            margin: margin.max(0.0),
+            visibility: None,
+        }
+    }
+
+    /// Render the next frame, like [`CameraPathSample::render`].
+    ///
+    /// Frames have to be rendered in order, with the same splats.
+    pub fn render(
+        &mut self,
+        sample: &CameraPathSample,
+        splats: &Splats<B>,
+        img_size: glam::UVec2,
+    ) -> Tensor<B, 3> {
+        let opacities = splats.opacities() * self.update(sample, splats);
+        let img = sample.render_with(splats, splats.sh_coeffs.val(), opacities, img_size);
+        sample.expose(img)
+    }
+
+    /// Render the next frame with motion blur, like [`render_shutter()`].
+    ///
+    /// Which splats are visible is decided once for the frame `sample`, and kept for all the
+    /// `shutter` samples.
+    pub fn render_shutter(
+        &mut self,
+        sample: &CameraPathSample,
+        shutter: &[CameraPathSample],
+        splats: &Splats<B>,
+        img_size: glam::UVec2,
+    ) -> Tensor<B, 3> {
+        let opacities = splats.opacities() * self.update(sample, splats);
+        // Without any samples the shutter is just the frame itself.
+        let shutter = if shutter.is_empty() {
+            std::slice::from_ref(sample)
+        } else {
+            shutter
+        };
+        average(shutter.iter().map(|sub| {
+            let img = sub.render_with(splats, splats.sh_coeffs.val(), opacities.clone(), img_size);
+            sub.expose(img)
+        }))
+        .expect("Shutter has samples")
+    }
+
+    // Step the visibility of each splat to the next frame.
+    fn update(&mut self, sample: &CameraPathSample, splats: &Splats<B>) -> Tensor<B, 1> {
+        let depth = sample.depths(splats);
+        let in_range = |near: f32, far: f32| {
+            depth.clone().greater_equal_elem(near).float()
+                * depth.clone().lower_equal_elem(far).float()
+        };
+        let inside = in_range(sample.near, sample.far);
+
+        let visibility = match self.visibility.take() {
+            // The first frame has nothing to be consistent with.
+            None => inside,
+            Some(prev) => {
+                let slack = 1.0 + self.margin;
+                let was_visible = prev.clone().greater_elem(0.0).float();
+                let target = inside
+                    .max_pair(in_range(sample.near / slack, sample.far * slack) * was_visible);
+                let step = 1.0 / self.fade_frames as f32;
+                prev.clone() + (target - prev).clamp(-step, step)
+            }
+        };
+
+        self.visibility = Some(visibility.clone());
+        visibility
+    }
+}
+
+fn average<B: Backend>(imgs: impl Iterator<Item = Tensor<B, 3>>) -> Option<Tensor<B, 3>> {
+    let (sum, count) = imgs.fold((None, 0), |(sum, count), img| {
+        let sum = match sum {
+            Some(sum) => sum + img,
+            None => img,
+        };
+        (Some(sum), count + 1)
+    });
+    sum.map(|sum| sum / count as f32)
+}
+
+/// Render splats with motion blur, by averaging renders at the `shutter` samples of a frame, as
+/// given by [`CameraPath::sample_shutter`].
+///
+/// NB: This doesn't work on a differentiable backend.
+pub fn render_shutter<B: Backend + SplatForward<B>>(
+    shutter: &[CameraPathSample],
+    splats: &Splats<B>,
+    img_size: glam::UVec2,
+) -> Option<Tensor<B, 3>> {
+    average(shutter.iter().map(|sub| sub.render(splats, img_size)))
+}
+
+/// Render a scene with copies of a prop with motion blur, like [`render_shutter`].
+pub fn render_shutter_instanced<B: Backend + SplatForward<B>>(
+    shutter: &[CameraPathSample],
+    splats: &InstancedSplats<B>,
+    img_size: glam::UVec2,
+) -> Option<Tensor<B, 3>> {
+    average(
+        shutter
+            .iter()
+            .map(|sub| sub.render_instanced(splats, img_size)),
+    )
+}
+
+/// A camera path, interpolating between keyframes.
+///
+/// Positions follow a Catmull-Rom spline through the keyframes, rotations are interpolated with
+/// a slerp. The field of view is interpolated in focal length, so a dolly zoom between two keyframes
+/// keeps the subject at a constant size. Near and far planes are interpolated in log space.
+///
+/// Paths are serialized as `{ "keyframes": [...], "events": [...] }`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CameraPath {
+    keyframes: Vec<CameraKeyframe>,
+    events: Vec<PathEvent>,
+}
+
+impl<'de> Deserialize<'de> for CameraPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Keyframes {
+            keyframes: Vec<CameraKeyframe>,
+            // Paths from before events existed have none.
+            #[serde(default)]
+            events: Vec<PathEvent>,
+        }
+        // Keyframes in a file might not be sorted.
+        let data = Keyframes::deserialize(deserializer)?;
+        let mut path = Self::new(data.keyframes);
+        for event in data.events {
+            path.add_event(event);
+        }
+        Ok(path)
+    }
+}
+
+/// A named point in time of a camera path, for playback to trigger things on, like a slide change or
+/// a beat of music.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PathEvent {
+    pub time: f32,
+    pub name: String,
+}
+
+/// Plays a camera path along with an external clock, like the time of a song.
+///
+/// Each call to [`Self::advance`] gives the events the clock passed since the previous call.
+#[derive(Debug, Clone, Default)]
+pub struct PathPlayback {
+    time: Option<f32>,
+}
+
+impl PathPlayback {
+    /// Move playback to `time`, returning the sample of the path there and the events passed on
+    /// the way, in order.
+    ///
+    /// Events at exactly `time` are included. Jumping back in time (eg. when seeking) passes no
+    /// events, except the ones at the time jumped to.
+    pub fn advance<'a>(
+        &mut self,
+        path: &'a CameraPath,
+        time: f32,
+        aspect_ratio: f32,
+    ) -> (Option<CameraPathSample>, Vec<&'a PathEvent>) {
+        let passed = match self.time {
+            Some(prev) if prev <= time => path
+                .events
+                .iter()
+                .filter(|e| e.time > prev && e.time <= time)
+                .collect(),
+            _ => path.events.iter().filter(|e| e.time == time).collect(),
+        };
+        self.time = Some(time);
+        (path.sample(time, aspect_ratio), passed)
+    }
+}
+
+/// Rotation of a camera at `position` looking at `target`, with `up` pointing up in the image.
+pub fn look_at(position: Vec3, target: Vec3, up: Vec3) -> Quat {
+    let forward = (target - position).normalize();
+    // Cameras look down +z with y pointing down the image.
+    let down = (forward * forward.dot(up) - up).normalize();
+    Quat::from_mat3(&Mat3::from_cols(down.cross(forward), down, forward))
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn log_lerp(a: f32, b: f32, t: f32) -> f32 {
+    lerp(a.ln(), b.ln(), t).exp()
+}
+
+/// Remap time within a segment to slow down at its ends. The speed at the start and end is
+/// scaled by `1 - ease_out` and `1 - ease_in`, so no easing leaves time as is, and full easing is a smoothstep.
+fn ease(t: f32, ease_out: f32, ease_in: f32) -> f32 {
+    let (out_speed, in_speed) = (
+        1.0 - ease_out.clamp(0.0, 1.0),
+        1.0 - ease_in.clamp(0.0, 1.0),
+    );
+    // Cubic hermite from 0 to 1 with the given tangents.
+    let (t2, t3) = (t * t, t * t * t);
+    (-2.0 * t3 + 3.0 * t2) + out_speed * (t3 - 2.0 * t2 + t) + in_speed * (t3 - t2)
+}
+
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * ((2.0 * p1)
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+impl CameraPath {
+    pub fn new(mut keyframes: Vec<CameraKeyframe>) -> Self {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self {
+            keyframes,
+            events: vec![],
+        }
+    }
+
+    /// A path orbiting around `center` once in `duration` seconds, looking at the center.
+    ///
+    /// The camera stays `height` above the center along `up`, at `radius` from the up axis.
+    pub fn turntable(
+        center: Vec3,
+        up: Vec3,
+        radius: f32,
+        height: f32,
+        fov_y: f64,
+        duration: f32,
+    ) -> Self {
+        // Enough keyframes for the spline to stay very close to a circle.
+        const KEYFRAMES: u32 = 32;
+
+        let up = up.normalize();
+        let (a, b) = up.any_orthonormal_pair();
+        let keyframes = (0..=KEYFRAMES)
+            .map(|i| {
+                let t = i as f32 / KEYFRAMES as f32;
+                let angle = t * std::f32::consts::TAU;
+                let position = center + (a * angle.cos() + b * angle.sin()) * radius + up * height;
+                CameraKeyframe {
+                    time: t * duration,
+                    position,
+                    rotation: look_at(position, center, up),
+                    fov_y,
+                    exposure: 0.0,
+                    near: DEFAULT_NEAR,
+                    far: DEFAULT_FAR,
+                    ease_in: 0.0,
+                    ease_out: 0.0,
+                }
+            })
+            .collect();
+        Self::new(keyframes)
+    }
+
+    pub fn add_keyframe(&mut self, keyframe: CameraKeyframe) {
+        let index = self.keyframes.partition_point(|k| k.time <= keyframe.time);
+        self.keyframes.insert(index, keyframe);
+    }
+
+    pub fn add_event(&mut self, event: PathEvent) {
+        let index = self.events.partition_point(|e| e.time <= event.time);
+        self.events.insert(index, event);
+    }
+
+    /// Events of the path, sorted by time.
+    pub fn events(&self) -> &[PathEvent] {
+        &self.events
+    }
+
+    pub fn remove_event(&mut self, index: usize) -> PathEvent {
+        self.events.remove(index)
+    }
+
+    pub fn keyframes(&self) -> &[CameraKeyframe] {
+        &self.keyframes
+    }
+
+    pub fn remove_keyframe(&mut self, index: usize) -> CameraKeyframe {
+        self.keyframes.remove(index)
+    }
+
+    /// Replace a keyframe. This keeps keyframes sorted, so the keyframe might end up at another index.
+    pub fn set_keyframe(&mut self, index: usize, keyframe: CameraKeyframe) {
+        self.keyframes.remove(index);
+        self.add_keyframe(keyframe);
+    }
+
+    /// Time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time)
+    }
+
+    /// Sample the path at the given time, for a view with the given aspect ratio (width / height).
+    ///
+    /// Times before the first or after the last keyframe are clamped. Returns None for an empty path.
+    pub fn sample(&self, time: f32, aspect_ratio: f32) -> Option<CameraPathSample> {
+        if self.keyframes.is_empty() {
+            return None;
+        }
+        let last = self.keyframes.len() - 1;
+
+        // Index of the segment start, and the position within the segment.
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        let (i, t) = if next == 0 {
+            (0, 0.0)
+        } else if next > last {
+            (last, 0.0)
+        } else {
+            let (a, b) = (&self.keyframes[next - 1], &self.keyframes[next]);
+            let span = b.time - a.time;
+            let t = if span > 0.0 {
+                (time - a.time) / span
+            } else {
+                0.0
+            };
+            (next - 1, t)
+        };
+
+        let k1 = &self.keyframes[i];
+        let k2 = &self.keyframes[(i + 1).min(last)];
+        let k0 = &self.keyframes[i.saturating_sub(1)];
+        let k3 = &self.keyframes[(i + 2).min(last)];
+        let t = ease(t, k1.ease_out, k2.ease_in);
+
+        let position = catmull_rom(k0.position, k1.position, k2.position, k3.position, t);
+        let rotation = k1.rotation.slerp(k2.rotation, t);
+
+        // Interpolate focal length at an arbitrary resolution, the field of view doesn't depend on it.
+        const REF_HEIGHT: u32 = 1000;
+        let focal_y = lerp(
+            fov_to_focal(k1.fov_y, REF_HEIGHT) as f32,
+            fov_to_focal(k2.fov_y, REF_HEIGHT) as f32,
+            t,
+        ) as f64;
+        let fov_y = focal_to_fov(focal_y, REF_HEIGHT);
+        let ref_width = (REF_HEIGHT as f32 * aspect_ratio).round() as u32;
+        let fov_x = focal_to_fov(focal_y, ref_width);
+
+        let camera = Camera::new(position, rotation, fov_x, fov_y, glam::vec2(0.5, 0.5));
+        Some(CameraPathSample {
+            camera,
+            exposure: lerp(k1.exposure, k2.exposure, t),
+            near: log_lerp(k1.near, k2.near, t),
+            far: log_lerp(k1.far, k2.far, t),
+        })
+    }
+
+    /// Times the shutter of a camera samples the path during a frame.
+    ///
+    /// The shutter is open for `shutter_angle / 360` of the frame duration, centered on the frame
+    /// `time`, so motion blur doesn't shift the image compared to a frame without blur. The open
+    /// time is split into `samples` evenly spaced samples.
+    pub fn shutter_times(
+        time: f32,
+        frame_duration: f32,
+        shutter_angle: f32,
+        samples: u32,
+    ) -> Vec<f32> {
+        let samples = samples.max(1);
+        let open = frame_duration * shutter_angle.clamp(0.0, 360.0) / 360.0;
+        (0..samples)
+            .map(|i| time + open * ((i as f32 + 0.5) / samples as f32 - 0.5))
+            .collect()
+    }
+
+    /// Sample the path at the [`Self::shutter_times`] of a frame.
+    pub fn sample_shutter(
+        &self,
+        time: f32,
+        frame_duration: f32,
+        shutter_angle: f32,
+        samples: u32,
+        aspect_ratio: f32,
+    ) -> Vec<CameraPathSample> {
+        Self::shutter_times(time, frame_duration, shutter_angle, samples)
+            .into_iter()
+            .filter_map(|t| self.sample(t, aspect_ratio))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(time: f32, position: Vec3, fov_y: f64) -> CameraKeyframe {
+        CameraKeyframe {
+            time,
+            position,
+            rotation: Quat::IDENTITY,
+            fov_y,
+            exposure: time,
+            near: DEFAULT_NEAR,
+            far: DEFAULT_FAR,
+            ease_in: 0.0,
+            ease_out: 0.0,
+        }
+    }
+
+    #[test]
+    fn passes_through_keyframes() {
+        let path = CameraPath::new(vec![
+            keyframe(2.0, Vec3::X, 0.5),
+            keyframe(0.0, Vec3::ZERO, 0.8),
+            keyframe(1.0, Vec3::Y, 0.6),
+        ]);
+        assert_eq!(path.duration(), 2.0);
+
+        for (time, pos) in [(0.0, Vec3::ZERO), (1.0, Vec3::Y), (2.0, Vec3::X)] {
+            let sample = path.sample(time, 1.0).expect("Path isn't empty");
+            assert!(sample.camera.position.distance(pos) < 1e-5);
+            assert!((sample.exposure - time).abs() < 1e-5);
+        }
+
+        // Clamped outside of the path.
+        let before = path.sample(-1.0, 1.0).expect("Path isn't empty");
+        assert!(before.camera.position.distance(Vec3::ZERO) < 1e-5);
+        let after = path.sample(5.0, 1.0).expect("Path isn't empty");
+        assert!(after.camera.position.distance(Vec3::X) < 1e-5);
+    }
+
+    #[test]
+    fn interpolates_focal_length() {
+        let path = CameraPath::new(vec![
+            keyframe(0.0, Vec3::ZERO, 1.2),
+            keyframe(1.0, Vec3::Z, 0.4),
+        ]);
+        let mid = path.sample(0.5, 2.0).expect("Path isn't empty");
+        let focal = |fov: f64| fov_to_focal(fov, 100);
+        assert!((focal(mid.camera.fov_y) - 0.5 * (focal(1.2) + focal(0.4))).abs() < 1e-3);
+        // Horizontal fov matches the aspect ratio.
+        assert!((fov_to_focal(mid.camera.fov_x, 200) - focal(mid.camera.fov_y)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn easing_slows_down_at_keyframes() {
+        assert!((ease(0.3, 0.0, 0.0) - 0.3).abs() < 1e-6);
+        assert!(ease(0.01, 1.0, 0.0) < 0.001);
+        assert!(1.0 - ease(0.99, 0.0, 1.0) < 0.001);
+        // Easing one end doesn't affect the speed at the other end.
+        assert!((1.0 - ease(0.99, 1.0, 0.0) - 0.01).abs() < 1e-3);
+
+        let mut from = keyframe(0.0, Vec3::ZERO, 0.5);
+        from.ease_out = 1.0;
+        let path = CameraPath::new(vec![from, keyframe(1.0, Vec3::X, 0.5)]);
+        let early = path.sample(0.05, 1.0).expect("Path isn't empty");
+        assert!(early.camera.position.x < 0.05 * 0.5);
+        for i in 0..=10 {
+            let t = ease(i as f32 / 10.0, 0.7, 0.3);
+            assert!((0.0..=1.0).contains(&t));
+        }
+    }
+
+    #[test]
+    fn turntable_looks_at_center() {
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        let path = CameraPath::turntable(center, Vec3::NEG_Y, 4.0, 1.0, 0.8, 10.0);
+        assert_eq!(path.duration(), 10.0);
+
+        for time in [0.0, 2.5, 3.3, 7.0] {
+            let camera = path.sample(time, 1.0).expect("Path isn't empty").camera;
+            let to_center = (center - camera.position).normalize();
+            assert!((camera.rotation * Vec3::Z).distance(to_center) < 1e-2);
+            // The image is upright.
+            assert!((camera.rotation * Vec3::NEG_Y).dot(Vec3::NEG_Y) > 0.0);
+            // Height above the center.
+            assert!((camera.position.y - (center.y - 1.0)).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn shutter_is_centered_on_frame() {
+        let times = CameraPath::shutter_times(2.0, 0.5, 180.0, 4);
+        assert_eq!(times.len(), 4);
+        let mean = times.iter().sum::<f32>() / 4.0;
+        assert!((mean - 2.0).abs() < 1e-5);
+        // Half the frame duration, minus half a sample on both ends.
+        assert!((times[3] - times[0] - 0.25 * 0.75).abs() < 1e-5);
+
+        // A closed shutter has no blur.
+        assert!(
+            CameraPath::shutter_times(1.0, 0.5, 0.0, 3)
+                .iter()
+                .all(|&t| (t - 1.0).abs() < 1e-6)
+        );
+    }
+
+    #[test]
+    fn playback_passes_events() {
+        let mut path = CameraPath::new(vec![keyframe(0.0, Vec3::ZERO, 0.8)]);
+        for (time, name) in [(2.0, "b"), (0.0, "start"), (1.0, "a")] {
+            path.add_event(PathEvent {
+                time,
+                name: name.to_owned(),
+            });
+        }
+        let names = |events: Vec<&PathEvent>| -> Vec<String> {
+            events.into_iter().map(|e| e.name.clone()).collect()
+        };
+
+        let mut playback = PathPlayback::default();
+        assert_eq!(names(playback.advance(&path, 0.0, 1.0).1), ["start"]);
+        assert!(playback.advance(&path, 0.5, 1.0).1.is_empty());
+        assert_eq!(names(playback.advance(&path, 2.5, 1.0).1), ["a", "b"]);
+        // Seeking back doesn't replay events.
+        assert!(playback.advance(&path, 0.5, 1.0).1.is_empty());
+        assert_eq!(names(playback.advance(&path, 1.0, 1.0).1), ["a"]);
+    }
+
+    #[test]
+    fn empty_path() {
+        assert!(CameraPath::default().sample(0.0, 1.0).is_none());
+    }
+
+    #[test]
+    fn coherence_keeps_and_fades_splats() {
+        use crate::MainBackend;
+
+        let device = burn_wgpu::WgpuDevice::DefaultDevice;
+        // Splats in front of the camera, which looks down +z.
+        let splats = Splats::<MainBackend>::from_raw(
+            &[Vec3::Z * 0.5, Vec3::Z * 2.0, Vec3::Z * 5.0],
+            None,
+            None,
+            None,
+            None,
+            &device,
+        );
+        let sample = |near: f32, far: f32| CameraPathSample {
+            camera: Camera::new(Vec3::ZERO, Quat::IDENTITY, 0.5, 0.5, glam::vec2(0.5, 0.5)),
+            exposure: 0.0,
+            near,
+            far,
+        };
+        let mut coherence = FrameCoherence::new(4, 0.1);
+        let mut visibility = |near: f32, far: f32| {
+            coherence
+                .update(&sample(near, far), &splats)
+                .into_data()
+                .into_vec::<f32>()
+                .expect("Wrong type")
+        };
+
+        // The first frame is culled as is.
+        assert_eq!(visibility(1.0, 4.0), [0.0, 1.0, 0.0]);
+        // The middle splat is just past the near plane, within the margin, so it stays. The far
+        // splat comes into range, and fades in.
+        assert_eq!(visibility(2.1, 6.0), [0.0, 1.0, 0.25]);
+        // Once past the margin, the middle splat fades out.
+        assert_eq!(visibility(3.0, 6.0), [0.0, 0.75, 0.5]);
+    }
+}
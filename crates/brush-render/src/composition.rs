@@ -0,0 +1,107 @@
+// Scenes put together out of several splat files, each placed with its own transform.
+use crate::gaussian_splats::Splats;
+use burn::prelude::Backend;
+use glam::{Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+/// Where to place the splats of a layer: scaled, rotated and then translated.
+///
+/// Rotations are stored as a quaternion, as [x, y, z, w] when serialized.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LayerTransform {
+    #[serde(default)]
+    pub translation: Vec3,
+    #[serde(default)]
+    pub rotation: Quat,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+}
+
+impl Default for LayerTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl LayerTransform {
+    pub const IDENTITY: Self = Self {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: 1.0,
+    };
+
+    /// Where a point of the layer ends up.
+    pub fn apply_point(&self, point: Vec3) -> Vec3 {
+        self.rotation * (point * self.scale) + self.translation
+    }
+
+    /// Rotate the layer by `rotation` around `pivot`, in world space.
+    pub fn rotate_around(&mut self, pivot: Vec3, rotation: Quat) {
+        self.rotation = (rotation * self.rotation).normalize();
+        self.translation = pivot + rotation * (self.translation - pivot);
+    }
+
+    /// Scale the layer by `factor` around `pivot`, in world space.
+    pub fn scale_around(&mut self, pivot: Vec3, factor: f32) {
+        self.scale *= factor;
+        self.translation = pivot + (self.translation - pivot) * factor;
+    }
+
+    pub fn apply<B: Backend>(&self, splats: Splats<B>) -> Splats<B> {
+        if *self == Self::IDENTITY {
+            return splats;
+        }
+        splats.transform(self.scale, self.rotation, self.translation)
+    }
+}
+
+/// A splat file placed in a composition.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompositionLayer {
+    /// Splat file of the layer, relative to the composition file.
+    pub file: String,
+    #[serde(flatten)]
+    pub transform: LayerTransform,
+}
+
+/// The layers of a composed scene, as stored in a JSON file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SceneComposition {
+    pub layers: Vec<CompositionLayer>,
+}
+
+/// Gather the splats of all layers, each placed by its transform, into one set of splats.
+///
+/// Returns None when there are no layers.
+pub fn compose_splats<B: Backend>(
+    layers: impl IntoIterator<Item = (Splats<B>, LayerTransform)>,
+) -> Option<Splats<B>> {
+    layers
+        .into_iter()
+        .map(|(splats, transform)| transform.apply(splats))
+        .reduce(Splats::merge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transforms_around_pivot() {
+        let mut transform = LayerTransform {
+            translation: Vec3::X,
+            ..LayerTransform::IDENTITY
+        };
+        let pivot = transform.apply_point(Vec3::Y);
+
+        // The pivot stays in place, whatever happens around it.
+        transform.rotate_around(pivot, Quat::from_rotation_z(1.2));
+        transform.scale_around(pivot, 3.0);
+        assert!(transform.apply_point(Vec3::Y).distance(pivot) < 1e-5);
+        assert!((transform.apply_point(Vec3::ZERO).distance(pivot) - 3.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,369 @@
+// Views of the splats that show their properties instead of their colors, to find out why a part of
+// a scene is blurry or full of floaters.
+use burn::{
+    prelude::Backend,
+    tensor::{Int, Tensor},
+};
+use glam::UVec2;
+
+use crate::{
+    SplatForward, camera::Camera, gaussian_splats::Splats, render::pack_rgba,
+    render_aux::RenderAux, sh::SH_C0, shaders::helpers::TILE_WIDTH,
+};
+
+/// What to show of the splats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DebugView {
+    #[default]
+    Color,
+    SplatCount,
+    Overdraw,
+    Depth,
+    Scale,
+    Opacity,
+    Gradient,
+    Age,
+    ShDegrees,
+}
+
+impl DebugView {
+    pub const ALL: [Self; 9] = [
+        Self::Color,
+        Self::SplatCount,
+        Self::Overdraw,
+        Self::Depth,
+        Self::Scale,
+        Self::Opacity,
+        Self::Gradient,
+        Self::Age,
+        Self::ShDegrees,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Color => "Color",
+            Self::SplatCount => "Splat count",
+            Self::Overdraw => "Overdraw",
+            Self::Depth => "Depth",
+            Self::Scale => "Scale",
+            Self::Opacity => "Opacity",
+            Self::Gradient => "Gradient",
+            Self::Age => "Age",
+            Self::ShDegrees => "SH degrees",
+        }
+    }
+
+    /// Short name of the view, eg. for links to it.
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Color => "color",
+            Self::SplatCount => "splat_count",
+            Self::Overdraw => "overdraw",
+            Self::Depth => "depth",
+            Self::Scale => "scale",
+            Self::Opacity => "opacity",
+            Self::Gradient => "gradient",
+            Self::Age => "age",
+            Self::ShDegrees => "sh_degrees",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Color => "The splats as they are.",
+            Self::SplatCount => {
+                "How many splats are blended for each pixel. High counts are slow to render."
+            }
+            Self::Overdraw => {
+                "How many splats overlap each tile of pixels, also the ones hidden behind others. \
+                 Far more than the splat count is work spent on splats that don't show."
+            }
+            Self::Depth => "Distance of each splat to the camera, near in blue and far in red.",
+            Self::Scale => {
+                "Size of the largest axis of each splat. Large splats are often floaters."
+            }
+            Self::Opacity => "Opacity of each splat, transparent in blue and opaque in red.",
+            Self::Gradient => {
+                "How much training moves each splat on screen since it last grew the splats. Only \
+                 available while training."
+            }
+            Self::Age => {
+                "When training added each splat, the first splats in blue and the latest in red. \
+                 Only available while training."
+            }
+            Self::ShDegrees => {
+                "The view with spherical harmonics of degree 0 to 3, to see how much view \
+                 dependent color is worth keeping on export."
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for DebugView {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|view| view.key() == s.to_lowercase())
+            .ok_or_else(|| format!("Unknown debug view '{s}'"))
+    }
+}
+
+// Blue to red heatmap of values in [0, 1], as [N, 3] colors.
+fn heatmap<B: Backend>(t: Tensor<B, 1>) -> Tensor<B, 2> {
+    let centers = Tensor::<B, 1>::from_floats([3.0, 2.0, 1.0], &t.device()).unsqueeze_dim(0);
+    let dist = (t.unsqueeze_dim::<2>(1) * 4.0 - centers).abs();
+    (dist.neg() + 1.5).clamp(0.0, 1.0)
+}
+
+// Rescale logarithmic values to [0, 1], covering this many orders of magnitude below the largest.
+fn normalize_log<B: Backend>(log_values: Tensor<B, 1>, decades: f32) -> Tensor<B, 1> {
+    let range = decades * std::f32::consts::LN_10;
+    let max = log_values.clone().max();
+    ((log_values - max) / range + 1.0).clamp(0.0, 1.0)
+}
+
+/// Color each splat by its value, from blue for the lowest to red for the highest.
+///
+/// Values are expected to be in [0, 1]. The splats lose their view dependent colors.
+pub fn color_by_value<B: Backend>(splats: &Splats<B>, values: Tensor<B, 1>) -> Splats<B> {
+    let n = splats.num_splats() as usize;
+    let sh_coeffs = (heatmap(values) - 0.5) / SH_C0;
+    Splats::from_tensor_data(
+        splats.means.val(),
+        splats.rotation.val(),
+        splats.log_scales.val(),
+        sh_coeffs.reshape([n, 1, 3]),
+        splats.raw_opacity.val(),
+    )
+}
+
+/// Recolor splats to show one of their properties as seen from `camera`.
+///
+/// `gradient` has the accumulated gradient norm of each splat, and `added_at` the step each splat
+/// was added at, as reported by training. Returns None for views that don't color the splats, or
+/// when they lack the data to show.
+pub fn debug_splats<B: Backend>(
+    splats: &Splats<B>,
+    view: DebugView,
+    camera: &Camera,
+    gradient: Option<Tensor<B, 1>>,
+    added_at: Option<Tensor<B, 1>>,
+) -> Option<Splats<B>> {
+    let n = splats.num_splats() as usize;
+    let device = splats.device();
+    let values = match view {
+        DebugView::Color | DebugView::SplatCount | DebugView::Overdraw | DebugView::ShDegrees => {
+            return None;
+        }
+        DebugView::Depth => {
+            let world_to_local = camera.world_to_local();
+            let forward =
+                Tensor::<B, 1>::from_floats(world_to_local.matrix3.row(2).to_array(), &device);
+            let depth = splats
+                .means
+                .val()
+                .matmul(forward.reshape([3, 1]))
+                .reshape([n])
+                + world_to_local.translation.z;
+            normalize_log(depth.clamp_min(1e-6).log(), 3.0)
+        }
+        DebugView::Scale => {
+            let log_scale = splats.log_scales.val().max_dim(1).reshape([n]);
+            let min = log_scale.clone().min();
+            let max = log_scale.clone().max();
+            ((log_scale - min.clone()) / (max - min).clamp_min(1e-6)).clamp(0.0, 1.0)
+        }
+        DebugView::Opacity => splats.opacities(),
+        DebugView::Gradient => {
+            let gradient = gradient.filter(|g| g.dims()[0] == n)?;
+            normalize_log(gradient.clamp_min(1e-20).log(), 4.0)
+        }
+        DebugView::Age => {
+            let added_at = added_at.filter(|a| a.dims()[0] == n)?;
+            let latest = added_at.clone().max().clamp_min(1.0);
+            added_at / latest
+        }
+    };
+    Some(color_by_value(splats, values))
+}
+
+// The value of the tile of each pixel of an image of [h, w] pixels, from the values of its tiles.
+fn per_pixel<B: Backend>(tiles: Tensor<B, 2, Int>, h: usize, w: usize) -> Tensor<B, 2, Int> {
+    let [ty, tx] = tiles.dims();
+    let tile = TILE_WIDTH as usize;
+    tiles
+        .reshape([ty, 1, tx, 1])
+        .repeat_dim(1, tile)
+        .repeat_dim(3, tile)
+        .reshape([ty * tile, tx * tile])
+        .slice([0..h, 0..w])
+}
+
+/// Heatmap of how many splats were blended for each pixel, up to `max_count`, on a log scale.
+///
+/// Needs the aux data of a render with a float buffer. Returns an image of packed RGBA8 colors, with
+/// black where no splats are hit.
+pub fn splat_count_image<B: Backend>(aux: &RenderAux<B>, max_count: u32) -> Tensor<B, 3, Int> {
+    let tile_offsets: Tensor<B, 1, Int> = Tensor::from_primitive(aux.tile_offsets.clone());
+    let final_index: Tensor<B, 2, Int> = Tensor::from_primitive(aux.final_idx.clone());
+    let [h, w] = final_index.dims();
+    let tile = TILE_WIDTH as usize;
+    let [ty, tx] = [h.div_ceil(tile), w.div_ceil(tile)];
+
+    // Offset of the first intersection of the tile of each pixel.
+    let tile_start = per_pixel(tile_offsets.slice([0..ty * tx]).reshape([ty, tx]), h, w);
+    count_image(final_index - tile_start, max_count)
+}
+
+/// Heatmap of how many splats overlap the tile of each pixel, up to `max_count`, on a log scale.
+/// Unlike [`splat_count_image`], this counts the splats hidden behind others, which the renderer
+/// goes through all the same until the pixels are opaque.
+///
+/// Needs the aux data of a render with a float buffer. Returns an image of packed RGBA8 colors, with
+/// black where no splats overlap.
+pub fn overdraw_image<B: Backend>(aux: &RenderAux<B>, max_count: u32) -> Tensor<B, 3, Int> {
+    let final_index: Tensor<B, 2, Int> = Tensor::from_primitive(aux.final_idx.clone());
+    let [h, w] = final_index.dims();
+    count_image(per_pixel(aux.calc_tile_depth(), h, w), max_count)
+}
+
+// Blue to red heatmap of counts of [h, w] pixels, as packed RGBA8 colors.
+fn count_image<B: Backend>(count: Tensor<B, 2, Int>, max_count: u32) -> Tensor<B, 3, Int> {
+    let [h, w] = count.dims();
+    let count = count.clamp_min(0).float().reshape([h * w]);
+
+    let max = (max_count.max(1) as f32).ln_1p();
+    let t = ((count.clone() + 1.0).log() / max).clamp(0.0, 1.0);
+    let rgb = heatmap(t) * count.greater_elem(0.0).float().unsqueeze_dim(1);
+    let alpha = Tensor::ones([h * w, 1], &rgb.device());
+    pack_rgba(Tensor::cat(vec![rgb, alpha], 1).reshape([h, w, 4]))
+}
+
+/// Renders of the splats at spherical harmonics degrees 0 to 3, in the quadrants of an image of
+/// about `img_size`, from the top left to the bottom right.
+///
+/// Degrees above those of the splats look the same as their own degree. Returns an image of packed
+/// RGBA8 colors.
+pub fn sh_degree_quadrants<B: Backend + SplatForward<B>>(
+    splats: &Splats<B>,
+    camera: &Camera,
+    img_size: UVec2,
+) -> Tensor<B, 3, Int> {
+    let half = (img_size / 2).max(UVec2::ONE);
+    let [q0, q1, q2, q3] = [0, 1, 2, 3].map(|degree| {
+        let splats = splats
+            .clone()
+            .with_sh_degree(degree.min(splats.sh_degree()));
+        splats.render(camera, half, true).0
+    });
+    let top = Tensor::cat(vec![q0, q1], 1);
+    let bottom = Tensor::cat(vec![q2, q3], 1);
+    pack_rgba(Tensor::cat(vec![top, bottom], 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MainBackend;
+    use burn_wgpu::WgpuDevice;
+    use glam::{Quat, Vec3};
+
+    const IMG_SIZE: UVec2 = glam::uvec2(100, 100);
+
+    fn values<const D: usize>(tensor: Tensor<MainBackend, D>) -> Vec<f32> {
+        tensor.into_data().to_vec::<f32>().expect("Wrong type")
+    }
+
+    fn assert_close(values: &[f32], expected: &[f32]) {
+        assert_eq!(values.len(), expected.len());
+        for (v, e) in values.iter().zip(expected) {
+            assert!((v - e).abs() < 1e-5, "{values:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn heatmap_goes_from_blue_to_red() {
+        let t = Tensor::<MainBackend, 1>::from_floats([0.0, 0.5, 1.0], &WgpuDevice::DefaultDevice);
+        assert_close(
+            &values(heatmap(t)),
+            &[0.0, 0.0, 0.5, 0.5, 1.0, 0.5, 0.5, 0.0, 0.0],
+        );
+    }
+
+    #[test]
+    fn log_values_are_normalized_below_the_largest() {
+        let v = Tensor::<MainBackend, 1>::from_floats(
+            [1.0, 10.0, 100.0, 1000.0, 1e-5],
+            &WgpuDevice::DefaultDevice,
+        );
+        assert_close(
+            &values(normalize_log(v.log(), 3.0)),
+            &[0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0, 0.0],
+        );
+    }
+
+    #[test]
+    fn age_colors_splats_by_step() {
+        let device = WgpuDevice::DefaultDevice;
+        let splats =
+            Splats::<MainBackend>::from_raw(&[Vec3::Z; 3], None, None, None, None, &device);
+        let camera = Camera::new(Vec3::ZERO, Quat::IDENTITY, 1.0, 1.0, glam::vec2(0.5, 0.5));
+        let added_at = Tensor::from_floats([0.0, 50.0, 100.0], &device);
+
+        let aged = debug_splats(&splats, DebugView::Age, &camera, None, Some(added_at))
+            .expect("Age view with steps");
+        let expected = (heatmap(Tensor::from_floats([0.0, 0.5, 1.0], &device)) - 0.5) / SH_C0;
+        assert_close(&values(aged.sh_coeffs.val()), &values(expected));
+
+        assert!(debug_splats(&splats, DebugView::Age, &camera, None, None).is_none());
+        let too_few = Tensor::from_floats([0.0, 50.0], &device);
+        assert!(debug_splats(&splats, DebugView::Age, &camera, None, Some(too_few)).is_none());
+    }
+
+    // Three faint splats on top of each other, in front of the pixel (50, 50) of a camera with a
+    // focal length of 50 pixels.
+    fn stacked_render() -> RenderAux<MainBackend> {
+        let fov = std::f64::consts::FRAC_PI_2;
+        let camera = Camera::new(Vec3::ZERO, Quat::IDENTITY, fov, fov, glam::vec2(0.5, 0.5));
+        let splats = Splats::<MainBackend>::from_raw(
+            &[Vec3::new(0.0, 0.0, 2.0); 3],
+            Some(&[Quat::IDENTITY; 3]),
+            Some(&[Vec3::splat(-3.0); 3]),
+            None,
+            Some(&[-1.0; 3]),
+            &WgpuDevice::DefaultDevice,
+        );
+        splats.render(&camera, IMG_SIZE, true).1
+    }
+
+    fn pixel(image: &Tensor<MainBackend, 3, Int>, x: usize, y: usize) -> i32 {
+        image
+            .clone()
+            .slice([y..y + 1, x..x + 1])
+            .into_data()
+            .to_vec::<i32>()
+            .expect("Wrong type")[0]
+    }
+
+    // Opaque black, packed.
+    const BLACK: i32 = i32::from_le_bytes([0, 0, 0, 255]);
+
+    #[test]
+    fn splat_count_shows_hit_pixels() {
+        let image = splat_count_image(&stacked_render(), 3);
+        assert_eq!(image.dims(), [100, 100, 1]);
+        assert_ne!(pixel(&image, 50, 50), BLACK);
+        assert_eq!(pixel(&image, 5, 5), BLACK);
+    }
+
+    #[test]
+    fn overdraw_counts_splats_of_the_tile() {
+        let image = overdraw_image(&stacked_render(), 3);
+        assert_eq!(image.dims(), [100, 100, 1]);
+        // All three splats overlap the tile, the most the scale shows, which is dark red.
+        assert_eq!(pixel(&image, 50, 50), BLACK | 127);
+        assert_eq!(pixel(&image, 5, 5), BLACK);
+    }
+}
@@ -0,0 +1,334 @@
+// Edits of splats, to compose scenes out of the splats of several trainings from tools and scripts.
+//
+// Edits apply to the splats selected by a mask, as made by the functions in [`crate::selection`] or
+// by [`Splats::select_ids`].
+use burn::{
+    prelude::Backend,
+    tensor::{Bool, Int, Tensor, TensorData},
+};
+use glam::{Mat3, Quat, Vec3};
+
+use crate::{gaussian_splats::Splats, sh::rgb_to_sh};
+
+// Left multiplication by a fixed quaternion is linear in the (w, x, y, z) components. This is the
+// transpose of that matrix, as rotations are stored as row vectors.
+fn quat_mul_t<B: Backend>(rotation: Quat, device: &B::Device) -> Tensor<B, 2> {
+    let [rx, ry, rz, rw] = rotation.to_array();
+    #[rustfmt::skip]
+    let quat_mul_t = [
+        rw, rx, ry, rz,
+        -rx, rw, rz, -ry,
+        -ry, -rz, rw, rx,
+        -rz, ry, -rx, rw,
+    ];
+    Tensor::from_data(TensorData::new(quat_mul_t.to_vec(), [4, 4]), device)
+}
+
+// Repeat a mask of the splats over `width` columns.
+fn expand_mask<B: Backend>(selection: &Tensor<B, 1, Bool>, width: usize) -> Tensor<B, 2, Bool> {
+    let n = selection.dims()[0];
+    selection.clone().reshape([n, 1]).repeat_dim(1, width)
+}
+
+impl<B: Backend> Splats<B> {
+    /// Mask of the splats with the given indices. Indices past the last splat are ignored.
+    pub fn select_ids(&self, ids: &[u32]) -> Tensor<B, 1, Bool> {
+        let n = self.num_splats() as usize;
+        let mut mask = vec![false; n];
+        for &id in ids {
+            if let Some(selected) = mask.get_mut(id as usize) {
+                *selected = true;
+            }
+        }
+        Tensor::from_data(TensorData::new(mask, [n]), &self.device())
+    }
+
+    /// Scale, rotate and then translate all splats.
+    ///
+    /// View dependent colors aren't rotated along, so they stay fixed to the world.
+    pub fn transform(self, scale: f32, rotation: Quat, translation: Vec3) -> Self {
+        let n = self.num_splats() as usize;
+        let all = Tensor::<B, 1>::ones([n], &self.device()).greater_elem(0.5);
+        self.transform_selected(&all, scale, rotation, translation)
+    }
+
+    /// Scale, rotate and then translate the selected splats, leaving the others in place.
+    ///
+    /// View dependent colors aren't rotated along, so they stay fixed to the world.
+    pub fn transform_selected(
+        mut self,
+        selection: &Tensor<B, 1, Bool>,
+        scale: f32,
+        rotation: Quat,
+        translation: Vec3,
+    ) -> Self {
+        let device = self.device();
+
+        // Means are stored as row vectors, so multiply by the transpose of the linear part. The
+        // column major layout of glam gives exactly the row major layout of the transpose.
+        let linear = Mat3::from_quat(rotation) * scale;
+        let linear_t = Tensor::<B, 2>::from_data(
+            TensorData::new(linear.to_cols_array().to_vec(), [3, 3]),
+            &device,
+        );
+        let translation =
+            Tensor::<B, 1>::from_floats(translation.to_array(), &device).reshape([1, 3]);
+        self.means = self.means.map(|m| {
+            let moved = m.clone().matmul(linear_t) + translation;
+            let mask = expand_mask(selection, 3);
+            m.mask_where(mask, moved).detach().require_grad()
+        });
+
+        let quat_mul_t = quat_mul_t(rotation, &device);
+        self.rotation = self.rotation.map(|r| {
+            let rotated = r.clone().matmul(quat_mul_t);
+            let mask = expand_mask(selection, 4);
+            r.mask_where(mask, rotated).detach().require_grad()
+        });
+
+        let log_scale = scale.ln();
+        self.log_scales = self.log_scales.map(|s| {
+            let scaled = s.clone().add_scalar(log_scale);
+            let mask = expand_mask(selection, 3);
+            s.mask_where(mask, scaled).detach().require_grad()
+        });
+        self
+    }
+
+    /// Multiply the opacity of the selected splats by `factor`, from 0 (invisible) to 1 (as is).
+    pub fn scale_opacity(mut self, selection: &Tensor<B, 1, Bool>, factor: f32) -> Self {
+        let factor = selection.clone().float() * (factor - 1.0) + 1.0;
+        // Convert back to raw opacities, staying clear of the infinities at 0 and 1.
+        let opacity = (self.opacities() * factor).clamp(1e-6, 1.0 - 1e-6);
+        let raw_opacity = (opacity.clone() / (opacity.neg() + 1.0)).log();
+        self.raw_opacity = self
+            .raw_opacity
+            .map(|_| raw_opacity.detach().require_grad());
+        self
+    }
+
+    /// Blend the color of the selected splats towards `color` by `amount`, from 0 to 1.
+    ///
+    /// View dependent color fades out along with it, so a full recolor looks flat from all sides.
+    pub fn recolor(mut self, selection: &Tensor<B, 1, Bool>, color: Vec3, amount: f32) -> Self {
+        let [n, coeffs, _] = self.sh_coeffs.dims();
+        let device = self.device();
+        let weight = selection.clone().float().reshape([n, 1, 1]) * amount;
+        let target =
+            Tensor::<B, 1>::from_floats(rgb_to_sh(color).to_array(), &device).reshape([1, 1, 3]);
+
+        self.sh_coeffs = self.sh_coeffs.map(|sh_coeffs| {
+            let dc = sh_coeffs.clone().slice([0..n, 0..1, 0..3]);
+            let dc = dc.clone() + (target - dc) * weight.clone();
+            let sh_coeffs = if coeffs > 1 {
+                let rest = sh_coeffs.slice([0..n, 1..coeffs, 0..3]);
+                Tensor::cat(vec![dc, rest * (weight.neg() + 1.0)], 1)
+            } else {
+                dc
+            };
+            sh_coeffs.detach().require_grad()
+        });
+        self
+    }
+
+    /// Remove the selected splats.
+    ///
+    /// Returns None if no splats would be left.
+    pub async fn delete_selected(self, selection: &Tensor<B, 1, Bool>) -> Option<Self> {
+        self.retain(selection.clone().bool_not()).await
+    }
+
+    /// A copy of only the selected splats, eg. to place elsewhere with [`Splats::merge`].
+    ///
+    /// Returns None if no splats are selected.
+    pub async fn extract_selected(&self, selection: &Tensor<B, 1, Bool>) -> Option<Self> {
+        self.clone().retain(selection.clone()).await
+    }
+
+    /// Add a copy of the selected splats, scaled, rotated and then translated. The copies come
+    /// after all existing splats.
+    pub async fn duplicate_selected(
+        self,
+        selection: &Tensor<B, 1, Bool>,
+        scale: f32,
+        rotation: Quat,
+        translation: Vec3,
+    ) -> Self {
+        match self.extract_selected(selection).await {
+            Some(copies) => self.merge(copies.transform(scale, rotation, translation)),
+            None => self,
+        }
+    }
+
+    /// How much each splat shows in renders: its opacity times the area of its two largest axes,
+    /// which is about how much of the screen it covers from most views.
+    pub fn importance(&self) -> Tensor<B, 1> {
+        let log_scales = self.log_scales.val();
+        let log_area = log_scales.clone().sum_dim(1) - log_scales.min_dim(1);
+        self.opacities() * log_area.squeeze::<1>(1).exp()
+    }
+
+    /// Indices of the splats from most to least important, see [`Splats::importance`].
+    pub fn importance_order(&self) -> Tensor<B, 1, Int> {
+        self.importance().argsort_descending(0)
+    }
+
+    /// The splats in the order of `indices`, eg. as given by [`Splats::importance_order`].
+    pub fn reorder(mut self, indices: Tensor<B, 1, Int>) -> Self {
+        self.means = self
+            .means
+            .map(|m| m.select(0, indices.clone()).detach().require_grad());
+        self.rotation = self
+            .rotation
+            .map(|r| r.select(0, indices.clone()).detach().require_grad());
+        self.log_scales = self
+            .log_scales
+            .map(|s| s.select(0, indices.clone()).detach().require_grad());
+        self.sh_coeffs = self
+            .sh_coeffs
+            .map(|c| c.select(0, indices.clone()).detach().require_grad());
+        self.raw_opacity = self
+            .raw_opacity
+            .map(|o| o.select(0, indices).detach().require_grad());
+        self
+    }
+
+    /// All splats of both sets, the splats of `other` after those of `self`.
+    ///
+    /// The merged splats have the higher SH degree of the two.
+    pub fn merge(self, other: Self) -> Self {
+        let sh_degree = self.sh_degree().max(other.sh_degree());
+        let (a, b) = (
+            self.with_sh_degree(sh_degree),
+            other.with_sh_degree(sh_degree),
+        );
+        Self::from_tensor_data(
+            Tensor::cat(vec![a.means.val(), b.means.val()], 0),
+            Tensor::cat(vec![a.rotation.val(), b.rotation.val()], 0),
+            Tensor::cat(vec![a.log_scales.val(), b.log_scales.val()], 0),
+            Tensor::cat(vec![a.sh_coeffs.val(), b.sh_coeffs.val()], 0),
+            Tensor::cat(vec![a.raw_opacity.val(), b.raw_opacity.val()], 0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MainBackend;
+    use burn_wgpu::WgpuDevice;
+
+    fn splats(positions: &[Vec3], sh_degree: u32) -> Splats<MainBackend> {
+        let n = positions.len();
+        let means: Vec<f32> = positions.iter().flat_map(|p| p.to_array()).collect();
+        let device = WgpuDevice::DefaultDevice;
+        Splats::from_tensor_data(
+            Tensor::from_data(TensorData::new(means, [n, 3]), &device),
+            Tensor::<MainBackend, 1>::from_floats([1.0, 0.0, 0.0, 0.0], &device)
+                .reshape([1, 4])
+                .repeat_dim(0, n),
+            Tensor::zeros([n, 3], &device),
+            Tensor::zeros([n, 1, 3], &device),
+            Tensor::zeros([n], &device),
+        )
+        .with_sh_degree(sh_degree)
+    }
+
+    fn values<const D: usize>(t: Tensor<MainBackend, D>) -> Vec<f32> {
+        t.into_data().to_vec::<f32>().expect("Wrong type")
+    }
+
+    #[test]
+    fn edits_only_selected() {
+        let splats = splats(&[Vec3::ZERO, Vec3::X], 0);
+        let selection = splats.select_ids(&[1, 7]);
+
+        let moved = splats.clone().transform_selected(
+            &selection,
+            2.0,
+            Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+            Vec3::Z,
+        );
+        let means = values(moved.means.val());
+        let expected = [0.0, 0.0, 0.0, 0.0, 2.0, 1.0];
+        for (m, e) in means.iter().zip(expected) {
+            assert!((m - e).abs() < 1e-5, "{means:?}");
+        }
+        let scales = values(moved.log_scales.val());
+        assert_eq!(scales[..3], [0.0; 3]);
+        assert!((scales[3] - 2.0f32.ln()).abs() < 1e-5);
+
+        let faded = values(splats.clone().scale_opacity(&selection, 0.5).opacities());
+        assert!((faded[0] - 0.5).abs() < 1e-5 && (faded[1] - 0.25).abs() < 1e-5);
+    }
+
+    #[test]
+    fn fades_and_tints_selected() {
+        let device = WgpuDevice::DefaultDevice;
+        let splats = splats(&[Vec3::ZERO, Vec3::X], 0);
+        let splats = Splats::from_tensor_data(
+            splats.means.val(),
+            splats.rotation.val(),
+            splats.log_scales.val(),
+            Tensor::ones([2, 4, 3], &device),
+            splats.raw_opacity.val(),
+        );
+        let selection = splats.select_ids(&[1]);
+
+        // Fading out completely keeps the raw opacity finite.
+        let faded = splats.clone().scale_opacity(&selection, 0.0);
+        let raw = values(faded.raw_opacity.val());
+        assert!(raw.iter().all(|r| r.is_finite()), "{raw:?}");
+        let opacity = values(faded.opacities());
+        assert!(
+            (opacity[0] - 0.5).abs() < 1e-5 && opacity[1] < 1e-5,
+            "{opacity:?}"
+        );
+
+        let color = Vec3::new(1.0, 0.5, 0.0);
+        let tinted = values(splats.recolor(&selection, color, 0.5).sh_coeffs.val());
+        let (first, second) = tinted.split_at(12);
+        assert!(first.iter().all(|c| *c == 1.0), "{first:?}");
+        let target = rgb_to_sh(color).to_array();
+        for (c, t) in second[..3].iter().zip(target) {
+            assert!((c - (1.0 + t) * 0.5).abs() < 1e-5, "{second:?}");
+        }
+        // View dependent color fades along with the tint.
+        assert!(
+            second[3..].iter().all(|c| (c - 0.5).abs() < 1e-5),
+            "{second:?}"
+        );
+    }
+
+    #[test]
+    fn orders_by_importance() {
+        let device = WgpuDevice::DefaultDevice;
+        let splats = splats(&[Vec3::ZERO, Vec3::X, Vec3::Y], 0);
+        // A small and a big splat, and a big one that's barely visible.
+        let log_scales = Tensor::<MainBackend, 1>::from_floats([-2.0, 1.0, 1.0], &device)
+            .reshape([3, 1])
+            .repeat_dim(1, 3);
+        let opacity = Tensor::from_floats([0.0, 0.0, -6.0], &device);
+        let splats = Splats::from_tensor_data(
+            splats.means.val(),
+            splats.rotation.val(),
+            log_scales,
+            splats.sh_coeffs.val(),
+            opacity,
+        );
+
+        let order = splats.importance_order();
+        let ordered = splats.reorder(order.clone());
+        let order = order.into_data().to_vec::<i32>().expect("Wrong type");
+        assert_eq!(order, [1, 2, 0]);
+        assert_eq!(values(ordered.means.val())[..3], [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn merges_sh_degrees() {
+        let merged = splats(&[Vec3::ZERO], 0).merge(splats(&[Vec3::X, Vec3::Y], 2));
+        assert_eq!(merged.num_splats(), 3);
+        assert_eq!(merged.sh_degree(), 2);
+        assert_eq!(values(merged.means.val())[3..6], [1.0, 0.0, 0.0]);
+    }
+}
@@ -2,16 +2,16 @@ use crate::{
     SplatForward,
     bounding_box::BoundingBox,
     camera::Camera,
+    knn::nearest_neighbour_log_scales_cpu,
     render_aux::RenderAux,
-    sh::{sh_coeffs_for_degree, sh_degree_from_coeffs},
+    sh::{SH_C0, sh_coeffs_for_degree, sh_degree_from_coeffs},
 };
-use ball_tree::BallTree;
 use burn::{
     config::Config,
     module::{Module, Param, ParamId},
     prelude::Backend,
     tensor::{
-        Tensor, TensorData, TensorPrimitive, activation::sigmoid, backend::AutodiffBackend, s,
+        Bool, Tensor, TensorData, TensorPrimitive, activation::sigmoid, backend::AutodiffBackend, s,
     },
 };
 use glam::{Quat, Vec3};
@@ -62,6 +62,13 @@ impl<B: Backend> Splats<B> {
             positions.push(Vec3::new(x, y, z));
         }
 
+        Self::from_random_points(&positions, rng, device)
+    }
+
+    /// Create splats at the given positions, with a random color.
+    pub fn from_random_points(positions: &[Vec3], rng: &mut impl Rng, device: &B::Device) -> Self {
+        let num_points = positions.len();
+
         let mut colors: Vec<f32> = Vec::with_capacity(num_points);
         for _ in 0..num_points {
             let r = rng.random_range(0.0..1.0);
@@ -72,7 +79,7 @@ impl<B: Backend> Splats<B> {
             colors.push(b);
         }
 
-        Self::from_raw(&positions, None, None, Some(&colors), None, device)
+        Self::from_raw(positions, None, None, Some(&colors), None, device)
     }
 
     pub fn from_raw(
@@ -107,23 +114,7 @@ impl<B: Backend> Splats<B> {
             let log_scales: Vec<f32> = log_scales.iter().flat_map(|v| [v.x, v.y, v.z]).collect();
             Tensor::from_data(TensorData::new(log_scales, [n_splats, 3]), device)
         } else {
-            let tree_pos: Vec<[f64; 3]> = means
-                .iter()
-                .map(|v| [v.x as f64, v.y as f64, v.z as f64])
-                .collect();
-
-            let empty = vec![(); tree_pos.len()];
-            let tree = BallTree::new(tree_pos.clone(), empty);
-
-            let extents: Vec<_> = tree_pos
-                .iter()
-                .map(|p| {
-                    // Get average of 4 nearest distances.
-                    0.5 * tree.query().nn(p).skip(1).take(2).map(|x| x.1).sum::<f64>() / 2.0
-                })
-                .map(|p| p.max(1e-12))
-                .map(|p| p.ln() as f32)
-                .collect();
+            let extents = nearest_neighbour_log_scales_cpu(means);
 
             Tensor::<B, 1>::from_floats(extents.as_slice(), device)
                 .reshape([n_splats, 1])
@@ -230,6 +221,24 @@ impl<B: Backend> Splats<B> {
         self
     }
 
+    /// Keep only the splats where `keep` is true.
+    ///
+    /// Returns None if no splats would be left.
+    pub async fn retain(self, keep: Tensor<B, 1, Bool>) -> Option<Self> {
+        let inds = keep.argwhere_async().await;
+        if inds.dims()[0] == 0 {
+            return None;
+        }
+        let inds = inds.squeeze(1);
+        Some(Self::from_tensor_data(
+            self.means.val().select(0, inds.clone()),
+            self.rotation.val().select(0, inds.clone()),
+            self.log_scales.val().select(0, inds.clone()),
+            self.sh_coeffs.val().select(0, inds.clone()),
+            self.raw_opacity.val().select(0, inds),
+        ))
+    }
+
     pub fn sh_degree(&self) -> u32 {
         let [_, coeffs, _] = self.sh_coeffs.dims();
         sh_degree_from_coeffs(coeffs as u32)
@@ -292,4 +301,35 @@ impl<B: Backend + SplatForward<B>> Splats<B> {
         }
         (img, aux)
     }
+
+    /// Render the depth of the splats along the view axis, as [H, W] tensors of the blended depth
+    /// and the opacity of each pixel.
+    ///
+    /// NB: This doesn't work on a differentiable backend.
+    pub fn render_depth(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+    ) -> (Tensor<B, 2>, Tensor<B, 2>) {
+        let n = self.num_splats() as usize;
+        let world_to_local = camera.world_to_local();
+        let forward =
+            Tensor::<B, 1>::from_floats(world_to_local.matrix3.row(2).to_array(), &self.device());
+        let depth = self.means.val().matmul(forward.reshape([3, 1])) + world_to_local.translation.z;
+        // Color each splat by its depth. Colors aren't clamped above 1 in a float buffer.
+        let sh_coeffs = ((depth - 0.5) / SH_C0).reshape([n, 1, 1]).repeat_dim(2, 3);
+        let depth_splats = Self::from_tensor_data(
+            self.means.val(),
+            self.rotation.val(),
+            self.log_scales.val(),
+            sh_coeffs,
+            self.raw_opacity.val(),
+        );
+        let (img, _) = depth_splats.render(camera, img_size, true);
+        let [h, w, _] = img.dims();
+        let alpha = img.clone().slice(s![.., .., 3]).reshape([h, w]);
+        // Blending weights sum to the opacity, so divide that out.
+        let depth = img.slice(s![.., .., 0]).reshape([h, w]) / alpha.clone().clamp_min(1e-6);
+        (depth, alpha)
+    }
 }
@@ -0,0 +1,171 @@
+// Copies of a captured prop (eg. a tree or a rock) placed around a scene, each with its own
+// transform and color variation.
+use crate::{SplatForward, camera::Camera, gaussian_splats::Splats, render_aux::RenderAux};
+use burn::{
+    prelude::Backend,
+    tensor::{Tensor, TensorData, TensorPrimitive},
+};
+use glam::{Quat, Vec3};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+fn default_tint() -> Vec3 {
+    Vec3::ONE
+}
+
+/// A placed copy of a prop.
+///
+/// Rotations are stored as a quaternion, as [x, y, z, w] when serialized.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PropInstance {
+    pub position: Vec3,
+    #[serde(default)]
+    pub rotation: Quat,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    /// Multiplier of the colors of the prop.
+    #[serde(default = "default_tint")]
+    pub tint: Vec3,
+    /// Seed of the random color variation of this copy.
+    #[serde(default)]
+    pub seed: u64,
+}
+
+impl PropInstance {
+    pub fn new(position: Vec3) -> Self {
+        Self {
+            position,
+            rotation: Quat::IDENTITY,
+            scale: 1.0,
+            tint: Vec3::ONE,
+            seed: 0,
+        }
+    }
+
+    // Random shift of each color channel, up to `variation`.
+    fn color_shift(&self, variation: f32) -> Vec3 {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        Vec3::from_array(std::array::from_fn(|_| {
+            variation * (rng.random::<f32>() * 2.0 - 1.0)
+        }))
+    }
+}
+
+/// Where to place copies of a prop in a scene, as stored in a JSON file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PropLayout {
+    /// Splat file of the prop, relative to the layout file.
+    pub prop: String,
+    /// Largest random shift of the colors of each copy.
+    #[serde(default)]
+    pub variation: f32,
+    pub instances: Vec<PropInstance>,
+}
+
+/// A scene with copies of a prop placed in it.
+///
+/// The copies aren't stored as splats of their own. The splats of the prop are stored once after
+/// those of the scene, and the renderer draws them again for each copy, with the transform, tint
+/// and color shift of that copy applied. So placing thousands of copies costs barely more memory
+/// than the prop itself.
+pub struct InstancedSplats<B: Backend> {
+    /// Splats of the scene, followed by those of the prop.
+    pub splats: Splats<B>,
+    /// Number of splats of the scene, where the splats of the prop start.
+    pub instance_start: u32,
+    /// [K, 16] tensor with a row for each copy, in the layout of `PropInstance` in `helpers.wgsl`.
+    pub transforms: Tensor<B, 2>,
+}
+
+impl<B: Backend> InstancedSplats<B> {
+    /// Place a copy of `prop` in `scene` at each of the `instances`.
+    ///
+    /// The colors of each copy are multiplied by its tint, and shifted by a random amount of up to
+    /// `variation` (in the 0-1 color range) per channel, picked by its seed.
+    ///
+    /// Like [`Splats::retain`], higher order SH coefficients are not rotated, so view dependent colors
+    /// rotate along with the prop only approximately.
+    ///
+    /// Returns None when there are no instances, or the prop has no splats.
+    pub fn new(
+        scene: Splats<B>,
+        prop: Splats<B>,
+        instances: &[PropInstance],
+        variation: f32,
+    ) -> Option<Self> {
+        if instances.is_empty() || prop.num_splats() == 0 {
+            return None;
+        }
+        let device = scene.device();
+        let transforms: Vec<f32> = instances
+            .iter()
+            .flat_map(|i| {
+                let rotation = [i.rotation.w, i.rotation.x, i.rotation.y, i.rotation.z];
+                [
+                    rotation,
+                    i.position.extend(i.scale).to_array(),
+                    i.tint.extend(1.0).to_array(),
+                    i.color_shift(variation).extend(0.0).to_array(),
+                ]
+                .concat()
+            })
+            .collect();
+        let transforms =
+            Tensor::from_data(TensorData::new(transforms, [instances.len(), 16]), &device);
+
+        let instance_start = scene.num_splats();
+        Some(Self {
+            splats: scene.merge(prop),
+            instance_start,
+            transforms,
+        })
+    }
+
+    /// Number of splats drawn, counting the splats of each copy.
+    pub fn num_drawn(&self) -> u32 {
+        let prop_splats = self.splats.num_splats() - self.instance_start;
+        self.instance_start + prop_splats * self.transforms.dims()[0] as u32
+    }
+}
+
+impl<B: Backend + SplatForward<B>> InstancedSplats<B> {
+    /// Render the scene and all copies of the prop, like [`Splats::render`].
+    ///
+    /// The aux buffers count the splats of each copy separately, see [`Self::num_drawn`].
+    pub fn render(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        float_buffer: bool,
+    ) -> (Tensor<B, 3>, RenderAux<B>) {
+        self.render_with(camera, img_size, self.splats.opacities(), float_buffer)
+    }
+
+    /// Render like [`Self::render`], with other opacities for the splats of the scene and prop.
+    pub fn render_with(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        opacities: Tensor<B, 1>,
+        float_buffer: bool,
+    ) -> (Tensor<B, 3>, RenderAux<B>) {
+        let splats = &self.splats;
+        let (img, aux) = B::render_splats_instanced(
+            camera,
+            img_size,
+            splats.means.val().into_primitive().tensor(),
+            splats.log_scales.val().into_primitive().tensor(),
+            splats.rotation.val().into_primitive().tensor(),
+            splats.sh_coeffs.val().into_primitive().tensor(),
+            opacities.into_primitive().tensor(),
+            self.instance_start,
+            self.transforms.clone().into_primitive().tensor(),
+            float_buffer,
+        );
+        (Tensor::from_primitive(TensorPrimitive::Float(img)), aux)
+    }
+}
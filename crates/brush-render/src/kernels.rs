@@ -1,10 +1,24 @@
-use super::shaders::{map_gaussian_to_intersects, project_forward, project_visible, rasterize};
+#[cfg(test)]
+use super::shaders::random_values;
+use super::shaders::{
+    knn, map_gaussian_to_intersects, project_forward, project_visible, rasterize, wind,
+};
 use brush_kernel::kernel_source_gen;
 
-kernel_source_gen!(ProjectSplats {}, project_forward);
-kernel_source_gen!(ProjectVisible {}, project_visible);
+kernel_source_gen!(ProjectSplats { instanced }, project_forward);
+kernel_source_gen!(ProjectVisible { instanced }, project_visible);
 kernel_source_gen!(
     MapGaussiansToIntersect { prepass },
     map_gaussian_to_intersects
 );
-kernel_source_gen!(Rasterize { bwd_info }, rasterize);
+kernel_source_gen!(
+    Rasterize {
+        bwd_info,
+        external_depth
+    },
+    rasterize
+);
+kernel_source_gen!(KnnScales {}, knn);
+kernel_source_gen!(AnimateWind {}, wind);
+#[cfg(test)]
+kernel_source_gen!(RandomValues {}, random_values);
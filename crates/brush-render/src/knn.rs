@@ -0,0 +1,275 @@
+// Initial sizes of splats from the distances to their nearest neighbours.
+//
+// The points are binned into a hashed grid on the CPU, which is a linear pass, and a kernel
+// searches the cells around each point on the GPU, which is where the time goes for big clouds.
+use std::collections::HashMap;
+
+use ball_tree::BallTree;
+use brush_kernel::{calc_cube_count, create_tensor, create_uniform_buffer};
+use burn::tensor::{DType, Shape, Tensor, TensorPrimitive};
+use burn_cubecl::cubecl::{Runtime, server::Bindings};
+use burn_wgpu::{CubeTensor, WgpuDevice, WgpuRuntime};
+use glam::{IVec3, Vec3};
+
+use crate::{MainBackendBase, kernels::KnnScales, shaders};
+
+// Below this many points the CPU is about as fast, and no kernel needs to be compiled.
+const MIN_GPU_POINTS: usize = 16384;
+
+// Aim for about this many points in the cell of each point.
+const TARGET_OCCUPANCY: f32 = 4.0;
+
+// Nr. of points the cell size is picked from.
+const SAMPLE_POINTS: usize = 65536;
+
+// Largest nr. of points handled by one dispatch.
+const MAX_DISPATCH_POINTS: usize = 256 * 65535;
+
+/// Log scale of a splat at each of `means`, half the average distance to its two nearest
+/// neighbours.
+pub fn nearest_neighbour_log_scales_cpu(means: &[Vec3]) -> Vec<f32> {
+    let tree_pos: Vec<[f64; 3]> = means
+        .iter()
+        .map(|v| [v.x as f64, v.y as f64, v.z as f64])
+        .collect();
+
+    let empty = vec![(); tree_pos.len()];
+    let tree = BallTree::new(tree_pos.clone(), empty);
+
+    tree_pos
+        .iter()
+        .map(|p| {
+            // Get average of 4 nearest distances.
+            0.5 * tree.query().nn(p).skip(1).take(2).map(|x| x.1).sum::<f64>() / 2.0
+        })
+        .map(|p| p.max(1e-12))
+        .map(|p| p.ln() as f32)
+        .collect()
+}
+
+/// Like [`nearest_neighbour_log_scales_cpu`], but on the GPU, which takes seconds instead of
+/// minutes for clouds of millions of points.
+///
+/// Neighbours further than a few grid cells away aren't looked for, so isolated points can end
+/// up smaller than their nearest neighbour would make them.
+pub async fn nearest_neighbour_log_scales(means: &[Vec3], device: &WgpuDevice) -> Vec<Vec3> {
+    let log_scales = if means.len() < MIN_GPU_POINTS {
+        nearest_neighbour_log_scales_cpu(means)
+    } else {
+        let grid = HashGrid::new(means);
+        let sorted = dispatch_knn(&grid, device)
+            .into_data_async()
+            .await
+            .into_vec::<f32>()
+            .expect("Wrong type");
+        grid.unsort(&sorted)
+    };
+    log_scales.into_iter().map(Vec3::splat).collect()
+}
+
+fn cell_of(pos: Vec3, cell_size: f32) -> IVec3 {
+    (pos / cell_size).floor().as_ivec3()
+}
+
+// Nb: Must match the hash of the knn kernel.
+fn bucket_of(cell: IVec3, num_buckets: u32) -> u32 {
+    let hash = (cell.x as u32).wrapping_mul(73_856_093)
+        ^ (cell.y as u32).wrapping_mul(19_349_663)
+        ^ (cell.z as u32).wrapping_mul(83_492_791);
+    hash % num_buckets
+}
+
+// Pick a cell size so cells hold a few points each, estimated on a sample of the points.
+fn pick_cell_size(means: &[Vec3]) -> f32 {
+    let step = means.len().div_ceil(SAMPLE_POINTS).max(1);
+    let sample: Vec<_> = means.iter().step_by(step).copied().collect();
+
+    // Start from the size cells would have if the points were spread evenly, ignoring outliers.
+    let percentile = |axis: usize, fraction: f32| {
+        let mut values: Vec<f32> = sample.iter().map(|p| p[axis]).collect();
+        values.sort_by(f32::total_cmp);
+        values[((values.len() - 1) as f32 * fraction) as usize]
+    };
+    let extent =
+        Vec3::from_array([0, 1, 2].map(|axis| percentile(axis, 0.95) - percentile(axis, 0.05)))
+            .max(Vec3::splat(1e-6));
+    let mut cell_size = (extent.x * extent.y * extent.z / means.len() as f32).cbrt();
+
+    // Points usually lie on surfaces, so shrink the cells until they aren't crowded.
+    let scale = means.len() as f32 / sample.len() as f32;
+    for _ in 0..16 {
+        let mut counts: HashMap<IVec3, u32> = HashMap::new();
+        for &p in &sample {
+            *counts.entry(cell_of(p, cell_size)).or_default() += 1;
+        }
+        let occupancy = sample
+            .iter()
+            .map(|&p| counts[&cell_of(p, cell_size)] as f32)
+            .sum::<f32>()
+            / sample.len() as f32
+            * scale;
+        if occupancy <= TARGET_OCCUPANCY {
+            break;
+        }
+        cell_size *= 0.5;
+    }
+    cell_size
+}
+
+// Points sorted by the hash bucket of their cell.
+struct HashGrid {
+    cell_size: f32,
+    num_buckets: u32,
+    bucket_starts: Vec<u32>,
+    // Index into the original points of each sorted point.
+    order: Vec<u32>,
+    sorted: Vec<Vec3>,
+}
+
+impl HashGrid {
+    fn new(means: &[Vec3]) -> Self {
+        let cell_size = pick_cell_size(means);
+        let num_buckets = means.len().next_power_of_two() as u32;
+        let buckets: Vec<u32> = means
+            .iter()
+            .map(|&p| bucket_of(cell_of(p, cell_size), num_buckets))
+            .collect();
+
+        // Counting sort by bucket.
+        let mut bucket_starts = vec![0u32; num_buckets as usize + 1];
+        for &bucket in &buckets {
+            bucket_starts[bucket as usize] += 1;
+        }
+        let mut total = 0;
+        for start in &mut bucket_starts {
+            let count = *start;
+            *start = total;
+            total += count;
+        }
+        let mut next = bucket_starts.clone();
+        let mut order = vec![0; means.len()];
+        for (i, &bucket) in buckets.iter().enumerate() {
+            order[next[bucket as usize] as usize] = i as u32;
+            next[bucket as usize] += 1;
+        }
+        let sorted = order.iter().map(|&i| means[i as usize]).collect();
+
+        Self {
+            cell_size,
+            num_buckets,
+            bucket_starts,
+            order,
+            sorted,
+        }
+    }
+
+    // Put values of the sorted points back in the order of the original points.
+    fn unsort(&self, sorted_values: &[f32]) -> Vec<f32> {
+        let mut values = vec![0.0; sorted_values.len()];
+        for (&i, &value) in self.order.iter().zip(sorted_values) {
+            values[i as usize] = value;
+        }
+        values
+    }
+}
+
+fn upload(data: &[u8], len: usize, dtype: DType, device: &WgpuDevice) -> CubeTensor<WgpuRuntime> {
+    let client = WgpuRuntime::client(device);
+    CubeTensor::new_contiguous(
+        client.clone(),
+        device.clone(),
+        Shape::new([len]),
+        client.create(data),
+        dtype,
+    )
+}
+
+// Log scales of the sorted points of `grid`.
+fn dispatch_knn(grid: &HashGrid, device: &WgpuDevice) -> Tensor<MainBackendBase, 1> {
+    let client = WgpuRuntime::client(device);
+    let num_points = grid.sorted.len();
+
+    let points: Vec<f32> = grid.sorted.iter().flat_map(|p| p.to_array()).collect();
+    let points = upload(
+        bytemuck::cast_slice(&points),
+        num_points * 3,
+        DType::F32,
+        device,
+    );
+    let bucket_starts = upload(
+        bytemuck::cast_slice(&grid.bucket_starts),
+        grid.bucket_starts.len(),
+        DType::U32,
+        device,
+    );
+    let log_scales = create_tensor([num_points], device, &client, DType::F32);
+
+    for offset in (0..num_points).step_by(MAX_DISPATCH_POINTS) {
+        let uniforms = create_uniform_buffer(
+            shaders::knn::Uniforms {
+                cell_size: grid.cell_size,
+                num_buckets: grid.num_buckets,
+                num_points: num_points as u32,
+                offset: offset as u32,
+            },
+            device,
+            &client,
+        );
+        let count = (num_points - offset).min(MAX_DISPATCH_POINTS) as u32;
+
+        // Safe execution, the search loops depend on the data.
+        client.execute(
+            KnnScales::task(),
+            calc_cube_count([count], KnnScales::WORKGROUP_SIZE),
+            Bindings::new().with_buffers(vec![
+                uniforms.handle.binding(),
+                points.clone().handle.binding(),
+                bucket_starts.clone().handle.binding(),
+                log_scales.clone().handle.binding(),
+            ]),
+        );
+    }
+
+    Tensor::from_primitive(TensorPrimitive::Float(log_scales))
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn gpu_matches_cpu() {
+        // Points on a noisy sphere, like the surface of a scanned object, plus a few outliers.
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut means: Vec<Vec3> = (0..MIN_GPU_POINTS * 2)
+            .map(|_| {
+                let dir = Vec3::new(
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0),
+                )
+                .normalize_or_zero();
+                dir * rng.random_range(0.99..1.01)
+            })
+            .collect();
+        means.extend([Vec3::splat(20.0), Vec3::splat(-30.0)]);
+
+        let grid = HashGrid::new(&means);
+        let sorted = dispatch_knn(&grid, &WgpuDevice::DefaultDevice)
+            .into_data()
+            .into_vec::<f32>()
+            .expect("Wrong type");
+        let gpu = grid.unsort(&sorted);
+        let cpu = nearest_neighbour_log_scales_cpu(&means);
+
+        // Points on the sphere find the same neighbours.
+        let num_points = MIN_GPU_POINTS * 2;
+        let matching = (0..num_points)
+            .filter(|&i| (gpu[i] - cpu[i]).abs() < 1e-3)
+            .count();
+        assert_eq!(matching, num_points);
+        // Outliers are too far from anything to find neighbours, and get capped.
+        assert!(gpu[num_points] < cpu[num_points]);
+    }
+}
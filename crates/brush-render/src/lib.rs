@@ -23,8 +23,25 @@ mod tests;
 
 pub mod bounding_box;
 pub mod camera;
+pub mod camera_path;
+pub mod composition;
+pub mod debug_view;
+pub mod edit;
 pub mod gaussian_splats;
+pub mod instancing;
+pub mod knn;
+pub mod lod;
+pub mod motion;
+pub mod occlusion;
+pub mod painter;
+pub mod picking;
+pub mod random;
 pub mod render;
+pub mod selection;
+pub mod shots;
+pub mod stereo;
+pub mod volume;
+pub mod wind;
 
 pub type MainBackendBase = CubeBackend<WgpuRuntime, f32, i32, u32>;
 pub type MainBackend = Fusion<MainBackendBase>;
@@ -57,6 +74,23 @@ pub trait SplatForward<B: Backend> {
         raw_opacities: FloatTensor<B>,
         bwd_info: bool,
     ) -> (FloatTensor<B>, RenderAux<B>);
+
+    /// Render splats like [`Self::render_splats`], drawing the splats from `instance_start` on
+    /// once for each row of the [K, 16] `instance_transforms`. The copies are read from the same
+    /// buffers, so they take no more memory than the splats they copy.
+    /// See [`instancing::InstancedSplats`] for a convenient way to use this.
+    fn render_splats_instanced(
+        camera: &Camera,
+        img_size: glam::UVec2,
+        means: FloatTensor<B>,
+        log_scales: FloatTensor<B>,
+        quats: FloatTensor<B>,
+        sh_coeffs: FloatTensor<B>,
+        raw_opacities: FloatTensor<B>,
+        instance_start: u32,
+        instance_transforms: FloatTensor<B>,
+        bwd_info: bool,
+    ) -> (FloatTensor<B>, RenderAux<B>);
 }
 
 fn burn_options() -> RuntimeOptions {
@@ -82,3 +116,9 @@ pub async fn burn_init_setup() -> WgpuDevice {
         .await;
     WgpuDevice::DefaultDevice
 }
+
+/// Like [`burn_init_setup`], but on the graphics API `G`. Returns the adapter the device runs on.
+pub async fn burn_init_setup_with<G: GraphicsApi>() -> (WgpuDevice, wgpu::AdapterInfo) {
+    let setup = burn_wgpu::init_setup_async::<G>(&WgpuDevice::DefaultDevice, burn_options()).await;
+    (WgpuDevice::DefaultDevice, setup.adapter.get_info())
+}
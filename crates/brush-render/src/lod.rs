@@ -0,0 +1,674 @@
+use crate::{
+    camera::{Camera, CameraModel},
+    gaussian_splats::{Splats, inverse_sigmoid},
+};
+use burn::{
+    prelude::Backend,
+    tensor::{Int, Tensor, TensorData},
+};
+use glam::{Mat3, Quat, Vec3};
+
+// Octree nodes with at most this many splats aren't split further.
+const LEAF_SIZE: usize = 8;
+// Stop splitting at some point, eg. when many splats sit at the exact same position.
+const MAX_DEPTH: u32 = 20;
+
+/// A level of detail hierarchy over splats, to render huge scenes interactively.
+///
+/// Splats are grouped in an octree, and every octree node gets a proxy splat merged from its
+/// children. When rendering, a node is drawn as its proxy once its bounds are small enough on
+/// screen, instead of drawing everything below it.
+pub struct SplatLod<B: Backend> {
+    // The original splats, followed by the proxies of all nodes.
+    splats: Splats<B>,
+    source_count: u32,
+    // Bounding spheres of each splat and everything it stands in for.
+    bound_centers: Tensor<B, 2>,
+    bound_radii: Tensor<B, 1>,
+    // Proxy that each splat is merged into. The root is its own parent.
+    parents: Tensor<B, 1, Int>,
+    // Where each splat goes in a cut. A proxy shares the slot of one of the splats below it, and
+    // a cut draws just one splat on the way from the root to any splat, so no slot is taken twice.
+    slots: Tensor<B, 1, Int>,
+    // 1 for the original splats, 0 for proxies.
+    leaves: Tensor<B, 1>,
+    // 1 for the root, 0 otherwise.
+    roots: Tensor<B, 1>,
+}
+
+// A splat as a single gaussian, to merge into proxies.
+#[derive(Clone, Copy)]
+struct Gaussian {
+    mean: Vec3,
+    cov: Mat3,
+    opacity: f32,
+    // Rough area the splat covers, to weigh splats by how much they contribute.
+    area: f32,
+    bound_center: Vec3,
+    bound_radius: f32,
+}
+
+// Eigen decomposition of a symmetric matrix with cyclic Jacobi rotations.
+//
+// Returns the eigenvalues, and the eigenvectors as the columns of a matrix.
+fn sym_eigen(m: Mat3) -> (Vec3, Mat3) {
+    let mut a = m.to_cols_array_2d();
+    let mut v = Mat3::IDENTITY.to_cols_array_2d();
+
+    for _ in 0..12 {
+        let off = a[0][1].powi(2) + a[0][2].powi(2) + a[1][2].powi(2);
+        if off < 1e-20 {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q].abs() < 1e-30 {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+
+            // a = J^T a J, with J the rotation in the (p, q) plane.
+            for k in 0..3 {
+                let (akp, akq) = (a[k][p], a[k][q]);
+                a[k][p] = c * akp - s * akq;
+                a[k][q] = s * akp + c * akq;
+            }
+            for k in 0..3 {
+                let (apk, aqk) = (a[p][k], a[q][k]);
+                a[p][k] = c * apk - s * aqk;
+                a[q][k] = s * apk + c * aqk;
+            }
+            for row in &mut v {
+                let (vp, vq) = (row[p], row[q]);
+                row[p] = c * vp - s * vq;
+                row[q] = s * vp + c * vq;
+            }
+        }
+    }
+
+    // v was built up as rows of the column major array, so transpose it back.
+    let vectors = Mat3::from_cols_array_2d(&v).transpose();
+    (Vec3::new(a[0][0], a[1][1], a[2][2]), vectors)
+}
+
+// Product of the two largest axes.
+fn area_of(scales: Vec3) -> f32 {
+    let mut axes = scales.to_array();
+    axes.sort_by(f32::total_cmp);
+    axes[1] * axes[2]
+}
+
+/// Merge gaussians into one, matching their combined mean and covariance.
+///
+/// Returns the proxy, and its rotation and scales.
+fn merge(parts: &[Gaussian]) -> (Gaussian, Quat, Vec3) {
+    let weights: Vec<f32> = parts.iter().map(|g| g.opacity * g.area + 1e-12).collect();
+    let total: f32 = weights.iter().sum();
+
+    let mean = parts
+        .iter()
+        .zip(&weights)
+        .fold(Vec3::ZERO, |acc, (g, w)| acc + g.mean * *w)
+        / total;
+    let cov = parts.iter().zip(&weights).fold(Mat3::ZERO, |acc, (g, w)| {
+        let d = g.mean - mean;
+        acc + (g.cov + Mat3::from_cols(d * d.x, d * d.y, d * d.z)) * *w
+    }) * (1.0 / total);
+
+    let (values, mut vectors) = sym_eigen(cov);
+    // Make it a proper rotation.
+    if vectors.determinant() < 0.0 {
+        vectors.z_axis = -vectors.z_axis;
+    }
+    let values = values.max(Vec3::splat(1e-12));
+    let scales = Vec3::new(values.x.sqrt(), values.y.sqrt(), values.z.sqrt());
+    let area = area_of(scales);
+
+    // Keep the total coverage of the parts.
+    let covered: f32 = parts.iter().map(|g| g.opacity * g.area).sum();
+    let opacity = (covered / area).clamp(1e-4, 0.99);
+
+    let bound_radius = parts
+        .iter()
+        .map(|g| (g.bound_center - mean).length() + g.bound_radius)
+        .fold(0.0, f32::max);
+
+    let proxy = Gaussian {
+        mean,
+        cov,
+        opacity,
+        area,
+        bound_center: mean,
+        bound_radius,
+    };
+    (proxy, Quat::from_mat3(&vectors).normalize(), scales)
+}
+
+// A child of an octree node: one of the splats, or another node.
+#[derive(Clone, Copy)]
+enum Child {
+    Splat(u32),
+    Node(usize),
+}
+
+// Octree node still to split.
+struct Split {
+    node: usize,
+    ids: Vec<u32>,
+    min: Vec3,
+    max: Vec3,
+    depth: u32,
+}
+
+/// Builds a [`SplatLod`] on the CPU, a step at a time.
+///
+/// Octree nodes are first split top down, and then merged into proxies bottom up. Neither
+/// needs the GPU, so large scenes can be built on a blocking thread, or spread over many frames.
+pub struct LodBuilder<B: Backend> {
+    source: Splats<B>,
+    gaussians: Vec<Gaussian>,
+    // SH coefficients of each entry, `coeffs` values each.
+    sh: Vec<f32>,
+    coeffs: usize,
+    parents: Vec<u32>,
+    // Slot of each entry in a cut, that of one of the splats it stands in for.
+    slots: Vec<u32>,
+    proxy_rotations: Vec<Quat>,
+    proxy_log_scales: Vec<Vec3>,
+    to_split: Vec<Split>,
+    // Children of each node. Nodes are created after their parents.
+    nodes: Vec<Vec<Child>>,
+    // Proxy of each node, once merged. Nodes are merged from the last one back to the root.
+    proxies: Vec<u32>,
+    merged: usize,
+}
+
+impl<B: Backend> LodBuilder<B> {
+    fn new(
+        source: Splats<B>,
+        means: &[f32],
+        rotations: &[f32],
+        scales: &[f32],
+        opacities: &[f32],
+        sh: Vec<f32>,
+    ) -> Self {
+        let n = opacities.len();
+        let gaussians: Vec<Gaussian> = (0..n)
+            .map(|i| {
+                let mean = Vec3::from_slice(&means[i * 3..i * 3 + 3]);
+                let scale = Vec3::from_slice(&scales[i * 3..i * 3 + 3]);
+                let [w, x, y, z] = [0, 1, 2, 3].map(|j| rotations[i * 4 + j]);
+                let m = Mat3::from_quat(Quat::from_xyzw(x, y, z, w)) * Mat3::from_diagonal(scale);
+                Gaussian {
+                    mean,
+                    cov: m * m.transpose(),
+                    opacity: opacities[i],
+                    area: area_of(scale),
+                    bound_center: mean,
+                    bound_radius: 3.0 * scale.max_element(),
+                }
+            })
+            .collect();
+
+        let (min, max) = gaussians.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), g| (min.min(g.mean), max.max(g.mean)),
+        );
+
+        Self {
+            source,
+            gaussians,
+            coeffs: sh.len() / n.max(1),
+            sh,
+            parents: (0..n as u32).collect(),
+            slots: (0..n as u32).collect(),
+            proxy_rotations: vec![],
+            proxy_log_scales: vec![],
+            to_split: vec![Split {
+                node: 0,
+                ids: (0..n as u32).collect(),
+                min,
+                max,
+                depth: 0,
+            }],
+            nodes: vec![vec![]],
+            proxies: vec![],
+            merged: 0,
+        }
+    }
+
+    // Split a node, and returns how many splats that went through.
+    fn split(&mut self, split: Split) -> usize {
+        let Split {
+            node,
+            ids,
+            min,
+            max,
+            depth,
+        } = split;
+        let work = ids.len();
+        if ids.len() <= LEAF_SIZE || depth >= MAX_DEPTH {
+            self.nodes[node] = ids.into_iter().map(Child::Splat).collect();
+            return work;
+        }
+
+        let center = (min + max) * 0.5;
+        let mut octants: [Vec<u32>; 8] = Default::default();
+        for id in ids {
+            let p = self.gaussians[id as usize].mean;
+            let octant = (p.x > center.x) as usize
+                | ((p.y > center.y) as usize) << 1
+                | ((p.z > center.z) as usize) << 2;
+            octants[octant].push(id);
+        }
+
+        let mut children = vec![];
+        for (octant, ids) in octants.into_iter().enumerate() {
+            // Nodes with just one child don't need a proxy of their own.
+            if ids.len() <= 1 {
+                children.extend(ids.into_iter().map(Child::Splat));
+                continue;
+            }
+            let pick = |bit: usize, axis: usize| {
+                if octant & bit != 0 {
+                    (center[axis], max[axis])
+                } else {
+                    (min[axis], center[axis])
+                }
+            };
+            let (x, y, z) = (pick(1, 0), pick(2, 1), pick(4, 2));
+            let child = self.nodes.len();
+            self.nodes.push(vec![]);
+            self.to_split.push(Split {
+                node: child,
+                ids,
+                min: Vec3::new(x.0, y.0, z.0),
+                max: Vec3::new(x.1, y.1, z.1),
+                depth: depth + 1,
+            });
+            children.push(Child::Node(child));
+        }
+        self.nodes[node] = children;
+        work
+    }
+
+    // Merge a node whose children are all merged into its proxy, and returns how many children
+    // that took.
+    fn merge_node(&mut self, node: usize) -> usize {
+        let children: Vec<u32> = self.nodes[node]
+            .iter()
+            .map(|child| match *child {
+                Child::Splat(id) => id,
+                Child::Node(node) => self.proxies[node],
+            })
+            .collect();
+        let parts: Vec<Gaussian> = children
+            .iter()
+            .map(|&c| self.gaussians[c as usize])
+            .collect();
+        let (proxy, rotation, scales) = merge(&parts);
+
+        let weights: Vec<f32> = parts.iter().map(|g| g.opacity * g.area + 1e-12).collect();
+        let total: f32 = weights.iter().sum();
+        let mut sh = vec![0.0; self.coeffs];
+        for (&c, w) in children.iter().zip(&weights) {
+            let start = c as usize * self.coeffs;
+            for (acc, v) in sh.iter_mut().zip(&self.sh[start..start + self.coeffs]) {
+                *acc += v * w / total;
+            }
+        }
+
+        let index = self.gaussians.len() as u32;
+        self.gaussians.push(proxy);
+        self.sh.extend(sh);
+        self.parents.push(index);
+        self.slots.push(self.slots[children[0] as usize]);
+        self.proxy_rotations.push(rotation);
+        self.proxy_log_scales
+            .push(Vec3::new(scales.x.ln(), scales.y.ln(), scales.z.ln()));
+        for &c in &children {
+            self.parents[c as usize] = index;
+        }
+        self.proxies[node] = index;
+        children.len()
+    }
+
+    /// Continue building, for about `budget` splats worth of work. Returns whether the hierarchy
+    /// is done, and can be finished.
+    pub fn step(&mut self, budget: usize) -> bool {
+        let mut work = 0;
+        while work < budget {
+            if let Some(split) = self.to_split.pop() {
+                work += self.split(split);
+                continue;
+            }
+            if self.merged == self.nodes.len() {
+                return true;
+            }
+            self.proxies.resize(self.nodes.len(), u32::MAX);
+            // Children were all created after their parent, so going from the back merges
+            // children first.
+            work += self.merge_node(self.nodes.len() - 1 - self.merged);
+            self.merged += 1;
+        }
+        self.merged == self.nodes.len()
+    }
+
+    /// Build whatever is left, and upload the hierarchy.
+    pub fn finish(mut self) -> SplatLod<B> {
+        while !self.step(usize::MAX) {}
+
+        let splats = self.source;
+        let device = splats.device();
+        let n = splats.num_splats() as usize;
+        let root = self.proxies[0];
+
+        let proxies = &self.gaussians[n..];
+        let proxy_means: Vec<Vec3> = proxies.iter().map(|g| g.mean).collect();
+        let proxy_opacities: Vec<f32> =
+            proxies.iter().map(|g| inverse_sigmoid(g.opacity)).collect();
+        let proxy_splats = Splats::<B>::from_raw(
+            &proxy_means,
+            Some(&self.proxy_rotations),
+            Some(&self.proxy_log_scales),
+            Some(&self.sh[n * self.coeffs..]),
+            Some(&proxy_opacities),
+            &device,
+        );
+        let all = Splats::from_tensor_data(
+            Tensor::cat(vec![splats.means.val(), proxy_splats.means.val()], 0),
+            Tensor::cat(
+                vec![splats.rotations_normed(), proxy_splats.rotation.val()],
+                0,
+            ),
+            Tensor::cat(
+                vec![splats.log_scales.val(), proxy_splats.log_scales.val()],
+                0,
+            ),
+            Tensor::cat(
+                vec![splats.sh_coeffs.val(), proxy_splats.sh_coeffs.val()],
+                0,
+            ),
+            Tensor::cat(
+                vec![splats.raw_opacity.val(), proxy_splats.raw_opacity.val()],
+                0,
+            ),
+        );
+
+        let total = self.gaussians.len();
+        let centers: Vec<f32> = self
+            .gaussians
+            .iter()
+            .flat_map(|g| g.bound_center.to_array())
+            .collect();
+        let radii: Vec<f32> = self.gaussians.iter().map(|g| g.bound_radius).collect();
+        let parents: Vec<i32> = self.parents.iter().map(|&p| p as i32).collect();
+        let slots: Vec<i32> = self.slots.iter().map(|&s| s as i32).collect();
+        let leaves: Vec<f32> = (0..total).map(|i| (i < n) as u32 as f32).collect();
+        let roots: Vec<f32> = (0..total)
+            .map(|i| (i == root as usize) as u32 as f32)
+            .collect();
+
+        SplatLod {
+            splats: all,
+            source_count: n as u32,
+            bound_centers: Tensor::from_data(TensorData::new(centers, [total, 3]), &device),
+            bound_radii: Tensor::from_floats(radii.as_slice(), &device),
+            parents: Tensor::from_ints(parents.as_slice(), &device),
+            slots: Tensor::from_ints(slots.as_slice(), &device),
+            leaves: Tensor::from_floats(leaves.as_slice(), &device),
+            roots: Tensor::from_floats(roots.as_slice(), &device),
+        }
+    }
+}
+
+impl<B: Backend> SplatLod<B> {
+    /// Start building the hierarchy for some splats. This reads the splats back, the build itself
+    /// happens in [`LodBuilder::step`] and [`LodBuilder::finish`].
+    pub async fn builder(splats: &Splats<B>) -> LodBuilder<B> {
+        let read = |t: Tensor<B, 2>| async move {
+            t.into_data_async()
+                .await
+                .into_vec::<f32>()
+                .expect("Wrong type")
+        };
+        let means = read(splats.means.val()).await;
+        let rotations = read(splats.rotations_normed()).await;
+        let scales = read(splats.scales()).await;
+        let opacities = splats
+            .opacities()
+            .into_data_async()
+            .await
+            .into_vec::<f32>()
+            .expect("Wrong type");
+        let sh = splats
+            .sh_coeffs
+            .val()
+            .into_data_async()
+            .await
+            .into_vec::<f32>()
+            .expect("Wrong type");
+
+        LodBuilder::new(splats.clone(), &means, &rotations, &scales, &opacities, sh)
+    }
+
+    /// Build the hierarchy for some splats. This runs on the CPU, and can take a while for large
+    /// scenes, see [`Self::builder`] to build it in steps.
+    pub async fn build(splats: &Splats<B>) -> Self {
+        Self::builder(splats).await.finish()
+    }
+
+    /// Number of splats the hierarchy was built from.
+    pub fn source_count(&self) -> u32 {
+        self.source_count
+    }
+
+    /// Pick the level of detail for a view.
+    ///
+    /// Nodes are drawn as their proxy once their bounds are at most `pixel_radius` pixels on
+    /// screen. This returns as many splats as the hierarchy was built from, with the slots of
+    /// splats stood in for by a proxy made fully transparent, which the renderer culls before
+    /// doing any real work.
+    pub fn cut(&self, camera: &Camera, img_size: glam::UVec2, pixel_radius: f32) -> Splats<B> {
+        let device = self.splats.device();
+        let n = self.bound_radii.dims()[0];
+
+        let position =
+            Tensor::<B, 1>::from_floats(camera.position.to_array(), &device).reshape([1, 3]);
+        let dist = (self.bound_centers.clone() - position)
+            .powi_scalar(2)
+            .sum_dim(1)
+            .sqrt()
+            .reshape([n]);
+        // Pixel radius of the bounds, from the point of the bounds closest to the camera. Bounds
+        // are nested, so this only grows going up the tree.
+        let focal = match camera.model {
+            CameraModel::Pinhole => camera.focal(img_size).max_element(),
+            // The bounds cover an angle of at most radius / (dist - radius) radians, which is
+            // as many pixels of latitude. Bounds near the poles are stretched wider than this in
+            // longitude, and switch to their proxies a bit too early.
+            CameraModel::Equirectangular => camera.focal(img_size).y,
+        };
+        let size =
+            self.bound_radii.clone() * focal / (dist - self.bound_radii.clone()).clamp_min(1e-6);
+
+        // A splat is drawn if it's detailed enough, and what it's merged into isn't.
+        let fine = size
+            .lower_elem(pixel_radius)
+            .float()
+            .max_pair(self.leaves.clone());
+        let parent_fine =
+            fine.clone().select(0, self.parents.clone()) * (self.roots.clone().neg() + 1.0);
+        let drawn = fine * (parent_fine.neg() + 1.0);
+
+        let count = self.source_count as usize;
+        let occupied = self.gather(Tensor::ones([n], &device), drawn.clone());
+        let empty = occupied.lower_elem(0.5);
+        // Keep the quaternions of empty slots valid, rotations are stored as wxyz.
+        let identity = Tensor::<B, 2>::from_floats([[1.0, 0.0, 0.0, 0.0]], &device);
+        let rotation = self.gather(self.splats.rotation.val(), drawn.clone())
+            + identity * empty.clone().float().reshape([count, 1]);
+        Splats::from_tensor_data(
+            self.gather(self.splats.means.val(), drawn.clone()),
+            rotation,
+            self.gather(self.splats.log_scales.val(), drawn.clone()),
+            self.gather(self.splats.sh_coeffs.val(), drawn.clone()),
+            self.gather(self.splats.raw_opacity.val(), drawn)
+                .mask_fill(empty, -1e4),
+        )
+    }
+
+    // Put the values of the drawn splats in their slots, and zeros everywhere else.
+    fn gather<const D: usize>(&self, values: Tensor<B, D>, drawn: Tensor<B, 1>) -> Tensor<B, D> {
+        let mut shape = values.dims();
+        let mut drawn_shape = [1; D];
+        drawn_shape[0] = shape[0];
+        shape[0] = self.source_count as usize;
+        Tensor::zeros(shape, &values.device()).select_assign(
+            0,
+            self.slots.clone(),
+            values * drawn.reshape(drawn_shape),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MainBackend;
+    use burn_wgpu::WgpuDevice;
+
+    // A grid of small splats on the z = 10 plane, and a builder over them.
+    fn grid_builder(side: usize) -> LodBuilder<MainBackend> {
+        let n = side * side;
+        let means: Vec<Vec3> = (0..n)
+            .map(|i| Vec3::new((i % side) as f32, (i / side) as f32, 10.0))
+            .collect();
+        let log_scales = vec![Vec3::splat(0.2f32.ln()); n];
+        let splats = Splats::<MainBackend>::from_raw(
+            &means,
+            None,
+            Some(&log_scales),
+            None,
+            Some(&vec![2.0; n]),
+            &WgpuDevice::DefaultDevice,
+        );
+        let flat: Vec<f32> = means.iter().flat_map(|m| m.to_array()).collect();
+        let rotations: Vec<f32> = (0..n).flat_map(|_| [1.0, 0.0, 0.0, 0.0]).collect();
+        let scales = vec![0.2; n * 3];
+        let opacities = vec![0.88; n];
+        LodBuilder::new(
+            splats,
+            &flat,
+            &rotations,
+            &scales,
+            &opacities,
+            vec![0.0; n * 3],
+        )
+    }
+
+    #[test]
+    fn steps_build_the_same_tree() {
+        let mut whole = grid_builder(12);
+        assert!(whole.step(usize::MAX));
+        let mut stepped = grid_builder(12);
+        let mut steps = 0;
+        while !stepped.step(16) {
+            steps += 1;
+        }
+        assert!(steps > 1);
+        assert_eq!(whole.parents, stepped.parents);
+        assert_eq!(whole.slots, stepped.slots);
+
+        // Every splat is merged into a proxy after it, up to the root.
+        let n = 12 * 12;
+        let root = whole.proxies[0] as usize;
+        assert_eq!(root, whole.parents.len() - 1);
+        for (i, &parent) in whole.parents.iter().enumerate() {
+            if i == root {
+                assert_eq!(parent as usize, root);
+            } else {
+                assert!(parent as usize > i && parent as usize >= n);
+            }
+        }
+        // Proxies take the slot of a splat below them.
+        for (i, &slot) in whole.slots.iter().enumerate() {
+            let mut at = slot as usize;
+            while at != i && at != root {
+                at = whole.parents[at] as usize;
+            }
+            assert_eq!(at, i, "Slot {slot} isn't below {i}");
+        }
+    }
+
+    #[test]
+    fn cut_has_at_most_the_source_splats() {
+        let lod = grid_builder(12).finish();
+        assert_eq!(lod.source_count(), 144);
+        let drawn = |distance: f32, pixel_radius: f32| {
+            let camera = Camera::new(
+                Vec3::new(5.5, 5.5, 10.0 - distance),
+                Quat::IDENTITY,
+                0.8,
+                0.8,
+                glam::vec2(0.5, 0.5),
+            );
+            let cut = lod.cut(&camera, glam::uvec2(64, 64), pixel_radius);
+            assert_eq!(cut.num_splats(), 144);
+            let opacities = cut
+                .raw_opacity
+                .val()
+                .into_data()
+                .to_vec::<f32>()
+                .expect("Wrong type");
+            opacities.iter().filter(|&&o| o > -1e3).count()
+        };
+        // Up close, every splat is drawn as is.
+        assert_eq!(drawn(1.0, 0.01), 144);
+        // Far away, a few proxies stand in for all of them.
+        let far = drawn(1000.0, 4.0);
+        assert!((1..144).contains(&far), "{far} splats drawn from far away");
+    }
+
+    fn gaussian(mean: Vec3, scale: f32, opacity: f32) -> Gaussian {
+        Gaussian {
+            mean,
+            cov: Mat3::from_diagonal(Vec3::splat(scale * scale)),
+            opacity,
+            area: scale * scale,
+            bound_center: mean,
+            bound_radius: 3.0 * scale,
+        }
+    }
+
+    #[test]
+    fn eigen_decomposition() {
+        let rot = Mat3::from_quat(Quat::from_euler(glam::EulerRot::XYZ, 0.3, -1.1, 0.7));
+        let m = rot * Mat3::from_diagonal(Vec3::new(1.0, 4.0, 9.0)) * rot.transpose();
+        let (values, vectors) = sym_eigen(m);
+        let rebuilt = vectors * Mat3::from_diagonal(values) * vectors.transpose();
+        assert!(rebuilt.abs_diff_eq(m, 1e-4), "{rebuilt} != {m}");
+
+        let mut sorted = values.to_array();
+        sorted.sort_by(f32::total_cmp);
+        for (v, expected) in sorted.into_iter().zip([1.0, 4.0, 9.0]) {
+            assert!((v - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn merge_keeps_spread() {
+        let a = gaussian(Vec3::new(-1.0, 0.0, 0.0), 0.1, 0.5);
+        let b = gaussian(Vec3::new(1.0, 0.0, 0.0), 0.1, 0.5);
+        let (proxy, _, scales) = merge(&[a, b]);
+
+        assert!(proxy.mean.abs_diff_eq(Vec3::ZERO, 1e-5));
+        // Stretched out along x to cover both, and as thin as the parts otherwise.
+        assert!((scales.max_element() - (1.0f32 + 0.01).sqrt()).abs() < 1e-4);
+        assert!((scales.min_element() - 0.1).abs() < 1e-4);
+        // Bounds contain the bounds of the parts.
+        assert!(proxy.bound_radius >= 1.0 + 0.3 - 1e-5);
+    }
+}
@@ -0,0 +1,90 @@
+// Motion of the splats of a dynamic scene over time, as trained from videos of several views.
+use burn::{
+    prelude::Backend,
+    tensor::{Tensor, s},
+};
+
+use crate::gaussian_splats::Splats;
+
+/// Offsets of the splats at a number of keyframes spread evenly over the time of a dynamic scene,
+/// from 0 to 1. Splats move linearly between keyframes.
+#[derive(Debug, Clone)]
+pub struct SplatMotion<B: Backend> {
+    /// [N, keyframes, 8] offsets of the splats at rest: 3 of the mean, 4 of the raw rotation and
+    /// 1 of the raw opacity.
+    pub keyframes: Tensor<B, 3>,
+}
+
+/// Blend the [N, K, C] values of the two keyframes around `time` (0 to 1) into [N, C] values.
+/// Times outside 0..1 hold the first or last keyframe.
+pub fn interpolate_keyframes<B: Backend>(keyframes: Tensor<B, 3>, time: f32) -> Tensor<B, 2> {
+    let [n, count, dim] = keyframes.dims();
+    if count == 1 {
+        return keyframes.reshape([n, dim]);
+    }
+    let pos = time.clamp(0.0, 1.0) * (count - 1) as f32;
+    let start = (pos.floor() as usize).min(count - 2);
+    let w = pos - start as f32;
+    let a = keyframes.clone().slice(s![.., start, ..]).reshape([n, dim]);
+    let b = keyframes.slice(s![.., start + 1, ..]).reshape([n, dim]);
+    a * (1.0 - w) + b * w
+}
+
+impl<B: Backend> SplatMotion<B> {
+    pub fn num_keyframes(&self) -> usize {
+        self.keyframes.dims()[1]
+    }
+
+    /// Time of a keyframe, from 0 to 1.
+    pub fn keyframe_time(&self, index: usize) -> f32 {
+        let count = self.num_keyframes();
+        if count < 2 {
+            0.0
+        } else {
+            index as f32 / (count - 1) as f32
+        }
+    }
+
+    /// Move the splats at rest to where they are at `time`.
+    pub fn apply(&self, splats: Splats<B>, time: f32) -> Splats<B> {
+        let offsets = interpolate_keyframes(self.keyframes.clone(), time);
+        let n = offsets.dims()[0];
+        let mut splats = splats;
+        splats.means = splats
+            .means
+            .map(|m| m + offsets.clone().slice(s![.., 0..3]));
+        splats.rotation = splats
+            .rotation
+            .map(|r| r + offsets.clone().slice(s![.., 3..7]));
+        splats.raw_opacity = splats
+            .raw_opacity
+            .map(|o| o + offsets.slice(s![.., 7..8]).reshape([n]));
+        splats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MainBackend;
+    use burn_wgpu::WgpuDevice;
+
+    #[test]
+    fn interpolates_keyframes() {
+        let device = WgpuDevice::DefaultDevice;
+        // One splat, three keyframes at times 0, 0.5 and 1.
+        let keyframes =
+            Tensor::<MainBackend, 3>::from_floats([[[0.0, 1.0], [2.0, 1.0], [4.0, 3.0]]], &device);
+        let at = |time| {
+            interpolate_keyframes(keyframes.clone(), time)
+                .into_data()
+                .into_vec::<f32>()
+                .expect("Wrong type")
+        };
+        assert_eq!(at(0.0), [0.0, 1.0]);
+        assert_eq!(at(0.25), [1.0, 1.0]);
+        assert_eq!(at(0.75), [3.0, 2.0]);
+        assert_eq!(at(1.0), [4.0, 3.0]);
+        assert_eq!(at(1.5), [4.0, 3.0]);
+    }
+}
@@ -0,0 +1,442 @@
+// Culling of splats hidden behind what was drawn in the last frame, for scenes like indoor scans
+// where walls hide most of the splats.
+use burn::{
+    prelude::Backend,
+    tensor::{Bool, Int, Tensor},
+};
+use glam::UVec2;
+
+use crate::{
+    camera::{Camera, project_points},
+    gaussian_splats::Splats,
+    render_aux::RenderAux,
+};
+
+// Pixels hide what's behind them once they're this opaque.
+const OPAQUE_ALPHA: f32 = 0.99;
+// How much further than the depth of a pixel splats need to be to count as hidden, relative to the
+// depth. Covers the camera moving a bit since the depth was rendered.
+const DEPTH_MARGIN: f32 = 0.05;
+// Depth of pixels that hide nothing.
+const FAR: f32 = 1e10;
+// Cells along each axis of the grid splats are clustered in.
+const CLUSTER_CELLS: usize = 16;
+// Classes of splat sizes in each cell. Class `k` holds splats with a 3 sigma radius up to
+// 2^(k - SIZE_CLASS_OFFSET) cells, and the last class any larger splat.
+const SIZE_CLASSES: usize = 8;
+const SIZE_CLASS_OFFSET: i32 = 4;
+
+/// The depth past which nothing shows in a rendered frame, at halving resolutions.
+///
+/// Each texel has the furthest depth of the pixels it covers, so anything behind it is hidden in
+/// all of those pixels.
+pub struct DepthPyramid<B: Backend> {
+    camera: Camera,
+    img_size: UVec2,
+    // All levels, flattened one after another, starting at the full resolution.
+    depths: Tensor<B, 1>,
+    // Start in `depths`, and the width and height of each level.
+    offsets: Tensor<B, 1, Int>,
+    widths: Tensor<B, 1, Int>,
+    heights: Tensor<B, 1, Int>,
+    num_levels: usize,
+}
+
+impl<B: Backend> DepthPyramid<B> {
+    /// Build the pyramid of a render of `splats` from `camera`.
+    ///
+    /// Needs the image and aux data of a render with a float buffer.
+    pub fn from_render(
+        splats: &Splats<B>,
+        camera: &Camera,
+        img: Tensor<B, 3>,
+        aux: &RenderAux<B>,
+    ) -> Self {
+        let [h, w, _] = img.dims();
+
+        // Pixels end on the last splat that was blended into them, which is where they turn
+        // opaque, if they do at all.
+        let final_index: Tensor<B, 2, Int> = Tensor::from_primitive(aux.final_idx.clone());
+        let final_index = final_index.reshape([h * w]);
+        let compact_from_isect: Tensor<B, 1, Int> =
+            Tensor::from_primitive(aux.compact_gid_from_isect.clone());
+        let global_from_compact: Tensor<B, 1, Int> =
+            Tensor::from_primitive(aux.global_from_compact_gid.clone());
+        let max_isect = compact_from_isect.dims()[0] as i32 - 1;
+        let max_compact = global_from_compact.dims()[0] as i32 - 1;
+        let isect = (final_index.clone() - 1).clamp(0, max_isect);
+        let compact = compact_from_isect.select(0, isect).clamp(0, max_compact);
+        let global = global_from_compact.select(0, compact);
+        let (depth, _, _) = project_points(
+            camera,
+            glam::uvec2(w as u32, h as u32),
+            splats.means.val().select(0, global),
+        );
+
+        let alpha = img.slice([0..h, 0..w, 3..4]).reshape([h * w]);
+        let covered =
+            alpha.greater_elem(OPAQUE_ALPHA).float() * final_index.greater_elem(0).float();
+        let depth = depth
+            .mask_fill(covered.lower_elem(0.5), FAR)
+            .reshape([h, w]);
+        Self::from_depth(camera, depth)
+    }
+
+    /// Build the pyramid of a `[height, width]` image of the depth past which nothing shows, as
+    /// seen from `camera`.
+    pub fn from_depth(camera: &Camera, depth: Tensor<B, 2>) -> Self {
+        let device = depth.device();
+        let [h, w] = depth.dims();
+        let mut level = depth;
+
+        let mut levels = vec![];
+        let mut sizes = vec![];
+        let [mut lh, mut lw] = [h, w];
+        loop {
+            levels.push(level.clone().reshape([lh * lw]));
+            sizes.push([lh, lw]);
+            if lh == 1 && lw == 1 {
+                break;
+            }
+            // Pad to an even size with texels that hide nothing, then take the max of each 2x2.
+            if lh % 2 == 1 {
+                level = Tensor::cat(vec![level, Tensor::full([1, lw], FAR, &device)], 0);
+                lh += 1;
+            }
+            if lw % 2 == 1 {
+                level = Tensor::cat(vec![level, Tensor::full([lh, 1], FAR, &device)], 1);
+                lw += 1;
+            }
+            (lh, lw) = (lh / 2, lw / 2);
+            level = level
+                .reshape([lh, 2, lw, 2])
+                .max_dim(3)
+                .max_dim(1)
+                .reshape([lh, lw]);
+        }
+
+        let mut offsets = vec![];
+        let mut start = 0;
+        for [lh, lw] in &sizes {
+            offsets.push(start as i32);
+            start += lh * lw;
+        }
+        let size_table = |f: fn(&[usize; 2]) -> usize| {
+            let values: Vec<i32> = sizes.iter().map(|s| f(s) as i32).collect();
+            Tensor::<B, 1, Int>::from_ints(values.as_slice(), &device)
+        };
+
+        Self {
+            camera: camera.clone(),
+            img_size: glam::uvec2(w as u32, h as u32),
+            depths: Tensor::cat(levels, 0),
+            offsets: Tensor::from_ints(offsets.as_slice(), &device),
+            widths: size_table(|s| s[1]),
+            heights: size_table(|s| s[0]),
+            num_levels: sizes.len(),
+        }
+    }
+
+    /// Make the splats hidden behind the rendered frame fully transparent, which the renderer culls
+    /// before sorting them.
+    ///
+    /// Each splat is tested with a sphere around its 3 sigma extent. Splats not fully on screen in
+    /// the rendered frame are never culled.
+    pub fn cull(&self, splats: &Splats<B>) -> Splats<B> {
+        let hidden = self.hidden(splats.means.val(), splat_radii(splats));
+        hide(splats, hidden)
+    }
+
+    /// Like [`DepthPyramid::cull`], but tests the bounds of each cluster instead of each splat.
+    ///
+    /// This tests far fewer spheres on large scenes, but culls fewer splats, as a cluster is only
+    /// hidden when all of its splats are. `clusters` have to be of `splats`.
+    pub fn cull_clusters(&self, splats: &Splats<B>, clusters: &SplatClusters<B>) -> Splats<B> {
+        let hidden = self.hidden(clusters.centers.clone(), clusters.radii.clone());
+        hide(splats, hidden.select(0, clusters.cluster_of.clone()))
+    }
+
+    // Which of the spheres around `centers` are hidden behind the frame, as 1 for hidden and 0
+    // otherwise.
+    //
+    // Spheres are tested against the level of the pyramid where they cover at most 2x2 texels.
+    // Spheres not fully on screen are never hidden.
+    fn hidden(&self, centers: Tensor<B, 2>, radius: Tensor<B, 1>) -> Tensor<B, 1> {
+        let [w, h] = [self.img_size.x as f32, self.img_size.y as f32];
+
+        let (z, px, py) = project_points(&self.camera, self.img_size, centers);
+        let near = z - radius.clone();
+        let focal = self.camera.focal(self.img_size).max_element();
+        let pixel_radius = radius * focal / near.clone().clamp_min(1e-6);
+
+        let mask = |t: Tensor<B, 1, Bool>| t.float();
+        let left = px.clone() - pixel_radius.clone();
+        let top = py.clone() - pixel_radius.clone();
+        let testable = mask(near.clone().greater_elem(0.01))
+            * mask(left.clone().greater_equal_elem(0.0))
+            * mask(top.clone().greater_equal_elem(0.0))
+            * mask((px + pixel_radius.clone()).lower_elem(w))
+            * mask((py + pixel_radius.clone()).lower_elem(h));
+
+        // The level where texels are at least as big as the sphere.
+        let ln2 = std::f32::consts::LN_2;
+        let level = ((pixel_radius * 2.0).clamp_min(1.0).log() / ln2)
+            .ceil()
+            .clamp(0.0, (self.num_levels - 1) as f32);
+        let texel = (level.clone() * ln2).exp();
+        let level = level.int();
+
+        let offset = self.offsets.clone().select(0, level.clone());
+        let width = self.widths.clone().select(0, level.clone());
+        let height = self.heights.clone().select(0, level);
+        let x0 = (left / texel.clone()).floor().int().clamp_min(0);
+        let y0 = (top / texel).floor().int().clamp_min(0);
+        let x1 = (x0.clone() + 1).min_pair(width.clone() - 1);
+        let y1 = (y0.clone() + 1).min_pair(height - 1);
+
+        let max_index = self.depths.dims()[0] as i32 - 1;
+        let texel_depth = |x: Tensor<B, 1, Int>, y: Tensor<B, 1, Int>| {
+            let index = (offset.clone() + y * width.clone() + x).clamp(0, max_index);
+            self.depths.clone().select(0, index)
+        };
+        let occluder = texel_depth(x0.clone(), y0.clone())
+            .max_pair(texel_depth(x1.clone(), y0))
+            .max_pair(texel_depth(x0, y1.clone()))
+            .max_pair(texel_depth(x1, y1));
+
+        testable * mask(near.greater(occluder * (1.0 + DEPTH_MARGIN)))
+    }
+}
+
+// Radius of a sphere around the 3 sigma extent of each splat.
+fn splat_radii<B: Backend>(splats: &Splats<B>) -> Tensor<B, 1> {
+    let n = splats.num_splats() as usize;
+    splats.log_scales.val().max_dim(1).reshape([n]).exp() * 3.0
+}
+
+// Make the splats where `hidden` is 1 fully transparent.
+fn hide<B: Backend>(splats: &Splats<B>, hidden: Tensor<B, 1>) -> Splats<B> {
+    Splats::from_tensor_data(
+        splats.means.val(),
+        splats.rotation.val(),
+        splats.log_scales.val(),
+        splats.sh_coeffs.val(),
+        splats
+            .raw_opacity
+            .val()
+            .mask_fill(hidden.greater_elem(0.5), -1e4),
+    )
+}
+
+/// Splats grouped into clusters of nearby splats of similar size, for
+/// [`DepthPyramid::cull_clusters`].
+///
+/// Clusters are the cells of a grid over the splats, split by the size of the splats. Their bounds
+/// follow from the cell and the size class, so building them is a few tensor operations, cheap
+/// enough to redo whenever the splats change.
+pub struct SplatClusters<B: Backend> {
+    // Cluster of each splat.
+    cluster_of: Tensor<B, 1, Int>,
+    // Bounding sphere of each cluster.
+    centers: Tensor<B, 2>,
+    radii: Tensor<B, 1>,
+}
+
+impl<B: Backend> SplatClusters<B> {
+    pub fn new(splats: &Splats<B>) -> Self {
+        let device = splats.device();
+        let n = splats.num_splats() as usize;
+        let cells = CLUSTER_CELLS as i32;
+
+        let means = splats.means.val();
+        let min = means.clone().min_dim(0);
+        let extent = (means.clone().max_dim(0) - min.clone()).max_dim(1);
+        let cell_size = (extent / CLUSTER_CELLS as f32).clamp_min(1e-6);
+
+        let cell = ((means - min.clone()) / cell_size.clone())
+            .floor()
+            .int()
+            .clamp(0, cells - 1);
+        let cell = cell.clone().slice([0..n, 0..1])
+            + cell.clone().slice([0..n, 1..2]) * cells
+            + cell.slice([0..n, 2..3]) * (cells * cells);
+        let size_class = ((splat_radii(splats) / cell_size.clone().reshape([1])).log()
+            / std::f32::consts::LN_2)
+            .ceil()
+            .int()
+            .add_scalar(SIZE_CLASS_OFFSET)
+            .clamp(0, SIZE_CLASSES as i32 - 1);
+        let cluster_of = cell.reshape([n]) * SIZE_CLASSES as i32 + size_class;
+
+        // Corner of the cell of each cluster, and the radius of its bounds, in cells.
+        let num_clusters = CLUSTER_CELLS.pow(3) * SIZE_CLASSES;
+        let mut corners = Vec::with_capacity(num_clusters * 3);
+        let mut radii = Vec::with_capacity(num_clusters);
+        for cluster in 0..num_clusters {
+            let (cell, class) = (cluster / SIZE_CLASSES, cluster % SIZE_CLASSES);
+            corners.extend([
+                (cell % CLUSTER_CELLS) as f32,
+                (cell / CLUSTER_CELLS % CLUSTER_CELLS) as f32,
+                (cell / CLUSTER_CELLS.pow(2)) as f32,
+            ]);
+            radii.push(if class == SIZE_CLASSES - 1 {
+                FAR
+            } else {
+                3.0f32.sqrt() / 2.0 + 2.0f32.powi(class as i32 - SIZE_CLASS_OFFSET)
+            });
+        }
+        let corners =
+            Tensor::<B, 1>::from_floats(corners.as_slice(), &device).reshape([num_clusters, 3]);
+        let radii = Tensor::<B, 1>::from_floats(radii.as_slice(), &device);
+
+        Self {
+            cluster_of,
+            centers: min + (corners + 0.5) * cell_size.clone(),
+            radii: radii * cell_size.reshape([1]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MainBackend;
+    use burn_wgpu::WgpuDevice;
+    use glam::{Quat, Vec3, vec2, vec3};
+
+    fn values<const D: usize>(tensor: Tensor<MainBackend, D>) -> Vec<f32> {
+        tensor.into_data().into_vec::<f32>().expect("Wrong type")
+    }
+
+    fn camera() -> Camera {
+        Camera::new(Vec3::ZERO, Quat::IDENTITY, 0.8, 0.8, vec2(0.5, 0.5))
+    }
+
+    // A wall across the whole view at a depth of 2.
+    fn wall(device: &WgpuDevice) -> DepthPyramid<MainBackend> {
+        DepthPyramid::from_depth(&camera(), Tensor::full([64, 64], 2.0, device))
+    }
+
+    // Tiny splats in front of the wall, behind it, and behind it but off screen.
+    fn splats(device: &WgpuDevice) -> Splats<MainBackend> {
+        let means = [
+            vec3(0.0, 0.0, 1.0),
+            vec3(0.1, 0.0, 5.0),
+            vec3(10.0, 0.0, 5.0),
+        ];
+        let log_scales = [Vec3::splat(-5.0); 3];
+        Splats::from_raw(
+            &means,
+            None,
+            Some(log_scales.as_slice()),
+            None,
+            None,
+            device,
+        )
+    }
+
+    fn hidden(splats: &Splats<MainBackend>) -> Vec<bool> {
+        values(splats.raw_opacity.val())
+            .into_iter()
+            .map(|opacity| opacity < -1e3)
+            .collect()
+    }
+
+    #[test]
+    fn pyramid_levels_take_the_furthest_depth() {
+        let device = WgpuDevice::DefaultDevice;
+        #[rustfmt::skip]
+        let depth = [
+            1.0, 2.0, 3.0, 4.0, 5.0,
+            6.0, 7.0, 8.0, 9.0, 1.0,
+            2.0, 3.0, 4.0, 5.0, 6.0,
+        ];
+        let depth = Tensor::<MainBackend, 1>::from_floats(depth, &device).reshape([3, 5]);
+        let pyramid = DepthPyramid::from_depth(&camera(), depth);
+
+        // 5x3, 3x2, 2x1 and 1x1 texels.
+        assert_eq!(pyramid.num_levels, 4);
+        let heights = pyramid.heights.into_data().into_vec::<i32>().expect("Ints");
+        let widths = pyramid.widths.into_data().into_vec::<i32>().expect("Ints");
+        assert_eq!(heights, [3, 2, 1, 1]);
+        assert_eq!(widths, [5, 3, 2, 1]);
+
+        // Odd rows and columns are padded with texels that hide nothing.
+        let depths = values(pyramid.depths);
+        assert_eq!(&depths[15..21], [7.0, 9.0, FAR, FAR, FAR, FAR]);
+        assert_eq!(&depths[21..24], [FAR, FAR, FAR]);
+    }
+
+    #[test]
+    fn splats_behind_the_wall_are_hidden() {
+        let device = WgpuDevice::DefaultDevice;
+        let splats = splats(&device);
+        let pyramid = wall(&device);
+        assert_eq!(hidden(&pyramid.cull(&splats)), [false, true, false]);
+        let clusters = SplatClusters::new(&splats);
+        assert_eq!(
+            hidden(&pyramid.cull_clusters(&splats, &clusters)),
+            [false, true, false]
+        );
+    }
+
+    #[test]
+    fn large_splats_are_not_hidden() {
+        let device = WgpuDevice::DefaultDevice;
+        // Sticks out in front of the wall.
+        let means = [vec3(0.0, 0.0, 3.0)];
+        let log_scales = [Vec3::splat(0.0)];
+        let splats = Splats::from_raw(
+            &means,
+            None,
+            Some(log_scales.as_slice()),
+            None,
+            None,
+            &device,
+        );
+        let pyramid = wall(&device);
+        assert_eq!(hidden(&pyramid.cull(&splats)), [false]);
+        let clusters = SplatClusters::new(&splats);
+        assert_eq!(hidden(&pyramid.cull_clusters(&splats, &clusters)), [false]);
+    }
+
+    #[test]
+    fn clusters_bound_their_splats() {
+        let device = WgpuDevice::DefaultDevice;
+        let means: Vec<_> = (0..200)
+            .map(|i| {
+                let t = i as f32;
+                vec3((t * 0.37).sin() * 4.0, (t * 0.71).cos() * 2.0, t * 0.05)
+            })
+            .collect();
+        let log_scales: Vec<_> = (0..200)
+            .map(|i| Vec3::splat(-4.0 + (i % 7) as f32 * 0.6))
+            .collect();
+        let splats = Splats::from_raw(
+            &means,
+            None,
+            Some(log_scales.as_slice()),
+            None,
+            None,
+            &device,
+        );
+        let clusters = SplatClusters::new(&splats);
+
+        let cluster_of = clusters
+            .cluster_of
+            .into_data()
+            .into_vec::<i32>()
+            .expect("Ints");
+        let centers = values(clusters.centers);
+        let radii = values(clusters.radii);
+        for (i, cluster) in cluster_of.into_iter().enumerate() {
+            let cluster = cluster as usize;
+            let center = Vec3::from_slice(&centers[cluster * 3..cluster * 3 + 3]);
+            let extent = means[i].distance(center) + log_scales[i].max_element().exp() * 3.0;
+            assert!(
+                extent <= radii[cluster] * 1.0001,
+                "Splat {i} sticks out of cluster {cluster}"
+            );
+        }
+    }
+}
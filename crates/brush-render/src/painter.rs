@@ -0,0 +1,418 @@
+// Draws splats into the render passes of other wgpu apps, so engines and UIs can composite splats
+// with their own geometry. The splats are rendered like any other render, copied into a texture,
+// and drawn over the target with premultiplied alpha blending.
+//
+// Splats are drawn without a depth target. Either the geometry behind them goes in the target
+// before they're painted, and geometry in front of them after, or the splats are rendered with the
+// depth of the geometry, which leaves out the splats it hides.
+use std::borrow::Cow;
+
+use brush_kernel::create_tensor;
+use burn::{
+    prelude::Backend,
+    tensor::{DType, Numeric, Tensor, TensorPrimitive},
+};
+use burn_cubecl::{cubecl::Runtime, tensor::CubeTensor};
+use burn_fusion::client::FusionClient;
+use burn_wgpu::{WgpuDevice, WgpuRuntime};
+use glam::UVec2;
+
+use crate::{
+    MainBackend, MainBackendBase, camera::Camera, gaussian_splats::Splats,
+    render::render_with_depth,
+};
+
+// Texture the splats are copied into. Renders hold sRGB colors premultiplied by alpha, which an
+// sRGB texture would decode as if they weren't premultiplied, so painter.wgsl decodes them.
+const IMAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// Pad the width of an image with 4 bytes per pixel for it to be copied into a texture. The
+/// `bytes_per_row` of a texture copy needs to be divisible by 256 in WebGPU, so the width needs
+/// to be divisible by 64.
+pub fn pad_copy_width<K: Numeric<MainBackendBase>>(
+    img: Tensor<MainBackendBase, 3, K>,
+) -> Tensor<MainBackendBase, 3, K> {
+    let [height, width, c] = img.dims();
+    if width % 64 == 0 {
+        return img;
+    }
+    let padded_shape = [height, width.div_ceil(64) * 64, c];
+    Tensor::zeros(padded_shape, &img.device()).slice_assign([0..height, 0..width], img)
+}
+
+/// How the values in the depth texture of another renderer map to view depth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DepthProjection {
+    /// The depth is the view depth already.
+    Linear,
+    /// A perspective projection to depths from 0 at `near` to 1 at `far`, like the projection
+    /// matrices of most wgpu apps. Pixels cleared to 1 hide the splats past the far plane.
+    Perspective { near: f32, far: f32 },
+    /// A perspective projection with reversed depths and without a far plane, from 1 at `near` to
+    /// 0 at infinity, like the cameras of Bevy.
+    ReversedInfinite { near: f32 },
+}
+
+impl DepthProjection {
+    /// The view depth of each pixel in `depth`.
+    pub fn view_depth<B: Backend>(self, depth: Tensor<B, 2>) -> Tensor<B, 2> {
+        match self {
+            Self::Linear => depth,
+            Self::Perspective { near, far } => (depth * (near - far) + far).recip() * (near * far),
+            Self::ReversedInfinite { near } => depth.recip() * near,
+        }
+    }
+}
+
+struct PaintedImage {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Paints splats into render targets of the wgpu device burn runs on.
+///
+/// Each frame, [`Self::prepare`] renders the splats from the camera of the frame, and
+/// [`Self::paint`] draws them in a render pass of the caller. [`Self::render_to_texture`] does
+/// both, in a pass of its own.
+pub struct SplatPainter {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    image: Option<PaintedImage>,
+}
+
+impl SplatPainter {
+    /// A painter for targets of `format`. `device` and `queue` need to be the ones burn renders
+    /// with, see [`crate::burn_init_device`].
+    ///
+    /// Colors are written linear, so targets with a format that isn't sRGB get linear colors.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Splat painter"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("painter.wgsl"))),
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Splat painter"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Splat painter"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Splat painter"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Splat painter"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            device: device.clone(),
+            queue: queue.clone(),
+            pipeline,
+            layout,
+            sampler,
+            image: None,
+        }
+    }
+
+    fn image_for_size(&mut self, size: UVec2) -> &PaintedImage {
+        let resized = self.image.as_ref().is_none_or(|image| {
+            image.texture.width() != size.x || image.texture.height() != size.y
+        });
+        if resized {
+            let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Splat painter image"),
+                size: wgpu::Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: IMAGE_FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Splat painter"),
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            });
+            self.image = Some(PaintedImage {
+                texture,
+                bind_group,
+            });
+        }
+        self.image.as_ref().expect("Image was just created")
+    }
+
+    /// Render `splats` from `camera` at `size` pixels, for the next [`Self::paint`]. Without
+    /// splats, the next paint draws nothing.
+    ///
+    /// The render is queued on the device before returning, so it's done before any work the
+    /// caller submits after.
+    pub fn prepare(&mut self, splats: &Splats<MainBackend>, camera: &Camera, size: UVec2) {
+        // Empty renders can't be drawn.
+        if splats.num_splats() == 0 || size.x == 0 || size.y == 0 {
+            self.image = None;
+            return;
+        }
+        let (img, _) = splats.render(camera, size, false);
+        let img = img.into_primitive().tensor();
+        let fusion_client = img.client.clone();
+        let img = fusion_client.resolve_tensor_float::<MainBackendBase>(img);
+        self.upload(Tensor::from_primitive(TensorPrimitive::Float(img)));
+    }
+
+    /// Like [`Self::prepare`], but leaving out splats behind the geometry in `depth`, the depth
+    /// texture another renderer drew its geometry with. The splats are rendered at the size of
+    /// `depth`, see [`render_with_depth`].
+    ///
+    /// `depth` needs to be a [`wgpu::TextureFormat::Depth32Float`] texture with
+    /// [`wgpu::TextureUsages::COPY_SRC`], drawn with the same camera and a projection following
+    /// `projection`.
+    pub fn prepare_with_depth(
+        &mut self,
+        splats: &Splats<MainBackend>,
+        camera: &Camera,
+        depth: &wgpu::Texture,
+        projection: DepthProjection,
+    ) {
+        let size = glam::uvec2(depth.width(), depth.height());
+        if splats.num_splats() == 0 || size.x == 0 || size.y == 0 {
+            self.image = None;
+            return;
+        }
+        let view_depth = projection.view_depth(self.read_depth(depth, &splats.device()));
+        self.upload(render_with_depth(splats, camera, view_depth));
+    }
+
+    // Copy a depth texture into a tensor.
+    fn read_depth(&self, depth: &wgpu::Texture, device: &WgpuDevice) -> Tensor<MainBackendBase, 2> {
+        let [width, height] = [depth.width() as usize, depth.height() as usize];
+        // Rows of copies need to be aligned like for images, see `pad_copy_width`.
+        let padded_width = width.div_ceil(64) * 64;
+        let client = WgpuRuntime::client(device);
+        let raw = create_tensor::<2, _>([height, padded_width], device, &client, DType::F32);
+        let resource = client.get_resource(raw.handle.clone().binding());
+        client.flush();
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Splat painter depth"),
+            });
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture: depth,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::DepthOnly,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: resource.resource().buffer(),
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: resource.resource().offset(),
+                    bytes_per_row: Some(4 * padded_width as u32),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: width as u32,
+                height: height as u32,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit([encoder.finish()]);
+
+        Tensor::<MainBackendBase, 2>::from_primitive(TensorPrimitive::Float(raw))
+            .slice([0..height, 0..width])
+    }
+
+    // Copy a packed RGBA [H, W, 1] image into the texture painted.
+    fn upload(&mut self, img: Tensor<MainBackendBase, 3>) {
+        let [height, width, _] = img.dims();
+        let size = glam::uvec2(width as u32, height as u32);
+        let img: CubeTensor<WgpuRuntime> = pad_copy_width(img).into_primitive().tensor();
+
+        let texture = self.image_for_size(size).texture.clone();
+        let client = &img.client;
+        let resource = client.get_resource(img.handle.clone().binding());
+        // Make sure the render is submitted before the copy.
+        client.flush();
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Splat painter"),
+            });
+        encoder.copy_buffer_to_texture(
+            wgpu::TexelCopyBufferInfo {
+                buffer: resource.resource().buffer(),
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: resource.resource().offset(),
+                    bytes_per_row: Some(4 * img.shape.dims[1] as u32),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit([encoder.finish()]);
+    }
+
+    /// Draw the splats of the last [`Self::prepare`] into `pass`, stretched over its viewport.
+    /// Does nothing when nothing was prepared.
+    ///
+    /// `pass` needs a single color target with the format of the painter, and no depth target.
+    pub fn paint(&self, pass: &mut wgpu::RenderPass<'_>) {
+        let Some(image) = &self.image else {
+            return;
+        };
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &image.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    /// Render `splats` from `camera` and draw them over `target`, in a render pass recorded into
+    /// `encoder`. The splats are rendered at `target_size`, which should be the size of `target`.
+    pub fn render_to_texture(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        target_size: UVec2,
+        splats: &Splats<MainBackend>,
+        camera: &Camera,
+    ) {
+        self.prepare(splats, camera, target_size);
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Splat painter"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        self.paint(&mut pass);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec4;
+    use wgpu::naga;
+
+    // What painter.wgsl draws for a texel of the splat image.
+    fn painted(texel: Vec4) -> Vec4 {
+        if texel.w <= 0.0 {
+            return Vec4::ZERO;
+        }
+        let srgb = (texel.truncate() / texel.w).clamp(glam::Vec3::ZERO, glam::Vec3::ONE);
+        let linear = srgb.to_array().map(|x| {
+            if x <= 0.04045 {
+                x / 12.92
+            } else {
+                ((x + 0.055) / 1.055).powf(2.4)
+            }
+        });
+        (glam::Vec3::from_array(linear) * texel.w).extend(texel.w)
+    }
+
+    #[test]
+    fn shader_is_valid() {
+        let module = naga::front::wgsl::parse_str(include_str!("painter.wgsl")).expect("Parse");
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::empty(),
+        )
+        .validate(&module)
+        .expect("Validate");
+    }
+
+    #[test]
+    fn decodes_premultiplied_srgb() {
+        let close = |a: Vec4, b: Vec4| (a - b).abs().max_element() < 1e-4;
+        // sRGB 0.5 is linear 0.214.
+        let opaque = painted(Vec4::new(0.5, 0.5, 0.5, 1.0));
+        assert!(
+            close(opaque, Vec4::new(0.2140, 0.2140, 0.2140, 1.0)),
+            "{opaque}"
+        );
+        // The same color at half alpha is half as bright, rather than decoding the premultiplied
+        // value of 0.25, which would make it much darker.
+        let half = painted(Vec4::new(0.25, 0.25, 0.25, 0.5));
+        assert!(close(half, opaque * 0.5), "{half}");
+        assert_eq!(painted(Vec4::ZERO), Vec4::ZERO);
+    }
+}
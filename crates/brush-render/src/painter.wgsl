@@ -0,0 +1,38 @@
+// Draws a rendered splat image over a render target, see `brush_render::painter::SplatPainter`.
+
+@group(0) @binding(0) var splat_image: texture_2d<f32>;
+@group(0) @binding(1) var splat_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4f,
+    @location(0) uv: vec2f,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One triangle covering the whole viewport.
+    let uv = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn srgb_to_linear(x: vec3f) -> vec3f {
+    let low = x / 12.92;
+    let high = pow((x + 0.055) / 1.055, vec3f(2.4));
+    return select(high, low, x <= vec3f(0.04045));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    // The render holds sRGB colors premultiplied by alpha. Blending needs linear colors
+    // premultiplied by alpha, so undo the premultiply before decoding the colors, and redo it
+    // after.
+    let texel = textureSample(splat_image, splat_sampler, in.uv);
+    if texel.a <= 0.0 {
+        return vec4f(0.0);
+    }
+    let srgb = clamp(texel.rgb / texel.a, vec3f(0.0), vec3f(1.0));
+    return vec4f(srgb_to_linear(srgb) * texel.a, texel.a);
+}
@@ -0,0 +1,175 @@
+// Finding what's under a pixel of a render, for editing and measuring tools, and for apps embedding
+// the renderer.
+use burn::{
+    prelude::Backend,
+    tensor::{Int, Tensor, TensorPrimitive},
+};
+use glam::{UVec2, Vec2, Vec3};
+
+use crate::{SplatForward, camera::Camera, gaussian_splats::Splats, shaders::helpers::TILE_WIDTH};
+
+/// What's under a pixel of a render.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PickHit {
+    /// Index of the splat that contributes most to the pixel.
+    pub splat: u32,
+    /// The expected point hit by the pixel ray, at the depth of the blended splats weighted by
+    /// their contribution.
+    pub point: Vec3,
+    /// Opacity of the pixel.
+    pub alpha: f32,
+}
+
+// A splat covering the pixel, as projected by the renderer.
+struct Candidate {
+    xy: Vec2,
+    conic: Vec3,
+    opacity: f32,
+    depth: f32,
+}
+
+// Blend the candidates front to back at `pixel_coord` the same way the rasterizer does. Returns the
+// index of the candidate with the largest weight, the weighted depth, and the final alpha.
+fn composite(candidates: &[Candidate], pixel_coord: Vec2) -> Option<(usize, f32, f32)> {
+    let mut t = 1.0;
+    let mut best = None;
+    let mut best_weight = 0.0;
+    let mut depth_sum = 0.0;
+    let mut weight_sum = 0.0;
+
+    for (i, c) in candidates.iter().enumerate() {
+        let delta = c.xy - pixel_coord;
+        let sigma = 0.5 * (c.conic.x * delta.x * delta.x + c.conic.z * delta.y * delta.y)
+            + c.conic.y * delta.x * delta.y;
+        let alpha = (c.opacity * (-sigma).exp()).min(0.999);
+        if sigma < 0.0 || alpha < 1.0 / 255.0 {
+            continue;
+        }
+        let next_t = t * (1.0 - alpha);
+        if next_t <= 1e-4 {
+            break;
+        }
+        let weight = alpha * t;
+        if weight > best_weight {
+            best_weight = weight;
+            best = Some(i);
+        }
+        depth_sum += weight * c.depth;
+        weight_sum += weight;
+        t = next_t;
+    }
+    best.map(|i| (i, depth_sum / weight_sum, 1.0 - t))
+}
+
+async fn read_ints<B: Backend>(t: Tensor<B, 1, Int>) -> Vec<i32> {
+    t.into_data_async()
+        .await
+        .into_vec::<i32>()
+        .expect("Wrong type")
+}
+
+async fn read_floats<B: Backend, const D: usize>(t: Tensor<B, D>) -> Vec<f32> {
+    t.into_data_async()
+        .await
+        .into_vec::<f32>()
+        .expect("Wrong type")
+}
+
+/// Find the splat and point under `pixel` of a render of `splats` from `camera`.
+///
+/// `pixel` is in pixel coordinates of an image of `img_size`. Returns None when the pixel is off
+/// the image or no splats are drawn there. This renders the splats again and reads back their data
+/// for the pixel, so it's meant for clicks and such rather than every frame.
+pub async fn pick<B: Backend + SplatForward<B>>(
+    splats: &Splats<B>,
+    camera: &Camera,
+    img_size: UVec2,
+    pixel: Vec2,
+) -> Option<PickHit> {
+    if pixel.x < 0.0
+        || pixel.y < 0.0
+        || pixel.x >= img_size.x as f32
+        || pixel.y >= img_size.y as f32
+    {
+        return None;
+    }
+    let (_, aux) = splats.render(camera, img_size, false);
+
+    let pixel = pixel.floor();
+    let tile_width = TILE_WIDTH as f32;
+    let tiles_x = img_size.x.div_ceil(TILE_WIDTH);
+    let tile_id = (pixel.y / tile_width) as u32 * tiles_x + (pixel.x / tile_width) as u32;
+
+    let tile_offsets: Tensor<B, 1, Int> = Tensor::from_primitive(aux.tile_offsets);
+    let tile_id = tile_id as usize;
+    let range = read_ints(tile_offsets.slice([tile_id..tile_id + 2])).await;
+    let compact_from_isect: Tensor<B, 1, Int> = Tensor::from_primitive(aux.compact_gid_from_isect);
+    let max_isect = compact_from_isect.dims()[0];
+    let [start, end] = [range[0], range[1]].map(|i| (i.max(0) as usize).min(max_isect));
+    if start >= end {
+        return None;
+    }
+
+    let compact = compact_from_isect.slice([start..end]);
+    let projected: Tensor<B, 2> =
+        Tensor::from_primitive(TensorPrimitive::Float(aux.projected_splats));
+    let projected = read_floats(projected.select(0, compact.clone())).await;
+    let global_from_compact: Tensor<B, 1, Int> =
+        Tensor::from_primitive(aux.global_from_compact_gid);
+    let global = global_from_compact.select(0, compact);
+    let means = read_floats(splats.means.val().select(0, global.clone())).await;
+    let global = read_ints(global).await;
+
+    let world_to_local = camera.world_to_local();
+    let candidates: Vec<Candidate> = projected
+        .chunks_exact(9)
+        .zip(means.chunks_exact(3))
+        .map(|(p, mean)| Candidate {
+            xy: Vec2::new(p[0], p[1]),
+            conic: Vec3::new(p[2], p[3], p[4]),
+            opacity: p[8],
+            depth: world_to_local.transform_point3(Vec3::from_slice(mean)).z,
+        })
+        .collect();
+
+    let pixel_coord = pixel + 0.5;
+    let (index, depth, alpha) = composite(&candidates, pixel_coord)?;
+    Some(PickHit {
+        splat: global[index] as u32,
+        point: camera.uv_to_world(pixel_coord / img_size.as_vec2(), depth),
+        alpha,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(xy: Vec2, opacity: f32, depth: f32) -> Candidate {
+        Candidate {
+            xy,
+            conic: Vec3::new(1.0, 0.0, 1.0),
+            opacity,
+            depth,
+        }
+    }
+
+    #[test]
+    fn composite_picks_largest_contribution() {
+        let pixel = Vec2::splat(0.5);
+        // A faint splat in front, a strong one behind it, and one that doesn't cover the pixel.
+        let candidates = [
+            candidate(pixel, 0.2, 1.0),
+            candidate(pixel + 100.0, 0.9, 1.5),
+            candidate(pixel, 0.9, 2.0),
+        ];
+        let (index, depth, alpha) = composite(&candidates, pixel).expect("Should hit");
+        assert_eq!(index, 2);
+        // Weights are 0.2 and 0.8 * 0.9.
+        let expected = (0.2 * 1.0 + 0.72 * 2.0) / 0.92;
+        assert!((depth - expected).abs() < 1e-5);
+        assert!((alpha - 0.92).abs() < 1e-5);
+
+        assert!(composite(&candidates[1..2], pixel).is_none());
+    }
+}
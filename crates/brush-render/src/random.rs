@@ -0,0 +1,101 @@
+// Random numbers for stochastic effects in the kernels, eg. random backgrounds or dropping splats
+// while training. These need to be stable: the backward pass has to draw the numbers the forward
+// pass drew, and deterministic runs have to draw the same numbers each run. So rather than keeping
+// RNG state, numbers are a hash of the seed of the render and the ids of a splat and a pixel.
+//
+// This mirrors `random_f32` in helpers.wgsl, for effects that (partly) run on the CPU.
+
+/// Id to pass as the splat or pixel to [`random_f32`] for numbers that don't depend on it.
+pub const NO_ID: u32 = u32::MAX;
+
+// PCG hash, see "Hash Functions for GPU Rendering" (Jarzynski & Olano, 2020).
+fn pcg_hash(x: u32) -> u32 {
+    let state = x.wrapping_mul(747_796_405).wrapping_add(2_891_336_453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277_803_737);
+    (word >> 22) ^ word
+}
+
+/// Random number in [0, 1) for the splat with global id `splat` at pixel `pixel`, the same as the
+/// kernels draw for a render with `seed`.
+///
+/// Effects with more than one number per splat and pixel pick a different `stream` for each.
+pub fn random_f32(seed: u32, splat: u32, pixel: u32, stream: u32) -> f32 {
+    let hash = pcg_hash(pcg_hash(pcg_hash(pcg_hash(seed) ^ splat) ^ pixel) ^ stream);
+    // Top 24 bits, which fit in an f32 exactly.
+    (hash >> 8) as f32 / 16_777_216.0
+}
+
+/// Seed of a render of view `view` at training step `step`, for a run seeded with `run_seed`.
+///
+/// Each step and view draws different numbers, but the same ones each run.
+pub fn step_seed(run_seed: u64, step: u32, view: u32) -> u32 {
+    let run = pcg_hash(run_seed as u32) ^ pcg_hash((run_seed >> 32) as u32);
+    pcg_hash(pcg_hash(run ^ step) ^ view)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_and_uniform() {
+        assert_eq!(random_f32(3, 10, 20, 0), random_f32(3, 10, 20, 0));
+        assert_ne!(random_f32(3, 10, 20, 0), random_f32(3, 10, 20, 1));
+        assert_ne!(random_f32(3, 10, 20, 0), random_f32(4, 10, 20, 0));
+        assert_ne!(step_seed(1, 5, 0), step_seed(1, 6, 0));
+        assert_eq!(step_seed(1, 5, 2), step_seed(1, 5, 2));
+
+        // Neighbouring pixels shouldn't be correlated, so buckets fill about evenly.
+        let mut buckets = [0; 10];
+        for pixel in 0..10_000 {
+            let value = random_f32(7, NO_ID, pixel, 0);
+            assert!((0.0..1.0).contains(&value));
+            buckets[(value * 10.0) as usize] += 1;
+        }
+        assert!(buckets.iter().all(|&count| (900..1100).contains(&count)));
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    #[test]
+    fn kernels_match_cpu() {
+        use crate::{MainBackendBase, kernels::RandomValues, shaders};
+        use brush_kernel::{calc_cube_count, create_tensor, create_uniform_buffer};
+        use burn::tensor::{DType, Tensor, TensorPrimitive};
+        use burn_cubecl::cubecl::{Runtime, server::Bindings};
+        use burn_wgpu::{WgpuDevice, WgpuRuntime};
+
+        let device = WgpuDevice::DefaultDevice;
+        let client = WgpuRuntime::client(&device);
+        let num_values = 1000;
+        for (seed, pixel, stream) in [(0, NO_ID, 0), (12345, 678, 3), (u32::MAX, 0, NO_ID)] {
+            let uniforms = create_uniform_buffer(
+                shaders::random_values::Uniforms {
+                    seed,
+                    pixel,
+                    stream,
+                    num_values,
+                },
+                &device,
+                &client,
+            );
+            let values = create_tensor([num_values as usize], &device, &client, DType::F32);
+            client.execute(
+                RandomValues::task(),
+                calc_cube_count([num_values], RandomValues::WORKGROUP_SIZE),
+                Bindings::new().with_buffers(vec![
+                    uniforms.handle.binding(),
+                    values.clone().handle.binding(),
+                ]),
+            );
+            let values =
+                Tensor::<MainBackendBase, 1>::from_primitive(TensorPrimitive::Float(values))
+                    .into_data()
+                    .into_vec::<f32>()
+                    .expect("Wrong type");
+            let expected: Vec<f32> = (0..num_values)
+                .map(|splat| random_f32(seed, splat, pixel, stream))
+                .collect();
+            assert_eq!(values, expected);
+        }
+    }
+}
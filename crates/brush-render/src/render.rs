@@ -1,7 +1,8 @@
 use crate::{
-    INTERSECTS_UPPER_BOUND, MainBackendBase,
-    camera::Camera,
+    INTERSECTS_UPPER_BOUND, MainBackend, MainBackendBase,
+    camera::{Camera, CameraModel},
     dim_check::DimCheck,
+    gaussian_splats::Splats,
     kernels::{MapGaussiansToIntersect, ProjectSplats, ProjectVisible, Rasterize},
     render_aux::RenderAux,
     sh::sh_degree_from_coeffs,
@@ -15,18 +16,36 @@ use brush_kernel::create_uniform_buffer;
 use brush_kernel::{CubeCount, calc_cube_count};
 use brush_prefix_sum::prefix_sum;
 use brush_sort::radix_argsort;
+use burn::prelude::Backend;
 use burn::tensor::{DType, Int, s};
 use burn::tensor::{
-    Tensor,
+    Tensor, TensorPrimitive,
     ops::{FloatTensorOps, IntTensorOps},
 };
 
 use burn_cubecl::{cubecl::server::Bindings, kernel::into_contiguous};
+use burn_fusion::client::FusionClient;
 use burn_wgpu::CubeTensor;
 use burn_wgpu::WgpuRuntime;
 use glam::uvec2;
 use std::mem::{offset_of, size_of};
 
+/// Pack a float RGBA image into 4 bytes per pixel, like renders without a float buffer.
+pub fn pack_rgba<B: Backend>(img: Tensor<B, 3>) -> Tensor<B, 3, Int> {
+    let [h, w, _] = img.dims();
+    let bytes = (img * 255.0)
+        .clamp(0.0, 255.0)
+        .floor()
+        .int()
+        .reshape([h * w, 4]);
+    let channel = |c: usize| bytes.clone().slice([0..h * w, c..c + 1]);
+    // Little endian, so alpha ends up in the top byte, which is the sign of an i32.
+    let alpha = channel(3);
+    let alpha = alpha.clone() - alpha.greater_equal_elem(128).int() * 256;
+    let packed = channel(0) + channel(1) * 256 + channel(2) * 65536 + alpha * 16_777_216;
+    packed.reshape([h, w, 1])
+}
+
 pub(crate) fn calc_tile_bounds(img_size: glam::UVec2) -> glam::UVec2 {
     uvec2(
         img_size.x.div_ceil(shaders::helpers::TILE_WIDTH),
@@ -51,6 +70,23 @@ pub fn max_intersections(img_size: glam::UVec2, num_splats: u32) -> u32 {
     expected_intersections.min(INTERSECTS_UPPER_BOUND)
 }
 
+/// Copies of a prop to draw along with the splats of a render, see
+/// [`crate::instancing::InstancedSplats`]. The splats of the prop are the last ones of the render.
+pub(crate) struct RenderInstances<T> {
+    /// First splat of the prop.
+    pub start: u32,
+    /// [K, 16] transform of each copy, as a `PropInstance` of helpers.wgsl.
+    pub transforms: T,
+}
+
+impl<T> RenderInstances<T> {
+    /// Number of splats drawn with the copies, for `num_splats` splats in the buffers.
+    pub fn total_splats(&self, num_splats: usize, num_instances: usize) -> usize {
+        let start = self.start as usize;
+        start + (num_splats - start) * num_instances
+    }
+}
+
 pub(crate) fn render_forward(
     camera: &Camera,
     img_size: glam::UVec2,
@@ -60,11 +96,17 @@ pub(crate) fn render_forward(
     sh_coeffs: CubeTensor<WgpuRuntime>,
     opacities: CubeTensor<WgpuRuntime>,
     bwd_info: bool,
+    external_depth: Option<CubeTensor<WgpuRuntime>>,
+    instances: Option<RenderInstances<CubeTensor<WgpuRuntime>>>,
 ) -> (CubeTensor<WgpuRuntime>, RenderAux<MainBackendBase>) {
     assert!(
         img_size[0] > 0 && img_size[1] > 0,
         "Can't render images with 0 size."
     );
+    assert!(
+        !(bwd_info && external_depth.is_some()),
+        "Can't render with an external depth and backward info."
+    );
 
     let device = &means.device.clone();
     let client = means.client.clone();
@@ -79,14 +121,37 @@ pub(crate) fn render_forward(
     let quats = into_contiguous(quats);
     let sh_coeffs = into_contiguous(sh_coeffs);
     let opacities = into_contiguous(opacities);
+    let external_depth = external_depth.map(into_contiguous);
+    let instances = instances.map(|instances| RenderInstances {
+        start: instances.start,
+        transforms: into_contiguous(instances.transforms),
+    });
 
     // Check whether input dimensions are valid.
-    DimCheck::new()
+    let dim_check = DimCheck::new()
         .check_dims("means", &means, &["D".into(), 3.into()])
         .check_dims("log_scales", &log_scales, &["D".into(), 3.into()])
         .check_dims("quats", &quats, &["D".into(), 4.into()])
         .check_dims("sh_coeffs", &sh_coeffs, &["D".into(), "C".into(), 3.into()])
         .check_dims("opacities", &opacities, &["D".into()]);
+    if let Some(external_depth) = &external_depth {
+        dim_check.check_dims(
+            "external_depth",
+            external_depth,
+            &[(img_size.y as usize).into(), (img_size.x as usize).into()],
+        );
+    }
+    if let Some(instances) = &instances {
+        dim_check.check_dims(
+            "instance_transforms",
+            &instances.transforms,
+            &["K".into(), 16.into()],
+        );
+        assert!(
+            (instances.start as usize) < means.shape.dims[0],
+            "Instanced props need at least one splat."
+        );
+    }
 
     // Divide screen into tiles.
     let tile_bounds = calc_tile_bounds(img_size);
@@ -105,7 +170,18 @@ pub(crate) fn render_forward(
 
     // Tile rendering setup.
     let sh_degree = sh_degree_from_coeffs(sh_coeffs.shape.dims[1] as u32);
-    let total_splats = means.shape.dims[0];
+    let (total_splats, instance_start, prop_splats) = match &instances {
+        Some(instances) => {
+            let num_splats = means.shape.dims[0];
+            let num_instances = instances.transforms.shape.dims[0];
+            (
+                instances.total_splats(num_splats, num_instances),
+                instances.start,
+                (num_splats - instances.start as usize) as u32,
+            )
+        }
+        None => (means.shape.dims[0], means.shape.dims[0] as u32, 0),
+    };
     let max_intersects = max_intersections(img_size, total_splats as u32);
 
     let uniforms = shaders::helpers::RenderUniforms {
@@ -118,6 +194,17 @@ pub(crate) fn render_forward(
         sh_degree,
         total_splats: total_splats as u32,
         max_intersects,
+        seed: camera.seed,
+        camera_model: match camera.model {
+            CameraModel::Pinhole => shaders::helpers::CAMERA_PINHOLE,
+            CameraModel::Equirectangular => shaders::helpers::CAMERA_EQUIRECT,
+        },
+        // Depths need to stay positive for the depth sort.
+        near: camera.culling.near.max(1e-6),
+        far: camera.culling.far,
+        min_radius: camera.culling.min_radius,
+        instance_start,
+        prop_splats,
         // Nb: Bit of a hack as these aren't _really_ uniforms but are written to by the shaders.
         num_visible: 0,
     };
@@ -128,26 +215,30 @@ pub(crate) fn render_forward(
 
     let client = &means.client.clone();
 
-    let (global_from_compact_gid, num_visible) = {
+    let (global_from_compact_gid, sorted_depths, num_visible) = {
         let global_from_presort_gid = MainBackendBase::int_zeros([total_splats].into(), device);
         let depths = create_tensor([total_splats], device, client, DType::F32);
 
+        let mut buffers = vec![
+            uniforms_buffer.clone().handle.binding(),
+            means.clone().handle.binding(),
+            quats.clone().handle.binding(),
+            log_scales.clone().handle.binding(),
+            opacities.clone().handle.binding(),
+            global_from_presort_gid.clone().handle.binding(),
+            depths.clone().handle.binding(),
+        ];
+        if let Some(instances) = &instances {
+            buffers.push(instances.transforms.handle.clone().binding());
+        }
+
         tracing::trace_span!("ProjectSplats", sync_burn = true).in_scope(||
             // SAFETY: Kernel checked to have no OOB, bounded loops.
             unsafe {
             client.execute_unchecked(
-                ProjectSplats::task(),
+                ProjectSplats::task(instances.is_some()),
                 calc_cube_count([total_splats as u32], ProjectSplats::WORKGROUP_SIZE),
-                Bindings::new().with_buffers(
-                vec![
-                    uniforms_buffer.clone().handle.binding(),
-                    means.clone().handle.binding(),
-                    quats.clone().handle.binding(),
-                    log_scales.clone().handle.binding(),
-                    opacities.clone().handle.binding(),
-                    global_from_presort_gid.clone().handle.binding(),
-                    depths.clone().handle.binding(),
-                ]),
+                Bindings::new().with_buffers(buffers),
             );
         });
 
@@ -158,14 +249,14 @@ pub(crate) fn render_forward(
             &[num_vis_field_offset..num_vis_field_offset + 1],
         );
 
-        let (_, global_from_compact_gid) = tracing::trace_span!("DepthSort", sync_burn = true)
-            .in_scope(|| {
+        let (sorted_depths, global_from_compact_gid) =
+            tracing::trace_span!("DepthSort", sync_burn = true).in_scope(|| {
                 // Interpret the depth as a u32. This is fine for a radix sort, as long as the depth > 0.0,
                 // which we know to be the case given how we cull splats.
                 radix_argsort(depths, global_from_presort_gid, &num_visible, 32)
             });
 
-        (global_from_compact_gid, num_visible)
+        (global_from_compact_gid, sorted_depths, num_visible)
     };
 
     // Create a buffer of 'projected' splats, that is,
@@ -181,20 +272,25 @@ pub(crate) fn render_forward(
             shaders::project_visible::WORKGROUP_SIZE,
         );
 
+        let mut buffers = vec![
+            uniforms_buffer.clone().handle.binding(),
+            means.handle.binding(),
+            log_scales.handle.binding(),
+            quats.handle.binding(),
+            sh_coeffs.handle.binding(),
+            opacities.handle.binding(),
+            global_from_compact_gid.handle.clone().binding(),
+            projected_splats.handle.clone().binding(),
+        ];
+        if let Some(instances) = &instances {
+            buffers.push(instances.transforms.handle.clone().binding());
+        }
+
         // Normal execute as loops in here could be iffy.
         client.execute(
-            ProjectVisible::task(),
+            ProjectVisible::task(instances.is_some()),
             CubeCount::Dynamic(num_vis_wg.handle.binding()),
-            Bindings::new().with_buffers(vec![
-                uniforms_buffer.clone().handle.binding(),
-                means.handle.binding(),
-                log_scales.handle.binding(),
-                quats.handle.binding(),
-                sh_coeffs.handle.binding(),
-                opacities.handle.binding(),
-                global_from_compact_gid.handle.clone().binding(),
-                projected_splats.handle.clone().binding(),
-            ]),
+            Bindings::new().with_buffers(buffers),
         );
     });
 
@@ -318,6 +414,12 @@ pub(crate) fn render_forward(
 
         (visible, final_idx)
     } else {
+        if let Some(external_depth) = &external_depth {
+            bindings = bindings.with_buffers(vec![
+                sorted_depths.handle.clone().binding(),
+                external_depth.handle.clone().binding(),
+            ]);
+        }
         let visible = create_tensor::<1, _>([1], device, client, DType::F32);
         let final_idx = create_tensor::<2, _>([1, 1], device, client, DType::I32);
         (visible, final_idx)
@@ -325,7 +427,7 @@ pub(crate) fn render_forward(
 
     // Compile the kernel, including/excluding info for backwards pass.
     // see the BWD_INFO define in the rasterize shader.
-    let raster_task = Rasterize::task(bwd_info);
+    let raster_task = Rasterize::task(bwd_info, external_depth.is_some());
 
     // Use safe execution as kernel has some looping which might be unbounded (depending on overflow rules?
     // idk, the slow down seems tiny anyway so might as well).
@@ -348,3 +450,39 @@ pub(crate) fn render_forward(
         },
     )
 }
+
+// Get the tensor of the backend under fusion, running the fused operations it depends on.
+fn resolve<const D: usize>(tensor: Tensor<MainBackend, D>) -> CubeTensor<WgpuRuntime> {
+    let tensor = tensor.into_primitive().tensor();
+    let client = tensor.client.clone();
+    client.resolve_tensor_float::<MainBackendBase>(tensor)
+}
+
+/// Render `splats` like [`Splats::render`] without a float buffer, leaving out the splats hidden
+/// behind the geometry of another renderer. `depth` is the [H, W] view depth of the geometry in
+/// each pixel, in the units of the scene: along the view axis, or the distance to the camera for
+/// panoramas. Pixels without geometry should be infinite.
+///
+/// Splats are hidden when their center is behind the geometry, so splats don't blend into the
+/// geometry they cross. Returns the packed RGBA [H, W, 1] image, in the backend under fusion, like
+/// `depth`, as the textures of other renderers are.
+pub fn render_with_depth(
+    splats: &Splats<MainBackend>,
+    camera: &Camera,
+    depth: Tensor<MainBackendBase, 2>,
+) -> Tensor<MainBackendBase, 3> {
+    let [h, w] = depth.dims();
+    let (img, _) = render_forward(
+        camera,
+        uvec2(w as u32, h as u32),
+        resolve(splats.means.val()),
+        resolve(splats.log_scales.val()),
+        resolve(splats.rotation.val()),
+        resolve(splats.sh_coeffs.val()),
+        resolve(splats.opacities()),
+        false,
+        Some(depth.into_primitive().tensor()),
+        None,
+    );
+    Tensor::from_primitive(TensorPrimitive::Float(img))
+}
@@ -0,0 +1,254 @@
+use crate::{
+    camera::{Camera, project_points},
+    gaussian_splats::Splats,
+    volume::MeshVolume,
+};
+use burn::{
+    prelude::Backend,
+    tensor::{Bool, Int, Tensor, TensorData},
+};
+use glam::{Vec2, Vec3};
+
+/// A region of the screen to select splats with, in pixel coordinates of a view.
+///
+/// Splats are selected based on where their center projects to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectionShape {
+    Rect {
+        min: Vec2,
+        max: Vec2,
+    },
+    /// A closed polygon.
+    Lasso(Vec<Vec2>),
+    /// All splats within `radius` of any point of a brush stroke.
+    Brush {
+        points: Vec<Vec2>,
+        radius: f32,
+    },
+    /// The front most splat within `radius` of `point`.
+    Pick {
+        point: Vec2,
+        radius: f32,
+    },
+}
+
+// Masks are handled as 0/1 floats, which makes combining them simple arithmetic.
+fn mask<B: Backend>(mask: Tensor<B, 1, Bool>) -> Tensor<B, 1> {
+    mask.float()
+}
+
+/// Select splats inside of a shape drawn on the screen, as seen by `camera` with an image of `img_size`.
+///
+/// Returns a mask with one element per splat. Splats behind the camera are never selected.
+pub fn select_splats<B: Backend>(
+    splats: &Splats<B>,
+    camera: &Camera,
+    img_size: glam::UVec2,
+    shape: &SelectionShape,
+) -> Tensor<B, 1, Bool> {
+    let device = splats.device();
+    let n = splats.num_splats() as usize;
+
+    // Project the means to pixel coordinates, like the renderer does for the splat centers.
+    let (depth, x, y) = project_points(camera, img_size, splats.means.val());
+
+    let visible = mask(depth.clone().greater_elem(0.01));
+
+    let selected = match shape {
+        SelectionShape::Rect { min, max } => {
+            let (lo, hi) = (min.min(*max), min.max(*max));
+            mask(x.clone().greater_equal_elem(lo.x))
+                * mask(x.lower_equal_elem(hi.x))
+                * mask(y.clone().greater_equal_elem(lo.y))
+                * mask(y.lower_equal_elem(hi.y))
+        }
+        SelectionShape::Lasso(points) => {
+            // Even-odd rule: count crossings of a ray going right from each point.
+            let mut inside = Tensor::<B, 1>::zeros([n], &device);
+            for (i, a) in points.iter().enumerate() {
+                let b = points[(i + 1) % points.len()];
+                // Horizontal edges never cross the ray.
+                if a.y == b.y {
+                    continue;
+                }
+                let straddles =
+                    (mask(y.clone().greater_elem(a.y)) - mask(y.clone().greater_elem(b.y))).abs();
+                let x_cross = (y.clone() - a.y) * ((b.x - a.x) / (b.y - a.y)) + a.x;
+                let crosses = straddles * mask(x.clone().lower(x_cross));
+                // Xor of 0/1 values.
+                inside = (inside - crosses).abs();
+            }
+            inside
+        }
+        SelectionShape::Brush { points, radius } => {
+            let mut hits = Tensor::<B, 1>::zeros([n], &device);
+            for p in points {
+                let dist_sq = (x.clone() - p.x).powi_scalar(2) + (y.clone() - p.y).powi_scalar(2);
+                hits = hits + mask(dist_sq.lower_elem(radius * radius));
+            }
+            hits.clamp_max(1.0)
+        }
+        SelectionShape::Pick { point, radius } => {
+            let dist_sq = (x - point.x).powi_scalar(2) + (y - point.y).powi_scalar(2);
+            let near = mask(dist_sq.lower_elem(radius * radius)) * visible.clone();
+
+            // Find the closest splat to the camera amongst the ones near the cursor.
+            const FAR: f32 = 1e20;
+            let masked_depth = depth.mask_fill(near.clone().lower_elem(0.5), FAR);
+            let front = masked_depth.argmin(0).repeat_dim(0, n);
+            let ids = Tensor::<B, 1, Int>::arange(0..n as i64, &device);
+            mask(ids.equal(front)) * near
+        }
+    };
+
+    (selected * visible).greater_elem(0.5)
+}
+
+/// Select splats whose center lies inside of an axis aligned box in world space.
+pub fn select_in_box<B: Backend>(splats: &Splats<B>, min: Vec3, max: Vec3) -> Tensor<B, 1, Bool> {
+    let n = splats.num_splats() as usize;
+    let means = splats.means.val();
+
+    let mut inside = Tensor::<B, 1>::ones([n], &splats.device());
+    for axis in 0..3 {
+        let coord = means.clone().slice([0..n, axis..axis + 1]).reshape([n]);
+        inside = inside
+            * mask(coord.clone().greater_equal_elem(min[axis]))
+            * mask(coord.lower_equal_elem(max[axis]));
+    }
+    inside.greater_elem(0.5)
+}
+
+/// Select splats whose center lies inside of a mesh volume.
+///
+/// The test runs on the CPU, as meshes can have any nr. of triangles.
+pub async fn select_in_volume<B: Backend>(
+    splats: &Splats<B>,
+    volume: &MeshVolume,
+) -> Tensor<B, 1, Bool> {
+    let means = splats
+        .means
+        .val()
+        .into_data_async()
+        .await
+        .convert::<f32>()
+        .into_vec::<f32>()
+        .expect("Wrong type");
+    let inside: Vec<bool> = means
+        .chunks_exact(3)
+        .map(|p| volume.contains(Vec3::from_slice(p)))
+        .collect();
+    let n = inside.len();
+    Tensor::from_data(TensorData::new(inside, [n]), &splats.device())
+}
+
+/// Select splats whose center is between `near` and `far` in front of the camera.
+pub fn select_in_depth<B: Backend>(
+    splats: &Splats<B>,
+    camera: &Camera,
+    near: f32,
+    far: f32,
+) -> Tensor<B, 1, Bool> {
+    let device = splats.device();
+    let n = splats.num_splats() as usize;
+
+    let forward = camera.rotation * Vec3::Z;
+    let forward = Tensor::<B, 1>::from_floats(forward.to_array(), &device).reshape([3, 1]);
+    let position = Tensor::<B, 1>::from_floats(camera.position.to_array(), &device).reshape([1, 3]);
+    let depth = (splats.means.val() - position).matmul(forward).reshape([n]);
+
+    (mask(depth.clone().greater_equal_elem(near)) * mask(depth.lower_equal_elem(far)))
+        .greater_elem(0.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MainBackend;
+    use burn_wgpu::WgpuDevice;
+    use glam::{Quat, UVec2};
+
+    const IMG_SIZE: UVec2 = glam::uvec2(100, 100);
+
+    // The camera looks down +Z with a focal length of 50 pixels, so a point at (x, y, z) lands on
+    // pixel (50 + 50 x / z, 50 + 50 y / z).
+    fn camera() -> Camera {
+        let fov = std::f64::consts::FRAC_PI_2;
+        Camera::new(Vec3::ZERO, Quat::IDENTITY, fov, fov, glam::vec2(0.5, 0.5))
+    }
+
+    fn splats() -> Splats<MainBackend> {
+        let means = [
+            // Pixel (50, 50), depth 2.
+            Vec3::new(0.0, 0.0, 2.0),
+            // Pixel (75, 50), depth 2.
+            Vec3::new(1.0, 0.0, 2.0),
+            // Pixel (50, 62.5), depth 4.
+            Vec3::new(0.0, 1.0, 4.0),
+            // Behind the camera.
+            Vec3::new(0.0, 0.0, -2.0),
+            // Pixel (50, 50), depth 6.
+            Vec3::new(0.0, 0.0, 6.0),
+        ];
+        Splats::from_raw(&means, None, None, None, None, &WgpuDevice::DefaultDevice)
+    }
+
+    fn selected(mask: Tensor<MainBackend, 1, Bool>) -> Vec<usize> {
+        let mask = mask.int().into_data().to_vec::<i32>().expect("Wrong type");
+        (0..mask.len()).filter(|&i| mask[i] != 0).collect()
+    }
+
+    fn select(shape: SelectionShape) -> Vec<usize> {
+        selected(select_splats(&splats(), &camera(), IMG_SIZE, &shape))
+    }
+
+    #[test]
+    fn selects_in_rect() {
+        // Corners can be given in any order.
+        let shape = SelectionShape::Rect {
+            min: Vec2::new(60.0, 55.0),
+            max: Vec2::new(40.0, 40.0),
+        };
+        assert_eq!(select(shape), [0, 4]);
+    }
+
+    #[test]
+    fn selects_in_lasso() {
+        let triangle = vec![
+            Vec2::new(70.0, 40.0),
+            Vec2::new(80.0, 40.0),
+            Vec2::new(75.0, 60.0),
+        ];
+        assert_eq!(select(SelectionShape::Lasso(triangle)), [1]);
+    }
+
+    #[test]
+    fn selects_along_brush() {
+        let shape = SelectionShape::Brush {
+            points: vec![Vec2::new(75.0, 50.0), Vec2::new(50.0, 63.0)],
+            radius: 2.0,
+        };
+        assert_eq!(select(shape), [1, 2]);
+    }
+
+    #[test]
+    fn picks_front_most() {
+        let shape = SelectionShape::Pick {
+            point: Vec2::new(50.0, 50.0),
+            radius: 3.0,
+        };
+        assert_eq!(select(shape), [0]);
+    }
+
+    #[test]
+    fn selects_in_world_space() {
+        let in_box = select_in_box(
+            &splats(),
+            Vec3::new(-0.5, -0.5, 1.0),
+            Vec3::new(0.5, 1.5, 5.0),
+        );
+        assert_eq!(selected(in_box), [0, 2]);
+        let in_depth = select_in_depth(&splats(), &camera(), 3.0, 7.0);
+        assert_eq!(selected(in_depth), [2, 4]);
+    }
+}
@@ -1,7 +1,7 @@
 use crate::shaders;
 
 use glam::Vec3;
-const SH_C0: f32 = shaders::project_visible::SH_C0;
+pub(crate) const SH_C0: f32 = shaders::project_visible::SH_C0;
 
 pub const fn sh_coeffs_for_degree(degree: u32) -> u32 {
     (degree + 1).pow(2)
@@ -29,3 +29,8 @@ pub fn rgb_to_sh(rgb: Vec3) -> Vec3 {
         channel_to_sh(rgb.z),
     )
 }
+
+/// The color of a splat from its base SH coefficients, the inverse of [`rgb_to_sh`].
+pub fn sh_to_rgb(sh: Vec3) -> Vec3 {
+    sh * SH_C0 + 0.5
+}
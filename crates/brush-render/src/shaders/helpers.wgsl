@@ -33,8 +33,33 @@ struct RenderUniforms {
 
     total_splats: u32,
     max_intersects: u32,
+
+    // Seed of the random numbers of this render, see `random_f32`.
+    seed: u32,
+
+    // How the camera maps directions to pixels, one of the CAMERA_ constants.
+    camera_model: u32,
+
+    // Splats outside the depth range [near, far] or with a smaller radius in pixels than
+    // min_radius are culled, see `brush_render::camera::Culling`.
+    near: f32,
+    far: f32,
+    min_radius: f32,
+
+    // Kernels compiled with INSTANCED draw the prop_splats splats from instance_start on once for
+    // each copy of the prop, see `brush_render::instancing`. Global ids past instance_start count
+    // through the splats of each copy in turn, and total_splats counts all copies.
+    instance_start: u32,
+    prop_splats: u32,
 }
 
+// Camera models, see `brush_render::camera::CameraModel`.
+const CAMERA_PINHOLE: u32 = 0u;
+const CAMERA_EQUIRECT: u32 = 1u;
+
+// Distance from the axis of a panorama under which splats count as at the pole.
+const EQUIRECT_MIN_RHO: f32 = 1e-4;
+
 // nb: this struct has a bunch of padding but that's probably fine.
 struct ProjectedSplat {
     xy_x: f32,
@@ -120,6 +145,34 @@ fn quat_to_mat(quat: vec4f) -> mat3x3f {
     );
 }
 
+// A placed copy of a prop, see `brush_render::instancing::InstancedSplats`.
+struct PropInstance {
+    // Rotation, as a (w, x, y, z) quaternion like the rotations of splats.
+    rotation: vec4f,
+    // Translation in xyz, and the scale in w.
+    translation_scale: vec4f,
+    // Factor on the colors of the prop in rgb.
+    tint: vec4f,
+    // Offset of the colors, added after the tint, in rgb.
+    shift: vec4f,
+}
+
+// Product of two (w, x, y, z) quaternions.
+fn quat_mul(a: vec4f, b: vec4f) -> vec4f {
+    return vec4f(
+        a.x * b.x - a.y * b.y - a.z * b.z - a.w * b.w,
+        a.x * b.y + a.y * b.x + a.z * b.w - a.w * b.z,
+        a.x * b.z - a.y * b.w + a.z * b.x + a.w * b.y,
+        a.x * b.w + a.y * b.z - a.z * b.y + a.w * b.x,
+    );
+}
+
+// Where a point of the prop ends up for a copy.
+fn instance_point(instance: PropInstance, point: vec3f) -> vec3f {
+    let translation_scale = instance.translation_scale;
+    return quat_to_mat(instance.rotation) * (point * translation_scale.w) + translation_scale.xyz;
+}
+
 fn scale_to_mat(scale: vec3f) -> mat3x3f {
     return mat3x3(
         vec3f(scale.x, 0.0, 0.0),
@@ -133,7 +186,46 @@ fn calc_cov3d(scale: vec3f, quat: vec4f) -> mat3x3f {
     return M * transpose(M);
 }
 
-fn calc_cam_J(mean_c: vec3f, focal: vec2f, img_size: vec2u, pixel_center: vec2f) -> mat3x2f {
+// Depth to sort and cull splats by: along the view axis, or the distance for panoramas.
+fn view_depth(mean_c: vec3f, camera_model: u32) -> f32 {
+    if camera_model == CAMERA_EQUIRECT {
+        return length(mean_c);
+    }
+    return mean_c.z;
+}
+
+// Project a point in camera space to pixels.
+fn project_mean(mean_c: vec3f, focal: vec2f, pixel_center: vec2f, camera_model: u32) -> vec2f {
+    if camera_model == CAMERA_EQUIRECT {
+        // Longitude around the y axis, and latitude with y pointing down.
+        let rho = max(length(mean_c.xz), EQUIRECT_MIN_RHO);
+        return focal * vec2f(atan2(mean_c.x, mean_c.z), atan2(mean_c.y, rho)) + pixel_center;
+    }
+    return focal * mean_c.xy * (1.0 / mean_c.z) + pixel_center;
+}
+
+// Jacobian of `project_mean` for panoramas.
+fn calc_equirect_J(mean_c: vec3f, focal: vec2f) -> mat3x2f {
+    let x = mean_c.x;
+    let y = mean_c.y;
+    let z = mean_c.z;
+    let rho2 = max(x * x + z * z, EQUIRECT_MIN_RHO * EQUIRECT_MIN_RHO);
+    let rho = sqrt(rho2);
+    let r2 = rho2 + y * y;
+    let g = y / (rho * r2);
+
+    return mat3x2f(
+        vec2f(focal.x * z / rho2, -focal.y * x * g),
+        vec2f(0.0, focal.y * rho / r2),
+        vec2f(-focal.x * x / rho2, -focal.y * z * g),
+    );
+}
+
+fn calc_cam_J(mean_c: vec3f, focal: vec2f, img_size: vec2u, pixel_center: vec2f, camera_model: u32) -> mat3x2f {
+    if camera_model == CAMERA_EQUIRECT {
+        return calc_equirect_J(mean_c, focal);
+    }
+
     let tan_fov = 0.5 * vec2f(img_size.xy) / focal;
 
     let lims_pos = (vec2f(img_size.xy) - pixel_center) / focal + 0.3f * tan_fov;
@@ -154,11 +246,11 @@ fn calc_cam_J(mean_c: vec3f, focal: vec2f, img_size: vec2u, pixel_center: vec2f)
     return J;
 }
 
-fn calc_cov2d(cov3d: mat3x3f, mean_c: vec3f, focal: vec2f, img_size: vec2u, pixel_center: vec2f, viewmat: mat4x4f) -> mat2x2f {
+fn calc_cov2d(cov3d: mat3x3f, mean_c: vec3f, focal: vec2f, img_size: vec2u, pixel_center: vec2f, viewmat: mat4x4f, camera_model: u32) -> mat2x2f {
     let R = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
     let covar_cam = R * cov3d * transpose(R);
 
-    let J = calc_cam_J(mean_c, focal, img_size, pixel_center);
+    let J = calc_cam_J(mean_c, focal, img_size, pixel_center, camera_model);
 
     var cov2d = J * covar_cam * transpose(J);
 
@@ -293,6 +385,30 @@ fn as_packed(vec: vec3f) -> PackedVec3 {
     return PackedVec3(vec.x, vec.y, vec.z);
 }
 
+// PCG hash, see "Hash Functions for GPU Rendering" (Jarzynski & Olano, 2020).
+fn pcg_hash(x: u32) -> u32 {
+    let state = x * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Random number in [0, 1) for a splat (by global id) at a pixel, for stochastic effects.
+//
+// The numbers only depend on the seed of the render and the ids passed in, so the backward pass
+// draws the same numbers as the forward pass, and a view renders the same way each time it's
+// rendered with the same seed. Effects with more than one random number per splat and pixel pick
+// a different `stream` for each. Pass `NO_ID` as the pixel for numbers per splat, and as the
+// splat for numbers per pixel.
+//
+// Mirrored by `brush_render::random::random_f32`.
+fn random_f32(seed: u32, global_gid: u32, pix_id: u32, stream: u32) -> f32 {
+    let hash = pcg_hash(pcg_hash(pcg_hash(pcg_hash(seed) ^ global_gid) ^ pix_id) ^ stream);
+    // Top 24 bits, which fit in an f32 exactly.
+    return f32(hash >> 8u) / 16777216.0;
+}
+
+const NO_ID: u32 = 0xffffffffu;
+
 // fn sigmoid(x: f32) -> f32 {
 //     return 1.0 / (1.0 + exp(-x));
 // }
@@ -0,0 +1,93 @@
+#import helpers;
+
+struct Uniforms {
+    // Side of the cells of the grid.
+    cell_size: f32,
+    // Nr. of hash buckets the cells are spread over.
+    num_buckets: u32,
+    // Nr. of points, and the first point of this dispatch.
+    num_points: u32,
+    offset: u32,
+}
+
+@group(0) @binding(0) var<storage, read> uniforms: Uniforms;
+// Points, sorted by the bucket of their cell.
+@group(0) @binding(1) var<storage, read> points: array<helpers::PackedVec3>;
+// Index of the first point of each bucket, and one past the last point.
+@group(0) @binding(2) var<storage, read> bucket_starts: array<u32>;
+@group(0) @binding(3) var<storage, read_write> log_scales: array<f32>;
+
+// Give up looking for neighbours this many cells away.
+const MAX_RINGS: i32 = 4;
+
+// Nb: Must match the hash the cells are sorted with.
+fn bucket_of(cell: vec3i) -> u32 {
+    let hash = (bitcast<u32>(cell.x) * 73856093u) ^
+        (bitcast<u32>(cell.y) * 19349663u) ^
+        (bitcast<u32>(cell.z) * 83492791u);
+    return hash % uniforms.num_buckets;
+}
+
+@compute
+@workgroup_size(256, 1, 1)
+fn main(@builtin(global_invocation_id) gid: vec3u) {
+    let index = uniforms.offset + gid.x;
+    if index >= uniforms.num_points {
+        return;
+    }
+
+    let pos = helpers::as_vec(points[index]);
+    let cell = vec3i(floor(pos / uniforms.cell_size));
+
+    // Squared distances and indices of the two nearest points found so far.
+    var near_0 = 1e30f;
+    var near_1 = 1e30f;
+    var index_0 = index;
+    var index_1 = index;
+
+    // Search shells of cells around the cell of the point, until no point further out can be
+    // nearer than the ones found.
+    for (var ring = 0; ring <= MAX_RINGS; ring++) {
+        for (var dz = -ring; dz <= ring; dz++) {
+            for (var dy = -ring; dy <= ring; dy++) {
+                for (var dx = -ring; dx <= ring; dx++) {
+                    if max(abs(dx), max(abs(dy), abs(dz))) != ring {
+                        continue;
+                    }
+
+                    let bucket = bucket_of(cell + vec3i(dx, dy, dz));
+                    let end = bucket_starts[bucket + 1u];
+                    for (var other = bucket_starts[bucket]; other < end; other++) {
+                        // Cells can share a bucket, so the same point can show up twice.
+                        if other == index || other == index_0 || other == index_1 {
+                            continue;
+                        }
+                        let delta = helpers::as_vec(points[other]) - pos;
+                        let dist = dot(delta, delta);
+                        if dist < near_0 {
+                            near_1 = near_0;
+                            index_1 = index_0;
+                            near_0 = dist;
+                            index_0 = other;
+                        } else if dist < near_1 {
+                            near_1 = dist;
+                            index_1 = other;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Points outside of the searched cells are at least this far away.
+        let searched = f32(ring) * uniforms.cell_size;
+        if near_1 <= searched * searched {
+            break;
+        }
+    }
+
+    // Isolated points get the size of the searched area.
+    let max_dist = f32(MAX_RINGS + 1) * uniforms.cell_size;
+    let dist_0 = min(sqrt(near_0), max_dist);
+    let dist_1 = min(sqrt(near_1), max_dist);
+    log_scales[index] = log(max(0.25 * (dist_0 + dist_1), 1e-12f));
+}
@@ -13,6 +13,23 @@
 @group(0) @binding(5) var<storage, read_write> global_from_compact_gid: array<u32>;
 @group(0) @binding(6) var<storage, read_write> depths: array<f32>;
 
+#ifdef INSTANCED
+@group(0) @binding(7) var<storage, read> instances: array<helpers::PropInstance>;
+#endif
+
+// Whether a sphere in camera space is at least partially inside the side planes of the view frustum.
+fn in_frustum(center: vec3f, radius: f32, focal: vec2f, pixel_center: vec2f, img_size: vec2f) -> bool {
+    // Planes through the camera origin, with normals pointing into the frustum.
+    let left = normalize(vec3f(focal.x, 0.0, pixel_center.x));
+    let right = normalize(vec3f(-focal.x, 0.0, img_size.x - pixel_center.x));
+    let top = normalize(vec3f(0.0, focal.y, pixel_center.y));
+    let bottom = normalize(vec3f(0.0, -focal.y, img_size.y - pixel_center.y));
+    // Some slack as the projected footprint is only an approximation near the edges.
+    let slack = 1.2 * radius;
+    return dot(left, center) > -slack && dot(right, center) > -slack &&
+        dot(top, center) > -slack && dot(bottom, center) > -slack;
+}
+
 @compute
 @workgroup_size(512, 1, 1)
 fn main(@builtin(global_invocation_id) global_id: vec3u) {
@@ -22,8 +39,27 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
         return;
     }
 
+#ifdef INSTANCED
+    // Copies of the prop read the splats of the prop.
+    let instanced = global_gid >= uniforms.instance_start;
+    let prop_gid = global_gid - uniforms.instance_start;
+    let source_gid = select(
+        global_gid,
+        uniforms.instance_start + prop_gid % uniforms.prop_splats,
+        instanced
+    );
+    let instance = instances[select(0u, prop_gid / uniforms.prop_splats, instanced)];
+#else
+    let source_gid = global_gid;
+#endif
+
     // Project world space to camera space.
-    let mean = helpers::as_vec(means[global_gid]);
+    var mean = helpers::as_vec(means[source_gid]);
+#ifdef INSTANCED
+    if instanced {
+        mean = helpers::instance_point(instance, mean);
+    }
+#endif
 
     let img_size = uniforms.img_size;
     let viewmat = uniforms.viewmat;
@@ -31,11 +67,39 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
     let mean_c = R * mean + viewmat[3].xyz;
 
     // Check if this splat is 'valid' (aka visible). Phrase as positive to bail on NaN.
-    var valid = true;
-    valid &= (mean_c.z > 0.01 && mean_c.z < 1e10);
+    // Cheap checks go first, so splats which are culled anyway skip the covariance math and most
+    // of the memory reads. For large scenes, most splats are usually culled here.
+    let depth = helpers::view_depth(mean_c, uniforms.camera_model);
+    if !(depth > uniforms.near && depth < uniforms.far) {
+        return;
+    }
 
-    let scale = exp(helpers::as_vec(log_scales[global_gid]));
-    var quat = quats[global_gid];
+    let opac = opacities[source_gid];
+    if !(opac > 1.0 / 255.0) {
+        return;
+    }
+
+    // Conservative frustum check, with a sphere around the 3 sigma extent of the splat.
+    var scale = exp(helpers::as_vec(log_scales[source_gid]));
+#ifdef INSTANCED
+    if instanced {
+        scale *= instance.translation_scale.w;
+    }
+#endif
+    let bound = 3.0 * max(scale.x, max(scale.y, scale.z));
+    // Panoramas see in all directions.
+    if uniforms.camera_model == helpers::CAMERA_PINHOLE &&
+        !in_frustum(mean_c, bound, uniforms.focal, uniforms.pixel_center, vec2f(img_size)) {
+        return;
+    }
+
+    var valid = true;
+    var quat = quats[source_gid];
+#ifdef INSTANCED
+    if instanced {
+        quat = helpers::quat_mul(instance.rotation, quat);
+    }
+#endif
 
     // Skip any invalid rotations. This will mean overtime
     // these gaussians just die off while optimizing. For the viewer, the importer
@@ -45,7 +109,7 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
     quat = normalize(quat);
 
     let cov3d = helpers::calc_cov3d(scale, quat);
-    let cov2d = helpers::calc_cov2d(cov3d, mean_c, uniforms.focal, uniforms.img_size, uniforms.pixel_center, viewmat);
+    let cov2d = helpers::calc_cov2d(cov3d, mean_c, uniforms.focal, uniforms.img_size, uniforms.pixel_center, viewmat, uniforms.camera_model);
     let det = determinant(cov2d);
 
     valid &= det > 0.0;
@@ -54,15 +118,10 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
     let conic = helpers::inverse(cov2d);
 
     // compute the projected mean
-    let mean2d = uniforms.focal * mean_c.xy * (1.0 / mean_c.z) + uniforms.pixel_center;
-
-    let opac = opacities[global_gid];
-
-    // Phrase as positive to bail on NaN.
-    valid &= opac > 1.0 / 255.0;
+    let mean2d = helpers::project_mean(mean_c, uniforms.focal, uniforms.pixel_center, uniforms.camera_model);
 
     let radius = helpers::radius_from_cov(cov2d, opac);
-    valid &= radius > 0.0;
+    valid &= radius > 0.0 && radius >= uniforms.min_radius;
     valid &= mean2d.x + radius > 0 && mean2d.x - radius < f32(uniforms.img_size.x) &&
             mean2d.y + radius > 0 && mean2d.y - radius < f32(uniforms.img_size.y);
 
@@ -74,5 +133,5 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
     // Now write all the data to the buffers.
     let write_id = atomicAdd(&uniforms.num_visible, 1);
     global_from_compact_gid[write_id] = global_gid;
-    depths[write_id] = mean_c.z;
+    depths[write_id] = depth;
 }
@@ -19,6 +19,10 @@ struct IsectInfo {
 
 @group(0) @binding(7) var<storage, read_write> projected: array<helpers::ProjectedSplat>;
 
+#ifdef INSTANCED
+@group(0) @binding(8) var<storage, read> instances: array<helpers::PropInstance>;
+#endif
+
 struct ShCoeffs {
     b0_c0: vec3f,
 
@@ -175,28 +179,48 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
 
     let global_gid = global_from_compact_gid[compact_gid];
 
+#ifdef INSTANCED
+    // Copies of the prop read the splats of the prop.
+    let instanced = u32(global_gid) >= uniforms.instance_start;
+    let prop_gid = u32(global_gid) - uniforms.instance_start;
+    let source_gid = select(
+        u32(global_gid),
+        uniforms.instance_start + prop_gid % uniforms.prop_splats,
+        instanced
+    );
+    let instance = instances[select(0u, prop_gid / uniforms.prop_splats, instanced)];
+#else
+    let source_gid = u32(global_gid);
+#endif
+
     // Project world space to camera space.
-    let mean = helpers::as_vec(means[global_gid]);
-    let scale = exp(helpers::as_vec(log_scales[global_gid]));
+    var mean = helpers::as_vec(means[source_gid]);
+    var scale = exp(helpers::as_vec(log_scales[source_gid]));
     // Safe to normalize, splats with length(quat) == 0 are invisible.
-    let quat = normalize(quats[global_gid]);
-    let opac = opacities[global_gid];
+    var quat = normalize(quats[source_gid]);
+    let opac = opacities[source_gid];
+#ifdef INSTANCED
+    if instanced {
+        mean = helpers::instance_point(instance, mean);
+        scale *= instance.translation_scale.w;
+        quat = helpers::quat_mul(instance.rotation, quat);
+    }
+#endif
 
     let viewmat = uniforms.viewmat;
     let R = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
     let mean_c = R * mean + viewmat[3].xyz;
 
     let covar = helpers::calc_cov3d(scale, quat);
-    let cov2d = helpers::calc_cov2d(covar, mean_c, uniforms.focal, uniforms.img_size, uniforms.pixel_center, viewmat);
+    let cov2d = helpers::calc_cov2d(covar, mean_c, uniforms.focal, uniforms.img_size, uniforms.pixel_center, viewmat, uniforms.camera_model);
     let conic = helpers::inverse(cov2d);
 
     // compute the projected mean
-    let rz = 1.0 / mean_c.z;
-    let mean2d = uniforms.focal * mean_c.xy * rz + uniforms.pixel_center;
+    let mean2d = helpers::project_mean(mean_c, uniforms.focal, uniforms.pixel_center, uniforms.camera_model);
 
     let sh_degree = uniforms.sh_degree;
     let num_coeffs = num_sh_coeffs(sh_degree);
-    var base_id = u32(global_gid) * num_coeffs;
+    var base_id = source_gid * num_coeffs;
 
     var sh = ShCoeffs();
     sh.b0_c0 = read_coeffs(&base_id);
@@ -240,6 +264,11 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     // Write projected splat information.
     let viewdir = normalize(mean - uniforms.camera_position.xyz);
     var color = sh_coeffs_to_color(sh_degree, viewdir, sh) + vec3f(0.5);
+#ifdef INSTANCED
+    if instanced {
+        color = color * instance.tint.rgb + instance.shift.rgb;
+    }
+#endif
 
     projected[compact_gid] = helpers::create_projected_splat(
         mean2d,
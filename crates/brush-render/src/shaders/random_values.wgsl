@@ -0,0 +1,23 @@
+#import helpers;
+
+// Draws `random_f32` for a range of splat ids, to check the kernels draw the same numbers as the
+// CPU mirror in `brush_render::random`.
+struct Uniforms {
+    seed: u32,
+    pixel: u32,
+    stream: u32,
+    num_values: u32,
+}
+
+@group(0) @binding(0) var<storage, read> uniforms: Uniforms;
+@group(0) @binding(1) var<storage, read_write> values: array<f32>;
+
+@compute
+@workgroup_size(256, 1, 1)
+fn main(@builtin(global_invocation_id) gid: vec3u) {
+    let splat = gid.x;
+    if splat >= uniforms.num_values {
+        return;
+    }
+    values[splat] = helpers::random_f32(uniforms.seed, splat, uniforms.pixel, uniforms.stream);
+}
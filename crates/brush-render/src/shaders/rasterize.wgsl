@@ -15,12 +15,24 @@
     @group(0) @binding(4) var<storage, read_write> out_img: array<u32>;
 #endif
 
+// Only without BWD_INFO. Splats behind the geometry of another renderer in a pixel are left out.
+#ifdef EXTERNAL_DEPTH
+    // View depth of the visible splats, in depth order.
+    @group(0) @binding(5) var<storage, read> sorted_depths: array<f32>;
+    // View depth of the geometry in each pixel.
+    @group(0) @binding(6) var<storage, read> external_depth: array<f32>;
+#endif
+
 var<workgroup> local_batch: array<helpers::ProjectedSplat, helpers::TILE_SIZE>;
 
 #ifdef BWD_INFO
     var<workgroup> load_gid: array<u32, helpers::TILE_SIZE>;
 #endif
 
+#ifdef EXTERNAL_DEPTH
+    var<workgroup> load_depth: array<f32, helpers::TILE_SIZE>;
+#endif
+
 var<workgroup> done_count: atomic<u32>;
 var<workgroup> done_count_uniform: u32;
 
@@ -46,6 +58,13 @@ fn main(
     let inside = global_id.x < img_size.x && global_id.y < img_size.y;
     var done = !inside;
 
+    #ifdef EXTERNAL_DEPTH
+        var pixel_depth = 0.0;
+        if inside {
+            pixel_depth = external_depth[pix_id];
+        }
+    #endif
+
     // have all threads in tile process the same gaussians in batches
     // first collect gaussians between the bin counts.
     let range = vec2u(
@@ -91,11 +110,25 @@ fn main(
             #ifdef BWD_INFO
                 load_gid[local_idx] = u32(global_from_compact_gid[compact_gid]);
             #endif
+
+            // Splats are sorted by depth, so compact ids are in depth order too.
+            #ifdef EXTERNAL_DEPTH
+                load_depth[local_idx] = sorted_depths[compact_gid];
+            #endif
         }
         // Wait for all writes to complete.
         workgroupBarrier();
 
         for (var t = 0u; t < remaining && !done; t++) {
+            // The splats of a tile go front to back, so all splats from here on are hidden.
+            #ifdef EXTERNAL_DEPTH
+                if load_depth[t] > pixel_depth {
+                    atomicAdd(&done_count, 1u);
+                    done = true;
+                    break;
+                }
+            #endif
+
             let projected = local_batch[t];
 
             let xy = vec2f(projected.xy_x, projected.xy_y);
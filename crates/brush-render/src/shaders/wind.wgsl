@@ -0,0 +1,56 @@
+#import helpers;
+
+struct Uniforms {
+    num_splats: u32,
+    num_labels: u32,
+    // Time of the sway, in seconds.
+    time: f32,
+}
+
+// Columns of the table of sway parameters per label, see `brush_render::wind`.
+const PARAMS: u32 = 7u;
+const TAU: f32 = 6.28318530718;
+
+@group(0) @binding(0) var<storage, read> uniforms: Uniforms;
+@group(0) @binding(1) var<storage, read> means: array<helpers::PackedVec3>;
+@group(0) @binding(2) var<storage, read> labels: array<i32>;
+// Rows of [amplitude, frequency, wavelength, turbulence, direction] per label.
+@group(0) @binding(3) var<storage, read> params: array<f32>;
+@group(0) @binding(4) var<storage, read_write> swayed_means: array<helpers::PackedVec3>;
+
+@compute
+@workgroup_size(256, 1, 1)
+fn main(@builtin(global_invocation_id) gid: vec3u) {
+    let index = gid.x;
+    if index >= uniforms.num_splats {
+        return;
+    }
+
+    let mean = helpers::as_vec(means[index]);
+    let label = u32(labels[index]);
+    // Labels without parameters stay still.
+    if label >= uniforms.num_labels {
+        swayed_means[index] = helpers::as_packed(mean);
+        return;
+    }
+
+    let base = label * PARAMS;
+    let amplitude = params[base];
+    let frequency = params[base + 1u];
+    let wavelength = params[base + 2u];
+    let turbulence = params[base + 3u];
+    let direction = vec3f(params[base + 4u], params[base + 5u], params[base + 6u]);
+
+    // Sway along the direction of the group, in a wave moving through it.
+    let cycle = frequency * TAU * uniforms.time;
+    let phase = dot(mean, direction) / wavelength * TAU;
+    let sway = sin(cycle - phase) * amplitude * direction;
+
+    // A different phase per splat, picked by hashing its position.
+    let hash = sin(dot(mean, vec3f(12.9898, 78.233, 37.719))) * 43758.545;
+    let seed = fract(hash) * TAU;
+    // Different rates per axis, so the flutter doesn't move in a line.
+    let flutter = sin(cycle * vec3f(2.3, 2.9, 3.7) + seed) * amplitude * turbulence;
+
+    swayed_means[index] = helpers::as_packed(mean + sway + flutter);
+}
@@ -0,0 +1,140 @@
+// Named cameras to render stills from, so the same set of images can be rendered again after every
+// retraining of a scene.
+use crate::camera::{Camera, focal_to_fov, fov_to_focal};
+use crate::camera_path::{CameraPathSample, DEFAULT_FAR, DEFAULT_NEAR};
+use glam::{Quat, UVec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+/// A saved camera, and the resolution to render it at.
+///
+/// Rotations are stored as a quaternion, as [x, y, z, w] when serialized.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Shot {
+    #[serde(default)]
+    pub name: String,
+    pub position: Vec3,
+    pub rotation: Quat,
+    /// Vertical field of view in radians. The horizontal field of view follows from the resolution.
+    pub fov_y: f64,
+    /// Exposure in stops, 0 leaves the image as is.
+    #[serde(default)]
+    pub exposure: f32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Shot {
+    pub fn from_camera(name: String, camera: &Camera, size: UVec2) -> Self {
+        Self {
+            name,
+            position: camera.position,
+            rotation: camera.rotation,
+            fov_y: camera.fov_y,
+            exposure: 0.0,
+            width: size.x,
+            height: size.y,
+        }
+    }
+
+    pub fn size(&self) -> UVec2 {
+        glam::uvec2(self.width.max(1), self.height.max(1))
+    }
+
+    /// The camera of the shot, with the aspect ratio of its resolution.
+    pub fn camera(&self) -> Camera {
+        let size = self.size();
+        let fov_x = focal_to_fov(fov_to_focal(self.fov_y, size.y), size.x);
+        Camera::new(
+            self.position,
+            self.rotation,
+            fov_x,
+            self.fov_y,
+            glam::vec2(0.5, 0.5),
+        )
+    }
+
+    /// The shot as a point of a camera path, to render it like one.
+    pub fn sample(&self) -> CameraPathSample {
+        CameraPathSample {
+            camera: self.camera(),
+            exposure: self.exposure,
+            near: DEFAULT_NEAR,
+            far: DEFAULT_FAR,
+        }
+    }
+}
+
+/// A list of shots, as saved to JSON.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ShotList {
+    pub shots: Vec<Shot>,
+}
+
+impl ShotList {
+    /// A file name for the still of each shot, from the name of the shot. Characters that aren't
+    /// safe in file names are replaced, and names used more than once are numbered.
+    pub fn file_names(&self, extension: &str) -> Vec<String> {
+        let mut names: Vec<String> = vec![];
+        for (index, shot) in self.shots.iter().enumerate() {
+            let stem: String = shot
+                .name
+                .trim()
+                .chars()
+                .map(|c| {
+                    if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            let stem = stem.trim_start_matches('.');
+            let stem = if stem.is_empty() {
+                format!("shot_{}", index + 1)
+            } else {
+                stem.to_owned()
+            };
+
+            let mut name = format!("{stem}.{extension}");
+            let mut count = 1;
+            while names.contains(&name) {
+                count += 1;
+                name = format!("{stem}_{count}.{extension}");
+            }
+            names.push(name);
+        }
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_file_names() {
+        let camera = Camera::new(Vec3::ZERO, Quat::IDENTITY, 0.8, 0.8, glam::vec2(0.5, 0.5));
+        let shot = |name: &str| Shot::from_camera(name.to_owned(), &camera, glam::uvec2(64, 32));
+        let list = ShotList {
+            shots: vec![
+                shot("Front door"),
+                shot("../hero"),
+                shot("Front door"),
+                shot(" "),
+            ],
+        };
+        assert_eq!(
+            list.file_names("png"),
+            [
+                "Front_door.png",
+                "_hero.png",
+                "Front_door_2.png",
+                "shot_4.png"
+            ]
+        );
+
+        // The horizontal field of view follows the resolution.
+        let camera = list.shots[0].camera();
+        assert!(camera.fov_x > camera.fov_y);
+    }
+}
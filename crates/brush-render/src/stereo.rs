@@ -0,0 +1,317 @@
+// Stereo rendering of splats, for headsets and 3D displays: a camera per eye, and foveated
+// rendering, which draws the periphery of an eye at a lower resolution than where it looks.
+use std::f32::consts::PI;
+
+use burn::{prelude::Backend, tensor::Tensor};
+use glam::{Quat, UVec2, Vec2, Vec3};
+
+use crate::{
+    SplatForward,
+    camera::{Camera, CameraModel, focal_to_fov},
+    gaussian_splats::Splats,
+};
+
+/// Average distance between the pupils of adults, in meters.
+pub const DEFAULT_IPD: f32 = 0.063;
+
+/// Field of view of an eye, as the angles of its sides from the view direction in radians, the
+/// way OpenXR reports them. Left and down are negative when the eye looks inside its view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EyeFov {
+    pub left: f32,
+    pub right: f32,
+    pub up: f32,
+    pub down: f32,
+}
+
+impl EyeFov {
+    pub fn symmetric(fov_x: f32, fov_y: f32) -> Self {
+        Self {
+            left: -fov_x / 2.0,
+            right: fov_x / 2.0,
+            up: fov_y / 2.0,
+            down: -fov_y / 2.0,
+        }
+    }
+}
+
+/// Camera of an eye from its pose as OpenXR reports it, with y up and looking down -z. Headsets
+/// have asymmetric fields of view, which end up in the principal point.
+pub fn eye_camera(position: Vec3, orientation: Quat, fov: EyeFov) -> Camera {
+    let (left, right) = (fov.left.tan(), fov.right.tan());
+    let (up, down) = (fov.up.tan(), fov.down.tan());
+    let fov_x = 2.0 * ((right - left) / 2.0).atan();
+    let fov_y = 2.0 * ((up - down) / 2.0).atan();
+    // Brush cameras have y pointing down, so the top of the image is up.
+    let center_uv = glam::vec2(-left / (right - left), up / (up - down));
+    let rotation = orientation * Quat::from_rotation_x(PI);
+    Camera::new(
+        position,
+        rotation,
+        f64::from(fov_x),
+        f64::from(fov_y),
+        center_uv,
+    )
+}
+
+/// Camera of an eye from its pose in the space of a headset, with the viewer camera `origin` as
+/// where the headset started, and `scene_scale` units of the scene to a meter.
+pub fn headset_eye_camera(
+    origin: &Camera,
+    scene_scale: f32,
+    position: Vec3,
+    orientation: Quat,
+    fov: EyeFov,
+) -> Camera {
+    // The headset space with y up and looking down -z, lined up with the origin camera.
+    let space = origin.rotation * Quat::from_rotation_x(PI);
+    eye_camera(
+        origin.position + space * position * scene_scale,
+        space * orientation,
+        fov,
+    )
+}
+
+/// Left and right eye cameras either side of `head`, `ipd` apart in the units of the scene. The
+/// eyes look parallel, as headsets expect, rather than converging on a point.
+pub fn eye_cameras(head: &Camera, ipd: f32) -> [Camera; 2] {
+    let offset = head.rotation * Vec3::X * (ipd / 2.0);
+    [-offset, offset].map(|offset| Camera {
+        position: head.position + offset,
+        ..head.clone()
+    })
+}
+
+/// How much to save on the periphery of an eye.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Foveation {
+    /// Size of the region drawn at full resolution around the gaze, as a fraction of the image.
+    pub inner_fraction: f32,
+    /// What the resolution is divided by everywhere else.
+    pub outer_downscale: u32,
+}
+
+impl Default for Foveation {
+    fn default() -> Self {
+        Self {
+            inner_fraction: 0.4,
+            outer_downscale: 2,
+        }
+    }
+}
+
+/// A part of a foveated view: `camera` rendered at `size`, and stretched over the `extent` pixels
+/// from `offset` on in the view.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoveatedPart {
+    pub camera: Camera,
+    pub size: UVec2,
+    pub offset: UVec2,
+    pub extent: UVec2,
+}
+
+// Corner and size of the full resolution region around `gaze`, or None without one.
+fn fovea_rect(img_size: UVec2, gaze: Vec2, foveation: Foveation) -> Option<(UVec2, UVec2)> {
+    let inner = (img_size.as_vec2() * foveation.inner_fraction.clamp(0.0, 1.0))
+        .round()
+        .as_uvec2();
+    if inner.x == 0 || inner.y == 0 {
+        return None;
+    }
+    let offset = (gaze * img_size.as_vec2() - inner.as_vec2() / 2.0)
+        .round()
+        .clamp(Vec2::ZERO, (img_size - inner).as_vec2())
+        .as_uvec2();
+    Some((offset, inner))
+}
+
+/// The renders a foveated view of `camera` is drawn from, for renderers that can stretch images
+/// over part of a target, like [`crate::painter::SplatPainter`]: the whole view at a lower
+/// resolution, then the region around `gaze` (in UV) at full resolution.
+pub fn foveated_parts(
+    camera: &Camera,
+    img_size: UVec2,
+    gaze: Vec2,
+    foveation: Foveation,
+) -> Vec<FoveatedPart> {
+    let scale = foveation.outer_downscale.max(1);
+    let full = FoveatedPart {
+        camera: camera.clone(),
+        size: img_size,
+        offset: UVec2::ZERO,
+        extent: img_size,
+    };
+    let fovea = fovea_rect(img_size, gaze, foveation);
+    if scale == 1 || fovea.is_some_and(|(_, inner)| inner == img_size) {
+        return vec![full];
+    }
+    // Stretching doesn't need the low resolution to divide the size.
+    let low = FoveatedPart {
+        size: (img_size / scale).max(UVec2::ONE),
+        ..full
+    };
+    let mut parts = vec![low];
+    if let Some((offset, inner)) = fovea {
+        parts.push(FoveatedPart {
+            camera: crop_camera(camera, img_size, offset, inner, 1),
+            size: inner,
+            offset,
+            extent: inner,
+        });
+    }
+    parts
+}
+
+// Camera showing the pixels of `camera` from `offset` on, in an image of `size`, at `1 / scale`
+// of the resolution.
+fn crop_camera(camera: &Camera, img_size: UVec2, offset: UVec2, size: UVec2, scale: u32) -> Camera {
+    let focal = camera.focal(img_size) / scale as f32;
+    let center = (camera.center(img_size) - offset.as_vec2()) / scale as f32;
+    Camera {
+        fov_x: focal_to_fov(f64::from(focal.x), size.x),
+        fov_y: focal_to_fov(f64::from(focal.y), size.y),
+        center_uv: center / size.as_vec2(),
+        jitter: Vec2::ZERO,
+        ..camera.clone()
+    }
+}
+
+impl<B: Backend + SplatForward<B>> Splats<B> {
+    /// Render with foveation: the whole view at a lower resolution, and the region around `gaze`
+    /// (in UV) at full resolution on top. Returns a float [H, W, 4] image, like
+    /// [`Splats::render`] with a float buffer.
+    pub fn render_foveated(
+        &self,
+        camera: &Camera,
+        img_size: UVec2,
+        gaze: Vec2,
+        foveation: Foveation,
+    ) -> Tensor<B, 3> {
+        let scale = foveation.outer_downscale.max(1);
+        let fovea = fovea_rect(img_size, gaze, foveation);
+        // Panoramas can't be cropped, see [`Camera::crop`].
+        if scale == 1
+            || camera.model == CameraModel::Equirectangular
+            || fovea.is_some_and(|(_, inner)| inner == img_size)
+        {
+            return self.render(camera, img_size, true).0;
+        }
+
+        // Render the periphery with pixels `scale` times the size, and blow them up again. The
+        // low resolution image can reach a bit past the edges.
+        let low_size = UVec2::new(img_size.x.div_ceil(scale), img_size.y.div_ceil(scale));
+        let low_camera = crop_camera(camera, img_size, UVec2::ZERO, low_size, scale);
+        let low = self.render(&low_camera, low_size, true).0;
+        let [h, w, c] = low.dims();
+        let (s, [width, height]) = (scale as usize, img_size.to_array().map(|s| s as usize));
+        let img = low
+            .reshape([h, 1, w, 1, c])
+            .repeat_dim(1, s)
+            .repeat_dim(3, s)
+            .reshape([h * s, w * s, c])
+            .slice([0..height, 0..width]);
+
+        let Some((offset, inner)) = fovea else {
+            return img;
+        };
+        let inner_camera = crop_camera(camera, img_size, offset, inner, 1);
+        let fovea = self.render(&inner_camera, inner, true).0;
+        let [x, y] = offset.to_array().map(|o| o as usize);
+        let [iw, ih] = inner.to_array().map(|s| s as usize);
+        img.slice_assign([y..y + ih, x..x + iw], fovea)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn asymmetric_eye() {
+        let fov = EyeFov {
+            left: -0.9,
+            right: 0.7,
+            up: 0.8,
+            down: -0.6,
+        };
+        let camera = eye_camera(Vec3::ZERO, Quat::IDENTITY, fov);
+        // OpenXR views look down -z, with y up.
+        let top_left = Vec3::new(fov.left.tan(), fov.up.tan(), -1.0);
+        let uv = camera.world_to_uv(top_left).expect("In front of the eye");
+        assert!(uv.length() < 1e-5, "{uv}");
+        let bottom_right = Vec3::new(fov.right.tan(), fov.down.tan(), -1.0);
+        let uv = camera
+            .world_to_uv(bottom_right)
+            .expect("In front of the eye");
+        assert!(uv.distance(Vec2::ONE) < 1e-5, "{uv}");
+
+        let [left, right] = eye_cameras(&camera, DEFAULT_IPD);
+        assert!((left.position.distance(right.position) - DEFAULT_IPD).abs() < 1e-6);
+        assert!(left.position.x < right.position.x);
+    }
+
+    #[test]
+    fn headset_follows_origin() {
+        let origin = Camera::new(
+            Vec3::new(1.0, 2.0, 3.0),
+            Quat::from_rotation_y(0.7),
+            1.0,
+            1.0,
+            glam::vec2(0.5, 0.5),
+        );
+        let fov = EyeFov::symmetric(1.0, 1.0);
+        // A headset where it started sees what the origin camera sees.
+        let eye = headset_eye_camera(&origin, 2.0, Vec3::ZERO, Quat::IDENTITY, fov);
+        let forward = origin.rotation * Vec3::Z;
+        assert!(eye.position.distance(origin.position) < 1e-5);
+        assert!((eye.rotation * Vec3::Z).distance(forward) < 1e-5);
+        assert!((eye.rotation * Vec3::Y).distance(origin.rotation * Vec3::Y) < 1e-5);
+        // Stepping forward, down -z for the headset, moves along the view, in scene units.
+        let eye = headset_eye_camera(&origin, 2.0, Vec3::NEG_Z * 0.5, Quat::IDENTITY, fov);
+        assert!(eye.position.distance(origin.position + forward) < 1e-5);
+        // Looking up moves what's above the origin view to the center.
+        let up = Quat::from_rotation_x(0.3);
+        let eye = headset_eye_camera(&origin, 1.0, Vec3::ZERO, up, fov);
+        let above = origin.position + origin.rotation * Vec3::new(0.0, -0.3f32.tan(), 1.0);
+        let uv = eye.world_to_uv(above).expect("In view");
+        assert!(uv.distance(glam::vec2(0.5, 0.5)) < 1e-4, "{uv}");
+    }
+
+    #[test]
+    fn foveated_parts_cover_view() {
+        let camera = Camera::new(Vec3::ZERO, Quat::IDENTITY, 1.2, 0.8, glam::vec2(0.4, 0.6));
+        let size = glam::uvec2(201, 101);
+        let parts = foveated_parts(&camera, size, glam::vec2(0.9, 0.5), Foveation::default());
+        assert_eq!(parts.len(), 2);
+        assert_eq!((parts[0].offset, parts[0].extent), (UVec2::ZERO, size));
+        assert_eq!(parts[0].size, glam::uvec2(100, 50));
+        // The fovea stays inside the view, and shows what the full view shows there.
+        let fovea = &parts[1];
+        assert!(fovea.offset.cmple(size - fovea.extent).all());
+        assert_eq!(fovea.size, fovea.extent);
+        let point = Vec3::new(0.3, 0.0, 1.0);
+        let pixel = camera.world_to_uv(point).expect("In view") * size.as_vec2();
+        let in_fovea = fovea.camera.world_to_uv(point).expect("In view") * fovea.size.as_vec2();
+        assert!((in_fovea + fovea.offset.as_vec2()).distance(pixel) < 1e-3);
+
+        let none = Foveation {
+            inner_fraction: 1.0,
+            outer_downscale: 2,
+        };
+        assert_eq!(foveated_parts(&camera, size, Vec2::ZERO, none).len(), 1);
+    }
+
+    #[test]
+    fn crop_keeps_pixels() {
+        let camera = Camera::new(Vec3::ZERO, Quat::IDENTITY, 1.2, 0.8, glam::vec2(0.4, 0.6));
+        let size = glam::uvec2(200, 100);
+        let crop = crop_camera(&camera, size, glam::uvec2(50, 20), glam::uvec2(80, 40), 1);
+        let low = crop_camera(&camera, size, UVec2::ZERO, glam::uvec2(50, 25), 4);
+        let point = Vec3::new(0.1, -0.05, 1.0);
+        let pixel = camera.world_to_uv(point).expect("In view") * size.as_vec2();
+        let in_crop = crop.world_to_uv(point).expect("In view") * glam::vec2(80.0, 40.0);
+        let in_low = low.world_to_uv(point).expect("In view") * glam::vec2(50.0, 25.0);
+        assert!((in_crop + glam::vec2(50.0, 20.0)).distance(pixel) < 1e-3);
+        assert!((in_low * 4.0).distance(pixel) < 1e-3);
+    }
+}
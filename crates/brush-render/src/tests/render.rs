@@ -1,6 +1,9 @@
-use crate::{SplatForward, camera::Camera};
+use crate::{
+    SplatForward,
+    camera::{Camera, Culling},
+};
 use assert_approx_eq::assert_approx_eq;
-use burn::tensor::{Tensor, TensorPrimitive};
+use burn::tensor::{ElementConversion, Tensor, TensorPrimitive};
 use burn_wgpu::{Wgpu, WgpuDevice};
 
 type Back = Wgpu;
@@ -52,3 +55,217 @@ fn renders_at_all() {
     assert_approx_eq!(rgb_mean, 0.0, 1e-5);
     assert_approx_eq!(alpha_mean, 0.0);
 }
+
+#[test]
+fn culls_outside_frustum() {
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(32, 32);
+    let device = WgpuDevice::DefaultDevice;
+
+    // In view, behind the camera, off to the side, and off to the side but large enough to
+    // reach into view.
+    let means = Tensor::<Back, 2>::from_floats(
+        [
+            [0.0, 0.0, 5.0],
+            [0.0, 0.0, -5.0],
+            [20.0, 0.0, 5.0],
+            [4.0, 0.0, 5.0],
+        ],
+        &device,
+    );
+    let log_scales = Tensor::<Back, 1>::from_floats([-4.0, -4.0, -4.0, 0.0], &device)
+        .unsqueeze_dim::<2>(1)
+        .repeat_dim(1, 3);
+    let quats: Tensor<Back, 2> =
+        Tensor::<Back, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+            .unsqueeze_dim(0)
+            .repeat_dim(0, 4);
+    let sh_coeffs = Tensor::<Back, 3>::ones([4, 1, 3], &device);
+    let raw_opacity = Tensor::<Back, 1>::zeros([4], &device);
+    let (_, aux) = <Back as SplatForward<Back>>::render_splats(
+        &cam,
+        img_size,
+        means.into_primitive().tensor(),
+        log_scales.into_primitive().tensor(),
+        quats.into_primitive().tensor(),
+        sh_coeffs.into_primitive().tensor(),
+        raw_opacity.into_primitive().tensor(),
+        true,
+    );
+    aux.debug_assert_valid();
+
+    let num_visible = aux.num_visible().into_scalar().elem::<i32>();
+    assert_eq!(num_visible, 2);
+}
+
+#[test]
+fn culls_by_depth_and_size() {
+    let img_size = glam::uvec2(32, 32);
+    let device = WgpuDevice::DefaultDevice;
+
+    // Small splats in front of the near plane, in range, and past the far plane, and a large one
+    // in range.
+    let means = Tensor::<Back, 2>::from_floats(
+        [
+            [0.0, 0.0, 0.5],
+            [0.0, 0.0, 5.0],
+            [0.0, 0.0, 50.0],
+            [0.0, 0.0, 5.0],
+        ],
+        &device,
+    );
+    let log_scales = Tensor::<Back, 1>::from_floats([-4.0, -4.0, -4.0, 0.0], &device)
+        .unsqueeze_dim::<2>(1)
+        .repeat_dim(1, 3);
+    let quats: Tensor<Back, 2> =
+        Tensor::<Back, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+            .unsqueeze_dim(0)
+            .repeat_dim(0, 4);
+    let sh_coeffs = Tensor::<Back, 3>::ones([4, 1, 3], &device);
+    let opacity = Tensor::<Back, 1>::ones([4], &device);
+
+    let num_visible = |min_radius: f32| {
+        let cam = Camera::new(
+            glam::vec3(0.0, 0.0, 0.0),
+            glam::Quat::IDENTITY,
+            0.5,
+            0.5,
+            glam::vec2(0.5, 0.5),
+        )
+        .with_culling(Culling {
+            near: 1.0,
+            far: 20.0,
+            min_radius,
+        });
+        let (_, aux) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            opacity.clone().into_primitive().tensor(),
+            true,
+        );
+        aux.debug_assert_valid();
+        aux.num_visible().into_scalar().elem::<i32>()
+    };
+    assert_eq!(num_visible(0.0), 2);
+    assert_eq!(num_visible(4.0), 1);
+}
+
+#[test]
+fn depth_hides_splats_behind() {
+    use crate::{MainBackend, MainBackendBase, gaussian_splats::Splats, render::render_with_depth};
+
+    let device = WgpuDevice::DefaultDevice;
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let splats = Splats::<MainBackend>::from_raw(
+        &[glam::vec3(0.0, 0.0, 8.0)],
+        Some(&[glam::Quat::IDENTITY]),
+        Some(&[glam::Vec3::splat(-1.0)]),
+        None,
+        Some(&[5.0]),
+        &device,
+    );
+    let center_alpha = |geometry_depth: f32| {
+        let depth = Tensor::<MainBackendBase, 2>::ones([32, 32], &device) * geometry_depth;
+        let img = render_with_depth(&splats, &cam, depth).into_data();
+        // Alpha is the last byte of the packed colors.
+        img.as_bytes()[(16 * 32 + 16) * 4 + 3]
+    };
+    assert!(center_alpha(10.0) > 200);
+    assert_eq!(center_alpha(5.0), 0);
+}
+
+#[test]
+fn instances_transform_prop() {
+    use crate::gaussian_splats::Splats;
+    use crate::instancing::{InstancedSplats, PropInstance};
+    use crate::sh::channel_to_sh;
+    use glam::{Quat, Vec3, vec3};
+
+    let device = WgpuDevice::DefaultDevice;
+    let splat = |mean: Vec3, rotation: Quat, log_scale: Vec3, color: Vec3| {
+        Splats::<Back>::from_raw(
+            &[mean],
+            Some(&[rotation]),
+            Some(&[log_scale]),
+            Some(&color.to_array().map(channel_to_sh)),
+            Some(&[2.0]),
+            &device,
+        )
+    };
+    // Stretched along x, so the render shows how the copies are rotated.
+    let prop_scale = vec3(-1.0, -2.5, -2.5);
+    let scene = splat(
+        vec3(0.0, -1.5, 1.0),
+        Quat::IDENTITY,
+        Vec3::splat(-1.5),
+        vec3(0.2, 0.6, 0.4),
+    );
+    let prop = splat(
+        vec3(1.0, 0.0, 0.0),
+        Quat::IDENTITY,
+        prop_scale,
+        Vec3::splat(0.5),
+    );
+
+    let rotation = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+    let instances = [
+        PropInstance::new(vec3(0.0, 1.5, 0.0)),
+        PropInstance {
+            rotation,
+            scale: 2.0,
+            tint: vec3(0.5, 1.0, 1.5),
+            ..PropInstance::new(vec3(-1.0, -1.0, 0.5))
+        },
+    ];
+    assert!(InstancedSplats::new(scene.clone(), prop.clone(), &[], 0.0).is_none());
+    let instanced =
+        InstancedSplats::new(scene.clone(), prop, &instances, 0.0).expect("Instances given");
+    assert_eq!(instanced.num_drawn(), 3);
+
+    // The same copies, transformed by hand.
+    let expected = scene
+        .merge(splat(
+            vec3(1.0, 1.5, 0.0),
+            Quat::IDENTITY,
+            prop_scale,
+            Vec3::splat(0.5),
+        ))
+        .merge(splat(
+            vec3(-1.0, 1.0, 0.5),
+            rotation,
+            prop_scale + Vec3::splat(2.0f32.ln()),
+            vec3(0.25, 0.5, 0.75),
+        ));
+
+    let cam = Camera::new(
+        vec3(0.0, 0.0, -8.0),
+        Quat::IDENTITY,
+        0.8,
+        0.8,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(64, 64);
+    let (img, _) = instanced.render(&cam, img_size, true);
+    let (expected_img, _) = expected.render(&cam, img_size, true);
+
+    let coverage = expected_img.clone().slice([0..64, 0..64, 3..4]).sum();
+    assert!(coverage.into_scalar().elem::<f32>() > 10.0);
+    let diff = (img - expected_img).abs().max().into_scalar().elem::<f32>();
+    assert!(diff < 1e-4, "Instanced render differs by {diff}");
+}
@@ -0,0 +1,208 @@
+// Volumes enclosed by triangle meshes, to carve splats out of a scene, eg. a doorway or a capture
+// rig, or to clip a scan to a building.
+//
+// Points are tested by casting a ray from them and counting how often it crosses the mesh, so
+// meshes need to be closed. Flat meshes, like the footprint of a building, are extruded along their
+// normal through the whole scene instead.
+use glam::{Vec2, Vec3};
+
+// A mesh is flat if all its vertices are within this fraction of its size of one plane.
+const FLAT_TOLERANCE: f32 = 1e-4;
+
+// Max nr. of cells along each side of the grid triangles are binned in.
+const MAX_GRID_RES: usize = 256;
+
+/// A volume enclosed by a triangle mesh.
+pub struct MeshVolume {
+    // Direction rays are cast in, or for flat meshes the direction they're extruded in.
+    axis: Vec3,
+    // Two directions perpendicular to the axis, spanning the plane triangles are binned in.
+    plane: [Vec3; 2],
+    flat: bool,
+    triangles: Vec<[Vec3; 3]>,
+    // Grid over the plane, each cell holding the triangles whose bounds overlap it.
+    grid_min: Vec2,
+    cell_size: Vec2,
+    res: usize,
+    cells: Vec<Vec<u32>>,
+}
+
+impl MeshVolume {
+    /// The volume enclosed by `triangles`, indices into `positions`.
+    ///
+    /// Returns None if the mesh has no triangles with an area.
+    pub fn new(positions: &[Vec3], triangles: &[[u32; 3]]) -> Option<Self> {
+        let triangles: Vec<[Vec3; 3]> = triangles
+            .iter()
+            .filter_map(|tri| {
+                let [a, b, c] = tri.map(|i| positions.get(i as usize).copied());
+                Some([a?, b?, c?])
+            })
+            .filter(|[a, b, c]| (*b - *a).cross(*c - *a).length_squared() > 0.0)
+            .collect();
+
+        // The normal of the largest triangle is the normal of the mesh if it's flat.
+        let normal = triangles
+            .iter()
+            .map(|[a, b, c]| (*b - *a).cross(*c - *a))
+            .max_by(|x, y| x.length_squared().total_cmp(&y.length_squared()))?
+            .normalize();
+        let origin = triangles[0][0];
+        let (min, max) = triangles
+            .iter()
+            .flatten()
+            .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), &p| {
+                (min.min(p), max.max(p))
+            });
+        let tolerance = (max - min).length() * FLAT_TOLERANCE;
+        let flat = triangles
+            .iter()
+            .flatten()
+            .all(|&p| (p - origin).dot(normal).abs() <= tolerance);
+
+        // Closed meshes can be tested with rays in any direction.
+        let axis = if flat { normal } else { Vec3::X };
+        let (u, v) = axis.any_orthonormal_pair();
+        let plane = [u, v];
+        let project = |p: Vec3| Vec2::new(p.dot(u), p.dot(v));
+
+        let (grid_min, grid_max) = triangles
+            .iter()
+            .flatten()
+            .fold((Vec2::INFINITY, Vec2::NEG_INFINITY), |(min, max), &p| {
+                (min.min(project(p)), max.max(project(p)))
+            });
+        let res = ((triangles.len() as f32).sqrt() as usize).clamp(1, MAX_GRID_RES);
+        let cell_size = ((grid_max - grid_min) / res as f32).max(Vec2::splat(1e-12));
+
+        let mut volume = Self {
+            axis,
+            plane,
+            flat,
+            triangles: vec![],
+            grid_min,
+            cell_size,
+            res,
+            cells: vec![vec![]; res * res],
+        };
+        for (i, tri) in triangles.iter().enumerate() {
+            let [a, b, c] = tri.map(project);
+            let lo = volume.cell_coords(a.min(b).min(c));
+            let hi = volume.cell_coords(a.max(b).max(c));
+            for y in lo[1]..=hi[1] {
+                for x in lo[0]..=hi[0] {
+                    volume.cells[y * res + x].push(i as u32);
+                }
+            }
+        }
+        volume.triangles = triangles;
+        Some(volume)
+    }
+
+    fn project(&self, p: Vec3) -> Vec2 {
+        Vec2::new(p.dot(self.plane[0]), p.dot(self.plane[1]))
+    }
+
+    fn cell_coords(&self, q: Vec2) -> [usize; 2] {
+        let cell = ((q - self.grid_min) / self.cell_size).floor();
+        [cell.x, cell.y].map(|c| (c.max(0.0) as usize).min(self.res - 1))
+    }
+
+    /// Whether `point` is inside of the volume.
+    pub fn contains(&self, point: Vec3) -> bool {
+        let q = self.project(point);
+        let extent = self.cell_size * self.res as f32;
+        if q.cmplt(self.grid_min).any() || q.cmpgt(self.grid_min + extent).any() {
+            return false;
+        }
+        let [x, y] = self.cell_coords(q);
+        let depth = point.dot(self.axis);
+
+        // Even-odd rule: count the triangles the ray from the point crosses. For flat meshes, that
+        // is all triangles the point lies over, which also works out for faces that were split
+        // into overlapping triangles.
+        let mut inside = false;
+        for &i in &self.cells[y * self.res + x] {
+            let tri = self.triangles[i as usize];
+            let [a, b, c] = tri.map(|p| self.project(p));
+            let (e1, e2, rel) = (b - a, c - a, q - a);
+            let det = e1.perp_dot(e2);
+            if det == 0.0 {
+                continue;
+            }
+            // Barycentric coordinates of the point in the triangle.
+            let s = rel.perp_dot(e2) / det;
+            let t = e1.perp_dot(rel) / det;
+            if s < 0.0 || t < 0.0 || s + t >= 1.0 {
+                continue;
+            }
+            if !self.flat {
+                let [da, db, dc] = tri.map(|p| p.dot(self.axis));
+                if da + s * (db - da) + t * (dc - da) <= depth {
+                    continue;
+                }
+            }
+            inside = !inside;
+        }
+        inside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unit cube from 0 to 1, with faces split in two triangles.
+    fn cube() -> (Vec<Vec3>, Vec<[u32; 3]>) {
+        let positions = (0..8)
+            .map(|i| Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32))
+            .collect();
+        let faces = [
+            [0, 1, 3, 2],
+            [4, 6, 7, 5],
+            [0, 4, 5, 1],
+            [2, 3, 7, 6],
+            [0, 2, 6, 4],
+            [1, 5, 7, 3],
+        ];
+        let triangles = faces
+            .iter()
+            .flat_map(|[a, b, c, d]| [[*a, *b, *c], [*a, *c, *d]])
+            .collect();
+        (positions, triangles)
+    }
+
+    #[test]
+    fn closed_mesh() {
+        let (positions, triangles) = cube();
+        let volume = MeshVolume::new(&positions, &triangles).expect("Valid mesh");
+        assert!(volume.contains(Vec3::new(0.3, 0.6, 0.2)));
+        assert!(volume.contains(Vec3::new(0.9, 0.15, 0.35)));
+        assert!(!volume.contains(Vec3::new(1.5, 0.6, 0.2)));
+        // The ray crosses both sides of the cube.
+        assert!(!volume.contains(Vec3::new(-0.5, 0.6, 0.2)));
+        assert!(!volume.contains(Vec3::new(0.3, 0.6, 2.0)));
+    }
+
+    #[test]
+    fn flat_mesh_is_extruded() {
+        // An L shaped footprint at a height of 3, as a fan of triangles from the inner corner.
+        let positions = [[1, 1], [0, 2], [0, 0], [2, 0], [2, 1], [1, 2]]
+            .map(|[x, z]| Vec3::new(x as f32, 3.0, z as f32));
+        let triangles = [[0, 1, 2], [0, 2, 3], [0, 3, 4], [0, 5, 1]];
+        let volume = MeshVolume::new(&positions, &triangles).expect("Valid mesh");
+        assert!(volume.contains(Vec3::new(0.5, -10.0, 0.3)));
+        assert!(volume.contains(Vec3::new(1.5, 20.0, 0.3)));
+        assert!(volume.contains(Vec3::new(0.5, 3.0, 1.7)));
+        // The notch of the L.
+        assert!(!volume.contains(Vec3::new(1.5, 0.0, 1.5)));
+        assert!(!volume.contains(Vec3::new(3.0, 0.0, 0.5)));
+    }
+
+    #[test]
+    fn empty_mesh() {
+        let positions = [Vec3::ZERO, Vec3::X, Vec3::X * 2.0];
+        assert!(MeshVolume::new(&positions, &[[0, 1, 2]]).is_none());
+        assert!(MeshVolume::new(&positions, &[[0, 1, 5]]).is_none());
+    }
+}
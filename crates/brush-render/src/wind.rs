@@ -0,0 +1,294 @@
+// Procedural sway of groups of splats, so captured foliage doesn't look frozen when walking through
+// a scene.
+//
+// The sway is computed by a kernel, as a chain of tensor ops per frame would be much slower for
+// millions of splats.
+use brush_kernel::{calc_cube_count, create_uniform_buffer};
+use burn::tensor::{Int, Tensor, TensorData};
+use burn_cubecl::{
+    cubecl::{Runtime, server::Bindings},
+    kernel::into_contiguous,
+};
+use burn_fusion::client::FusionClient;
+use burn_wgpu::{CubeTensor, WgpuRuntime};
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{MainBackend, MainBackendBase, gaussian_splats::Splats, kernels::AnimateWind, shaders};
+
+fn default_amplitude() -> f32 {
+    0.02
+}
+
+fn default_frequency() -> f32 {
+    0.5
+}
+
+fn default_wavelength() -> f32 {
+    2.0
+}
+
+fn default_turbulence() -> f32 {
+    0.3
+}
+
+fn default_direction() -> Vec3 {
+    Vec3::X
+}
+
+/// How a group of splats sways in the wind.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupSway {
+    /// Label of the splats in this group.
+    pub label: u32,
+    /// How far the splats move, in scene units.
+    #[serde(default = "default_amplitude")]
+    pub amplitude: f32,
+    /// Sways per second.
+    #[serde(default = "default_frequency")]
+    pub frequency: f32,
+    /// Distance between gusts moving through the group, in scene units.
+    #[serde(default = "default_wavelength")]
+    pub wavelength: f32,
+    /// Flutter of each splat on its own, relative to the amplitude.
+    #[serde(default = "default_turbulence")]
+    pub turbulence: f32,
+    #[serde(default = "default_direction")]
+    pub direction: Vec3,
+}
+
+impl GroupSway {
+    pub fn new(label: u32) -> Self {
+        Self {
+            label,
+            amplitude: default_amplitude(),
+            frequency: default_frequency(),
+            wavelength: default_wavelength(),
+            turbulence: default_turbulence(),
+            direction: default_direction(),
+        }
+    }
+}
+
+/// Wind over a scene, as stored in a JSON file. Splats without a group stay still.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Wind {
+    #[serde(default)]
+    pub groups: Vec<GroupSway>,
+}
+
+// Columns of the table of sway parameters per label, nb: must match wind.wgsl.
+const PARAMS: usize = 7;
+
+impl Wind {
+    pub fn group(&self, label: u32) -> Option<&GroupSway> {
+        self.groups.iter().find(|g| g.label == label)
+    }
+
+    pub fn group_mut(&mut self, label: u32) -> Option<&mut GroupSway> {
+        self.groups.iter_mut().find(|g| g.label == label)
+    }
+
+    pub fn set_group(&mut self, label: u32, enabled: bool) {
+        if !enabled {
+            self.groups.retain(|g| g.label != label);
+        } else if self.group(label).is_none() {
+            self.groups.push(GroupSway::new(label));
+        }
+    }
+
+    // Sway parameters of labels 0..num_labels, as rows of
+    // [amplitude, frequency, wavelength, turbulence, direction].
+    fn table(&self, num_labels: u32) -> Vec<f32> {
+        (0..num_labels)
+            .flat_map(|label| match self.group(label) {
+                Some(g) => {
+                    let dir = g.direction.normalize_or_zero();
+                    [
+                        g.amplitude,
+                        g.frequency,
+                        g.wavelength.max(1e-3),
+                        g.turbulence,
+                        dir.x,
+                        dir.y,
+                        dir.z,
+                    ]
+                }
+                None => [0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0],
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WindError {
+    #[error("Got {labels} labels for {splats} splats")]
+    LabelCount { labels: usize, splats: usize },
+}
+
+/// Move the splats as swayed by `wind` at `time` (in seconds).
+///
+/// `labels` has the label of each splat, splats with a label of `num_labels` or more stay still.
+/// Splats sway along their group direction in a wave moving through the group, and flutter a bit
+/// on their own.
+pub fn animate_splats(
+    splats: &Splats<MainBackend>,
+    labels: Tensor<MainBackend, 1, Int>,
+    num_labels: u32,
+    wind: &Wind,
+    time: f32,
+) -> Result<Splats<MainBackend>, WindError> {
+    let n = splats.num_splats() as usize;
+    let num_labelled = labels.dims()[0];
+    if num_labelled != n {
+        return Err(WindError::LabelCount {
+            labels: num_labelled,
+            splats: n,
+        });
+    }
+    if wind.groups.is_empty() || n == 0 || num_labels == 0 {
+        return Ok(splats.clone());
+    }
+    let device = splats.device();
+
+    let table = TensorData::new(wind.table(num_labels), [num_labels as usize * PARAMS]);
+    let params = Tensor::<MainBackend, 1>::from_data(table, &device);
+    let swayed = Tensor::<MainBackend, 2>::zeros([n, 3], &device);
+
+    let client = WgpuRuntime::client(&device);
+    let uniforms = create_uniform_buffer(
+        shaders::wind::Uniforms {
+            num_splats: n as u32,
+            num_labels,
+            time,
+        },
+        &device,
+        &client,
+    );
+    // The kernel writes the swayed means into the buffer of `swayed`.
+    // SAFETY: Kernel checked to have no OOB, and has no loops.
+    unsafe {
+        client.execute_unchecked(
+            AnimateWind::task(),
+            calc_cube_count([n as u32], AnimateWind::WORKGROUP_SIZE),
+            Bindings::new().with_buffers(vec![
+                uniforms.handle.binding(),
+                into_contiguous(resolve(splats.means.val()))
+                    .handle
+                    .binding(),
+                into_contiguous(resolve_int(labels)).handle.binding(),
+                resolve(params).handle.binding(),
+                resolve(swayed.clone()).handle.binding(),
+            ]),
+        );
+    }
+
+    let mut animated = splats.clone();
+    animated.means = animated.means.map(|_| swayed);
+    Ok(animated)
+}
+
+// Get the tensors of the backend under fusion, running the fused operations they depend on.
+fn resolve<const D: usize>(tensor: Tensor<MainBackend, D>) -> CubeTensor<WgpuRuntime> {
+    let tensor = tensor.into_primitive().tensor();
+    let client = tensor.client.clone();
+    client.resolve_tensor_float::<MainBackendBase>(tensor)
+}
+
+fn resolve_int(tensor: Tensor<MainBackend, 1, Int>) -> CubeTensor<WgpuRuntime> {
+    let tensor = tensor.into_primitive();
+    let client = tensor.client.clone();
+    client.resolve_tensor_int::<MainBackendBase>(tensor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    #[test]
+    fn ungrouped_labels_stay_still() {
+        let mut wind = Wind::default();
+        wind.set_group(1, true);
+        wind.set_group(1, true);
+        assert_eq!(wind.groups.len(), 1);
+
+        let table = wind.table(3);
+        assert_eq!(table.len(), 3 * PARAMS);
+        // Only label 1 moves.
+        assert_eq!(table[0], 0.0);
+        assert_eq!(table[PARAMS], default_amplitude());
+        assert_eq!(table[2 * PARAMS], 0.0);
+
+        wind.set_group(1, false);
+        assert!(wind.groups.is_empty());
+    }
+
+    fn labeled_splats(
+        means: &[Vec3],
+        labels: &[i32],
+    ) -> (Splats<MainBackend>, Tensor<MainBackend, 1, Int>) {
+        let device = burn_wgpu::WgpuDevice::DefaultDevice;
+        let splats = Splats::from_raw(means, None, None, None, None, &device);
+        let labels = Tensor::from_data(TensorData::new(labels.to_vec(), [labels.len()]), &device);
+        (splats, labels)
+    }
+
+    #[test]
+    fn sway_follows_group() {
+        let means = [
+            Vec3::ZERO,
+            Vec3::new(0.5, 1.0, -2.0),
+            Vec3::new(3.0, 0.0, 1.0),
+        ];
+        let (splats, labels) = labeled_splats(&means, &[0, 1, 1]);
+        let group = GroupSway {
+            amplitude: 0.1,
+            turbulence: 0.0,
+            direction: Vec3::new(0.0, 2.0, 0.0),
+            ..GroupSway::new(1)
+        };
+        let wind = Wind {
+            groups: vec![group.clone()],
+        };
+        let time = 0.7;
+
+        let animated = animate_splats(&splats, labels, 2, &wind, time).expect("Labels match");
+        let moved = animated
+            .means
+            .val()
+            .into_data()
+            .into_vec::<f32>()
+            .expect("Wrong type");
+        for (i, mean) in means.iter().enumerate() {
+            let expected = if i == 0 {
+                *mean
+            } else {
+                let phase = mean.y / group.wavelength * TAU;
+                let sway = (group.frequency * TAU * time - phase).sin() * group.amplitude;
+                *mean + Vec3::Y * sway
+            };
+            let got = Vec3::from_slice(&moved[i * 3..i * 3 + 3]);
+            assert!(
+                got.distance(expected) < 1e-5,
+                "Splat {i}: {got} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn mismatched_labels_fail() {
+        let (splats, labels) = labeled_splats(&[Vec3::ZERO, Vec3::X], &[1]);
+        let mut wind = Wind::default();
+        wind.set_group(1, true);
+        let result = animate_splats(&splats, labels, 2, &wind, 0.0);
+        assert!(matches!(
+            result,
+            Err(WindError::LabelCount {
+                labels: 1,
+                splats: 2
+            })
+        ));
+    }
+}
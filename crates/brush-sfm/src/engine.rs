@@ -0,0 +1,525 @@
+use std::{
+    ffi::OsString,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use thiserror::Error;
+use tokio::process::Command;
+
+use crate::{SfmEngine, SfmMatcher, SfmOptions};
+
+// Nr. of lines of output of a failed step to show.
+const ERROR_LINES: usize = 20;
+
+// Bump when the way reconstructions are made changes, so old ones aren't reused.
+const WORKSPACE_VERSION: u32 = 1;
+
+// Arguments of a custom engine command that are replaced by the directory of photos, and the
+// directory to write the reconstruction to.
+const IMAGES_ARG: &str = "{images}";
+const OUTPUT_ARG: &str = "{output}";
+
+#[derive(Debug, Error)]
+pub enum SfmError {
+    #[error("IO error while reconstructing cameras.")]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to run {binary}. Is it installed, or on the PATH?")]
+    NotInstalled {
+        binary: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("{step} failed:\n{output}")]
+    StepFailed { step: &'static str, output: String },
+
+    #[error("No cameras could be reconstructed from the images.")]
+    NoReconstruction,
+
+    #[error("Invalid SfM command: {0}")]
+    InvalidCommand(&'static str),
+}
+
+async fn run(step: &'static str, command: &mut Command) -> Result<(), SfmError> {
+    log::info!("Structure from motion: {step}");
+    let binary = command
+        .as_std()
+        .get_program()
+        .to_string_lossy()
+        .into_owned();
+    // Stop the engine if the reconstruction is cancelled.
+    let output = command
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|source| SfmError::NotInstalled { binary, source })?;
+
+    if !output.status.success() {
+        let log = String::from_utf8_lossy(&output.stdout) + String::from_utf8_lossy(&output.stderr);
+        let lines: Vec<_> = log.lines().collect();
+        let output = lines[lines.len().saturating_sub(ERROR_LINES)..].join("\n");
+        return Err(SfmError::StepFailed { step, output });
+    }
+    Ok(())
+}
+
+async fn remove_dir(dir: &Path) -> std::io::Result<()> {
+    if tokio::fs::try_exists(dir).await? {
+        tokio::fs::remove_dir_all(dir).await?;
+    }
+    Ok(())
+}
+
+// All files in `dir` and its subdirectories, sorted.
+async fn files_in(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if tokio::fs::metadata(entry.path()).await?.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Key of a reconstruction of the photos in `images` with `options`. Photos are told apart by
+// name, size and modification time, so changing, adding or removing any changes the key.
+//
+// The hash isn't stable across Rust versions, at worst that reconstructs the photos again.
+async fn inputs_key(images: &Path, options: &SfmOptions) -> std::io::Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    WORKSPACE_VERSION.hash(&mut hasher);
+    for path in files_in(images).await? {
+        let meta = tokio::fs::metadata(&path).await?;
+        let modified = meta
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        (
+            path.strip_prefix(images).unwrap_or(&path),
+            meta.len(),
+            modified,
+        )
+            .hash(&mut hasher);
+    }
+    // Paths to the binaries don't change the result, so aren't part of the key.
+    (
+        options.engine,
+        options.matcher,
+        options.single_camera,
+        &options.command,
+    )
+        .hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+// Arguments of the custom engine command, split on whitespace, with the placeholders replaced.
+// Placeholders have to be whole arguments, so paths with spaces stay one argument.
+fn command_args(command: &str, images: &Path, output: &Path) -> Result<Vec<OsString>, SfmError> {
+    let words: Vec<_> = command.split_whitespace().collect();
+    if words.is_empty() {
+        return Err(SfmError::InvalidCommand("the command is empty"));
+    }
+    if !words.contains(&OUTPUT_ARG) {
+        return Err(SfmError::InvalidCommand(
+            "the command needs an {output} argument",
+        ));
+    }
+    Ok(words
+        .into_iter()
+        .map(|word| match word {
+            IMAGES_ARG => images.as_os_str().to_owned(),
+            OUTPUT_ARG => output.as_os_str().to_owned(),
+            word => word.into(),
+        })
+        .collect())
+}
+
+// Run the custom engine, writing to `output`. The photos are copied next to the model when the
+// engine doesn't write any, so the reconstruction loads like one of COLMAP.
+async fn run_command(images: &Path, output: &Path, options: &SfmOptions) -> Result<(), SfmError> {
+    let command = options
+        .command
+        .as_deref()
+        .ok_or(SfmError::InvalidCommand("no command was given"))?;
+    let args = command_args(command, images, output)?;
+    tokio::fs::create_dir_all(output).await?;
+    run("Reconstruction", Command::new(&args[0]).args(&args[1..])).await?;
+
+    let has_model = files_in(output).await?.iter().any(|path| {
+        path.file_name()
+            .is_some_and(|name| name == "cameras.bin" || name == "cameras.txt")
+    });
+    if !has_model {
+        return Err(SfmError::NoReconstruction);
+    }
+
+    let copies = output.join("images");
+    if !tokio::fs::try_exists(&copies).await? {
+        for path in files_in(images).await? {
+            let copy = copies.join(path.strip_prefix(images).unwrap_or(&path));
+            if let Some(dir) = copy.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            tokio::fs::copy(&path, &copy).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Reconstruct the cameras of the photos in `images`, keeping intermediate files in `workspace`.
+///
+/// Returns a directory with a COLMAP reconstruction, and copies of the photos in `images`
+/// undistorted to pinhole cameras, as Brush doesn't model lens distortion. A finished
+/// reconstruction in the workspace is reused, as long as the photos and options are the same.
+pub async fn reconstruct(
+    images: &Path,
+    workspace: &Path,
+    options: &SfmOptions,
+) -> Result<PathBuf, SfmError> {
+    let output = workspace.join("undistorted");
+    // Written once the reconstruction is done, so it's only there for finished ones.
+    let key_file = workspace.join("inputs.key");
+    let key = format!("{:016x}", inputs_key(images, options).await?);
+    if tokio::fs::read_to_string(&key_file)
+        .await
+        .is_ok_and(|done| done == key)
+        && tokio::fs::try_exists(&output).await?
+    {
+        log::info!("Reusing reconstruction in {}", output.display());
+        return Ok(output);
+    }
+
+    // Start over, files of an interrupted run could be half written.
+    let partial = workspace.join("undistorted.partial");
+    if tokio::fs::try_exists(&key_file).await? {
+        tokio::fs::remove_file(&key_file).await?;
+    }
+    remove_dir(&output).await?;
+    remove_dir(&partial).await?;
+
+    match options.engine {
+        SfmEngine::Colmap | SfmEngine::Glomap => {
+            run_colmap(images, workspace, &partial, options).await?;
+        }
+        SfmEngine::Command => run_command(images, &partial, options).await?,
+    }
+    tokio::fs::rename(&partial, &output).await?;
+    tokio::fs::write(&key_file, key).await?;
+    Ok(output)
+}
+
+// Match features with COLMAP, map them with COLMAP or GLOMAP, and undistort the photos to
+// `output`.
+async fn run_colmap(
+    images: &Path,
+    workspace: &Path,
+    output: &Path,
+    options: &SfmOptions,
+) -> Result<(), SfmError> {
+    let database = workspace.join("database.db");
+    let sparse = workspace.join("sparse");
+    remove_dir(&sparse).await?;
+    tokio::fs::create_dir_all(&sparse).await?;
+    if tokio::fs::try_exists(&database).await? {
+        tokio::fs::remove_file(&database).await?;
+    }
+
+    let colmap = options
+        .colmap_binary
+        .clone()
+        .unwrap_or_else(|| "colmap".into());
+
+    run(
+        "Feature extraction",
+        Command::new(&colmap)
+            .arg("feature_extractor")
+            .arg("--database_path")
+            .arg(&database)
+            .arg("--image_path")
+            .arg(images)
+            .args(["--ImageReader.camera_model", "OPENCV"])
+            .args([
+                "--ImageReader.single_camera",
+                if options.single_camera { "1" } else { "0" },
+            ]),
+    )
+    .await?;
+
+    let matcher = match options.matcher {
+        SfmMatcher::Exhaustive => "exhaustive_matcher",
+        SfmMatcher::Sequential => "sequential_matcher",
+    };
+    run(
+        "Feature matching",
+        Command::new(&colmap)
+            .arg(matcher)
+            .arg("--database_path")
+            .arg(&database),
+    )
+    .await?;
+
+    let mut mapper = if options.engine == SfmEngine::Glomap {
+        Command::new(
+            options
+                .glomap_binary
+                .clone()
+                .unwrap_or_else(|| "glomap".into()),
+        )
+    } else {
+        Command::new(&colmap)
+    };
+    mapper
+        .arg("mapper")
+        .arg("--database_path")
+        .arg(&database)
+        .arg("--image_path")
+        .arg(images)
+        .arg("--output_path")
+        .arg(&sparse);
+    run("Mapping", &mut mapper).await?;
+
+    // Mappers write a reconstruction for each group of images they could connect, the first is
+    // usually the largest.
+    let model = sparse.join("0");
+    if !tokio::fs::try_exists(&model).await? {
+        return Err(SfmError::NoReconstruction);
+    }
+
+    run(
+        "Undistortion",
+        Command::new(&colmap)
+            .arg("image_undistorter")
+            .arg("--image_path")
+            .arg(images)
+            .arg("--input_path")
+            .arg(&model)
+            .arg("--output_path")
+            .arg(output)
+            .args(["--output_type", "COLMAP"]),
+    )
+    .await
+}
+
+/// Copy the features and sparse model of the reconstruction in `workspace` to `out`, in the
+/// layout of COLMAP, for tools that localize new photos against a reconstruction (eg. hloc).
+///
+/// This writes `database.db`, with the keypoints, descriptors and matches of all photos, and the
+/// model of the photos as they were taken (not undistorted) to `sparse/0`, both as binary and text
+/// files. Image names in the model are relative to the directory of photos.
+pub async fn export_features(
+    workspace: &Path,
+    out: &Path,
+    options: &SfmOptions,
+) -> Result<(), SfmError> {
+    let database = workspace.join("database.db");
+    let model = workspace.join("sparse").join("0");
+    if !tokio::fs::try_exists(&database).await?
+        || !tokio::fs::try_exists(model.join("cameras.bin")).await?
+    {
+        return Err(SfmError::NoReconstruction);
+    }
+
+    let out_model = out.join("sparse").join("0");
+    tokio::fs::create_dir_all(&out_model).await?;
+    tokio::fs::copy(&database, out.join("database.db")).await?;
+    let mut files = tokio::fs::read_dir(&model).await?;
+    while let Some(entry) = files.next_entry().await? {
+        if entry.file_type().await?.is_file() {
+            tokio::fs::copy(entry.path(), out_model.join(entry.file_name())).await?;
+        }
+    }
+
+    let colmap = options
+        .colmap_binary
+        .clone()
+        .unwrap_or_else(|| "colmap".into());
+    run(
+        "Model conversion",
+        Command::new(&colmap)
+            .arg("model_converter")
+            .arg("--input_path")
+            .arg(&out_model)
+            .arg("--output_path")
+            .arg(&out_model)
+            .args(["--output_type", "TXT"]),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SfmMatcher;
+
+    // An empty directory for a test.
+    async fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("brush_sfm_{name}_{}", std::process::id()));
+        remove_dir(&dir).await.expect("Remove old test files");
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .expect("Create test dir");
+        dir
+    }
+
+    fn options(command: Option<String>) -> SfmOptions {
+        SfmOptions {
+            engine: SfmEngine::Command,
+            matcher: SfmMatcher::Exhaustive,
+            single_camera: false,
+            colmap_binary: None,
+            glomap_binary: None,
+            command,
+        }
+    }
+
+    #[test]
+    fn commands_replace_placeholders() {
+        let args = command_args(
+            "vggt --images {images} --out {output}",
+            Path::new("/my photos"),
+            Path::new("/out"),
+        )
+        .expect("Valid command");
+        assert_eq!(args, ["vggt", "--images", "/my photos", "--out", "/out"]);
+
+        for command in ["", "  ", "vggt {images}"] {
+            assert!(matches!(
+                command_args(command, Path::new("a"), Path::new("b")),
+                Err(SfmError::InvalidCommand(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn keys_change_with_the_photos() {
+        let photos = temp_dir("keys").await;
+        tokio::fs::write(photos.join("a.jpg"), "a")
+            .await
+            .expect("Write test file");
+        tokio::fs::create_dir_all(photos.join("sub"))
+            .await
+            .expect("Create photos dir");
+        tokio::fs::write(photos.join("sub/b.jpg"), "b")
+            .await
+            .expect("Write test file");
+
+        let options = options(None);
+        let key = inputs_key(&photos, &options).await.expect("Key");
+        assert_eq!(inputs_key(&photos, &options).await.expect("Key"), key);
+
+        let sequential = SfmOptions {
+            matcher: SfmMatcher::Sequential,
+            ..options.clone()
+        };
+        assert_ne!(inputs_key(&photos, &sequential).await.expect("Key"), key);
+        // The binaries don't change the result.
+        let colmap = SfmOptions {
+            colmap_binary: Some("/opt/colmap".into()),
+            ..options.clone()
+        };
+        assert_eq!(inputs_key(&photos, &colmap).await.expect("Key"), key);
+
+        tokio::fs::write(photos.join("sub/b.jpg"), "changed")
+            .await
+            .expect("Write test file");
+        let changed = inputs_key(&photos, &options).await.expect("Key");
+        assert_ne!(changed, key);
+        tokio::fs::write(photos.join("c.jpg"), "c")
+            .await
+            .expect("Write test file");
+        assert_ne!(inputs_key(&photos, &options).await.expect("Key"), changed);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn reconstructions_are_reused_until_the_photos_change() {
+        let dir = temp_dir("reuse").await;
+        let photos = dir.join("photos");
+        let workspace = dir.join("photos_sfm");
+        tokio::fs::create_dir_all(&photos)
+            .await
+            .expect("Create photos dir");
+        tokio::fs::write(photos.join("a.jpg"), "a")
+            .await
+            .expect("Write test file");
+
+        // Writes an empty model, and counts how often it ran.
+        let script = dir.join("engine.sh");
+        tokio::fs::write(
+            &script,
+            "echo run >> \"$(dirname \"$0\")/runs\"\n\
+             mkdir -p \"$2/sparse/0\"\n\
+             touch \"$2/sparse/0/cameras.txt\"\n",
+        )
+        .await
+        .expect("Write test file");
+        let options = options(Some(format!(
+            "sh {} {{images}} {{output}}",
+            script.display()
+        )));
+        let runs = async || {
+            let runs = tokio::fs::read_to_string(dir.join("runs"))
+                .await
+                .expect("Engine ran");
+            runs.lines().count()
+        };
+
+        let output = reconstruct(&photos, &workspace, &options)
+            .await
+            .expect("Reconstruct");
+        assert!(output.join("sparse/0/cameras.txt").exists());
+        assert_eq!(
+            std::fs::read(output.join("images/a.jpg")).expect("Copied photo"),
+            b"a"
+        );
+        assert_eq!(runs().await, 1);
+
+        reconstruct(&photos, &workspace, &options)
+            .await
+            .expect("Reconstruct");
+        assert_eq!(runs().await, 1);
+
+        tokio::fs::write(photos.join("a.jpg"), "changed")
+            .await
+            .expect("Write test file");
+        let output = reconstruct(&photos, &workspace, &options)
+            .await
+            .expect("Reconstruct");
+        assert_eq!(runs().await, 2);
+        assert_eq!(
+            std::fs::read(output.join("images/a.jpg")).expect("Copied photo"),
+            b"changed"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn failed_commands_are_errors() {
+        let photos = temp_dir("failed").await;
+        let workspace = temp_dir("failed_workspace").await;
+        let failing = options(Some("false {output}".to_owned()));
+        assert!(matches!(
+            reconstruct(&photos, &workspace, &failing).await,
+            Err(SfmError::StepFailed { .. })
+        ));
+        // Succeeding without writing a model isn't a reconstruction either.
+        let empty = options(Some("true {output}".to_owned()));
+        assert!(matches!(
+            reconstruct(&photos, &workspace, &empty).await,
+            Err(SfmError::NoReconstruction)
+        ));
+        assert!(!workspace.join("undistorted").exists());
+    }
+}
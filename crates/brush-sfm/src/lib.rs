@@ -0,0 +1,54 @@
+// Camera poses for a directory of photos, from a structure from motion engine.
+//
+// Brush doesn't match features or solve for poses itself. This runs an installed engine on the
+// photos, or a command of the user's own, and its reconstruction is then loaded like any other
+// COLMAP dataset.
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_family = "wasm"))]
+mod engine;
+
+#[cfg(not(target_family = "wasm"))]
+pub use engine::{SfmError, export_features, reconstruct};
+
+/// Engine to solve for the camera poses with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Serialize, Deserialize)]
+pub enum SfmEngine {
+    /// The incremental mapper of COLMAP. Robust, but slow for large sets of photos.
+    Colmap,
+    /// The global mapper of GLOMAP, on features matched by COLMAP. Much faster for large sets of
+    /// photos, needs both to be installed.
+    Glomap,
+    /// The command of [`SfmOptions::command`], for tools that don't need COLMAP, like learned
+    /// reconstruction models. It has to write a COLMAP model of pinhole cameras.
+    Command,
+}
+
+/// Which pairs of images to match features between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Serialize, Deserialize)]
+pub enum SfmMatcher {
+    /// All pairs, for photos taken in no particular order.
+    Exhaustive,
+    /// Each image with the ones just before and after it by name, for frames of a video.
+    Sequential,
+}
+
+/// How to run a reconstruction.
+#[derive(Debug, Clone)]
+pub struct SfmOptions {
+    pub engine: SfmEngine,
+    pub matcher: SfmMatcher,
+    /// All photos were taken with the same camera and zoom, so they can share intrinsics.
+    pub single_camera: bool,
+    /// Path to the COLMAP binary, if it isn't on the PATH.
+    pub colmap_binary: Option<PathBuf>,
+    /// Path to the GLOMAP binary, if it isn't on the PATH.
+    pub glomap_binary: Option<PathBuf>,
+    /// Command line of the [`SfmEngine::Command`] engine. `{images}` is replaced by the directory
+    /// of photos, and `{output}` by the directory to write the model to. The photos are copied
+    /// to `images` in the output, unless the command writes its own.
+    pub command: Option<String>,
+}
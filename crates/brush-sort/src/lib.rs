@@ -23,19 +23,66 @@ mod shaders;
 const WG: u32 = shaders::sorting::WG;
 const ELEMENTS_PER_THREAD: u32 = shaders::sorting::ELEMENTS_PER_THREAD;
 const BLOCK_SIZE: u32 = WG * ELEMENTS_PER_THREAD;
-const BIN_COUNT: u32 = shaders::sorting::BIN_COUNT;
 
-kernel_source_gen!(SortCount {}, sort_count);
-kernel_source_gen!(SortReduce {}, sort_reduce);
-kernel_source_gen!(SortScanAdd {}, sort_scan_add);
-kernel_source_gen!(SortScan {}, sort_scan);
-kernel_source_gen!(SortScatter {}, sort_scatter);
+kernel_source_gen!(SortCount { wide }, sort_count);
+kernel_source_gen!(SortReduce { wide }, sort_reduce);
+kernel_source_gen!(SortScanAdd { wide }, sort_scan_add);
+kernel_source_gen!(SortScan { wide }, sort_scan);
+kernel_source_gen!(SortScatter { wide }, sort_scatter);
+
+/// Number of key bits sorted in each pass of the radix sort.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RadixDigits {
+    /// 16 bins per pass.
+    Four,
+    /// 256 bins per pass. Each pass is a bit more work, but it takes half the passes over the
+    /// keys, which wins for large inputs.
+    Eight,
+}
+
+impl RadixDigits {
+    pub fn bits(self) -> u32 {
+        match self {
+            Self::Four => 4,
+            Self::Eight => 8,
+        }
+    }
+
+    fn bin_count(self) -> u32 {
+        1 << self.bits()
+    }
 
+    fn wide(self) -> bool {
+        self == Self::Eight
+    }
+}
+
+/// Sort values by their keys, using the lowest `sorting_bits` bits of the keys.
+///
+/// The number of keys to sort is read from `n_sort` on the GPU, so the work is dispatched
+/// without reading anything back.
 pub fn radix_argsort(
     input_keys: CubeTensor<WgpuRuntime>,
     input_values: CubeTensor<WgpuRuntime>,
     n_sort: &CubeTensor<WgpuRuntime>,
     sorting_bits: u32,
+) -> (CubeTensor<WgpuRuntime>, CubeTensor<WgpuRuntime>) {
+    radix_argsort_with_digits(
+        input_keys,
+        input_values,
+        n_sort,
+        sorting_bits,
+        RadixDigits::Eight,
+    )
+}
+
+/// Like [`radix_argsort`], with a specific digit size.
+pub fn radix_argsort_with_digits(
+    input_keys: CubeTensor<WgpuRuntime>,
+    input_values: CubeTensor<WgpuRuntime>,
+    n_sort: &CubeTensor<WgpuRuntime>,
+    sorting_bits: u32,
+    digits: RadixDigits,
 ) -> (CubeTensor<WgpuRuntime>, CubeTensor<WgpuRuntime>) {
     assert_eq!(
         input_keys.shape.dims[0], input_values.shape.dims[0],
@@ -53,25 +100,29 @@ pub fn radix_argsort(
     let device = &input_keys.device.clone();
 
     let max_needed_wgs = max_n.div_ceil(BLOCK_SIZE);
+    let bin_count = digits.bin_count();
+    let wide = digits.wide();
 
     let num_wgs = create_dispatch_buffer(n_sort.clone(), [BLOCK_SIZE, 1, 1]);
     let num_reduce_wgs: Tensor<CubeBackend<WgpuRuntime, f32, i32, u32>, 1, Int> =
         Tensor::from_primitive(create_dispatch_buffer(num_wgs.clone(), [BLOCK_SIZE, 1, 1]))
-            * Tensor::from_ints([BIN_COUNT, 1, 1], device);
+            * Tensor::from_ints([bin_count, 1, 1], device);
     let num_reduce_wgs: CubeTensor<WgpuRuntime> = num_reduce_wgs.into_primitive();
 
     let mut cur_keys = input_keys;
     let mut cur_vals = input_values;
 
-    for pass in 0..sorting_bits.div_ceil(4) {
+    for pass in 0..sorting_bits.div_ceil(digits.bits()) {
         let uniforms_buffer: CubeTensor<WgpuRuntime> = create_uniform_buffer(
-            shaders::sort_count::Uniforms { shift: pass * 4 },
+            shaders::sort_count::Uniforms {
+                shift: pass * digits.bits(),
+            },
             device,
             client,
         );
 
         let count_buf = create_tensor::<1, WgpuRuntime>(
-            [(max_needed_wgs as usize) * 16],
+            [(max_needed_wgs * bin_count) as usize],
             device,
             client,
             DType::I32,
@@ -79,7 +130,7 @@ pub fn radix_argsort(
 
         // use safe distpatch as dynamic work count isn't verified.
         client.execute(
-            SortCount::task(),
+            SortCount::task(wide),
             CubeCount::Dynamic(num_wgs.clone().handle.binding()),
             Bindings::new().with_buffers(vec![
                 uniforms_buffer.clone().handle.binding(),
@@ -90,11 +141,18 @@ pub fn radix_argsort(
         );
 
         {
-            let reduced_buf =
-                create_tensor::<1, WgpuRuntime>([BLOCK_SIZE as usize], device, client, DType::I32);
+            // One partial sum per bin for each block of counts. Allocate at least a full block,
+            // as the scan reads whole blocks.
+            let max_reduce_wgs = bin_count * max_needed_wgs.div_ceil(BLOCK_SIZE);
+            let reduced_buf = create_tensor::<1, WgpuRuntime>(
+                [max_reduce_wgs.max(BLOCK_SIZE) as usize],
+                device,
+                client,
+                DType::I32,
+            );
 
             client.execute(
-                SortReduce::task(),
+                SortReduce::task(wide),
                 CubeCount::Dynamic(num_reduce_wgs.clone().handle.binding()),
                 Bindings::new().with_buffers(vec![
                     n_sort.clone().handle.binding(),
@@ -103,20 +161,18 @@ pub fn radix_argsort(
                 ]),
             );
 
-            // SAFETY: No OOB or loops in kernel.
-            unsafe {
-                client.execute_unchecked(
-                    SortScan::task(),
-                    CubeCount::Static(1, 1, 1),
-                    Bindings::new().with_buffers(vec![
-                        n_sort.clone().handle.binding(),
-                        reduced_buf.clone().handle.binding(),
-                    ]),
-                );
-            }
+            // The scan loops over a dynamic number of blocks, so use a checked dispatch.
+            client.execute(
+                SortScan::task(wide),
+                CubeCount::Static(1, 1, 1),
+                Bindings::new().with_buffers(vec![
+                    n_sort.clone().handle.binding(),
+                    reduced_buf.clone().handle.binding(),
+                ]),
+            );
 
             client.execute(
-                SortScanAdd::task(),
+                SortScanAdd::task(wide),
                 CubeCount::Dynamic(num_reduce_wgs.handle.clone().binding()),
                 Bindings::new().with_buffers(vec![
                     n_sort.clone().handle.binding(),
@@ -131,7 +187,7 @@ pub fn radix_argsort(
             create_tensor::<1, _>([max_n as usize], device, client, cur_vals.dtype());
 
         client.execute(
-            SortScatter::task(),
+            SortScatter::task(wide),
             CubeCount::Dynamic(num_wgs.clone().handle.binding()),
             Bindings::new().with_buffers(vec![
                 uniforms_buffer.handle.clone().binding(),
@@ -152,19 +208,59 @@ pub fn radix_argsort(
 
 #[cfg(all(test, not(target_family = "wasm")))]
 mod tests {
-    use crate::radix_argsort;
+    use crate::{RadixDigits, radix_argsort_with_digits};
     use burn::tensor::{Int, Tensor};
     use burn_wgpu::{CubeBackend, WgpuRuntime};
     use rand::Rng;
 
     type Backend = CubeBackend<WgpuRuntime, f32, i32, u32>;
 
+    const DIGITS: [RadixDigits; 2] = [RadixDigits::Four, RadixDigits::Eight];
+
     pub fn argsort<T: Ord>(data: &[T]) -> Vec<usize> {
         let mut indices = (0..data.len()).collect::<Vec<_>>();
         indices.sort_by_key(|&i| &data[i]);
         indices
     }
 
+    fn check_sort(keys_inp: &[u32], digits: RadixDigits) {
+        let values_inp: Vec<_> = keys_inp.iter().map(|&x| x.wrapping_mul(2) + 5).collect();
+
+        let device = Default::default();
+        let to_tensor = |data: &[u32]| {
+            let data: Vec<i32> = data.iter().map(|&x| x as i32).collect();
+            Tensor::<Backend, 1, Int>::from_ints(data.as_slice(), &device).into_primitive()
+        };
+        let num_points =
+            Tensor::<Backend, 1, Int>::from_ints([keys_inp.len() as i32], &device).into_primitive();
+        let (ret_keys, ret_values) = radix_argsort_with_digits(
+            to_tensor(keys_inp),
+            to_tensor(&values_inp),
+            &num_points,
+            32,
+            digits,
+        );
+
+        let ret_keys = Tensor::<Backend, 1, Int>::from_primitive(ret_keys).into_data();
+        let ret_values = Tensor::<Backend, 1, Int>::from_primitive(ret_values).into_data();
+
+        let inds = argsort(keys_inp);
+        let ref_keys: Vec<u32> = inds.iter().map(|&i| keys_inp[i]).collect();
+        let ref_values: Vec<u32> = inds.iter().map(|&i| values_inp[i]).collect();
+
+        for (((key, val), ref_key), ref_val) in ret_keys
+            .as_slice::<i32>()
+            .expect("Wrong type")
+            .iter()
+            .zip(ret_values.as_slice::<i32>().expect("Wrong type"))
+            .zip(ref_keys)
+            .zip(ref_values)
+        {
+            assert_eq!(*key, ref_key as i32, "{digits:?}");
+            assert_eq!(*val, ref_val as i32, "{digits:?}");
+        }
+    }
+
     #[test]
     fn test_sorting() {
         for i in 0..128 {
@@ -176,7 +272,7 @@ mod tests {
                 74657,
                 123,
                 999,
-                2i32.pow(24) + 123,
+                2u32.pow(24) + 123,
                 6,
                 7,
                 8,
@@ -185,37 +281,8 @@ mod tests {
                 16 + i,
                 128 * i,
             ];
-
-            let values_inp: Vec<_> = keys_inp.iter().copied().map(|x| x * 2 + 5).collect();
-
-            let device = Default::default();
-            let keys = Tensor::<Backend, 1, Int>::from_ints(keys_inp, &device).into_primitive();
-
-            let values = Tensor::<Backend, 1, Int>::from_ints(values_inp.as_slice(), &device)
-                .into_primitive();
-            let num_points = Tensor::<Backend, 1, Int>::from_ints([keys_inp.len() as i32], &device)
-                .into_primitive();
-            let (ret_keys, ret_values) = radix_argsort(keys, values, &num_points, 32);
-
-            let ret_keys = Tensor::<Backend, 1, Int>::from_primitive(ret_keys).into_data();
-
-            let ret_values = Tensor::<Backend, 1, Int>::from_primitive(ret_values).into_data();
-
-            let inds = argsort(&keys_inp);
-
-            let ref_keys: Vec<u32> = inds.iter().map(|&i| keys_inp[i] as u32).collect();
-            let ref_values: Vec<u32> = inds.iter().map(|&i| values_inp[i] as u32).collect();
-
-            for (((key, val), ref_key), ref_val) in ret_keys
-                .as_slice::<i32>()
-                .expect("Wrong type")
-                .iter()
-                .zip(ret_values.as_slice::<i32>().expect("Wrong type"))
-                .zip(ref_keys)
-                .zip(ref_values)
-            {
-                assert_eq!(*key, ref_key as i32);
-                assert_eq!(*val, ref_val as i32);
+            for digits in DIGITS {
+                check_sort(&keys_inp, digits);
             }
         }
     }
@@ -235,36 +302,18 @@ mod tests {
                 }
             }
         }
+        for digits in DIGITS {
+            check_sort(&keys_inp, digits);
+        }
+    }
 
-        let values_inp: Vec<_> = keys_inp.iter().map(|&x| x * 2 + 5).collect();
-
-        let device = Default::default();
-        let keys =
-            Tensor::<Backend, 1, Int>::from_ints(keys_inp.as_slice(), &device).into_primitive();
-        let values =
-            Tensor::<Backend, 1, Int>::from_ints(values_inp.as_slice(), &device).into_primitive();
-        let num_points =
-            Tensor::<Backend, 1, Int>::from_ints([keys_inp.len() as i32], &device).into_primitive();
-
-        let (ret_keys, ret_values) = radix_argsort(keys, values, &num_points, 32);
-
-        let ret_keys = Tensor::<Backend, 1, Int>::from_primitive(ret_keys).to_data();
-        let ret_values = Tensor::<Backend, 1, Int>::from_primitive(ret_values).to_data();
-
-        let inds = argsort(&keys_inp);
-        let ref_keys: Vec<u32> = inds.iter().map(|&i| keys_inp[i]).collect();
-        let ref_values: Vec<u32> = inds.iter().map(|&i| values_inp[i]).collect();
-
-        for (((key, val), ref_key), ref_val) in ret_keys
-            .as_slice::<i32>()
-            .expect("Wrong type")
-            .iter()
-            .zip(ret_values.as_slice::<i32>().expect("Wrong type"))
-            .zip(ref_keys)
-            .zip(ref_values)
-        {
-            assert_eq!(*key, ref_key as i32);
-            assert_eq!(*val, ref_val as i32);
+    #[test]
+    fn test_sorting_many_blocks() {
+        // Enough keys that the partial sums of wide digits don't fit in a single block.
+        let mut rng = rand::rng();
+        let keys_inp: Vec<u32> = (0..5_000_000).map(|_| rng.random()).collect();
+        for digits in DIGITS {
+            check_sort(&keys_inp, digits);
         }
     }
 }
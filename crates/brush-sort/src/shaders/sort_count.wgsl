@@ -39,7 +39,7 @@ fn main(
 
     for (var i = 0u; i < sorting::ELEMENTS_PER_THREAD; i++) {
         if data_index < num_keys {
-            let local_key = (src[data_index] >> shift_bit) & 0xfu;
+            let local_key = (src[data_index] >> shift_bit) & sorting::DIGIT_MASK;
             atomicAdd(&histogram[local_key], 1u);
         }
         data_index += sorting::WG;
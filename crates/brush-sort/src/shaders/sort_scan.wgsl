@@ -13,49 +13,64 @@ fn main(
     @builtin(workgroup_id) group_id: vec3<u32>,
 ) {
     let num_keys = num_keys_arr[0];
-    // let num_keys = num_keys_arr[0];
     let num_wgs = sorting::div_ceil(num_keys, sorting::BLOCK_SIZE);
     let num_reduce_wgs = sorting::BIN_COUNT * sorting::div_ceil(num_wgs, sorting::BLOCK_SIZE);
 
-    for (var i = 0u; i < sorting::ELEMENTS_PER_THREAD; i++) {
-        let data_index = i * sorting::WG + local_id.x;
-        let col = (i * sorting::WG + local_id.x) / sorting::ELEMENTS_PER_THREAD;
-        let row = (i * sorting::WG + local_id.x) % sorting::ELEMENTS_PER_THREAD;
-        lds[row][col] = reduced[data_index];
-    }
-    workgroupBarrier();
-    var sum = 0u;
-    for (var i = 0u; i < sorting::ELEMENTS_PER_THREAD; i++) {
-        let tmp = lds[i][local_id.x];
-        lds[i][local_id.x] = sum;
-        sum += tmp;
-    }
-    // workgroup prefix sum
-    sums[local_id.x] = sum;
-    for (var i = 0u; i < 8u; i++) {
+    // With wide digits there can be more partial sums than fit in one block. Scan them block by
+    // block, carrying the total of the previous blocks.
+    let num_blocks = sorting::div_ceil(num_reduce_wgs, sorting::BLOCK_SIZE);
+    var carry = 0u;
+
+    for (var block = 0u; block < num_blocks; block++) {
+        let block_start = block * sorting::BLOCK_SIZE;
+        // Previous block might still be reading from lds.
         workgroupBarrier();
-        if local_id.x >= (1u << i) {
-            sum += sums[local_id.x - (1u << i)];
+
+        for (var i = 0u; i < sorting::ELEMENTS_PER_THREAD; i++) {
+            let data_index = block_start + i * sorting::WG + local_id.x;
+            let col = (i * sorting::WG + local_id.x) / sorting::ELEMENTS_PER_THREAD;
+            let row = (i * sorting::WG + local_id.x) % sorting::ELEMENTS_PER_THREAD;
+            var value = 0u;
+            if data_index < num_reduce_wgs {
+                value = reduced[data_index];
+            }
+            lds[row][col] = value;
         }
         workgroupBarrier();
+        var sum = 0u;
+        for (var i = 0u; i < sorting::ELEMENTS_PER_THREAD; i++) {
+            let tmp = lds[i][local_id.x];
+            lds[i][local_id.x] = sum;
+            sum += tmp;
+        }
+        // workgroup prefix sum
         sums[local_id.x] = sum;
-    }
-    workgroupBarrier();
-    sum = 0u;
-    if local_id.x > 0u {
-        sum = sums[local_id.x - 1u];
-    }
-    for (var i = 0u; i < sorting::ELEMENTS_PER_THREAD; i++) {
-        lds[i][local_id.x] += sum;
-    }
-    // lds now contains exclusive prefix sum
-    workgroupBarrier();
-    for (var i = 0u; i < sorting::ELEMENTS_PER_THREAD; i++) {
-        let data_index = i * sorting::WG + local_id.x;
-        let col = (i * sorting::WG + local_id.x) / sorting::ELEMENTS_PER_THREAD;
-        let row = (i * sorting::WG + local_id.x) % sorting::ELEMENTS_PER_THREAD;
-        if data_index < num_reduce_wgs {
-            reduced[data_index] = lds[row][col];
+        for (var i = 0u; i < 8u; i++) {
+            workgroupBarrier();
+            if local_id.x >= (1u << i) {
+                sum += sums[local_id.x - (1u << i)];
+            }
+            workgroupBarrier();
+            sums[local_id.x] = sum;
+        }
+        workgroupBarrier();
+        sum = carry;
+        if local_id.x > 0u {
+            sum += sums[local_id.x - 1u];
+        }
+        carry += sums[sorting::WG - 1u];
+        for (var i = 0u; i < sorting::ELEMENTS_PER_THREAD; i++) {
+            lds[i][local_id.x] += sum;
+        }
+        // lds now contains exclusive prefix sum
+        workgroupBarrier();
+        for (var i = 0u; i < sorting::ELEMENTS_PER_THREAD; i++) {
+            let data_index = block_start + i * sorting::WG + local_id.x;
+            let col = (i * sorting::WG + local_id.x) / sorting::ELEMENTS_PER_THREAD;
+            let row = (i * sorting::WG + local_id.x) % sorting::ELEMENTS_PER_THREAD;
+            if data_index < num_reduce_wgs {
+                reduced[data_index] = lds[row][col];
+            }
         }
     }
 }
@@ -53,7 +53,7 @@ fn main(
         }
 
         for (var bit_shift = 0u; bit_shift < sorting::BITS_PER_PASS; bit_shift += 2u) {
-            let key_index = (local_key >> config.shift) & 0xfu;
+            let key_index = (local_key >> config.shift) & sorting::DIGIT_MASK;
             let bit_key = (key_index >> bit_shift) & 3u;
             var packed_histogram = 1u << (bit_key * 8u);
             // workgroup prefix sum
@@ -86,7 +86,7 @@ fn main(
             local_value = lds_sums[local_id.x];
             workgroupBarrier();
         }
-        let key_index = (local_key >> config.shift) & 0xfu;
+        let key_index = (local_key >> config.shift) & sorting::DIGIT_MASK;
         atomicAdd(&local_histogram[key_index], 1u);
         workgroupBarrier();
         var histogram_local_sum = 0u;
@@ -98,7 +98,7 @@ fn main(
         if local_id.x < sorting::BIN_COUNT {
             lds_scratch[local_id.x] = histogram_prefix_sum;
         }
-        for (var i = 0u; i < 4u; i++) {
+        for (var i = 0u; i < sorting::BITS_PER_PASS; i++) {
             workgroupBarrier();
             if local_id.x >= (1u << i) && local_id.x < sorting::BIN_COUNT {
                 histogram_prefix_sum += lds_scratch[local_id.x - (1u << i)];
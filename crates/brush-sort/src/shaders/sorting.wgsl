@@ -16,8 +16,14 @@
 const OFFSET: u32 = 42;
 const WG: u32 = 256;
 
+// Wide sorts use 8 bit digits, which halves the number of passes over the keys.
+#ifdef WIDE
+const BITS_PER_PASS: u32 = 8;
+#else
 const BITS_PER_PASS: u32 = 4;
+#endif
 const BIN_COUNT: u32 = 1u << BITS_PER_PASS;
+const DIGIT_MASK: u32 = BIN_COUNT - 1u;
 const HISTOGRAM_SIZE: u32 = WG * BIN_COUNT;
 const ELEMENTS_PER_THREAD: u32 = 4;
 
@@ -1,3 +1,4 @@
+use brush_dataset::{color::ColorSpace, scene_loader::ViewSampling};
 use burn::config::Config;
 use clap::Args;
 
@@ -8,6 +9,13 @@ pub struct TrainConfig {
     #[arg(long, help_heading = "Training options", default_value = "30000")]
     pub total_steps: u32,
 
+    /// Train on downsampled images for this many steps at the start, first at a quarter and then
+    /// at half resolution, before moving on to full resolution. Speeds up early training and
+    /// densification on large images.
+    #[config(default = 0)]
+    #[arg(long, help_heading = "Training options", default_value = "0")]
+    pub coarse_to_fine_steps: u32,
+
     /// Max nr. of splats. This is an upper bound, but the actual final number of splats might be lower than this.
     #[config(default = 10000000)]
     #[arg(long, help_heading = "Refine options", default_value = "10000000")]
@@ -80,6 +88,48 @@ pub struct TrainConfig {
     #[arg(long, help_heading = "Refine options", default_value = "12500")]
     pub growth_stop_iter: u32,
 
+    /// Number of views rendered for each step, with the loss averaged over them. Larger batches
+    /// give less noisy gradients, which helps with few input views, but each step takes longer
+    /// and needs more memory.
+    #[config(default = 1)]
+    #[arg(long, help_heading = "Training options", default_value = "1")]
+    pub batch_size: u32,
+
+    /// How to pick the views to train on. `loss` trains views with a higher recent loss more
+    /// often, which helps on captures that cover some parts of the scene with few views. Runs
+    /// with --deterministic always sample uniformly.
+    #[config(default = "ViewSampling::Loss")]
+    #[arg(
+        long,
+        help_heading = "Training options",
+        value_enum,
+        default_value = "loss"
+    )]
+    pub view_sampling: ViewSampling,
+
+    /// Train on random crops of at most this many pixels on a side instead of whole images, so
+    /// very large images need far less GPU memory. SSIM is computed within each crop. Panoramas
+    /// are always trained whole.
+    #[arg(long, help_heading = "Training options")]
+    pub patch_size: Option<u32>,
+
+    /// Splats closer to the training cameras than this are left out of the training renders.
+    /// Raise this when splats grow huge right in front of the cameras, and blow up training.
+    #[config(default = 0.01)]
+    #[arg(long, help_heading = "Training options", default_value = "0.01")]
+    pub near_plane: f32,
+
+    /// Splats further from the training cameras than this are left out of the training renders.
+    #[config(default = 1e10)]
+    #[arg(long, help_heading = "Training options", default_value = "1e10")]
+    pub far_plane: f32,
+
+    /// Splats drawn with a radius below this many pixels are left out of the training renders.
+    /// These also don't get gradients, so this is best left low.
+    #[config(default = 0.0)]
+    #[arg(long, help_heading = "Training options", default_value = "0.0")]
+    pub min_splat_radius: f32,
+
     /// Weight of SSIM loss (compared to l1 loss)
     #[config(default = 0.2)]
     #[clap(long, help_heading = "Training options", default_value = "0.2")]
@@ -90,8 +140,185 @@ pub struct TrainConfig {
     #[arg(long, help_heading = "Training options", default_value = "1e-8")]
     pub opac_loss_weight: f32,
 
+    /// Color space to compare renders to images in. Images are sRGB encoded, except for HDR
+    /// datasets, which are linear and compared through their tonemapping in `srgb`.
+    #[config(default = "ColorSpace::Srgb")]
+    #[arg(
+        long,
+        help_heading = "Training options",
+        value_enum,
+        default_value = "srgb"
+    )]
+    pub loss_color_space: ColorSpace,
+
+    /// Leave transient objects, like people and cars passing through a casual capture, out of the
+    /// loss, so they don't leave ghost splats behind. Pixels that differ from the render much more
+    /// than the rest of the view are ignored, in regions and patches where most pixels do.
+    #[config(default = false)]
+    #[arg(long, help_heading = "Training options", default_value = "false")]
+    pub robust_loss: bool,
+
+    /// Quantile of the errors of a view above which pixels can be left out by the robust loss.
+    /// Lower values leave out more.
+    #[config(default = 0.8)]
+    #[arg(long, help_heading = "Training options", default_value = "0.8")]
+    pub robust_inlier_quantile: f32,
+
+    /// Step at which the robust loss starts. Early on, the errors are large everywhere, and
+    /// don't tell transients apart yet.
+    #[config(default = 1000)]
+    #[arg(long, help_heading = "Training options", default_value = "1000")]
+    pub robust_start: u32,
+
+    /// Fraction of splats to randomly leave out of each training render, with the opacity of the
+    /// others raised to make up for it. This keeps splats from depending on each other, which
+    /// reduces floaters, especially with few input views. 0 disables dropout.
+    ///
+    /// Opacities are divided by 1 - rate, which keeps the expected render the same, as splats are
+    /// left out independently. Splats more opaque than 1 - rate can't be raised that far though,
+    /// so with dropout, opaque surfaces render more transparent on average than they are.
+    #[config(default = 0.0)]
+    #[arg(long, help_heading = "Training options", default_value = "0.0")]
+    pub dropout_rate: f32,
+
+    /// Step at which splat dropout ends. The rate decreases linearly to zero up to this step, so
+    /// the splats settle without dropout at the end of training.
+    #[config(default = 15000)]
+    #[arg(long, help_heading = "Training options", default_value = "15000")]
+    pub dropout_end: u32,
+
+    /// Offset the camera of each step by a different subpixel amount, which over many steps
+    /// supersamples the training views. This antialiases splats smaller than a pixel.
+    #[config(default = false)]
+    #[arg(long, help_heading = "Training options", default_value = "false")]
+    pub subpixel_jitter: bool,
+
     /// Weight of l1 loss on alpha if input view has transparency.
     #[config(default = 0.1)]
     #[arg(long, help_heading = "Refine options", default_value = "0.1")]
     pub match_alpha_weight: f32,
+
+    /// Compute the image losses in half precision (f16), while keeping the splats in f32. This saves
+    /// memory and time on large images. Needs a GPU with f16 support, otherwise training falls back
+    /// to full precision.
+    #[config(default = false)]
+    #[arg(long, help_heading = "Training options", default_value = "false")]
+    pub half_precision: bool,
+
+    /// Starting loss scale for half precision training, to keep small gradients from rounding to
+    /// zero. Lowered automatically when gradients overflow.
+    #[config(default = 1024.0)]
+    #[arg(long, help_heading = "Training options", default_value = "1024.0")]
+    pub loss_scale: f32,
+
+    /// Detect abrupt white balance changes between adjacent frames (eg. from phone video), and learn
+    /// a color correction per group of frames, so color flicker isn't baked into the splats.
+    #[config(default = false)]
+    #[arg(long, help_heading = "White balance options", default_value = "false")]
+    pub wb_correction: bool,
+
+    /// How far the chromaticity of a frame has to jump to start a new white balance group.
+    #[config(default = 0.02)]
+    #[arg(long, help_heading = "White balance options", default_value = "0.02")]
+    pub wb_group_threshold: f32,
+
+    /// Learning rate for the per group white balance correction.
+    #[config(default = 1e-3)]
+    #[arg(long, help_heading = "White balance options", default_value = "1e-3")]
+    pub lr_wb: f64,
+
+    /// Number of features to learn per splat, from the feature maps of the views (eg. SAM or CLIP
+    /// features). Feature maps are [H, W, C] .npy files with the name of the image, in a
+    /// `features` directory next to the images, with C equal to this. 0 disables this.
+    #[config(default = 0)]
+    #[arg(long, help_heading = "Feature options", default_value = "0")]
+    pub feature_dim: u32,
+
+    /// Number of latent features the splats render, which a learned linear map decodes to the
+    /// features of the feature maps. Each 3 latents take an extra render of every view.
+    #[config(default = 12)]
+    #[arg(long, help_heading = "Feature options", default_value = "12")]
+    pub feature_latent_dim: u32,
+
+    /// Weight of the loss between rendered features and the feature maps.
+    #[config(default = 1.0)]
+    #[arg(long, help_heading = "Feature options", default_value = "1.0")]
+    pub feature_weight: f32,
+
+    /// Learning rate for the features of the splats.
+    #[config(default = 2.5e-3)]
+    #[arg(long, help_heading = "Feature options", default_value = "2.5e-3")]
+    pub lr_features: f64,
+
+    /// Weight of the loss between the rendered depth and the depth maps of the views. Depth maps
+    /// are 16-bit PNGs or float EXRs with the name of the image, in a `depths` directory next to
+    /// the images. 0 disables this.
+    #[config(default = 0.1)]
+    #[arg(long, help_heading = "Depth options", default_value = "0.1")]
+    pub depth_weight: f32,
+
+    /// How much the depth loss of a pixel is weighted by the confidence of its depth, from
+    /// confidence maps in a `confidence` directory next to the images. The confidence is raised to
+    /// this power, so 0 trusts all depths the same, and higher values only trust the most
+    /// confident ones.
+    #[config(default = 1.0)]
+    #[arg(long, help_heading = "Depth options", default_value = "1.0")]
+    pub depth_confidence_power: f32,
+
+    /// Train flat splats, squashed to disks, with the depth distortion and normal consistency
+    /// losses of 2D Gaussian Splatting. Flat splats fit the surfaces of a scene much better, which
+    /// makes for better meshes, at some cost in image quality.
+    ///
+    /// This isn't the ray-disk rasterization of 2DGS: the disks are still drawn as 3D Gaussians,
+    /// with a constant depth over each splat.
+    #[config(default = false)]
+    #[arg(long, help_heading = "Flat splat options", default_value = "false")]
+    pub flat_splats: bool,
+
+    /// Weight of the depth distortion loss of flat splats, which pulls the splats blended into a
+    /// pixel together in depth.
+    #[config(default = 100.0)]
+    #[arg(long, help_heading = "Flat splat options", default_value = "100.0")]
+    pub depth_distortion_weight: f32,
+
+    /// Step to start the depth distortion loss at.
+    #[config(default = 3000)]
+    #[arg(long, help_heading = "Flat splat options", default_value = "3000")]
+    pub depth_distortion_start: u32,
+
+    /// Weight of the normal consistency loss of flat splats, which aligns the disks with the
+    /// surface of the rendered depth.
+    #[config(default = 0.05)]
+    #[arg(long, help_heading = "Flat splat options", default_value = "0.05")]
+    pub normal_consistency_weight: f32,
+
+    /// Step to start the normal consistency loss at.
+    #[config(default = 7000)]
+    #[arg(long, help_heading = "Flat splat options", default_value = "7000")]
+    pub normal_consistency_start: u32,
+
+    /// Train a dynamic scene, where splats move over time, from views with timestamps (eg. the
+    /// `time` of the frames of a transforms.json). Splats learn an offset of their position,
+    /// rotation and opacity at this many keyframes spread evenly over the video, and move linearly
+    /// in between. Each keyframe adds 8 values to train per splat. 0 trains a static scene.
+    #[config(default = 0)]
+    #[arg(long, help_heading = "Dynamic scene options", default_value = "0")]
+    pub motion_keyframes: u32,
+
+    /// Learning rate for the motion of the splats.
+    #[config(default = 1e-3)]
+    #[arg(long, help_heading = "Dynamic scene options", default_value = "1e-3")]
+    pub lr_motion: f64,
+
+    /// Weight of the loss on the change of the motion from one keyframe to the next, which keeps
+    /// the motion smooth.
+    #[config(default = 1.0)]
+    #[arg(long, help_heading = "Dynamic scene options", default_value = "1.0")]
+    pub motion_smoothness_weight: f32,
+
+    /// Weight of the L1 loss on the motion offsets, which keeps the parts of the scene that don't
+    /// move still, instead of wobbling to fit noise in the frames.
+    #[config(default = 0.01)]
+    #[arg(long, help_heading = "Dynamic scene options", default_value = "0.01")]
+    pub motion_sparsity_weight: f32,
 }
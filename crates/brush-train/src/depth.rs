@@ -0,0 +1,73 @@
+// Supervision of the rendered depth of the splats by metric depth maps of the views, eg. from the
+// LiDAR of a phone or rendered from a registered laser scan.
+//
+// Depth is drawn by the regular rasterizer as the base color of the splats, like the moments of
+// flat splats. Unlike for features, the geometry isn't detached, moving the splats is the point.
+use brush_render::{
+    camera::{Camera, CameraModel},
+    shaders::project_visible::SH_C0,
+};
+use brush_render_bwd::{burn_glue::SplatForwardDiff, diff_render::render_splats};
+use burn::{
+    prelude::Backend,
+    tensor::{Tensor, s},
+};
+use glam::Mat3;
+
+/// The geometry of the splats to draw the depth of.
+pub(crate) struct DepthView<'a, B: Backend> {
+    pub(crate) camera: &'a Camera,
+    pub(crate) means: Tensor<B, 2>,
+    pub(crate) log_scales: Tensor<B, 2>,
+    pub(crate) quats: Tensor<B, 2>,
+    pub(crate) opacities: Tensor<B, 1>,
+}
+
+impl<B: Backend + SplatForwardDiff<B>> DepthView<'_, B> {
+    /// Render the depth at the resolution of `target`, [H, W, 2] depths and confidences, and
+    /// compare it to the depths. Errors are relative to the depth, so near surfaces count more
+    /// than far ones, and are weighted by the confidence raised to `confidence_power`.
+    pub(crate) fn loss(&self, target: Tensor<B, 3>, confidence_power: f32) -> Tensor<B, 1> {
+        let [h, w, _] = target.dims();
+        let device = self.means.device();
+        let n = self.means.dims()[0];
+
+        let world_to_local = self.camera.world_to_local();
+        // Points are row vectors, so multiply by the transposed rotation.
+        let rot = Mat3::from(world_to_local.matrix3).to_cols_array();
+        let rot = Tensor::<B, 1>::from_floats(rot, &device).reshape([3, 3]);
+        let translation =
+            Tensor::<B, 1>::from_floats(world_to_local.translation.to_array(), &device)
+                .reshape([1, 3]);
+        let local = self.means.clone().matmul(rot) + translation;
+        // Depth maps of panoramas hold the distance, there's no single view axis.
+        let depth = match self.camera.model {
+            CameraModel::Pinhole => local.slice(s![.., 2..3]),
+            CameraModel::Equirectangular => local.powi_scalar(2).sum_dim(1).sqrt(),
+        };
+
+        let values = Tensor::cat(vec![depth, Tensor::zeros([n, 2], &device)], 1);
+        let sh_coeffs = ((values - 0.5) / SH_C0).reshape([n, 1, 3]);
+        let img = render_splats(
+            self.camera,
+            glam::uvec2(w as u32, h as u32),
+            self.means.clone(),
+            self.log_scales.clone(),
+            self.quats.clone(),
+            sh_coeffs,
+            self.opacities.clone(),
+        )
+        .image;
+
+        // The blended depth is premultiplied by alpha, so compare it to the target depth times
+        // alpha. Pixels the splats barely cover are left to the image loss.
+        let weighted_depth = img.clone().slice(s![.., .., 0..1]);
+        let alpha = img.slice(s![.., .., 3..4]);
+        let target_depth = target.clone().slice(s![.., .., 0..1]);
+        let has_depth = target_depth.clone().greater_elem(0.0).float();
+        let weight = target.slice(s![.., .., 1..2]).powf_scalar(confidence_power) * has_depth;
+        let err =
+            (weighted_depth - alpha * target_depth.clone()).abs() / target_depth.clamp_min(1e-6);
+        (err * weight).mean()
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::Result;
 use brush_dataset::scene::{SceneView, sample_to_tensor, view_to_sample_image};
+use brush_dataset::tonemap::Tonemap;
 use brush_render::SplatForward;
 use brush_render::gaussian_splats::Splats;
 use brush_render::render_aux::RenderAux;
@@ -20,6 +21,7 @@ pub struct EvalSample<B: Backend> {
 pub async fn eval_stats<B: Backend + SplatForward<B>>(
     splats: Splats<B>,
     eval_view: &SceneView,
+    tonemap: Option<Tonemap>,
     device: &B::Device,
 ) -> Result<EvalSample<B>> {
     let gt_img = eval_view.image.load().await?;
@@ -37,6 +39,14 @@ pub async fn eval_stats<B: Backend + SplatForward<B>>(
     let (rendered, aux) = splats.render(&eval_view.camera, res, true);
     let render_rgb = rendered.slice(s![.., .., 0..3]);
 
+    // HDR views are compared, and saved, as they're shown.
+    let (render_rgb, gt_rgb, gt_img) = if let Some(tonemap) = tonemap {
+        let gt_img = tonemap.apply_image(&gt_img);
+        (tonemap.apply(render_rgb), tonemap.apply(gt_rgb), gt_img)
+    } else {
+        (render_rgb, gt_rgb, gt_img)
+    };
+
     // Simulate an 8-bit roundtrip for fair comparison.
     let render_rgb = (render_rgb * 255.0).round() / 255.0;
 
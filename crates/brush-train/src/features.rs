@@ -0,0 +1,317 @@
+// A learned feature vector per splat, rendered like a color and trained to match 2D feature maps
+// of the views (eg. from SAM or CLIP), as in Feature 3DGS (Zhou et al. 2024). Once trained, the
+// features give each splat a meaning, to segment or query the scene by.
+//
+// Feature maps have hundreds of channels, and the rasterizer draws three at a time as the base
+// color of the splats. So splats learn a few latent features instead, which a learned linear map
+// decodes to the channels of the feature maps after rendering, like the speed-up module of
+// Feature 3DGS. Latents go through a sigmoid, which keeps them in the range the rasterizer draws
+// exactly (it clamps colors at zero), and the decoder maps them to whatever range the feature
+// maps have. The geometry of the splats is detached, so only the images shape the scene.
+use brush_render::{camera::Camera, shaders::project_visible::SH_C0};
+use brush_render_bwd::{burn_glue::SplatForwardDiff, diff_render::render_splats};
+use burn::{
+    module::{Module, Param},
+    optim::{GradientsParams, Optimizer, adaptor::OptimizerAdaptor},
+    prelude::Backend,
+    tensor::{Bool, Distribution, Int, Tensor, activation::sigmoid, backend::AutodiffBackend, s},
+};
+
+use crate::{
+    adam_scaled::{AdamScaled, AdamScaledConfig},
+    loss_scale::grad_overflows,
+    train::map_opt,
+};
+
+#[derive(Module, Debug)]
+pub(crate) struct SplatFeatures<B: Backend> {
+    // [N, L] latent features of the splats, before the sigmoid.
+    latents: Param<Tensor<B, 2>>,
+    // [L, C] weights and [C] bias of the map from latents to features.
+    decoder: Param<Tensor<B, 2>>,
+    bias: Param<Tensor<B, 1>>,
+}
+
+impl<B: Backend> SplatFeatures<B> {
+    // Features of the [.., L] `latents`, after the sigmoid.
+    fn decode(&self, latents: Tensor<B, 2>) -> Tensor<B, 2> {
+        latents.matmul(self.decoder.val()) + self.bias.val().unsqueeze_dim(0)
+    }
+}
+
+/// The geometry of the splats to draw the features with.
+pub(crate) struct FeatureView<'a, B: Backend> {
+    pub(crate) camera: &'a Camera,
+    pub(crate) means: Tensor<B, 2>,
+    pub(crate) log_scales: Tensor<B, 2>,
+    pub(crate) quats: Tensor<B, 2>,
+    pub(crate) opacities: Tensor<B, 1>,
+}
+
+pub(crate) struct FeatureTrainer<B: AutodiffBackend> {
+    features: SplatFeatures<B>,
+    optim: OptimizerAdaptor<AdamScaled, SplatFeatures<B>, B>,
+}
+
+fn create_optimizer<B: AutodiffBackend>() -> OptimizerAdaptor<AdamScaled, SplatFeatures<B>, B> {
+    AdamScaledConfig::new().with_epsilon(1e-15).init()
+}
+
+impl<B: AutodiffBackend + SplatForwardDiff<B>> FeatureTrainer<B> {
+    /// Train `dim` features per splat, rendered from `latent_dim` latents (rounded up to a
+    /// multiple of 3).
+    pub(crate) fn new(num_splats: u32, dim: u32, latent_dim: u32, device: &B::Device) -> Self {
+        let latent_dim = (latent_dim.max(1).div_ceil(3) * 3) as usize;
+        let dim = dim as usize;
+        let latents = Tensor::zeros([num_splats as usize, latent_dim], device);
+        // All latents start out the same, the random decoder tells their gradients apart.
+        let std = 1.0 / (latent_dim as f64).sqrt();
+        let decoder = Tensor::random([latent_dim, dim], Distribution::Normal(0.0, std), device);
+        Self {
+            features: SplatFeatures {
+                latents: Param::from_tensor(latents),
+                decoder: Param::from_tensor(decoder),
+                bias: Param::from_tensor(Tensor::zeros([dim], device)),
+            },
+            optim: create_optimizer(),
+        }
+    }
+
+    pub(crate) fn dim(&self) -> usize {
+        self.features.decoder.dims()[1]
+    }
+
+    /// Render the features into a view, at the resolution of `target`, and compare them to the
+    /// [H, W, C] `target` features.
+    pub(crate) fn loss(&self, view: &FeatureView<B>, target: Tensor<B, 3>) -> Tensor<B, 1> {
+        let [h, w, channels] = target.dims();
+        let dim = self.dim();
+        assert_eq!(
+            channels, dim,
+            "Feature maps have {channels} channels, but splats are trained with {dim} features"
+        );
+        let latents = sigmoid(self.features.latents.val());
+        let [n, latent_dim] = latents.dims();
+
+        let rendered: Vec<_> = (0..latent_dim)
+            .step_by(3)
+            .map(|start| {
+                let values = latents.clone().slice(s![.., start..start + 3]);
+                let sh_coeffs = ((values - 0.5) / SH_C0).reshape([n, 1, 3]);
+                // Colors are premultiplied by alpha, so uncovered pixels have zero latents.
+                render_splats(
+                    view.camera,
+                    glam::uvec2(w as u32, h as u32),
+                    view.means.clone().detach(),
+                    view.log_scales.clone().detach(),
+                    view.quats.clone().detach(),
+                    sh_coeffs,
+                    view.opacities.clone().detach(),
+                )
+                .image
+                .slice(s![.., .., 0..3])
+            })
+            .collect();
+        let rendered = Tensor::cat(rendered, 2).reshape([h * w, latent_dim]);
+        let features = self.features.decode(rendered).reshape([h, w, dim]);
+        (features - target).abs().mean()
+    }
+
+    /// Number of gradient values of the features that overflowed.
+    pub(crate) fn overflows(&self, grads: &B::Gradients) -> Tensor<B::InnerBackend, 1, Int> {
+        let SplatFeatures {
+            latents,
+            decoder,
+            bias,
+        } = &self.features;
+        grad_overflows(&latents.val(), grads)
+            + grad_overflows(&decoder.val(), grads)
+            + grad_overflows(&bias.val(), grads)
+    }
+
+    pub(crate) fn step(&mut self, lr: f64, grads: &mut B::Gradients) {
+        let SplatFeatures {
+            latents,
+            decoder,
+            bias,
+        } = &self.features;
+        let ids = [latents.id, decoder.id, bias.id];
+        let grad = GradientsParams::from_params(grads, &self.features, &ids);
+        let features = self.features.clone();
+        self.features = self.optim.step(lr, features, grad);
+    }
+
+    // Map the latents of the splats, and their optimizer state, along with the splats.
+    fn map(
+        &mut self,
+        map_latents: impl FnOnce(Tensor<B::InnerBackend, 2>) -> Tensor<B::InnerBackend, 2>,
+        map_state: impl Fn(Tensor<B::InnerBackend, 2>) -> Tensor<B::InnerBackend, 2>,
+    ) {
+        let id = self.features.latents.id;
+        let mut record = self.optim.to_record();
+        // Steps without feature maps don't create any state.
+        if record.contains_key(&id) {
+            map_opt(id, &mut record, &map_state);
+        }
+        self.optim = create_optimizer().load_record(record);
+        self.features.latents = self
+            .features
+            .latents
+            .clone()
+            .map(|f| Tensor::from_inner(map_latents(f.inner())).require_grad());
+    }
+
+    /// Keep the features of the splats at `indices`.
+    pub(crate) fn retain(&mut self, indices: Tensor<B::InnerBackend, 1, Int>) {
+        self.map(
+            |x| x.select(0, indices.clone()),
+            |x| x.select(0, indices.clone()),
+        );
+    }
+
+    /// Add splats with the features of the splats at `indices`. Their optimizer state starts at
+    /// zero, like that of the rest of the new splats.
+    pub(crate) fn grow(&mut self, indices: Tensor<B::InnerBackend, 1, Int>) {
+        let copied = self.features.latents.val().inner().select(0, indices);
+        let [count, latent_dim] = copied.dims();
+        self.map(
+            |x| Tensor::cat(vec![x, copied], 0),
+            |x| {
+                let device = x.device();
+                Tensor::cat(vec![x, Tensor::zeros([count, latent_dim], &device)], 0)
+            },
+        );
+    }
+
+    /// The learned [N, C] features of the splats.
+    pub(crate) fn features(&self) -> Tensor<B::InnerBackend, 2> {
+        let features = self.features.valid();
+        features.decode(sigmoid(features.latents.val()))
+    }
+}
+
+/// Cosine similarity of the [N, C] `features` of splats to the [C] `query`, eg. the CLIP
+/// embedding of a text prompt, or the features of a picked splat.
+pub fn feature_similarity<B: Backend>(features: Tensor<B, 2>, query: Tensor<B, 1>) -> Tensor<B, 1> {
+    let query = query.clone() / query.powi_scalar(2).sum().sqrt().clamp_min(1e-12);
+    let norms = features
+        .clone()
+        .powi_scalar(2)
+        .sum_dim(1)
+        .sqrt()
+        .clamp_min(1e-12);
+    (features / norms).matmul(query.unsqueeze_dim(1)).squeeze(1)
+}
+
+/// The splats whose features have a cosine similarity of at least `threshold` to `query`, to
+/// segment the scene by what the features mean.
+pub fn segment_by_features<B: Backend>(
+    features: Tensor<B, 2>,
+    query: Tensor<B, 1>,
+    threshold: f32,
+) -> Tensor<B, 1, Bool> {
+    feature_similarity(features, query).greater_equal_elem(threshold)
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::*;
+    use brush_render::MainBackend;
+    use burn::{
+        backend::{Autodiff, wgpu::WgpuDevice},
+        tensor::ElementConversion,
+    };
+
+    type Diff = Autodiff<MainBackend>;
+
+    fn set_latents(trainer: &mut FeatureTrainer<Diff>, latents: &[f32], device: &WgpuDevice) {
+        let latent_dim = trainer.features.latents.dims()[1];
+        let latents = Tensor::<Diff, 1>::from_floats(latents, device)
+            .reshape([latents.len() / latent_dim, latent_dim]);
+        trainer.features.latents = Param::from_tensor(latents);
+    }
+
+    fn rows(features: Tensor<MainBackend, 2>) -> Vec<Vec<f32>> {
+        let dim = features.dims()[1];
+        let values = features.into_data().into_vec::<f32>().expect("Wrong type");
+        values.chunks_exact(dim).map(<[f32]>::to_vec).collect()
+    }
+
+    fn assert_rows_eq(rows: &[Vec<f32>], expected: &[&Vec<f32>]) {
+        assert_eq!(rows.len(), expected.len());
+        for (row, expected) in rows.iter().zip(expected) {
+            for (a, b) in row.iter().zip(expected.iter()) {
+                assert!((a - b).abs() < 1e-5, "{row:?} != {expected:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn grow_and_retain_follow_splats() {
+        let device = WgpuDevice::DefaultDevice;
+        let mut trainer = FeatureTrainer::<Diff>::new(4, 5, 3, &device);
+        let latents: Vec<f32> = (0..12).map(|i| i as f32 * 0.3 - 1.5).collect();
+        set_latents(&mut trainer, &latents, &device);
+        let before = rows(trainer.features());
+
+        trainer.retain(Tensor::from_ints([2, 0], &device));
+        assert_rows_eq(&rows(trainer.features()), &[&before[2], &before[0]]);
+
+        trainer.grow(Tensor::from_ints([1], &device));
+        let after = rows(trainer.features());
+        assert_rows_eq(&after, &[&before[2], &before[0], &before[0]]);
+    }
+
+    #[test]
+    fn learns_features_out_of_color_range() {
+        let device = WgpuDevice::DefaultDevice;
+        let camera = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            0.8,
+            0.8,
+            glam::vec2(0.5, 0.5),
+        );
+        // A single big opaque splat covering the view.
+        let view = FeatureView {
+            camera: &camera,
+            means: Tensor::<Diff, 1>::from_floats([0.0, 0.0, 2.0], &device).reshape([1, 3]),
+            log_scales: Tensor::ones([1, 3], &device) * 0.5,
+            quats: Tensor::<Diff, 1>::from_floats([1.0, 0.0, 0.0, 0.0], &device).reshape([1, 4]),
+            opacities: Tensor::ones([1], &device),
+        };
+        // Features well outside of the range of colors, as SAM and CLIP features are.
+        let target = Tensor::<Diff, 1>::from_floats([-3.0, 5.0, 0.2, -0.7], &device)
+            .reshape([1, 1, 4])
+            .repeat_dim(0, 16)
+            .repeat_dim(1, 16);
+
+        let mut trainer = FeatureTrainer::<Diff>::new(1, 4, 3, &device);
+        let loss_value = |loss: Tensor<Diff, 1>| loss.into_scalar().elem::<f32>();
+        let initial = loss_value(trainer.loss(&view, target.clone()));
+        for _ in 0..300 {
+            let loss = trainer.loss(&view, target.clone());
+            let mut grads = loss.backward();
+            trainer.step(0.05, &mut grads);
+        }
+        let trained = loss_value(trainer.loss(&view, target));
+        assert!(trained < initial * 0.25, "{initial} -> {trained}");
+    }
+
+    #[test]
+    fn segments_by_similarity() {
+        let device = WgpuDevice::DefaultDevice;
+        let features = Tensor::<MainBackend, 1>::from_floats(
+            [1.0, 0.0, 0.0, 2.0, 0.1, 0.0, 0.0, 1.0, 0.0, -1.0, 0.0, 0.0],
+            &device,
+        )
+        .reshape([4, 3]);
+        let query = Tensor::<MainBackend, 1>::from_floats([3.0, 0.0, 0.0], &device);
+        let segment = segment_by_features(features, query, 0.9)
+            .int()
+            .into_data()
+            .into_vec::<i32>()
+            .expect("Wrong type");
+        assert_eq!(segment, [1, 1, 0, 0]);
+    }
+}
@@ -0,0 +1,264 @@
+// Flat splats, squashed to disks, with the depth distortion and normal consistency losses of 2D
+// Gaussian Splatting (Huang et al. 2024) that pull them onto the surfaces of a scene.
+//
+// Unlike 2DGS, the disks aren't rasterized by intersecting rays with them. They are drawn by the
+// regular rasterizer as 3D Gaussians with a tiny third axis, so each splat has a single depth, that
+// of its center, rather than one per pixel. The losses are computed from blends of that depth.
+use brush_render::{camera::Camera, shaders::project_visible::SH_C0};
+use brush_render_bwd::{burn_glue::SplatForwardDiff, diff_render::render_splats};
+use burn::{
+    prelude::Backend,
+    tensor::{Int, Tensor, s},
+};
+use glam::Mat3;
+
+use crate::quat_vec::quaternion_vec_multiply;
+
+// Thickness of a flat splat relative to its smaller axis.
+const FLATNESS: f32 = 1e-3;
+
+/// Flatten splats to disks, by setting their third axis to a sliver of the other two.
+pub(crate) fn flatten_scales<B: Backend>(log_scales: Tensor<B, 2>) -> Tensor<B, 2> {
+    let disk = log_scales.slice(s![.., 0..2]);
+    let thickness = disk.clone().min_dim(1) + FLATNESS.ln();
+    Tensor::cat(vec![disk, thickness], 1)
+}
+
+// Normalize vectors along the last dimension.
+fn normalize<B: Backend, const D: usize>(v: Tensor<B, D>) -> Tensor<B, D> {
+    let norm = v.clone().powi_scalar(2).sum_dim(D - 1).sqrt();
+    v / norm.clamp_min(1e-8)
+}
+
+fn cross<B: Backend>(a: Tensor<B, 3>, b: Tensor<B, 3>) -> Tensor<B, 3> {
+    let c = |t: &Tensor<B, 3>, i: usize| t.clone().slice(s![.., .., i..i + 1]);
+    let [ax, ay, az] = [0, 1, 2].map(|i| c(&a, i));
+    let [bx, by, bz] = [0, 1, 2].map(|i| c(&b, i));
+    Tensor::cat(
+        vec![
+            ay.clone() * bz.clone() - az.clone() * by.clone(),
+            az * bx.clone() - ax.clone() * bz,
+            ax * by - ay * bx,
+        ],
+        2,
+    )
+}
+
+/// The losses of a render of flat splats.
+pub(crate) struct FlatSplatLosses<B: Backend> {
+    /// How spread out the splats blended into a pixel are in depth.
+    pub(crate) distortion: Tensor<B, 1>,
+    /// How far the normals of the disks are from the normals of the rendered depth.
+    pub(crate) normal: Tensor<B, 1>,
+}
+
+/// Flat splats as rendered into a view.
+pub(crate) struct FlatSplatView<'a, B: Backend> {
+    pub(crate) camera: &'a Camera,
+    pub(crate) img_size: glam::UVec2,
+    pub(crate) means: Tensor<B, 2>,
+    pub(crate) log_scales: Tensor<B, 2>,
+    pub(crate) quats: Tensor<B, 2>,
+    pub(crate) opacities: Tensor<B, 1>,
+}
+
+impl<B: Backend + SplatForwardDiff<B>> FlatSplatView<'_, B> {
+    // Render 3 values of each splat, as the base color of the splat. Values can't be negative, as
+    // colors are clamped at zero. Returns the blended values premultiplied by alpha, and the alpha.
+    fn render_values(&self, values: Tensor<B, 2>) -> (Tensor<B, 3>, Tensor<B, 3>) {
+        let n = values.dims()[0];
+        let sh_coeffs = ((values - 0.5) / SH_C0).reshape([n, 1, 3]);
+        let img = render_splats(
+            self.camera,
+            self.img_size,
+            self.means.clone(),
+            self.log_scales.clone(),
+            self.quats.clone(),
+            sh_coeffs,
+            self.opacities.clone(),
+        )
+        .image;
+        (
+            img.clone().slice(s![.., .., 0..3]),
+            img.slice(s![.., .., 3..4]),
+        )
+    }
+
+    /// Render the losses. Depths are divided by `depth_scale`, so the distortion is about the same
+    /// for scenes of any size.
+    pub(crate) fn losses(
+        &self,
+        depth_scale: f32,
+        with_distortion: bool,
+        with_normal: bool,
+    ) -> FlatSplatLosses<B> {
+        let device = self.means.device();
+        let n = self.means.dims()[0];
+
+        let world_to_local = self.camera.world_to_local();
+        // Points are row vectors, so multiply by the transposed rotation.
+        let rot = Mat3::from(world_to_local.matrix3).to_cols_array();
+        let rot = Tensor::<B, 1>::from_floats(rot, &device).reshape([3, 3]);
+        let translation =
+            Tensor::<B, 1>::from_floats(world_to_local.translation.to_array(), &device)
+                .reshape([1, 3]);
+        let local = self.means.clone().matmul(rot.clone()) + translation;
+        let depth = local.clone().slice(s![.., 2..3]) / depth_scale;
+
+        // Sums of the blend weights times the depth, and the depth squared.
+        let (moments, alpha) = self.render_values(Tensor::cat(
+            vec![
+                depth.clone(),
+                depth.clone().powi_scalar(2),
+                Tensor::zeros([n, 1], &device),
+            ],
+            1,
+        ));
+        let weighted_depth = moments.clone().slice(s![.., .., 0..1]);
+        let weighted_depth_sq = moments.slice(s![.., .., 1..2]);
+
+        let distortion = if with_distortion {
+            // Sum over pairs of splats i, j of w_i w_j (d_i - d_j)^2, halved. This is the squared
+            // version of the 2DGS distortion, which the blended moments give without a custom
+            // rasterizer.
+            (alpha.clone() * weighted_depth_sq - weighted_depth.clone().powi_scalar(2))
+                .clamp_min(0.0)
+                .mean()
+        } else {
+            Tensor::zeros([1], &device)
+        };
+
+        let normal = if with_normal {
+            let [h, w, _] = alpha.dims();
+            // Normals of the disks, facing the camera.
+            let one_z = Tensor::<B, 1>::from_floats([0.0, 0.0, 1.0], &device)
+                .reshape([1, 3])
+                .repeat_dim(0, n);
+            let normals = quaternion_vec_multiply(self.quats.clone(), one_z).matmul(rot);
+            let facing_away = (normals.clone() * local)
+                .sum_dim(1)
+                .greater_elem(0.0)
+                .float();
+            let normals = normals * (facing_away * -2.0 + 1.0);
+            // Blended normals, as sum w (n / 2 + 1 / 2) = sum w n / 2 + alpha / 2.
+            let (colors, _) = self.render_values(normals * 0.5 + 0.5);
+            let rendered = normalize(colors * 2.0 - alpha.clone());
+
+            // Normals of the surface through the rendered depth.
+            let focal = self.camera.focal(self.img_size);
+            let center = self.camera.center(self.img_size);
+            let pixels = |len: usize, focal: f32, center: f32| {
+                (Tensor::<B, 1, Int>::arange(0..len as i64, &device).float() + 0.5 - center) / focal
+            };
+            let x = pixels(w, focal.x, center.x)
+                .reshape([1, w, 1])
+                .repeat_dim(0, h);
+            let y = pixels(h, focal.y, center.y)
+                .reshape([h, 1, 1])
+                .repeat_dim(1, w);
+            let z = weighted_depth / alpha.clone().clamp_min(1e-6);
+            let points = Tensor::cat(vec![x * z.clone(), y * z.clone(), z], 2);
+            let dx = points.clone().slice(s![1..h - 1, 2..w, ..])
+                - points.clone().slice(s![1..h - 1, 0..w - 2, ..]);
+            let dy = points.clone().slice(s![2..h, 1..w - 1, ..])
+                - points.slice(s![0..h - 2, 1..w - 1, ..]);
+            // Camera y points down, so this faces the camera.
+            let from_depth = normalize(cross(dy, dx));
+
+            let rendered = rendered.slice(s![1..h - 1, 1..w - 1, ..]);
+            let alpha = alpha.slice(s![1..h - 1, 1..w - 1, ..]);
+            let cos = (rendered * from_depth).sum_dim(2);
+            (alpha * (-cos + 1.0)).mean()
+        } else {
+            Tensor::zeros([1], &device)
+        };
+
+        FlatSplatLosses { distortion, normal }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brush_render::MainBackend;
+    use burn::backend::{Autodiff, Wgpu, wgpu::WgpuDevice};
+    use glam::{Quat, Vec3};
+
+    type Diff = Autodiff<MainBackend>;
+
+    // Disks of the given radius, in front of a camera at the origin with a focal length of 32
+    // pixels.
+    fn losses(disks: &[(Vec3, f32, Quat)]) -> (f32, f32) {
+        let device = WgpuDevice::DefaultDevice;
+        let fov = std::f64::consts::FRAC_PI_2;
+        let camera = Camera::new(Vec3::ZERO, Quat::IDENTITY, fov, fov, glam::vec2(0.5, 0.5));
+        let n = disks.len();
+        let floats = |values: Vec<f32>, width: usize| {
+            Tensor::<Diff, 1>::from_floats(values.as_slice(), &device).reshape([n, width])
+        };
+        let means = floats(disks.iter().flat_map(|d| d.0.to_array()).collect(), 3);
+        let log_scales = floats(disks.iter().flat_map(|d| [d.1.ln(); 3]).collect(), 3);
+        // Quaternions are stored as wxyz.
+        let quats = floats(
+            disks
+                .iter()
+                .flat_map(|d| [d.2.w, d.2.x, d.2.y, d.2.z])
+                .collect(),
+            4,
+        );
+        let view = FlatSplatView {
+            camera: &camera,
+            img_size: glam::uvec2(64, 64),
+            means,
+            log_scales: flatten_scales(log_scales),
+            quats,
+            opacities: Tensor::ones([n], &device) * 0.5,
+        };
+        let losses = view.losses(1.0, true, true);
+        let value = |t: Tensor<Diff, 1>| t.into_data().to_vec::<f32>().expect("Wrong type")[0];
+        (value(losses.distortion), value(losses.normal))
+    }
+
+    #[test]
+    fn distortion_grows_with_depth_spread() {
+        // Disks covering the same pixels, at different depths.
+        let stacked = |far: f32| {
+            losses(&[
+                (Vec3::new(0.0, 0.0, 2.0), 0.3, Quat::IDENTITY),
+                (Vec3::new(0.0, 0.0, far), 0.15 * far, Quat::IDENTITY),
+            ])
+            .0
+        };
+        let (same, near, far) = (stacked(2.0), stacked(3.0), stacked(5.0));
+        assert!(same.abs() < 1e-6, "{same}");
+        assert!(near > 0.0 && far > near, "{near} {far}");
+        // A lone disk has nothing to be spread from.
+        let (lone, _) = losses(&[(Vec3::new(0.0, 0.0, 3.0), 0.3, Quat::IDENTITY)]);
+        assert!(lone.abs() < 1e-6, "{lone}");
+    }
+
+    #[test]
+    fn normal_loss_penalizes_tilted_disks() {
+        let disk = |rotation: Quat| losses(&[(Vec3::new(0.0, 0.0, 2.0), 0.3, rotation)]).1;
+        // The depth of a single disk is flat, so only disks facing the camera match it.
+        let facing = disk(Quat::IDENTITY);
+        let tilted = disk(Quat::from_rotation_x(std::f32::consts::FRAC_PI_3));
+        // Disks facing away are flipped to face the camera.
+        let flipped = disk(Quat::from_rotation_x(std::f32::consts::PI));
+        assert!(tilted > 0.0, "{tilted}");
+        assert!(facing < tilted * 0.25, "{facing} {tilted}");
+        assert!((flipped - facing).abs() < 1e-4, "{flipped} {facing}");
+    }
+
+    #[test]
+    fn flattened_disks_keep_their_size() {
+        let device = WgpuDevice::DefaultDevice;
+        let log_scales = Tensor::<Wgpu, 2>::from_floats([[-1.0, -2.0, 0.5]], &device);
+        let flat = flatten_scales(log_scales)
+            .into_data()
+            .into_vec::<f32>()
+            .expect("Wrong type");
+        assert_eq!(&flat[0..2], &[-1.0, -2.0]);
+        assert!((flat[2] - (-2.0 + FLATNESS.ln())).abs() < 1e-5);
+    }
+}
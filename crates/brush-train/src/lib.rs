@@ -2,11 +2,20 @@
 
 pub mod config;
 pub mod eval;
+pub mod features;
 pub mod msg;
+pub mod simplify;
 pub mod train;
+pub mod view_sampler;
 
 mod adam_scaled;
+mod depth;
+mod flat_splats;
+mod loss_scale;
+mod motion;
 mod multinomial;
 mod quat_vec;
+mod robust;
 mod ssim;
 mod stats;
+mod wb_correction;
@@ -0,0 +1,114 @@
+use burn::{
+    backend::wgpu::{WgpuDevice, WgpuRuntime},
+    prelude::Backend,
+    tensor::{Int, Tensor, backend::AutodiffBackend},
+};
+use burn_cubecl::cubecl::{
+    Feature, Runtime,
+    ir::{Elem, FloatKind},
+};
+
+// Largest scale to grow to. Beyond this, even small gradients overflow f16.
+const MAX_SCALE: f32 = 65536.0;
+// Grow the scale after this many steps without an overflow.
+const GROW_AFTER: u32 = 2000;
+
+/// Whether the device can run f16 kernels.
+pub(crate) fn supports_f16(device: &WgpuDevice) -> bool {
+    WgpuRuntime::client(device)
+        .properties()
+        .feature_enabled(Feature::Type(Elem::Float(FloatKind::F16)))
+}
+
+/// Dynamic loss scale for half precision training.
+///
+/// Small gradients underflow in f16, so the loss is scaled up before the backward pass. When
+/// gradients overflow the step is skipped and the scale is halved, and after a while without
+/// overflows it's doubled again.
+pub(crate) struct LossScaler {
+    scale: f32,
+    good_steps: u32,
+}
+
+impl LossScaler {
+    pub(crate) fn new(scale: f32) -> Self {
+        Self {
+            scale: scale.clamp(1.0, MAX_SCALE),
+            good_steps: 0,
+        }
+    }
+
+    pub(crate) fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub(crate) fn update(&mut self, overflowed: bool) {
+        if overflowed {
+            self.scale = (self.scale / 2.0).max(1.0);
+            self.good_steps = 0;
+        } else {
+            self.good_steps += 1;
+            if self.good_steps >= GROW_AFTER {
+                self.scale = (self.scale * 2.0).min(MAX_SCALE);
+                self.good_steps = 0;
+            }
+        }
+    }
+}
+
+/// Number of values of `tensor` that aren't finite.
+pub(crate) fn count_non_finite<B: Backend, const D: usize>(
+    tensor: Tensor<B, D>,
+) -> Tensor<B, 1, Int> {
+    tensor.is_finite().bool_not().int().sum()
+}
+
+/// Number of values of the gradient of `param` in `grads` that aren't finite. A parameter without
+/// a gradient has none.
+pub(crate) fn grad_overflows<B: AutodiffBackend, const D: usize>(
+    param: &Tensor<B, D>,
+    grads: &B::Gradients,
+) -> Tensor<B::InnerBackend, 1, Int> {
+    match param.grad(grads) {
+        Some(grad) => count_non_finite(grad),
+        None => Tensor::zeros([1], &param.device()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_backs_off_and_grows() {
+        let mut scaler = LossScaler::new(1024.0);
+        scaler.update(true);
+        assert_eq!(scaler.scale(), 512.0);
+
+        for _ in 0..GROW_AFTER - 1 {
+            scaler.update(false);
+        }
+        assert_eq!(scaler.scale(), 512.0);
+        scaler.update(false);
+        assert_eq!(scaler.scale(), 1024.0);
+
+        // The scale stays within bounds.
+        for _ in 0..20 {
+            scaler.update(true);
+        }
+        assert_eq!(scaler.scale(), 1.0);
+        assert_eq!(LossScaler::new(1e9).scale(), MAX_SCALE);
+    }
+
+    #[test]
+    fn counts_non_finite_values() {
+        type B = brush_render::MainBackend;
+
+        let device = WgpuDevice::DefaultDevice;
+        let values =
+            Tensor::<B, 1>::from_floats([1.0, f32::NAN, f32::INFINITY, -2.0, 0.0], &device);
+        assert_eq!(count_non_finite(values).into_scalar(), 2);
+        let finite = Tensor::<B, 2>::ones([3, 4], &device);
+        assert_eq!(count_non_finite(finite).into_scalar(), 0);
+    }
+}
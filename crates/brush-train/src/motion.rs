@@ -0,0 +1,268 @@
+// Motion of the splats over time, to train dynamic scenes from videos of several views. Each
+// splat learns an offset of its mean, rotation and opacity at a number of keyframes spread over
+// the video, and moves linearly between them. The splats themselves are the scene at rest, which
+// every frame deforms.
+//
+// The offsets are a plain tensor of deltas per keyframe, rather than a deformation network, so
+// they grow and prune along with the splats.
+use brush_render::motion::{SplatMotion, interpolate_keyframes};
+use burn::{
+    module::{Module, Param},
+    optim::{GradientsParams, Optimizer, adaptor::OptimizerAdaptor},
+    prelude::Backend,
+    tensor::{Int, Tensor, backend::AutodiffBackend, s},
+};
+
+use crate::{
+    adam_scaled::{AdamScaled, AdamScaledConfig},
+    loss_scale::grad_overflows,
+    train::map_opt,
+};
+
+// Offsets of the mean, the rotation and the raw opacity, per keyframe.
+const MOTION_DIM: usize = 8;
+
+#[derive(Module, Debug)]
+pub(crate) struct MotionOffsets<B: Backend> {
+    // [N, keyframes, 8] offsets. Offsets of means are in units of the scene extent, so one
+    // learning rate fits any scene.
+    deltas: Param<Tensor<B, 3>>,
+}
+
+/// Offsets of the splats at a point in time.
+pub(crate) struct MotionDeltas<B: Backend> {
+    pub(crate) means: Tensor<B, 2>,
+    pub(crate) rotation: Tensor<B, 2>,
+    pub(crate) raw_opacity: Tensor<B, 1>,
+}
+
+pub(crate) struct MotionTrainer<B: AutodiffBackend> {
+    offsets: MotionOffsets<B>,
+    optim: OptimizerAdaptor<AdamScaled, MotionOffsets<B>, B>,
+    scene_extent: f32,
+}
+
+fn create_optimizer<B: AutodiffBackend>() -> OptimizerAdaptor<AdamScaled, MotionOffsets<B>, B> {
+    AdamScaledConfig::new().with_epsilon(1e-15).init()
+}
+
+fn split<B: Backend>(deltas: Tensor<B, 2>, scene_extent: f32) -> MotionDeltas<B> {
+    let n = deltas.dims()[0];
+    MotionDeltas {
+        means: deltas.clone().slice(s![.., 0..3]) * scene_extent,
+        rotation: deltas.clone().slice(s![.., 3..7]),
+        raw_opacity: deltas.slice(s![.., 7..8]).reshape([n]),
+    }
+}
+
+impl<B: AutodiffBackend> MotionTrainer<B> {
+    pub(crate) fn new(
+        num_splats: u32,
+        keyframes: u32,
+        scene_extent: f32,
+        device: &B::Device,
+    ) -> Self {
+        let shape = [num_splats as usize, keyframes.max(1) as usize, MOTION_DIM];
+        Self {
+            offsets: MotionOffsets {
+                deltas: Param::from_tensor(Tensor::zeros(shape, device)),
+            },
+            optim: create_optimizer(),
+            scene_extent,
+        }
+    }
+
+    /// Offsets of the splats at `time`, to train with.
+    pub(crate) fn deltas(&self, time: f32) -> MotionDeltas<B> {
+        split(
+            interpolate_keyframes(self.offsets.deltas.val(), time),
+            self.scene_extent,
+        )
+    }
+
+    /// The motion of the splats, to render them at any time with.
+    pub(crate) fn motion(&self) -> SplatMotion<B::InnerBackend> {
+        let deltas = self.offsets.deltas.val().inner();
+        let e = self.scene_extent;
+        let scale = [e, e, e, 1.0, 1.0, 1.0, 1.0, 1.0];
+        let scale =
+            Tensor::<_, 1>::from_floats(scale, &deltas.device()).reshape([1, 1, MOTION_DIM]);
+        SplatMotion {
+            keyframes: deltas * scale,
+        }
+    }
+
+    /// The highest raw opacity of the splats over all keyframes, given their `raw_opacity` at rest.
+    pub(crate) fn peak_raw_opacity(
+        &self,
+        raw_opacity: Tensor<B::InnerBackend, 1>,
+    ) -> Tensor<B::InnerBackend, 1> {
+        let deltas = self.offsets.deltas.val().inner();
+        let n = deltas.dims()[0];
+        let peak = deltas.slice(s![.., .., 7..8]).max_dim(1).reshape([n]);
+        raw_opacity + peak
+    }
+
+    /// Mean squared change of the offsets from one keyframe to the next, which keeps splats from
+    /// jumping around between frames that are far apart.
+    pub(crate) fn smoothness_loss(&self) -> Tensor<B, 1> {
+        let deltas = self.offsets.deltas.val();
+        let count = deltas.dims()[1];
+        if count < 2 {
+            return Tensor::zeros([1], &deltas.device());
+        }
+        let change =
+            deltas.clone().slice(s![.., 1..count, ..]) - deltas.slice(s![.., 0..count - 1, ..]);
+        change.powi_scalar(2).mean()
+    }
+
+    /// Mean absolute offset, which keeps splats still unless moving them helps.
+    pub(crate) fn sparsity_loss(&self) -> Tensor<B, 1> {
+        self.offsets.deltas.val().abs().mean()
+    }
+
+    /// Number of gradient values of the offsets that overflowed.
+    pub(crate) fn overflows(&self, grads: &B::Gradients) -> Tensor<B::InnerBackend, 1, Int> {
+        grad_overflows(&self.offsets.deltas.val(), grads)
+    }
+
+    pub(crate) fn step(&mut self, lr: f64, grads: &mut B::Gradients) {
+        let id = self.offsets.deltas.id;
+        let grad = GradientsParams::from_params(grads, &self.offsets, &[id]);
+        let offsets = self.offsets.clone();
+        self.offsets = self.optim.step(lr, offsets, grad);
+    }
+
+    // Map the offsets, and their optimizer state, along with the splats.
+    fn map(
+        &mut self,
+        map_deltas: impl FnOnce(Tensor<B::InnerBackend, 3>) -> Tensor<B::InnerBackend, 3>,
+        map_state: impl Fn(Tensor<B::InnerBackend, 3>) -> Tensor<B::InnerBackend, 3>,
+    ) {
+        let id = self.offsets.deltas.id;
+        let mut record = self.optim.to_record();
+        // Steps without timestamped views don't create any state.
+        if record.contains_key(&id) {
+            map_opt(id, &mut record, &map_state);
+        }
+        self.optim = create_optimizer().load_record(record);
+        self.offsets.deltas = self
+            .offsets
+            .deltas
+            .clone()
+            .map(|d| Tensor::from_inner(map_deltas(d.inner())).require_grad());
+    }
+
+    /// Keep the offsets of the splats at `indices`.
+    pub(crate) fn retain(&mut self, indices: Tensor<B::InnerBackend, 1, Int>) {
+        self.map(
+            |x| x.select(0, indices.clone()),
+            |x| x.select(0, indices.clone()),
+        );
+    }
+
+    /// Add splats which move like the splats at `indices`. Their optimizer state starts at zero,
+    /// like that of the rest of the new splats.
+    pub(crate) fn grow(&mut self, indices: Tensor<B::InnerBackend, 1, Int>) {
+        let copied = self.offsets.deltas.val().inner().select(0, indices);
+        let [count, keyframes, dim] = copied.dims();
+        self.map(
+            |x| Tensor::cat(vec![x, copied], 0),
+            |x| {
+                let device = x.device();
+                Tensor::cat(vec![x, Tensor::zeros([count, keyframes, dim], &device)], 0)
+            },
+        );
+    }
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::*;
+    use brush_render::MainBackend;
+    use burn::{
+        backend::{Autodiff, wgpu::WgpuDevice},
+        tensor::TensorData,
+    };
+
+    type Diff = Autodiff<MainBackend>;
+
+    const SPLATS: usize = 4;
+    const KEYFRAMES: usize = 2;
+
+    // A trainer where all offsets of a splat are its index, which took a step so the optimizer has
+    // state. The step has no learning rate, so the offsets stay as they are.
+    fn trainer(device: &WgpuDevice) -> MotionTrainer<Diff> {
+        let mut trainer = MotionTrainer::new(SPLATS as u32, KEYFRAMES as u32, 1.0, device);
+        let values: Vec<f32> = (0..SPLATS * KEYFRAMES * MOTION_DIM)
+            .map(|i| (i / (KEYFRAMES * MOTION_DIM)) as f32)
+            .collect();
+        let shape = [SPLATS, KEYFRAMES, MOTION_DIM];
+        trainer.offsets.deltas =
+            Param::from_tensor(Tensor::from_data(TensorData::new(values, shape), device));
+        take_step(&mut trainer);
+        trainer
+    }
+
+    fn take_step(trainer: &mut MotionTrainer<Diff>) {
+        let loss = trainer.sparsity_loss() + trainer.smoothness_loss();
+        let mut grads = loss.backward();
+        trainer.step(0.0, &mut grads);
+    }
+
+    // The splat each row of offsets belongs to, if all its offsets agree.
+    fn rows(trainer: &MotionTrainer<Diff>) -> Vec<Option<f32>> {
+        let values = trainer
+            .offsets
+            .deltas
+            .val()
+            .inner()
+            .into_data()
+            .into_vec::<f32>()
+            .expect("Wrong type");
+        values
+            .chunks(KEYFRAMES * MOTION_DIM)
+            .map(|row| row.iter().all(|&v| v == row[0]).then_some(row[0]))
+            .collect()
+    }
+
+    fn indices(values: &[i32], device: &WgpuDevice) -> Tensor<MainBackend, 1, Int> {
+        Tensor::from_ints(values, device)
+    }
+
+    #[test]
+    fn grown_splats_copy_the_motion_of_their_source() {
+        let device = WgpuDevice::DefaultDevice;
+        let mut trainer = trainer(&device);
+        trainer.grow(indices(&[2, 0], &device));
+        let expected = [0.0, 1.0, 2.0, 3.0, 2.0, 0.0].map(Some);
+        assert_eq!(rows(&trainer), expected);
+        // The optimizer state grew along with the offsets.
+        take_step(&mut trainer);
+        assert_eq!(rows(&trainer), expected);
+    }
+
+    #[test]
+    fn retained_splats_keep_their_motion() {
+        let device = WgpuDevice::DefaultDevice;
+        let mut trainer = trainer(&device);
+        trainer.retain(indices(&[3, 1], &device));
+        assert_eq!(rows(&trainer), [Some(3.0), Some(1.0)]);
+        take_step(&mut trainer);
+        assert_eq!(rows(&trainer), [Some(3.0), Some(1.0)]);
+
+        // Growing after pruning copies from the kept splats.
+        trainer.grow(indices(&[1], &device));
+        assert_eq!(rows(&trainer), [Some(3.0), Some(1.0), Some(1.0)]);
+    }
+
+    #[test]
+    fn sparsity_loss_is_the_mean_absolute_offset() {
+        let device = WgpuDevice::DefaultDevice;
+        let trainer = trainer(&device);
+        let loss = trainer.sparsity_loss().inner().into_scalar();
+        // The mean of 0, 1, 2 and 3.
+        assert!((loss - 1.5).abs() < 1e-6);
+        assert_eq!(trainer.smoothness_loss().inner().into_scalar(), 0.0);
+    }
+}
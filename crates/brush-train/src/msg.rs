@@ -12,10 +12,16 @@ pub struct RefineStats {
 #[derive(Clone)]
 pub struct TrainStepStats<B: Backend> {
     pub pred_image: Tensor<B, 3>,
+    /// Index of the training view used for this step.
+    pub view_index: usize,
 
     pub num_intersections: Tensor<B, 1, Int>,
     pub num_visible: Tensor<B, 1, Int>,
     pub loss: Tensor<B, 1>,
+    /// Screen space gradient norm of each splat, accumulated since the splats were last refined.
+    pub grad_norm: Option<Tensor<B, 1>>,
+    /// Step each splat was added at, once the splats have been refined.
+    pub added_at: Option<Tensor<B, 1>>,
 
     pub lr_mean: f64,
     pub lr_rotation: f64,
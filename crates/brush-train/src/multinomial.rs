@@ -1,7 +1,8 @@
-pub(crate) fn multinomial_sample(weights: &[f32], n: u32) -> Vec<i32> {
-    let mut rng = rand::rng();
+use rand::Rng;
+
+pub(crate) fn multinomial_sample(weights: &[f32], n: u32, rng: &mut impl Rng) -> Vec<i32> {
     rand::seq::index::sample_weighted(
-        &mut rng,
+        rng,
         weights.len(),
         |i| if weights[i].is_nan() { 0.0 } else { weights[i] },
         n as usize,
@@ -0,0 +1,105 @@
+// Photometric loss weights that leave out transient objects, like people and cars passing through
+// a casual capture, following RobustNeRF (Sabour et al. 2023). Without these, splats try to
+// explain the transients of every view, and leave ghosts of them floating around the scene.
+//
+// Pixels with a residual above a quantile of the residuals of the view are outliers. Transients
+// cover whole regions while detail the splats haven't learned yet is scattered, so a pixel stays
+// an inlier if most of its neighbours are, or if most of the patch it's in is.
+use burn::tensor::{Distribution, Tensor, backend::Backend, module::conv2d, ops::ConvOptions};
+
+// Pixels in the 3x3 neighbourhood of a pixel that need to be inliers for it to be one.
+const NEIGHBOUR_SHARE: f32 = 0.5;
+// Side of the patches pixels are grouped in.
+const PATCH_SIZE: usize = 16;
+// Pixels of a patch that need to be inliers for the whole patch to be.
+const PATCH_SHARE: f32 = 0.6;
+// Residuals the quantile is estimated from. Sorting all pixels every step would be slow.
+const QUANTILE_SAMPLES: usize = 4096;
+
+// Share of inliers in the [H, W] `inliers` over windows of `size`, moving in steps of `stride`.
+fn window_share<B: Backend>(inliers: Tensor<B, 2>, size: usize, stride: usize) -> Tensor<B, 2> {
+    let device = inliers.device();
+    let padding = if stride == 1 { size / 2 } else { 0 };
+    let kernel = Tensor::<B, 4>::ones([1, 1, size, size], &device) / (size * size) as f32;
+    let options = ConvOptions::new([stride, stride], [padding, padding], [1, 1], 1);
+    let share = conv2d(inliers.unsqueeze::<4>(), kernel, None, options);
+    share.squeeze::<3>(0).squeeze::<2>(0)
+}
+
+/// Weights of the pixels in the loss of a view: 1 for inliers, 0 for transients. `residual` is
+/// the [H, W, C] error of each pixel, and pixels above `inlier_quantile` of it can be outliers.
+///
+/// Returns [H, W, 1] weights, without gradients.
+pub(crate) fn inlier_weights<B: Backend>(
+    residual: Tensor<B, 3>,
+    inlier_quantile: f32,
+) -> Tensor<B, 3> {
+    let device = residual.device();
+    let [h, w, _] = residual.dims();
+    let residual = residual.detach().mean_dim(2).reshape([h, w]);
+
+    let samples = (Tensor::<B, 1>::random([QUANTILE_SAMPLES], Distribution::Default, &device)
+        * (h * w) as f32)
+        .int()
+        .clamp(0, (h * w - 1) as i32);
+    let sorted = residual.clone().reshape([h * w]).select(0, samples).sort(0);
+    let index = ((QUANTILE_SAMPLES - 1) as f32 * inlier_quantile.clamp(0.0, 1.0)) as usize;
+    let threshold = sorted.slice([index..index + 1]).reshape([1, 1]);
+    let inliers = (residual - threshold).lower_equal_elem(0.0).float();
+
+    let neighbours = window_share(inliers.clone(), 3, 1)
+        .greater_equal_elem(NEIGHBOUR_SHARE)
+        .float();
+
+    // Patches past the last whole patch are left to the neighbourhood test alone.
+    let (ph, pw) = (h / PATCH_SIZE, w / PATCH_SIZE);
+    let weights = if ph > 0 && pw > 0 {
+        let patches = window_share(inliers, PATCH_SIZE, PATCH_SIZE)
+            .greater_equal_elem(PATCH_SHARE)
+            .float()
+            .reshape([ph, 1, pw, 1])
+            .repeat_dim(1, PATCH_SIZE)
+            .repeat_dim(3, PATCH_SIZE)
+            .reshape([ph * PATCH_SIZE, pw * PATCH_SIZE]);
+        let patches = Tensor::zeros([h, w], &device)
+            .slice_assign([0..ph * PATCH_SIZE, 0..pw * PATCH_SIZE], patches);
+        neighbours.max_pair(patches)
+    } else {
+        neighbours
+    };
+    weights.reshape([h, w, 1])
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::*;
+    use burn::backend::{Wgpu, wgpu::WgpuDevice};
+
+    #[test]
+    fn transients_get_no_weight() {
+        let device = WgpuDevice::DefaultDevice;
+        // A small residual all over, with a square transient and a few detail pixels.
+        let size = 64;
+        let mut residual = vec![0.01; size * size];
+        for y in 16..40 {
+            for x in 20..44 {
+                residual[y * size + x] = 0.8;
+            }
+        }
+        for i in [5, 300, 3000] {
+            residual[i] = 0.5;
+        }
+        let residual =
+            Tensor::<Wgpu, 1>::from_floats(residual.as_slice(), &device).reshape([size, size, 1]);
+        let weights = inlier_weights(residual, 0.8)
+            .into_data()
+            .into_vec::<f32>()
+            .expect("Wrong type");
+        assert_eq!(weights[30 * size + 30], 0.0);
+        assert_eq!(weights[2 * size + 2], 1.0);
+        // Scattered pixels with a high residual are still trained on.
+        for i in [5, 300, 3000] {
+            assert_eq!(weights[i], 1.0);
+        }
+    }
+}
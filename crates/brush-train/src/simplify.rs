@@ -0,0 +1,95 @@
+// Removes the splats that add least to the training views, to fit a trained scene into a splat
+// budget. Splats are scored by how often they show in the views, weighted by their opacity and
+// how many pixels they cover there. Removing splats uncovers others behind them, so splats are
+// removed over a few rounds, scoring the remaining splats again after each.
+use brush_dataset::scene::SceneView;
+use brush_render::{SplatForward, gaussian_splats::Splats};
+use burn::{
+    prelude::Backend,
+    tensor::{Int, Tensor, TensorPrimitive},
+};
+
+const ROUNDS: u32 = 4;
+
+// Splats left after each round, removing an even share of the splats each time.
+fn keep_counts(num_splats: u32, target: u32) -> Vec<u32> {
+    let mut counts = vec![];
+    let mut count = num_splats;
+    for round in 0..ROUNDS {
+        if count <= target {
+            break;
+        }
+        count -= (count - target).div_ceil(ROUNDS - round);
+        counts.push(count);
+    }
+    counts
+}
+
+/// How much each splat adds to the views, roughly the pixels it covers summed over all views it
+/// shows in, times its opacity.
+pub fn contribution<B: Backend + SplatForward<B>>(
+    splats: &Splats<B>,
+    views: &[SceneView],
+) -> Tensor<B, 1> {
+    let device = splats.device();
+    let means = splats.means.val();
+    let mut pixels = Tensor::<B, 1>::zeros([splats.num_splats() as usize], &device);
+    for view in views {
+        let size = view.image.dimensions();
+        let (_, aux) = splats.render(&view.camera, size, true);
+        let visible = Tensor::<B, 1>::from_primitive(TensorPrimitive::Float(aux.visible));
+        let position =
+            Tensor::<B, 1>::from_floats(view.camera.position.to_array(), &device).reshape([1, 3]);
+        let dist_sq = (means.clone() - position)
+            .powi_scalar(2)
+            .sum_dim(1)
+            .squeeze::<1>(1)
+            .clamp_min(1e-6);
+        let focal = view.camera.focal(size);
+        pixels = pixels + visible * (focal.x * focal.y) / dist_sq;
+    }
+    // Importance is the opacity times the largest cross section, which the pixels scale.
+    pixels * splats.importance()
+}
+
+/// Remove the splats adding least to `views` until at most `target` are left.
+///
+/// Returns the remaining splats in their original order, with their indices in `splats`, eg. to
+/// pick out per splat data that goes along with them. Without views, splats are kept by
+/// [`Splats::importance`] alone.
+pub fn simplify<B: Backend + SplatForward<B>>(
+    mut splats: Splats<B>,
+    views: &[SceneView],
+    target: u32,
+) -> (Splats<B>, Tensor<B, 1, Int>) {
+    let device = splats.device();
+    let mut kept = Tensor::<B, 1, Int>::arange(0..i64::from(splats.num_splats()), &device);
+    // Keep at least one splat, empty splats can't be rendered.
+    for keep in keep_counts(splats.num_splats(), target.max(1)) {
+        let scores = if views.is_empty() {
+            splats.importance()
+        } else {
+            contribution(&splats, views)
+        };
+        let order = scores
+            .argsort_descending(0)
+            .slice([0..keep as usize])
+            .sort(0);
+        kept = kept.select(0, order.clone());
+        splats = splats.reorder(order);
+    }
+    (splats, kept)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_reach_target() {
+        assert_eq!(keep_counts(1000, 600), vec![900, 800, 700, 600]);
+        assert_eq!(keep_counts(10, 8), vec![9, 8]);
+        assert_eq!(keep_counts(100, 100), Vec::<u32>::new());
+        assert_eq!(keep_counts(5, 0), vec![3, 2, 1, 0]);
+    }
+}
@@ -1,4 +1,4 @@
-use burn::tensor::{Tensor, backend::Backend, module::conv2d, ops::ConvOptions};
+use burn::tensor::{FloatDType, Tensor, backend::Backend, module::conv2d, ops::ConvOptions};
 
 pub(crate) struct Ssim<B: Backend> {
     weights_1d_v: Tensor<B, 4>,
@@ -23,6 +23,13 @@ impl<B: Backend> Ssim<B> {
         Self { weights_1d_v }
     }
 
+    /// Compute SSIM of f16 images.
+    pub fn with_half_precision(self) -> Self {
+        Self {
+            weights_1d_v: self.weights_1d_v.cast(FloatDType::F16),
+        }
+    }
+
     fn gaussian_blur(&self, img: Tensor<B, 4>) -> Tensor<B, 4> {
         let [channels, _, window_size, _] = self.weights_1d_v.dims();
         let padding = window_size / 2;
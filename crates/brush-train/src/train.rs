@@ -1,20 +1,35 @@
 use crate::{
     adam_scaled::{AdamScaled, AdamScaledConfig, AdamState},
     config::TrainConfig,
+    depth::DepthView,
+    features::{FeatureTrainer, FeatureView},
+    flat_splats::{FlatSplatView, flatten_scales},
+    loss_scale::{LossScaler, grad_overflows, supports_f16},
+    motion::MotionTrainer,
     msg::{RefineStats, TrainStepStats},
     multinomial::multinomial_sample,
     quat_vec::quaternion_vec_multiply,
+    robust::inlier_weights,
     ssim::Ssim,
     stats::RefineRecord,
+    wb_correction::WbTrainer,
 };
 
-use brush_dataset::scene::SceneBatch;
+use brush_dataset::{
+    color::{ColorSpace, srgb_to_linear_tensor},
+    scene::SceneBatch,
+    tonemap::Tonemap,
+};
+use brush_render::random::{NO_ID, random_f32, step_seed};
 use brush_render::sh::sh_coeffs_for_degree;
 use brush_render::{
     MainBackend,
+    camera::{Culling, jitter_offset},
     gaussian_splats::{Splats, inverse_sigmoid},
+    motion::SplatMotion,
+    render_aux::RenderAux,
 };
-use brush_render_bwd::burn_glue::SplatForwardDiff;
+use brush_render_bwd::diff_render::{DiffRenderOutput, render_splats};
 use burn::{
     backend::{
         Autodiff,
@@ -28,13 +43,14 @@ use burn::{
     optim::{GradientsParams, Optimizer, adaptor::OptimizerAdaptor, record::AdaptorRecord},
     prelude::Backend,
     tensor::{
-        Bool, Distribution, Tensor, TensorData, TensorPrimitive, activation::sigmoid,
+        Bool, Distribution, FloatDType, Tensor, TensorData, TensorPrimitive, activation::sigmoid,
         backend::AutodiffBackend, s,
     },
 };
 
 use burn_cubecl::cubecl::Runtime;
 use hashbrown::{HashMap, HashSet};
+use rand::{SeedableRng, rngs::StdRng};
 use std::f64::consts::SQRT_2;
 use tracing::trace_span;
 
@@ -43,6 +59,16 @@ const MIN_OPACITY: f32 = 0.9 / 255.0;
 type OptimizerType =
     OptimizerAdaptor<AdamScaled, Splats<Autodiff<MainBackend>>, Autodiff<MainBackend>>;
 
+// A rendered training view, and its loss.
+struct ViewLoss {
+    pred_image: Tensor<Autodiff<MainBackend>, 3>,
+    aux: RenderAux<Autodiff<MainBackend>>,
+    refine_weight_holder: Tensor<Autodiff<MainBackend>, 1>,
+    img_size: glam::UVec2,
+    view_index: usize,
+    loss: Tensor<Autodiff<MainBackend>, 1>,
+}
+
 pub struct SplatTrainer {
     config: TrainConfig,
     sched_mean: ExponentialLrScheduler,
@@ -50,21 +76,115 @@ pub struct SplatTrainer {
     ssim: Ssim<Autodiff<MainBackend>>,
     refine_record: Option<RefineRecord<MainBackend>>,
     optim: Option<OptimizerType>,
+    wb: Option<WbTrainer<Autodiff<MainBackend>>>,
+    /// Features of the splats, when training them, see [`TrainConfig::feature_dim`].
+    features: Option<FeatureTrainer<Autodiff<MainBackend>>>,
+    /// Motion of the splats, when training a dynamic scene, see [`TrainConfig::motion_keyframes`].
+    motion: Option<MotionTrainer<Autodiff<MainBackend>>>,
+    /// Set when training on HDR images, which are compared to renders after tonemapping both.
+    tonemap: Option<Tonemap>,
+    /// Set when training in half precision.
+    loss_scaler: Option<LossScaler>,
+    /// Picks which splats to grow.
+    rng: StdRng,
+    /// Seeds the random numbers renders draw, see [`step_seed`].
+    seed: u64,
+    /// Sum gradients in a fixed order, and draw all noise from the seed, so runs can be repeated
+    /// exactly.
+    deterministic: bool,
+    /// Factor on all learning rates, set while training.
+    lr_scale: f64,
+    /// Step each splat was added at, zero for the starting splats.
+    added_at: Option<Tensor<MainBackend, 1>>,
 }
 
 fn inv_sigmoid<B: Backend>(x: Tensor<B, 1>) -> Tensor<B, 1> {
     (x.clone() / (1.0f32 - x)).log()
 }
 
+// Fraction of splats to leave out of renders at step `iter`, see `TrainConfig::dropout_rate`.
+fn dropout_rate(config: &TrainConfig, iter: u32) -> f32 {
+    if iter >= config.dropout_end {
+        return 0.0;
+    }
+    let rate = config.dropout_rate * (1.0 - iter as f32 / config.dropout_end as f32);
+    // Leaving out every splat would leave nothing to compensate with.
+    rate.clamp(0.0, 0.95)
+}
+
+// Streams of the random numbers of the render seed that decide which splats are left out, and
+// how means are moved by noise and growth.
+const DROPOUT_STREAM: u32 = 0;
+const MEAN_NOISE_STREAM: u32 = 1;
+const GROWTH_STREAM: u32 = 2;
+
+// Factor on the opacity of each of `num_splats` splats for dropout at `rate`: zero for the splats
+// left out, and enough to make up for them on average for the others. Which splats are left out
+// only depends on the seed of the render, so deterministic runs leave out the same splats.
+fn dropout_factors(seed: u32, num_splats: u32, rate: f32) -> Vec<f32> {
+    (0..num_splats)
+        .map(|id| {
+            if random_f32(seed, id, NO_ID, DROPOUT_STREAM) < rate {
+                0.0
+            } else {
+                1.0 / (1.0 - rate)
+            }
+        })
+        .collect()
+}
+
+// [N, 3] standard normal samples for `num_splats` splats, from the random numbers of `seed` (by a
+// Box-Muller transform), so deterministic runs draw the same samples.
+fn normal_samples(
+    seed: u32,
+    stream: u32,
+    num_splats: usize,
+    device: &WgpuDevice,
+) -> Tensor<MainBackend, 2> {
+    let samples = (0..num_splats as u32)
+        .flat_map(|id| {
+            (0..3).map(move |axis| {
+                let u1 = 1.0 - random_f32(seed, id, 2 * axis, stream);
+                let u2 = random_f32(seed, id, 2 * axis + 1, stream);
+                (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos()
+            })
+        })
+        .collect();
+    Tensor::from_data(TensorData::new(samples, [num_splats, 3]), device)
+}
+
 fn create_default_optimizer() -> OptimizerType {
     AdamScaledConfig::new().with_epsilon(1e-15).init()
 }
 
+// Gradients of a single parameter.
+fn param_grads(
+    grads: &mut <Autodiff<MainBackend> as AutodiffBackend>::Gradients,
+    splats: &Splats<Autodiff<MainBackend>>,
+    id: ParamId,
+) -> GradientsParams {
+    GradientsParams::from_params(grads, splats, &[id])
+}
+
 impl SplatTrainer {
     pub fn new(config: &TrainConfig, device: &WgpuDevice) -> Self {
         const SSIM_WINDOW_SIZE: usize = 11; // Could be configurable but meh, rather keep consistent.
         let ssim = Ssim::new(SSIM_WINDOW_SIZE, 3, device);
 
+        let loss_scaler = if !config.half_precision {
+            None
+        } else if supports_f16(device) {
+            Some(LossScaler::new(config.loss_scale))
+        } else {
+            log::warn!("This GPU doesn't support f16, training in full precision.");
+            None
+        };
+        let ssim = if loss_scaler.is_some() {
+            ssim.with_half_precision()
+        } else {
+            ssim
+        };
+
         let decay = (config.lr_mean_end / config.lr_mean).powf(1.0 / config.total_steps as f64);
         let lr_mean = ExponentialLrSchedulerConfig::new(config.lr_mean, decay);
 
@@ -78,52 +198,219 @@ impl SplatTrainer {
             optim: None,
             refine_record: None,
             ssim,
+            wb: None,
+            features: None,
+            motion: None,
+            tonemap: None,
+            loss_scaler,
+            rng: StdRng::from_rng(&mut rand::rng()),
+            seed: rand::random(),
+            deterministic: false,
+            lr_scale: 1.0,
+            added_at: None,
         }
     }
 
-    pub fn step(
-        &mut self,
-        scene_extent: f32,
-        iter: u32,
-        batch: &SceneBatch<Autodiff<MainBackend>>,
-        splats: Splats<Autodiff<MainBackend>>,
-    ) -> (Splats<Autodiff<MainBackend>>, TrainStepStats<MainBackend>) {
-        let mut splats = splats;
+    /// Seed the random choices of refining, and the random numbers of renders, so runs can be
+    /// reproduced.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.seed = seed;
+        self
+    }
+
+    /// Sum gradients in a fixed order, and draw all noise from the seed, so seeded runs give the
+    /// same splats each time. This is slower than the default.
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Learn a color correction for each white balance group while training.
+    ///
+    /// `groups` holds the white balance group of each training view.
+    pub fn with_wb_groups(
+        mut self,
+        groups: Vec<u32>,
+        num_groups: u32,
+        device: &WgpuDevice,
+    ) -> Self {
+        self.wb = Some(WbTrainer::new(groups, num_groups, device));
+        self
+    }
+
+    /// Compare renders to the (linear) images of HDR datasets through `tonemap`, so errors are
+    /// weighed like they're seen instead of being dominated by the brightest highlights. Unused
+    /// when the loss is computed in linear color.
+    pub fn with_tonemap(mut self, tonemap: Tonemap) -> Self {
+        self.tonemap = Some(tonemap);
+        self
+    }
 
+    /// Scale all learning rates by `scale`, relative to the configured ones. The schedules keep
+    /// decaying from the scaled rates.
+    pub fn set_lr_scale(&mut self, scale: f64) {
+        self.lr_scale = scale;
+    }
+
+    /// The learned [N, C] features of the splats, when training with feature maps.
+    pub fn features(&self) -> Option<Tensor<MainBackend, 2>> {
+        self.features.as_ref().map(|f| f.features())
+    }
+
+    /// Motion of the splats over time, when training a dynamic scene.
+    pub fn motion(&self) -> Option<SplatMotion<MainBackend>> {
+        self.motion.as_ref().map(|m| m.motion())
+    }
+
+    /// The splats as they are at `time` (0 to 1) of a dynamic scene. Splats of a static scene
+    /// don't move.
+    pub fn splats_at_time(&self, splats: Splats<MainBackend>, time: f32) -> Splats<MainBackend> {
+        match self.motion() {
+            Some(motion) => motion.apply(splats, time),
+            None => splats,
+        }
+    }
+
+    // Render a training view and compute its image loss.
+    fn view_loss(
+        &self,
+        batch: &SceneBatch<Autodiff<MainBackend>>,
+        splats: &Splats<Autodiff<MainBackend>>,
+        opacity: Tensor<Autodiff<MainBackend>, 1>,
+        iter: u32,
+        scene_extent: f32,
+    ) -> ViewLoss {
         let [img_h, img_w, _] = batch.img_tensor.dims();
-        let camera = &batch.camera;
+        let img_size = glam::uvec2(img_w as u32, img_h as u32);
+
+        // Move the splats to where they are at the time of the view.
+        let (means, rotation, quats, opacity) = match (&self.motion, batch.time) {
+            (Some(motion), Some(time)) => {
+                let deltas = motion.deltas(time);
+                let rotation = splats.rotation.val() + deltas.rotation;
+                let norm = rotation.clone().powi_scalar(2).sum_dim(1).sqrt();
+                (
+                    splats.means.val() + deltas.means,
+                    rotation.clone(),
+                    rotation / norm.clamp_min(1e-32),
+                    sigmoid(splats.raw_opacity.val() + deltas.raw_opacity),
+                )
+            }
+            _ => (
+                splats.means.val(),
+                splats.rotation.val(),
+                splats.rotations_normed(),
+                opacity,
+            ),
+        };
 
-        let current_opacity = splats.opacities();
-        let (pred_image, aux, refine_weight_holder) = {
-            let diff_out = <Autodiff<MainBackend> as SplatForwardDiff<_>>::render_splats(
-                camera,
-                glam::uvec2(img_w as u32, img_h as u32),
-                splats.means.val().into_primitive().tensor(),
-                splats.log_scales.val().into_primitive().tensor(),
-                splats.rotation.val().into_primitive().tensor(),
-                splats.sh_coeffs.val().into_primitive().tensor(),
-                current_opacity.clone().into_primitive().tensor(),
-            );
-            let img = Tensor::from_primitive(TensorPrimitive::Float(diff_out.img));
-            (img, diff_out.aux, diff_out.refine_weight_holder)
+        // Stochastic effects draw different numbers each step, but the same ones each run.
+        let seed = step_seed(self.seed, iter, batch.view_index as u32);
+
+        // Leave out random splats, and make the others more opaque so the render stays about as
+        // bright on average.
+        let dropout = dropout_rate(&self.config, iter);
+        let opacity = if dropout > 0.0 {
+            let n = opacity.dims()[0];
+            let factors = dropout_factors(seed, n as u32, dropout);
+            let factors = Tensor::from_data(TensorData::new(factors, [n]), &opacity.device());
+            (opacity * factors).clamp_max(1.0)
+        } else {
+            opacity
         };
 
-        let train_t = (iter as f32 / self.config.total_steps as f32).clamp(0.0, 1.0);
+        let camera = batch
+            .camera
+            .clone()
+            .with_seed(seed)
+            .with_culling(Culling {
+                near: self.config.near_plane,
+                far: self.config.far_plane,
+                min_radius: self.config.min_splat_radius,
+            })
+            .with_deterministic(self.deterministic);
+        let camera = if self.config.subpixel_jitter {
+            camera.with_jitter(jitter_offset(iter))
+        } else {
+            camera
+        };
+
+        let log_scales = if self.config.flat_splats {
+            flatten_scales(splats.log_scales.val())
+        } else {
+            splats.log_scales.val()
+        };
+
+        let DiffRenderOutput {
+            image: pred_image,
+            aux,
+            refine_weight_holder,
+        } = render_splats(
+            &camera,
+            img_size,
+            means.clone(),
+            log_scales.clone(),
+            rotation,
+            splats.sh_coeffs.val(),
+            opacity.clone(),
+        );
 
         let _span = trace_span!("Calculate losses", sync_burn = true).entered();
 
         let pred_rgb = pred_image.clone().slice(s![.., .., 0..3]);
+        // Compensate for the white balance of this view, so it isn't baked into the splats.
+        let pred_rgb = if let Some(wb) = self.wb.as_ref() {
+            wb.apply(batch.view_index, pred_rgb)
+        } else {
+            pred_rgb
+        };
         let gt_rgb = batch.img_tensor.clone().slice(s![.., .., 0..3]);
+        // Regular images are sRGB encoded, HDR images are linear.
+        let (pred_rgb, gt_rgb) = match (self.config.loss_color_space, self.tonemap) {
+            (ColorSpace::Srgb, Some(tonemap)) => (tonemap.apply(pred_rgb), tonemap.apply(gt_rgb)),
+            (ColorSpace::Linear, None) => (
+                srgb_to_linear_tensor(pred_rgb),
+                srgb_to_linear_tensor(gt_rgb),
+            ),
+            (ColorSpace::Srgb, None) | (ColorSpace::Linear, Some(_)) => (pred_rgb, gt_rgb),
+        };
 
-        let l1_rgb = (pred_rgb.clone() - gt_rgb).abs();
+        // In half precision, the per pixel losses and their intermediates are computed in f16.
+        // The splats, and the render passes, stay in f32.
+        let half = self.loss_scaler.is_some();
+        let (pred_rgb, gt_rgb) = if half {
+            (pred_rgb.cast(FloatDType::F16), gt_rgb.cast(FloatDType::F16))
+        } else {
+            (pred_rgb, gt_rgb)
+        };
+
+        let l1_rgb = (pred_rgb.clone() - gt_rgb.clone()).abs();
+        let inliers = (self.config.robust_loss && iter >= self.config.robust_start).then(|| {
+            let residual = if half {
+                l1_rgb.clone().cast(FloatDType::F32)
+            } else {
+                l1_rgb.clone()
+            };
+            inlier_weights(residual, self.config.robust_inlier_quantile)
+        });
 
         let total_err = if self.config.ssim_weight > 0.0 {
-            let gt_rgb = batch.img_tensor.clone().slice(s![.., .., 0..3]);
             let ssim_err = self.ssim.ssim(pred_rgb, gt_rgb);
             l1_rgb * (1.0 - self.config.ssim_weight) - (ssim_err * self.config.ssim_weight)
         } else {
             l1_rgb
         };
+        // Average in f32, f16 can't accumulate this many pixels accurately.
+        let total_err = if half {
+            total_err.cast(FloatDType::F32)
+        } else {
+            total_err
+        };
+        let total_err = match inliers {
+            Some(inliers) => total_err * inliers,
+            None => total_err,
+        };
 
         let loss = if batch.has_alpha() {
             let alpha_input = batch.img_tensor.clone().slice(s![.., .., 3..4]);
@@ -139,10 +426,121 @@ impl SplatTrainer {
             total_err.mean()
         };
 
-        let opac_loss_weight = self.config.opac_loss_weight;
-        let visible: Tensor<_, 1> =
-            Tensor::from_primitive(TensorPrimitive::Float(aux.visible.clone()));
+        let with_distortion = self.config.flat_splats
+            && self.config.depth_distortion_weight > 0.0
+            && iter >= self.config.depth_distortion_start;
+        let with_normal = self.config.flat_splats
+            && self.config.normal_consistency_weight > 0.0
+            && iter >= self.config.normal_consistency_start;
+        let loss = match (&self.features, &batch.features) {
+            (Some(features), Some(target)) => {
+                let view = FeatureView {
+                    camera: &camera,
+                    means: means.clone(),
+                    log_scales: log_scales.clone(),
+                    quats: quats.clone(),
+                    opacities: opacity.clone(),
+                };
+                loss + features.loss(&view, target.clone()) * self.config.feature_weight
+            }
+            _ => loss,
+        };
+        let loss = match &batch.depth {
+            Some(target) if self.config.depth_weight > 0.0 => {
+                let view = DepthView {
+                    camera: &camera,
+                    means: means.clone(),
+                    log_scales: log_scales.clone(),
+                    quats: quats.clone(),
+                    opacities: opacity.clone(),
+                };
+                loss + view.loss(target.clone(), self.config.depth_confidence_power)
+                    * self.config.depth_weight
+            }
+            _ => loss,
+        };
+
+        let loss = if with_distortion || with_normal {
+            let flat = FlatSplatView {
+                camera: &camera,
+                img_size,
+                means,
+                log_scales,
+                quats,
+                opacities: opacity,
+            };
+            let losses = flat.losses(scene_extent, with_distortion, with_normal);
+            loss + losses.distortion * self.config.depth_distortion_weight
+                + losses.normal * self.config.normal_consistency_weight
+        } else {
+            loss
+        };
+
+        ViewLoss {
+            pred_image,
+            aux,
+            refine_weight_holder,
+            img_size,
+            view_index: batch.view_index,
+            loss,
+        }
+    }
+
+    /// Take an optimization step on a batch of views. The loss is averaged over the views.
+    ///
+    /// The stats of the step show the first view of the batch. In half precision, steps where any
+    /// gradient overflowed leave the splats as they are.
+    pub async fn step(
+        &mut self,
+        scene_extent: f32,
+        iter: u32,
+        batches: &[SceneBatch<Autodiff<MainBackend>>],
+        splats: Splats<Autodiff<MainBackend>>,
+    ) -> (Splats<Autodiff<MainBackend>>, TrainStepStats<MainBackend>) {
+        assert!(!batches.is_empty(), "Need at least one view to train on.");
+        let mut splats = splats;
+
+        if self.config.feature_dim > 0 && self.features.is_none() {
+            let features = FeatureTrainer::new(
+                splats.num_splats(),
+                self.config.feature_dim,
+                self.config.feature_latent_dim,
+                &splats.device(),
+            );
+            self.features = Some(features);
+        }
+        if self.config.motion_keyframes > 0 && self.motion.is_none() {
+            let motion = MotionTrainer::new(
+                splats.num_splats(),
+                self.config.motion_keyframes,
+                scene_extent,
+                &splats.device(),
+            );
+            self.motion = Some(motion);
+        }
+
+        let current_opacity = splats.opacities();
+        let views: Vec<_> = batches
+            .iter()
+            .map(|batch| {
+                self.view_loss(batch, &splats, current_opacity.clone(), iter, scene_extent)
+            })
+            .collect();
+        let batch_size = views.len() as f32;
+
+        let train_t = (iter as f32 / self.config.total_steps as f32).clamp(0.0, 1.0);
+
+        let loss = Tensor::cat(views.iter().map(|v| v.loss.clone()).collect(), 0).mean();
 
+        // How often each splat was visible in the batch.
+        let visible = views
+            .iter()
+            .map(|v| Tensor::<_, 1>::from_primitive(TensorPrimitive::Float(v.aux.visible.clone())))
+            .reduce(|a, b| a + b)
+            .expect("Batch has views")
+            / batch_size;
+
+        let opac_loss_weight = self.config.opac_loss_weight;
         let loss = if opac_loss_weight > 0.0 {
             // Invisible splats still have a tiny bit of loss. Otherwise,
             // they would never die off.
@@ -151,18 +549,64 @@ impl SplatTrainer {
         } else {
             loss
         };
+        let loss = match &self.motion {
+            Some(motion) if batches.iter().any(|b| b.time.is_some()) => {
+                loss + motion.smoothness_loss() * self.config.motion_smoothness_weight
+                    + motion.sparsity_loss() * self.config.motion_sparsity_weight
+            }
+            _ => loss,
+        };
+        // Scale up the loss so small f16 gradients don't underflow. Adam updates don't depend on
+        // the scale of the gradients, so only the refine weights need to be unscaled.
+        let scaled_loss = match &self.loss_scaler {
+            Some(scaler) => loss.clone() * scaler.scale(),
+            None => loss.clone(),
+        };
+        let mut grads =
+            trace_span!("Backward pass", sync_burn = true).in_scope(|| scaled_loss.backward());
 
-        let mut grads = trace_span!("Backward pass", sync_burn = true).in_scope(|| loss.backward());
-
-        let (lr_mean, lr_rotation, lr_scale, lr_coeffs, lr_opac) = (
-            self.sched_mean.step() * scene_extent as f64,
-            self.config.lr_rotation,
+        let lrs = (
+            self.sched_mean.step() * scene_extent as f64 * self.lr_scale,
+            self.config.lr_rotation * self.lr_scale,
             // Scale is relative to the scene scale, but the exp() activation function
             // means "offsetting" all values also solves the learning rate scaling.
-            self.sched_scale.step(),
-            self.config.lr_coeffs_dc,
-            self.config.lr_opac,
+            self.sched_scale.step() * self.lr_scale,
+            self.config.lr_coeffs_dc * self.lr_scale,
+            self.config.lr_opac * self.lr_scale,
         );
+        let (lr_mean, lr_rotation, lr_scale, lr_coeffs, lr_opac) = lrs;
+
+        // Overflowed gradients would turn splats into NaN for good, and poison the state of the
+        // optimizer, so the whole step is skipped, and the loss scale backed off right away.
+        if let Some(scaler) = self.loss_scaler.as_mut() {
+            let mut overflows = grad_overflows(&splats.sh_coeffs.val(), &grads)
+                + grad_overflows(&splats.rotation.val(), &grads)
+                + grad_overflows(&splats.log_scales.val(), &grads)
+                + grad_overflows(&splats.means.val(), &grads)
+                + grad_overflows(&splats.raw_opacity.val(), &grads);
+            for view in &views {
+                overflows = overflows + grad_overflows(&view.refine_weight_holder, &grads);
+            }
+            if let Some(wb) = &self.wb {
+                overflows = overflows + wb.overflows(&grads);
+            }
+            if let Some(features) = &self.features {
+                overflows = overflows + features.overflows(&grads);
+            }
+            if let Some(motion) = &self.motion {
+                overflows = overflows + motion.overflows(&grads);
+            }
+            let overflowed = overflows.into_scalar_async().await > 0;
+            scaler.update(overflowed);
+            if overflowed {
+                log::debug!(
+                    "Gradients overflowed at step {iter}, lowering the loss scale to {}",
+                    scaler.scale()
+                );
+                let stats = self.step_stats(views, loss, lrs);
+                return (splats, stats);
+            }
+        }
 
         let optimizer = self.optim.get_or_insert_with(|| {
             let sh_degree = splats.sh_degree();
@@ -188,51 +632,78 @@ impl SplatTrainer {
 
         splats = trace_span!("Optimizer step", sync_burn = true).in_scope(|| {
             splats = trace_span!("SH Coeffs step", sync_burn = true).in_scope(|| {
-                let grad_coeff =
-                    GradientsParams::from_params(&mut grads, &splats, &[splats.sh_coeffs.id]);
+                let grad_coeff = param_grads(&mut grads, &splats, splats.sh_coeffs.id);
                 optimizer.step(lr_coeffs, splats, grad_coeff)
             });
             splats = trace_span!("Rotation step", sync_burn = true).in_scope(|| {
-                let grad_rot =
-                    GradientsParams::from_params(&mut grads, &splats, &[splats.rotation.id]);
+                let grad_rot = param_grads(&mut grads, &splats, splats.rotation.id);
                 optimizer.step(lr_rotation, splats, grad_rot)
             });
             splats = trace_span!("Scale step", sync_burn = true).in_scope(|| {
-                let grad_scale =
-                    GradientsParams::from_params(&mut grads, &splats, &[splats.log_scales.id]);
+                let grad_scale = param_grads(&mut grads, &splats, splats.log_scales.id);
                 optimizer.step(lr_scale, splats, grad_scale)
             });
             splats = trace_span!("Mean step", sync_burn = true).in_scope(|| {
-                let grad_means =
-                    GradientsParams::from_params(&mut grads, &splats, &[splats.means.id]);
+                let grad_means = param_grads(&mut grads, &splats, splats.means.id);
                 optimizer.step(lr_mean, splats, grad_means)
             });
             splats = trace_span!("Opacity step", sync_burn = true).in_scope(|| {
-                let grad_opac =
-                    GradientsParams::from_params(&mut grads, &splats, &[splats.raw_opacity.id]);
+                let grad_opac = param_grads(&mut grads, &splats, splats.raw_opacity.id);
                 optimizer.step(lr_opac, splats, grad_opac)
             });
             splats
         });
 
-        let _housekeep = trace_span!("Housekeeping", sync_burn = true);
-        // Get the xy gradient norm from the dummy tensor.
-        let refine_weight = refine_weight_holder
-            .grad_remove(&mut grads)
-            .expect("XY gradients need to be calculated.");
+        // Keep splats flat, so they're exported and viewed as the disks they were trained as.
+        if self.config.flat_splats {
+            splats.log_scales = splats
+                .log_scales
+                .map(|s| Tensor::from_inner(flatten_scales(s.inner())).require_grad());
+        }
+
+        if let Some(wb) = self.wb.as_mut() {
+            trace_span!("White balance step", sync_burn = true)
+                .in_scope(|| wb.step(self.config.lr_wb, &mut grads));
+        }
 
+        if let Some(features) = self.features.as_mut() {
+            let lr = self.config.lr_features * self.lr_scale;
+            trace_span!("Features step", sync_burn = true)
+                .in_scope(|| features.step(lr, &mut grads));
+        }
+
+        if let Some(motion) = self.motion.as_mut() {
+            let lr = self.config.lr_motion * self.lr_scale;
+            trace_span!("Motion step", sync_burn = true).in_scope(|| motion.step(lr, &mut grads));
+        }
+
+        let _housekeep = trace_span!("Housekeeping", sync_burn = true);
         let device = splats.device();
         let num_splats = splats.num_splats();
-        let record = self
-            .refine_record
-            .get_or_insert_with(|| RefineRecord::new(num_splats, &device));
-
-        record.gather_stats(
-            refine_weight,
-            glam::uvec2(img_w as u32, img_h as u32),
-            aux.global_from_compact_gid.clone(),
-            aux.num_visible().into_primitive(),
-        );
+        for view in &views {
+            // Get the xy gradient norm from the dummy tensor.
+            let refine_weight = view
+                .refine_weight_holder
+                .grad_remove(&mut grads)
+                .expect("XY gradients need to be calculated.");
+            let refine_weight = match &self.loss_scaler {
+                Some(scaler) => refine_weight / scaler.scale(),
+                None => refine_weight,
+            };
+
+            let record = self
+                .refine_record
+                .get_or_insert_with(|| RefineRecord::new(num_splats, &device));
+            // Each view of the batch counts like a step of its own. The loss is averaged over the
+            // views, so undo that for the gradients of each view to be as large as in a step of
+            // one view, and splats to grow like they would with a batch size of 1.
+            record.gather_stats(
+                refine_weight * batch_size,
+                view.img_size,
+                view.aux.global_from_compact_gid.clone(),
+                view.aux.num_visible().into_primitive(),
+            );
+        }
         drop(_housekeep);
 
         let mean_noise_weight_scale = self.config.mean_noise_weight * (1.0 - train_t);
@@ -249,13 +720,21 @@ impl SplatTrainer {
             let noise_weight = noise_weight * visible.inner(); // Only noise visible gaussians.
             let noise_weight = noise_weight.unsqueeze_dim(1);
 
-            let samples = quaternion_vec_multiply(
-                splats.rotations_normed().inner(),
+            // Drawing the samples on the CPU is slow for big scenes, so only do so when the noise
+            // has to be reproducible.
+            let noise = if self.deterministic {
+                let seed = step_seed(self.seed, iter, NO_ID);
+                normal_samples(seed, MEAN_NOISE_STREAM, num_splats as usize, &device)
+            } else {
                 Tensor::random(
-                    [splats.num_splats() as usize, 3],
+                    [num_splats as usize, 3],
                     Distribution::Normal(0.0, 1.0),
                     &device,
-                ) * splats.scales().inner(),
+                )
+            };
+            let samples = quaternion_vec_multiply(
+                splats.rotations_normed().inner(),
+                noise * splats.scales().inner(),
             );
 
             let noise_weight = noise_weight * (lr_mean as f32 * mean_noise_weight_scale);
@@ -264,19 +743,35 @@ impl SplatTrainer {
                 .map(|m| Tensor::from_inner(m.inner() + samples * noise_weight).require_grad());
         }
 
-        let stats = TrainStepStats {
-            pred_image: pred_image.inner(),
-            num_visible: aux.num_visible().inner(),
-            num_intersections: aux.num_intersections().inner(),
+        let stats = self.step_stats(views, loss, lrs);
+        (splats, stats)
+    }
+
+    // Stats of a step, for the first view of the batch.
+    fn step_stats(
+        &self,
+        views: Vec<ViewLoss>,
+        loss: Tensor<Autodiff<MainBackend>, 1>,
+        (lr_mean, lr_rotation, lr_scale, lr_coeffs, lr_opac): (f64, f64, f64, f64, f64),
+    ) -> TrainStepStats<MainBackend> {
+        let view = views.into_iter().next().expect("Batch has views");
+        TrainStepStats {
+            pred_image: view.pred_image.inner(),
+            view_index: view.view_index,
+            num_visible: view.aux.num_visible().inner(),
+            num_intersections: view.aux.num_intersections().inner(),
             loss: loss.inner(),
+            grad_norm: self
+                .refine_record
+                .as_ref()
+                .map(|r| r.refine_weight_norm.clone()),
+            added_at: self.added_at.clone(),
             lr_mean,
             lr_rotation,
             lr_scale,
             lr_coeffs,
             lr_opac,
-        };
-
-        (splats, stats)
+        }
     }
 
     pub async fn refine_if_needed(
@@ -303,14 +798,30 @@ impl SplatTrainer {
             .refine_record
             .take()
             .expect("Can only refine if refine stats are initialized");
-        let alpha_mask = splats
-            .raw_opacity
-            .val()
-            .inner()
-            .lower_elem(inverse_sigmoid(MIN_OPACITY));
-
-        let (mut splats, refiner, pruned_count) =
-            prune_points(splats, &mut record, refiner, alpha_mask).await;
+        let raw_opacity = splats.raw_opacity.val().inner();
+        // Splats of dynamic scenes only need to show up at some point of the video.
+        let raw_opacity = match &self.motion {
+            Some(motion) => motion.peak_raw_opacity(raw_opacity),
+            None => raw_opacity,
+        };
+        let alpha_mask = raw_opacity.lower_elem(inverse_sigmoid(MIN_OPACITY));
+        let num_splats = splats.num_splats() as usize;
+        let mut added_at = self
+            .added_at
+            .take()
+            .filter(|a| a.dims()[0] == num_splats)
+            .unwrap_or_else(|| Tensor::zeros([num_splats], &device));
+
+        let (mut splats, refiner, pruned_count) = prune_points(
+            splats,
+            &mut record,
+            refiner,
+            alpha_mask,
+            self.features.as_mut(),
+            self.motion.as_mut(),
+            &mut added_at,
+        )
+        .await;
         let mut add_indices = HashSet::new();
 
         // Replace dead gaussians if we're still refining.
@@ -322,7 +833,8 @@ impl SplatTrainer {
                 .await
                 .to_vec::<f32>()
                 .expect("Failed to read weights");
-            let resampled_inds = multinomial_sample(&resampled_weights, pruned_count);
+            let resampled_inds =
+                multinomial_sample(&resampled_weights, pruned_count, &mut self.rng);
             add_indices.extend(resampled_inds);
         }
 
@@ -351,7 +863,7 @@ impl SplatTrainer {
                     .await
                     .to_vec::<f32>()
                     .expect("Failed to read weights");
-                let growth_inds = multinomial_sample(&weights, grow_count);
+                let growth_inds = multinomial_sample(&weights, grow_count, &mut self.rng);
                 add_indices.extend(growth_inds);
             }
         }
@@ -359,10 +871,11 @@ impl SplatTrainer {
         let refine_count = add_indices.len();
 
         if refine_count > 0 {
-            let refine_inds = Tensor::from_data(
-                TensorData::new(add_indices.into_iter().collect(), [refine_count]),
-                &device,
-            );
+            // Sets iterate in a random order, sort to keep refines reproducible.
+            let mut add_indices: Vec<i32> = add_indices.into_iter().collect();
+            add_indices.sort_unstable();
+            let refine_inds =
+                Tensor::from_data(TensorData::new(add_indices, [refine_count]), &device);
 
             let cur_means = splats.means.val().inner().select(0, refine_inds.clone());
             let cur_rots = splats
@@ -400,7 +913,12 @@ impl SplatTrainer {
 
             let samples = quaternion_vec_multiply(
                 cur_rots.clone(),
-                Tensor::random([refine_count, 3], Distribution::Normal(0.0, 0.5), &device)
+                normal_samples(
+                    step_seed(self.seed, iter, NO_ID),
+                    GROWTH_STREAM,
+                    refine_count,
+                    &device,
+                ) * 0.5
                     * cur_log_scale.clone().exp(),
             );
 
@@ -444,8 +962,17 @@ impl SplatTrainer {
                 },
                 |x| Tensor::cat(vec![x, Tensor::zeros([refine_count], &device)], 0),
             );
+            if let Some(motion) = self.motion.as_mut() {
+                motion.grow(refine_inds.clone());
+            }
+            if let Some(features) = self.features.as_mut() {
+                features.grow(refine_inds);
+            }
+            let new_added_at = Tensor::full([refine_count], iter as f32, &device);
+            added_at = Tensor::cat(vec![added_at, new_added_at], 0);
         }
 
+        self.added_at = Some(added_at);
         self.optim = Some(create_default_optimizer().load_record(record));
 
         client.memory_cleanup();
@@ -503,7 +1030,7 @@ fn map_splats_and_opt(
     splats
 }
 
-fn map_opt<B: AutodiffBackend, const D: usize>(
+pub(crate) fn map_opt<B: AutodiffBackend, const D: usize>(
     param_id: ParamId,
     record: &mut HashMap<ParamId, AdaptorRecord<AdamScaled, B>>,
     map_opt: &impl Fn(Tensor<B::InnerBackend, D>) -> Tensor<B::InnerBackend, D>,
@@ -531,6 +1058,9 @@ async fn prune_points(
     record: &mut HashMap<ParamId, AdaptorRecord<AdamScaled, Autodiff<MainBackend>>>,
     mut refiner: RefineRecord<MainBackend>,
     prune: Tensor<MainBackend, 1, Bool>,
+    features: Option<&mut FeatureTrainer<Autodiff<MainBackend>>>,
+    motion: Option<&mut MotionTrainer<Autodiff<MainBackend>>>,
+    added_at: &mut Tensor<MainBackend, 1>,
 ) -> (
     Splats<Autodiff<MainBackend>>,
     RefineRecord<MainBackend>,
@@ -572,7 +1102,173 @@ async fn prune_points(
             |x| x.select(0, valid_inds.clone()),
             |x| x.select(0, valid_inds.clone()),
         );
+        if let Some(features) = features {
+            features.retain(valid_inds.clone());
+        }
+        if let Some(motion) = motion {
+            motion.retain(valid_inds.clone());
+        }
+        *added_at = added_at.clone().select(0, valid_inds.clone());
         refiner = refiner.keep(valid_inds);
     }
     (splats, refiner, start_splats - new_points)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brush_render::camera::Camera;
+
+    #[test]
+    fn dropout_decays() {
+        let config = TrainConfig::new()
+            .with_dropout_rate(0.2)
+            .with_dropout_end(1000);
+        assert_eq!(dropout_rate(&config, 0), 0.2);
+        assert!((dropout_rate(&config, 500) - 0.1).abs() < 1e-6);
+        assert_eq!(dropout_rate(&config, 1000), 0.0);
+        assert_eq!(dropout_rate(&TrainConfig::new(), 0), 0.0);
+    }
+
+    #[test]
+    fn dropout_is_seeded() {
+        let factors = dropout_factors(7, 10_000, 0.2);
+        assert_eq!(factors, dropout_factors(7, 10_000, 0.2));
+        assert_ne!(factors, dropout_factors(8, 10_000, 0.2));
+        // Opacity is kept the same on average.
+        let mean = factors.iter().sum::<f32>() / factors.len() as f32;
+        assert!((mean - 1.0).abs() < 0.05, "{mean}");
+        let dropped = factors.iter().filter(|&&f| f == 0.0).count();
+        assert!((1800..2200).contains(&dropped), "{dropped}");
+    }
+
+    // A grid of splats in front of a camera, and a gray view of them.
+    fn grid_scene(
+        device: &WgpuDevice,
+    ) -> (
+        Splats<Autodiff<MainBackend>>,
+        SceneBatch<Autodiff<MainBackend>>,
+    ) {
+        let mut rng = StdRng::seed_from_u64(0);
+        let positions: Vec<_> = (0..64)
+            .map(|i| {
+                glam::vec3(
+                    (i % 8) as f32 * 0.1 - 0.35,
+                    (i / 8) as f32 * 0.1 - 0.35,
+                    2.0,
+                )
+            })
+            .collect();
+        let splats = Splats::from_random_points(&positions, &mut rng, device).into_autodiff();
+        let batch = SceneBatch {
+            img_tensor: Tensor::ones([32, 32, 3], device) * 0.5,
+            alpha_is_mask: false,
+            features: None,
+            depth: None,
+            time: None,
+            camera: Camera::new(
+                glam::Vec3::ZERO,
+                glam::Quat::IDENTITY,
+                0.8,
+                0.8,
+                glam::vec2(0.5, 0.5),
+            ),
+            view_index: 0,
+        };
+        (splats, batch)
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    #[tokio::test]
+    async fn batches_grow_like_single_views() {
+        let device = WgpuDevice::DefaultDevice;
+        let (splats, batch) = grid_scene(&device);
+
+        let mut weights = vec![];
+        for batches in [vec![batch.clone()], vec![batch.clone(), batch]] {
+            let mut trainer = SplatTrainer::new(&TrainConfig::new(), &device).with_seed(0);
+            let _ = trainer.step(1.0, 1, &batches, splats.clone()).await;
+            weights.push(
+                trainer
+                    .refine_record
+                    .expect("Stats are gathered every step")
+                    .refine_weight_norm
+                    .into_data()
+                    .into_vec::<f32>()
+                    .expect("Wrong type"),
+            );
+        }
+        let [single, double] = [&weights[0], &weights[1]];
+
+        assert!(single.iter().any(|&w| w > 0.0));
+        for (a, b) in single.iter().zip(double) {
+            assert!((a - b).abs() <= 1e-4 * a.abs().max(1.0), "{a} != {b}");
+        }
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    #[tokio::test]
+    async fn deterministic_runs_match() {
+        let device = WgpuDevice::DefaultDevice;
+        let (splats, batch) = grid_scene(&device);
+        // Grow every other step, so the noise of growth matters too.
+        let config = TrainConfig::new()
+            .with_refine_every(2)
+            .with_growth_grad_threshold(0.0);
+
+        let mut runs = vec![];
+        for _ in 0..2 {
+            let mut trainer = SplatTrainer::new(&config, &device)
+                .with_seed(3)
+                .with_deterministic(true);
+            let mut splats = splats.clone();
+            for iter in 1..=4 {
+                (splats, _) = trainer.step(1.0, iter, &[batch.clone()], splats).await;
+                (splats, _) = trainer.refine_if_needed(iter, splats).await;
+            }
+            let values = [
+                splats.means.val(),
+                splats.log_scales.val(),
+                splats.raw_opacity.val().unsqueeze_dim(1),
+            ]
+            .map(|t| t.into_data().into_vec::<f32>().expect("Wrong type"));
+            runs.push(values);
+        }
+
+        assert!(runs[0][0].len() > 64 * 3, "Splats should have grown");
+        assert_eq!(runs[0], runs[1]);
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    #[tokio::test]
+    async fn overflowed_steps_are_skipped() {
+        let device = WgpuDevice::DefaultDevice;
+        if !supports_f16(&device) {
+            return;
+        }
+        let (splats, mut batch) = grid_scene(&device);
+        // A NaN image makes every gradient overflow.
+        batch.img_tensor = Tensor::full([32, 32, 3], f32::NAN, &device);
+
+        let config = TrainConfig::new().with_half_precision(true);
+        let mut trainer = SplatTrainer::new(&config, &device).with_seed(0);
+        let (stepped, _) = trainer.step(1.0, 1, &[batch], splats.clone()).await;
+
+        let scale = trainer
+            .loss_scaler
+            .as_ref()
+            .expect("Half precision")
+            .scale();
+        assert_eq!(scale, config.loss_scale / 2.0);
+        assert!(trainer.refine_record.is_none());
+        let means = |splats: &Splats<Autodiff<MainBackend>>| {
+            splats
+                .means
+                .val()
+                .into_data()
+                .into_vec::<f32>()
+                .expect("Wrong type")
+        };
+        assert_eq!(means(&stepped), means(&splats));
+    }
+}
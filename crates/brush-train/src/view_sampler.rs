@@ -0,0 +1,156 @@
+// Which view to train on next. Captures often cover parts of a scene with far fewer views than
+// others, and those parts converge slowly when every view is trained as often as any other.
+// Sampling views with a high recent loss more often spends the steps where the scene is still off.
+use brush_dataset::scene_loader::ViewSampling;
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+
+// Weight of the latest loss of a view in its running average.
+const LOSS_DECAY: f32 = 0.3;
+// Share of steps trained on a uniformly drawn view, so views with a low loss still get trained
+// now and then, and their loss stays up to date.
+const UNIFORM_SHARE: f32 = 0.25;
+
+/// Picks the views to train on, and learns from the loss of training on them.
+pub trait ViewSampler: Send {
+    /// The view to train on next, one of `views`.
+    ///
+    /// `views` isn't empty, and can grow between calls while the dataset loads.
+    fn next_view(&mut self, views: &[usize]) -> usize;
+
+    /// Record the loss of a step trained on `view`.
+    fn record_loss(&mut self, _view: usize, _loss: f32) {}
+}
+
+/// A sampler following `sampling`.
+pub fn view_sampler(sampling: ViewSampling, num_views: usize, seed: u64) -> Box<dyn ViewSampler> {
+    match sampling {
+        ViewSampling::Uniform => Box::new(UniformSampler::new(seed)),
+        ViewSampling::Loss => Box::new(LossSampler::new(num_views, seed)),
+    }
+}
+
+/// Trains every view once per epoch, in a random order.
+pub struct UniformSampler {
+    rng: StdRng,
+    epoch: Vec<usize>,
+}
+
+impl UniformSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            epoch: vec![],
+        }
+    }
+}
+
+impl ViewSampler for UniformSampler {
+    fn next_view(&mut self, views: &[usize]) -> usize {
+        if self.epoch.is_empty() {
+            // Start the next epoch over the views loaded by now.
+            self.epoch = views.to_vec();
+            self.epoch.shuffle(&mut self.rng);
+        }
+        self.epoch.pop().expect("Need at least one view")
+    }
+}
+
+/// Trains views more often the higher their recent loss is.
+///
+/// Views are first all trained once, to find their loss. After that, views are drawn in proportion
+/// to a running average of their loss, mixed with some uniform draws.
+pub struct LossSampler {
+    rng: StdRng,
+    losses: Vec<Option<f32>>,
+}
+
+impl LossSampler {
+    pub fn new(num_views: usize, seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            losses: vec![None; num_views],
+        }
+    }
+
+    fn loss(&self, view: usize) -> Option<f32> {
+        self.losses.get(view).copied().flatten()
+    }
+}
+
+impl ViewSampler for LossSampler {
+    fn next_view(&mut self, views: &[usize]) -> usize {
+        let untrained: Vec<_> = views
+            .iter()
+            .copied()
+            .filter(|&v| self.loss(v).is_none())
+            .collect();
+        if !untrained.is_empty() {
+            return untrained[self.rng.random_range(0..untrained.len())];
+        }
+        let total: f32 = views.iter().filter_map(|&v| self.loss(v)).sum();
+        if total <= 0.0 || self.rng.random::<f32>() < UNIFORM_SHARE {
+            return views[self.rng.random_range(0..views.len())];
+        }
+        let mut pick = self.rng.random::<f32>() * total;
+        for &view in views {
+            pick -= self.loss(view).unwrap_or(0.0);
+            if pick <= 0.0 {
+                return view;
+            }
+        }
+        // Rounding can leave a bit of the total over.
+        *views.last().expect("Need at least one view")
+    }
+
+    fn record_loss(&mut self, view: usize, loss: f32) {
+        if !loss.is_finite() {
+            return;
+        }
+        if view >= self.losses.len() {
+            self.losses.resize(view + 1, None);
+        }
+        let loss = loss.max(0.0);
+        self.losses[view] = Some(match self.losses[view] {
+            Some(average) => average + (loss - average) * LOSS_DECAY,
+            None => loss,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_trains_every_view_per_epoch() {
+        let mut sampler = UniformSampler::new(0);
+        let views = [0, 1, 2, 3, 4];
+        let mut picked: Vec<_> = (0..5).map(|_| sampler.next_view(&views)).collect();
+        picked.sort_unstable();
+        assert_eq!(picked, views);
+    }
+
+    #[test]
+    fn high_loss_views_train_more() {
+        let mut sampler = LossSampler::new(4, 0);
+        let views = [0, 1, 2, 3];
+        let mut first: Vec<_> = (0..4)
+            .map(|_| {
+                let view = sampler.next_view(&views);
+                sampler.record_loss(view, if view == 3 { 0.4 } else { 0.05 });
+                view
+            })
+            .collect();
+        // Untrained views go first.
+        first.sort_unstable();
+        assert_eq!(first, views);
+
+        let mut counts = [0; 4];
+        for _ in 0..2000 {
+            counts[sampler.next_view(&views)] += 1;
+        }
+        assert!(counts[3] > 3 * counts[0]);
+        // All views still get trained.
+        assert!(counts.iter().all(|&c| c > 0));
+    }
+}
@@ -0,0 +1,78 @@
+use burn::{
+    module::{Module, Param},
+    optim::{GradientsParams, Optimizer, adaptor::OptimizerAdaptor},
+    prelude::Backend,
+    tensor::{Int, Tensor, backend::AutodiffBackend},
+};
+
+use crate::{
+    adam_scaled::{AdamScaled, AdamScaledConfig},
+    loss_scale::grad_overflows,
+};
+
+/// A learned color gain per white balance group.
+///
+/// Gains are stored in log space, so that the correction starts out as the identity
+/// and can never flip sign. Only the relative gains between groups are meaningful.
+#[derive(Module, Debug)]
+pub(crate) struct WbCorrection<B: Backend> {
+    log_gains: Param<Tensor<B, 2>>,
+}
+
+impl<B: Backend> WbCorrection<B> {
+    pub(crate) fn new(num_groups: u32, device: &B::Device) -> Self {
+        Self {
+            log_gains: Param::from_tensor(Tensor::zeros([num_groups as usize, 3], device)),
+        }
+    }
+
+    /// Apply the correction of a group to a [H, W, 3] image.
+    pub(crate) fn apply(&self, group: u32, rgb: Tensor<B, 3>) -> Tensor<B, 3> {
+        let group = group as usize;
+        let log_gains = self.log_gains.val();
+        // Keep the average gain fixed at 1, the overall color of the scene should
+        // be learned by the splats.
+        let log_gains = log_gains.clone() - log_gains.mean_dim(0);
+        let gain = log_gains
+            .slice([group..group + 1, 0..3])
+            .exp()
+            .reshape([1, 1, 3]);
+        rgb * gain
+    }
+}
+
+pub(crate) struct WbTrainer<B: AutodiffBackend> {
+    /// White balance group for each view.
+    groups: Vec<u32>,
+    correction: WbCorrection<B>,
+    optim: OptimizerAdaptor<AdamScaled, WbCorrection<B>, B>,
+}
+
+impl<B: AutodiffBackend> WbTrainer<B> {
+    pub(crate) fn new(groups: Vec<u32>, num_groups: u32, device: &B::Device) -> Self {
+        Self {
+            groups,
+            correction: WbCorrection::new(num_groups, device),
+            optim: AdamScaledConfig::new().with_epsilon(1e-15).init(),
+        }
+    }
+
+    pub(crate) fn apply(&self, view_index: usize, rgb: Tensor<B, 3>) -> Tensor<B, 3> {
+        match self.groups.get(view_index) {
+            Some(&group) => self.correction.apply(group, rgb),
+            None => rgb,
+        }
+    }
+
+    /// Number of gradient values of the gains that overflowed.
+    pub(crate) fn overflows(&self, grads: &B::Gradients) -> Tensor<B::InnerBackend, 1, Int> {
+        grad_overflows(&self.correction.log_gains.val(), grads)
+    }
+
+    pub(crate) fn step(&mut self, lr: f64, grads: &mut B::Gradients) {
+        let id = self.correction.log_gains.id;
+        let grad = GradientsParams::from_params(grads, &self.correction, &[id]);
+        let correction = self.correction.clone();
+        self.correction = self.optim.step(lr, correction, grad);
+    }
+}
@@ -1,9 +1,19 @@
 use crate::UiMode;
 use crate::{
-    BrushUiProcess, camera_controls::CameraClamping, datasets::DatasetPanel, panels::PaneType,
-    scene::ScenePanel, settings::SettingsPanel, stats::StatsPanel,
+    BrushUiProcess,
+    camera_controls::{CameraClamping, ControllerMode},
+    camera_path::CameraPathPanel,
+    datasets::DatasetPanel,
+    gallery::GalleryPanel,
+    panels::PaneType,
+    scene::ScenePanel,
+    settings::SettingsPanel,
+    stats::StatsPanel,
+    stills::StillsPanel,
 };
 use brush_process::message::ProcessMessage;
+use brush_render::{camera::Culling, debug_view::DebugView};
+use brush_vfs::{DataSource, MemoryFile};
 use eframe::egui;
 use egui::ThemePreference;
 use egui_tiles::{Container, SimplificationOptions, Tile, TileId, Tiles};
@@ -60,6 +70,8 @@ pub struct CameraSettings {
     pub focus_distance: f32,
     pub speed_scale: Option<f32>,
     pub clamping: CameraClamping,
+    pub controller: ControllerMode,
+    pub inertia: Option<f32>,
 }
 
 impl Default for CameraSettings {
@@ -71,6 +83,61 @@ impl Default for CameraSettings {
             focus_distance: 4.0,
             speed_scale: None,
             clamping: CameraClamping::default(),
+            controller: ControllerMode::default(),
+            inertia: None,
+        }
+    }
+}
+
+/// How the splats are drawn in the scene view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewSettings {
+    /// Exposure in stops, for splats with linear HDR colors.
+    pub exposure: f32,
+    pub debug_view: DebugView,
+    pub lod: bool,
+    /// Screen size in pixels of the splats the level of detail merges.
+    pub lod_pixels: f32,
+    pub show_cameras: bool,
+    /// Splats left out by their distance to the camera and size on screen.
+    pub culling: Culling,
+}
+
+impl Default for ViewSettings {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            debug_view: DebugView::Color,
+            lod: false,
+            lod_pixels: 2.0,
+            show_cameras: true,
+            culling: Culling::default(),
+        }
+    }
+}
+
+/// How splats are exported from the scene view, following the export settings of training runs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportSettings {
+    /// Encrypt exports with this passphrase.
+    pub passphrase: Option<String>,
+    /// Write the most important splats first, see `--export-progressive`.
+    pub progressive: bool,
+}
+
+/// Real world size of the units of a scene, to measure it with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeasureScale {
+    /// Real world units per scene unit.
+    pub scale: f32,
+    pub unit: String,
+}
+
+impl Default for MeasureScale {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            unit: "m".to_owned(),
         }
     }
 }
@@ -83,6 +150,30 @@ pub struct App {
 
 impl App {
     pub fn new(cc: &eframe::CreationContext, context: Arc<dyn BrushUiProcess>) -> Self {
+        Self::new_with(cc, context, |_| {})
+    }
+
+    /// Like [`Self::new`], also showing the scene in the headset of `xr`. eframe needs to run
+    /// on the device of [`crate::xr::create_xr_graphics`].
+    #[cfg(all(feature = "xr", not(target_family = "wasm")))]
+    pub fn with_xr(
+        cc: &eframe::CreationContext,
+        context: Arc<dyn BrushUiProcess>,
+        xr: crate::xr::XrContext,
+    ) -> Self {
+        let state = cc
+            .wgpu_render_state
+            .as_ref()
+            .expect("Must use wgpu to render UI.");
+        let viewer = crate::xr::XrViewer::start(xr, state.device.clone(), state.queue.clone());
+        Self::new_with(cc, context, |scene| scene.set_xr(viewer))
+    }
+
+    fn new_with(
+        cc: &eframe::CreationContext,
+        context: Arc<dyn BrushUiProcess>,
+        setup_scene: impl FnOnce(&mut ScenePanel),
+    ) -> Self {
         // For now just assume we're running on the default
         let state = cc
             .wgpu_render_state
@@ -106,17 +197,23 @@ impl App {
             .options_mut(|opt| opt.theme_preference = ThemePreference::Dark);
 
         let mut tiles: Tiles<PaneType> = Tiles::default();
-        let scene_pane = ScenePanel::new(
+        let mut scene_pane = ScenePanel::new(
             state.device.clone(),
             state.queue.clone(),
             state.renderer.clone(),
             context.ui_mode(),
         );
+        setup_scene(&mut scene_pane);
 
         let scene_pane_id = tiles.insert_pane(Box::new(scene_pane));
 
         let root_container = if context.ui_mode() == UiMode::Full {
-            let loading_subs = vec![tiles.insert_pane(Box::new(SettingsPanel::new()))];
+            let loading_subs = vec![
+                tiles.insert_pane(Box::new(SettingsPanel::new())),
+                tiles.insert_pane(Box::new(GalleryPanel::new())),
+                tiles.insert_pane(Box::new(CameraPathPanel::new())),
+                tiles.insert_pane(Box::new(StillsPanel::new())),
+            ];
             let loading_pane = tiles.insert_tab_tile(loading_subs);
 
             #[allow(unused_mut)]
@@ -198,9 +295,53 @@ impl App {
     }
 }
 
+// A dropped file as something to load. Native drops come with a path, web drops with the data.
+fn dropped_source(file: egui::DroppedFile) -> Option<DataSource> {
+    if let Some(path) = file.path {
+        return Some(DataSource::Path(path.to_string_lossy().into_owned()));
+    }
+    let data = file.bytes?;
+    Some(DataSource::Memory(MemoryFile {
+        name: file.name,
+        data,
+    }))
+}
+
+impl App {
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        if ctx.input(|i| !i.raw.hovered_files.is_empty()) {
+            let painter = ctx.layer_painter(egui::LayerId::new(
+                egui::Order::Foreground,
+                egui::Id::new("drop_overlay"),
+            ));
+            let rect = ctx.screen_rect();
+            painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(180));
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "Drop a ply, splat, spz or zipped dataset to load it",
+                egui::FontId::proportional(22.0),
+                egui::Color32::WHITE,
+            );
+        }
+
+        // Only one source can load at a time, so just take the first file.
+        let dropped = ctx.input(|i| i.raw.dropped_files.first().cloned());
+        let Some(source) = dropped.and_then(dropped_source) else {
+            return;
+        };
+        for (_, pane) in self.tree.tiles.iter_mut() {
+            if let Tile::Pane(pane) = pane {
+                pane.on_dropped(&source, self.tree_ctx.context.as_ref());
+            }
+        }
+    }
+}
+
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _: &mut eframe::Frame) {
         self.receive_messages();
+        self.handle_dropped_files(ctx);
 
         egui::CentralPanel::default()
             .frame(egui::Frame::central_panel(ctx.style().as_ref()).inner_margin(0.0))
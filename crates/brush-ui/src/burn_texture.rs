@@ -1,8 +1,8 @@
 use std::sync::Arc;
 
-use brush_render::{MainBackend, MainBackendBase};
-use burn::tensor::{Tensor, TensorPrimitive};
-use burn_cubecl::cubecl::Runtime;
+use brush_render::{MainBackend, MainBackendBase, painter::pad_copy_width};
+use burn::tensor::{Int, Tensor, TensorPrimitive};
+use burn_cubecl::{cubecl::Runtime, tensor::CubeTensor};
 use burn_fusion::client::FusionClient;
 use burn_wgpu::WgpuRuntime;
 use eframe::egui_wgpu::Renderer;
@@ -56,7 +56,29 @@ impl BurnTexture {
     pub fn update_texture(&mut self, img: Tensor<MainBackend, 3>) -> TextureId {
         let [h, w, c] = img.shape().dims();
         assert!(c == 1, "texture should be u8 packed RGBA");
-        let size = glam::uvec2(w as u32, h as u32);
+
+        let img_prim = img.into_primitive().tensor();
+        let fusion_client = img_prim.client.clone();
+        let img = fusion_client.resolve_tensor_float::<MainBackendBase>(img_prim);
+        let img: Tensor<MainBackendBase, 3> = Tensor::from_primitive(TensorPrimitive::Float(img));
+        self.upload(pad_copy_width(img).into_primitive().tensor(), [h, w])
+    }
+
+    /// Like [`Self::update_texture`], for an image already packed as RGBA8 in an int tensor.
+    pub fn update_texture_packed(&mut self, img: Tensor<MainBackend, 3, Int>) -> TextureId {
+        let [h, w, c] = img.shape().dims();
+        assert!(c == 1, "texture should be u8 packed RGBA");
+
+        let img_prim = img.into_primitive();
+        let fusion_client = img_prim.client.clone();
+        let img = fusion_client.resolve_tensor_int::<MainBackendBase>(img_prim);
+        let img: Tensor<MainBackendBase, 3, Int> = Tensor::from_primitive(img);
+        self.upload(pad_copy_width(img).into_primitive(), [h, w])
+    }
+
+    // Copy a padded image with 4 bytes per pixel to the texture.
+    fn upload(&mut self, img: CubeTensor<WgpuRuntime>, [height, width]: [usize; 2]) -> TextureId {
+        let size = glam::uvec2(width as u32, height as u32);
 
         let dirty = if let Some(s) = self.state.as_ref() {
             s.texture.width() != size.x || s.texture.height() != size.y
@@ -66,10 +88,10 @@ impl BurnTexture {
 
         if dirty {
             // Resizing has some really bad memory profiles, so cleanup memory when it's detected.
-            let client = WgpuRuntime::client(&img.device());
+            let client = WgpuRuntime::client(&img.device);
             client.memory_cleanup();
 
-            let texture = create_texture(glam::uvec2(w as u32, h as u32), &self.device);
+            let texture = create_texture(size, &self.device);
 
             if let Some(s) = self.state.as_mut() {
                 s.texture = texture;
@@ -94,7 +116,6 @@ impl BurnTexture {
             unreachable!("Somehow failed to initialize")
         };
         let texture: &wgpu::Texture = &s.texture;
-        let [height, width, c] = img.dims();
 
         let mut encoder = self
             .device
@@ -102,24 +123,6 @@ impl BurnTexture {
                 label: Some("viewer encoder"),
             });
 
-        let padded_shape = vec![height, width.div_ceil(64) * 64, c];
-
-        let img_prim = img.into_primitive().tensor();
-        let fusion_client = img_prim.client.clone();
-        let img = fusion_client.resolve_tensor_float::<MainBackendBase>(img_prim);
-        let img: Tensor<MainBackendBase, 3> = Tensor::from_primitive(TensorPrimitive::Float(img));
-
-        // Create padded tensor if needed. The bytes_per_row needs to be divisible
-        // by 256 in WebGPU, so 4 bytes per pixel means width needs to be divisible by 64.
-        let img = if width % 64 != 0 {
-            let padded: Tensor<MainBackendBase, 3> = Tensor::zeros(&padded_shape, &img.device());
-            padded.slice_assign([0..height, 0..width], img)
-        } else {
-            img
-        };
-
-        let img = img.into_primitive().tensor();
-
         // Get a hold of the Burn resource.
         let client = &img.client;
         let img_res_handle = client.get_resource(img.handle.clone().binding());
@@ -128,7 +131,7 @@ impl BurnTexture {
         client.flush();
 
         // Put compute passes in encoder before copying the buffer.
-        let bytes_per_row = Some(4 * padded_shape[1] as u32);
+        let bytes_per_row = Some(4 * img.shape.dims[1] as u32);
 
         // Now copy the buffer to the texture.
         encoder.copy_buffer_to_texture(
@@ -5,6 +5,53 @@ use glam::{Quat, Vec2, Vec3};
 
 use crate::app::CameraSettings;
 
+/// How mouse and keyboard input moves the camera.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ControllerMode {
+    /// Orbit around a focus point in front of the camera, and zoom towards it.
+    #[default]
+    Orbit,
+    /// Look around freely, and fly in the direction the camera is looking.
+    Fly,
+    /// Look around with the pitch limited, and walk along the horizontal plane.
+    /// There is no collision, so this can still move through the scene.
+    Fps,
+}
+
+impl ControllerMode {
+    pub const ALL: [Self; 3] = [Self::Orbit, Self::Fly, Self::Fps];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Orbit => "Orbit",
+            Self::Fly => "Fly",
+            Self::Fps => "First person",
+        }
+    }
+
+    /// Short name of the controller, as parsed by [`std::str::FromStr`].
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Orbit => "orbit",
+            Self::Fly => "fly",
+            Self::Fps => "fps",
+        }
+    }
+}
+
+impl std::str::FromStr for ControllerMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "orbit" => Ok(Self::Orbit),
+            "fly" => Ok(Self::Fly),
+            "fps" => Ok(Self::Fps),
+            _ => Err(format!("Unknown camera controller '{s}'")),
+        }
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct CameraClamping {
     pub min_focus_distance: Option<f32>,
@@ -21,6 +68,9 @@ pub struct CameraController {
     pub focus_distance: f32,
     pub speed_scale: f32,
     pub clamping: CameraClamping,
+    pub mode: ControllerMode,
+    /// How long the camera keeps moving after input stops, 1 is the default feel and 0 stops instantly.
+    pub inertia: f32,
 
     roll: Quat,
     fly_velocity: Vec3,
@@ -123,6 +173,8 @@ impl CameraController {
             focus_distance: settings.focus_distance,
             clamping: settings.clamping,
             speed_scale: settings.speed_scale.unwrap_or(1.0),
+            mode: settings.controller,
+            inertia: settings.inertia.unwrap_or(1.0),
         }
     }
 
@@ -134,19 +186,27 @@ impl CameraController {
         let mmb = response.dragged_by(egui::PointerButton::Middle);
 
         let look_pan = mmb || lmb && ui.input(|r| r.modifiers.ctrl);
-        let look_fps = rmb || lmb && ui.input(|r| r.key_down(egui::Key::Space));
-        let look_orbit = lmb;
+        let (look_fps, look_orbit) = match self.mode {
+            ControllerMode::Orbit => (
+                rmb || lmb && ui.input(|r| r.key_down(egui::Key::Space)),
+                lmb,
+            ),
+            ControllerMode::Fly | ControllerMode::Fps => (lmb || rmb, false),
+        };
 
         let mouselook_speed = 0.002;
 
         let right = self.rotation * Vec3::X;
         let up = self.rotation * Vec3::NEG_Y;
         let forward = self.rotation * Vec3::Z;
+        let world_up = self.roll * Vec3::NEG_Y;
 
         if response.hovered() {
             if ui.input(|r| r.modifiers.ctrl) {
                 ui.ctx().set_cursor_icon(egui::CursorIcon::Move);
-            } else if ui.input(|r| r.key_down(egui::Key::Space)) {
+            } else if ui.input(|r| r.key_down(egui::Key::Space))
+                || self.mode != ControllerMode::Orbit
+            {
                 ui.ctx().set_cursor_icon(egui::CursorIcon::Crosshair);
             } else {
                 ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
@@ -160,9 +220,18 @@ impl CameraController {
             ui.ctx().set_cursor_icon(egui::CursorIcon::Move);
         } else if look_fps {
             let axis = response.drag_delta();
-            let yaw = Quat::from_axis_angle(self.roll * Vec3::NEG_Y, -axis.x * mouselook_speed);
+            let yaw = Quat::from_axis_angle(world_up, -axis.x * mouselook_speed);
             let pitch = Quat::from_rotation_x(-axis.y * mouselook_speed);
-            self.rotation = yaw * self.rotation * pitch;
+            let rotation = yaw * self.rotation * pitch;
+            // Don't allow looking past straight up or down when walking around.
+            let max_up = 89.0f32.to_radians().sin();
+            self.rotation = if self.mode == ControllerMode::Fps
+                && (rotation * Vec3::Z).dot(world_up).abs() > max_up
+            {
+                yaw * self.rotation
+            } else {
+                rotation
+            };
             ui.ctx().set_cursor_icon(egui::CursorIcon::Crosshair);
         } else if look_orbit {
             let delta_yaw = response.drag_delta().x * mouselook_speed;
@@ -264,16 +333,32 @@ impl CameraController {
         }
 
         let delta = self.fly_velocity * delta_time;
-        self.position += delta.x * right + delta.y * up + delta.z * forward;
+        self.position += if self.mode == ControllerMode::Fps {
+            // Walk along the horizontal plane, and move straight up & down.
+            let flat_forward = (forward - world_up * forward.dot(world_up)).normalize_or_zero();
+            let flat_right = (right - world_up * right.dot(world_up)).normalize_or_zero();
+            delta.x * flat_right + delta.y * world_up + delta.z * flat_forward
+        } else {
+            delta.x * right + delta.y * up + delta.z * forward
+        };
 
         // Damp velocities towards zero.
-        self.orbit_velocity = exp_lerp2(self.orbit_velocity, Vec2::ZERO, delta_time, 8.0);
-        self.fly_velocity = exp_lerp3(self.fly_velocity, Vec3::ZERO, delta_time, 7.0);
+        let inertia = self.inertia.max(0.05);
+        self.orbit_velocity = exp_lerp2(self.orbit_velocity, Vec2::ZERO, delta_time, 8.0 / inertia);
+        self.fly_velocity = exp_lerp3(self.fly_velocity, Vec3::ZERO, delta_time, 7.0 / inertia);
 
-        // Handle scroll wheel: move back, and adjust focus distance.
         let scrolled = ui.input(|r| r.smooth_scroll_delta.y);
         let scroll_speed = 0.001;
 
+        if self.mode != ControllerMode::Orbit {
+            // Without a focus point, scrolling changes how fast the camera moves.
+            self.speed_scale =
+                (self.speed_scale * (scrolled * scroll_speed).exp()).clamp(0.01, 100.0);
+            return;
+        }
+
+        // Handle scroll wheel: move back, and adjust focus distance.
+
         let old_pivot = self.position + self.rotation * Vec3::Z * self.focus_distance;
 
         // Scroll speed depends on how far zoomed out we are.
@@ -291,6 +376,21 @@ impl CameraController {
         self.position = old_pivot - (self.rotation * Vec3::Z * self.focus_distance);
     }
 
+    /// Change the pose and settings of the controller, keeping its roll. Movement only stops when
+    /// the controller mode changes.
+    pub fn apply_settings(&mut self, settings: &CameraSettings) {
+        self.position = settings.position;
+        self.rotation = settings.rotation;
+        self.focus_distance = settings.focus_distance;
+        self.clamping = settings.clamping.clone();
+        self.speed_scale = settings.speed_scale.unwrap_or(1.0);
+        self.inertia = settings.inertia.unwrap_or(1.0);
+        if self.mode != settings.controller {
+            self.stop_movement();
+            self.mode = settings.controller;
+        }
+    }
+
     pub fn local_to_world(&self) -> glam::Affine3A {
         glam::Affine3A::from_rotation_translation(self.rotation, self.position)
     }
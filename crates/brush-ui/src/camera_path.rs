@@ -0,0 +1,301 @@
+use brush_render::camera_path::{CameraKeyframe, CameraPath, PathEvent};
+use egui::{Slider, Ui};
+use tokio::{
+    io::AsyncReadExt,
+    sync::oneshot::{Receiver, channel},
+};
+use tokio_with_wasm::alias as tokio_wasm;
+use web_time::Instant;
+
+use crate::{BrushUiProcess, panels::AppPanel};
+
+// Time between new keyframes when adding them at the end of the path.
+const KEYFRAME_SPACING: f32 = 2.0;
+
+/// Edit camera paths, as used to render videos.
+///
+/// Keyframes are taken from the current view, and the path can be previewed in the viewer. Paths
+/// are saved as JSON, which can be rendered with `--render-path`.
+pub struct CameraPathPanel {
+    path: CameraPath,
+    time: f32,
+    // When the preview started playing, and at which time in the path.
+    playing: Option<(Instant, f32)>,
+    loading: Option<Receiver<anyhow::Result<CameraPath>>>,
+    // Name of the next event to add.
+    event_name: String,
+}
+
+async fn load_path() -> anyhow::Result<CameraPath> {
+    let mut reader = rrfd::pick_file().await?;
+    let mut data = vec![];
+    reader.read_to_end(&mut data).await?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+impl CameraPathPanel {
+    pub(crate) fn new() -> Self {
+        Self {
+            path: CameraPath::default(),
+            time: 0.0,
+            playing: None,
+            loading: None,
+            event_name: String::new(),
+        }
+    }
+
+    fn preview(&self, process: &dyn BrushUiProcess) {
+        let current = process.current_camera();
+        let aspect_ratio = ((current.fov_x * 0.5).tan() / (current.fov_y * 0.5).tan()) as f32;
+        if let Some(sample) = self.path.sample(self.time, aspect_ratio) {
+            process.set_camera(&sample.camera);
+        }
+    }
+
+    fn poll_loading(&mut self) {
+        let Some(loading) = self.loading.as_mut() else {
+            return;
+        };
+        let Ok(result) = loading.try_recv() else {
+            return;
+        };
+        self.loading = None;
+        match result {
+            Ok(path) => {
+                self.path = path;
+                self.time = 0.0;
+                self.playing = None;
+            }
+            Err(e) => log::error!("Failed to load camera path: {e}"),
+        }
+    }
+
+    fn file_buttons(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(self.loading.is_none(), egui::Button::new("📂 Load"))
+                .clicked()
+            {
+                let (sender, receiver) = channel();
+                let ctx = ui.ctx().clone();
+                tokio_wasm::task::spawn(async move {
+                    let _ = sender.send(load_path().await);
+                    ctx.request_repaint();
+                });
+                self.loading = Some(receiver);
+            }
+
+            let has_keyframes = !self.path.keyframes().is_empty();
+            if ui
+                .add_enabled(has_keyframes, egui::Button::new("💾 Save"))
+                .clicked()
+            {
+                match serde_json::to_vec_pretty(&self.path) {
+                    Ok(data) => {
+                        tokio_wasm::task::spawn(async move {
+                            let _ = rrfd::save_file("camera_path.json", data)
+                                .await
+                                .inspect_err(|e| log::error!("Failed to save file: {e}"));
+                        });
+                    }
+                    Err(e) => log::error!("Failed to serialize camera path: {e}"),
+                }
+            }
+
+            if ui
+                .add_enabled(has_keyframes, egui::Button::new("🗑 Clear"))
+                .clicked()
+            {
+                *self = Self::new();
+            }
+        });
+    }
+
+    fn timeline(&mut self, ui: &mut Ui, process: &dyn BrushUiProcess) {
+        let duration = self.path.duration();
+
+        if let Some((start, start_time)) = self.playing {
+            self.time = start_time + start.elapsed().as_secs_f32();
+            if self.time >= duration {
+                self.time = duration;
+                self.playing = None;
+            }
+            self.preview(process);
+            ui.ctx().request_repaint();
+        }
+
+        ui.horizontal(|ui| {
+            let playing = self.playing.is_some();
+            if ui.button(if playing { "⏸" } else { "▶" }).clicked() {
+                self.playing = if playing {
+                    None
+                } else {
+                    // Start over when at the end.
+                    if self.time >= duration {
+                        self.time = 0.0;
+                    }
+                    Some((Instant::now(), self.time))
+                };
+            }
+
+            let response = ui.add(
+                Slider::new(&mut self.time, 0.0..=duration)
+                    .suffix(" s")
+                    .fixed_decimals(2),
+            );
+            if response.changed() {
+                self.playing = None;
+                self.preview(process);
+            }
+        });
+    }
+
+    fn event_list(&mut self, ui: &mut Ui) {
+        ui.collapsing(format!("Events ({})", self.path.events().len()), |ui| {
+            ui.label("Named times of the path. Embedding pages get these during playback.");
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.event_name);
+                if ui
+                    .add_enabled(
+                        !self.event_name.is_empty(),
+                        egui::Button::new(format!("➕ At {:.2} s", self.time)),
+                    )
+                    .clicked()
+                {
+                    self.path.add_event(PathEvent {
+                        time: self.time,
+                        name: std::mem::take(&mut self.event_name),
+                    });
+                }
+            });
+
+            let mut remove = None;
+            for (index, event) in self.path.events().iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{:.2} s", event.time));
+                    ui.label(&event.name);
+                    if ui.small_button("🗑").on_hover_text("Delete").clicked() {
+                        remove = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = remove {
+                self.path.remove_event(index);
+            }
+        });
+    }
+
+    fn keyframe_list(&mut self, ui: &mut Ui, process: &dyn BrushUiProcess) {
+        // Edits re-sort the keyframes, so apply them after drawing the list.
+        let mut edit = None;
+        let mut remove = None;
+        let mut go_to = None;
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (index, keyframe) in self.path.keyframes().iter().enumerate() {
+                let mut keyframe = keyframe.clone();
+                let mut changed = false;
+
+                ui.push_id(index, |ui| {
+                    egui::Frame::group(ui.style()).show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("#{}", index + 1));
+                            changed |= ui
+                                .add(
+                                    egui::DragValue::new(&mut keyframe.time)
+                                        .speed(0.05)
+                                        .range(0.0..=f32::MAX)
+                                        .suffix(" s"),
+                                )
+                                .changed();
+
+                            if ui
+                                .small_button("👁")
+                                .on_hover_text("Go to keyframe")
+                                .clicked()
+                            {
+                                go_to = Some(keyframe.time);
+                            }
+                            if ui
+                                .small_button("⟲")
+                                .on_hover_text("Replace with current view")
+                                .clicked()
+                            {
+                                let camera = process.current_camera();
+                                keyframe = CameraKeyframe {
+                                    ease_in: keyframe.ease_in,
+                                    ease_out: keyframe.ease_out,
+                                    ..CameraKeyframe::from_camera(keyframe.time, &camera)
+                                };
+                                changed = true;
+                            }
+                            if ui.small_button("🗑").on_hover_text("Delete").clicked() {
+                                remove = Some(index);
+                            }
+                        });
+
+                        changed |= ui
+                            .add(Slider::new(&mut keyframe.ease_in, 0.0..=1.0).text("Ease in"))
+                            .changed();
+                        changed |= ui
+                            .add(Slider::new(&mut keyframe.ease_out, 0.0..=1.0).text("Ease out"))
+                            .changed();
+                    });
+                });
+
+                if changed {
+                    edit = Some((index, keyframe));
+                }
+            }
+        });
+
+        if let Some(index) = remove {
+            self.path.remove_keyframe(index);
+        } else if let Some((index, keyframe)) = edit {
+            self.path.set_keyframe(index, keyframe);
+        }
+        self.time = self.time.min(self.path.duration());
+
+        if let Some(time) = go_to {
+            self.time = time;
+            self.playing = None;
+            self.preview(process);
+        }
+    }
+}
+
+impl AppPanel for CameraPathPanel {
+    fn title(&self) -> String {
+        "Camera path".to_owned()
+    }
+
+    fn ui(&mut self, ui: &mut Ui, process: &dyn BrushUiProcess) {
+        self.poll_loading();
+
+        self.file_buttons(ui);
+        ui.add_space(6.0);
+
+        if ui.button("➕ Add keyframe").clicked() {
+            let time = self
+                .path
+                .keyframes()
+                .last()
+                .map_or(0.0, |k| k.time + KEYFRAME_SPACING);
+            self.path
+                .add_keyframe(CameraKeyframe::from_camera(time, &process.current_camera()));
+            self.time = time;
+        }
+
+        if self.path.keyframes().is_empty() {
+            ui.label("Move the camera and add keyframes to build a path.");
+            return;
+        }
+
+        ui.add_space(6.0);
+        self.timeline(ui, process);
+        ui.add_space(6.0);
+        self.event_list(ui);
+        ui.add_space(6.0);
+        self.keyframe_list(ui, process);
+    }
+}
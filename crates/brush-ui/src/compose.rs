@@ -0,0 +1,404 @@
+use brush_dataset::{
+    composition::{find_composition, load_composition},
+    splat_formats::{SplatFormat, load_splats},
+};
+use brush_render::{
+    MainBackend,
+    camera::Camera,
+    composition::{CompositionLayer, LayerTransform},
+    gaussian_splats::Splats,
+};
+use brush_vfs::BrushVfs;
+use burn_wgpu::WgpuDevice;
+use egui::{Color32, Rect, Sense, Stroke};
+use glam::{Quat, Vec3};
+use tokio::{
+    io::AsyncReadExt,
+    sync::oneshot::{Receiver, channel},
+};
+use tokio_stream::StreamExt;
+use tokio_with_wasm::alias as tokio_wasm;
+
+// Radius of the handles of the gizmo, in screen points.
+const HANDLE_RADIUS: f32 = 6.0;
+
+// Length of the axes of the gizmo, relative to its distance to the camera.
+const GIZMO_SIZE: f32 = 0.15;
+
+const AXIS_COLORS: [Color32; 3] = [
+    Color32::from_rgb(230, 80, 80),
+    Color32::from_rgb(80, 200, 80),
+    Color32::from_rgb(80, 140, 255),
+];
+
+/// A set of splats placed in a composition.
+pub(crate) struct Layer {
+    /// Name of the file the layer was loaded from, which it's saved as in composition files.
+    pub(crate) name: String,
+    pub(crate) transform: LayerTransform,
+    pub(crate) visible: bool,
+    splats: Splats<MainBackend>,
+    // The splats moved by the transform, until it changes.
+    placed: Option<(LayerTransform, Splats<MainBackend>)>,
+    // Center of the splats before they're placed, which the gizmo moves the layer around.
+    center: Vec3,
+    center_pending: Option<Receiver<Vec3>>,
+}
+
+impl Layer {
+    fn new(
+        name: String,
+        transform: LayerTransform,
+        splats: Splats<MainBackend>,
+        ctx: &egui::Context,
+    ) -> Self {
+        let (sender, receiver) = channel();
+        let ctx = ctx.clone();
+        let means = splats.means.val();
+        tokio_wasm::task::spawn(async move {
+            let center = means
+                .mean_dim(0)
+                .into_data_async()
+                .await
+                .into_vec::<f32>()
+                .expect("Wrong type");
+            // If the layer is gone, that's fine.
+            let _ = sender.send(Vec3::from_slice(&center));
+            ctx.request_repaint();
+        });
+        Self {
+            name,
+            transform,
+            visible: true,
+            splats,
+            placed: None,
+            center: Vec3::ZERO,
+            center_pending: Some(receiver),
+        }
+    }
+
+    /// Where the center of the layer is placed.
+    pub(crate) fn pivot(&self) -> Vec3 {
+        self.transform.apply_point(self.center)
+    }
+
+    /// The splats where the transform places them.
+    fn placed(&mut self) -> Splats<MainBackend> {
+        match &self.placed {
+            Some((transform, splats)) if *transform == self.transform => splats.clone(),
+            _ => {
+                let splats = self.transform.apply(self.splats.clone());
+                self.placed = Some((self.transform, splats.clone()));
+                splats
+            }
+        }
+    }
+}
+
+// Name of a layer loaded from an unnamed file, from the format of the file.
+fn unnamed_layer(index: usize, format: SplatFormat) -> String {
+    let extension = match format {
+        SplatFormat::Ply => "ply",
+        SplatFormat::Splat => "splat",
+        SplatFormat::Spz => "spz",
+        SplatFormat::Chunked => "bsplat",
+        SplatFormat::Compressed => "bsz",
+    };
+    format!("layer_{index}.{extension}")
+}
+
+type LoadedLayers = Vec<(CompositionLayer, Splats<MainBackend>)>;
+
+// Load splats from a picked file, or all layers of a picked composition zip. Only the first frame
+// of animated files is kept. `index` is the number of the first new layer, to name unnamed files.
+async fn load_layers(device: WgpuDevice, index: usize) -> anyhow::Result<LoadedLayers> {
+    let (name, mut reader) = rrfd::pick_named_file().await?;
+    let mut data = vec![];
+    reader.read_to_end(&mut data).await?;
+
+    if data.starts_with(b"PK") {
+        let vfs = BrushVfs::from_reader(std::io::Cursor::new(data)).await?;
+        let path = find_composition(&vfs)
+            .ok_or_else(|| anyhow::anyhow!("No composition file in the zip"))?;
+        return Ok(load_composition(&vfs, &path, device).await?);
+    }
+
+    let format = name
+        .as_deref()
+        .and_then(|name| SplatFormat::from_path(std::path::Path::new(name)))
+        .unwrap_or_else(|| SplatFormat::from_header(&data));
+    let stream = load_splats(format, std::io::Cursor::new(data), None, device);
+    let mut stream = std::pin::pin!(stream);
+    let mut splats = None;
+    while let Some(message) = stream.next().await {
+        let message = message?;
+        if message.meta.current_frame > 0 {
+            break;
+        }
+        splats = Some(message.splats);
+    }
+    let splats = splats.ok_or_else(|| anyhow::anyhow!("No splats in file"))?;
+    let layer = CompositionLayer {
+        file: name.unwrap_or_else(|| unnamed_layer(index, format)),
+        transform: LayerTransform::IDENTITY,
+    };
+    Ok(vec![(layer, splats)])
+}
+
+/// Several sets of splats shown together, each placed by its own transform.
+///
+/// The composition can be exported as one merged file, or as a zip of the file of each layer and a
+/// JSON description of where it's placed, which can be added back as layers.
+pub(crate) struct Composition {
+    pub(crate) layers: Vec<Layer>,
+    pub(crate) selected: usize,
+    loading: Option<Receiver<anyhow::Result<LoadedLayers>>>,
+    // Merged splats of the visible layers, until a layer changes.
+    composed: Option<Splats<MainBackend>>,
+}
+
+impl Composition {
+    /// Start a composition with the splats of the scene as the first layer.
+    pub(crate) fn new(splats: Splats<MainBackend>, ctx: &egui::Context) -> Self {
+        Self {
+            layers: vec![Layer::new(
+                "scene.ply".to_owned(),
+                LayerTransform::IDENTITY,
+                splats,
+                ctx,
+            )],
+            selected: 0,
+            loading: None,
+            composed: None,
+        }
+    }
+
+    /// Pick a splat file to add as a layer, or a composition zip to add all its layers.
+    pub(crate) fn add_file(&mut self, ctx: &egui::Context) {
+        let device = self.layers[0].splats.device();
+        let index = self.layers.len();
+        let (sender, receiver) = channel();
+        let ctx = ctx.clone();
+        tokio_wasm::task::spawn(async move {
+            let _ = sender.send(load_layers(device, index).await);
+            ctx.request_repaint();
+        });
+        self.loading = Some(receiver);
+    }
+
+    pub(crate) fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// Check for loaded layers and their centers. Returns true if the composition changed.
+    pub(crate) fn poll(&mut self, ctx: &egui::Context) -> bool {
+        let mut changed = false;
+        if let Some(Ok(result)) = self.loading.as_mut().map(Receiver::try_recv) {
+            self.loading = None;
+            match result {
+                Ok(layers) => {
+                    for (layer, splats) in layers {
+                        self.layers
+                            .push(Layer::new(layer.file, layer.transform, splats, ctx));
+                    }
+                    self.selected = self.layers.len() - 1;
+                    self.composed = None;
+                    changed = true;
+                }
+                Err(e) => {
+                    if !matches!(
+                        e.downcast_ref::<rrfd::PickFileError>(),
+                        Some(rrfd::PickFileError::NoFileSelected)
+                    ) {
+                        log::error!("Failed to load splats: {e}");
+                    }
+                }
+            }
+        }
+        for layer in &mut self.layers {
+            if let Some(Ok(center)) = layer.center_pending.as_mut().map(Receiver::try_recv) {
+                layer.center_pending = None;
+                layer.center = center;
+                // Only the gizmo moves, the splats stay as they are.
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Mark the composition as changed, after showing, hiding or moving layers.
+    pub(crate) fn invalidate(&mut self) {
+        self.composed = None;
+    }
+
+    pub(crate) fn remove_selected(&mut self) {
+        if self.layers.len() > 1 {
+            self.layers.remove(self.selected);
+            self.selected = self.selected.min(self.layers.len() - 1);
+            self.composed = None;
+        }
+    }
+
+    /// Whether anything was added to or moved in the scene.
+    pub(crate) fn is_edited(&self) -> bool {
+        self.layers.len() > 1
+            || self
+                .layers
+                .iter()
+                .any(|layer| !layer.visible || layer.transform != LayerTransform::IDENTITY)
+    }
+
+    /// All visible layers merged into one set of splats. Only layers which moved since the last
+    /// merge are transformed again.
+    pub(crate) fn splats(&mut self) -> Option<Splats<MainBackend>> {
+        if self.composed.is_none() {
+            self.composed = self
+                .layers
+                .iter_mut()
+                .filter(|layer| layer.visible)
+                .map(Layer::placed)
+                .reduce(Splats::merge);
+        }
+        self.composed.clone()
+    }
+
+    /// The splats of each visible layer as loaded, and where it's placed, to save the composition.
+    pub(crate) fn visible_layers(&self) -> LoadedLayers {
+        self.layers
+            .iter()
+            .filter(|layer| layer.visible)
+            .map(|layer| {
+                let layer_info = CompositionLayer {
+                    file: layer.name.clone(),
+                    transform: layer.transform,
+                };
+                (layer_info, layer.splats.clone())
+            })
+            .collect()
+    }
+
+    /// Draw the gizmo of the selected layer: arrows to move it along each axis, handles to rotate it
+    /// around each axis and a handle in the middle to scale it. Returns true if the layer changed.
+    pub(crate) fn gizmo(&mut self, ui: &egui::Ui, rect: Rect, camera: &Camera) -> bool {
+        let Some(layer) = self.layers.get_mut(self.selected) else {
+            return false;
+        };
+        if !layer.visible {
+            return false;
+        }
+
+        let to_screen = |point: Vec3| {
+            camera
+                .world_to_uv(point)
+                .map(|uv| rect.min + egui::vec2(uv.x, uv.y) * rect.size())
+        };
+        let pivot = layer.pivot();
+        let Some(pivot_screen) = to_screen(pivot) else {
+            return false;
+        };
+        let painter = ui.painter_at(rect);
+        let size = (pivot - camera.position).length() * GIZMO_SIZE;
+
+        // Screen direction a point moves in for a small change, to map drags back to that change.
+        let drag_amount = |response: &egui::Response, from: Vec3, to: Vec3| {
+            let (Some(a), Some(b)) = (to_screen(from), to_screen(to)) else {
+                return 0.0;
+            };
+            let screen_dir = b - a;
+            let len_sq = screen_dir.length_sq();
+            if len_sq < 1e-6 {
+                return 0.0;
+            }
+            response.drag_delta().dot(screen_dir) / len_sq
+        };
+        let handle = |id: (&str, usize), pos: egui::Pos2| {
+            let handle_rect = Rect::from_center_size(pos, egui::Vec2::splat(HANDLE_RADIUS * 3.0));
+            ui.interact(
+                handle_rect,
+                ui.id().with(("compose_handle", id)),
+                Sense::drag(),
+            )
+        };
+        let radius = |response: &egui::Response| {
+            if response.hovered() || response.dragged() {
+                HANDLE_RADIUS * 1.3
+            } else {
+                HANDLE_RADIUS
+            }
+        };
+
+        let mut changed = false;
+        for axis in 0..3 {
+            let dir = Vec3::AXES[axis];
+            let color = AXIS_COLORS[axis];
+
+            // Move along the axis.
+            let tip = pivot + dir * size;
+            if let Some(tip_screen) = to_screen(tip) {
+                painter.line_segment([pivot_screen, tip_screen], Stroke::new(2.0, color));
+                let response = handle(("move", axis), tip_screen);
+                painter.circle(
+                    tip_screen,
+                    radius(&response),
+                    color,
+                    Stroke::new(1.0, Color32::WHITE),
+                );
+                if response.dragged() {
+                    let step = size * 0.1;
+                    let delta = drag_amount(&response, tip, tip + dir * step) * step;
+                    if delta != 0.0 {
+                        layer.transform.translation += dir * delta;
+                        changed = true;
+                    }
+                }
+            }
+
+            // Rotate around the axis, with a handle between the other two axes.
+            let offset = (Vec3::AXES[(axis + 1) % 3] + Vec3::AXES[(axis + 2) % 3]) * size * 0.5;
+            let ring = pivot + offset;
+            if let Some(ring_screen) = to_screen(ring) {
+                let response = handle(("rotate", axis), ring_screen);
+                let r = radius(&response);
+                painter.rect(
+                    Rect::from_center_size(ring_screen, egui::Vec2::splat(r * 1.6)),
+                    2.0,
+                    color,
+                    Stroke::new(1.0, Color32::WHITE),
+                    egui::StrokeKind::Middle,
+                );
+                if response.dragged() {
+                    const STEP: f32 = 0.1;
+                    let turned = pivot + Quat::from_axis_angle(dir, STEP) * offset;
+                    let angle = drag_amount(&response, ring, turned) * STEP;
+                    if angle != 0.0 {
+                        layer
+                            .transform
+                            .rotate_around(pivot, Quat::from_axis_angle(dir, angle));
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        // Scale by dragging the middle up or down.
+        let response = handle(("scale", 0), pivot_screen);
+        painter.circle(
+            pivot_screen,
+            radius(&response),
+            Color32::WHITE,
+            Stroke::new(1.0, Color32::BLACK),
+        );
+        if response.dragged() {
+            let factor = (-response.drag_delta().y * 0.01).exp();
+            if factor != 1.0 {
+                layer.transform.scale_around(pivot, factor);
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.composed = None;
+        }
+        changed
+    }
+}
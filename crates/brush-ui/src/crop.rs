@@ -0,0 +1,219 @@
+use brush_render::{
+    MainBackend, camera::Camera, gaussian_splats::Splats, selection::select_in_box,
+};
+use egui::{Color32, Rect, Sense, Stroke};
+use glam::Vec3;
+use tokio::sync::oneshot::{Receiver, channel};
+use tokio_with_wasm::alias as tokio_wasm;
+
+// Fraction of splats on either side of each axis left out when fitting the box. Trained scenes
+// usually have some splats far out, which would make the box useless.
+const OUTLIER_FRACTION: f32 = 0.01;
+
+// Radius of the handles to drag the faces of the box, in screen points.
+const HANDLE_RADIUS: f32 = 6.0;
+
+const AXIS_COLORS: [Color32; 3] = [
+    Color32::from_rgb(230, 80, 80),
+    Color32::from_rgb(80, 200, 80),
+    Color32::from_rgb(80, 140, 255),
+];
+
+/// An axis aligned box in world space to crop splats to.
+///
+/// Splats outside of the box are hidden in the viewer, and removed on export if `apply_on_export` is set.
+pub(crate) struct CropBox {
+    pub(crate) min: Vec3,
+    pub(crate) max: Vec3,
+    pub(crate) apply_on_export: bool,
+    pending: Option<Receiver<(Vec3, Vec3)>>,
+}
+
+async fn splat_bounds(splats: Splats<MainBackend>) -> (Vec3, Vec3) {
+    let means = splats
+        .means
+        .val()
+        .into_data_async()
+        .await
+        .into_vec::<f32>()
+        .expect("Wrong type");
+
+    let mut min = Vec3::NEG_INFINITY;
+    let mut max = Vec3::INFINITY;
+    for axis in 0..3 {
+        let mut coords: Vec<f32> = means.iter().skip(axis).step_by(3).copied().collect();
+        if coords.is_empty() {
+            continue;
+        }
+        let last = (coords.len() - 1) as f32;
+        let lo = (last * OUTLIER_FRACTION) as usize;
+        let hi = (last * (1.0 - OUTLIER_FRACTION)) as usize;
+        min[axis] = *coords.select_nth_unstable_by(lo, f32::total_cmp).1;
+        max[axis] = *coords.select_nth_unstable_by(hi, f32::total_cmp).1;
+    }
+    (min, max)
+}
+
+impl CropBox {
+    /// Create a crop box, fit to the given splats.
+    pub(crate) fn new(splats: Splats<MainBackend>, ctx: &egui::Context) -> Self {
+        // Until the bounds are known this doesn't crop anything.
+        let mut crop = Self {
+            min: Vec3::NEG_INFINITY,
+            max: Vec3::INFINITY,
+            apply_on_export: true,
+            pending: None,
+        };
+        crop.fit(splats, ctx);
+        crop
+    }
+
+    /// Fit the box around the splats, ignoring outliers.
+    pub(crate) fn fit(&mut self, splats: Splats<MainBackend>, ctx: &egui::Context) {
+        let (sender, receiver) = channel();
+        let ctx = ctx.clone();
+        tokio_wasm::task::spawn(async move {
+            // If the crop box is gone, that's fine.
+            let _ = sender.send(splat_bounds(splats).await);
+            ctx.request_repaint();
+        });
+        self.pending = Some(receiver);
+    }
+
+    /// Check whether fitting the box finished. Returns true if the box changed.
+    pub(crate) fn poll(&mut self) -> bool {
+        let Some(pending) = self.pending.as_mut() else {
+            return false;
+        };
+        let Ok((min, max)) = pending.try_recv() else {
+            return false;
+        };
+        self.pending = None;
+        self.min = min;
+        self.max = max;
+        true
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.min.is_finite() && self.max.is_finite()
+    }
+
+    /// Hide splats outside of the box. This keeps all splats around, so is cheap to update.
+    pub(crate) fn cull(&self, splats: &Splats<MainBackend>) -> Splats<MainBackend> {
+        if !self.is_bounded() {
+            return splats.clone();
+        }
+        let outside = select_in_box(splats, self.min, self.max).bool_not();
+        // Opacities are stored pre-sigmoid, so this makes the splats fully transparent.
+        let raw_opacity = splats.raw_opacity.val().mask_fill(outside, -1e4);
+        Splats::from_tensor_data(
+            splats.means.val(),
+            splats.rotation.val(),
+            splats.log_scales.val(),
+            splats.sh_coeffs.val(),
+            raw_opacity,
+        )
+    }
+
+    /// Bounds to crop exported splats to, if any.
+    pub(crate) fn export_bounds(&self) -> Option<(Vec3, Vec3)> {
+        (self.apply_on_export && self.is_bounded()).then_some((self.min, self.max))
+    }
+
+    /// Draw the box, with a handle on each face to drag it. Returns true if the box changed.
+    pub(crate) fn gizmo(&mut self, ui: &egui::Ui, rect: Rect, camera: &Camera) -> bool {
+        if !self.is_bounded() {
+            return false;
+        }
+
+        let to_screen = |point: Vec3| {
+            camera
+                .world_to_uv(point)
+                .map(|uv| rect.min + egui::vec2(uv.x, uv.y) * rect.size())
+        };
+        let painter = ui.painter_at(rect);
+
+        let (min, max) = (self.min, self.max);
+        let corner = |i: usize| {
+            Vec3::select(
+                glam::BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0),
+                max,
+                min,
+            )
+        };
+        let edge_stroke = Stroke::new(1.5, Color32::from_white_alpha(180));
+        for i in 0..8 {
+            for axis in 0..3 {
+                let j = i | (1 << axis);
+                if j == i {
+                    continue;
+                }
+                if let (Some(a), Some(b)) = (to_screen(corner(i)), to_screen(corner(j))) {
+                    painter.line_segment([a, b], edge_stroke);
+                }
+            }
+        }
+
+        let center = (min + max) * 0.5;
+        let mut changed = false;
+
+        for axis in 0..3 {
+            for is_max in [false, true] {
+                let mut pos = center;
+                pos[axis] = if is_max { max[axis] } else { min[axis] };
+                let Some(screen_pos) = to_screen(pos) else {
+                    continue;
+                };
+
+                let id = ui.id().with(("crop_handle", axis, is_max));
+                let handle_rect =
+                    Rect::from_center_size(screen_pos, egui::Vec2::splat(HANDLE_RADIUS * 3.0));
+                let response = ui.interact(handle_rect, id, Sense::drag());
+
+                let active = response.hovered() || response.dragged();
+                let radius = if active {
+                    HANDLE_RADIUS * 1.3
+                } else {
+                    HANDLE_RADIUS
+                };
+                painter.circle(
+                    screen_pos,
+                    radius,
+                    AXIS_COLORS[axis],
+                    Stroke::new(1.0, Color32::WHITE),
+                );
+
+                if !response.dragged() {
+                    continue;
+                }
+
+                // Move the face by the part of the drag along the axis as seen on screen.
+                let mut step = Vec3::ZERO;
+                step[axis] = ((max[axis] - min[axis]) * 0.1).max(1e-3);
+                let Some(step_pos) = to_screen(pos + step) else {
+                    continue;
+                };
+                let screen_dir = step_pos - screen_pos;
+                let len_sq = screen_dir.length_sq();
+                if len_sq < 1e-6 {
+                    continue;
+                }
+                let delta = response.drag_delta().dot(screen_dir) / len_sq * step[axis];
+                if delta == 0.0 {
+                    continue;
+                }
+
+                // Don't let the box turn inside out.
+                const MIN_SIZE: f32 = 1e-3;
+                if is_max {
+                    self.max[axis] = (self.max[axis] + delta).max(self.min[axis] + MIN_SIZE);
+                } else {
+                    self.min[axis] = (self.min[axis] + delta).min(self.max[axis] - MIN_SIZE);
+                }
+                changed = true;
+            }
+        }
+
+        changed
+    }
+}
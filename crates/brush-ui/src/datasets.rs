@@ -250,6 +250,39 @@ impl AppPanel for DatasetPanel {
             }
         }
 
+        if !self.cur_dataset.residuals.is_empty() {
+            ui.collapsing("Calibration", |ui| {
+                egui::Grid::new("calibration_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for header in ["Camera", "Model", "Images", "Points", "Error (px)"] {
+                            ui.strong(header);
+                        }
+                        ui.strong("Without distortion")
+                            .on_hover_text("How far off points are as Brush sees the camera");
+                        ui.end_row();
+
+                        for camera in &self.cur_dataset.residuals {
+                            ui.label(camera.camera_id.to_string());
+                            ui.label(camera.model);
+                            ui.label(camera.num_images.to_string());
+                            ui.label(camera.num_observations.to_string());
+                            match camera.residuals {
+                                Some(stats) => ui
+                                    .label(format!("{:.2}", stats.mean))
+                                    .on_hover_text(format!(
+                                        "median {:.2}, 90% under {:.2}",
+                                        stats.median, stats.p90
+                                    )),
+                                None => ui.label("unsupported model"),
+                            };
+                            ui.label(format!("{:.2}", camera.pinhole.mean));
+                            ui.end_row();
+                        }
+                    });
+            });
+        }
+
         if process.is_loading() && process.is_training() {
             ui.label("Loading...");
         }
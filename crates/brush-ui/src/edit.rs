@@ -0,0 +1,658 @@
+use anyhow::Context;
+use brush_dataset::splat_export::SplatLabels;
+use brush_process::mesh::Mesh;
+use brush_render::{
+    MainBackend,
+    camera::Camera,
+    gaussian_splats::Splats,
+    selection::{SelectionShape, select_in_depth, select_in_volume, select_splats},
+    sh::rgb_to_sh,
+    volume::MeshVolume,
+};
+use burn::tensor::{Bool, Int, Tensor, TensorData};
+use burn_wgpu::WgpuDevice;
+use egui::{Color32, Pos2, Rect, Stroke};
+use glam::Vec2;
+use tokio::{
+    io::AsyncReadExt,
+    sync::oneshot::{Receiver, channel},
+};
+use tokio_with_wasm::alias as tokio_wasm;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SelectTool {
+    Rect,
+    Lasso,
+    Brush,
+    Pick,
+}
+
+impl SelectTool {
+    pub(crate) const ALL: [Self; 4] = [Self::Rect, Self::Lasso, Self::Brush, Self::Pick];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Self::Rect => "⬜ Box",
+            Self::Lasso => "➰ Lasso",
+            Self::Brush => "🖌 Brush",
+            Self::Pick => "👆 Pick",
+        }
+    }
+}
+
+/// Brushes that paint an edit onto the splats under the cursor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PaintBrush {
+    /// Lower the opacity.
+    Fade,
+    /// Blend the color towards a tint.
+    Tint,
+    /// Delete splats.
+    Erase,
+}
+
+impl PaintBrush {
+    pub(crate) const ALL: [Self; 3] = [Self::Fade, Self::Tint, Self::Erase];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Self::Fade => "🌫 Fade",
+            Self::Tint => "🎨 Tint",
+            Self::Erase => "⌫ Erase",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum EditTool {
+    Select(SelectTool),
+    Paint(PaintBrush),
+}
+
+// Nr. of edits that can be undone. Each undo step keeps a full copy of the splats alive.
+const MAX_UNDO: usize = 32;
+
+/// Select splats in screen space and delete or isolate them, or paint edits onto them.
+pub(crate) struct SplatEditor {
+    pub(crate) tool: EditTool,
+    /// Radius of the brush, pick and paint tools, in screen points.
+    pub(crate) brush_radius: f32,
+    /// How much each dab of the fade and tint brushes changes the splats, from 0 to 1.
+    pub(crate) paint_strength: f32,
+    pub(crate) tint: [f32; 3],
+    /// Only paint splats within this distance range from the camera.
+    pub(crate) depth_range: Option<(f32, f32)>,
+
+    /// Name to give the selected splats when labeling them.
+    pub(crate) label_name: String,
+
+    splats: Splats<MainBackend>,
+    // Label of each splat, an index into the label names. Label 0 is for unlabeled splats.
+    labels: Option<SplatLabeling>,
+    selection: Option<Tensor<MainBackend, 1, Bool>>,
+    // Splats with the selection highlighted.
+    display: Splats<MainBackend>,
+    undo: Vec<(Splats<MainBackend>, Option<SplatLabeling>)>,
+    stroke: Vec<Pos2>,
+    // Splats erased so far in the current stroke of the erase brush.
+    erased: Option<Tensor<MainBackend, 1, Bool>>,
+    pending: Option<Receiver<Option<(Splats<MainBackend>, Option<SplatLabeling>)>>>,
+    pending_selection: Option<Receiver<anyhow::Result<Tensor<MainBackend, 1, Bool>>>>,
+}
+
+/// Labels of splats, eg. to tell the objects in a scene apart.
+#[derive(Clone)]
+pub(crate) struct SplatLabeling {
+    /// Label of each splat, an index into `names`.
+    pub(crate) labels: Tensor<MainBackend, 1, Int>,
+    pub(crate) names: Vec<String>,
+}
+
+impl SplatLabeling {
+    /// Upload loaded labels, eg. of a ply file. Labels without a name are named by their index.
+    pub(crate) fn new(labels: &SplatLabels, device: &WgpuDevice) -> Self {
+        let mut names = labels.names.clone();
+        let count = labels
+            .labels
+            .iter()
+            .max()
+            .map_or(0, |&max| max as usize + 1);
+        for index in names.len()..count {
+            names.push(format!("label {index}"));
+        }
+        let values: Vec<i32> = labels.labels.iter().map(|&l| l as i32).collect();
+        Self {
+            labels: Tensor::from_data(TensorData::new(values, [labels.labels.len()]), device),
+            names,
+        }
+    }
+
+    /// Keep the labels of the splats where `keep` is true, like [`Splats::retain`].
+    pub(crate) async fn retain(self, keep: Tensor<MainBackend, 1, Bool>) -> Self {
+        let inds = keep.argwhere_async().await.squeeze(1);
+        Self {
+            labels: self.labels.select(0, inds),
+            names: self.names,
+        }
+    }
+
+    /// Read back the labels, for exporting them.
+    pub(crate) async fn read(&self) -> SplatLabels {
+        let labels = self
+            .labels
+            .clone()
+            .into_data_async()
+            .await
+            .convert::<i32>()
+            .into_vec::<i32>()
+            .expect("Wrong type")
+            .into_iter()
+            .map(|l| l as u32)
+            .collect();
+        SplatLabels {
+            labels,
+            names: self.names.clone(),
+        }
+    }
+}
+
+// Pick a mesh file, and select the splats inside of it.
+async fn select_in_mesh_file(
+    splats: Splats<MainBackend>,
+) -> anyhow::Result<Tensor<MainBackend, 1, Bool>> {
+    let mut reader = rrfd::pick_file().await?;
+    let mut data = vec![];
+    reader.read_to_end(&mut data).await?;
+    let mesh = Mesh::from_obj(&data)?;
+    let volume =
+        MeshVolume::new(&mesh.positions, &mesh.triangles).context("Mesh doesn't have any faces")?;
+    Ok(select_in_volume(&splats, &volume).await)
+}
+
+fn highlight(
+    splats: &Splats<MainBackend>,
+    selection: &Tensor<MainBackend, 1, Bool>,
+) -> Splats<MainBackend> {
+    let [n, coeffs, _] = splats.sh_coeffs.dims();
+    let device = splats.device();
+
+    // Replace the color of selected splats with a flat orange.
+    let selected = selection
+        .clone()
+        .float()
+        .reshape([n, 1, 1])
+        .repeat_dim(1, coeffs)
+        .repeat_dim(2, 3);
+    let color = rgb_to_sh(glam::vec3(1.0, 0.5, 0.0));
+    let color = Tensor::<MainBackend, 1>::from_floats(color.to_array(), &device)
+        .reshape([1, 1, 3])
+        .repeat_dim(0, n);
+    let tint = if coeffs > 1 {
+        Tensor::cat(vec![color, Tensor::zeros([n, coeffs - 1, 3], &device)], 1)
+    } else {
+        color
+    };
+    let sh_coeffs = splats.sh_coeffs.val() * (selected.clone().neg() + 1.0) + tint * selected;
+
+    Splats::from_tensor_data(
+        splats.means.val(),
+        splats.rotation.val(),
+        splats.log_scales.val(),
+        sh_coeffs,
+        splats.raw_opacity.val(),
+    )
+}
+
+impl SplatEditor {
+    pub(crate) fn new(splats: Splats<MainBackend>, labels: Option<SplatLabeling>) -> Self {
+        Self {
+            tool: EditTool::Select(SelectTool::Rect),
+            brush_radius: 20.0,
+            paint_strength: 0.3,
+            tint: [1.0, 1.0, 1.0],
+            depth_range: None,
+            label_name: String::new(),
+            display: splats.clone(),
+            splats,
+            labels,
+            selection: None,
+            undo: vec![],
+            stroke: vec![],
+            erased: None,
+            pending: None,
+            pending_selection: None,
+        }
+    }
+
+    /// The edited splats.
+    pub(crate) fn splats(&self) -> &Splats<MainBackend> {
+        &self.splats
+    }
+
+    /// The edited splats, with the current selection highlighted.
+    pub(crate) fn display_splats(&self) -> Splats<MainBackend> {
+        self.display.clone()
+    }
+
+    /// Labels of the edited splats, if any were labeled.
+    pub(crate) fn labels(&self) -> Option<&SplatLabeling> {
+        self.labels.as_ref()
+    }
+
+    /// Label the selected splats with `label_name`.
+    pub(crate) fn label_selected(&mut self) {
+        let Some(selection) = self.selection.clone() else {
+            return;
+        };
+        let name = self.label_name.trim();
+        if name.is_empty() || self.pending.is_some() {
+            return;
+        }
+        self.push_undo();
+
+        let mut labeling = self.labels.take().unwrap_or_else(|| SplatLabeling {
+            labels: Tensor::zeros([self.splats.num_splats() as usize], &self.splats.device()),
+            names: vec!["unlabeled".to_owned()],
+        });
+        let index = match labeling.names.iter().position(|n| n == name) {
+            Some(index) => index,
+            None => {
+                labeling.names.push(name.to_owned());
+                labeling.names.len() - 1
+            }
+        };
+        labeling.labels = labeling.labels.mask_fill(selection, index as i32);
+        self.labels = Some(labeling);
+        self.set_selection(None);
+    }
+
+    pub(crate) fn has_selection(&self) -> bool {
+        self.selection.is_some()
+    }
+
+    pub(crate) fn can_undo(&self) -> bool {
+        !self.undo.is_empty() && self.pending.is_none()
+    }
+
+    fn set_selection(&mut self, selection: Option<Tensor<MainBackend, 1, Bool>>) {
+        self.display = match &selection {
+            Some(selection) => highlight(&self.splats, selection),
+            None => self.splats.clone(),
+        };
+        self.selection = selection;
+    }
+
+    pub(crate) fn clear_selection(&mut self) {
+        self.set_selection(None);
+    }
+
+    pub(crate) fn undo(&mut self) {
+        if !self.can_undo() {
+            return;
+        }
+        if let Some((splats, labels)) = self.undo.pop() {
+            self.splats = splats;
+            self.labels = labels;
+            self.set_selection(None);
+        }
+    }
+
+    fn push_undo(&mut self) {
+        self.undo.push((self.splats.clone(), self.labels.clone()));
+        if self.undo.len() > MAX_UNDO {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Select the splats inside of a mesh picked from an OBJ file. Deleting them carves the mesh
+    /// out of the scene, isolating them clips the scene to the mesh.
+    ///
+    /// Flat meshes, like the footprint of a building, are extruded through the whole scene.
+    pub(crate) fn select_in_mesh(&mut self, add_to_selection: bool, ctx: &egui::Context) {
+        if self.pending_selection.is_some() {
+            return;
+        }
+        let (sender, receiver) = channel();
+        let splats = self.splats.clone();
+        let current = self.selection.clone().filter(|_| add_to_selection);
+        let ctx = ctx.clone();
+        tokio_wasm::task::spawn(async move {
+            let result = select_in_mesh_file(splats)
+                .await
+                .map(|selection| match current {
+                    Some(current) => (selection.float() + current.float()).greater_elem(0.5),
+                    None => selection,
+                });
+            // If the editor is gone, that's fine.
+            let _ = sender.send(result);
+            ctx.request_repaint();
+        });
+        self.pending_selection = Some(receiver);
+    }
+
+    pub(crate) fn is_selecting_in_mesh(&self) -> bool {
+        self.pending_selection.is_some()
+    }
+
+    /// Delete the selected splats, or with `isolate`, everything _but_ the selected splats.
+    pub(crate) fn remove_selected(&mut self, isolate: bool, ctx: &egui::Context) {
+        let Some(selection) = self.selection.clone() else {
+            return;
+        };
+        let keep = if isolate {
+            selection
+        } else {
+            selection.bool_not()
+        };
+        self.retain(keep, ctx);
+    }
+
+    fn retain(&mut self, keep: Tensor<MainBackend, 1, Bool>, ctx: &egui::Context) {
+        if self.pending.is_some() {
+            return;
+        }
+        let (sender, receiver) = channel();
+        let splats = self.splats.clone();
+        let labels = self.labels.clone();
+        let ctx = ctx.clone();
+        tokio_wasm::task::spawn(async move {
+            let result = match splats.retain(keep.clone()).await {
+                Some(splats) => {
+                    let labels = match labels {
+                        Some(labels) => Some(labels.retain(keep).await),
+                        None => None,
+                    };
+                    Some((splats, labels))
+                }
+                None => None,
+            };
+            // If the editor is gone, that's fine.
+            let _ = sender.send(result);
+            ctx.request_repaint();
+        });
+        self.pending = Some(receiver);
+    }
+
+    /// Check whether an edit finished. Returns true if the splats changed.
+    pub(crate) fn poll(&mut self) -> bool {
+        let selected = self.poll_selection();
+
+        let Some(pending) = self.pending.as_mut() else {
+            return selected;
+        };
+        let Ok(result) = pending.try_recv() else {
+            return selected;
+        };
+        self.pending = None;
+
+        match result {
+            Some((splats, labels)) => {
+                self.push_undo();
+                self.splats = splats;
+                self.labels = labels;
+            }
+            None => log::warn!("Can't remove all splats"),
+        }
+        self.set_selection(None);
+        true
+    }
+
+    // Check whether selecting splats in a mesh finished. Returns true if the selection changed.
+    fn poll_selection(&mut self) -> bool {
+        let Some(pending) = self.pending_selection.as_mut() else {
+            return false;
+        };
+        let Ok(result) = pending.try_recv() else {
+            return false;
+        };
+        self.pending_selection = None;
+        match result {
+            Ok(selection) => {
+                self.set_selection(Some(selection));
+                true
+            }
+            Err(e) => {
+                log::error!("Failed to select splats in mesh: {e:#}");
+                false
+            }
+        }
+    }
+
+    /// Handle selection or painting input on the viewport. Returns true if the displayed splats
+    /// changed.
+    pub(crate) fn handle_input(
+        &mut self,
+        response: &egui::Response,
+        rect: Rect,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        add_to_selection: bool,
+    ) -> bool {
+        match self.tool {
+            EditTool::Select(tool) => {
+                self.select_input(tool, response, rect, camera, img_size, add_to_selection)
+            }
+            EditTool::Paint(brush) => self.paint_input(brush, response, rect, camera, img_size),
+        }
+    }
+
+    fn paint_input(
+        &mut self,
+        brush: PaintBrush,
+        response: &egui::Response,
+        rect: Rect,
+        camera: &Camera,
+        img_size: glam::UVec2,
+    ) -> bool {
+        let primary = egui::PointerButton::Primary;
+        if self.pending.is_some() {
+            return false;
+        }
+
+        if response.drag_started_by(primary) {
+            self.stroke.clear();
+            self.erased = None;
+            self.set_selection(None);
+            if brush != PaintBrush::Erase {
+                self.push_undo();
+            }
+        }
+
+        let mut changed = false;
+        if response.dragged_by(primary) {
+            if let Some(pos) = response.interact_pointer_pos() {
+                // Space out dabs by a fraction of the radius, so painting doesn't depend on the
+                // frame rate.
+                let spacing = self.brush_radius * 0.25;
+                let far_enough = self
+                    .stroke
+                    .last()
+                    .is_none_or(|last| last.distance(pos) >= spacing);
+                if far_enough {
+                    self.stroke.push(pos);
+                    self.dab(brush, pos, rect, camera, img_size);
+                    changed = true;
+                }
+            }
+        }
+
+        if response.drag_stopped_by(primary) {
+            self.stroke.clear();
+            if let Some(erased) = self.erased.take() {
+                self.retain(erased.bool_not(), &response.ctx);
+            }
+        }
+        changed
+    }
+
+    fn dab(
+        &mut self,
+        brush: PaintBrush,
+        pos: Pos2,
+        rect: Rect,
+        camera: &Camera,
+        img_size: glam::UVec2,
+    ) {
+        let px_scale = img_size.x as f32 / rect.width().max(1.0);
+        let point = (pos - rect.min) * px_scale;
+        let shape = SelectionShape::Brush {
+            points: vec![Vec2::new(point.x, point.y)],
+            radius: self.brush_radius * px_scale,
+        };
+        let mut hit = select_splats(&self.splats, camera, img_size, &shape);
+        if let Some((near, far)) = self.depth_range {
+            let in_range = select_in_depth(&self.splats, camera, near, far);
+            hit = (hit.float() * in_range.float()).greater_elem(0.5);
+        }
+
+        match brush {
+            PaintBrush::Fade => {
+                self.splats = self
+                    .splats
+                    .clone()
+                    .scale_opacity(&hit, 1.0 - self.paint_strength);
+                self.display = self.splats.clone();
+            }
+            PaintBrush::Tint => {
+                let color = glam::Vec3::from_array(self.tint);
+                self.splats = self
+                    .splats
+                    .clone()
+                    .recolor(&hit, color, self.paint_strength);
+                self.display = self.splats.clone();
+            }
+            PaintBrush::Erase => {
+                // Only hide the splats while painting, they are removed when the stroke ends.
+                let erased = match self.erased.take() {
+                    Some(erased) => (erased.float() + hit.float()).greater_elem(0.5),
+                    None => hit,
+                };
+                self.display = self.splats.clone().scale_opacity(&erased, 0.0);
+                self.erased = Some(erased);
+            }
+        }
+    }
+
+    fn select_input(
+        &mut self,
+        tool: SelectTool,
+        response: &egui::Response,
+        rect: Rect,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        add_to_selection: bool,
+    ) -> bool {
+        let px_scale = img_size.x as f32 / rect.width().max(1.0);
+        let to_px = |p: Pos2| {
+            let p = (p - rect.min) * px_scale;
+            Vec2::new(p.x, p.y)
+        };
+
+        let pointer = response.interact_pointer_pos();
+        let primary = egui::PointerButton::Primary;
+
+        let shape = if tool == SelectTool::Pick {
+            match pointer {
+                Some(pos) if response.clicked() => Some(SelectionShape::Pick {
+                    point: to_px(pos),
+                    radius: self.brush_radius * px_scale,
+                }),
+                _ => None,
+            }
+        } else {
+            if response.drag_started_by(primary) {
+                self.stroke.clear();
+            }
+            if response.dragged_by(primary) {
+                if let Some(pos) = pointer {
+                    if self.stroke.last() != Some(&pos) {
+                        self.stroke.push(pos);
+                    }
+                }
+            }
+            if response.drag_stopped_by(primary) && !self.stroke.is_empty() {
+                let points: Vec<_> = self.stroke.drain(..).map(to_px).collect();
+                match tool {
+                    SelectTool::Rect => Some(SelectionShape::Rect {
+                        min: points[0],
+                        max: points[points.len() - 1],
+                    }),
+                    SelectTool::Lasso if points.len() >= 3 => Some(SelectionShape::Lasso(points)),
+                    SelectTool::Brush => Some(SelectionShape::Brush {
+                        points,
+                        radius: self.brush_radius * px_scale,
+                    }),
+                    _ => None,
+                }
+            } else {
+                None
+            }
+        };
+
+        let Some(shape) = shape else {
+            return false;
+        };
+
+        let mut selection = select_splats(&self.splats, camera, img_size, &shape);
+        if add_to_selection {
+            if let Some(current) = &self.selection {
+                selection = (selection.float() + current.clone().float()).greater_elem(0.5);
+            }
+        }
+        self.set_selection(Some(selection));
+        true
+    }
+
+    /// Draw the selection shape while it's being drawn.
+    pub(crate) fn draw_overlay(&self, painter: &egui::Painter) {
+        let stroke = Stroke::new(1.5, Color32::from_rgb(255, 160, 0));
+        let fill = Color32::from_rgba_unmultiplied(255, 160, 0, 30);
+
+        if let EditTool::Paint(_) = self.tool {
+            // Show the brush under the cursor.
+            if let Some(pos) = painter.ctx().pointer_hover_pos() {
+                if painter.clip_rect().contains(pos) {
+                    painter.circle_stroke(pos, self.brush_radius, stroke);
+                }
+            }
+        }
+
+        let EditTool::Select(tool) = self.tool else {
+            self.draw_pending(painter);
+            return;
+        };
+        match (tool, self.stroke.first(), self.stroke.last()) {
+            (SelectTool::Rect, Some(&first), Some(&last)) => {
+                painter.rect(
+                    Rect::from_two_pos(first, last),
+                    0.0,
+                    fill,
+                    stroke,
+                    egui::StrokeKind::Middle,
+                );
+            }
+            (SelectTool::Lasso, Some(_), Some(_)) => {
+                painter.add(egui::Shape::closed_line(self.stroke.clone(), stroke));
+            }
+            (SelectTool::Brush, Some(_), Some(_)) => {
+                for &p in &self.stroke {
+                    painter.circle_filled(p, self.brush_radius, fill);
+                }
+            }
+            _ => {}
+        }
+        self.draw_pending(painter);
+    }
+
+    fn draw_pending(&self, painter: &egui::Painter) {
+        if self.pending.is_some() {
+            painter.text(
+                painter.clip_rect().left_top() + egui::vec2(10.0, 10.0),
+                egui::Align2::LEFT_TOP,
+                "Applying edit...",
+                egui::FontId::default(),
+                Color32::WHITE,
+            );
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use brush_dataset::{scene_transform::SceneTransform, splat_export};
+use brush_render::{MainBackend, gaussian_splats::Splats, selection::select_in_box};
+use glam::Vec3;
+use rrfd::PickFileError;
+use tokio::sync::watch;
+use tokio_with_wasm::alias as tokio_wasm;
+use web_time::{Duration, Instant};
+
+use crate::{app::ExportSettings, edit::SplatLabeling};
+
+// How long to show that an export is done.
+const SHOW_DONE_FOR: Duration = Duration::from_secs(4);
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ExportStatus {
+    /// Working on `stage`, which starts at `progress` of the whole export.
+    Running {
+        stage: &'static str,
+        progress: f32,
+    },
+    Done {
+        num_splats: u32,
+    },
+    /// No file was picked to save to.
+    Cancelled,
+    Failed(String),
+}
+
+// The export, reporting its progress to `status`.
+async fn export_ply(
+    splats: Splats<MainBackend>,
+    transform: SceneTransform,
+    crop: Option<(Vec3, Vec3)>,
+    labels: Option<SplatLabeling>,
+    settings: ExportSettings,
+    status: &watch::Sender<ExportStatus>,
+    ctx: &egui::Context,
+) -> ExportStatus {
+    let set_stage = |stage, progress| {
+        let _ = status.send(ExportStatus::Running { stage, progress });
+        ctx.request_repaint();
+    };
+
+    // Crop in the frame the box was drawn in, before mapping the splats back.
+    let (splats, labels) = if let Some((min, max)) = crop {
+        set_stage("Cropping", 0.0);
+        let keep = select_in_box(&splats, min, max);
+        let Some(splats) = splats.retain(keep.clone()).await else {
+            return ExportStatus::Failed("No splats left to export after cropping".to_owned());
+        };
+        let labels = match labels {
+            Some(labels) => Some(labels.retain(keep).await),
+            None => None,
+        };
+        (splats, labels)
+    } else {
+        (splats, labels)
+    };
+    let num_splats = splats.num_splats();
+    // There are no views to measure how much each splat covers here.
+    let scores = settings.progressive.then(|| splats.importance());
+
+    set_stage("Writing ply", 0.2);
+    // Give the UI a frame to show the progress, this is where it stalls on the web.
+    tokio_wasm::task::yield_now().await;
+    let splats = transform.transform_splats(splats);
+    let labels = match &labels {
+        Some(labels) => Some(labels.read().await),
+        None => None,
+    };
+    let data = match scores {
+        Some(scores) => {
+            splat_export::splat_to_progressive_ply(splats, scores, None, labels.as_ref())
+                .await
+                .map(|(data, _)| data)
+        }
+        None => splat_export::splat_to_ply_with(splats, None, labels.as_ref()).await,
+    };
+    let data = match data {
+        Ok(data) => data,
+        Err(e) => return ExportStatus::Failed(format!("Failed to serialize file: {e}")),
+    };
+    let data = match &settings.passphrase {
+        Some(passphrase) => match brush_vfs::encryption::encrypt(&data, passphrase) {
+            Ok(data) => data,
+            Err(e) => return ExportStatus::Failed(format!("Failed to encrypt file: {e}")),
+        },
+        None => data,
+    };
+
+    set_stage("Saving", 0.8);
+    match rrfd::save_file("export.ply", data).await {
+        Ok(()) => ExportStatus::Done { num_splats },
+        Err(PickFileError::NoFileSelected) => ExportStatus::Cancelled,
+        Err(e) => ExportStatus::Failed(format!("Failed to save file: {e}")),
+    }
+}
+
+/// An export of splats to a ply file, running in the background.
+pub(crate) struct ExportJob {
+    status: watch::Receiver<ExportStatus>,
+    task: tokio_wasm::task::JoinHandle<()>,
+    finished_at: Option<Instant>,
+}
+
+impl ExportJob {
+    /// Export `splats`, cropped to `crop` and then mapped by `transform`.
+    pub(crate) fn spawn(
+        splats: Splats<MainBackend>,
+        transform: SceneTransform,
+        crop: Option<(Vec3, Vec3)>,
+        labels: Option<SplatLabeling>,
+        settings: ExportSettings,
+        ctx: &egui::Context,
+    ) -> Self {
+        let (sender, receiver) = watch::channel(ExportStatus::Running {
+            stage: "Starting",
+            progress: 0.0,
+        });
+        let ctx = ctx.clone();
+        let task = tokio_wasm::task::spawn(async move {
+            let result = export_ply(splats, transform, crop, labels, settings, &sender, &ctx).await;
+            if let ExportStatus::Failed(e) = &result {
+                log::error!("{e}");
+            }
+            let _ = sender.send(result);
+            ctx.request_repaint();
+        });
+        Self {
+            status: receiver,
+            task,
+            finished_at: None,
+        }
+    }
+
+    pub(crate) fn status(&self) -> ExportStatus {
+        self.status.borrow().clone()
+    }
+
+    /// Stop the export. The file isn't written if it wasn't yet.
+    pub(crate) fn cancel(&self) {
+        self.task.abort();
+    }
+
+    /// Whether the job is done showing, after it finished a while ago or was cancelled. Errors
+    /// show until they're dismissed.
+    pub(crate) fn poll(&mut self) -> bool {
+        match self.status() {
+            ExportStatus::Running { .. } | ExportStatus::Failed(_) => false,
+            ExportStatus::Cancelled => true,
+            ExportStatus::Done { .. } => {
+                let finished_at = *self.finished_at.get_or_insert_with(Instant::now);
+                finished_at.elapsed() > SHOW_DONE_FOR
+            }
+        }
+    }
+}
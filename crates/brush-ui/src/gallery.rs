@@ -0,0 +1,211 @@
+use brush_process::config::ProcessArgs;
+use brush_vfs::{DataSource, resolve_url};
+use egui::{RichText, Ui};
+use serde::Deserialize;
+use tokio::sync::oneshot::{Receiver, channel};
+use tokio_with_wasm::alias as tokio_wasm;
+
+use crate::{BrushUiProcess, panels::AppPanel};
+
+/// A scene listed in a gallery index.
+#[derive(Debug, Clone, Deserialize)]
+struct GalleryScene {
+    name: String,
+    /// Url of a ply or zip file. Relative urls are relative to the index.
+    url: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    author: Option<String>,
+    #[serde(default)]
+    size_mb: Option<f32>,
+}
+
+/// A static JSON file listing scenes, as `{ "scenes": [{ "name": ..., "url": ... }] }`.
+#[derive(Debug, Deserialize)]
+struct GalleryIndex {
+    scenes: Vec<GalleryScene>,
+}
+
+/// Resolve a scene url relative to the url of the index it's listed in.
+fn scene_url(index_url: &str, url: &str) -> String {
+    if url.contains("://") || url.starts_with('/') {
+        return url.to_owned();
+    }
+    let index_url = resolve_url(index_url);
+    match index_url.rsplit_once('/') {
+        Some((base, _)) => format!("{base}/{url}"),
+        None => url.to_owned(),
+    }
+}
+
+async fn fetch_index(url: String) -> anyhow::Result<Vec<GalleryScene>> {
+    let data = reqwest::get(resolve_url(&url))
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let index: GalleryIndex = serde_json::from_slice(&data)?;
+    Ok(index
+        .scenes
+        .into_iter()
+        .map(|scene| GalleryScene {
+            url: scene_url(&url, &scene.url),
+            ..scene
+        })
+        .collect())
+}
+
+fn is_cached(url: &str) -> bool {
+    #[cfg(not(target_family = "wasm"))]
+    {
+        brush_vfs::cache::cache_path(url).is_some_and(|path| path.is_file())
+    }
+    #[cfg(target_family = "wasm")]
+    {
+        let _ = url;
+        false
+    }
+}
+
+/// Browse example scenes from a gallery index, to have something to look at without any data
+/// of your own.
+pub struct GalleryPanel {
+    index_url: String,
+    scenes: Vec<GalleryScene>,
+    fetching: Option<Receiver<anyhow::Result<Vec<GalleryScene>>>>,
+    error: Option<String>,
+    fetched: bool,
+}
+
+impl GalleryPanel {
+    pub(crate) fn new() -> Self {
+        // On the web, look for a gallery next to the app.
+        let index_url = if cfg!(target_family = "wasm") {
+            "/gallery.json".to_owned()
+        } else {
+            String::new()
+        };
+        Self {
+            index_url,
+            scenes: vec![],
+            fetching: None,
+            error: None,
+            fetched: false,
+        }
+    }
+
+    fn fetch(&mut self, ctx: &egui::Context) {
+        let (sender, receiver) = channel();
+        let url = self.index_url.trim().to_owned();
+        let ctx = ctx.clone();
+        tokio_wasm::task::spawn(async move {
+            let _ = sender.send(fetch_index(url).await);
+            ctx.request_repaint();
+        });
+        self.fetching = Some(receiver);
+        self.error = None;
+        self.fetched = true;
+    }
+
+    fn poll_fetch(&mut self) {
+        let Some(fetching) = self.fetching.as_mut() else {
+            return;
+        };
+        let Ok(result) = fetching.try_recv() else {
+            return;
+        };
+        self.fetching = None;
+        match result {
+            Ok(scenes) => self.scenes = scenes,
+            Err(e) => self.error = Some(format!("Failed to load gallery: {e}")),
+        }
+    }
+
+    fn open_scene(scene: &GalleryScene, process: &dyn BrushUiProcess) {
+        // Scenes are meant to be explored right away, so datasets train with the default settings.
+        let (sender, receiver) = channel();
+        let _ = sender.send(ProcessArgs::default());
+        process.start_new_process(DataSource::CachedUrl(scene.url.clone()), receiver);
+    }
+}
+
+impl AppPanel for GalleryPanel {
+    fn title(&self) -> String {
+        "Gallery".to_owned()
+    }
+
+    fn ui(&mut self, ui: &mut Ui, process: &dyn BrushUiProcess) {
+        self.poll_fetch();
+
+        ui.horizontal(|ui| {
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.index_url)
+                    .hint_text("Gallery index url, eg. example.com/gallery.json")
+                    .desired_width(ui.available_width() - 60.0),
+            );
+            let enter = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            let can_fetch = self.fetching.is_none() && !self.index_url.trim().is_empty();
+            if can_fetch && (ui.button("Browse").clicked() || enter) {
+                self.fetch(ui.ctx());
+            }
+        });
+
+        // Load the gallery right away if there's a default one.
+        if !self.fetched && !self.index_url.is_empty() {
+            self.fetch(ui.ctx());
+        }
+
+        if self.fetching.is_some() {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Loading gallery...");
+            });
+        }
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+
+        ui.add_space(6.0);
+
+        let loading = process.is_loading();
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (index, scene) in self.scenes.iter().enumerate() {
+                ui.push_id(index, |ui| {
+                    egui::Frame::group(ui.style()).show(ui, |ui| {
+                        ui.set_width(ui.available_width());
+                        ui.horizontal(|ui| {
+                            ui.label(RichText::new(&scene.name).strong());
+                            if is_cached(&scene.url) {
+                                ui.label("💾")
+                                    .on_hover_text("Downloaded before, opens offline");
+                            }
+                        });
+                        if !scene.description.is_empty() {
+                            ui.label(&scene.description);
+                        }
+
+                        ui.horizontal(|ui| {
+                            if ui
+                                .add_enabled(!loading, egui::Button::new("Open"))
+                                .clicked()
+                            {
+                                Self::open_scene(scene, process);
+                            }
+                            let mut info = vec![];
+                            if let Some(author) = &scene.author {
+                                info.push(format!("by {author}"));
+                            }
+                            if let Some(size) = scene.size_mb {
+                                info.push(format!("{size:.0} MB"));
+                            }
+                            if !info.is_empty() {
+                                ui.weak(info.join(", "));
+                            }
+                        });
+                    });
+                });
+            }
+        });
+    }
+}
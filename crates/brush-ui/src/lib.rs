@@ -6,9 +6,9 @@ pub mod camera_controls;
 
 use std::sync::Arc;
 
-use app::CameraSettings;
+use app::{CameraSettings, ExportSettings, MeasureScale, ViewSettings};
 use brush_dataset::scene::SceneView;
-use brush_process::{config::ProcessArgs, message::ProcessMessage};
+use brush_process::{config::ProcessArgs, control::TrainCommand, message::ProcessMessage};
 use brush_render::camera::Camera;
 use brush_vfs::DataSource;
 use burn_wgpu::WgpuDevice;
@@ -18,11 +18,23 @@ use glam::Vec3;
 use tokio::sync::oneshot::Receiver;
 use wgpu::{Adapter, Features};
 
+mod camera_path;
+mod compose;
+mod crop;
 mod datasets;
+mod edit;
+mod export;
+mod gallery;
+mod measure;
 mod panels;
 mod scene;
 mod settings;
 mod stats;
+mod stills;
+mod wind;
+mod wizard;
+#[cfg(all(feature = "xr", not(target_family = "wasm")))]
+pub mod xr;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UiMode {
@@ -44,10 +56,21 @@ pub trait BrushUiProcess {
     fn model_local_to_world(&self) -> glam::Affine3A;
     fn selected_view(&self) -> Option<SceneView>;
     fn set_train_paused(&self, paused: bool);
+    /// Change the running training process, if there is one.
+    fn send_train_command(&self, command: TrainCommand);
     fn get_cam_settings(&self) -> CameraSettings;
     fn set_cam_settings(&self, settings: CameraSettings);
+    fn get_view_settings(&self) -> ViewSettings;
+    fn set_view_settings(&self, settings: ViewSettings);
+    fn get_export_settings(&self) -> ExportSettings;
+    fn set_export_settings(&self, settings: ExportSettings);
+    /// Scale of measurements of the current scene, as last set for the same source.
+    fn get_measure_scale(&self) -> MeasureScale;
+    fn set_measure_scale(&self, scale: MeasureScale);
     fn focus_view(&self, view: &SceneView);
     fn set_model_up(&self, up: Vec3);
+    /// Move the view to the given world space camera.
+    fn set_camera(&self, camera: &Camera);
     fn start_new_process(&self, source: DataSource, args: Receiver<ProcessArgs>);
     fn try_recv_message(&self) -> Option<anyhow::Result<ProcessMessage>>;
     fn connect_device(&self, device: WgpuDevice, ctx: egui::Context);
@@ -0,0 +1,262 @@
+use crate::app::MeasureScale;
+use brush_render::{
+    MainBackend,
+    camera::Camera,
+    gaussian_splats::Splats,
+    picking::{PickHit, pick},
+};
+use egui::{Color32, Rect, Stroke};
+use glam::{UVec2, Vec3};
+use tokio::sync::oneshot::{Receiver, channel};
+use tokio_with_wasm::alias as tokio_wasm;
+
+const POINT_RADIUS: f32 = 4.0;
+const LINE_COLOR: Color32 = Color32::from_rgb(255, 200, 40);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MeasureMode {
+    Distance,
+    Area,
+}
+
+impl MeasureMode {
+    pub(crate) const ALL: [Self; 2] = [Self::Distance, Self::Area];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Self::Distance => "📏 Distance",
+            Self::Area => "⬠ Area",
+        }
+    }
+}
+
+// Length of the path through the points, in order.
+fn path_length(points: &[Vec3]) -> f32 {
+    points.windows(2).map(|w| w[0].distance(w[1])).sum()
+}
+
+// Area of the polygon through the points. Points of a scan are hardly ever exactly on a plane, so
+// this is the area of the polygon projected on the plane it's closest to.
+fn polygon_area(points: &[Vec3]) -> f32 {
+    let Some(&origin) = points.first() else {
+        return 0.0;
+    };
+    let doubled: Vec3 = points
+        .windows(2)
+        .map(|w| (w[0] - origin).cross(w[1] - origin))
+        .sum();
+    doubled.length() * 0.5
+}
+
+/// Distances and areas between points picked on the splats.
+///
+/// Scenes are in arbitrary units, so the measurements are multiplied by `scale` to get real world
+/// units, as set from the known length of something in the scene. See [`Self::measure_scale`] to
+/// keep it for the scene.
+pub(crate) struct Measurement {
+    pub(crate) mode: MeasureMode,
+    pub(crate) scale: f32,
+    pub(crate) unit: String,
+    // Real world length of the measured path, to derive the scale from.
+    pub(crate) known_length: f32,
+    points: Vec<Vec3>,
+    pending: Option<Receiver<Option<PickHit>>>,
+}
+
+impl Measurement {
+    pub(crate) fn new(scale: MeasureScale) -> Self {
+        Self {
+            mode: MeasureMode::Distance,
+            scale: scale.scale,
+            unit: scale.unit,
+            known_length: 1.0,
+            points: vec![],
+            pending: None,
+        }
+    }
+
+    /// Pick a point on the splats under `pixel` of a render of `size`, to add once it's found.
+    pub(crate) fn pick_point(
+        &mut self,
+        splats: Splats<MainBackend>,
+        camera: &Camera,
+        size: UVec2,
+        pixel: glam::Vec2,
+        ctx: &egui::Context,
+    ) {
+        let (sender, receiver) = channel();
+        let camera = camera.clone();
+        let ctx = ctx.clone();
+        tokio_wasm::task::spawn(async move {
+            let _ = sender.send(pick(&splats, &camera, size, pixel).await);
+            ctx.request_repaint();
+        });
+        self.pending = Some(receiver);
+    }
+
+    /// Check for a picked point. Clicks on empty space don't add a point.
+    pub(crate) fn poll(&mut self) {
+        let Some(pending) = self.pending.as_mut() else {
+            return;
+        };
+        let Ok(hit) = pending.try_recv() else {
+            return;
+        };
+        self.pending = None;
+        if let Some(hit) = hit {
+            self.points.push(hit.point);
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    pub(crate) fn undo(&mut self) {
+        self.points.pop();
+    }
+
+    pub(crate) fn num_points(&self) -> usize {
+        self.points.len()
+    }
+
+    pub(crate) fn measure_scale(&self) -> MeasureScale {
+        MeasureScale {
+            scale: self.scale,
+            unit: self.unit.clone(),
+        }
+    }
+
+    /// Set the scale so the measured path is `known_length` long.
+    pub(crate) fn calibrate(&mut self) {
+        let length = path_length(&self.points);
+        if length > 0.0 {
+            self.scale = self.known_length / length;
+        }
+    }
+
+    /// The measurement in real world units, as text.
+    pub(crate) fn result(&self) -> String {
+        match self.mode {
+            MeasureMode::Distance => {
+                format!(
+                    "{:.3} {}",
+                    path_length(&self.points) * self.scale,
+                    self.unit
+                )
+            }
+            MeasureMode::Area => {
+                let area = polygon_area(&self.points) * self.scale * self.scale;
+                format!("{area:.3} {}²", self.unit)
+            }
+        }
+    }
+
+    /// Draw the picked points, and the path or polygon through them.
+    pub(crate) fn draw_overlay(&self, painter: &egui::Painter, rect: Rect, camera: &Camera) {
+        let to_screen = |point: Vec3| {
+            camera
+                .world_to_uv(point)
+                .map(|uv| rect.min + egui::vec2(uv.x, uv.y) * rect.size())
+        };
+        let screen: Vec<_> = self.points.iter().map(|&p| to_screen(p)).collect();
+
+        let stroke = Stroke::new(2.0, LINE_COLOR);
+        let mut segments: Vec<_> = screen.windows(2).map(|w| (w[0], w[1])).collect();
+        if self.mode == MeasureMode::Area && screen.len() > 2 {
+            segments.push((screen[screen.len() - 1], screen[0]));
+        }
+        for (a, b) in segments {
+            if let (Some(a), Some(b)) = (a, b) {
+                painter.line_segment([a, b], stroke);
+            }
+        }
+        for pos in screen.iter().flatten() {
+            painter.circle(
+                *pos,
+                POINT_RADIUS,
+                LINE_COLOR,
+                Stroke::new(1.0, Color32::BLACK),
+            );
+        }
+
+        let min_points = match self.mode {
+            MeasureMode::Distance => 2,
+            MeasureMode::Area => 3,
+        };
+        if let Some(Some(last)) = screen.last().filter(|_| screen.len() >= min_points) {
+            painter.text(
+                *last + egui::vec2(8.0, -8.0),
+                egui::Align2::LEFT_BOTTOM,
+                self.result(),
+                egui::FontId::proportional(14.0),
+                Color32::WHITE,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(points: &[Vec3]) -> Measurement {
+        let mut measure = Measurement::new(MeasureScale::default());
+        measure.points = points.to_vec();
+        measure
+    }
+
+    #[test]
+    fn paths_are_measured_in_order() {
+        let points = [
+            Vec3::ZERO,
+            Vec3::new(3.0, 4.0, 0.0),
+            Vec3::new(3.0, 4.0, 2.0),
+        ];
+        assert_eq!(path_length(&points), 7.0);
+        assert_eq!(path_length(&points[..1]), 0.0);
+        assert_eq!(path_length(&[]), 0.0);
+    }
+
+    #[test]
+    fn areas_are_measured_on_any_plane() {
+        let rotation = glam::Quat::from_euler(glam::EulerRot::XYZ, 0.3, -0.7, 1.1);
+        let rectangle = [
+            Vec3::ZERO,
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(2.0, 3.0, 0.0),
+            Vec3::new(0.0, 3.0, 0.0),
+        ]
+        .map(|p| rotation * p + Vec3::ONE);
+        assert!((polygon_area(&rectangle) - 6.0).abs() < 1e-5);
+        assert_eq!(polygon_area(&rectangle[..2]), 0.0);
+    }
+
+    #[test]
+    fn calibrating_scales_results() {
+        let mut measure = measurement(&[Vec3::ZERO, Vec3::X * 2.0]);
+        measure.known_length = 5.0;
+        measure.unit = "cm".to_owned();
+        measure.calibrate();
+        assert_eq!(
+            measure.measure_scale(),
+            MeasureScale {
+                scale: 2.5,
+                unit: "cm".to_owned(),
+            }
+        );
+        assert_eq!(measure.result(), "5.000 cm");
+
+        measure.points.push(Vec3::new(2.0, 2.0, 0.0));
+        measure.mode = MeasureMode::Area;
+        assert_eq!(measure.result(), "12.500 cm²");
+    }
+
+    #[test]
+    fn calibrating_without_a_length_keeps_the_scale() {
+        let mut measure = measurement(&[Vec3::ONE, Vec3::ONE]);
+        measure.known_length = 5.0;
+        measure.calibrate();
+        assert_eq!(measure.scale, 1.0);
+    }
+}
@@ -1,4 +1,5 @@
 use brush_process::message::ProcessMessage;
+use brush_vfs::DataSource;
 
 use crate::BrushUiProcess;
 
@@ -22,6 +23,12 @@ pub(crate) trait AppPanel {
         let _ = process;
     }
 
+    /// Handle a file dropped onto the app, to be loaded.
+    fn on_dropped(&mut self, source: &DataSource, process: &dyn BrushUiProcess) {
+        let _ = source;
+        let _ = process;
+    }
+
     /// Override the inner margin for this panel.
     fn inner_margin(&self) -> f32 {
         12.0
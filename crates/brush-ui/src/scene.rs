@@ -1,5 +1,13 @@
-use brush_dataset::splat_export;
-use brush_process::message::ProcessMessage;
+use brush_dataset::{
+    composition::composition_to_zip, scene::Scene, scene_transform::SceneTransform,
+    tonemap::Tonemap,
+};
+use brush_process::{
+    control::TrainCommand,
+    message::{MAX_REPLAY_SNAPSHOTS, ProcessMessage},
+};
+use brush_vfs::MemoryFile;
+use burn::tensor::{Tensor, s};
 use core::f32;
 use egui::{Area, epaint::mutex::RwLock as EguiRwLock};
 use std::sync::Arc;
@@ -7,25 +15,67 @@ use std::sync::Arc;
 use brush_render::{
     MainBackend,
     camera::{Camera, focal_to_fov, fov_to_focal},
+    composition::LayerTransform,
+    debug_view::{
+        DebugView, debug_splats, overdraw_image, sh_degree_quadrants, splat_count_image,
+    },
     gaussian_splats::Splats,
+    lod::SplatLod,
+    motion::SplatMotion,
+    occlusion::{DepthPyramid, SplatClusters},
+    render::pack_rgba,
+    stereo::{DEFAULT_IPD, Foveation, eye_cameras},
 };
 use eframe::egui_wgpu::Renderer;
 use egui::{Color32, Rect, Slider};
-use glam::UVec2;
+use glam::{UVec2, Vec3, vec2};
+use tokio::sync::oneshot::{Receiver, channel};
 use tokio_with_wasm::alias as tokio_wasm;
 use tracing::trace_span;
 use web_time::Instant;
 
 use crate::{
-    BrushUiProcess, UiMode, app::CameraSettings, burn_texture::BurnTexture, draw_checkerboard,
-    panels::AppPanel, size_for_splat_view,
+    BrushUiProcess, UiMode,
+    app::{CameraSettings, ViewSettings},
+    burn_texture::BurnTexture,
+    camera_controls::ControllerMode,
+    compose::Composition,
+    crop::CropBox,
+    draw_checkerboard,
+    edit::{EditTool, PaintBrush, SelectTool, SplatEditor, SplatLabeling},
+    export::{ExportJob, ExportStatus},
+    measure::{MeasureMode, Measurement},
+    panels::AppPanel,
+    size_for_splat_view,
+    wind::WindControls,
 };
 
+// Tonemap a float render of splats with linear HDR colors for display, brightened by `exposure`
+// stops.
+fn tonemap_render(
+    img: Tensor<MainBackend, 3>,
+    tonemap: Tonemap,
+    exposure: f32,
+) -> Tensor<MainBackend, 3> {
+    let rgb = img.clone().slice(s![.., .., 0..3]) * 2.0f32.powf(exposure);
+    let alpha = img.slice(s![.., .., 3..4]);
+    Tensor::cat(vec![tonemap.apply(rgb), alpha], 2)
+}
+
 #[derive(Debug, Clone, PartialEq)]
 struct RenderState {
     size: UVec2,
     cam: Camera,
     frame: f32,
+    view: ViewSettings,
+}
+
+// Side by side stereo, for 3D displays and to check what a headset would show.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct StereoView {
+    // Distance between the eyes, in units of the scene.
+    eye_distance: f32,
+    foveated: bool,
 }
 
 struct ErrorDisplay {
@@ -41,9 +91,80 @@ pub struct ScenePanel {
     frame_count: u32,
     frame: f32,
 
+    // Maps splats back to the original frame of the dataset on export.
+    export_transform: SceneTransform,
+
+    // Training views, drawn as camera frusta.
+    train_scene: Option<Scene>,
+    // The train cameras before their poses were smoothed, drawn to compare against.
+    unsmoothed_scene: Option<Scene>,
+    frustum_size: f32,
+    sampled_view: Option<usize>,
+
+    // Curve to show the linear colors of splats trained on HDR images with.
+    tonemap: Option<Tonemap>,
+
+    // Snapshots of training to scrub through, and the one being shown (if not live).
+    replay: Vec<(u32, Splats<MainBackend>)>,
+    replay_index: Option<usize>,
+    replay_playing: bool,
+    replay_time: f32,
+
+    // How the splats of a dynamic scene move, the time (0 to 1) they're shown at, and how many
+    // frames the videos of the dataset have, to play the motion back at their pace.
+    motion: Option<SplatMotion<MainBackend>>,
+    motion_time: f32,
+    motion_playing: bool,
+    video_frames: usize,
+
+    // Selection and deletion of splats, when in edit mode.
+    editor: Option<SplatEditor>,
+    // Labels given to the splats of a frame while editing, to export along with them.
+    labels: Option<(usize, SplatLabeling)>,
+
+    // Box to crop splats to, for display and export.
+    crop: Option<CropBox>,
+
+    // Points picked on the splats to measure distances and areas between.
+    measure: Option<Measurement>,
+
+    // Other splat files placed along with the scene, when composing.
+    compose: Option<Composition>,
+
+    // Export running in the background, or finished but still showing.
+    export: Option<ExportJob>,
+    // Files of the latest export of the training run, and its step, when the run can't write
+    // them to disk itself.
+    run_export: Option<(u32, Vec<MemoryFile>)>,
+
+    // Level of detail hierarchy of the shown splats, built in the background.
+    lod: Option<SplatLod<MainBackend>>,
+    lod_pending: Option<Receiver<SplatLod<MainBackend>>>,
+
+    // Scale of the splat count and overdraw views, and the gradient norms and ages of the splats of
+    // the latest training step to show in the gradient and age views.
+    max_splat_count: u32,
+    grad_norm: Option<Tensor<MainBackend, 1>>,
+    added_at: Option<Tensor<MainBackend, 1>>,
+
+    // Sway of labeled splats.
+    wind: WindControls,
+
+    // Depth of the last frame, to cull splats hidden behind it in the next.
+    occlusion_enabled: bool,
+    occlusion: Option<DepthPyramid<MainBackend>>,
+    occlusion_settling: bool,
+
+    stereo: Option<StereoView>,
+    // Headset showing the scene, when running with one.
+    #[cfg(all(feature = "xr", not(target_family = "wasm")))]
+    xr: Option<crate::xr::XrViewer>,
+
     // Ui state.
     live_update: bool,
     paused: bool,
+    // Factor on the learning rates of the training run.
+    lr_scale: f64,
     err: Option<ErrorDisplay>,
     ui_mode: UiMode,
 
@@ -65,26 +186,64 @@ impl ScenePanel {
             view_splats: vec![],
             live_update: true,
             paused: false,
+            lr_scale: 1.0,
             last_state: None,
             ui_mode,
             frame_count: 0,
             frame: 0.0,
+            export_transform: SceneTransform::IDENTITY,
+            train_scene: None,
+            unsmoothed_scene: None,
+            frustum_size: 1.0,
+            sampled_view: None,
+            tonemap: None,
+            replay: vec![],
+            replay_index: None,
+            replay_playing: false,
+            replay_time: 0.0,
+            motion: None,
+            motion_time: 0.0,
+            motion_playing: false,
+            video_frames: 0,
+            editor: None,
+            labels: None,
+            crop: None,
+            measure: None,
+            compose: None,
+            export: None,
+            run_export: None,
+            lod: None,
+            lod_pending: None,
+            max_splat_count: 256,
+            grad_norm: None,
+            added_at: None,
+            wind: WindControls::new(),
+            occlusion_enabled: false,
+            occlusion: None,
+            occlusion_settling: false,
+            stereo: None,
+            #[cfg(all(feature = "xr", not(target_family = "wasm")))]
+            xr: None,
         }
     }
 
+    #[cfg(all(feature = "xr", not(target_family = "wasm")))]
+    pub(crate) fn set_xr(&mut self, xr: crate::xr::XrViewer) {
+        self.xr = Some(xr);
+    }
+
     pub(crate) fn draw_splats(
         &mut self,
         ui: &mut egui::Ui,
         process: &dyn BrushUiProcess,
         splats: Option<Splats<MainBackend>>,
+        sway_labels: Option<SplatLabeling>,
     ) -> egui::Rect {
         let size = size_for_splat_view(ui, self.ui_mode == UiMode::Full);
 
         let mut size = size.floor();
 
-        let view = process.selected_view();
-
-        if let Some(view) = view {
+        if let Some(view) = process.selected_view() {
             let aspect_ratio = view.image.aspect_ratio();
             if size.x / size.y > aspect_ratio {
                 size.x = size.y * aspect_ratio;
@@ -93,23 +252,71 @@ impl ScenePanel {
             }
         }
         let size = glam::uvec2(size.x.round() as u32, size.y.round() as u32);
+        let view_settings = process.get_view_settings();
 
         let (rect, response) = ui.allocate_exact_size(
             egui::Vec2::new(size.x as f32, size.y as f32),
-            egui::Sense::drag(),
+            egui::Sense::click_and_drag(),
         );
 
-        process.tick_controls(&response, ui);
+        // When editing, the left mouse button is used to select or paint splats.
+        let primary = egui::PointerButton::Primary;
+        let selecting = self.editor.is_some()
+            && (response.dragged_by(primary) || response.drag_stopped_by(primary));
+        if !selecting {
+            process.tick_controls(&response, ui);
+        }
 
         // Get camera after modifying the controls.
-        let mut camera = process.current_camera();
+        let mut camera = process.current_camera().with_culling(view_settings.culling);
         let focal_y = fov_to_focal(camera.fov_y, size.y) as f32;
         camera.fov_x = focal_to_fov(focal_y as f64, size.x);
 
+        let splats = if let Some(editor) = &mut self.editor {
+            let add_to_selection = ui.input(|r| r.modifiers.shift);
+            if editor.handle_input(&response, rect, &camera, size, add_to_selection) {
+                self.last_state = None;
+            }
+            Some(editor.display_splats())
+        } else {
+            splats
+        };
+
+        // Sway labeled splats in the wind, which needs a new render every frame.
+        let splats = match (splats, &sway_labels) {
+            (Some(splats), Some(labels)) => {
+                self.last_state = None;
+                Some(self.wind.animate(&splats, labels).unwrap_or(splats))
+            }
+            (splats, _) => splats,
+        };
+
+        // Measure at the point under a click, on the splats as shown.
+        let click = response
+            .interact_pointer_pos()
+            .filter(|_| response.clicked());
+        if let (Some(measure), Some(splats), Some(pos), None) =
+            (&mut self.measure, &splats, click, &self.editor)
+        {
+            let splats = match &self.crop {
+                Some(crop) => crop.cull(splats),
+                None => splats.clone(),
+            };
+            let uv = (pos - rect.min) / rect.size();
+            let pixel = glam::vec2(uv.x, uv.y) * size.as_vec2();
+            measure.pick_point(splats, &camera, size, pixel, ui.ctx());
+        }
+
+        let settling = std::mem::take(&mut self.occlusion_settling);
+        if settling {
+            self.last_state = None;
+        }
+
         let state = RenderState {
             size,
             cam: camera.clone(),
             frame: self.frame,
+            view: view_settings,
         };
 
         let dirty = self.last_state != Some(state.clone());
@@ -125,16 +332,143 @@ impl ScenePanel {
             // If this viewport is re-rendering.
             if size.x > 8 && size.y > 8 && dirty {
                 let _span = trace_span!("Render splats").entered();
-                let (img, _) = splats.render(&camera, size, false);
-                self.backbuffer.update_texture(img);
+                // The headset shows the whole scene around the camera, without the level of detail
+                // cut for this view.
+                #[cfg(all(feature = "xr", not(target_family = "wasm")))]
+                if let Some(xr) = self.xr.as_ref().filter(|xr| xr.is_running()) {
+                    let stereo = self.stereo.unwrap_or(StereoView {
+                        eye_distance: DEFAULT_IPD,
+                        foveated: true,
+                    });
+                    xr.update(crate::xr::XrScene {
+                        splats: match &self.crop {
+                            Some(crop) => crop.cull(&splats),
+                            None => splats.clone(),
+                        },
+                        origin: camera.clone(),
+                        scene_scale: stereo.eye_distance / DEFAULT_IPD,
+                        foveation: stereo.foveated.then(Foveation::default),
+                    });
+                }
+                // Editing and replays show the exact splats.
+                let splats = match &self.lod {
+                    Some(lod)
+                        if view_settings.lod
+                            && self.editor.is_none()
+                            && self.compose.is_none()
+                            && self.replay_index.is_none()
+                            && sway_labels.is_none() =>
+                    {
+                        lod.cut(&camera, size, view_settings.lod_pixels)
+                    }
+                    _ => splats,
+                };
+                let splats = match &self.crop {
+                    Some(crop) => crop.cull(&splats),
+                    None => splats,
+                };
+                if view_settings.debug_view == DebugView::SplatCount {
+                    let (_, aux) = splats.render(&camera, size, true);
+                    self.backbuffer
+                        .update_texture_packed(splat_count_image(&aux, self.max_splat_count));
+                } else if view_settings.debug_view == DebugView::Overdraw {
+                    let (_, aux) = splats.render(&camera, size, true);
+                    self.backbuffer
+                        .update_texture_packed(overdraw_image(&aux, self.max_splat_count));
+                } else if view_settings.debug_view == DebugView::ShDegrees {
+                    self.backbuffer
+                        .update_texture_packed(sh_degree_quadrants(&splats, &camera, size));
+                } else if let Some(stereo) = self.stereo.filter(|_| {
+                    self.editor.is_none() && view_settings.debug_view == DebugView::Color
+                }) {
+                    // Each eye gets half the width, with the same vertical field of view.
+                    let eye_size = glam::uvec2((size.x / 2).max(1), size.y);
+                    let mut head = camera.clone();
+                    head.fov_x = focal_to_fov(fov_to_focal(camera.fov_y, size.y), eye_size.x);
+                    let eyes = eye_cameras(&head, stereo.eye_distance).map(|eye| {
+                        if stereo.foveated {
+                            splats.render_foveated(
+                                &eye,
+                                eye_size,
+                                glam::vec2(0.5, 0.5),
+                                Foveation::default(),
+                            )
+                        } else {
+                            splats.render(&eye, eye_size, true).0
+                        }
+                    });
+                    let img = Tensor::cat(eyes.to_vec(), 1);
+                    let img = match self.tonemap {
+                        Some(tonemap) => tonemap_render(img, tonemap, view_settings.exposure),
+                        None => img,
+                    };
+                    self.backbuffer.update_texture_packed(pack_rgba(img));
+                } else if self.occlusion_enabled
+                    && self.editor.is_none()
+                    && view_settings.debug_view == DebugView::Color
+                {
+                    // The splats change with the level of detail cut, so are clustered again for
+                    // every frame.
+                    let splats = match &self.occlusion {
+                        Some(depth) => depth.cull_clusters(&splats, &SplatClusters::new(&splats)),
+                        None => splats,
+                    };
+                    let (img, aux) = splats.render(&camera, size, true);
+                    self.occlusion = Some(DepthPyramid::from_render(
+                        &splats,
+                        &camera,
+                        img.clone(),
+                        &aux,
+                    ));
+                    // Splats coming into view were culled by the depth of the frame before, so
+                    // render once more after any change.
+                    self.occlusion_settling = !settling;
+                    let img = match self.tonemap {
+                        Some(tonemap) => tonemap_render(img, tonemap, view_settings.exposure),
+                        None => img,
+                    };
+                    self.backbuffer.update_texture_packed(pack_rgba(img));
+                } else {
+                    // Editing shows the selection in the splat colors instead.
+                    let debug = if self.editor.is_none() {
+                        let grad_norm = self
+                            .grad_norm
+                            .clone()
+                            .filter(|_| self.replay_index.is_none());
+                        let added_at = self
+                            .added_at
+                            .clone()
+                            .filter(|_| self.replay_index.is_none());
+                        debug_splats(
+                            &splats,
+                            view_settings.debug_view,
+                            &camera,
+                            grad_norm,
+                            added_at,
+                        )
+                    } else {
+                        None
+                    };
+                    let splats = debug.unwrap_or(splats);
+                    // The regular render clips colors to 8 bits, too early to tonemap them.
+                    if let (Some(tonemap), DebugView::Color) =
+                        (self.tonemap, view_settings.debug_view)
+                    {
+                        let (img, _) = splats.render(&camera, size, true);
+                        let img = tonemap_render(img, tonemap, view_settings.exposure);
+                        self.backbuffer.update_texture_packed(pack_rgba(img));
+                    } else {
+                        let (img, _) = splats.render(&camera, size, false);
+                        self.backbuffer.update_texture(img);
+                    }
+                }
             }
         }
 
         ui.scope(|ui| {
             let mut background = false;
 
-            let view = process.selected_view();
-            if let Some(view) = view {
+            if let Some(view) = process.selected_view() {
                 // if training views have alpha, show a background checker. Masked images
                 // should still use a black background.
                 if view.image.has_alpha() && !view.image.is_masked() {
@@ -159,10 +493,779 @@ impl ScenePanel {
                     Color32::WHITE,
                 );
             }
+
+            if view_settings.debug_view == DebugView::ShDegrees {
+                let painter = ui.painter_at(rect);
+                let half = rect.size() * 0.5;
+                for degree in 0..4 {
+                    let offset = egui::vec2((degree % 2) as f32, (degree / 2) as f32) * half;
+                    painter.text(
+                        rect.min + offset + egui::vec2(8.0, 8.0),
+                        egui::Align2::LEFT_TOP,
+                        format!("SH degree {degree}"),
+                        egui::FontId::proportional(14.0),
+                        Color32::WHITE,
+                    );
+                }
+            }
         });
 
+        if let Some(editor) = &self.editor {
+            editor.draw_overlay(&ui.painter_at(rect));
+        } else {
+            // Clicks are for placing points while measuring.
+            if view_settings.show_cameras && self.measure.is_none() {
+                self.draw_frusta(ui, rect, &camera, &response, process);
+            }
+            if let Some(measure) = &self.measure {
+                measure.draw_overlay(&ui.painter_at(rect), rect, &camera);
+            }
+            if let Some(crop) = &mut self.crop {
+                if crop.gizmo(ui, rect, &camera) {
+                    self.last_state = None;
+                    ui.ctx().request_repaint();
+                }
+            }
+            if let Some(compose) = &mut self.compose {
+                if compose.gizmo(ui, rect, &camera) {
+                    self.last_state = None;
+                    ui.ctx().request_repaint();
+                }
+            }
+        }
+
         rect
     }
+
+    /// Tools to select splats, and delete or isolate them.
+    fn edit_toolbar(&mut self, ui: &mut egui::Ui, frame: usize) {
+        let Some(editor) = self.editor.as_mut() else {
+            return;
+        };
+
+        let mut done = false;
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            for tool in SelectTool::ALL {
+                ui.selectable_value(&mut editor.tool, EditTool::Select(tool), tool.label());
+            }
+            ui.separator();
+            for brush in PaintBrush::ALL {
+                ui.selectable_value(&mut editor.tool, EditTool::Paint(brush), brush.label())
+                    .on_hover_text("Paint onto the splats under the cursor");
+            }
+
+            if matches!(
+                editor.tool,
+                EditTool::Select(SelectTool::Brush | SelectTool::Pick) | EditTool::Paint(_)
+            ) {
+                ui.add(
+                    Slider::new(&mut editor.brush_radius, 2.0..=100.0)
+                        .prefix("radius ")
+                        .suffix("px"),
+                );
+            }
+
+            if ui
+                .add_enabled(
+                    !editor.is_selecting_in_mesh(),
+                    egui::Button::new("📦 Select in mesh"),
+                )
+                .on_hover_text(
+                    "Select the splats inside of a mesh from an .obj file, to delete or isolate \
+                     them. Flat meshes, like a floor plan, are extruded through the scene.",
+                )
+                .clicked()
+            {
+                let add_to_selection = ui.input(|r| r.modifiers.shift);
+                editor.select_in_mesh(add_to_selection, ui.ctx());
+            }
+
+            ui.add_space(15.0);
+
+            let has_selection = editor.has_selection();
+            let delete_key = ui.input(|r| r.key_pressed(egui::Key::Delete));
+            if ui
+                .add_enabled(has_selection, egui::Button::new("🗑 Delete"))
+                .clicked()
+                || has_selection && delete_key
+            {
+                editor.remove_selected(false, ui.ctx());
+            }
+            if ui
+                .add_enabled(has_selection, egui::Button::new("Isolate"))
+                .clicked()
+            {
+                editor.remove_selected(true, ui.ctx());
+            }
+            if ui
+                .add_enabled(has_selection, egui::Button::new("Clear selection"))
+                .clicked()
+            {
+                editor.clear_selection();
+                changed = true;
+            }
+
+            let undo_key = ui.input(|r| r.modifiers.command && r.key_pressed(egui::Key::Z));
+            if ui
+                .add_enabled(editor.can_undo(), egui::Button::new("↶ Undo"))
+                .clicked()
+                || undo_key
+            {
+                editor.undo();
+                changed = true;
+            }
+
+            ui.add_space(15.0);
+            done = ui.button("✔ Done").clicked();
+        })
+        .response
+        .on_hover_text(
+            "Drag with the left mouse button to select or paint, hold shift to add to the selection.",
+        );
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut editor.label_name)
+                    .hint_text("Label name")
+                    .desired_width(120.0),
+            );
+            let can_label = editor.has_selection() && !editor.label_name.trim().is_empty();
+            if ui
+                .add_enabled(can_label, egui::Button::new("🏷 Label selection"))
+                .on_hover_text("Label the selected splats, to export the label with each splat")
+                .clicked()
+            {
+                editor.label_selected();
+                changed = true;
+            }
+            if let Some(labels) = editor.labels() {
+                // The first label is for unlabeled splats.
+                ui.label(format!("{} labels", labels.names.len() - 1));
+            }
+        });
+
+        if let EditTool::Paint(brush) = editor.tool {
+            ui.horizontal(|ui| {
+                if brush != PaintBrush::Erase {
+                    ui.add(Slider::new(&mut editor.paint_strength, 0.01..=1.0).prefix("strength "));
+                }
+                if brush == PaintBrush::Tint {
+                    egui::color_picker::color_edit_button_rgb(ui, &mut editor.tint);
+                }
+
+                let mut limit_depth = editor.depth_range.is_some();
+                ui.checkbox(&mut limit_depth, "Limit depth")
+                    .on_hover_text("Only paint splats within a distance range from the camera");
+                match (limit_depth, editor.depth_range.as_mut()) {
+                    (true, Some((near, far))) => {
+                        ui.add(
+                            egui::DragValue::new(near)
+                                .range(0.0..=*far)
+                                .speed(0.05)
+                                .prefix("near "),
+                        );
+                        ui.add(
+                            egui::DragValue::new(far)
+                                .range(*near..=f32::MAX)
+                                .speed(0.05)
+                                .prefix("far "),
+                        );
+                    }
+                    (true, None) => editor.depth_range = Some((0.0, 10.0)),
+                    (false, _) => editor.depth_range = None,
+                }
+            });
+        }
+
+        if changed {
+            self.last_state = None;
+        }
+
+        if done {
+            self.finish_editing(frame);
+        }
+    }
+
+    /// Leave edit mode, and keep the edited splats.
+    fn finish_editing(&mut self, frame: usize) {
+        if let Some(editor) = self.editor.take() {
+            if let Some(splats) = self.view_splats.get_mut(frame) {
+                *splats = editor.splats().clone();
+                self.labels = editor.labels().map(|labels| (frame, labels.clone()));
+                // The edit was made to the splats at the time shown, which stay there.
+                self.motion = None;
+            }
+        }
+        self.reset_lod();
+        self.last_state = None;
+    }
+
+    // Labels of the splats currently shown, if any.
+    fn shown_labels(&self, frame: usize) -> Option<SplatLabeling> {
+        if let Some(editor) = &self.editor {
+            return editor.labels().cloned();
+        }
+        if self.replay_index.is_some() {
+            return None;
+        }
+        self.labels
+            .as_ref()
+            .filter(|(labeled, _)| *labeled == frame)
+            .map(|(_, labels)| labels.clone())
+    }
+
+    fn reset_lod(&mut self) {
+        self.lod = None;
+        self.lod_pending = None;
+    }
+
+    /// Build the level of detail hierarchy once the splats are final, ie. after loading or training.
+    fn update_lod(&mut self, process: &dyn BrushUiProcess, ctx: &egui::Context) {
+        if let Some(pending) = self.lod_pending.as_mut() {
+            if let Ok(lod) = pending.try_recv() {
+                self.lod = Some(lod);
+                self.lod_pending = None;
+                self.last_state = None;
+            }
+            return;
+        }
+
+        // Animations change splats every frame, so they aren't worth building a hierarchy for.
+        if !process.get_view_settings().lod
+            || self.lod.is_some()
+            || self.frame_count > 1
+            || process.is_training()
+            || process.is_loading()
+        {
+            return;
+        }
+        let Some(splats) = self.view_splats.first().cloned() else {
+            return;
+        };
+
+        let (sender, receiver) = channel();
+        let ctx = ctx.clone();
+        tokio_wasm::task::spawn(async move {
+            let mut builder = SplatLod::builder(&splats).await;
+            // Building takes a while for the scenes that need it, so keep it off the runtime.
+            #[cfg(not(target_family = "wasm"))]
+            let Ok(lod) = tokio_wasm::task::spawn_blocking(move || builder.finish()).await else {
+                return;
+            };
+            // There are no threads to block on the web, so build a bit at a time instead.
+            #[cfg(target_family = "wasm")]
+            let lod = {
+                const LOD_BUILD_STEP: usize = 50_000;
+                while !builder.step(LOD_BUILD_STEP) {
+                    tokio_wasm::task::yield_now().await;
+                }
+                builder.finish()
+            };
+            // If the scene was changed in the meantime, that's fine.
+            let _ = sender.send(lod);
+            ctx.request_repaint();
+        });
+        self.lod_pending = Some(receiver);
+    }
+
+    /// Numeric bounds of the crop box.
+    fn crop_toolbar(&mut self, ui: &mut egui::Ui, splats: Option<&Splats<MainBackend>>) {
+        let Some(crop) = self.crop.as_mut() else {
+            return;
+        };
+
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("✂ Crop box");
+
+            // Drag speed relative to the box, so it's usable at any scene scale.
+            let speed = if crop.min.is_finite() && crop.max.is_finite() {
+                (crop.max - crop.min).length() * 0.002
+            } else {
+                0.01
+            };
+            for (label, bound) in [("min", &mut crop.min), ("max", &mut crop.max)] {
+                ui.label(label);
+                for axis in 0..3 {
+                    changed |= ui
+                        .add(egui::DragValue::new(&mut bound[axis]).speed(speed))
+                        .changed();
+                }
+            }
+
+            if let Some(splats) = splats {
+                if ui.button("Fit to splats").clicked() {
+                    crop.fit(splats.clone(), ui.ctx());
+                }
+            }
+            ui.checkbox(&mut crop.apply_on_export, "Crop on export");
+        });
+
+        if changed {
+            crop.min = crop.min.min(crop.max);
+            self.last_state = None;
+        }
+    }
+
+    /// Progress or result of an export. Returns whether the export was cancelled or dismissed.
+    fn export_status(ui: &mut egui::Ui, job: &ExportJob) -> bool {
+        match job.status() {
+            ExportStatus::Running { stage, progress } => {
+                ui.add(
+                    egui::ProgressBar::new(progress)
+                        .desired_width(160.0)
+                        .animate(true)
+                        .text(stage),
+                );
+                if ui.button("Cancel").clicked() {
+                    job.cancel();
+                    return true;
+                }
+            }
+            ExportStatus::Done { num_splats } => {
+                ui.label(format!("✅ Exported {num_splats} splats"));
+                // Repaint to hide this again.
+                ui.ctx()
+                    .request_repaint_after(web_time::Duration::from_secs(1));
+            }
+            ExportStatus::Cancelled => {}
+            ExportStatus::Failed(e) => {
+                ui.colored_label(Color32::LIGHT_RED, format!("❌ {e}"));
+                return ui.button("✖").on_hover_text("Dismiss").clicked();
+            }
+        }
+        false
+    }
+
+    /// Mode, result and scale of the measurement. The scale is kept for the scene.
+    fn measure_toolbar(&mut self, ui: &mut egui::Ui, process: &dyn BrushUiProcess) {
+        let Some(measure) = self.measure.as_mut() else {
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            for mode in MeasureMode::ALL {
+                ui.selectable_value(&mut measure.mode, mode, mode.label());
+            }
+            ui.add_space(15.0);
+
+            if measure.num_points() == 0 {
+                ui.label("Click on the splats to place points.");
+            } else {
+                ui.strong(measure.result());
+            }
+            if ui
+                .add_enabled(measure.num_points() > 0, egui::Button::new("↶ Undo"))
+                .clicked()
+            {
+                measure.undo();
+            }
+            if ui
+                .add_enabled(measure.num_points() > 0, egui::Button::new("Clear"))
+                .clicked()
+            {
+                measure.clear();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Scale");
+            ui.add(
+                egui::DragValue::new(&mut measure.scale)
+                    .range(1e-6..=1e6)
+                    .speed(0.01),
+            )
+            .on_hover_text("Real world units per scene unit");
+            ui.add(egui::TextEdit::singleline(&mut measure.unit).desired_width(40.0));
+            ui.separator();
+
+            let can_calibrate = measure.mode == MeasureMode::Distance && measure.num_points() >= 2;
+            ui.add_enabled(
+                can_calibrate,
+                egui::DragValue::new(&mut measure.known_length)
+                    .range(1e-6..=f32::MAX)
+                    .speed(0.01),
+            );
+            if ui
+                .add_enabled(can_calibrate, egui::Button::new("Set as length"))
+                .on_hover_text("Set the scale so the measured distance has this real world length")
+                .clicked()
+            {
+                measure.calibrate();
+            }
+        });
+
+        let scale = measure.measure_scale();
+        if scale != process.get_measure_scale() {
+            process.set_measure_scale(scale);
+        }
+    }
+
+    /// Layers of the composition, the placement of the selected one, and its exports.
+    fn compose_toolbar(&mut self, ui: &mut egui::Ui, process: &dyn BrushUiProcess) {
+        let training = process.is_training();
+        let Some(compose) = self.compose.as_mut() else {
+            return;
+        };
+
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("🧩 Layers");
+            for (index, layer) in compose.layers.iter_mut().enumerate() {
+                changed |= ui
+                    .checkbox(&mut layer.visible, "")
+                    .on_hover_text("Show layer")
+                    .changed();
+                ui.selectable_value(&mut compose.selected, index, layer.name.as_str());
+            }
+            if compose.is_loading() {
+                ui.spinner();
+            } else if ui
+                .button("➕ Add splats")
+                .on_hover_text("Load a splat file as a new layer")
+                .clicked()
+            {
+                compose.add_file(ui.ctx());
+            }
+            if ui
+                .add_enabled(compose.layers.len() > 1, egui::Button::new("🗑 Remove"))
+                .clicked()
+            {
+                compose.remove_selected();
+                changed = true;
+            }
+        });
+
+        if let Some(layer) = compose.layers.get_mut(compose.selected) {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut layer.name).desired_width(120.0))
+                    .on_hover_text("File name of the layer in exported scenes");
+
+                // Edits are relative to the center of the layer, like the gizmo.
+                let pivot = layer.pivot();
+                let speed = 0.01 * layer.transform.scale;
+                ui.label("position");
+                let mut position = pivot;
+                for axis in 0..3 {
+                    ui.add(egui::DragValue::new(&mut position[axis]).speed(speed));
+                }
+                if position != pivot {
+                    layer.transform.translation += position - pivot;
+                    changed = true;
+                }
+
+                ui.label("rotation");
+                let (x, y, z) = layer.transform.rotation.to_euler(glam::EulerRot::XYZ);
+                let mut angles = [x, y, z].map(f32::to_degrees);
+                let old_angles = angles;
+                for angle in &mut angles {
+                    ui.add(egui::DragValue::new(angle).speed(0.5).suffix("°"));
+                }
+                if angles != old_angles {
+                    let [x, y, z] = angles.map(f32::to_radians);
+                    let rotation = glam::Quat::from_euler(glam::EulerRot::XYZ, x, y, z);
+                    layer
+                        .transform
+                        .rotate_around(pivot, rotation * layer.transform.rotation.inverse());
+                    changed = true;
+                }
+
+                ui.label("scale");
+                let mut scale = layer.transform.scale;
+                if ui
+                    .add(
+                        egui::DragValue::new(&mut scale)
+                            .speed(0.01)
+                            .range(0.001..=1000.0),
+                    )
+                    .changed()
+                {
+                    layer
+                        .transform
+                        .scale_around(pivot, scale / layer.transform.scale);
+                    changed = true;
+                }
+
+                if ui.button("Reset").clicked() {
+                    layer.transform = LayerTransform::IDENTITY;
+                    changed = true;
+                }
+            });
+        }
+
+        if changed {
+            compose.invalidate();
+            self.last_state = None;
+        }
+        let merged = compose.splats();
+
+        ui.horizontal(|ui| {
+            if ui
+                .button("📄 Export scene")
+                .on_hover_text("Save the file of each layer, and where it's placed, as a zip")
+                .clicked()
+            {
+                let layers = compose.visible_layers();
+                tokio_wasm::task::spawn(async move {
+                    match composition_to_zip(layers).await {
+                        Ok(data) => {
+                            let _ = rrfd::save_file("scene.zip", data)
+                                .await
+                                .inspect_err(|e| log::error!("Failed to save file: {e}"));
+                        }
+                        Err(e) => log::error!("Failed to save scene: {e}"),
+                    }
+                });
+            }
+
+            // While training, the export shows along with the training controls.
+            if !training && self.export.as_mut().is_some_and(ExportJob::poll) {
+                self.export = None;
+            }
+            if let Some(job) = &self.export {
+                if !training && Self::export_status(ui, job) {
+                    self.export = None;
+                }
+            } else if let Some(splats) = merged {
+                if ui
+                    .button("⬆ Export merged")
+                    .on_hover_text("Save all visible layers as one ply file")
+                    .clicked()
+                {
+                    // Viewers expect sRGB colors, not the linear colors of HDR splats.
+                    let splats = match self.tonemap {
+                        Some(tonemap) => tonemap.apply_splats(splats),
+                        None => splats,
+                    };
+                    self.export = Some(ExportJob::spawn(
+                        splats,
+                        self.export_transform,
+                        self.crop.as_ref().and_then(CropBox::export_bounds),
+                        None,
+                        process.get_export_settings(),
+                        ui.ctx(),
+                    ));
+                }
+            }
+        });
+    }
+
+    /// Leave compose mode, and keep the composed splats as the scene if anything was added or
+    /// moved.
+    fn finish_composing(&mut self, frame: usize) {
+        if let Some(mut compose) = self.compose.take() {
+            if let (true, Some(splats), Some(composed)) = (
+                compose.is_edited(),
+                self.view_splats.get_mut(frame),
+                compose.splats(),
+            ) {
+                *splats = composed;
+                // Labels were given to the splats from before.
+                self.labels = None;
+                self.motion = None;
+            }
+        }
+        self.reset_lod();
+        self.last_state = None;
+    }
+
+    /// Timeline to scrub through the training snapshots.
+    fn replay_timeline(&mut self, ui: &mut egui::Ui) {
+        // Snapshots to show per second when playing back the replay.
+        const REPLAY_FPS: f32 = 10.0;
+
+        let last = self.replay.len() - 1;
+        let old_index = self.replay_index;
+
+        if self.replay_playing {
+            self.replay_time += ui.input(|r| r.predicted_dt);
+            let index = (self.replay_time * REPLAY_FPS) as usize;
+            if index >= last {
+                self.replay_playing = false;
+            }
+            self.replay_index = Some(index.min(last));
+            ui.ctx().request_repaint();
+        }
+
+        ui.horizontal(|ui| {
+            let label = if self.replay_playing {
+                "⏸ Replay"
+            } else {
+                "⏵ Replay"
+            };
+            if ui.selectable_label(self.replay_playing, label).clicked() {
+                self.replay_playing = !self.replay_playing;
+                let start = match self.replay_index {
+                    Some(index) if index < last => index,
+                    _ => 0,
+                };
+                self.replay_time = start as f32 / REPLAY_FPS;
+            }
+
+            let mut index = self.replay_index.unwrap_or(last);
+            let replay = &self.replay;
+            let response = ui.add(
+                Slider::new(&mut index, 0..=last)
+                    .show_value(true)
+                    .custom_formatter(|val, _| format!("step {}", replay[val as usize].0)),
+            );
+            if response.changed() {
+                self.replay_index = Some(index);
+                self.replay_playing = false;
+            }
+
+            if ui
+                .selectable_label(self.replay_index.is_none(), "🔴 Live")
+                .clicked()
+            {
+                self.replay_index = None;
+                self.replay_playing = false;
+            }
+        });
+
+        if self.replay_index != old_index {
+            self.last_state = None;
+        }
+    }
+
+    /// Timeline to play back the motion of a dynamic scene, and scrub through it.
+    fn motion_timeline(&mut self, ui: &mut egui::Ui) {
+        // Frames of the videos to show per second.
+        const MOTION_FPS: f32 = 24.0;
+
+        let old_time = self.motion_time;
+        if self.motion_playing {
+            let frames = self.video_frames.max(2) - 1;
+            self.motion_time += ui.input(|r| r.predicted_dt) * MOTION_FPS / frames as f32;
+            self.motion_time = self.motion_time.rem_euclid(1.0);
+            ui.ctx().request_repaint();
+        }
+
+        ui.horizontal(|ui| {
+            let label = if self.motion_playing {
+                "⏸ Pause"
+            } else {
+                "⏵ Play"
+            };
+            if ui.selectable_label(self.motion_playing, label).clicked() {
+                self.motion_playing = !self.motion_playing;
+            }
+
+            let frames = self.video_frames;
+            let response = ui.add(
+                Slider::new(&mut self.motion_time, 0.0..=1.0)
+                    .show_value(true)
+                    .custom_formatter(|val, _| {
+                        if frames > 1 {
+                            format!("frame {}", (val * (frames - 1) as f64).round())
+                        } else {
+                            format!("{val:.2}")
+                        }
+                    }),
+            );
+            if response.changed() {
+                self.motion_playing = false;
+            }
+        });
+
+        if self.motion_time != old_time {
+            self.last_state = None;
+        }
+    }
+
+    /// Draw the training cameras as frusta, and focus a view when its frustum is clicked.
+    fn draw_frusta(
+        &self,
+        ui: &egui::Ui,
+        rect: Rect,
+        camera: &Camera,
+        response: &egui::Response,
+        process: &dyn BrushUiProcess,
+    ) {
+        let Some(scene) = &self.train_scene else {
+            return;
+        };
+
+        let to_screen = |point: Vec3| {
+            camera
+                .world_to_uv(point)
+                .map(|uv| rect.min + egui::vec2(uv.x, uv.y) * rect.size())
+        };
+
+        let painter = ui.painter_at(rect);
+        let selected = process.selected_view().map(|view| view.camera);
+        let click_pos = if response.clicked() {
+            response.interact_pointer_pos()
+        } else {
+            None
+        };
+        let mut clicked: Option<(f32, usize)> = None;
+
+        let frustum = |cam: &Camera| {
+            let apex = to_screen(cam.position)?;
+            let corners = [
+                vec2(0.0, 0.0),
+                vec2(1.0, 0.0),
+                vec2(1.0, 1.0),
+                vec2(0.0, 1.0),
+            ]
+            .map(|uv| to_screen(cam.uv_to_world(uv, self.frustum_size)));
+            let corners = corners.into_iter().collect::<Option<Vec<_>>>()?;
+            Some((apex, corners))
+        };
+
+        // Original poses of smoothed cameras, with a line to where they were moved.
+        if let Some(unsmoothed) = &self.unsmoothed_scene {
+            let stroke = egui::Stroke::new(1.0, Color32::from_rgba_unmultiplied(255, 90, 90, 120));
+            for (view, smoothed) in unsmoothed.views.iter().zip(scene.views.iter()) {
+                let Some((apex, corners)) = frustum(&view.camera) else {
+                    continue;
+                };
+                for i in 0..4 {
+                    painter.line_segment([apex, corners[i]], stroke);
+                    painter.line_segment([corners[i], corners[(i + 1) % 4]], stroke);
+                }
+                if let Some(moved) = to_screen(smoothed.camera.position) {
+                    painter.line_segment([apex, moved], stroke);
+                }
+            }
+        }
+
+        for (index, view) in scene.views.iter().enumerate() {
+            let cam = &view.camera;
+            let Some((apex, corners)) = frustum(cam) else {
+                continue;
+            };
+
+            let stroke = if self.sampled_view == Some(index) {
+                egui::Stroke::new(2.0, Color32::from_rgb(255, 160, 0))
+            } else if selected.as_ref() == Some(cam) {
+                egui::Stroke::new(2.0, Color32::from_rgb(80, 200, 80))
+            } else {
+                egui::Stroke::new(1.0, Color32::from_white_alpha(100))
+            };
+
+            for i in 0..4 {
+                painter.line_segment([apex, corners[i]], stroke);
+                painter.line_segment([corners[i], corners[(i + 1) % 4]], stroke);
+            }
+
+            if let Some(pos) = click_pos {
+                let mut points = corners.clone();
+                points.push(apex);
+                let bounds = Rect::from_points(&points).expand(4.0);
+                let dist = bounds.center().distance(pos);
+                if bounds.contains(pos) && clicked.is_none_or(|(best, _)| dist < best) {
+                    clicked = Some((dist, index));
+                }
+            }
+        }
+
+        if let Some((_, index)) = clicked {
+            process.focus_view(&scene.views[index]);
+        }
+    }
 }
 
 impl AppPanel for ScenePanel {
@@ -179,15 +1282,56 @@ impl AppPanel for ScenePanel {
                 self.frame = 0.0;
                 self.live_update = true;
                 self.paused = false;
+                self.lr_scale = 1.0;
                 self.err = None;
                 self.backbuffer.reset();
                 self.last_state = None;
+                self.export_transform = SceneTransform::IDENTITY;
+                self.train_scene = None;
+                self.unsmoothed_scene = None;
+                self.tonemap = None;
+                self.sampled_view = None;
+                self.replay = vec![];
+                self.replay_index = None;
+                self.replay_playing = false;
+                self.motion = None;
+                self.motion_time = 0.0;
+                self.motion_playing = false;
+                self.video_frames = 0;
+                self.run_export = None;
+                self.editor = None;
+                self.labels = None;
+                self.crop = None;
+                self.measure = None;
+                self.compose = None;
+                self.grad_norm = None;
+                self.added_at = None;
+                self.occlusion = None;
+                self.reset_lod();
+            }
+            ProcessMessage::Dataset { dataset } => {
+                self.export_transform = dataset.transform.inverse();
+                // Size frusta relative to the scene, so they're visible but don't clutter the view.
+                self.frustum_size = dataset.train.estimate_extent().unwrap_or(1.0) * 0.05;
+                self.train_scene = Some(dataset.train.clone());
+                self.unsmoothed_scene = dataset.unsmoothed_train.clone();
+                self.tonemap = dataset.tonemap;
+                let mut times: Vec<f32> = dataset
+                    .train
+                    .views
+                    .iter()
+                    .filter_map(|v| v.image.time)
+                    .collect();
+                times.sort_by(f32::total_cmp);
+                times.dedup();
+                self.video_frames = times.len();
             }
             ProcessMessage::ViewSplats {
                 up_axis,
                 splats,
                 frame,
                 total_frames,
+                labels,
             } => {
                 if let Some(up_axis) = up_axis {
                     context.set_model_up(*up_axis);
@@ -195,15 +1339,67 @@ impl AppPanel for ScenePanel {
                 self.view_splats.truncate(*frame as usize);
                 self.view_splats.push(*splats.clone());
                 self.frame_count = *total_frames;
+                // Labels of the frames replaced by this one are gone with them.
+                let frame = *frame as usize;
+                self.labels = match labels
+                    .as_ref()
+                    .filter(|labels| labels.labels.len() == splats.num_splats() as usize)
+                {
+                    Some(labels) => Some((frame, SplatLabeling::new(labels, &splats.device()))),
+                    None => self.labels.take().filter(|(labeled, _)| *labeled < frame),
+                };
+                self.motion = None;
+                self.grad_norm = None;
+                self.added_at = None;
+
+                // Big scenes are hardly interactive without a level of detail.
+                const LOD_AUTO_SPLATS: u32 = 4_000_000;
+                if *total_frames == 1 && splats.num_splats() >= LOD_AUTO_SPLATS {
+                    context.set_view_settings(ViewSettings {
+                        lod: true,
+                        ..context.get_view_settings()
+                    });
+                }
+                self.reset_lod();
 
                 // Mark redraw as dirty if we're live updating.
                 if self.live_update {
                     self.last_state = None;
                 }
             }
-            ProcessMessage::TrainStep { splats, .. } => {
+            ProcessMessage::Snapshot { splats, iter } => {
+                // Keep memory bounded on long runs by thinning out older snapshots.
+                if self.replay.len() >= MAX_REPLAY_SNAPSHOTS {
+                    let mut i = 0;
+                    self.replay.retain(|_| {
+                        i += 1;
+                        i % 2 == 1
+                    });
+                    self.replay_index = self.replay_index.map(|i| i / 2);
+                }
+                self.replay.push((*iter, *splats.clone()));
+            }
+            ProcessMessage::Exported { iter, files } => {
+                self.run_export = Some((*iter, files.clone()));
+            }
+            ProcessMessage::RemoteTrainStep { paused, .. } => {
+                // Other viewers of the run can pause it too.
+                self.paused = *paused;
+            }
+            ProcessMessage::TrainStep {
+                splats,
+                motion,
+                stats,
+                ..
+            } => {
+                self.sampled_view = Some(stats.view_index);
                 let splats = *splats.clone();
                 self.view_splats = vec![splats];
+                self.motion = motion.as_deref().cloned();
+                self.labels = None;
+                self.grad_norm = stats.grad_norm.clone();
+                self.added_at = stats.added_at.clone();
+                self.reset_lod();
                 // Mark redraw as dirty if we're live updating.
                 if self.live_update {
                     self.last_state = None;
@@ -287,8 +1483,45 @@ For bigger training runs consider using the native app."#,
                 .rem_euclid(self.frame_count as f32)
                 .floor() as usize;
 
-            let splats = self.view_splats.get(frame).cloned();
-            let rect = self.draw_splats(ui, process, splats.clone());
+            if self.editor.as_mut().is_some_and(|editor| editor.poll()) {
+                self.last_state = None;
+            }
+            if self.crop.as_mut().is_some_and(CropBox::poll) {
+                self.last_state = None;
+            }
+            if let Some(measure) = &mut self.measure {
+                measure.poll();
+            }
+            if let Some(compose) = &mut self.compose {
+                if compose.poll(ui.ctx()) {
+                    self.last_state = None;
+                }
+            }
+            self.update_lod(process, ui.ctx());
+
+            let splats = if let Some(editor) = &self.editor {
+                Some(editor.splats().clone())
+            } else if let Some(compose) = &mut self.compose {
+                compose.splats()
+            } else if let Some((_, snapshot)) = self.replay_index.and_then(|i| self.replay.get(i)) {
+                Some(snapshot.clone())
+            } else {
+                self.view_splats
+                    .get(frame)
+                    .cloned()
+                    .map(|splats| match &self.motion {
+                        Some(motion) => motion.apply(splats, self.motion_time),
+                        None => splats,
+                    })
+            };
+            if self.wind.poll() {
+                self.last_state = None;
+            }
+            // Edits are made to the splats at rest.
+            let sway_labels = self
+                .shown_labels(frame)
+                .filter(|_| self.editor.is_none() && self.wind.is_animating());
+            let rect = self.draw_splats(ui, process, splats.clone(), sway_labels);
 
             if process.is_loading() {
                 let id = ui.auto_id_with("loading_bar");
@@ -307,6 +1540,30 @@ For bigger training runs consider using the native app."#,
                     });
             }
 
+            if !self.replay.is_empty() && self.editor.is_none() {
+                self.replay_timeline(ui);
+            }
+
+            if self.motion.is_some() && self.editor.is_none() && self.compose.is_none() {
+                self.motion_timeline(ui);
+            }
+
+            if self.editor.is_some() {
+                self.edit_toolbar(ui, frame);
+            }
+
+            if self.crop.is_some() {
+                self.crop_toolbar(ui, splats.as_ref());
+            }
+
+            if self.measure.is_some() && self.editor.is_none() {
+                self.measure_toolbar(ui, process);
+            }
+
+            if self.compose.is_some() {
+                self.compose_toolbar(ui, process);
+            }
+
             if self.view_splats.len() > 1 && self.view_splats.len() as u32 == self.frame_count {
                 let label = if self.paused {
                     "⏸ paused"
@@ -319,7 +1576,229 @@ For bigger training runs consider using the native app."#,
                 }
             }
 
+            let label_names = self.shown_labels(frame).map(|labels| labels.names);
+            let mut view_settings = process.get_view_settings();
             ui.horizontal(|ui| {
+                if self.ui_mode == UiMode::Full {
+                    if let Some(splats) = &splats {
+                        let editing = self.editor.is_some();
+                        // Each training step replaces the splats, which would undo any edits.
+                        if self.compose.is_none()
+                            && ui
+                                .add_enabled(
+                                    !process.is_training(),
+                                    egui::SelectableLabel::new(editing, "✏ Edit splats"),
+                                )
+                                .on_disabled_hover_text("Splats can be edited once training is done")
+                                .clicked()
+                        {
+                            if editing {
+                                self.finish_editing(frame);
+                            } else {
+                                self.editor = Some(SplatEditor::new(
+                                    splats.clone(),
+                                    self.shown_labels(frame),
+                                ));
+                                self.last_state = None;
+                            }
+                        }
+
+                        if !editing
+                            && ui
+                                .selectable_label(self.compose.is_some(), "🧩 Compose")
+                                .on_hover_text(
+                                    "Place other splat files in the scene, and export them together",
+                                )
+                                .clicked()
+                        {
+                            if self.compose.is_some() {
+                                self.finish_composing(frame);
+                            } else {
+                                // Don't let training updates replace the scene being composed.
+                                self.live_update = false;
+                                self.compose = Some(Composition::new(splats.clone(), ui.ctx()));
+                                self.last_state = None;
+                            }
+                        }
+
+                        if ui.selectable_label(self.crop.is_some(), "✂ Crop").clicked() {
+                            self.crop = match self.crop {
+                                Some(_) => None,
+                                None => Some(CropBox::new(splats.clone(), ui.ctx())),
+                            };
+                            self.last_state = None;
+                        }
+
+                        if ui
+                            .selectable_label(self.measure.is_some(), "📐 Measure")
+                            .on_hover_text("Measure distances and areas on the splats")
+                            .clicked()
+                        {
+                            self.measure = match self.measure {
+                                Some(_) => None,
+                                None => Some(Measurement::new(process.get_measure_scale())),
+                            };
+                        }
+
+                        if ui
+                            .selectable_label(view_settings.lod, "🗺 LOD")
+                            .on_hover_text(
+                                "Draw far away parts of the scene simplified, to keep huge scenes interactive",
+                            )
+                            .clicked()
+                        {
+                            view_settings.lod = !view_settings.lod;
+                            self.last_state = None;
+                        }
+                        if view_settings.lod {
+                            if self.lod_pending.is_some() {
+                                ui.spinner().on_hover_text("Building level of detail...");
+                            } else if ui
+                                .add(
+                                    Slider::new(&mut view_settings.lod_pixels, 0.5..=8.0)
+                                        .prefix("detail ")
+                                        .suffix("px"),
+                                )
+                                .on_hover_text("Merge parts of the scene smaller than this on screen")
+                                .changed()
+                            {
+                                self.last_state = None;
+                            }
+                        }
+
+                        if ui
+                            .selectable_label(self.occlusion_enabled, "🧱 Occlusion")
+                            .on_hover_text(
+                                "Skip splats hidden behind what was drawn the frame before. Speeds up dense indoor scans, but splats coming into view can show up a frame late",
+                            )
+                            .clicked()
+                        {
+                            self.occlusion_enabled = !self.occlusion_enabled;
+                            self.occlusion = None;
+                            self.last_state = None;
+                        }
+
+                        if ui
+                            .selectable_label(self.stereo.is_some(), "👓 Stereo")
+                            .on_hover_text(
+                                "Show the view of a left and a right eye side by side, as a headset would",
+                            )
+                            .clicked()
+                        {
+                            self.stereo = match self.stereo {
+                                Some(_) => None,
+                                None => Some(StereoView {
+                                    eye_distance: DEFAULT_IPD,
+                                    foveated: false,
+                                }),
+                            };
+                            self.last_state = None;
+                        }
+                        if let Some(stereo) = &mut self.stereo {
+                            let changed = ui
+                                .add(
+                                    Slider::new(&mut stereo.eye_distance, 0.001..=1.0)
+                                        .logarithmic(true)
+                                        .prefix("eyes "),
+                                )
+                                .on_hover_text(
+                                    "Distance between the eyes, in units of the scene. About 0.063 for scenes scaled in meters",
+                                )
+                                .changed()
+                                | ui.checkbox(&mut stereo.foveated, "Foveated")
+                                    .on_hover_text(
+                                        "Draw the edges of each eye at half the resolution, like headsets do to keep up their frame rate",
+                                    )
+                                    .changed();
+                            if changed {
+                                self.last_state = None;
+                            }
+                        }
+
+                        ui.menu_button("✂ Clipping", |ui| {
+                            let culling = &mut view_settings.culling;
+                            let near = ui
+                                .add(
+                                    Slider::new(&mut culling.near, 0.001..=100.0)
+                                        .logarithmic(true)
+                                        .prefix("near "),
+                                )
+                                .on_hover_text("Hide splats closer to the camera than this");
+                            let far = ui
+                                .add(
+                                    Slider::new(&mut culling.far, 0.1..=1e10)
+                                        .logarithmic(true)
+                                        .prefix("far "),
+                                )
+                                .on_hover_text("Hide splats further from the camera than this");
+                            let min_radius = ui
+                                .add(
+                                    Slider::new(&mut culling.min_radius, 0.0..=8.0)
+                                        .prefix("min size ")
+                                        .suffix("px"),
+                                )
+                                .on_hover_text(
+                                    "Hide splats smaller than this on screen. Speeds up big scenes seen from afar",
+                                );
+                            if near.changed() || far.changed() || min_radius.changed() {
+                                self.last_state = None;
+                            }
+                        })
+                        .response
+                        .on_hover_text("Hide splats by their distance to the camera and size on screen");
+
+                        ui.menu_button(format!("🔍 {}", view_settings.debug_view.name()), |ui| {
+                            for view in DebugView::ALL {
+                                if ui
+                                    .radio_value(&mut view_settings.debug_view, view, view.name())
+                                    .on_hover_text(view.description())
+                                    .changed()
+                                {
+                                    self.last_state = None;
+                                }
+                            }
+                        })
+                        .response
+                        .on_hover_text("Show a property of the splats instead of their colors");
+                        if matches!(
+                            view_settings.debug_view,
+                            DebugView::SplatCount | DebugView::Overdraw
+                        ) && ui
+                                .add(
+                                    Slider::new(&mut self.max_splat_count, 16..=4096)
+                                        .logarithmic(true)
+                                        .prefix("max "),
+                                )
+                                .on_hover_text("Pixels with this many splats or more show as red")
+                                .changed()
+                        {
+                            self.last_state = None;
+                        }
+                        if self.tonemap.is_some()
+                            && ui
+                                .add(
+                                    Slider::new(&mut view_settings.exposure, -8.0..=8.0)
+                                        .prefix("exposure ")
+                                        .suffix(" EV"),
+                                )
+                                .on_hover_text("Brighten or darken the HDR colors before tonemapping")
+                                .changed()
+                        {
+                            self.last_state = None;
+                        }
+
+                        if let Some(names) = &label_names {
+                            ui.menu_button("🍃 Wind", |ui| {
+                                if self.wind.ui(ui, names) {
+                                    self.last_state = None;
+                                }
+                            })
+                            .response
+                            .on_hover_text("Sway labeled splats, eg. to bring foliage to life");
+                        }
+                    }
+                }
+
                 if process.is_training() {
                     ui.add_space(15.0);
 
@@ -336,6 +1815,31 @@ For bigger training runs consider using the native app."#,
 
                     ui.add_space(15.0);
 
+                    if ui
+                        .add(
+                            egui::DragValue::new(&mut self.lr_scale)
+                                .range(0.01..=10.0)
+                                .speed(0.01)
+                                .prefix("LR ×"),
+                        )
+                        .on_hover_text("Scale the learning rates of the run")
+                        .changed()
+                    {
+                        process.send_train_command(TrainCommand::SetLrScale {
+                            scale: self.lr_scale,
+                        });
+                    }
+
+                    if ui
+                        .button("Eval now")
+                        .on_hover_text("Evaluate after the next step")
+                        .clicked()
+                    {
+                        process.send_train_command(TrainCommand::Eval);
+                    }
+
+                    ui.add_space(15.0);
+
                     ui.scope(|ui| {
                         ui.style_mut().visuals.selection.bg_fill = Color32::DARK_RED;
                         if ui
@@ -348,26 +1852,54 @@ For bigger training runs consider using the native app."#,
 
                     ui.add_space(15.0);
 
-                    if let Some(splats) = splats {
+                    if ui
+                        .selectable_label(view_settings.show_cameras, "📷 Show cameras")
+                        .clicked()
+                    {
+                        view_settings.show_cameras = !view_settings.show_cameras;
+                    }
+
+                    ui.add_space(15.0);
+
+                    // Runs without a disk to export to hand over their exports instead.
+                    if let Some((iter, files)) = &self.run_export {
+                        if ui
+                            .button(format!("💾 Download step {iter}"))
+                            .on_hover_text("Save the latest export of the run")
+                            .clicked()
+                        {
+                            for file in files.clone() {
+                                tokio_wasm::task::spawn(async move {
+                                    let _ = rrfd::save_file(&file.name, file.data.to_vec())
+                                        .await
+                                        .inspect_err(|e| log::error!("Failed to save file: {e}"));
+                                });
+                            }
+                        }
+                    }
+
+                    if self.export.as_mut().is_some_and(ExportJob::poll) {
+                        self.export = None;
+                    }
+                    if let Some(job) = &self.export {
+                        if Self::export_status(ui, job) {
+                            self.export = None;
+                        }
+                    } else if let Some(splats) = splats {
                         if ui.button("⬆ Export").clicked() {
-                            let fut = async move {
-                                let data = splat_export::splat_to_ply(splats).await;
-
-                                let data = match data {
-                                    Ok(data) => data,
-                                    Err(e) => {
-                                        log::error!("Failed to serialize file: {e}");
-                                        return;
-                                    }
-                                };
-
-                                // Not sure where/how to show this error if any.
-                                let _ = rrfd::save_file("export.ply", data)
-                                    .await
-                                    .inspect_err(|e| log::error!("Failed to save file: {e}"));
+                            // Viewers expect sRGB colors, not the linear colors of HDR splats.
+                            let splats = match self.tonemap {
+                                Some(tonemap) => tonemap.apply_splats(splats),
+                                None => splats,
                             };
-
-                            tokio_wasm::task::spawn(fut);
+                            self.export = Some(ExportJob::spawn(
+                                splats,
+                                self.export_transform,
+                                self.crop.as_ref().and_then(CropBox::export_bounds),
+                                self.shown_labels(frame),
+                                process.get_export_settings(),
+                                ui.ctx(),
+                            ));
                         }
                     }
                 }
@@ -393,22 +1925,62 @@ For bigger training runs consider using the native app."#,
                         });
                     }
 
+                    let settings = process.get_cam_settings();
+                    let mode = settings.controller;
+
+                    ui.menu_button(format!("🎥 {}", mode.label()), |ui| {
+                        let mut new_settings = settings.clone();
+                        for mode in ControllerMode::ALL {
+                            ui.radio_value(&mut new_settings.controller, mode, mode.label());
+                        }
+                        ui.separator();
+
+                        let mut speed = new_settings.speed_scale.unwrap_or(1.0);
+                        ui.label("Speed");
+                        ui.add(Slider::new(&mut speed, 0.01..=100.0).logarithmic(true));
+                        new_settings.speed_scale = Some(speed);
+
+                        let mut inertia = new_settings.inertia.unwrap_or(1.0);
+                        ui.label("Inertia");
+                        ui.add(Slider::new(&mut inertia, 0.0..=4.0));
+                        new_settings.inertia = Some(inertia);
+
+                        if new_settings.controller != settings.controller
+                            || new_settings.speed_scale != settings.speed_scale.or(Some(1.0))
+                            || new_settings.inertia != settings.inertia.or(Some(1.0))
+                        {
+                            process.set_cam_settings(new_settings);
+                        }
+                    });
+
                     ui.selectable_label(false, "Controls")
                         .on_hover_ui_at_pointer(|ui| {
                             ui.heading("Controls");
 
-                            ui.label("• Left click and drag to orbit");
-                            ui.label(
-                                "• Right click, or left click + spacebar, and drag to look around.",
-                            );
+                            if mode == ControllerMode::Orbit {
+                                ui.label("• Left click and drag to orbit");
+                                ui.label(
+                                    "• Right click, or left click + spacebar, and drag to look around.",
+                                );
+                                ui.label("• Scroll to zoom");
+                            } else {
+                                ui.label("• Left or right click and drag to look around.");
+                                ui.label("• Scroll to change the movement speed");
+                            }
                             ui.label("• Middle click, or left click + control, and drag to pan");
-                            ui.label("• Scroll to zoom");
-                            ui.label("• WASD to fly, Q&E to move up & down.");
+                            if mode == ControllerMode::Fps {
+                                ui.label("• WASD to walk, Q&E to move up & down.");
+                            } else {
+                                ui.label("• WASD to fly, Q&E to move up & down.");
+                            }
                             ui.label("• Z&C to roll, X to reset roll");
                             ui.label("• Shift to move faster");
                         });
                 }
             });
+            if view_settings != process.get_view_settings() {
+                process.set_view_settings(view_settings);
+            }
         }
     }
 
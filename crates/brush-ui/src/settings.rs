@@ -1,5 +1,8 @@
-use crate::{BrushUiProcess, panels::AppPanel};
-use brush_process::config::ProcessArgs;
+use crate::{BrushUiProcess, app::ExportSettings, panels::AppPanel, wizard::Wizard};
+use brush_dataset::{config::PointInit, scene_loader::ViewSampling, tonemap::Tonemap};
+use brush_process::{config::ProcessArgs, message::ProcessMessage};
+#[cfg(not(target_family = "wasm"))]
+use brush_sfm::{SfmEngine, SfmMatcher};
 use brush_vfs::DataSource;
 use egui::{Align2, Slider, Ui};
 use tokio::sync::oneshot::Sender;
@@ -9,6 +12,15 @@ pub struct SettingsPanel {
     url: String,
     send_args: Option<Sender<ProcessArgs>>,
     show_url_dialog: bool,
+    passphrase_prompt: Option<PassphrasePrompt>,
+    wizard: Option<Wizard>,
+    wizard_offered: bool,
+}
+
+struct PassphrasePrompt {
+    reply: tokio::sync::mpsc::Sender<String>,
+    retry: bool,
+    passphrase: String,
 }
 
 impl SettingsPanel {
@@ -19,6 +31,68 @@ impl SettingsPanel {
             url: "splat.com/example.ply".to_owned(),
             send_args: None,
             show_url_dialog: false,
+            passphrase_prompt: None,
+            wizard: None,
+            wizard_offered: false,
+        }
+    }
+
+    // Start loading `source`, with the settings picked here if it turns out to need them.
+    fn load(&mut self, source: DataSource, process: &dyn BrushUiProcess) {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        self.send_args = Some(sender);
+        process.start_new_process(source, receiver);
+    }
+
+    fn passphrase_window(&mut self, ui: &egui::Ui) {
+        let Some(prompt) = self.passphrase_prompt.as_mut() else {
+            return;
+        };
+        // Stop asking if loading was cancelled.
+        if prompt.reply.is_closed() {
+            self.passphrase_prompt = None;
+            return;
+        }
+
+        let mut submit = false;
+        let mut cancel = false;
+        egui::Window::new("Encrypted file")
+            .resizable(false)
+            .collapsible(false)
+            .default_pos(ui.ctx().screen_rect().center())
+            .pivot(Align2::CENTER_CENTER)
+            .show(ui.ctx(), |ui| {
+                ui.label("This file is encrypted. Enter the passphrase to open it.");
+                if prompt.retry {
+                    ui.colored_label(ui.visuals().error_fg_color, "Wrong passphrase, try again.");
+                }
+                ui.add_space(5.0);
+
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut prompt.passphrase)
+                        .password(true)
+                        .desired_width(300.0),
+                );
+                response.request_focus();
+                if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    submit = true;
+                }
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    submit |= ui.button("Unlock").clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if submit && !prompt.passphrase.is_empty() {
+            let _ = prompt
+                .reply
+                .try_send(std::mem::take(&mut prompt.passphrase));
+            self.passphrase_prompt = None;
+        } else if cancel {
+            // Dropping the reply makes loading fail.
+            self.passphrase_prompt = None;
         }
     }
 
@@ -49,6 +123,24 @@ impl SettingsPanel {
                     .custom_formatter(|n, _| format!("{:.0}k", n as f32 / 1000.0))
                     .clamping(egui::SliderClamping::Never));
 
+                ui.label("Coarse to fine");
+                slider(ui, &mut self.args.train_config.coarse_to_fine_steps, 0..=10000, " steps at lower resolution", false);
+
+                let tc = &mut self.args.train_config;
+                let mut patches = tc.patch_size.is_some();
+                ui.checkbox(&mut patches, "Train on crops of the images")
+                    .on_hover_text("Needs far less GPU memory for very large images");
+                if patches != tc.patch_size.is_some() {
+                    tc.patch_size = patches.then_some(1024);
+                }
+                if let Some(patch_size) = tc.patch_size.as_mut() {
+                    slider(ui, patch_size, 128..=4096, " px crops", false);
+                }
+
+                ui.label("Near plane")
+                    .on_hover_text("Splats closer to the cameras are left out. Raise this when splats grow huge right in front of the cameras");
+                slider(ui, &mut tc.near_plane, 0.001..=10.0, "", true);
+
                 ui.collapsing("Learning rates", |ui| {
                     let tc = &mut self.args.train_config;
                     slider(ui, &mut tc.lr_mean, 1e-7..=1e-4, "Mean learning rate start", true);
@@ -75,6 +167,40 @@ impl SettingsPanel {
                     slider(ui, &mut tc.ssim_weight, 0.0..=1.0, "ssim weight", false);
                     slider(ui, &mut tc.opac_loss_weight, 1e-9..=1e-7, "Splat opacity loss weight", true);
                     slider(ui, &mut tc.match_alpha_weight, 0.01..=1.0, "Alpha match weight", false);
+                    slider(ui, &mut tc.depth_weight, 0.0..=1.0, "Depth map weight", false);
+                    slider(ui, &mut tc.depth_confidence_power, 0.0..=4.0, "Depth confidence power", false);
+                    ui.checkbox(&mut tc.half_precision, "Compute losses in half precision (f16)")
+                        .on_hover_text("Saves memory and time, if the GPU supports f16");
+                    ui.checkbox(&mut tc.robust_loss, "Ignore transient objects")
+                        .on_hover_text("Leaves people and cars passing through the capture out of the loss");
+                    let mut by_loss = tc.view_sampling == ViewSampling::Loss;
+                    if ui.checkbox(&mut by_loss, "Train views with a high loss more often")
+                        .on_hover_text("Helps parts of the scene covered by few views")
+                        .clicked()
+                    {
+                        tc.view_sampling = if by_loss { ViewSampling::Loss } else { ViewSampling::Uniform };
+                    }
+                });
+
+                ui.collapsing("White balance", |ui| {
+                    let tc = &mut self.args.train_config;
+                    ui.checkbox(&mut tc.wb_correction, "Correct white balance changes between frames");
+                    if tc.wb_correction {
+                        slider(ui, &mut tc.wb_group_threshold, 0.005..=0.1, "Group threshold", true);
+                        slider(ui, &mut tc.lr_wb, 1e-4..=1e-2, "Learning rate", true);
+                    }
+                });
+
+                ui.collapsing("Flat splats", |ui| {
+                    let tc = &mut self.args.train_config;
+                    ui.checkbox(&mut tc.flat_splats, "Train flat splats")
+                        .on_hover_text("Better geometry for meshing, at some cost in image quality");
+                    if tc.flat_splats {
+                        slider(ui, &mut tc.depth_distortion_weight, 1.0..=1000.0, "Depth distortion weight", true);
+                        slider(ui, &mut tc.depth_distortion_start, 0..=15000, "Depth distortion start", false);
+                        slider(ui, &mut tc.normal_consistency_weight, 0.001..=1.0, "Normal consistency weight", true);
+                        slider(ui, &mut tc.normal_consistency_start, 0..=15000, "Normal consistency start", false);
+                    }
                 });
 
                 ui.add_space(15.0);
@@ -84,6 +210,12 @@ impl SettingsPanel {
                 ui.label("Spherical Harmonics Degree:");
                 ui.add(Slider::new(&mut self.args.model_config.sh_degree, 0..=4));
 
+                ui.collapsing("Initialization without point cloud", |ui| {
+                    let mc = &mut self.args.model_config;
+                    slider(ui, &mut mc.random_init_count, 1000..=100000, "Initial splats", true);
+                    ui.checkbox(&mut mc.random_init_frustum, "Sample inside camera frustums");
+                });
+
                 ui.add_space(15.0);
 
                 // Dataset
@@ -126,6 +258,76 @@ impl SettingsPanel {
                         .prefix("Load every 1/").suffix(" points"));
                 }
 
+                let mut smooth_poses = self.args.load_config.smooth_poses.is_some();
+                if ui.checkbox(&mut smooth_poses, "Smooth video poses")
+                    .on_hover_text("Smooth jittery camera poses of frames of a video, and replace poses far off the trajectory")
+                    .clicked()
+                {
+                    self.args.load_config.smooth_poses = if smooth_poses { Some(5) } else { None };
+                }
+                if let Some(window) = self.args.load_config.smooth_poses.as_mut() {
+                    ui.add(Slider::new(window, 1..=30).clamping(egui::SliderClamping::Never)
+                        .prefix("Over ").suffix(" frames on each side"));
+                }
+
+                let mut track_weighted = self.args.load_config.point_init == PointInit::TrackWeighted;
+                if ui.checkbox(&mut track_weighted, "Weight initial points by their tracks")
+                    .on_hover_text("Points seen by many images with little error start smaller and more opaque")
+                    .clicked()
+                {
+                    self.args.load_config.point_init = if track_weighted { PointInit::TrackWeighted } else { PointInit::Neighbors };
+                }
+
+                ui.checkbox(&mut self.args.load_config.init_surface_aligned, "Align scan points to their surface")
+                    .on_hover_text("Splats of a .las or .e57 scan in the dataset start as flat discs on the surface around them");
+
+                ui.checkbox(&mut self.args.load_config.exif_intrinsics, "Focal lengths from EXIF")
+                    .on_hover_text("Use the focal lengths photos were taken with, for datasets with rough intrinsics");
+
+                ui.checkbox(&mut self.args.load_config.hdr, "HDR images")
+                    .on_hover_text("Train on EXR and 16-bit PNG images in linear color, keeping highlights brighter than white");
+                if self.args.load_config.hdr {
+                    let tonemap = &mut self.args.load_config.tonemap;
+                    egui::ComboBox::from_label("Tonemapping")
+                        .selected_text(format!("{tonemap:?}"))
+                        .show_ui(ui, |ui| {
+                            for option in [Tonemap::Clip, Tonemap::Reinhard, Tonemap::Aces] {
+                                ui.selectable_value(tonemap, option, format!("{option:?}"));
+                            }
+                        });
+                }
+
+                #[cfg(not(target_family = "wasm"))]
+                {
+                    let sfm = &mut self.args.sfm_config;
+                    egui::ComboBox::from_label("Reconstruct cameras")
+                        .selected_text(match sfm.sfm {
+                            None => "Off",
+                            Some(SfmEngine::Colmap) => "COLMAP",
+                            Some(SfmEngine::Glomap) => "GLOMAP",
+                            Some(SfmEngine::Command) => "Custom command",
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut sfm.sfm, None, "Off");
+                            ui.selectable_value(&mut sfm.sfm, Some(SfmEngine::Colmap), "COLMAP");
+                            ui.selectable_value(&mut sfm.sfm, Some(SfmEngine::Glomap), "GLOMAP");
+                            ui.selectable_value(&mut sfm.sfm, Some(SfmEngine::Command), "Custom command");
+                        })
+                        .response
+                        .on_hover_text("Find the cameras of a directory of photos without any, with an installed structure from motion engine");
+                    if sfm.sfm == Some(SfmEngine::Command) {
+                        ui.label("Command, with {images} and {output} directories:");
+                        ui.text_edit_singleline(sfm.sfm_command.get_or_insert_default())
+                            .on_hover_text("A tool that writes a COLMAP model of pinhole cameras to {output}");
+                    } else if sfm.sfm.is_some() {
+                        let mut sequential = sfm.sfm_matcher == SfmMatcher::Sequential;
+                        if ui.checkbox(&mut sequential, "Photos are frames of a video").clicked() {
+                            sfm.sfm_matcher = if sequential { SfmMatcher::Sequential } else { SfmMatcher::Exhaustive };
+                        }
+                        ui.checkbox(&mut sfm.sfm_single_camera, "All photos taken with one camera");
+                    }
+                }
+
                 ui.add_space(15.0);
 
                 // Process
@@ -148,6 +350,28 @@ impl SettingsPanel {
                         .clamping(egui::SliderClamping::Never).prefix("every ").suffix(" steps"));
                     text_input(ui, "Export path:", &mut pc.export_path);
                     text_input(ui, "Export filename:", &mut pc.export_name);
+                    ui.checkbox(&mut pc.export_progressive, "Most important splats first")
+                        .on_hover_text("Viewers that stream files in show the scene after loading only the first part");
+
+                    let mut simplify = pc.simplify_splats.is_some();
+                    ui.checkbox(&mut simplify, "Simplify the final export")
+                        .on_hover_text("Remove the splats adding least to the training views");
+                    if simplify != pc.simplify_splats.is_some() {
+                        pc.simplify_splats = simplify.then_some(1_000_000);
+                    }
+                    if let Some(budget) = pc.simplify_splats.as_mut() {
+                        ui.add(Slider::new(budget, 10_000..=10_000_000)
+                            .clamping(egui::SliderClamping::Never).logarithmic(true).suffix(" splats"));
+                    }
+
+                    let mut encrypt = pc.export_passphrase.is_some();
+                    ui.checkbox(&mut encrypt, "Encrypt exports with a passphrase");
+                    if encrypt != pc.export_passphrase.is_some() {
+                        pc.export_passphrase = encrypt.then(String::new);
+                    }
+                    if let Some(passphrase) = pc.export_passphrase.as_mut() {
+                        ui.add(egui::TextEdit::singleline(passphrase).password(true).hint_text("Passphrase"));
+                    }
                 });
 
                 ui.collapsing("Evaluate", |ui| {
@@ -157,6 +381,20 @@ impl SettingsPanel {
                     ui.checkbox(&mut pc.eval_save_to_disk, "Save Eval images to disk");
                 });
 
+                ui.collapsing("Replay", |ui| {
+                    let pc = &mut self.args.process_config;
+                    let mut record = pc.replay_snapshot_every.is_some();
+                    ui.checkbox(&mut record, "Record snapshots to replay training");
+                    if record != pc.replay_snapshot_every.is_some() {
+                        pc.replay_snapshot_every = if record { Some(250) } else { None };
+                    }
+                    if let Some(every) = pc.replay_snapshot_every.as_mut() {
+                        ui.add(Slider::new(every, 10..=5000)
+                            .clamping(egui::SliderClamping::Never).prefix("every ").suffix(" steps"));
+                        *every = (*every).max(1);
+                    }
+                });
+
                 ui.add_space(15.0);
 
                 // Rerun
@@ -211,7 +449,47 @@ impl AppPanel for SettingsPanel {
         "Settings".to_owned()
     }
 
+    fn on_message(&mut self, message: &ProcessMessage, process: &dyn BrushUiProcess) {
+        if let Some(wizard) = self.wizard.as_mut() {
+            if !wizard.on_message(message, process) {
+                self.wizard = None;
+            }
+        }
+
+        match message {
+            ProcessMessage::NewSource => {
+                self.passphrase_prompt = None;
+            }
+            ProcessMessage::PassphraseRequired { retry, reply } => {
+                self.passphrase_prompt = Some(PassphrasePrompt {
+                    reply: reply.clone(),
+                    retry: *retry,
+                    passphrase: String::new(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn on_dropped(&mut self, source: &DataSource, process: &dyn BrushUiProcess) {
+        self.load(source.clone(), process);
+    }
+
+    fn on_error(&mut self, error: &anyhow::Error, _: &dyn BrushUiProcess) {
+        if let Some(wizard) = self.wizard.as_mut() {
+            wizard.on_error(error);
+        }
+    }
+
     fn ui(&mut self, ui: &mut egui::Ui, process: &dyn BrushUiProcess) {
+        // Offer a guided setup to new users, unless something is being loaded already.
+        if !self.wizard_offered {
+            self.wizard_offered = true;
+            if !process.is_loading() {
+                self.wizard = Some(Wizard::new());
+            }
+        }
+
         egui::ScrollArea::vertical().show(ui, |ui| {
             ui.add_space(20.0);
 
@@ -269,6 +547,11 @@ impl AppPanel for SettingsPanel {
                 }
             });
 
+            ui.add_space(5.0);
+            if ui.button("✨ Guided setup").clicked() {
+                self.wizard = Some(Wizard::new());
+            }
+
             ui.add_space(15.0);
 
             // URL dialog window
@@ -313,9 +596,7 @@ impl AppPanel for SettingsPanel {
             }
 
             if let Some(source) = load_option {
-                let (sender, receiver) = tokio::sync::oneshot::channel();
-                self.send_args = Some(sender);
-                process.start_new_process(source, receiver);
+                self.load(source, process);
             }
         });
 
@@ -323,6 +604,22 @@ impl AppPanel for SettingsPanel {
         // this wont' do anything, only if process args are needed).
         if process.is_loading() {
             self.ui_window(ui);
+
+            // Exports from the scene view are encrypted and ordered like those of training.
+            let export = ExportSettings {
+                passphrase: self.args.process_config.export_passphrase.clone().filter(|p| !p.is_empty()),
+                progressive: self.args.process_config.export_progressive,
+            };
+            if export != process.get_export_settings() {
+                process.set_export_settings(export);
+            }
+        }
+        self.passphrase_window(ui);
+
+        if let Some(wizard) = self.wizard.as_mut() {
+            if !wizard.ui(ui, process) {
+                self.wizard = None;
+            }
         }
     }
 }
@@ -1,5 +1,5 @@
 use crate::{BrushUiProcess, panels::AppPanel};
-use brush_process::message::ProcessMessage;
+use brush_process::{message::ProcessMessage, planner::RunPlan};
 use burn_cubecl::cubecl::Runtime;
 use burn_wgpu::{WgpuDevice, WgpuRuntime};
 use web_time::Duration;
@@ -10,6 +10,7 @@ pub struct StatsPanel {
     last_train_step: (Duration, u32),
     train_iter_per_s: f32,
     last_eval: Option<String>,
+    plan: Option<RunPlan>,
     cur_sh_degree: u32,
     training_started: bool,
     num_splats: u32,
@@ -24,6 +25,7 @@ impl StatsPanel {
             last_train_step: (Duration::from_secs(0), 0),
             train_iter_per_s: 0.0,
             last_eval: None,
+            plan: None,
             training_started: false,
             num_splats: 0,
             frames: 0,
@@ -31,6 +33,17 @@ impl StatsPanel {
             adapter_info,
         }
     }
+
+    fn record_step(&mut self, iter: u32, total_elapsed: Duration) {
+        let elapsed = total_elapsed
+            .saturating_sub(self.last_train_step.0)
+            .as_secs_f32();
+        if elapsed > 0.0 {
+            let current_iter_per_s = iter.saturating_sub(self.last_train_step.1) as f32 / elapsed;
+            self.train_iter_per_s = 0.95 * self.train_iter_per_s + 0.05 * current_iter_per_s;
+        }
+        self.last_train_step = (total_elapsed, iter);
+    }
 }
 
 fn bytes_format(bytes: u64) -> String {
@@ -68,6 +81,7 @@ impl AppPanel for StatsPanel {
                 self.num_splats = 0;
                 self.cur_sh_degree = 0;
                 self.last_eval = None;
+                self.plan = None;
                 self.training_started = *training;
             }
             ProcessMessage::ViewSplats {
@@ -75,6 +89,7 @@ impl AppPanel for StatsPanel {
                 splats,
                 frame,
                 total_frames: _,
+                labels: _,
             } => {
                 self.num_splats = splats.num_splats();
                 self.frames = *frame;
@@ -82,16 +97,25 @@ impl AppPanel for StatsPanel {
             }
             ProcessMessage::TrainStep {
                 splats,
-                stats: _,
                 iter,
                 total_elapsed,
+                ..
             } => {
                 self.cur_sh_degree = splats.sh_degree();
                 self.num_splats = splats.num_splats();
-                let current_iter_per_s = (iter - self.last_train_step.1) as f32
-                    / (*total_elapsed - self.last_train_step.0).as_secs_f32();
-                self.train_iter_per_s = 0.95 * self.train_iter_per_s + 0.05 * current_iter_per_s;
-                self.last_train_step = (*total_elapsed, *iter);
+                self.record_step(*iter, *total_elapsed);
+            }
+            ProcessMessage::RemoteTrainStep {
+                iter,
+                num_splats,
+                total_elapsed,
+                ..
+            } => {
+                self.num_splats = *num_splats;
+                self.record_step(*iter, *total_elapsed);
+            }
+            ProcessMessage::RunPlan { plan } => {
+                self.plan = Some(plan.clone());
             }
             ProcessMessage::EvalResult {
                 iter: _,
@@ -170,6 +194,34 @@ impl AppPanel for StatsPanel {
                             ))
                         ));
                         ui.end_row();
+
+                        if let Some(plan) = &self.plan {
+                            // Once training runs, the measured speed beats the estimate.
+                            let remaining = if self.train_iter_per_s > 0.0 {
+                                let steps = plan.total_steps.saturating_sub(self.last_train_step.1);
+                                Duration::from_secs_f32(steps as f32 / self.train_iter_per_s)
+                            } else {
+                                plan.duration
+                            };
+                            ui.label("Time remaining");
+                            ui.label(format!(
+                                "~{}",
+                                humantime::format_duration(Duration::from_secs(
+                                    remaining.as_secs()
+                                ))
+                            ));
+                            ui.end_row();
+
+                            ui.label("Peak memory");
+                            let text = format!("up to {}", bytes_format(plan.peak_memory));
+                            if plan.exceeds_budget() {
+                                ui.colored_label(ui.visuals().warn_fg_color, text)
+                                    .on_hover_text("This run is likely to run out of GPU memory");
+                            } else {
+                                ui.label(text);
+                            }
+                            ui.end_row();
+                        }
                     });
             }
 
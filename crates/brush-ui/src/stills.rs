@@ -0,0 +1,268 @@
+use brush_process::{
+    message::ProcessMessage,
+    stills::{render_shots, zip_stills},
+};
+use brush_render::{
+    MainBackend,
+    gaussian_splats::Splats,
+    shots::{Shot, ShotList},
+};
+use egui::Ui;
+use rrfd::PickFileError;
+use tokio::{
+    io::AsyncReadExt,
+    sync::oneshot::{Receiver, channel},
+};
+use tokio_with_wasm::alias as tokio_wasm;
+
+use crate::{BrushUiProcess, panels::AppPanel};
+
+/// Bookmark named cameras, and render a still of each of them in one go.
+///
+/// Shot lists are saved as JSON, which can be rendered with `--render-shots`, eg. to render the
+/// same set of stills after every retraining.
+pub struct StillsPanel {
+    shots: ShotList,
+    splats: Option<Splats<MainBackend>>,
+    // Name of the next shot to add.
+    shot_name: String,
+    loading: Option<Receiver<anyhow::Result<ShotList>>>,
+    rendering: Option<Receiver<anyhow::Result<usize>>>,
+    // Outcome of the last render, to show until the next one.
+    render_status: Option<String>,
+}
+
+async fn load_shots() -> anyhow::Result<ShotList> {
+    let mut reader = rrfd::pick_file().await?;
+    let mut data = vec![];
+    reader.read_to_end(&mut data).await?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+async fn render_all(splats: Splats<MainBackend>, shots: ShotList) -> anyhow::Result<usize> {
+    let stills = render_shots(&splats, &shots, None).await?;
+    let data = zip_stills(&stills)?;
+    rrfd::save_file("stills.zip", data).await?;
+    Ok(stills.len())
+}
+
+impl StillsPanel {
+    pub(crate) fn new() -> Self {
+        Self {
+            shots: ShotList::default(),
+            splats: None,
+            shot_name: String::new(),
+            loading: None,
+            rendering: None,
+            render_status: None,
+        }
+    }
+
+    fn poll(&mut self) {
+        if let Some(Ok(result)) = self.loading.as_mut().map(Receiver::try_recv) {
+            self.loading = None;
+            match result {
+                Ok(shots) => self.shots = shots,
+                Err(e) => log::error!("Failed to load shot list: {e}"),
+            }
+        }
+        if let Some(Ok(result)) = self.rendering.as_mut().map(Receiver::try_recv) {
+            self.rendering = None;
+            self.render_status = match result {
+                Ok(count) => Some(format!("Rendered {count} stills")),
+                Err(e) => match e.downcast_ref::<PickFileError>() {
+                    Some(PickFileError::NoFileSelected) => None,
+                    _ => {
+                        log::error!("Failed to render stills: {e}");
+                        Some(format!("Failed to render stills: {e}"))
+                    }
+                },
+            };
+        }
+    }
+
+    fn file_buttons(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(self.loading.is_none(), egui::Button::new("📂 Load"))
+                .clicked()
+            {
+                let (sender, receiver) = channel();
+                let ctx = ui.ctx().clone();
+                tokio_wasm::task::spawn(async move {
+                    let _ = sender.send(load_shots().await);
+                    ctx.request_repaint();
+                });
+                self.loading = Some(receiver);
+            }
+
+            let has_shots = !self.shots.shots.is_empty();
+            if ui
+                .add_enabled(has_shots, egui::Button::new("💾 Save"))
+                .clicked()
+            {
+                match serde_json::to_vec_pretty(&self.shots) {
+                    Ok(data) => {
+                        tokio_wasm::task::spawn(async move {
+                            let _ = rrfd::save_file("shots.json", data)
+                                .await
+                                .inspect_err(|e| log::error!("Failed to save file: {e}"));
+                        });
+                    }
+                    Err(e) => log::error!("Failed to serialize shot list: {e}"),
+                }
+            }
+
+            if ui
+                .add_enabled(has_shots, egui::Button::new("🗑 Clear"))
+                .clicked()
+            {
+                self.shots = ShotList::default();
+            }
+        });
+    }
+
+    fn render_button(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            if self.rendering.is_some() {
+                ui.spinner();
+                ui.label(format!("Rendering {} stills...", self.shots.shots.len()));
+                return;
+            }
+            let button = ui
+                .add_enabled(
+                    self.splats.is_some(),
+                    egui::Button::new(format!("📸 Render {} stills", self.shots.shots.len())),
+                )
+                .on_hover_text("Render every shot at its resolution, and save them as a zip");
+            if let (true, Some(splats)) = (button.clicked(), self.splats.clone()) {
+                let (sender, receiver) = channel();
+                let ctx = ui.ctx().clone();
+                let shots = self.shots.clone();
+                tokio_wasm::task::spawn(async move {
+                    let _ = sender.send(render_all(splats, shots).await);
+                    ctx.request_repaint();
+                });
+                self.rendering = Some(receiver);
+                self.render_status = None;
+            }
+            if let Some(status) = &self.render_status {
+                ui.label(status);
+            }
+        });
+    }
+
+    fn shot_list(&mut self, ui: &mut Ui, process: &dyn BrushUiProcess) {
+        let mut remove = None;
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (index, shot) in self.shots.shots.iter_mut().enumerate() {
+                ui.push_id(index, |ui| {
+                    egui::Frame::group(ui.style()).show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.text_edit_singleline(&mut shot.name);
+                            if ui.small_button("👁").on_hover_text("Go to shot").clicked() {
+                                process.set_camera(&shot.camera());
+                            }
+                            if ui
+                                .small_button("⟲")
+                                .on_hover_text("Replace with current view")
+                                .clicked()
+                            {
+                                *shot = Shot {
+                                    name: std::mem::take(&mut shot.name),
+                                    exposure: shot.exposure,
+                                    ..Shot::from_camera(
+                                        String::new(),
+                                        &process.current_camera(),
+                                        shot.size(),
+                                    )
+                                };
+                            }
+                            if ui.small_button("🗑").on_hover_text("Delete").clicked() {
+                                remove = Some(index);
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut shot.width)
+                                    .range(16..=16384)
+                                    .suffix(" px"),
+                            );
+                            ui.label("×");
+                            ui.add(
+                                egui::DragValue::new(&mut shot.height)
+                                    .range(16..=16384)
+                                    .suffix(" px"),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut shot.exposure)
+                                    .speed(0.05)
+                                    .range(-8.0..=8.0)
+                                    .suffix(" EV"),
+                            );
+                        });
+                    });
+                });
+            }
+        });
+
+        if let Some(index) = remove {
+            self.shots.shots.remove(index);
+        }
+    }
+}
+
+impl AppPanel for StillsPanel {
+    fn title(&self) -> String {
+        "Stills".to_owned()
+    }
+
+    fn on_message(&mut self, message: &ProcessMessage, _: &dyn BrushUiProcess) {
+        match message {
+            ProcessMessage::NewSource => self.splats = None,
+            ProcessMessage::ViewSplats { splats, .. }
+            | ProcessMessage::TrainStep { splats, .. } => {
+                self.splats = Some(*splats.clone());
+            }
+            _ => (),
+        }
+    }
+
+    fn ui(&mut self, ui: &mut Ui, process: &dyn BrushUiProcess) {
+        self.poll();
+
+        self.file_buttons(ui);
+        ui.add_space(6.0);
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.shot_name).hint_text("Shot name"));
+            if ui.button("➕ Add current view").clicked() {
+                let name = if self.shot_name.trim().is_empty() {
+                    format!("Shot {}", self.shots.shots.len() + 1)
+                } else {
+                    std::mem::take(&mut self.shot_name)
+                };
+                // Default to the resolution of the last shot, so a set of stills matches.
+                let size = self
+                    .shots
+                    .shots
+                    .last()
+                    .map_or(glam::uvec2(1920, 1080), Shot::size);
+                self.shots
+                    .shots
+                    .push(Shot::from_camera(name, &process.current_camera(), size));
+            }
+        });
+
+        if self.shots.shots.is_empty() {
+            ui.label("Move the camera and add shots to render stills of.");
+            return;
+        }
+
+        ui.add_space(6.0);
+        self.render_button(ui);
+        ui.add_space(6.0);
+        self.shot_list(ui, process);
+    }
+}
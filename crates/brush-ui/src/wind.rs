@@ -0,0 +1,179 @@
+use brush_render::{
+    MainBackend,
+    gaussian_splats::Splats,
+    wind::{Wind, animate_splats},
+};
+use egui::{Slider, Ui};
+use tokio::{
+    io::AsyncReadExt,
+    sync::oneshot::{Receiver, channel},
+};
+use tokio_with_wasm::alias as tokio_wasm;
+use web_time::Instant;
+
+use crate::edit::SplatLabeling;
+
+async fn load_wind() -> anyhow::Result<Wind> {
+    let mut reader = rrfd::pick_file().await?;
+    let mut data = vec![];
+    reader.read_to_end(&mut data).await?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Sway labeled groups of splats in the wind, eg. to bring captured foliage to life.
+///
+/// The wind settings are saved as JSON, next to the scene they were made for.
+pub(crate) struct WindControls {
+    wind: Wind,
+    enabled: bool,
+    start: Instant,
+    loading: Option<Receiver<anyhow::Result<Wind>>>,
+}
+
+impl WindControls {
+    pub(crate) fn new() -> Self {
+        Self {
+            wind: Wind::default(),
+            enabled: true,
+            start: Instant::now(),
+            loading: None,
+        }
+    }
+
+    /// Whether any splats are swaying.
+    pub(crate) fn is_animating(&self) -> bool {
+        self.enabled && !self.wind.groups.is_empty()
+    }
+
+    /// The labeled splats as swayed by the wind right now.
+    ///
+    /// Returns None when the labels don't fit the splats, which also stops the animation.
+    pub(crate) fn animate(
+        &mut self,
+        splats: &Splats<MainBackend>,
+        labels: &SplatLabeling,
+    ) -> Option<Splats<MainBackend>> {
+        let animated = animate_splats(
+            splats,
+            labels.labels.clone(),
+            labels.names.len() as u32,
+            &self.wind,
+            self.start.elapsed().as_secs_f32(),
+        );
+        match animated {
+            Ok(animated) => Some(animated),
+            Err(e) => {
+                log::error!("Failed to animate splats: {e}");
+                self.enabled = false;
+                None
+            }
+        }
+    }
+
+    /// Check for loaded wind settings, returns whether they changed.
+    pub(crate) fn poll(&mut self) -> bool {
+        let Some(loading) = self.loading.as_mut() else {
+            return false;
+        };
+        let Ok(result) = loading.try_recv() else {
+            return false;
+        };
+        self.loading = None;
+        match result {
+            Ok(wind) => {
+                self.wind = wind;
+                true
+            }
+            Err(e) => {
+                log::error!("Failed to load wind: {e}");
+                false
+            }
+        }
+    }
+
+    /// Settings of the groups with the given label names, returns whether anything changed.
+    pub(crate) fn ui(&mut self, ui: &mut Ui, names: &[String]) -> bool {
+        let mut changed = ui.checkbox(&mut self.enabled, "Animate").changed();
+        ui.separator();
+
+        // The first label is for unlabeled splats, which stay still.
+        for (label, name) in names.iter().enumerate().skip(1) {
+            let label = label as u32;
+            let mut swaying = self.wind.group(label).is_some();
+            if ui.checkbox(&mut swaying, name).changed() {
+                self.wind.set_group(label, swaying);
+                changed = true;
+            }
+            let Some(group) = self.wind.group_mut(label) else {
+                continue;
+            };
+            ui.indent(label, |ui| {
+                changed |= ui
+                    .add(
+                        Slider::new(&mut group.amplitude, 0.001..=1.0)
+                            .logarithmic(true)
+                            .text("amplitude"),
+                    )
+                    .changed();
+                changed |= ui
+                    .add(Slider::new(&mut group.frequency, 0.05..=5.0).text("sways per second"))
+                    .changed();
+                changed |= ui
+                    .add(
+                        Slider::new(&mut group.wavelength, 0.1..=50.0)
+                            .logarithmic(true)
+                            .text("gust size"),
+                    )
+                    .changed();
+                changed |= ui
+                    .add(Slider::new(&mut group.turbulence, 0.0..=1.0).text("flutter"))
+                    .changed();
+                ui.horizontal(|ui| {
+                    ui.label("direction");
+                    let dir = &mut group.direction;
+                    for value in [&mut dir.x, &mut dir.y, &mut dir.z] {
+                        changed |= ui
+                            .add(egui::DragValue::new(value).speed(0.01).range(-1.0..=1.0))
+                            .changed();
+                    }
+                });
+            });
+        }
+        if names.len() <= 1 {
+            ui.label("Label splats while editing to make them sway.");
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(self.loading.is_none(), egui::Button::new("📂 Load"))
+                .clicked()
+            {
+                let (sender, receiver) = channel();
+                let ctx = ui.ctx().clone();
+                tokio_wasm::task::spawn(async move {
+                    let _ = sender.send(load_wind().await);
+                    ctx.request_repaint();
+                });
+                self.loading = Some(receiver);
+            }
+
+            if ui
+                .add_enabled(!self.wind.groups.is_empty(), egui::Button::new("💾 Save"))
+                .clicked()
+            {
+                match serde_json::to_vec_pretty(&self.wind) {
+                    Ok(data) => {
+                        tokio_wasm::task::spawn(async move {
+                            let _ = rrfd::save_file("wind.json", data)
+                                .await
+                                .inspect_err(|e| log::error!("Failed to save file: {e}"));
+                        });
+                    }
+                    Err(e) => log::error!("Failed to serialize wind: {e}"),
+                }
+            }
+        });
+        changed
+    }
+}
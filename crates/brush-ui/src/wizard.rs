@@ -0,0 +1,295 @@
+use brush_dataset::health::{HealthIssue, check_health};
+use brush_process::{
+    config::{ProcessArgs, QualityPreset},
+    message::ProcessMessage,
+    planner::RunPlan,
+};
+use brush_vfs::DataSource;
+#[cfg(target_family = "wasm")]
+use brush_vfs::web_store::{StoredRun, WebStore};
+use egui::{Align2, RichText, Ui};
+use tokio::sync::oneshot::Sender;
+
+use crate::BrushUiProcess;
+
+enum Step {
+    Pick {
+        error: Option<String>,
+    },
+    Preset {
+        send_args: Sender<ProcessArgs>,
+    },
+    Loading,
+    Health {
+        issues: Vec<HealthIssue>,
+        plan: Option<RunPlan>,
+    },
+}
+
+/// Step by step setup of a first training run: pick data, choose a preset, check the dataset
+/// and start training.
+pub(crate) struct Wizard {
+    step: Step,
+    preset: QualityPreset,
+    // The training run kept in the browser, to pick up after the page was reloaded.
+    #[cfg(target_family = "wasm")]
+    stored_run: Option<StoredRun>,
+    #[cfg(target_family = "wasm")]
+    stored_pending: Option<tokio::sync::oneshot::Receiver<Option<StoredRun>>>,
+}
+
+#[cfg(target_family = "wasm")]
+fn read_stored_run() -> tokio::sync::oneshot::Receiver<Option<StoredRun>> {
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    tokio_with_wasm::alias::task::spawn(async move {
+        let run = match WebStore::open().await {
+            Ok(store) => store.stored_run().await.ok().flatten(),
+            Err(_) => None,
+        };
+        let _ = sender.send(run);
+    });
+    receiver
+}
+
+const STEPS: [&str; 3] = ["Pick photos", "Choose quality", "Check & train"];
+
+impl Wizard {
+    pub(crate) fn new() -> Self {
+        Self {
+            step: Step::Pick { error: None },
+            // Browsers have little memory to train with.
+            preset: if cfg!(target_family = "wasm") {
+                QualityPreset::Preview
+            } else {
+                QualityPreset::Balanced
+            },
+            #[cfg(target_family = "wasm")]
+            stored_run: None,
+            #[cfg(target_family = "wasm")]
+            stored_pending: Some(read_stored_run()),
+        }
+    }
+
+    fn step_index(&self) -> usize {
+        match self.step {
+            Step::Pick { .. } => 0,
+            Step::Preset { .. } => 1,
+            Step::Loading | Step::Health { .. } => 2,
+        }
+    }
+
+    /// Handle a process message. Returns false if the wizard should close.
+    pub(crate) fn on_message(
+        &mut self,
+        message: &ProcessMessage,
+        process: &dyn BrushUiProcess,
+    ) -> bool {
+        match message {
+            // Something else was loaded, the user found their way already.
+            ProcessMessage::NewSource => !matches!(self.step, Step::Pick { .. }),
+            ProcessMessage::Dataset { dataset } if matches!(self.step, Step::Loading) => {
+                // Hold off on training until the user has seen the report.
+                process.set_train_paused(true);
+                self.step = Step::Health {
+                    issues: check_health(dataset),
+                    plan: None,
+                };
+                true
+            }
+            ProcessMessage::RunPlan { plan } => {
+                if let Step::Health {
+                    plan: step_plan, ..
+                } = &mut self.step
+                {
+                    *step_plan = Some(plan.clone());
+                }
+                true
+            }
+            _ => true,
+        }
+    }
+
+    pub(crate) fn on_error(&mut self, error: &anyhow::Error) {
+        self.step = Step::Pick {
+            error: Some(format!("{error:#}")),
+        };
+    }
+
+    fn start(&mut self, source: DataSource, process: &dyn BrushUiProcess) {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        process.start_new_process(source, receiver);
+        self.step = Step::Preset { send_args: sender };
+    }
+
+    fn pick_ui(&mut self, ui: &mut Ui, process: &dyn BrushUiProcess) {
+        ui.label("Brush turns photos of a scene into a 3D model you can look around in.");
+        ui.label(
+            "Take photos while walking around your subject, then align them with a tool like \
+             COLMAP. Pick the result as a zip file or folder.",
+        );
+        ui.weak("Videos aren't supported directly, extract frames from them first.");
+        if let Step::Pick { error: Some(error) } = &self.step {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        ui.add_space(10.0);
+
+        ui.horizontal(|ui| {
+            if ui.button("📦 Pick a zip file").clicked() {
+                self.start(DataSource::PickFile, process);
+            }
+            let can_pick_dir = !cfg!(target_family = "wasm") && !cfg!(target_os = "android");
+            if can_pick_dir && ui.button("📁 Pick a folder").clicked() {
+                self.start(DataSource::PickDirectory, process);
+            }
+        });
+
+        #[cfg(target_family = "wasm")]
+        self.resume_ui(ui, process);
+    }
+
+    #[cfg(target_family = "wasm")]
+    fn resume_ui(&mut self, ui: &mut Ui, process: &dyn BrushUiProcess) {
+        if let Some(pending) = self.stored_pending.as_mut() {
+            if let Ok(run) = pending.try_recv() {
+                self.stored_run = run;
+                self.stored_pending = None;
+            }
+        }
+        let Some(run) = &self.stored_run else {
+            return;
+        };
+        ui.add_space(10.0);
+        let label = match run.checkpoint_iter {
+            Some(iter) => format!("⟳ Continue {} from step {iter}", run.dataset_name),
+            None => format!("⟳ Train {} again", run.dataset_name),
+        };
+        if ui
+            .button(label)
+            .on_hover_text("Pick up the last training run, kept in this browser")
+            .clicked()
+        {
+            self.start(DataSource::Stored, process);
+        }
+    }
+
+    fn preset_ui(&mut self, ui: &mut Ui) {
+        let Step::Preset { send_args } = &self.step else {
+            return;
+        };
+        // The process doesn't wait for settings when viewing a splat file.
+        if send_args.is_closed() {
+            self.step = Step::Pick {
+                error: Some(
+                    "This is a splat file, not a set of photos. It is shown in the viewer."
+                        .to_owned(),
+                ),
+            };
+            return;
+        }
+
+        ui.label("How good should the result be? Higher quality takes longer to train.");
+        ui.add_space(5.0);
+        for preset in QualityPreset::ALL {
+            ui.radio_value(
+                &mut self.preset,
+                preset,
+                RichText::new(preset.name()).strong(),
+            );
+            ui.indent(preset.name(), |ui| ui.weak(preset.description()));
+        }
+        ui.add_space(10.0);
+
+        if ui.button("Next").clicked() {
+            if let Step::Preset { send_args } = std::mem::replace(&mut self.step, Step::Loading) {
+                let _ = send_args.send(self.preset.args());
+            }
+        }
+    }
+
+    fn health_ui(&self, ui: &mut Ui, issues: &[HealthIssue], plan: Option<&RunPlan>) -> bool {
+        if issues.is_empty() {
+            ui.label("✅ Your photos look good.");
+        } else {
+            ui.label("Training will work, but the result might not be great:");
+            for issue in issues {
+                ui.label(format!("⚠ {}", issue.advice()));
+            }
+        }
+        ui.add_space(10.0);
+        ui.weak(format!("Training with the {} preset.", self.preset.name()));
+        match plan {
+            Some(plan) => {
+                let minutes = (plan.duration.as_secs_f32() / 60.0).ceil();
+                ui.weak(format!("This will take about {minutes:.0} minutes."));
+                if plan.exceeds_budget() {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        "⚠ This might not fit in GPU memory, try a lower quality preset.",
+                    );
+                }
+            }
+            None => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.weak("Estimating training time...");
+                });
+            }
+        }
+        ui.button("▶ Start training").clicked()
+    }
+
+    /// Draw the wizard. Returns false if it should close.
+    pub(crate) fn ui(&mut self, ui: &Ui, process: &dyn BrushUiProcess) -> bool {
+        let mut open = true;
+        let mut done = false;
+
+        egui::Window::new("Get started")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .default_width(420.0)
+            .default_pos(ui.ctx().screen_rect().center())
+            .pivot(Align2::CENTER_CENTER)
+            .show(ui.ctx(), |ui| {
+                let current = self.step_index();
+                ui.horizontal(|ui| {
+                    for (i, name) in STEPS.iter().enumerate() {
+                        let text = RichText::new(format!("{}. {name}", i + 1));
+                        let text = if i == current {
+                            text.strong()
+                        } else {
+                            text.weak()
+                        };
+                        ui.label(text);
+                        if i + 1 < STEPS.len() {
+                            ui.weak("→");
+                        }
+                    }
+                });
+                ui.separator();
+
+                match &self.step {
+                    Step::Pick { .. } => self.pick_ui(ui, process),
+                    Step::Preset { .. } => self.preset_ui(ui),
+                    Step::Loading => {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label("Loading photos...");
+                        });
+                    }
+                    Step::Health { issues, plan } => {
+                        if self.health_ui(ui, issues, plan.as_ref()) {
+                            process.set_train_paused(false);
+                            done = true;
+                        }
+                    }
+                }
+            });
+
+        if !open && matches!(self.step, Step::Health { .. }) {
+            // Don't leave training paused when closing the wizard.
+            process.set_train_paused(false);
+        }
+        open && !done
+    }
+}
@@ -0,0 +1,536 @@
+// Shows the scene in an OpenXR headset, next to the viewer on the desktop.
+//
+// OpenXR runtimes pick the GPU and create the Vulkan instance and device they present from. So
+// with a headset, the device the viewer and burn run on is created through OpenXR, see
+// [`create_xr_graphics`], and eframe and burn are set up on it like on any other device. The
+// headset then renders on its own thread, with the splats and camera the viewer last drew, and
+// paints each eye into the swapchain of the headset with a [`SplatPainter`], foveated as set in
+// the viewer.
+use std::{
+    ffi::{CString, c_void},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+};
+
+use anyhow::Context;
+use ash::vk::{self, Handle};
+use brush_render::{
+    MainBackend,
+    camera::Camera,
+    gaussian_splats::Splats,
+    painter::SplatPainter,
+    stereo::{EyeFov, Foveation, foveated_parts, headset_eye_camera},
+};
+use glam::{Quat, UVec2, Vec3};
+use openxr as xr;
+use wgpu::hal::{Api, api::Vulkan};
+
+const VIEW_TYPE: xr::ViewConfigurationType = xr::ViewConfigurationType::PRIMARY_STEREO;
+// Oldest Vulkan wgpu runs on.
+const VK_VERSION: u32 = vk::make_api_version(0, 1, 1, 0);
+
+/// The OpenXR instance and system of a headset, with the Vulkan handles of the device the viewer
+/// runs on, to start a session on with [`XrViewer::start`].
+pub struct XrContext {
+    instance: xr::Instance,
+    system: xr::SystemId,
+    session_info: xr::vulkan::SessionCreateInfo,
+}
+
+// The session info only holds handles of Vulkan objects, which live as long as the wgpu device.
+unsafe impl Send for XrContext {}
+
+/// A wgpu device created through OpenXR, for eframe and burn to run on.
+pub struct XrGraphics {
+    pub instance: wgpu::Instance,
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+}
+
+/// Connect to the headset of the OpenXR runtime, and create the Vulkan device it presents from.
+pub fn create_xr_graphics() -> anyhow::Result<(XrContext, XrGraphics)> {
+    let entry = unsafe { xr::Entry::load() }.context("No OpenXR loader found")?;
+    let available = entry.enumerate_extensions()?;
+    anyhow::ensure!(
+        available.khr_vulkan_enable2,
+        "The OpenXR runtime doesn't support Vulkan"
+    );
+    let mut extensions = xr::ExtensionSet::default();
+    extensions.khr_vulkan_enable2 = true;
+    let instance = entry.create_instance(
+        &xr::ApplicationInfo {
+            application_name: "Brush",
+            engine_name: "Brush",
+            api_version: xr::Version::new(1, 0, 0),
+            ..Default::default()
+        },
+        &extensions,
+        &[],
+    )?;
+    let system = instance
+        .system(xr::FormFactor::HEAD_MOUNTED_DISPLAY)
+        .context("No headset connected")?;
+    let requirements = instance.graphics_requirements::<xr::Vulkan>(system)?;
+    let version = xr::Version::new(1, 1, 0);
+    anyhow::ensure!(
+        requirements.min_api_version_supported <= version
+            && version.major() <= requirements.max_api_version_supported.major(),
+        "The OpenXR runtime needs Vulkan {}",
+        requirements.min_api_version_supported
+    );
+
+    let vk_entry = unsafe { ash::Entry::load() }.context("No Vulkan loader found")?;
+    let flags = wgpu::InstanceFlags::from_build_config().with_env();
+    let vk_extensions =
+        <Vulkan as Api>::Instance::desired_extensions(&vk_entry, VK_VERSION, flags)?;
+    let get_instance_proc_addr = vk_entry.static_fn().get_instance_proc_addr;
+
+    let vk_instance = unsafe {
+        let extension_names: Vec<_> = vk_extensions.iter().map(|e| e.as_ptr()).collect();
+        let name = CString::new("Brush")?;
+        let app_info = vk::ApplicationInfo::default()
+            .application_name(&name)
+            .engine_name(&name)
+            .api_version(VK_VERSION);
+        let create_info = vk::InstanceCreateInfo::default()
+            .application_info(&app_info)
+            .enabled_extension_names(&extension_names);
+        let raw = instance
+            .create_vulkan_instance(
+                system,
+                std::mem::transmute::<vk::PFN_vkGetInstanceProcAddr, _>(get_instance_proc_addr),
+                std::ptr::from_ref(&create_info).cast(),
+            )?
+            .map_err(vk::Result::from_raw)?;
+        ash::Instance::load(vk_entry.static_fn(), vk::Instance::from_raw(raw as _))
+    };
+    let physical_device = vk::PhysicalDevice::from_raw(unsafe {
+        instance.vulkan_graphics_device(system, vk_instance.handle().as_raw() as _)? as _
+    });
+    let api_version =
+        unsafe { vk_instance.get_physical_device_properties(physical_device) }.api_version;
+
+    let hal_instance = unsafe {
+        <Vulkan as Api>::Instance::from_raw(
+            vk_entry,
+            vk_instance.clone(),
+            api_version,
+            0,
+            None,
+            vk_extensions,
+            flags,
+            false,
+            None,
+        )?
+    };
+    let adapter = hal_instance
+        .expose_adapter(physical_device)
+        .context("wgpu can't run on the GPU of the headset")?;
+    // Like the device eframe creates, see `create_egui_options`.
+    let features = adapter
+        .features
+        .difference(wgpu::Features::MAPPABLE_PRIMARY_BUFFERS);
+    let device_extensions = adapter.adapter.required_device_extensions(features);
+    let mut device_features = adapter
+        .adapter
+        .physical_device_features(&device_extensions, features);
+    // wgpu runs on the first queue family.
+    let queue_family_index = 0;
+    let queue_info = vk::DeviceQueueCreateInfo::default()
+        .queue_family_index(queue_family_index)
+        .queue_priorities(&[1.0]);
+    let extension_names: Vec<_> = device_extensions.iter().map(|e| e.as_ptr()).collect();
+    let queue_infos = [queue_info];
+    let device_info = device_features
+        .add_to_device_create(vk::DeviceCreateInfo::default().queue_create_infos(&queue_infos))
+        .enabled_extension_names(&extension_names);
+    let vk_device = unsafe {
+        let raw = instance
+            .create_vulkan_device(
+                system,
+                std::mem::transmute::<vk::PFN_vkGetInstanceProcAddr, _>(get_instance_proc_addr),
+                physical_device.as_raw() as _,
+                std::ptr::from_ref(&device_info).cast(),
+            )?
+            .map_err(vk::Result::from_raw)?;
+        ash::Device::load(vk_instance.fp_v1_0(), vk::Device::from_raw(raw as _))
+    };
+    let session_info = xr::vulkan::SessionCreateInfo {
+        instance: vk_instance.handle().as_raw() as *const c_void,
+        physical_device: physical_device.as_raw() as *const c_void,
+        device: vk_device.handle().as_raw() as *const c_void,
+        queue_family_index,
+        queue_index: 0,
+    };
+    let open_device = unsafe {
+        adapter.adapter.device_from_raw(
+            vk_device,
+            None,
+            &device_extensions,
+            features,
+            &wgpu::MemoryHints::MemoryUsage,
+            queue_family_index,
+            0,
+        )?
+    };
+
+    let wgpu_instance = unsafe { wgpu::Instance::from_hal::<Vulkan>(hal_instance) };
+    let wgpu_adapter = unsafe { wgpu_instance.create_adapter_from_hal(adapter) };
+    let (device, queue) = unsafe {
+        wgpu_adapter.create_device_from_hal(
+            open_device,
+            &wgpu::DeviceDescriptor {
+                label: Some("egui+burn+xr"),
+                required_features: features,
+                required_limits: wgpu_adapter.limits(),
+                memory_hints: wgpu::MemoryHints::MemoryUsage,
+                trace: wgpu::Trace::Off,
+            },
+        )?
+    };
+    Ok((
+        XrContext {
+            instance,
+            system,
+            session_info,
+        },
+        XrGraphics {
+            instance: wgpu_instance,
+            adapter: wgpu_adapter,
+            device,
+            queue,
+        },
+    ))
+}
+
+/// What the headset shows.
+#[derive(Clone)]
+pub struct XrScene {
+    pub splats: Splats<MainBackend>,
+    /// Where the headset starts out in the scene, usually the camera of the viewer.
+    pub origin: Camera,
+    /// Units of the scene to a meter.
+    pub scene_scale: f32,
+    pub foveation: Option<Foveation>,
+}
+
+/// A session on a headset, rendering on a thread of its own until dropped.
+pub struct XrViewer {
+    scene: Arc<Mutex<Option<XrScene>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl XrViewer {
+    /// Start a session on the headset of `context`. `device` and `queue` need to be the ones of
+    /// [`create_xr_graphics`], which burn renders with.
+    pub fn start(context: XrContext, device: wgpu::Device, queue: wgpu::Queue) -> Self {
+        let scene = Arc::new(Mutex::new(None));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::Builder::new()
+            .name("OpenXR".to_owned())
+            .spawn({
+                let (scene, stop) = (scene.clone(), stop.clone());
+                move || {
+                    if let Err(error) = run_session(&context, &device, &queue, &scene, &stop) {
+                        log::error!("Headset session failed: {error:#}");
+                    }
+                }
+            })
+            .expect("Failed to start OpenXR thread");
+        Self {
+            scene,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Show `scene` from the next frame of the headset on.
+    pub fn update(&self, scene: XrScene) {
+        *self.scene.lock().expect("Poisoned XR scene") = Some(scene);
+    }
+
+    /// Whether the session still runs. It ends when the runtime or the user stops it.
+    pub fn is_running(&self) -> bool {
+        self.thread.as_ref().is_some_and(|t| !t.is_finished())
+    }
+}
+
+impl Drop for XrViewer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn wgpu_format(format: vk::Format) -> Option<wgpu::TextureFormat> {
+    match format {
+        vk::Format::R8G8B8A8_SRGB => Some(wgpu::TextureFormat::Rgba8UnormSrgb),
+        vk::Format::B8G8R8A8_SRGB => Some(wgpu::TextureFormat::Bgra8UnormSrgb),
+        vk::Format::R8G8B8A8_UNORM => Some(wgpu::TextureFormat::Rgba8Unorm),
+        vk::Format::B8G8R8A8_UNORM => Some(wgpu::TextureFormat::Bgra8Unorm),
+        _ => None,
+    }
+}
+
+fn eye_fov(fov: xr::Fovf) -> EyeFov {
+    EyeFov {
+        left: fov.angle_left,
+        right: fov.angle_right,
+        up: fov.angle_up,
+        down: fov.angle_down,
+    }
+}
+
+// Images of a swapchain as wgpu textures, each with a layer per eye.
+fn swapchain_textures(
+    swapchain: &xr::Swapchain<xr::Vulkan>,
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    size: UVec2,
+) -> anyhow::Result<Vec<wgpu::Texture>> {
+    let extent = wgpu::Extent3d {
+        width: size.x,
+        height: size.y,
+        depth_or_array_layers: 2,
+    };
+    let images = swapchain.enumerate_images()?;
+    Ok(images
+        .into_iter()
+        .map(|image| unsafe {
+            // The runtime owns the images, so nothing is dropped with the texture.
+            let hal_texture = <Vulkan as Api>::Device::texture_from_raw(
+                vk::Image::from_raw(image),
+                &wgpu::hal::TextureDescriptor {
+                    label: Some("Headset swapchain"),
+                    size: extent,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUses::COLOR_TARGET,
+                    memory_flags: wgpu::hal::MemoryFlags::empty(),
+                    view_formats: vec![],
+                },
+                Some(Box::new(|| {})),
+            );
+            device.create_texture_from_hal::<Vulkan>(
+                hal_texture,
+                &wgpu::TextureDescriptor {
+                    label: Some("Headset swapchain"),
+                    size: extent,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    view_formats: &[],
+                },
+            )
+        })
+        .collect())
+}
+
+fn run_session(
+    context: &XrContext,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    scene: &Mutex<Option<XrScene>>,
+    stop: &AtomicBool,
+) -> anyhow::Result<()> {
+    let XrContext {
+        instance,
+        system,
+        session_info,
+    } = context;
+    let (session, mut frame_waiter, mut frame_stream) =
+        unsafe { instance.create_session::<xr::Vulkan>(*system, session_info)? };
+    // The space the headset started in, the origin of the scene.
+    let space =
+        session.create_reference_space(xr::ReferenceSpaceType::LOCAL, xr::Posef::IDENTITY)?;
+
+    let views = instance.enumerate_view_configuration_views(*system, VIEW_TYPE)?;
+    anyhow::ensure!(views.len() == 2, "Headsets need a view per eye");
+    let size = UVec2::new(
+        views[0].recommended_image_rect_width,
+        views[0].recommended_image_rect_height,
+    );
+    let (vk_format, format) = session
+        .enumerate_swapchain_formats()?
+        .into_iter()
+        .find_map(|raw| {
+            let vk_format = vk::Format::from_raw(raw as i32);
+            Some((vk_format, wgpu_format(vk_format)?))
+        })
+        .context("The headset doesn't support any 8 bit color formats")?;
+    let mut swapchain = session.create_swapchain(&xr::SwapchainCreateInfo {
+        create_flags: xr::SwapchainCreateFlags::EMPTY,
+        usage_flags: xr::SwapchainUsageFlags::COLOR_ATTACHMENT,
+        format: vk_format.as_raw() as u32,
+        sample_count: 1,
+        width: size.x,
+        height: size.y,
+        face_count: 1,
+        array_size: 2,
+        mip_count: 1,
+    })?;
+    let textures = swapchain_textures(&swapchain, device, format, size)?;
+    // A painter for each part of the foveated view of each eye.
+    let mut painters: Vec<[SplatPainter; 2]> = (0..2)
+        .map(|_| [(); 2].map(|()| SplatPainter::new(device, queue, format)))
+        .collect();
+
+    let mut event_storage = xr::EventDataBuffer::new();
+    let mut running = false;
+    let mut exit_requested = false;
+    loop {
+        if stop.load(Ordering::Relaxed) && !exit_requested {
+            if !running {
+                return Ok(());
+            }
+            session.request_exit()?;
+            exit_requested = true;
+        }
+        while let Some(event) = instance.poll_event(&mut event_storage)? {
+            match event {
+                xr::Event::SessionStateChanged(change) => match change.state() {
+                    xr::SessionState::READY => {
+                        session.begin(VIEW_TYPE)?;
+                        running = true;
+                    }
+                    xr::SessionState::STOPPING => {
+                        session.end()?;
+                        running = false;
+                    }
+                    xr::SessionState::EXITING | xr::SessionState::LOSS_PENDING => return Ok(()),
+                    _ => {}
+                },
+                xr::Event::InstanceLossPending(_) => return Ok(()),
+                _ => {}
+            }
+        }
+        if !running {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            continue;
+        }
+
+        let frame = frame_waiter.wait()?;
+        frame_stream.begin()?;
+        if !frame.should_render {
+            frame_stream.end(
+                frame.predicted_display_time,
+                xr::EnvironmentBlendMode::OPAQUE,
+                &[],
+            )?;
+            continue;
+        }
+        let image = swapchain.acquire_image()?;
+        swapchain.wait_image(xr::Duration::INFINITE)?;
+        let (_, eyes) = session.locate_views(VIEW_TYPE, frame.predicted_display_time, &space)?;
+        let scene = scene.lock().expect("Poisoned XR scene").clone();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Headset frame"),
+        });
+        for (layer, (eye, painters)) in eyes.iter().zip(&mut painters).enumerate() {
+            let target = textures[image as usize].create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer as u32,
+                array_layer_count: Some(1),
+                ..Default::default()
+            });
+            let mut parts = vec![];
+            if let Some(scene) = &scene {
+                let position = Vec3::new(
+                    eye.pose.position.x,
+                    eye.pose.position.y,
+                    eye.pose.position.z,
+                );
+                let o = eye.pose.orientation;
+                let orientation = Quat::from_xyzw(o.x, o.y, o.z, o.w);
+                let camera = headset_eye_camera(
+                    &scene.origin,
+                    scene.scene_scale,
+                    position,
+                    orientation,
+                    eye_fov(eye.fov),
+                );
+                // Headsets look through the principal point of the eye.
+                let gaze = camera.center_uv;
+                parts = match scene.foveation {
+                    Some(foveation) => foveated_parts(&camera, size, gaze, foveation),
+                    None => foveated_parts(
+                        &camera,
+                        size,
+                        gaze,
+                        Foveation {
+                            inner_fraction: 1.0,
+                            outer_downscale: 1,
+                        },
+                    ),
+                };
+                for (painter, part) in painters.iter_mut().zip(&parts) {
+                    painter.prepare(&scene.splats, &part.camera, part.size);
+                }
+            }
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Headset eye"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            for (painter, part) in painters.iter().zip(&parts) {
+                let offset = part.offset.as_vec2();
+                let extent = part.extent.as_vec2();
+                pass.set_viewport(offset.x, offset.y, extent.x, extent.y, 0.0, 1.0);
+                painter.paint(&mut pass);
+            }
+        }
+        queue.submit([encoder.finish()]);
+        swapchain.release_image()?;
+
+        let rect = xr::Rect2Di {
+            offset: xr::Offset2Di { x: 0, y: 0 },
+            extent: xr::Extent2Di {
+                width: size.x as i32,
+                height: size.y as i32,
+            },
+        };
+        let projection_views: Vec<_> = eyes
+            .iter()
+            .enumerate()
+            .map(|(layer, eye)| {
+                xr::CompositionLayerProjectionView::new()
+                    .pose(eye.pose)
+                    .fov(eye.fov)
+                    .sub_image(
+                        xr::SwapchainSubImage::new()
+                            .swapchain(&swapchain)
+                            .image_array_index(layer as u32)
+                            .image_rect(rect),
+                    )
+            })
+            .collect();
+        frame_stream.end(
+            frame.predicted_display_time,
+            xr::EnvironmentBlendMode::OPAQUE,
+            &[&xr::CompositionLayerProjection::new()
+                .space(&space)
+                .views(&projection_views)],
+        )?;
+    }
+}
@@ -0,0 +1,141 @@
+// Local cache of downloaded files, so scenes only need to be downloaded once.
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::PathBuf,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::io::{AsyncRead, ReadBuf};
+
+// FNV-1a, to give each url a stable file name.
+fn url_hash(url: &str) -> u64 {
+    url.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Where a download of `url` is cached. None if there's no cache directory on this system.
+pub fn cache_path(url: &str) -> Option<PathBuf> {
+    // Keep the file name around to make the cache easier to browse.
+    let name: String = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|url| url.rsplit('/').next())
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        .collect();
+    let file_name = format!("{:016x}_{name}", url_hash(url));
+    Some(dirs::cache_dir()?.join("brush").join(file_name))
+}
+
+/// Passes data through, while writing a copy to a file.
+///
+/// The copy only shows up at its path once it's complete, so an interrupted download doesn't
+/// leave a broken file in the cache. It's complete when the end is reached, or when `len` bytes
+/// were read, as readers might stop before they see the end. If the copy can't be written, data
+/// is still passed through.
+pub(crate) struct TeeReader<R> {
+    inner: R,
+    file: Option<File>,
+    part_path: PathBuf,
+    path: PathBuf,
+    len: Option<u64>,
+    written: u64,
+}
+
+impl<R> TeeReader<R> {
+    pub(crate) fn new(inner: R, path: PathBuf, len: Option<u64>) -> Self {
+        let part_path = path.with_extension("part");
+        let file = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| File::create(&part_path))
+            .ok();
+        Self {
+            inner,
+            file,
+            part_path,
+            path,
+            len,
+            written: 0,
+        }
+    }
+
+    fn finish(&mut self) {
+        self.file = None;
+        if std::fs::rename(&self.part_path, &self.path).is_err() {
+            self.abandon();
+        }
+    }
+
+    fn abandon(&mut self) {
+        self.file = None;
+        let _ = std::fs::remove_file(&self.part_path);
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for TeeReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+
+        match &poll {
+            Poll::Ready(Ok(())) => {
+                let read = &buf.filled()[before..];
+                if let Some(file) = this.file.as_mut() {
+                    if !read.is_empty() {
+                        if file.write_all(read).is_err() {
+                            this.abandon();
+                        } else {
+                            this.written += read.len() as u64;
+                            if this.len == Some(this.written) {
+                                this.finish();
+                            }
+                        }
+                    } else if buf.remaining() > 0 {
+                        // Nothing read while there was room means the end was reached.
+                        this.finish();
+                    }
+                }
+            }
+            Poll::Ready(Err(_)) => this.abandon(),
+            Poll::Pending => {}
+        }
+        poll
+    }
+}
+
+impl<R> Drop for TeeReader<R> {
+    fn drop(&mut self) {
+        // Not read to the end, so the copy isn't complete.
+        if self.file.is_some() {
+            self.abandon();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_names_are_stable() {
+        assert_eq!(url_hash("a"), 0xaf63_dc4c_8601_ec8c);
+        let path = cache_path("https://example.com/scenes/garden.ply?v=2");
+        if let Some(path) = path {
+            let name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default();
+            assert!(name.ends_with("_garden.ply"), "{name}");
+        }
+    }
+}
@@ -1,6 +1,6 @@
-use crate::{BrushVfs, VfsConstructError};
+use crate::{BrushVfs, DynRead, VfsConstructError};
 use rrfd::PickFileError;
-use std::{path::Path, str::FromStr};
+use std::{path::Path, str::FromStr, sync::Arc};
 use tokio_stream::StreamExt;
 use tokio_util::io::StreamReader;
 
@@ -9,7 +9,29 @@ pub enum DataSource {
     PickFile,
     PickDirectory,
     Url(String),
+    /// Like [`DataSource::Url`], but keeps a copy of the download around to load next time.
+    ///
+    /// Nb: On the web, only the latest download is kept, in the storage of the browser.
+    CachedUrl(String),
     Path(String),
+    /// A file already read into memory, eg. one dropped onto the app on the web.
+    Memory(MemoryFile),
+    /// The dataset of the latest training run, kept in the browser to continue the run from its
+    /// latest checkpoint.
+    #[cfg(target_family = "wasm")]
+    Stored,
+}
+
+#[derive(Clone)]
+pub struct MemoryFile {
+    pub name: String,
+    pub data: Arc<[u8]>,
+}
+
+impl std::fmt::Debug for MemoryFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MemoryFile({}, {} bytes)", self.name, self.data.len())
+    }
 }
 
 // Implement FromStr to allow Clap to parse string arguments into DataSource
@@ -18,7 +40,11 @@ impl FromStr for DataSource {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            s if s.starts_with("http://") || s.starts_with("https://") => {
+            // Websockets are training runs served elsewhere, which the viewer follows.
+            s if ["http://", "https://", "ws://", "wss://"]
+                .iter()
+                .any(|scheme| s.starts_with(scheme)) =>
+            {
                 Ok(Self::Url(s.to_owned()))
             }
             // This path might not exist but that's ok, rather find that out later.
@@ -36,6 +62,48 @@ pub enum DataSourceError {
     VfsError(#[from] VfsConstructError),
     #[error(transparent)]
     ReqwestError(#[from] reqwest::Error),
+    #[cfg(target_family = "wasm")]
+    #[error(transparent)]
+    WebStore(#[from] crate::web_store::WebStoreError),
+    #[cfg(target_family = "wasm")]
+    #[error("No training run is kept in this browser")]
+    NothingStored,
+}
+
+/// Turn a user entered url into something to request.
+pub fn resolve_url(url: &str) -> String {
+    let mut url = url.replace("https://", "");
+
+    if url.starts_with("https://") || url.starts_with("http://") {
+        // fine, can use as is.
+    } else if url.starts_with('/') {
+        #[cfg(target_family = "wasm")]
+        {
+            // Assume that this instead points to a GET request for the server.
+            url = web_sys::window()
+                .expect("No window object available")
+                .location()
+                .origin()
+                .expect("Coultn't figure out origin")
+                + &url;
+        }
+        // On non-wasm... not much we can do here, what server would we ask?
+    } else {
+        // Just try to add https:// and hope for the best. Eg. if someone specifies google.com/splat.ply.
+        url = format!("https://{url}");
+    }
+    url
+}
+
+fn response_reader(response: reqwest::Response) -> impl DynRead + 'static {
+    let response = response
+        .bytes_stream()
+        .map(|b| b.map_err(|_e| std::io::ErrorKind::ConnectionAborted));
+    StreamReader::new(response)
+}
+
+async fn url_reader(url: &str) -> Result<impl DynRead + 'static, reqwest::Error> {
+    Ok(response_reader(reqwest::get(resolve_url(url)).await?))
 }
 
 impl DataSource {
@@ -49,37 +117,54 @@ impl DataSource {
                 let picked = rrfd::pick_directory().await?;
                 Ok(BrushVfs::from_path(&picked).await?)
             }
-            Self::Url(url) => {
-                let mut url = url.clone();
-                url = url.replace("https://", "");
-
-                if url.starts_with("https://") || url.starts_with("http://") {
-                    // fine, can use as is.
-                } else if url.starts_with('/') {
-                    #[cfg(target_family = "wasm")]
-                    {
-                        // Assume that this instead points to a GET request for the server.
-                        url = web_sys::window()
-                            .expect("No window object available")
-                            .location()
-                            .origin()
-                            .expect("Coultn't figure out origin")
-                            + &url;
+            Self::Url(url) => Ok(BrushVfs::from_reader(url_reader(&url).await?).await?),
+            Self::CachedUrl(url) => {
+                #[cfg(not(target_family = "wasm"))]
+                if let Some(path) = crate::cache::cache_path(&url) {
+                    if path.is_file() {
+                        return Ok(BrushVfs::from_path(&path).await?);
                     }
-                    // On non-wasm... not much we can do here, what server would we ask?
-                } else {
-                    // Just try to add https:// and hope for the best. Eg. if someone specifies google.com/splat.ply.
-                    url = format!("https://{url}");
+                    // Don't cache error pages.
+                    let response = reqwest::get(resolve_url(&url)).await?.error_for_status()?;
+                    let len = response.content_length();
+                    let reader = crate::cache::TeeReader::new(response_reader(response), path, len);
+                    return Ok(BrushVfs::from_reader(reader).await?);
                 }
+                // Without files to cache in, keep the latest download in the storage of the
+                // browser.
+                #[cfg(target_family = "wasm")]
+                {
+                    use crate::web_store::{WebStore, WebTeeReader};
 
-                let response = reqwest::get(url).await?.bytes_stream();
-
-                let response =
-                    response.map(|b| b.map_err(|_e| std::io::ErrorKind::ConnectionAborted));
-                let reader = StreamReader::new(response);
-                Ok(BrushVfs::from_reader(reader).await?)
+                    if let Ok(store) = WebStore::open().await {
+                        if let Ok(Some(data)) = store.download(&url).await {
+                            return Ok(BrushVfs::from_reader(std::io::Cursor::new(data)).await?);
+                        }
+                    }
+                    // Don't cache error pages.
+                    let response = reqwest::get(resolve_url(&url)).await?.error_for_status()?;
+                    let len = response.content_length();
+                    let reader = WebTeeReader::new(response_reader(response), url, len);
+                    Ok(BrushVfs::from_reader(reader).await?)
+                }
+                #[cfg(not(target_family = "wasm"))]
+                Ok(BrushVfs::from_reader(url_reader(&url).await?).await?)
             }
             Self::Path(path) => Ok(BrushVfs::from_path(Path::new(&path)).await?),
+            Self::Memory(file) => {
+                let reader = std::io::Cursor::new(file.data);
+                Ok(BrushVfs::from_named_reader(reader, Some(&file.name)).await?)
+            }
+            #[cfg(target_family = "wasm")]
+            Self::Stored => {
+                let store = crate::web_store::WebStore::open().await?;
+                let file = store
+                    .dataset()
+                    .await?
+                    .ok_or(DataSourceError::NothingStored)?;
+                let reader = std::io::Cursor::new(file.data);
+                Ok(BrushVfs::from_named_reader(reader, Some(&file.name)).await?)
+            }
         }
     }
 }
@@ -0,0 +1,130 @@
+// Passphrase encryption of files, so scans can be shared without exposing them to anyone
+// who gets hold of the file.
+//
+// Files are laid out as: MAGIC | version | salt | nonce | AES-256-GCM ciphertext. The key is
+// derived from the passphrase with Argon2id, so guessing passphrases is slow.
+use aes_gcm::{
+    Aes256Gcm, KeyInit,
+    aead::{Aead, Payload},
+};
+use argon2::Argon2;
+use thiserror::Error;
+
+/// Start of every encrypted file.
+pub const MAGIC: &[u8; 8] = b"BRUSHENC";
+
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + 1 + SALT_LEN + NONCE_LEN;
+
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    #[error("Wrong passphrase, or the file is corrupted.")]
+    WrongPassphrase,
+    #[error("File is too short to be an encrypted file.")]
+    Truncated,
+    #[error("Encrypted with a newer version of Brush (format version {0}).")]
+    UnsupportedVersion(u8),
+    #[error("Failed to derive key from passphrase: {0}")]
+    KeyDerivation(argon2::Error),
+    #[error("Failed to get random numbers: {0}")]
+    Random(getrandom::Error),
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+fn derive_cipher(passphrase: &str, salt: &[u8]) -> Result<Aes256Gcm, EncryptionError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(EncryptionError::KeyDerivation)?;
+    Ok(Aes256Gcm::new(&key.into()))
+}
+
+/// Encrypt data with a passphrase.
+pub fn encrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>, EncryptionError> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    getrandom::fill(&mut salt).map_err(EncryptionError::Random)?;
+    getrandom::fill(&mut nonce).map_err(EncryptionError::Random)?;
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.push(VERSION);
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&nonce);
+
+    let cipher = derive_cipher(passphrase, &salt)?;
+    // The header is authenticated too, so it can't be tampered with.
+    let ciphertext = cipher
+        .encrypt(
+            &nonce.into(),
+            Payload {
+                msg: data,
+                aad: &header,
+            },
+        )
+        .expect("AES-GCM only fails for data over 64 GB");
+
+    let mut out = header;
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// Decrypt data encrypted with [`encrypt`].
+pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>, EncryptionError> {
+    if data.len() < HEADER_LEN || !is_encrypted(data) {
+        return Err(EncryptionError::Truncated);
+    }
+    let (header, ciphertext) = data.split_at(HEADER_LEN);
+    let version = header[MAGIC.len()];
+    if version != VERSION {
+        return Err(EncryptionError::UnsupportedVersion(version));
+    }
+    let salt = &header[MAGIC.len() + 1..MAGIC.len() + 1 + SALT_LEN];
+    let nonce = &header[MAGIC.len() + 1 + SALT_LEN..];
+
+    let cipher = derive_cipher(passphrase, salt)?;
+    cipher
+        .decrypt(
+            nonce.into(),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_e| EncryptionError::WrongPassphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let data = b"ply\nformat binary_little_endian 1.0\n".repeat(10);
+        let encrypted = encrypt(&data, "site scan").expect("Failed to encrypt");
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(
+            decrypt(&encrypted, "site scan").expect("Failed to decrypt"),
+            data
+        );
+
+        assert!(matches!(
+            decrypt(&encrypted, "wrong"),
+            Err(EncryptionError::WrongPassphrase)
+        ));
+        assert!(matches!(
+            decrypt(&encrypted[..HEADER_LEN - 1], "site scan"),
+            Err(EncryptionError::Truncated)
+        ));
+
+        // Tampering with the header is caught as well.
+        let mut tampered = encrypted;
+        tampered[MAGIC.len() + 1] ^= 1;
+        assert!(decrypt(&tampered, "site scan").is_err());
+    }
+}
@@ -1,4 +1,10 @@
+#[cfg(not(target_family = "wasm"))]
+pub mod cache;
 mod data_source;
+pub mod encryption;
+pub mod ranges;
+#[cfg(target_family = "wasm")]
+pub mod web_store;
 
 // This class helps working with an archive as a somewhat more regular filesystem.
 //
@@ -34,7 +40,7 @@ mod wasm_send {
     pub trait SendNotWasm: Send {}
     impl<T: Send> SendNotWasm for T {}
 }
-pub use data_source::DataSource;
+pub use data_source::{DataSource, DataSourceError, MemoryFile, resolve_url};
 pub use wasm_send::*;
 
 pub trait DynStream<Item>: Stream<Item = Item> + SendNotWasm {}
@@ -94,8 +100,13 @@ enum VfsContainer {
     Manual {
         readers: HashMap<PathBuf, SharedRead>,
     },
+    Memory {
+        files: HashMap<PathBuf, Arc<[u8]>>,
+    },
     #[cfg(not(target_family = "wasm"))]
-    Directory { base_path: PathBuf },
+    Directory {
+        base_path: PathBuf,
+    },
 }
 
 pub struct BrushVfs {
@@ -135,8 +146,33 @@ pub enum VfsConstructError {
     #[error("Got a status page instead of content: \n\n {0}")]
     InvalidHtml(String),
 
-    #[error("Unknown data type. Only zip and ply files are supported")]
+    #[error("Unknown data type. Only zip, ply, splat and spz files are supported")]
     UnknownDataType,
+
+    #[error("This file is encrypted, a passphrase is needed to open it.")]
+    Encrypted(EncryptedData),
+
+    #[error(transparent)]
+    Decrypt(#[from] encryption::EncryptionError),
+}
+
+/// An encrypted file, waiting on a passphrase to be opened.
+pub struct EncryptedData {
+    data: Arc<Vec<u8>>,
+}
+
+impl std::fmt::Debug for EncryptedData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EncryptedData({} bytes)", self.data.len())
+    }
+}
+
+impl EncryptedData {
+    /// Decrypt the file and mount its contents.
+    pub async fn unlock(&self, passphrase: &str) -> Result<BrushVfs, VfsConstructError> {
+        let data = encryption::decrypt(&self.data, passphrase)?;
+        BrushVfs::from_reader(Cursor::new(data)).await
+    }
 }
 
 impl BrushVfs {
@@ -148,8 +184,55 @@ impl BrushVfs {
         self.lookup.values().cloned()
     }
 
+    pub fn contains(&self, path: &Path) -> bool {
+        self.lookup.contains_key(&PathKey::from_path(path))
+    }
+
+    /// The directory on disk the files are read from, if they are read from a directory.
+    pub fn base_path(&self) -> Option<&Path> {
+        match &self.container {
+            #[cfg(not(target_family = "wasm"))]
+            VfsContainer::Directory { base_path } => Some(base_path),
+            _ => None,
+        }
+    }
+
+    /// The data of the zip archive the files are read from, if they are read from one.
+    pub fn archive_data(&self) -> Option<Arc<Vec<u8>>> {
+        match &self.container {
+            // Archive is cheap to clone, as the data is an Arc<[u8]>.
+            VfsContainer::Zip { archive } => Some(archive.clone().into_inner().into_inner().data),
+            _ => None,
+        }
+    }
+
+    /// A VFS of files in memory, eg. images a program generated. Unlike readers, these can be
+    /// read any number of times.
+    pub fn from_memory_files(files: Vec<MemoryFile>) -> Self {
+        let paths: Vec<_> = files.iter().map(|file| PathBuf::from(&file.name)).collect();
+        Self {
+            lookup: lookup_from_paths(&paths),
+            container: VfsContainer::Memory {
+                files: paths
+                    .into_iter()
+                    .zip(files)
+                    .map(|(path, file)| (path.clean(), file.data))
+                    .collect(),
+            },
+        }
+    }
+
     pub async fn from_reader(
         reader: impl AsyncRead + SendNotWasm + Unpin + 'static,
+    ) -> Result<Self, VfsConstructError> {
+        Self::from_named_reader(reader, None).await
+    }
+
+    /// Like [`BrushVfs::from_reader`], but files which can't be told apart by their contents are
+    /// recognized by the extension of `name`.
+    pub async fn from_named_reader(
+        reader: impl AsyncRead + SendNotWasm + Unpin + 'static,
+        name: Option<&str>,
     ) -> Result<Self, VfsConstructError> {
         // Small hack to peek some bytes: Read them
         // and add them at the start again.
@@ -158,15 +241,31 @@ impl BrushVfs {
         let mut reader: Box<dyn DynRead> =
             Box::new(AsyncReadExt::chain(Cursor::new(peek.clone()), data));
 
-        if peek.as_slice().starts_with(b"ply") {
-            let path = PathBuf::from("input.ply");
-            let reader = Arc::new(Mutex::new(Some(reader)));
-            Ok(Self {
+        let single_file = |path: &str, reader: Box<dyn DynRead>| {
+            let path = PathBuf::from(path);
+            Self {
                 lookup: lookup_from_paths(&[path.clone()]),
                 container: VfsContainer::Manual {
-                    readers: HashMap::from([(path, reader)]),
+                    readers: HashMap::from([(path, Arc::new(Mutex::new(Some(reader))))]),
                 },
-            })
+            }
+        };
+        // .splat files are raw numbers, without a header to recognize.
+        let splat_name = name.filter(|name| name.to_lowercase().ends_with(".splat"));
+
+        if peek.as_slice().starts_with(b"ply") {
+            Ok(single_file("input.ply", reader))
+        } else if peek.starts_with(&[0x1f, 0x8b]) {
+            // Gzipped splats, the only gzipped files Brush reads.
+            Ok(single_file("input.spz", reader))
+        } else if peek.starts_with(b"bsplat") {
+            // Chunked splats, see brush_dataset::chunked_splats.
+            Ok(single_file("input.bsplat", reader))
+        } else if peek.starts_with(b"bsz") {
+            // Compressed splats, see brush_dataset::compression.
+            Ok(single_file("input.bsz", reader))
+        } else if let Some(name) = splat_name {
+            Ok(single_file(name, reader))
         } else if peek.starts_with(b"PK") {
             let mut bytes = vec![];
             reader.read_to_end(&mut bytes).await?;
@@ -178,6 +277,12 @@ impl BrushVfs {
                 lookup: lookup_from_paths(&file_names),
                 container: VfsContainer::Zip { archive },
             })
+        } else if encryption::is_encrypted(&peek) {
+            let mut bytes = vec![];
+            reader.read_to_end(&mut bytes).await?;
+            Err(VfsConstructError::Encrypted(EncryptedData {
+                data: Arc::new(bytes),
+            }))
         } else if peek.starts_with(b"<!DOCTYPE html>") {
             let mut html = String::new();
             reader.read_to_string(&mut html).await?;
@@ -195,7 +300,8 @@ impl BrushVfs {
                 // it's not really just a single path.
                 let file = tokio::fs::File::open(dir).await?;
                 let reader = BufReader::new(file);
-                Self::from_reader(reader).await
+                let name = dir.file_name().and_then(|name| name.to_str());
+                Self::from_named_reader(reader, name).await
             } else {
                 // Make a VFS with all files contained in the directory.
                 async fn walk_dir(dir: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
@@ -308,6 +414,10 @@ impl BrushVfs {
                     )
                 })
             }
+            VfsContainer::Memory { files } => {
+                let data = files.get(path).expect("Unreachable").clone();
+                Ok(Box::new(Cursor::new(data)))
+            }
             #[cfg(not(target_family = "wasm"))]
             VfsContainer::Directory { base_path: dir } => {
                 // TODO: Use a string -> PathBuf cache.
@@ -0,0 +1,94 @@
+// Reads parts of a file at any offset, without reading the rest of it. This lets large files be
+// loaded in any order while they download.
+use std::{io, sync::Arc};
+
+#[cfg(not(target_family = "wasm"))]
+use std::path::PathBuf;
+
+use reqwest::{StatusCode, header};
+
+use crate::{DataSource, resolve_url};
+
+/// A file to read parts of.
+#[derive(Clone)]
+pub enum RangeSource {
+    /// A file on a server, read with range requests.
+    Url {
+        url: String,
+        client: reqwest::Client,
+    },
+    #[cfg(not(target_family = "wasm"))]
+    Path(PathBuf),
+    Memory(Arc<[u8]>),
+}
+
+impl RangeSource {
+    pub fn url(url: &str) -> Self {
+        Self::Url {
+            url: resolve_url(url),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// The file `source` points at, if it is a single file that can be read in parts.
+    pub fn from_data_source(source: &DataSource) -> Option<Self> {
+        match source {
+            DataSource::Url(url) | DataSource::CachedUrl(url) => Some(Self::url(url)),
+            #[cfg(not(target_family = "wasm"))]
+            DataSource::Path(path) if std::path::Path::new(path).is_file() => {
+                Some(Self::Path(PathBuf::from(path)))
+            }
+            DataSource::Memory(file) => Some(Self::Memory(file.data.clone())),
+            _ => None,
+        }
+    }
+
+    /// Read `len` bytes from `start` on.
+    pub async fn read(&self, start: u64, len: u64) -> io::Result<Vec<u8>> {
+        if len == 0 {
+            return Ok(vec![]);
+        }
+        match self {
+            Self::Url { url, client } => {
+                let range = format!("bytes={start}-{}", start + len - 1);
+                let response = client
+                    .get(url)
+                    .header(header::RANGE, range)
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .map_err(io::Error::other)?;
+                // Servers that ignore the range send the whole file instead.
+                if response.status() != StatusCode::PARTIAL_CONTENT {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        format!("{url} can't be read in parts, the server ignores range requests"),
+                    ));
+                }
+                let data = response.bytes().await.map_err(io::Error::other)?;
+                if data.len() as u64 != len {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                Ok(data.to_vec())
+            }
+            #[cfg(not(target_family = "wasm"))]
+            Self::Path(path) => {
+                use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+                let mut file = tokio::fs::File::open(path).await?;
+                file.seek(io::SeekFrom::Start(start)).await?;
+                let mut data = vec![0; len as usize];
+                file.read_exact(&mut data).await?;
+                Ok(data)
+            }
+            Self::Memory(data) => {
+                let range = usize::try_from(start)
+                    .ok()
+                    .and_then(|start| Some(start..start.checked_add(len as usize)?))
+                    .filter(|range| range.end <= data.len())
+                    .ok_or(io::ErrorKind::UnexpectedEof)?;
+                Ok(data[range].to_vec())
+            }
+        }
+    }
+}
@@ -0,0 +1,315 @@
+// Files kept in the browser across page loads, in IndexedDB: the dataset of the latest training
+// run with its latest checkpoint, and the latest download of a cached url. Without these, reloading
+// the page would lose the whole run.
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use js_sys::{Function, Promise, Uint8Array};
+use thiserror::Error;
+use tokio::io::{AsyncRead, ReadBuf};
+use wasm_bindgen::{JsCast, JsValue, closure::Closure};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbDatabase, IdbKeyRange, IdbObjectStore, IdbRequest, IdbTransactionMode};
+
+use crate::MemoryFile;
+
+const DB_NAME: &str = "brush";
+const STORE_NAME: &str = "files";
+
+const DATASET_KEY: &str = "run/dataset";
+const DATASET_NAME_KEY: &str = "run/dataset_name";
+const DATASET_URL_KEY: &str = "run/dataset_url";
+const CHECKPOINT_KEY: &str = "run/checkpoint";
+const CHECKPOINT_ITER_KEY: &str = "run/checkpoint_iter";
+
+// Only one download is kept, from the url in `DOWNLOAD_URL_KEY`. All keys of downloads start with
+// this, which also clears downloads kept by older versions under a key per url.
+const DOWNLOAD_PREFIX: &str = "url/";
+const DOWNLOAD_KEY: &str = "url/data";
+const DOWNLOAD_URL_KEY: &str = "url/url";
+
+#[derive(Debug, Error)]
+pub enum WebStoreError {
+    #[error("This browser has no IndexedDB to store files in.")]
+    Unavailable,
+    #[error("Failed to store files in the browser: {0}")]
+    Js(String),
+}
+
+impl From<JsValue> for WebStoreError {
+    fn from(value: JsValue) -> Self {
+        Self::Js(format!("{value:?}"))
+    }
+}
+
+/// The training run kept in the browser.
+#[derive(Debug, Clone)]
+pub struct StoredRun {
+    pub dataset_name: String,
+    /// Step of the latest checkpoint, if the run got to one.
+    pub checkpoint_iter: Option<u32>,
+}
+
+/// Splats of a training run to continue from.
+pub struct StoredCheckpoint {
+    pub iter: u32,
+    /// The splats, as a ply file.
+    pub ply: Vec<u8>,
+}
+
+// Wait for an IndexedDB request to finish, and return its result.
+async fn finish(request: &IdbRequest) -> Result<JsValue, WebStoreError> {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let on_success = {
+            let request = request.clone();
+            Closure::once_into_js(move || {
+                let result = request.result().unwrap_or(JsValue::UNDEFINED);
+                let _ = resolve.call1(&JsValue::NULL, &result);
+            })
+        };
+        let on_error = Closure::once_into_js(move || {
+            let _ = reject.call1(
+                &JsValue::NULL,
+                &JsValue::from_str("IndexedDB request failed"),
+            );
+        });
+        request.set_onsuccess(Some(on_success.unchecked_ref()));
+        request.set_onerror(Some(on_error.unchecked_ref()));
+    });
+    Ok(JsFuture::from(promise).await?)
+}
+
+pub struct WebStore {
+    db: IdbDatabase,
+}
+
+impl WebStore {
+    pub async fn open() -> Result<Self, WebStoreError> {
+        let window = web_sys::window().ok_or(WebStoreError::Unavailable)?;
+        let factory = window.indexed_db()?.ok_or(WebStoreError::Unavailable)?;
+        let request = factory.open_with_u32(DB_NAME, 1)?;
+        let on_upgrade = {
+            let request = request.clone();
+            Closure::once_into_js(move || {
+                if let Ok(db) = request.result() {
+                    let _ = db
+                        .unchecked_into::<IdbDatabase>()
+                        .create_object_store(STORE_NAME);
+                }
+            })
+        };
+        request.set_onupgradeneeded(Some(on_upgrade.unchecked_ref()));
+        let db = finish(&request).await?.unchecked_into();
+        Ok(Self { db })
+    }
+
+    fn store(&self, mode: IdbTransactionMode) -> Result<IdbObjectStore, WebStoreError> {
+        let transaction = self.db.transaction_with_str_and_mode(STORE_NAME, mode)?;
+        Ok(transaction.object_store(STORE_NAME)?)
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, WebStoreError> {
+        let request = self
+            .store(IdbTransactionMode::Readonly)?
+            .get(&JsValue::from_str(key))?;
+        let value = finish(&request).await?;
+        Ok((!value.is_undefined()).then(|| Uint8Array::new(&value).to_vec()))
+    }
+
+    pub async fn put(&self, key: &str, data: &[u8]) -> Result<(), WebStoreError> {
+        let request = self
+            .store(IdbTransactionMode::Readwrite)?
+            .put_with_key(&Uint8Array::from(data), &JsValue::from_str(key))?;
+        finish(&request).await?;
+        Ok(())
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<(), WebStoreError> {
+        let request = self
+            .store(IdbTransactionMode::Readwrite)?
+            .delete(&JsValue::from_str(key))?;
+        finish(&request).await?;
+        Ok(())
+    }
+
+    async fn clear_downloads(&self) -> Result<(), WebStoreError> {
+        // Keys are sorted, and '0' comes right after '/'.
+        let prefix_end = DOWNLOAD_PREFIX.replace('/', "0");
+        let range = IdbKeyRange::bound(
+            &JsValue::from_str(DOWNLOAD_PREFIX),
+            &JsValue::from_str(&prefix_end),
+        )?;
+        let request = self.store(IdbTransactionMode::Readwrite)?.delete(&range)?;
+        finish(&request).await?;
+        Ok(())
+    }
+
+    /// Keep the download of `url`, in place of the download kept before.
+    pub async fn save_download(&self, url: &str, data: &[u8]) -> Result<(), WebStoreError> {
+        // The url is written last, so a download cut off halfway is never picked up.
+        self.clear_downloads().await?;
+        self.put(DOWNLOAD_KEY, data).await?;
+        self.put(DOWNLOAD_URL_KEY, url.as_bytes()).await
+    }
+
+    /// The data downloaded from `url`, if it's kept. This is either the latest download, or the
+    /// dataset of the run kept in the browser.
+    pub async fn download(&self, url: &str) -> Result<Option<Vec<u8>>, WebStoreError> {
+        if self.get(DOWNLOAD_URL_KEY).await?.as_deref() == Some(url.as_bytes()) {
+            return self.get(DOWNLOAD_KEY).await;
+        }
+        if self.get(DATASET_NAME_KEY).await?.is_some()
+            && self.get(DATASET_URL_KEY).await?.as_deref() == Some(url.as_bytes())
+        {
+            return self.get(DATASET_KEY).await;
+        }
+        Ok(None)
+    }
+
+    /// Keep the dataset of a new training run, in place of the run kept before. Datasets from a
+    /// url replace the download of that url, so the data is only kept once.
+    pub async fn save_dataset(
+        &self,
+        name: &str,
+        url: Option<&str>,
+        data: &[u8],
+    ) -> Result<(), WebStoreError> {
+        // The name is written last, so a dataset cut off halfway is never picked up.
+        self.delete(DATASET_NAME_KEY).await?;
+        self.delete(DATASET_URL_KEY).await?;
+        self.delete(CHECKPOINT_ITER_KEY).await?;
+        self.delete(CHECKPOINT_KEY).await?;
+        self.clear_downloads().await?;
+        self.put(DATASET_KEY, data).await?;
+        if let Some(url) = url {
+            self.put(DATASET_URL_KEY, url.as_bytes()).await?;
+        }
+        self.put(DATASET_NAME_KEY, name.as_bytes()).await?;
+
+        // Otherwise the browser clears the run whenever it runs low on space. Downloads can be
+        // downloaded again, so only runs ask for this.
+        if let Some(window) = web_sys::window() {
+            if let Ok(promise) = window.navigator().storage().persist() {
+                let _ = JsFuture::from(promise).await;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn dataset(&self) -> Result<Option<MemoryFile>, WebStoreError> {
+        // Without the name, the dataset wasn't written fully.
+        let Some(name) = self.get(DATASET_NAME_KEY).await? else {
+            return Ok(None);
+        };
+        let Some(data) = self.get(DATASET_KEY).await? else {
+            return Ok(None);
+        };
+        Ok(Some(MemoryFile {
+            name: String::from_utf8_lossy(&name).into_owned(),
+            data: data.into(),
+        }))
+    }
+
+    /// Keep the splats of the run at step `iter`.
+    ///
+    /// Nb: The step is written last, so a checkpoint cut off halfway is never picked up.
+    pub async fn save_checkpoint(&self, iter: u32, ply: &[u8]) -> Result<(), WebStoreError> {
+        self.delete(CHECKPOINT_ITER_KEY).await?;
+        self.put(CHECKPOINT_KEY, ply).await?;
+        self.put(CHECKPOINT_ITER_KEY, &iter.to_le_bytes()).await
+    }
+
+    async fn checkpoint_iter(&self) -> Result<Option<u32>, WebStoreError> {
+        let iter = self.get(CHECKPOINT_ITER_KEY).await?;
+        Ok(iter.and_then(|iter| Some(u32::from_le_bytes(iter.try_into().ok()?))))
+    }
+
+    pub async fn checkpoint(&self) -> Result<Option<StoredCheckpoint>, WebStoreError> {
+        let Some(iter) = self.checkpoint_iter().await? else {
+            return Ok(None);
+        };
+        let ply = self.get(CHECKPOINT_KEY).await?;
+        Ok(ply.map(|ply| StoredCheckpoint { iter, ply }))
+    }
+
+    /// The run kept in the browser, if any, without reading its files.
+    pub async fn stored_run(&self) -> Result<Option<StoredRun>, WebStoreError> {
+        let Some(name) = self.get(DATASET_NAME_KEY).await? else {
+            return Ok(None);
+        };
+        Ok(Some(StoredRun {
+            dataset_name: String::from_utf8_lossy(&name).into_owned(),
+            checkpoint_iter: self.checkpoint_iter().await?,
+        }))
+    }
+}
+
+/// Passes a download through, while keeping a copy of it in the store once it's complete.
+///
+/// It's complete when the end is reached, or when `len` bytes were read, as readers might stop
+/// before they see the end. Downloads that aren't read to the end aren't kept.
+pub(crate) struct WebTeeReader<R> {
+    inner: R,
+    url: String,
+    data: Option<Vec<u8>>,
+    len: Option<u64>,
+}
+
+impl<R> WebTeeReader<R> {
+    pub(crate) fn new(inner: R, url: String, len: Option<u64>) -> Self {
+        Self {
+            inner,
+            url,
+            data: Some(vec![]),
+            len,
+        }
+    }
+
+    fn finish(&mut self) {
+        let Some(data) = self.data.take() else {
+            return;
+        };
+        let url = self.url.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            // Not caching is fine, eg. when the browser is out of space.
+            if let Ok(store) = WebStore::open().await {
+                let _ = store.save_download(&url, &data).await;
+            }
+        });
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for WebTeeReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+
+        match &poll {
+            Poll::Ready(Ok(())) => {
+                let read = &buf.filled()[before..];
+                if let Some(data) = this.data.as_mut() {
+                    if !read.is_empty() {
+                        data.extend_from_slice(read);
+                        if this.len == Some(data.len() as u64) {
+                            this.finish();
+                        }
+                    } else if buf.remaining() > 0 {
+                        // Nothing read while there was room means the end was reached.
+                        this.finish();
+                    }
+                }
+            }
+            Poll::Ready(Err(_)) => this.data = None,
+            Poll::Pending => {}
+        }
+        poll
+    }
+}
@@ -58,6 +58,23 @@ impl CameraModel {
         }
     }
 
+    /// The name COLMAP uses for this model, eg. `SIMPLE_RADIAL`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SimplePinhole => "SIMPLE_PINHOLE",
+            Self::Pinhole => "PINHOLE",
+            Self::SimpleRadial => "SIMPLE_RADIAL",
+            Self::Radial => "RADIAL",
+            Self::OpenCV => "OPENCV",
+            Self::OpenCvFishEye => "OPENCV_FISHEYE",
+            Self::FullOpenCV => "FULL_OPENCV",
+            Self::Fov => "FOV",
+            Self::SimpleRadialFisheye => "SIMPLE_RADIAL_FISHEYE",
+            Self::RadialFisheye => "RADIAL_FISHEYE",
+            Self::ThinPrismFisheye => "THIN_PRISM_FISHEYE",
+        }
+    }
+
     fn num_params(&self) -> usize {
         match self {
             Self::SimplePinhole => 3,
@@ -151,6 +168,70 @@ impl Camera {
         }] as f32;
         glam::vec2(x, y)
     }
+
+    /// Apply the lens distortion of this camera to a point on the normalized image plane (x / z,
+    /// y / z), as COLMAP does before scaling by the focal length.
+    ///
+    /// Returns None for models whose distortion isn't implemented (`THIN_PRISM_FISHEYE`).
+    pub fn distort(&self, point: glam::Vec2) -> Option<glam::Vec2> {
+        let p = |i: usize| self.params[i] as f32;
+        let (u, v) = (point.x, point.y);
+        let r2 = u * u + v * v;
+
+        // Fisheye models distort the angle to the optical axis instead of the radius.
+        let fisheye = |coeffs: &[f32]| {
+            let r = r2.sqrt();
+            if r < f32::EPSILON {
+                return point;
+            }
+            let theta = r.atan();
+            let theta2 = theta * theta;
+            let mut scale = 1.0;
+            let mut power = theta2;
+            for k in coeffs {
+                scale += k * power;
+                power *= theta2;
+            }
+            point * (theta * scale / r)
+        };
+
+        let opencv_tangential = |p1: f32, p2: f32| {
+            glam::vec2(
+                2.0 * p1 * u * v + p2 * (r2 + 2.0 * u * u),
+                p1 * (r2 + 2.0 * v * v) + 2.0 * p2 * u * v,
+            )
+        };
+
+        let distorted = match self.model {
+            CameraModel::SimplePinhole | CameraModel::Pinhole => point,
+            CameraModel::SimpleRadial => point * (1.0 + p(3) * r2),
+            CameraModel::Radial => point * (1.0 + p(3) * r2 + p(4) * r2 * r2),
+            CameraModel::OpenCV => {
+                point * (1.0 + p(4) * r2 + p(5) * r2 * r2) + opencv_tangential(p(6), p(7))
+            }
+            CameraModel::FullOpenCV => {
+                let r4 = r2 * r2;
+                let r6 = r4 * r2;
+                let radial = (1.0 + p(4) * r2 + p(5) * r4 + p(8) * r6)
+                    / (1.0 + p(9) * r2 + p(10) * r4 + p(11) * r6);
+                point * radial + opencv_tangential(p(6), p(7))
+            }
+            CameraModel::OpenCvFishEye => fisheye(&[p(4), p(5), p(6), p(7)]),
+            CameraModel::SimpleRadialFisheye => fisheye(&[p(3)]),
+            CameraModel::RadialFisheye => fisheye(&[p(3), p(4)]),
+            CameraModel::Fov => {
+                let omega = p(4);
+                let r = r2.sqrt();
+                if omega.abs() < f32::EPSILON || r < f32::EPSILON {
+                    point
+                } else {
+                    point * ((2.0 * r * (omega / 2.0).tan()).atan() / (omega * r))
+                }
+            }
+            CameraModel::ThinPrismFisheye => return None,
+        };
+        Some(distorted)
+    }
 }
 
 fn parse<T: std::str::FromStr>(s: &str) -> io::Result<T> {
@@ -16,31 +16,37 @@ pub enum PickFileError {
     IoError(#[from] std::io::Error),
 }
 
-/// Pick a file and return the name & bytes of the file.
+/// Pick a file and return a reader of its bytes.
 pub async fn pick_file() -> Result<impl AsyncRead + Unpin, PickFileError> {
+    Ok(pick_named_file().await?.1)
+}
+
+/// Pick a file and return its name, where the platform tells it, and a reader of its bytes.
+pub async fn pick_named_file() -> Result<(Option<String>, impl AsyncRead + Unpin), PickFileError> {
     #[cfg(not(target_os = "android"))]
     {
         let file = rfd::AsyncFileDialog::new()
             .pick_file()
             .await
             .ok_or(PickFileError::NoFileSelected)?;
+        let name = Some(file.file_name());
 
         #[cfg(target_family = "wasm")]
         {
-            Ok(std::io::Cursor::new(file.read().await))
+            Ok((name, std::io::Cursor::new(file.read().await)))
         }
 
         #[cfg(not(target_family = "wasm"))]
         {
             let file = tokio::fs::File::open(file.path()).await?;
-            Ok(tokio::io::BufReader::new(file))
+            Ok((name, tokio::io::BufReader::new(file)))
         }
     }
 
     #[cfg(target_os = "android")]
     {
         let file = android::pick_file().await?;
-        Ok(tokio::io::BufReader::new(file))
+        Ok((None, tokio::io::BufReader::new(file)))
     }
 }
 
@@ -74,7 +80,7 @@ pub async fn save_file(default_name: &str, data: Vec<u8>) -> Result<(), PickFile
             .ok_or(PickFileError::NoFileSelected)?;
 
         #[cfg(not(target_family = "wasm"))]
-        tokio::fs::write(file.path(), data).await?;
+        write_atomic(file.path(), &data).await?;
 
         #[cfg(target_family = "wasm")]
         file.write(&data).await?;
@@ -89,3 +95,78 @@ pub async fn save_file(default_name: &str, data: Vec<u8>) -> Result<(), PickFile
         panic!("No saving on Android yet.")
     }
 }
+
+/// Writes data to a file, such that the file either has its old contents or all of `data`.
+///
+/// The data is first written to a `.part` file next to it, which is flushed to disk before it
+/// replaces the file. The file is then read back, so a write that didn't make it to disk fails
+/// here instead of when the file is loaded later.
+#[cfg(not(target_family = "wasm"))]
+pub async fn write_atomic(path: &std::path::Path, data: &[u8]) -> std::io::Result<()> {
+    let mut part_path = path.as_os_str().to_owned();
+    part_path.push(".part");
+    let part_path = PathBuf::from(part_path);
+
+    let write_part = async {
+        tokio::fs::write(&part_path, data).await?;
+        tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(&part_path)
+            .await?
+            .sync_all()
+            .await?;
+        tokio::fs::rename(&part_path, path).await
+    };
+    if let Err(e) = write_part.await {
+        let _ = tokio::fs::remove_file(&part_path).await;
+        return Err(e);
+    }
+
+    // The rename itself only survives a crash once the directory is flushed.
+    #[cfg(unix)]
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        tokio::fs::File::open(parent).await?.sync_all().await?;
+    }
+
+    if tokio::fs::read(path).await? != data {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} doesn't read back as what was written", path.display()),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn write_atomic_replaces_file() {
+        let dir = std::env::temp_dir().join(format!("rrfd_replace_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Create dir");
+        let path = dir.join("scene.ply");
+        std::fs::write(&path, b"old").expect("Write old file");
+
+        write_atomic(&path, b"new").await.expect("Write file");
+        assert_eq!(std::fs::read(&path).expect("Read file"), b"new");
+        assert!(!dir.join("scene.ply.part").exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn failed_write_keeps_old_file() {
+        let dir = std::env::temp_dir().join(format!("rrfd_failed_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Create dir");
+        let path = dir.join("scene.ply");
+        std::fs::write(&path, b"old").expect("Write old file");
+        // A directory in place of the part file makes writing it fail.
+        std::fs::create_dir_all(dir.join("scene.ply.part")).expect("Block part file");
+
+        assert!(write_atomic(&path, b"new").await.is_err());
+        assert_eq!(std::fs::read(&path).expect("Read file"), b"old");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}